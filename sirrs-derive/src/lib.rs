@@ -0,0 +1,52 @@
+//! Derive macros for sirrs, re-exported by `sirrs::compartments` with the
+//! `derive` feature.
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, parse_macro_input};
+
+/// Derive `sirrs::compartments::Compartments` for a struct of named `f64`
+/// fields, one per compartment. The state vector holds the fields in
+/// declaration order, named by the field names.
+#[proc_macro_derive(Compartments)]
+pub fn derive_compartments(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return syn::Error::new_spanned(
+                    &input.ident,
+                    "Compartments expects a struct with named fields",
+                )
+                .to_compile_error()
+                .into();
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(&input.ident, "Compartments expects a struct")
+                .to_compile_error()
+                .into();
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let names: Vec<String> = idents.iter().map(|i| i.to_string()).collect();
+    let indices = 0..idents.len();
+    let expanded = quote! {
+        impl #impl_generics ::sirrs::compartments::Compartments for #name #type_generics #where_clause {
+            const NAMES: &'static [&'static str] = &[#(#names),*];
+
+            fn to_state(&self) -> ::std::vec::Vec<f64> {
+                return ::std::vec![#(self.#idents),*];
+            }
+
+            fn from_state(y: &[f64]) -> Self {
+                return Self {
+                    #(#idents: y[#indices]),*
+                };
+            }
+        }
+    };
+    return expanded.into();
+}
@@ -0,0 +1,60 @@
+//! JSON over HTTP simulation service, see [`sirrs::serve`].
+//!
+//! Usage: `sirrs-serve [address]`, listening on `127.0.0.1:8080` by
+//! default. Connections are answered by [`MAX_CONNECTIONS`] worker threads,
+//! and wait to be accepted while all are busy. Events are logged to
+//! standard error, filtered by `RUST_LOG`, `info` by default.
+use sirrs::serve::{MAX_CONNECTIONS, READ_TIMEOUT, handle_connection};
+use std::io::{self, BufReader, IsTerminal};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use tracing_subscriber::EnvFilter;
+
+/// Answer one connection, giving up on a client silent for
+/// [`READ_TIMEOUT`].
+fn answer(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    let reader = BufReader::new(stream.try_clone()?);
+    return handle_connection(reader, &stream);
+}
+
+fn main() -> io::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let listener = TcpListener::bind(&address)?;
+    tracing::info!(address = %listener.local_addr()?, "listening");
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..MAX_CONNECTIONS {
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || {
+            loop {
+                let stream = match receiver.lock().unwrap().recv() {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let peer = stream.peer_addr().ok();
+                if let Err(error) = answer(stream) {
+                    tracing::warn!(?peer, %error, "connection failed");
+                }
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => sender.send(stream).unwrap(),
+            Err(error) => tracing::warn!(%error, "connection failed"),
+        }
+    }
+    return Ok(());
+}
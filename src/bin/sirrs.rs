@@ -0,0 +1,395 @@
+//! Command line runs of the models, see [`sirrs::cli`].
+//!
+//! Usage: `sirrs <model> [parameters] [outputs]`, for example
+//! `sirrs sir --incidence-rate 0.3 --summary --json run.json`. Run
+//! `sirrs help <model>` for each model's parameters and their defaults.
+//! Parameters out of range, or runs too long to hold in memory, exit with
+//! status 2. `--log <filter>` prints the solvers' `tracing` events, for
+//! example `--log info` for a summary of each run.
+//!
+//! With the `tui` feature, `--tui` plots the series live in the terminal as
+//! the model is solved, see `sirrs::tui`, then writes the outputs once a
+//! key is pressed.
+//!
+//! `sirrs diff a.json b.json` compares two runs saved with `--json`, see
+//! [`sirrs::diff`], exiting with status 1 if they differ and 2 if either
+//! cannot be read.
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use sirrs::cli::Outputs;
+use sirrs::diff::diff;
+use sirrs::metadata::SimulationResult;
+use sirrs::units::Rate;
+use sirrs::{dismod, erlang, sir};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing_subscriber::EnvFilter;
+
+/// Run an epidemic model and write its series.
+#[derive(Parser)]
+#[command(name = "sirrs", version)]
+struct Cli {
+    /// Log events passing this filter, such as `info` or `sirrs=debug`, to
+    /// standard error. Defaults to `RUST_LOG`, else warnings.
+    #[arg(long, global = true, value_name = "FILTER")]
+    log: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Subcommands.
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Model(Model),
+    /// Compare two runs saved with `--json`.
+    Diff(DiffArgs),
+}
+
+/// Models to run.
+#[derive(Subcommand)]
+enum Model {
+    /// SIR model.
+    Sir(SirArgs),
+    /// SEIR model with Erlang distributed latent and infectious periods.
+    Seir(SeirArgs),
+    /// DisMod model of a condition along age in a birth cohort.
+    Dismod(DismodArgs),
+}
+
+/// Integration method.
+#[derive(Clone, Copy, ValueEnum)]
+enum Solver {
+    Euler,
+    Rk4,
+}
+
+impl Solver {
+    fn name(&self) -> &'static str {
+        match self {
+            Solver::Euler => return "euler",
+            Solver::Rk4 => return "rk4",
+        }
+    }
+}
+
+/// Options of every run.
+#[derive(Args)]
+struct Run {
+    /// Length of the run in unit time.
+    #[arg(long, default_value_t = 100, value_parser = at_least_one())]
+    length: usize,
+    /// Size of integration step.
+    #[arg(long, default_value_t = 0.1, value_parser = positive)]
+    step_size: f64,
+    /// Integration method.
+    #[arg(long, value_enum, default_value_t = Solver::Rk4)]
+    solver: Solver,
+    /// Plot the series live in the terminal while solving, with rk4.
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+    #[command(flatten)]
+    outputs: Outputs,
+}
+
+#[derive(Args)]
+struct SirArgs {
+    /// Initial infected population fraction.
+    #[arg(long, default_value_t = 0.01, value_parser = fraction)]
+    i_init: f64,
+    /// Initial removed population fraction.
+    #[arg(long, default_value_t = 0.0, value_parser = fraction)]
+    r_init: f64,
+    /// Transition rate from S into I.
+    #[arg(long, default_value_t = 0.3, value_parser = non_negative)]
+    incidence_rate: f64,
+    /// Transition rate from I into R.
+    #[arg(long, default_value_t = 0.1, value_parser = non_negative)]
+    removal_rate: f64,
+    /// Transition rate from I into S.
+    #[arg(long, default_value_t = 0.0, value_parser = non_negative)]
+    recovery_rate: f64,
+    #[command(flatten)]
+    run: Run,
+}
+
+#[derive(Args)]
+struct SeirArgs {
+    /// Initial infectious population fraction.
+    #[arg(long, default_value_t = 0.01, value_parser = fraction)]
+    i_init: f64,
+    /// Transition rate from S into E.
+    #[arg(long, default_value_t = 0.3, value_parser = non_negative)]
+    incidence_rate: f64,
+    /// Inverse of the mean latent period.
+    #[arg(long, default_value_t = 0.2, value_parser = non_negative)]
+    latent_rate: f64,
+    /// Inverse of the mean infectious period.
+    #[arg(long, default_value_t = 0.1, value_parser = non_negative)]
+    removal_rate: f64,
+    /// Number of latent sub-stages.
+    #[arg(long, default_value_t = 1, value_parser = at_least_one())]
+    latent_stages: usize,
+    /// Number of infectious sub-stages.
+    #[arg(long, default_value_t = 1, value_parser = at_least_one())]
+    infectious_stages: usize,
+    #[command(flatten)]
+    run: Run,
+}
+
+#[derive(Args)]
+struct DismodArgs {
+    /// Fraction with the condition at birth.
+    #[arg(long, default_value_t = 0.0, value_parser = fraction)]
+    c_init: f64,
+    /// Incidence rate, S into C.
+    #[arg(long, default_value_t = 0.01, value_parser = non_negative)]
+    iota: f64,
+    /// Remission rate, C into S.
+    #[arg(long, default_value_t = 0.1, value_parser = non_negative)]
+    rho: f64,
+    /// Excess mortality rate of those with the condition.
+    #[arg(long, default_value_t = 0.05, value_parser = non_negative)]
+    chi: f64,
+    /// Other-cause mortality rate.
+    #[arg(long, default_value_t = 0.01, value_parser = non_negative)]
+    omega: f64,
+    #[command(flatten)]
+    run: Run,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// First run.
+    a: PathBuf,
+    /// Second run.
+    b: PathBuf,
+    /// Relative tolerance below which values are the same.
+    #[arg(long, default_value_t = 0.0, value_parser = non_negative)]
+    tolerance: f64,
+}
+
+/// Most values, steps times compartments, of a run's series, bounding the
+/// memory of a run.
+const MAX_VALUES: f64 = 1e8;
+
+/// Parser of counts of at least 1.
+fn at_least_one() -> RangedU64ValueParser<usize> {
+    return RangedU64ValueParser::new().range(1..);
+}
+
+/// Parse a positive, finite number.
+fn positive(text: &str) -> Result<f64, String> {
+    let x: f64 = text.parse().map_err(|error| format!("{}", error))?;
+    if !x.is_finite() | (x <= 0.0) {
+        return Err(format!("must be positive and finite, got {}", x));
+    }
+    return Ok(x);
+}
+
+/// Parse a population fraction, in [0, 1].
+fn fraction(text: &str) -> Result<f64, String> {
+    let x: f64 = text.parse().map_err(|error| format!("{}", error))?;
+    if !(0.0..=1.0).contains(&x) {
+        return Err(format!("must be in [0, 1], got {}", x));
+    }
+    return Ok(x);
+}
+
+/// Parse a non-negative number.
+fn non_negative(text: &str) -> Result<f64, String> {
+    let x: f64 = text.parse().map_err(|error| format!("{}", error))?;
+    if x.is_nan() | (x < 0.0) {
+        return Err(format!("must be non-negative, got {}", x));
+    }
+    return Ok(x);
+}
+
+/// Read a run saved with `--json` from `path`.
+fn read_run(path: &PathBuf) -> io::Result<SimulationResult> {
+    return fs::read_to_string(path)
+        .and_then(|text| SimulationResult::from_json(&text))
+        .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error)));
+}
+
+impl Run {
+    /// Check that a run of `compartments` series fits in memory.
+    fn check(&self, compartments: usize) -> io::Result<()> {
+        let steps = ((self.length as f64) / self.step_size).ceil();
+        if steps * (compartments as f64) > MAX_VALUES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:e} steps of {} compartments exceed {:e} values, use a longer step size",
+                    steps, compartments, MAX_VALUES
+                ),
+            ));
+        }
+        return Ok(());
+    }
+}
+
+/// Solve `system` through a pipeline, plotting it live in the terminal.
+#[cfg(feature = "tui")]
+fn watch(system: Box<dyn sirrs::pipeline::System>, run: &Run) -> io::Result<SimulationResult> {
+    use ratatui::crossterm::event::{self, Event, KeyEventKind};
+    use sirrs::pipeline::Pipeline;
+    use sirrs::tui::TuiSink;
+    if let Solver::Euler = run.solver {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--tui solves with rk4",
+        ));
+    }
+    let mut pipeline = Pipeline::new(system);
+    pipeline.configure(run.length, run.step_size);
+    let t_end = ((pipeline.state.nrows() - 1) as f64) * run.step_size;
+    let mut sink = TuiSink::new(ratatui::init(), t_end);
+    let solved = pipeline.run_rk4_into(&mut sink).map(|_| ());
+    let closed = solved.and_then(|_| {
+        loop {
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                return Ok(());
+            }
+        }
+    });
+    ratatui::restore();
+    closed?;
+    return Ok(pipeline.result());
+}
+
+/// Run the chosen model, returning its result and outputs.
+fn run(model: Model) -> io::Result<(SimulationResult, Outputs)> {
+    match model {
+        Model::Sir(args) => {
+            let r = args.run;
+            r.check(3)?;
+            let mut model = sir::Model::new();
+            model.configure(
+                r.length,
+                r.step_size,
+                args.i_init,
+                args.r_init,
+                Rate::per_day(args.incidence_rate),
+                Rate::per_day(args.removal_rate),
+                Rate::per_day(args.recovery_rate),
+            );
+            #[cfg(feature = "tui")]
+            if r.tui {
+                return Ok((watch(Box::new(model), &r)?, r.outputs));
+            }
+            model.init_popf();
+            match r.solver {
+                Solver::Euler => model.run_euler(),
+                Solver::Rk4 => model.run_rk4(),
+            };
+            return Ok((model.result(r.solver.name()), r.outputs));
+        }
+        Model::Seir(args) => {
+            let r = args.run;
+            r.check(args.latent_stages + args.infectious_stages + 2)?;
+            let mut model = erlang::Model::new();
+            model.configure(
+                r.length,
+                r.step_size,
+                args.i_init,
+                Rate::per_day(args.incidence_rate),
+                Rate::per_day(args.latent_rate),
+                Rate::per_day(args.removal_rate),
+                args.latent_stages,
+                args.infectious_stages,
+            );
+            #[cfg(feature = "tui")]
+            if r.tui {
+                return Ok((watch(Box::new(model), &r)?, r.outputs));
+            }
+            model.init_popf();
+            match r.solver {
+                Solver::Euler => model.run_euler(),
+                Solver::Rk4 => model.run_rk4(),
+            };
+            return Ok((model.result(r.solver.name()), r.outputs));
+        }
+        Model::Dismod(args) => {
+            let r = args.run;
+            r.check(4)?;
+            let mut model = dismod::Model::new();
+            model.configure(
+                r.length,
+                r.step_size,
+                args.c_init,
+                Rate::per_year(args.iota),
+                Rate::per_year(args.rho),
+                Rate::per_year(args.chi),
+                Rate::per_year(args.omega),
+            );
+            #[cfg(feature = "tui")]
+            if r.tui {
+                return Ok((watch(Box::new(model), &r)?, r.outputs));
+            }
+            model.init_popf();
+            match r.solver {
+                Solver::Euler => model.run_euler(),
+                Solver::Rk4 => model.run_rk4(),
+            };
+            return Ok((model.result(r.solver.name()), r.outputs));
+        }
+    }
+}
+
+/// Install a console subscriber of events passing `filter`, else those of
+/// `RUST_LOG`, else warnings.
+fn init_logging(filter: Option<&str>) -> Result<(), String> {
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter).map_err(|error| error.to_string())?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    return Ok(());
+}
+
+fn main() -> io::Result<ExitCode> {
+    let cli = Cli::parse();
+    if let Err(error) = init_logging(cli.log.as_deref()) {
+        eprintln!("sirrs: bad --log filter: {}", error);
+        return Ok(ExitCode::from(2));
+    }
+    match cli.command {
+        Command::Model(model) => {
+            let (result, outputs) = match run(model) {
+                Ok(run) => run,
+                Err(error) => {
+                    eprintln!("sirrs: {}", error);
+                    return Ok(ExitCode::from(2));
+                }
+            };
+            outputs.write(&result, &mut io::stdout().lock())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Diff(args) => {
+            let runs = read_run(&args.a).and_then(|a| Ok((a, read_run(&args.b)?)));
+            let (a, b) = match runs {
+                Ok(runs) => runs,
+                Err(error) => {
+                    eprintln!("sirrs diff: {}", error);
+                    return Ok(ExitCode::from(2));
+                }
+            };
+            let difference = diff(&a, &b, args.tolerance);
+            print!("{}", difference.report());
+            if difference.is_empty() {
+                return Ok(ExitCode::SUCCESS);
+            }
+            return Ok(ExitCode::from(1));
+        }
+    }
+}
@@ -2,3 +2,72 @@ mod sirrs;
 
 pub use crate::sirrs::sir;
 pub use crate::sirrs::dismod;
+pub use crate::sirrs::hospital;
+pub use crate::sirrs::age;
+pub use crate::sirrs::data;
+pub use crate::sirrs::multistrain;
+pub use crate::sirrs::fit;
+pub use crate::sirrs::erlang;
+pub use crate::sirrs::delay;
+pub use crate::sirrs::decompose;
+pub use crate::sirrs::chainbinomial;
+pub use crate::sirrs::sde;
+pub use crate::sirrs::ssa;
+pub use crate::sirrs::observation;
+pub use crate::sirrs::mcmc;
+pub use crate::sirrs::pipeline;
+pub use crate::sirrs::sink;
+pub use crate::sirrs::schedule;
+pub use crate::sirrs::interventions;
+pub use crate::sirrs::export;
+pub use crate::sirrs::input;
+pub use crate::sirrs::sensitivity;
+pub use crate::sirrs::ensemble;
+pub use crate::sirrs::reproducible;
+pub use crate::sirrs::sampling;
+pub use crate::sirrs::schema;
+pub use crate::sirrs::particle;
+pub use crate::sirrs::scalar;
+pub use crate::sirrs::batch;
+pub use crate::sirrs::output;
+pub use crate::sirrs::grid;
+pub use crate::sirrs::stability;
+pub use crate::sirrs::bifurcation;
+pub use crate::sirrs::phase;
+pub use crate::sirrs::system;
+pub use crate::sirrs::scenarios;
+pub use crate::sirrs::metadata;
+pub use crate::sirrs::rng;
+pub use crate::sirrs::sirs;
+pub use crate::sirrs::msir;
+pub use crate::sirrs::carrier;
+pub use crate::sirrs::riskgroup;
+pub use crate::sirrs::household;
+pub use crate::sirrs::abm;
+pub use crate::sirrs::network;
+pub use crate::sirrs::generators;
+pub use crate::sirrs::pairwise;
+pub use crate::sirrs::allocation;
+pub use crate::sirrs::control;
+pub use crate::sirrs::isolation;
+pub use crate::sirrs::serve;
+pub use crate::sirrs::spec;
+pub use crate::sirrs::compartments;
+pub use crate::sirrs::testing;
+pub use crate::sirrs::forward;
+pub use crate::sirrs::adjoint;
+pub use crate::sirrs::reproduction;
+pub use crate::sirrs::distributions;
+pub use crate::sirrs::twosex;
+pub use crate::sirrs::waterborne;
+pub use crate::sirrs::resistance;
+pub use crate::sirrs::renewal;
+pub use crate::sirrs::convert;
+pub use crate::sirrs::units;
+pub use crate::sirrs::lifetable;
+pub use crate::sirrs::burden;
+pub use crate::sirrs::costeffectiveness;
+pub use crate::sirrs::cli;
+pub use crate::sirrs::diff;
+#[cfg(feature = "tui")]
+pub use crate::sirrs::tui;
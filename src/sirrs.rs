@@ -1,3 +1,72 @@
 //! SIR-type compartmental models and methods.
 pub mod sir;
 pub mod dismod;
+pub mod hospital;
+pub mod age;
+pub mod data;
+pub mod multistrain;
+pub mod fit;
+pub mod erlang;
+pub mod delay;
+pub mod decompose;
+pub mod chainbinomial;
+pub mod sde;
+pub mod ssa;
+pub mod observation;
+pub mod mcmc;
+pub mod pipeline;
+pub mod sink;
+pub mod schedule;
+pub mod interventions;
+pub mod export;
+pub mod input;
+pub mod sensitivity;
+pub mod ensemble;
+pub mod reproducible;
+pub mod sampling;
+pub mod schema;
+pub mod particle;
+pub mod scalar;
+pub mod batch;
+pub mod output;
+pub mod grid;
+pub mod stability;
+pub mod bifurcation;
+pub mod phase;
+pub mod system;
+pub mod scenarios;
+pub mod metadata;
+pub mod rng;
+pub mod sirs;
+pub mod msir;
+pub mod carrier;
+pub mod riskgroup;
+pub mod household;
+pub mod abm;
+pub mod network;
+pub mod generators;
+pub mod pairwise;
+pub mod allocation;
+pub mod control;
+pub mod isolation;
+pub mod serve;
+pub mod spec;
+pub mod compartments;
+pub mod testing;
+pub mod forward;
+pub mod adjoint;
+pub mod reproduction;
+pub mod distributions;
+pub mod twosex;
+pub mod waterborne;
+pub mod resistance;
+pub mod renewal;
+pub mod convert;
+pub mod units;
+pub mod lifetable;
+pub mod burden;
+pub mod costeffectiveness;
+pub mod cli;
+pub mod diff;
+#[cfg(feature = "tui")]
+pub mod tui;
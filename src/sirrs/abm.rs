@@ -0,0 +1,423 @@
+//! Agent-based SIR simulation.
+//!
+//! Every individual is an explicit agent with its own state, susceptibility
+//! and infectiousness, in a well-mixed population of `N` agents. Events run
+//! in continuous time on exponential clocks:
+//!  - susceptible agent `k` is infected at rate `β σ[k] Σ a[j] / N`, the sum
+//!    over infectious agents `j`
+//!  - each infected agent draws an exponential removal clock of rate `γ`
+//!    when it is infected
+//!
+//! With every susceptibility and infectiousness 1 this is the stochastic
+//! counterpart of the SIR model without recovery, and [`Model::result`]
+//! has the same columns as [`crate::sir::Model::result`] so the two can be
+//! compared directly.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::reproducible::ln;
+use crate::sirrs::rng::{self, SimRng};
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Disease state of an agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentState {
+    /// Can be infected.
+    Susceptible,
+    /// Infects others until removed.
+    Infectious,
+    /// Neither infectious nor susceptible.
+    Removed,
+}
+
+/// Pending removal of an agent, ordered by time.
+struct Clock {
+    t: f64,
+    agent: usize,
+}
+
+impl PartialEq for Clock {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Clock {}
+
+impl PartialOrd for Clock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Clock {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .t
+            .total_cmp(&other.t)
+            .then(self.agent.cmp(&other.agent));
+    }
+}
+
+/// Create and run an agent-based SIR simulation.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Spacing of the recorded series.
+    pub step_size: f64,
+    /// Number of agents.
+    pub population: usize,
+    /// Number of agents infectious at the start, the first `i_init`.
+    pub i_init: usize,
+    /// Transition rate from S into I between agents of susceptibility and
+    /// infectiousness 1.
+    pub incidence_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Relative susceptibility of each agent. All 1 by default.
+    pub susceptibility: Vec<f64>,
+    /// Relative infectiousness of each agent. All 1 by default.
+    pub infectiousness: Vec<f64>,
+    /// State of each agent at the end of a run.
+    pub states: Vec<AgentState>,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// Infections, as a population fraction, since the previous index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub incidence: Mat<f64>,
+    /// Infections, as a population fraction, up to each index. 1D Array
+    /// with one element per index of [`Model::grid`].
+    pub cumulative_incidence: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            population: 0,
+            i_init: 0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            seed: 0,
+            susceptibility: Vec::new(),
+            infectiousness: Vec::new(),
+            states: Vec::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            incidence: Mat::new(),
+            cumulative_incidence: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. Every agent has susceptibility and
+    /// infectiousness 1.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: usize,
+        i_init: usize,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        seed: u64,
+    ) -> &mut Self {
+        assert!(
+            i_init <= population,
+            "i_init must not exceed population, got {} > {}",
+            i_init,
+            population
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.population = population;
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.seed = seed;
+        self.susceptibility = vec![1.0; population];
+        self.infectiousness = vec![1.0; population];
+        self.states = Vec::new();
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.incidence = Mat::zeros(n_steps, 1);
+        self.cumulative_incidence = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Set the relative susceptibility of each agent, one non-negative value
+    /// per agent.
+    pub fn heterogeneous_susceptibility(&mut self, susceptibility: Vec<f64>) -> &mut Self {
+        assert_eq!(
+            susceptibility.len(),
+            self.population,
+            "susceptibility must have one value per agent, got {} for {} agents",
+            susceptibility.len(),
+            self.population
+        );
+        self.susceptibility = susceptibility;
+        return self;
+    }
+
+    /// Set the relative infectiousness of each agent, one non-negative value
+    /// per agent.
+    pub fn heterogeneous_infectiousness(&mut self, infectiousness: Vec<f64>) -> &mut Self {
+        assert_eq!(
+            infectiousness.len(),
+            self.population,
+            "infectiousness must have one value per agent, got {} for {} agents",
+            infectiousness.len(),
+            self.population
+        );
+        self.infectiousness = infectiousness;
+        return self;
+    }
+
+    /// Time grid of the recorded series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Waiting time to the next event of a clock with `rate`.
+    fn waiting_time(rng: &mut SimRng, rate: f64) -> f64 {
+        return -ln(1.0 - rng.r#gen::<f64>()) / rate;
+    }
+
+    /// Infect `agent` at time `t`, starting its removal clock.
+    fn infect(
+        &mut self,
+        agent: usize,
+        t: f64,
+        rng: &mut SimRng,
+        clocks: &mut BinaryHeap<Reverse<Clock>>,
+    ) {
+        self.states[agent] = AgentState::Infectious;
+        let t = t + Self::waiting_time(rng, self.removal_rate);
+        clocks.push(Reverse(Clock { t, agent }));
+    }
+
+    /// Record agent counts at index `k`.
+    fn record(&mut self, k: usize, counts: &[usize; 3], infections: usize) {
+        let n = self.population as f64;
+        self.s_popf[(k, 0)] = (counts[0] as f64) / n;
+        self.i_popf[(k, 0)] = (counts[1] as f64) / n;
+        self.r_popf[(k, 0)] = (counts[2] as f64) / n;
+        self.cumulative_incidence[(k, 0)] = (infections as f64) / n;
+        if k > 0 {
+            self.incidence[(k, 0)] =
+                self.cumulative_incidence[(k, 0)] - self.cumulative_incidence[(k - 1, 0)];
+        }
+    }
+
+    /// Simulate the epidemic.
+    ///
+    /// The infected agent is chosen by scanning for the susceptible whose
+    /// cumulative susceptibility passes a uniform draw, so each infection
+    /// costs time linear in the population.
+    pub fn run(&mut self) -> &Model {
+        let mut rng = rng::rng(self.seed);
+        let grid = self.grid();
+        let n = self.population as f64;
+        let mut clocks = BinaryHeap::new();
+        self.states = vec![AgentState::Susceptible; self.population];
+        for agent in 0..self.i_init {
+            self.infect(agent, 0.0, &mut rng, &mut clocks);
+        }
+        let mut counts = [self.population - self.i_init, self.i_init, 0];
+        let mut susceptibility: f64 = self.susceptibility[self.i_init..].iter().sum();
+        let mut infectiousness: f64 = self.infectiousness[..self.i_init].iter().sum();
+        let mut infections = 0;
+        let mut t = 0.0;
+        let mut k = 0;
+        while k < grid.n_steps {
+            let rate = self.incidence_rate * susceptibility * infectiousness / n;
+            let t_infection = if rate > 0.0 {
+                t + Self::waiting_time(&mut rng, rate)
+            } else {
+                f64::INFINITY
+            };
+            let t_removal = clocks.peek().map_or(f64::INFINITY, |c| c.0.t);
+            let t_next = t_infection.min(t_removal);
+            while (k < grid.n_steps) && (grid.time(k) < t_next) {
+                self.record(k, &counts, infections);
+                k += 1;
+            }
+            if t_next.is_infinite() {
+                break;
+            }
+            t = t_next;
+            if t_removal <= t_infection {
+                let agent = clocks.pop().expect("pending removal").0.agent;
+                self.states[agent] = AgentState::Removed;
+                infectiousness -= self.infectiousness[agent];
+                counts[1] -= 1;
+                counts[2] += 1;
+                continue;
+            }
+            let target = rng.r#gen::<f64>() * susceptibility;
+            let mut cumulative = 0.0;
+            let mut chosen = None;
+            for agent in 0..self.population {
+                if (self.states[agent] != AgentState::Susceptible)
+                    | (self.susceptibility[agent] <= 0.0)
+                {
+                    continue;
+                }
+                cumulative += self.susceptibility[agent];
+                chosen = Some(agent);
+                if cumulative > target {
+                    break;
+                }
+            }
+            let agent = chosen.expect("susceptible agent");
+            self.infect(agent, t, &mut rng, &mut clocks);
+            susceptibility -= self.susceptibility[agent];
+            infectiousness += self.infectiousness[agent];
+            counts[0] -= 1;
+            counts[1] += 1;
+            infections += 1;
+        }
+        return self;
+    }
+
+    /// The recorded series with metadata of the run, in the columns of
+    /// [`crate::sir::Model::result`].
+    pub fn result(&self) -> SimulationResult {
+        let parameters = Parameters::from([
+            ("length".to_string(), self.length as f64),
+            ("population".to_string(), self.population as f64),
+            ("i_init".to_string(), self.i_init as f64),
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+        let mut metadata = RunMetadata::new("abm", self.step_size, &parameters);
+        metadata.seed(self.seed);
+        let columns = [
+            &self.s_popf,
+            &self.i_popf,
+            &self.r_popf,
+            &self.incidence,
+            &self.cumulative_incidence,
+        ];
+        let n_steps = self.s_popf.nrows();
+        return SimulationResult {
+            metadata,
+            names: ["s", "i", "r", "incidence", "cumulative_incidence"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            times: self.grid().times(),
+            values: Mat::from_fn(n_steps, columns.len(), |t, j| columns[j][(t, 0)]),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::abm::{AgentState, Model};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_run_matches_sir() {
+        let mut model = Model::new();
+        model.configure(60, 0.5, 5000, 50, Rate::per_day(0.4), Rate::per_day(0.1), 5);
+        model.run();
+        let mut ode = sir::Model::new();
+        ode.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        ode.init_popf();
+        ode.run_rk4();
+        let result = model.result();
+        let expected = ode.result("rk4");
+        assert_eq!(result.names, expected.names, "Bad result columns");
+        assert_eq!(result.times, expected.times, "Bad result times");
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)] + model.i_popf[(t, 0)] + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-12,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+            assert!(
+                (model.i_popf[(t, 0)] - ode.i_popf[(t, 0)]).abs() < 0.05,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                ode.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+        assert_eq!(
+            result.metadata.seed,
+            Some(5),
+            "Expected the seed in the metadata"
+        );
+    }
+
+    #[test]
+    fn test_heterogeneous_susceptibility() {
+        let mut model = Model::new();
+        model.configure(80, 0.5, 1000, 10, Rate::per_day(0.5), Rate::per_day(0.1), 2);
+        let susceptibility = (0..1000).map(|k| (k % 2) as f64).collect();
+        model.heterogeneous_susceptibility(susceptibility);
+        model.run();
+        for k in (10..1000).step_by(2) {
+            assert_eq!(
+                model.states[k],
+                AgentState::Susceptible,
+                "Expected agent {} without susceptibility to stay susceptible",
+                k
+            );
+        }
+        let last = model.s_popf.nrows() - 1;
+        assert!(
+            model.cumulative_incidence[(last, 0)] > 0.3,
+            "Expected susceptible agents to be infected, got {}",
+            model.cumulative_incidence[(last, 0)]
+        );
+        let mut again = Model::new();
+        again.configure(80, 0.5, 1000, 10, Rate::per_day(0.5), Rate::per_day(0.1), 2);
+        again.heterogeneous_susceptibility((0..1000).map(|k| (k % 2) as f64).collect());
+        again.run();
+        assert_eq!(model.i_popf, again.i_popf, "Same seed gave different runs");
+    }
+
+    #[test]
+    fn test_no_infectiousness() {
+        let mut model = Model::new();
+        model.configure(40, 0.5, 200, 5, Rate::per_day(0.8), Rate::per_day(0.1), 1);
+        model.heterogeneous_infectiousness(vec![0.0; 200]);
+        model.run();
+        let last = model.s_popf.nrows() - 1;
+        assert_eq!(
+            model.cumulative_incidence[(last, 0)],
+            0.0,
+            "Expected no infections from non-infectious agents"
+        );
+    }
+}
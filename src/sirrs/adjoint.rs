@@ -0,0 +1,423 @@
+//! Adjoint gradients of outcomes of the age-structured model.
+//!
+//! The age-structured model has a parameter for every entry of its contact
+//! matrix, so forward sensitivities, one extra solve per parameter, grow
+//! with the square of the number of groups. The adjoint method instead
+//! runs once backwards through a solved model, carrying the derivative of
+//! a scalar outcome with respect to the state, and collects the gradient
+//! with respect to every parameter at once, for about the cost of one more
+//! solve.
+//!
+//! [`gradient`] differentiates the RK4 steps of [`age::Model::run_rk4`]
+//! exactly (a discrete adjoint), so the gradient is that of the computed
+//! solution, which is what a calibration minimizes. The outcome is given
+//! by its derivative with respect to the compartments at each index, for
+//! example a final size, see [`final_size_gradient`], or a sum of squared
+//! errors against observations. Vaccination doses are exogenous and are
+//! held fixed, so the gradient is exact unless doses are limited by the
+//! susceptibles left to vaccinate.
+use crate::sirrs::age;
+use faer::Mat;
+
+/// One value per age group for each compartment, such as the derivative
+/// of an outcome with respect to the compartments at one index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeVars {
+    /// Susceptible, one value per age group.
+    pub s: Vec<f64>,
+    /// Vaccinated, one value per age group.
+    pub v: Vec<f64>,
+    /// Infectious, one value per age group.
+    pub i: Vec<f64>,
+    /// Removed, one value per age group.
+    pub r: Vec<f64>,
+}
+
+impl AgeVars {
+    /// Zero for every compartment of `n_groups` age groups.
+    pub fn zeros(n_groups: usize) -> Self {
+        return Self {
+            s: vec![0.0; n_groups],
+            v: vec![0.0; n_groups],
+            i: vec![0.0; n_groups],
+            r: vec![0.0; n_groups],
+        };
+    }
+
+    /// Compartments of `model` at index `t`.
+    fn at(model: &age::Model, t: usize) -> Self {
+        let n = model.n_groups;
+        return Self {
+            s: (0..n).map(|g| model.s_popf[(t, g)]).collect(),
+            v: (0..n).map(|g| model.v_popf[(t, g)]).collect(),
+            i: (0..n).map(|g| model.i_popf[(t, g)]).collect(),
+            r: (0..n).map(|g| model.r_popf[(t, g)]).collect(),
+        };
+    }
+
+    /// `self + (scale * other)`.
+    fn add(&self, other: &Self, scale: f64) -> Self {
+        let add = |a: &[f64], b: &[f64]| -> Vec<f64> {
+            return a.iter().zip(b).map(|(a, b)| a + (scale * b)).collect();
+        };
+        return Self {
+            s: add(&self.s, &other.s),
+            v: add(&self.v, &other.v),
+            i: add(&self.i, &other.i),
+            r: add(&self.r, &other.r),
+        };
+    }
+}
+
+/// Gradient of an outcome with respect to the age-structured model's
+/// parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdjointGradient {
+    /// With respect to each entry of [`age::Model::contact_matrix`]. Zero
+    /// when contact settings are used.
+    pub contact_matrix: Mat<f64>,
+    /// With respect to each entry of each setting's matrix, in the order of
+    /// [`age::Model::settings`].
+    pub settings: Vec<Mat<f64>>,
+    /// With respect to [`age::Model::incidence_rate`].
+    pub incidence_rate: f64,
+    /// With respect to [`age::Model::removal_rate`].
+    pub removal_rate: f64,
+    /// With respect to [`age::Model::vaccine_efficacy`].
+    pub vaccine_efficacy: f64,
+    /// With respect to [`age::Model::i_init`].
+    pub i_init: f64,
+}
+
+/// Equations of the model at fixed contacts, with their vector-Jacobian
+/// products.
+struct Equations<'a> {
+    model: &'a age::Model,
+    contacts: Mat<f64>,
+}
+
+impl Equations<'_> {
+    /// Infectious fraction within each group, 0 for empty groups.
+    fn prevalence(&self, y: &AgeVars) -> Vec<f64> {
+        let population = &self.model.population;
+        return (0..self.model.n_groups)
+            .map(|b| match population[(b, 0)] > 0.0 {
+                true => y.i[b] / population[(b, 0)],
+                false => 0.0,
+            })
+            .collect();
+    }
+
+    /// Force of infection on each group.
+    fn force(&self, y: &AgeVars) -> Vec<f64> {
+        let n = self.model.n_groups;
+        let prevalence = self.prevalence(y);
+        return (0..n)
+            .map(|a| {
+                let contacts: f64 = (0..n).map(|b| self.contacts[(a, b)] * prevalence[b]).sum();
+                self.model.incidence_rate * contacts
+            })
+            .collect();
+    }
+
+    /// Derivative of every compartment.
+    fn derivatives(&self, y: &AgeVars) -> AgeVars {
+        let foi = self.force(y);
+        let leak = 1.0 - self.model.vaccine_efficacy;
+        let gamma = self.model.removal_rate;
+        let mut d = AgeVars::zeros(self.model.n_groups);
+        for g in 0..self.model.n_groups {
+            d.s[g] = -foi[g] * y.s[g];
+            d.v[g] = -leak * foi[g] * y.v[g];
+            d.i[g] = (foi[g] * (y.s[g] + (leak * y.v[g]))) - (gamma * y.i[g]);
+            d.r[g] = gamma * y.i[g];
+        }
+        return d;
+    }
+
+    /// `u` times the Jacobian of the derivatives at `y`, with `u` times the
+    /// derivatives' Jacobian with respect to the parameters added to
+    /// `gradient` and, for the contacts, to `contacts`.
+    fn vjp(
+        &self,
+        y: &AgeVars,
+        u: &AgeVars,
+        gradient: &mut AdjointGradient,
+        contacts: &mut Mat<f64>,
+    ) -> AgeVars {
+        let n = self.model.n_groups;
+        let beta = self.model.incidence_rate;
+        let leak = 1.0 - self.model.vaccine_efficacy;
+        let gamma = self.model.removal_rate;
+        let population = &self.model.population;
+        let foi = self.force(y);
+        let prevalence = self.prevalence(y);
+        // Derivative with respect to the force of infection on each group.
+        let q: Vec<f64> = (0..n)
+            .map(|a| {
+                (u.i[a] * (y.s[a] + (leak * y.v[a]))) - (u.s[a] * y.s[a]) - (u.v[a] * leak * y.v[a])
+            })
+            .collect();
+        let mut back = AgeVars::zeros(n);
+        for a in 0..n {
+            back.s[a] = foi[a] * (u.i[a] - u.s[a]);
+            back.v[a] = leak * foi[a] * (u.i[a] - u.v[a]);
+            back.i[a] += gamma * (u.r[a] - u.i[a]);
+            gradient.removal_rate += (u.r[a] - u.i[a]) * y.i[a];
+            gradient.vaccine_efficacy += foi[a] * y.v[a] * (u.v[a] - u.i[a]);
+            for b in 0..n {
+                if population[(b, 0)] > 0.0 {
+                    back.i[b] += q[a] * beta * self.contacts[(a, b)] / population[(b, 0)];
+                }
+                gradient.incidence_rate += q[a] * self.contacts[(a, b)] * prevalence[b];
+                contacts[(a, b)] += q[a] * beta * prevalence[b];
+            }
+        }
+        return back;
+    }
+}
+
+/// Gradient of an outcome of a model solved by [`age::Model::run_rk4`].
+/// `loss_gradient(t)` is the derivative of the outcome with respect to the
+/// compartments at index `t`, or `None` where the outcome does not depend
+/// on them.
+pub fn gradient(
+    model: &age::Model,
+    loss_gradient: impl Fn(usize) -> Option<AgeVars>,
+) -> AdjointGradient {
+    let n = model.n_groups;
+    let n_steps = model.s_popf.nrows();
+    let h = model.step_size;
+    let mut gradient = AdjointGradient {
+        contact_matrix: Mat::zeros(n, n),
+        settings: vec![Mat::zeros(n, n); model.settings.len()],
+        incidence_rate: 0.0,
+        removal_rate: 0.0,
+        vaccine_efficacy: 0.0,
+        i_init: 0.0,
+    };
+    let zeros = AgeVars::zeros(n);
+    let mut adjoint = loss_gradient(n_steps - 1).unwrap_or_else(|| zeros.clone());
+    for t in (0..n_steps - 1).rev() {
+        let time = (t as f64) * h;
+        let equations = Equations {
+            model,
+            contacts: model.contact_matrix_at(time),
+        };
+        // Stages of the step, as in `age::Model::rk4_step`.
+        let y0 = AgeVars::at(model, t);
+        let k1 = equations.derivatives(&y0);
+        let y2 = y0.add(&k1, h / 2.0);
+        let k2 = equations.derivatives(&y2);
+        let y3 = y0.add(&k2, h / 2.0);
+        let k3 = equations.derivatives(&y3);
+        let y4 = y0.add(&k3, h);
+        let mut contacts = Mat::zeros(n, n);
+        let k4_bar = zeros.add(&adjoint, h / 6.0);
+        let y4_bar = equations.vjp(&y4, &k4_bar, &mut gradient, &mut contacts);
+        let k3_bar = zeros.add(&adjoint, h / 3.0).add(&y4_bar, h);
+        let y3_bar = equations.vjp(&y3, &k3_bar, &mut gradient, &mut contacts);
+        let k2_bar = zeros.add(&adjoint, h / 3.0).add(&y3_bar, h / 2.0);
+        let y2_bar = equations.vjp(&y2, &k2_bar, &mut gradient, &mut contacts);
+        let k1_bar = zeros.add(&adjoint, h / 6.0).add(&y2_bar, h / 2.0);
+        let y1_bar = equations.vjp(&y0, &k1_bar, &mut gradient, &mut contacts);
+        adjoint = adjoint
+            .add(&y1_bar, 1.0)
+            .add(&y2_bar, 1.0)
+            .add(&y3_bar, 1.0)
+            .add(&y4_bar, 1.0);
+        if let Some(direct) = loss_gradient(t) {
+            adjoint = adjoint.add(&direct, 1.0);
+        }
+        if model.settings.is_empty() {
+            gradient.contact_matrix += &contacts;
+        }
+        for (k, setting) in model.settings.iter().enumerate() {
+            gradient.settings[k] += setting.multiplier_at(time) * &contacts;
+        }
+    }
+    // S and I at index 0 are population * (1 - i_init) and population * i_init.
+    gradient.i_init = (0..n)
+        .map(|g| model.population[(g, 0)] * (adjoint.i[g] - adjoint.s[g]))
+        .sum();
+    return gradient;
+}
+
+/// Gradient of the final size, the removed fraction of the whole
+/// population at the last index, of a model solved by
+/// [`age::Model::run_rk4`].
+pub fn final_size_gradient(model: &age::Model) -> AdjointGradient {
+    let last = model.s_popf.nrows() - 1;
+    return gradient(model, |t| {
+        if t != last {
+            return None;
+        }
+        let mut weights = AgeVars::zeros(model.n_groups);
+        weights.r = vec![1.0; model.n_groups];
+        return Some(weights);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::adjoint::{AgeVars, final_size_gradient, gradient};
+    use crate::sirrs::age::{ContactSetting, Model};
+    use crate::sirrs::data::CoverageRecord;
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::{Mat, mat};
+
+    /// Solved three group model with vaccination, with `contacts`, β, γ,
+    /// vaccine efficacy and `i_init` shifted by `delta`.
+    fn solve(contacts: Mat<f64>, delta: [f64; 4]) -> Model {
+        let mut model = Model::new();
+        model.configure(
+            30,
+            0.25,
+            mat![[0.3], [0.5], [0.2]],
+            contacts,
+            0.01 + delta[3],
+            Fraction::of(0.08 + delta[0]),
+            Rate::per_day(0.2 + delta[1]),
+            Fraction::of(0.7 + delta[2]),
+        );
+        model.vaccination(vec![
+            CoverageRecord {
+                t: 2.0,
+                group: 2,
+                coverage: 0.4,
+            },
+            CoverageRecord {
+                t: 4.0,
+                group: 1,
+                coverage: 0.2,
+            },
+        ]);
+        model.init_popf();
+        model.run_rk4();
+        return model;
+    }
+
+    fn contacts() -> Mat<f64> {
+        return mat![[6.0, 3.0, 1.0], [3.0, 8.0, 2.0], [1.0, 2.0, 4.0]];
+    }
+
+    /// Sum of squared differences of I from a fixed series.
+    fn loss(model: &Model) -> f64 {
+        let mut total = 0.0;
+        for t in 0..model.i_popf.nrows() {
+            for g in 0..3 {
+                total += (model.i_popf[(t, g)] - 0.01).powi(2);
+            }
+        }
+        return total;
+    }
+
+    #[test]
+    fn test_gradient() {
+        let model = solve(contacts(), [0.0; 4]);
+        let result = gradient(&model, |t| {
+            let mut weights = AgeVars::zeros(3);
+            weights.i = (0..3)
+                .map(|g| 2.0 * (model.i_popf[(t, g)] - 0.01))
+                .collect();
+            return Some(weights);
+        });
+        let delta = 1e-6;
+        let fd = |shift: [f64; 4], matrix: Mat<f64>| {
+            let up = loss(&solve(matrix.clone() + &contacts(), shift));
+            let down = loss(&solve(contacts() - &matrix, shift.map(|x| -x)));
+            return (up - down) / (2.0 * delta);
+        };
+        let zero = Mat::<f64>::zeros(3, 3);
+        let checks = [
+            (
+                "incidence_rate",
+                result.incidence_rate,
+                fd([delta, 0.0, 0.0, 0.0], zero.clone()),
+            ),
+            (
+                "removal_rate",
+                result.removal_rate,
+                fd([0.0, delta, 0.0, 0.0], zero.clone()),
+            ),
+            (
+                "vaccine_efficacy",
+                result.vaccine_efficacy,
+                fd([0.0, 0.0, delta, 0.0], zero.clone()),
+            ),
+            (
+                "i_init",
+                result.i_init,
+                fd([0.0, 0.0, 0.0, delta], zero.clone()),
+            ),
+            ("contact_matrix[(0, 2)]", result.contact_matrix[(0, 2)], {
+                let mut shift = zero.clone();
+                shift[(0, 2)] = delta;
+                fd([0.0; 4], shift)
+            }),
+            ("contact_matrix[(1, 1)]", result.contact_matrix[(1, 1)], {
+                let mut shift = zero.clone();
+                shift[(1, 1)] = delta;
+                fd([0.0; 4], shift)
+            }),
+        ];
+        for (name, actual, expected) in checks {
+            assert!(
+                (actual - expected).abs() < 1e-6 * (1.0 + expected.abs()),
+                "Bad gradient {}, expected {} got {}",
+                name,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_final_size_gradient_settings() {
+        let mut model = solve(contacts(), [0.0; 4]);
+        let mut school = ContactSetting::new(
+            "school",
+            mat![[4.0, 1.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 0.0]],
+        );
+        school.intervention(10.0, 20.0, 0.25);
+        let home = ContactSetting::new("home", contacts());
+        model.contact_settings(vec![home, school]);
+        model.init_popf();
+        model.run_rk4();
+        let result = final_size_gradient(&model);
+        let final_size = |model: &Model| -> f64 {
+            let last = model.r_popf.nrows() - 1;
+            return (0..3).map(|g| model.r_popf[(last, g)]).sum();
+        };
+        let delta = 1e-6;
+        let mut sizes = Vec::new();
+        for sign in [1.0, -1.0] {
+            let mut shifted = solve(contacts(), [0.0; 4]);
+            let mut matrix = mat![[4.0, 1.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 0.0]];
+            matrix[(0, 1)] += sign * delta;
+            let mut school = ContactSetting::new("school", matrix);
+            school.intervention(10.0, 20.0, 0.25);
+            shifted.contact_settings(vec![ContactSetting::new("home", contacts()), school]);
+            shifted.init_popf();
+            shifted.run_rk4();
+            sizes.push(final_size(&shifted));
+        }
+        let expected = (sizes[0] - sizes[1]) / (2.0 * delta);
+        assert!(
+            (result.settings[1][(0, 1)] - expected).abs() < 1e-6 * (1.0 + expected.abs()),
+            "Bad school contact gradient, expected {} got {}",
+            expected,
+            result.settings[1][(0, 1)]
+        );
+        assert_eq!(
+            result.contact_matrix,
+            Mat::zeros(3, 3),
+            "Expected no contact_matrix gradient with settings"
+        );
+        assert!(
+            result.incidence_rate > 0.0,
+            "Expected transmission to increase the final size, got {}",
+            result.incidence_rate
+        );
+    }
+}
@@ -0,0 +1,660 @@
+//! Age-structured SIRV model and methods.
+//!
+//! Each age group has its own susceptible, vaccinated, infectious and
+//! removed compartments, and groups mix according to a contact matrix.
+//! Allows transition rates:
+//!  - S → I
+//!  - S → V
+//!  - V → I
+//!  - I → R
+//!
+//! S → V is not a rate but an exogenous input: observed cumulative coverage
+//! by age group and date, see [`crate::data::read_coverage_csv`].
+//!
+//! Contacts may be split by setting (home, work, school, other), each with
+//! its own contact matrix and time-bounded intervention multipliers. The
+//! settings are recombined into a single contact matrix at every step.
+use crate::sirrs::data::CoverageRecord;
+use crate::sirrs::export::{LongRecord, index_names, to_long};
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Numerical integrator variables
+///
+/// This private struct exists to make indexing k and y during integration
+/// simpler. Each field holds one value per age group.
+struct SystemVars {
+    s: Vec<f64>,
+    v: Vec<f64>,
+    i: Vec<f64>,
+    r: Vec<f64>,
+}
+
+/// A contact setting, such as home, work or school, with its own contact
+/// matrix and intervention multipliers.
+pub struct ContactSetting {
+    /// Name of the setting.
+    pub name: String,
+    /// Contacts per unit time in this setting, `n_groups` × `n_groups`.
+    pub matrix: Mat<f64>,
+    /// Interventions as `(start, end, multiplier)`. Contacts in this setting
+    /// are scaled by `multiplier` while `start <= t < end`.
+    pub interventions: Vec<(f64, f64, f64)>,
+}
+
+impl ContactSetting {
+    /// Create a new contact setting with no interventions.
+    pub fn new(name: &str, matrix: Mat<f64>) -> Self {
+        return Self {
+            name: name.to_string(),
+            matrix,
+            interventions: Vec::new(),
+        };
+    }
+
+    /// Scale contacts in this setting by `multiplier` while
+    /// `start <= t < end`. Overlapping interventions multiply.
+    pub fn intervention(&mut self, start: f64, end: f64, multiplier: f64) -> &mut Self {
+        self.interventions.push((start, end, multiplier));
+        return self;
+    }
+
+    /// Combined intervention multiplier at time `t`.
+    pub fn multiplier_at(&self, t: f64) -> f64 {
+        return self
+            .interventions
+            .iter()
+            .filter(|(start, end, _)| (*start <= t) & (t < *end))
+            .map(|(_, _, m)| m)
+            .product();
+    }
+}
+
+/// Contact matrix under preferential mixing, parameterized by a single
+/// assortativity coefficient.
+///
+/// Group `a` makes `activity[a]` contacts per unit time. A fraction
+/// `assortativity` of them are reserved for its own group and the rest are
+/// spread over all groups in proportion to the contacts they make:
+///
+/// ```text
+/// C[a, b] = activity[a] * (ε δ[a, b] + (1 - ε) activity[b] N[b] / Σ_c activity[c] N[c])
+/// ```
+///
+/// Zero assortativity is proportionate mixing, one is fully assortative.
+pub fn assortative_contacts(
+    population: &Mat<f64>,
+    activity: &Mat<f64>,
+    assortativity: f64,
+) -> Mat<f64> {
+    let n = population.nrows();
+    assert_eq!(
+        activity.nrows(),
+        n,
+        "activity must have one row per group, got {} for {} groups",
+        activity.nrows(),
+        n
+    );
+    let total: f64 = (0..n).map(|b| activity[(b, 0)] * population[(b, 0)]).sum();
+    return Mat::from_fn(n, n, |a, b| {
+        let own = if a == b { assortativity } else { 0.0 };
+        let shared = (1.0 - assortativity) * activity[(b, 0)] * population[(b, 0)] / total;
+        activity[(a, 0)] * (own + shared)
+    });
+}
+
+/// Create and run an age-structured SIRV model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Number of age groups.
+    pub n_groups: usize,
+    /// Population fraction in each age group. Column with `n_groups` rows
+    /// which must sum to 1.
+    pub population: Mat<f64>,
+    /// Contacts per unit time an individual in group `a` (row) has with
+    /// members of group `b` (column). `n_groups` × `n_groups`. Ignored
+    /// when `settings` is not empty.
+    pub contact_matrix: Mat<f64>,
+    /// Setting-specific contacts, summed after applying interventions to
+    /// give the contact matrix at each step.
+    pub settings: Vec<ContactSetting>,
+    /// Initial infectious fraction within each age group.
+    pub i_init: f64,
+    /// Probability of transmission per contact with an infectious individual.
+    /// Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Reduction in susceptibility of vaccinated individuals. Must be in [0, 1].
+    pub vaccine_efficacy: f64,
+    /// Observed cumulative vaccination coverage, sorted by time.
+    pub coverage: Vec<CoverageRecord>,
+    /// Susceptible population fraction of each age group (column) at each
+    /// index (row).
+    pub s_popf: Mat<f64>,
+    /// Vaccinated population fraction of each age group (column) at each
+    /// index (row).
+    pub v_popf: Mat<f64>,
+    /// Infectious population fraction of each age group (column) at each
+    /// index (row).
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction of each age group (column) at each index
+    /// (row).
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            n_groups: 0,
+            population: Mat::new(),
+            contact_matrix: Mat::new(),
+            settings: Vec::new(),
+            i_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            vaccine_efficacy: 0.0,
+            coverage: Vec::new(),
+            s_popf: Mat::new(),
+            v_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: Mat<f64>,
+        contact_matrix: Mat<f64>,
+        i_init: f64,
+        incidence_rate: Fraction,
+        removal_rate: Rate,
+        vaccine_efficacy: Fraction,
+    ) -> &mut Self {
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        let n_groups = population.nrows();
+        assert_eq!(
+            contact_matrix.shape(),
+            (n_groups, n_groups),
+            "contact_matrix must be {} x {}",
+            n_groups,
+            n_groups
+        );
+        self.length = length;
+        self.step_size = step_size;
+        self.n_groups = n_groups;
+        self.population = population;
+        self.contact_matrix = contact_matrix;
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.value();
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.vaccine_efficacy = vaccine_efficacy.value();
+        self.s_popf = Mat::zeros(n_steps, n_groups);
+        self.v_popf = Mat::zeros(n_steps, n_groups);
+        self.i_popf = Mat::zeros(n_steps, n_groups);
+        self.r_popf = Mat::zeros(n_steps, n_groups);
+        return self;
+    }
+
+    /// Set observed vaccination coverage. Records are sorted by time, and
+    /// each must be of a configured age group.
+    pub fn vaccination(&mut self, mut coverage: Vec<CoverageRecord>) -> &mut Self {
+        for record in coverage.iter() {
+            assert!(
+                record.group < self.n_groups,
+                "coverage age group must be less than {}, got {}",
+                self.n_groups,
+                record.group
+            );
+        }
+        coverage.sort_by(|a, b| a.t.total_cmp(&b.t));
+        self.coverage = coverage;
+        return self;
+    }
+
+    /// Set setting-specific contact matrices. Each must be
+    /// `n_groups` × `n_groups`.
+    pub fn contact_settings(&mut self, settings: Vec<ContactSetting>) -> &mut Self {
+        for setting in settings.iter() {
+            assert_eq!(
+                setting.matrix.shape(),
+                (self.n_groups, self.n_groups),
+                "{} contact matrix must be {} x {}",
+                setting.name,
+                self.n_groups,
+                self.n_groups
+            );
+        }
+        self.settings = settings;
+        return self;
+    }
+
+    /// Contact matrix in effect at time `t`. The sum of every setting's
+    /// matrix scaled by its intervention multiplier, or `contact_matrix` if
+    /// no settings are configured.
+    pub fn contact_matrix_at(&self, t: f64) -> Mat<f64> {
+        if self.settings.is_empty() {
+            return self.contact_matrix.clone();
+        }
+        let mut contacts = Mat::zeros(self.n_groups, self.n_groups);
+        for setting in self.settings.iter() {
+            let m = setting.multiplier_at(t);
+            for a in 0..self.n_groups {
+                for b in 0..self.n_groups {
+                    contacts[(a, b)] += m * setting.matrix[(a, b)];
+                }
+            }
+        }
+        return contacts;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment in each age group from `population` and `i_init`.
+    pub fn init_popf(&mut self) -> &mut Model {
+        for g in 0..self.n_groups {
+            let n = self.population[(g, 0)];
+            self.s_popf[(0, g)] = n * (1.0 - self.i_init);
+            self.v_popf[(0, g)] = 0.0;
+            self.i_popf[(0, g)] = n * self.i_init;
+            self.r_popf[(0, g)] = 0.0;
+        }
+        return self;
+    }
+
+    /// Force of infection acting on each age group, with transmission
+    /// probability `incidence_rate`.
+    pub(crate) fn force_of_infection(
+        &self,
+        i: &[f64],
+        contacts: &Mat<f64>,
+        incidence_rate: f64,
+    ) -> Vec<f64> {
+        return (0..self.n_groups)
+            .map(|a| {
+                let contacts: f64 = (0..self.n_groups)
+                    .filter(|&b| self.population[(b, 0)] > 0.0)
+                    .map(|b| contacts[(a, b)] * i[b] / self.population[(b, 0)])
+                    .sum();
+                incidence_rate * contacts
+            })
+            .collect();
+    }
+
+    /// Compute the derivative of every compartment in every age group.
+    fn derivatives(&self, y: &SystemVars, contacts: &Mat<f64>) -> SystemVars {
+        let foi = self.force_of_infection(&y.i, contacts, self.incidence_rate);
+        let leak = 1.0 - self.vaccine_efficacy;
+        let mut d = self.init_vars();
+        for g in 0..self.n_groups {
+            d.s[g] = -foi[g] * y.s[g];
+            d.v[g] = -leak * foi[g] * y.v[g];
+            d.i[g] = (foi[g] * (y.s[g] + (leak * y.v[g]))) - (self.removal_rate * y.i[g]);
+            d.r[g] = self.removal_rate * y.i[g];
+        }
+        return d;
+    }
+
+    /// Construct a zeroed set of integrator variables.
+    fn init_vars(&self) -> SystemVars {
+        return SystemVars {
+            s: vec![0.0; self.n_groups],
+            v: vec![0.0; self.n_groups],
+            i: vec![0.0; self.n_groups],
+            r: vec![0.0; self.n_groups],
+        };
+    }
+
+    /// Read the integrator variables stored at index `t`.
+    fn vars_at(&self, t: usize) -> SystemVars {
+        let mut y = self.init_vars();
+        for g in 0..self.n_groups {
+            y.s[g] = self.s_popf[(t, g)];
+            y.v[g] = self.v_popf[(t, g)];
+            y.i[g] = self.i_popf[(t, g)];
+            y.r[g] = self.r_popf[(t, g)];
+        }
+        return y;
+    }
+
+    /// Move people from S to V at index `t` so that cumulative vaccinations
+    /// in each age group reach the latest observed coverage at or before `t`.
+    ///
+    /// `next` is the index of the first coverage record not yet applied and
+    /// `vaccinated` the cumulative vaccinated fraction of each group.
+    fn vaccinate(&mut self, t: usize, next: &mut usize, vaccinated: &mut [f64]) {
+        let time = (t as f64) * self.step_size;
+        while (*next < self.coverage.len()) && (self.coverage[*next].t <= time) {
+            let record = self.coverage[*next];
+            *next += 1;
+            let g = record.group;
+            let target = record.coverage * self.population[(g, 0)];
+            let doses = (target - vaccinated[g]).clamp(0.0, self.s_popf[(t, g)]);
+            self.s_popf[(t, g)] -= doses;
+            self.v_popf[(t, g)] += doses;
+            vaccinated[g] += doses;
+        }
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = ((self.length as f64) / h).ceil() as usize;
+        let mut next = 0;
+        let mut vaccinated = vec![0.0; self.n_groups];
+        for t in 0..n - 1 {
+            self.vaccinate(t, &mut next, &mut vaccinated);
+            let y = self.vars_at(t);
+            let contacts = self.contact_matrix_at((t as f64) * h);
+            let d = self.derivatives(&y, &contacts);
+            for g in 0..self.n_groups {
+                self.s_popf[(t + 1, g)] = y.s[g] + (h * d.s[g]);
+                self.v_popf[(t + 1, g)] = y.v[g] + (h * d.v[g]);
+                self.i_popf[(t + 1, g)] = y.i[g] + (h * d.i[g]);
+                self.r_popf[(t + 1, g)] = y.r[g] + (h * d.r[g]);
+            }
+        }
+        self.vaccinate(n - 1, &mut next, &mut vaccinated);
+        return self;
+    }
+
+    /// Compute a 4th order runge-kutta time step for the system. The contact
+    /// matrix is held fixed over the step.
+    fn rk4_step(&self, t: usize) -> [SystemVars; 4] {
+        let h = [self.step_size / 2.0, self.step_size / 2.0, self.step_size];
+        let contacts = self.contact_matrix_at((t as f64) * self.step_size);
+        let y0 = self.vars_at(t);
+        let k1 = self.derivatives(&y0, &contacts);
+        let mut k = [k1, self.init_vars(), self.init_vars(), self.init_vars()];
+        for i in 0..3 {
+            let mut y = self.init_vars();
+            for g in 0..self.n_groups {
+                y.s[g] = y0.s[g] + (h[i] * k[i].s[g]);
+                y.v[g] = y0.v[g] + (h[i] * k[i].v[g]);
+                y.i[g] = y0.i[g] + (h[i] * k[i].i[g]);
+                y.r[g] = y0.r[g] + (h[i] * k[i].r[g]);
+            }
+            k[i + 1] = self.derivatives(&y, &contacts);
+        }
+        return k;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let n = ((self.length as f64) / self.step_size).ceil() as usize;
+        let w = self.step_size / 6.0;
+        let mut next = 0;
+        let mut vaccinated = vec![0.0; self.n_groups];
+        for t in 0..n - 1 {
+            self.vaccinate(t, &mut next, &mut vaccinated);
+            let k = self.rk4_step(t);
+            for g in 0..self.n_groups {
+                let ds = (k[0].s[g] + (2.0 * k[1].s[g]) + (2.0 * k[2].s[g]) + k[3].s[g]) * w;
+                let dv = (k[0].v[g] + (2.0 * k[1].v[g]) + (2.0 * k[2].v[g]) + k[3].v[g]) * w;
+                let di = (k[0].i[g] + (2.0 * k[1].i[g]) + (2.0 * k[2].i[g]) + k[3].i[g]) * w;
+                let dr = (k[0].r[g] + (2.0 * k[1].r[g]) + (2.0 * k[2].r[g]) + k[3].r[g]) * w;
+                self.s_popf[(t + 1, g)] = self.s_popf[(t, g)] + ds;
+                self.v_popf[(t + 1, g)] = self.v_popf[(t, g)] + dv;
+                self.i_popf[(t + 1, g)] = self.i_popf[(t, g)] + di;
+                self.r_popf[(t + 1, g)] = self.r_popf[(t, g)] + dr;
+            }
+        }
+        self.vaccinate(n - 1, &mut next, &mut vaccinated);
+        return self;
+    }
+
+    /// Compartments in long format, one record per time, age group and
+    /// compartment. Groups are named by `group_names`, or by index if it is
+    /// empty.
+    pub fn to_long(&self, group_names: &[String]) -> Vec<LongRecord> {
+        let names = if group_names.is_empty() {
+            index_names(self.n_groups)
+        } else {
+            group_names.to_vec()
+        };
+        return to_long(
+            self.step_size,
+            &names,
+            &[
+                ("s", &self.s_popf),
+                ("v", &self.v_popf),
+                ("i", &self.i_popf),
+                ("r", &self.r_popf),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::age::{ContactSetting, Model, assortative_contacts};
+    use crate::sirrs::data::CoverageRecord;
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::{Mat, mat};
+
+    fn two_group_model() -> Model {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            mat![[0.6], [0.4]],
+            mat![[8.0, 2.0], [3.0, 5.0]],
+            0.01,
+            Fraction::of(0.05),
+            Rate::per_day(0.2),
+            Fraction::of(0.9),
+        );
+        return model;
+    }
+
+    #[test]
+    fn test_new() {
+        let model = Model::new();
+        assert_eq!(
+            model.n_groups, 0,
+            "Bad n_groups, expected 0 got {}",
+            model.n_groups
+        );
+        assert_eq!(
+            model.s_popf,
+            Mat::new(),
+            "Bad s_popf, expected Mat::new() got {:?}",
+            model.s_popf,
+        );
+    }
+
+    #[test]
+    fn test_init_popf() {
+        let mut model = two_group_model();
+        model.init_popf();
+        assert_eq!(
+            model.s_popf.shape(),
+            (100, 2),
+            "Bad s_popf dimensions, expected {:?} got {:?}",
+            (100, 2),
+            model.s_popf.shape()
+        );
+        for g in 0..2 {
+            let total = model.s_popf[(0, g)] + model.i_popf[(0, g)];
+            assert!(
+                (total - model.population[(g, 0)]).abs() < 1e-12,
+                "Bad group {} initial total, expected {} got {}",
+                g,
+                model.population[(g, 0)],
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_groups() {
+        let mut model = two_group_model();
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            for g in 0..2 {
+                let total = model.s_popf[(t, g)]
+                    + model.v_popf[(t, g)]
+                    + model.i_popf[(t, g)]
+                    + model.r_popf[(t, g)];
+                assert!(
+                    (total - model.population[(g, 0)]).abs() < 1e-9,
+                    "Group {} not conserved at index {}, got {}",
+                    g,
+                    t,
+                    total
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_contact_matrix_at() {
+        let mut model = two_group_model();
+        let mut school = ContactSetting::new("school", mat![[6.0, 0.0], [0.0, 1.0]]);
+        school.intervention(10.0, 66.0, 0.0);
+        let home = ContactSetting::new("home", mat![[2.0, 2.0], [3.0, 4.0]]);
+        model.contact_settings(vec![home, school]);
+        assert_eq!(
+            model.contact_matrix_at(0.0),
+            mat![[8.0, 2.0], [3.0, 5.0]],
+            "Bad contact matrix before intervention, got {:?}",
+            model.contact_matrix_at(0.0)
+        );
+        assert_eq!(
+            model.contact_matrix_at(10.0),
+            mat![[2.0, 2.0], [3.0, 4.0]],
+            "Bad contact matrix during intervention, got {:?}",
+            model.contact_matrix_at(10.0)
+        );
+        assert_eq!(
+            model.contact_matrix_at(66.0),
+            mat![[8.0, 2.0], [3.0, 5.0]],
+            "Bad contact matrix after intervention, got {:?}",
+            model.contact_matrix_at(66.0)
+        );
+    }
+
+    #[test]
+    fn test_school_closure_reduces_infections() {
+        let mut baseline = two_group_model();
+        baseline.init_popf();
+        baseline.run_rk4();
+        let mut closed = two_group_model();
+        let mut school = ContactSetting::new("school", mat![[6.0, 0.0], [0.0, 1.0]]);
+        school.intervention(0.0, 50.0, 0.0);
+        let home = ContactSetting::new("home", mat![[2.0, 2.0], [3.0, 4.0]]);
+        closed.contact_settings(vec![home, school]);
+        closed.init_popf();
+        closed.run_rk4();
+        let last = baseline.r_popf.nrows() - 1;
+        assert!(
+            closed.r_popf[(last, 0)] < baseline.r_popf[(last, 0)],
+            "Expected school closure to reduce removed fraction, got {} vs {}",
+            closed.r_popf[(last, 0)],
+            baseline.r_popf[(last, 0)]
+        );
+    }
+
+    #[test]
+    fn test_assortative_contacts() {
+        let population = mat![[0.6], [0.4]];
+        let activity = mat![[10.0], [5.0]];
+        let proportionate = assortative_contacts(&population, &activity, 0.0);
+        // Contacts made by group 0 with group 1 equal those of group 1 with group 0.
+        assert!(
+            ((population[(0, 0)] * proportionate[(0, 1)])
+                - (population[(1, 0)] * proportionate[(1, 0)]))
+                .abs()
+                < 1e-12,
+            "Proportionate contacts are not balanced, got {:?}",
+            proportionate
+        );
+        let assortative = assortative_contacts(&population, &activity, 1.0);
+        assert_eq!(
+            assortative,
+            mat![[10.0, 0.0], [0.0, 5.0]],
+            "Bad fully assortative contacts, got {:?}",
+            assortative
+        );
+        let mixed = assortative_contacts(&population, &activity, 0.3);
+        for a in 0..2 {
+            let row: f64 = (0..2).map(|b| mixed[(a, b)]).sum();
+            assert!(
+                (row - activity[(a, 0)]).abs() < 1e-12,
+                "Bad group {} total contacts, expected {} got {}",
+                a,
+                activity[(a, 0)],
+                row
+            );
+        }
+    }
+
+    #[test]
+    fn test_vaccination_coverage() {
+        let mut model = two_group_model();
+        model.vaccination(vec![
+            CoverageRecord {
+                t: 5.0,
+                group: 1,
+                coverage: 0.5,
+            },
+            CoverageRecord {
+                t: 2.0,
+                group: 1,
+                coverage: 0.25,
+            },
+            CoverageRecord {
+                t: 49.5,
+                group: 0,
+                coverage: 0.1,
+            },
+        ]);
+        model.init_popf();
+        model.run_rk4();
+        assert_eq!(
+            model.v_popf[(3, 1)],
+            0.0,
+            "Bad v_popf before first record, expected 0.0 got {}",
+            model.v_popf[(3, 1)]
+        );
+        assert!(
+            (model.v_popf[(4, 1)] - (0.25 * 0.4)).abs() < 1e-12,
+            "Bad v_popf at first record, expected {} got {}",
+            0.25 * 0.4,
+            model.v_popf[(4, 1)]
+        );
+        assert!(
+            model.v_popf[(10, 1)] > model.v_popf[(9, 1)],
+            "Expected second record to vaccinate more of group 1"
+        );
+        let last = model.v_popf.nrows() - 1;
+        for t in 0..last {
+            assert_eq!(
+                model.v_popf[(t, 0)],
+                0.0,
+                "Bad v_popf in unvaccinated group at index {}, got {}",
+                t,
+                model.v_popf[(t, 0)]
+            );
+        }
+        assert!(
+            (model.v_popf[(last, 0)] - (0.1 * 0.6)).abs() < 1e-12,
+            "Bad v_popf at a record on the last index, expected {} got {}",
+            0.1 * 0.6,
+            model.v_popf[(last, 0)]
+        );
+    }
+}
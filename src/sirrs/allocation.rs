@@ -0,0 +1,343 @@
+//! Vaccine prioritization across age or risk groups.
+//!
+//! A fixed daily vaccine supply, as a population fraction per unit time, is
+//! split between groups in fixed shares. Each group's doses accumulate into
+//! cumulative coverage, capped at the whole group, which drives the
+//! vaccination of the age-structured model, see [`crate::age`]. The shares
+//! minimizing an [`Objective`] are found by grid search over the simplex of
+//! shares, or by the Nelder-Mead method on softmax-transformed shares.
+use crate::sirrs::age;
+use crate::sirrs::data::CoverageRecord;
+use crate::sirrs::fit::nelder_mead;
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Quantity to minimize.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Objective {
+    /// Total infections, as a population fraction.
+    Infections,
+    /// Deaths, infections weighted by the infection fatality ratio of each
+    /// group. Column with one row per group.
+    Deaths(Mat<f64>),
+    /// Peak hospital load, the largest over time of infectious fractions
+    /// weighted by the probability of hospitalization of each group. Column
+    /// with one row per group.
+    PeakHospitalLoad(Mat<f64>),
+}
+
+impl Objective {
+    /// Value of the objective for a solved model.
+    pub fn evaluate(&self, model: &age::Model) -> f64 {
+        let last = model.s_popf.nrows() - 1;
+        let infected =
+            |g: usize| model.population[(g, 0)] - model.s_popf[(last, g)] - model.v_popf[(last, g)];
+        return match self {
+            Objective::Infections => (0..model.n_groups).map(infected).sum(),
+            Objective::Deaths(ifr) => (0..model.n_groups).map(|g| ifr[(g, 0)] * infected(g)).sum(),
+            Objective::PeakHospitalLoad(hospitalization) => (0..=last)
+                .map(|t| {
+                    (0..model.n_groups)
+                        .map(|g| hospitalization[(g, 0)] * model.i_popf[(t, g)])
+                        .sum::<f64>()
+                })
+                .fold(0.0, f64::max),
+        };
+    }
+}
+
+/// Cumulative coverage records, one per group per unit time from 1 to
+/// `length`, of `daily_supply` split in `shares` between groups of
+/// `population`. Coverage of a group is capped at 1.
+pub fn coverage_schedule(
+    population: &Mat<f64>,
+    daily_supply: f64,
+    shares: &[f64],
+    length: usize,
+) -> Vec<CoverageRecord> {
+    let mut records = Vec::with_capacity(length * shares.len());
+    for day in 1..=length {
+        for (g, share) in shares.iter().enumerate() {
+            let doses = daily_supply * share * (day as f64);
+            records.push(CoverageRecord {
+                t: day as f64,
+                group: g,
+                coverage: (doses / population[(g, 0)]).min(1.0),
+            });
+        }
+    }
+    return records;
+}
+
+/// Every way of splitting `resolution` units between `n` groups.
+fn compositions(n: usize, resolution: usize) -> Vec<Vec<usize>> {
+    if n == 1 {
+        return vec![vec![resolution]];
+    }
+    let mut all = Vec::new();
+    for first in 0..=resolution {
+        for mut rest in compositions(n - 1, resolution - first) {
+            rest.insert(0, first);
+            all.push(rest);
+        }
+    }
+    return all;
+}
+
+/// Find the split of a daily vaccine supply between groups that minimizes
+/// an objective.
+pub struct VaccineAllocation {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Population fraction in each group. Column with one row per group.
+    pub population: Mat<f64>,
+    /// Contacts per unit time between groups, see [`age::Model`].
+    pub contact_matrix: Mat<f64>,
+    /// Initial infectious fraction within each group.
+    pub i_init: f64,
+    /// Probability of transmission per contact.
+    pub incidence_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+    /// Reduction in susceptibility of vaccinated individuals.
+    pub vaccine_efficacy: f64,
+    /// Doses available per unit time, as a population fraction.
+    pub daily_supply: f64,
+    /// Quantity to minimize.
+    pub objective: Objective,
+    /// Share of the supply given to each group. After optimizing, the best
+    /// shares found.
+    pub shares: Vec<f64>,
+    /// Objective at `shares`.
+    pub value: f64,
+    /// Number of model solves used by the last optimization.
+    pub evaluations: usize,
+}
+
+impl VaccineAllocation {
+    /// Create a new allocation object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            population: Mat::new(),
+            contact_matrix: Mat::new(),
+            i_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            vaccine_efficacy: 0.0,
+            daily_supply: 0.0,
+            objective: Objective::Infections,
+            shares: Vec::new(),
+            value: f64::INFINITY,
+            evaluations: 0,
+        };
+    }
+
+    /// Configure the allocation problem. Shares start equal.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: Mat<f64>,
+        contact_matrix: Mat<f64>,
+        i_init: f64,
+        incidence_rate: Fraction,
+        removal_rate: Rate,
+        vaccine_efficacy: Fraction,
+        daily_supply: f64,
+        objective: Objective,
+    ) -> &mut Self {
+        let n_groups = population.nrows();
+        self.length = length;
+        self.step_size = step_size;
+        self.population = population;
+        self.contact_matrix = contact_matrix;
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.value();
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.vaccine_efficacy = vaccine_efficacy.value();
+        self.daily_supply = daily_supply;
+        self.objective = objective;
+        self.shares = vec![1.0 / (n_groups as f64); n_groups];
+        self.value = f64::INFINITY;
+        self.evaluations = 0;
+        return self;
+    }
+
+    /// Solve the age-structured model with the supply split in `shares`.
+    pub fn simulate(&self, shares: &[f64]) -> age::Model {
+        let mut model = age::Model::new();
+        model.configure(
+            self.length,
+            self.step_size,
+            self.population.clone(),
+            self.contact_matrix.clone(),
+            self.i_init,
+            Fraction::of(self.incidence_rate),
+            Rate::per_day(self.removal_rate),
+            Fraction::of(self.vaccine_efficacy),
+        );
+        model.vaccination(coverage_schedule(
+            &self.population,
+            self.daily_supply,
+            shares,
+            self.length,
+        ));
+        model.init_popf();
+        model.run_rk4();
+        return model;
+    }
+
+    /// Objective with the supply split in `shares`.
+    pub fn objective_at(&self, shares: &[f64]) -> f64 {
+        return self.objective.evaluate(&self.simulate(shares));
+    }
+
+    /// Evaluate every split of the supply into multiples of
+    /// `1 / resolution` and keep the best. The number of splits grows as
+    /// `resolution` to the power of the number of groups less one.
+    pub fn run_grid_search(&mut self, resolution: usize) -> &VaccineAllocation {
+        let n_groups = self.population.nrows();
+        self.value = f64::INFINITY;
+        self.evaluations = 0;
+        for units in compositions(n_groups, resolution) {
+            let shares: Vec<f64> = units
+                .iter()
+                .map(|u| (*u as f64) / (resolution as f64))
+                .collect();
+            let value = self.objective_at(&shares);
+            self.evaluations += 1;
+            if value < self.value {
+                self.value = value;
+                self.shares = shares;
+            }
+        }
+        return self;
+    }
+
+    /// Minimize the objective by the Nelder-Mead method, starting from the
+    /// current shares. Shares are the softmax of free parameters, the last
+    /// group's fixed at 0, so every candidate is a valid split; shares of
+    /// exactly 0 are approached but never reached.
+    pub fn run_nelder_mead(&mut self, max_iter: usize) -> &VaccineAllocation {
+        let n_groups = self.population.nrows();
+        let softmax = |x: &[f64]| -> Vec<f64> {
+            let weights: Vec<f64> = x.iter().chain([0.0].iter()).map(|v| exp(*v)).collect();
+            let total: f64 = weights.iter().sum();
+            return weights.iter().map(|w| w / total).collect();
+        };
+        let last = self.shares[n_groups - 1].max(1e-6);
+        let x0: Vec<f64> = self.shares[..n_groups - 1]
+            .iter()
+            .map(|s| ln(s.max(1e-6) / last))
+            .collect();
+        let (x, value, iterations) = nelder_mead(
+            |x: &[f64]| self.objective_at(&softmax(x)),
+            &x0,
+            1.0,
+            max_iter,
+            1e-12,
+        );
+        self.shares = softmax(&x);
+        self.value = value;
+        self.evaluations = iterations;
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::allocation::{Objective, VaccineAllocation, coverage_schedule};
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::mat;
+
+    fn allocation(objective: Objective) -> VaccineAllocation {
+        // A small, highly connected group 0 and a larger, less connected
+        // group 1.
+        let mut allocation = VaccineAllocation::new();
+        allocation.configure(
+            80,
+            0.5,
+            mat![[0.3], [0.7]],
+            mat![[12.0, 1.0], [1.0, 5.0]],
+            0.001,
+            Fraction::of(0.05),
+            Rate::per_day(0.2),
+            Fraction::of(0.9),
+            0.01,
+            objective,
+        );
+        return allocation;
+    }
+
+    #[test]
+    fn test_coverage_schedule() {
+        let records = coverage_schedule(&mat![[0.2], [0.8]], 0.05, &[0.5, 0.5], 10);
+        assert_eq!(records.len(), 20, "Bad number of records");
+        assert!(
+            (records[2].coverage - 0.25).abs() < 1e-12,
+            "Bad group 0 coverage on day 2, expected 0.25 got {}",
+            records[2].coverage
+        );
+        assert_eq!(
+            records[18].coverage, 1.0,
+            "Expected coverage to be capped at 1"
+        );
+    }
+
+    #[test]
+    fn test_grid_search() {
+        let mut infections = allocation(Objective::Infections);
+        infections.run_grid_search(10);
+        assert_eq!(infections.evaluations, 11, "Bad number of evaluations");
+        assert!(
+            infections.shares[0] > 0.5,
+            "Expected the connected group to be prioritized against infections, got {:?}",
+            infections.shares
+        );
+        for share in [0.0, 0.5, 1.0] {
+            let value = infections.objective_at(&[share, 1.0 - share]);
+            assert!(
+                infections.value <= value,
+                "Expected the best split to beat {}, got {} and {}",
+                share,
+                infections.value,
+                value
+            );
+        }
+        // When only group 1 can die, protecting it directly competes with
+        // cutting transmission through group 0.
+        let mut deaths = allocation(Objective::Deaths(mat![[0.0], [0.01]]));
+        deaths.run_grid_search(10);
+        assert!(
+            deaths.shares[1] > infections.shares[1],
+            "Expected deaths to shift doses to group 1, got {:?} and {:?}",
+            deaths.shares,
+            infections.shares
+        );
+    }
+
+    #[test]
+    fn test_nelder_mead() {
+        let mut grid = allocation(Objective::PeakHospitalLoad(mat![[0.01], [0.05]]));
+        grid.run_grid_search(20);
+        let mut search = allocation(Objective::PeakHospitalLoad(mat![[0.01], [0.05]]));
+        search.run_nelder_mead(100);
+        assert!(
+            search.value <= grid.value * (1.0 + 1e-3),
+            "Expected Nelder-Mead to match the grid search, got {} and {}",
+            search.value,
+            grid.value
+        );
+        let total: f64 = search.shares.iter().sum();
+        assert!(
+            (total - 1.0).abs() < 1e-12,
+            "Bad shares, expected a sum of 1 got {}",
+            total
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Batched SIR trajectories for parameter sweeps.
+//!
+//! [`Batch`] integrates many parameter sets of the [`crate::sir::Model`]
+//! equations at once. The state of every run is held as columns over the
+//! parameter sets, and each runge-kutta stage is computed with faer's
+//! elementwise kernels over those columns, so a sweep runs as vectorized
+//! loops across runs instead of one scalar loop per run. Each run takes
+//! exactly the steps [`crate::sir::Model::run_rk4`] would take for it alone.
+use faer::{Col, Mat, unzip, zip};
+
+/// Population fractions, or their rates of change, of every run, one
+/// element per run. `x` is new infections.
+struct Columns {
+    s: Col<f64>,
+    i: Col<f64>,
+    r: Col<f64>,
+    x: Col<f64>,
+}
+
+impl Columns {
+    fn zeros(n: usize) -> Self {
+        return Self {
+            s: Col::zeros(n),
+            i: Col::zeros(n),
+            r: Col::zeros(n),
+            x: Col::zeros(n),
+        };
+    }
+}
+
+/// Many runs of the SIR model, solved together.
+pub struct Batch {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Parameters of each run (row), as columns `i_popf_init`,
+    /// `r_popf_init`, `incidence_rate`, `removal_rate` and `recovery_rate`,
+    /// in the order of [`crate::sir::Model::configure`].
+    pub parameters: Mat<f64>,
+    /// Susceptible population fraction at each index (row) of each run
+    /// (column).
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index (row) of each run
+    /// (column).
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index (row) of each run (column).
+    pub r_popf: Mat<f64>,
+    /// New infections, as a population fraction, over the step ending at
+    /// each index (row) of each run (column). Zero at index 0.
+    pub incidence: Mat<f64>,
+}
+
+impl Batch {
+    /// Create a new batch object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            parameters: Mat::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            incidence: Mat::new(),
+        };
+    }
+
+    /// Configure the series and the parameters of each run, one row per run.
+    pub fn configure(&mut self, length: usize, step_size: f64, parameters: Mat<f64>) -> &mut Self {
+        assert_eq!(
+            parameters.ncols(),
+            5,
+            "expected 5 parameter columns, got {}",
+            parameters.ncols()
+        );
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        let n_runs = parameters.nrows();
+        self.length = length;
+        self.step_size = step_size;
+        self.parameters = parameters;
+        self.s_popf = Mat::zeros(n_steps, n_runs);
+        self.i_popf = Mat::zeros(n_steps, n_runs);
+        self.r_popf = Mat::zeros(n_steps, n_runs);
+        self.incidence = Mat::zeros(n_steps, n_runs);
+        return self;
+    }
+
+    /// Number of runs.
+    pub fn n_runs(&self) -> usize {
+        return self.parameters.nrows();
+    }
+
+    /// Rates of change of every run at `y`, written into `k`.
+    fn derivatives(&self, y: &Columns, k: &mut Columns) {
+        let incidence_rate = self.parameters.col(2);
+        let removal_rate = self.parameters.col(3);
+        let recovery_rate = self.parameters.col(4);
+        zip!(
+            &mut k.s,
+            &mut k.i,
+            &mut k.r,
+            &mut k.x,
+            &y.s,
+            &y.i,
+            incidence_rate,
+            removal_rate,
+            recovery_rate
+        )
+        .for_each(|unzip!(ks, ki, kr, kx, s, i, beta, removal, recovery)| {
+            *ks = (-*beta * *s * *i) + (*recovery * *i);
+            *ki = (*beta * *s * *i) - ((*recovery + *removal) * *i);
+            *kr = *removal * *i;
+            *kx = *beta * *s * *i;
+        });
+    }
+
+    /// Stage values `y0 + (k * h)`, written into `y`.
+    fn stage(y0: &Columns, k: &Columns, h: f64, y: &mut Columns) {
+        for (out, start, rate) in [
+            (&mut y.s, &y0.s, &k.s),
+            (&mut y.i, &y0.i, &k.i),
+            (&mut y.r, &y0.r, &k.r),
+        ] {
+            zip!(out, start, rate).for_each(|unzip!(out, start, rate)| {
+                *out = *start + (*rate * h);
+            });
+        }
+    }
+
+    /// Solve every run by the 4th order Runge-Kutta method.
+    pub fn run_rk4(&mut self) -> &Self {
+        let n = self.s_popf.nrows();
+        let n_runs = self.n_runs();
+        let h = [
+            self.step_size / 2.0,
+            self.step_size / 2.0,
+            self.step_size,
+            self.step_size,
+        ];
+        let h6 = self.step_size / 6.0;
+        let mut y0 = Columns::zeros(n_runs);
+        let mut y = Columns::zeros(n_runs);
+        let mut k = [
+            Columns::zeros(n_runs),
+            Columns::zeros(n_runs),
+            Columns::zeros(n_runs),
+            Columns::zeros(n_runs),
+        ];
+        zip!(
+            &mut y0.s,
+            &mut y0.i,
+            &mut y0.r,
+            self.parameters.col(0),
+            self.parameters.col(1)
+        )
+        .for_each(|unzip!(s, i, r, i_init, r_init)| {
+            *s = 1.0 - *i_init - *r_init;
+            *i = *i_init;
+            *r = *r_init;
+        });
+        self.record(0, &y0);
+        for t in 1..n {
+            self.derivatives(&y0, &mut k[0]);
+            for stage in 1..4 {
+                let (done, rest) = k.split_at_mut(stage);
+                Self::stage(&y0, &done[stage - 1], h[stage - 1], &mut y);
+                self.derivatives(&y, &mut rest[0]);
+            }
+            for (out, k1, k2, k3, k4) in [
+                (&mut y0.s, &k[0].s, &k[1].s, &k[2].s, &k[3].s),
+                (&mut y0.i, &k[0].i, &k[1].i, &k[2].i, &k[3].i),
+                (&mut y0.r, &k[0].r, &k[1].r, &k[2].r, &k[3].r),
+            ] {
+                zip!(out, k1, k2, k3, k4).for_each(|unzip!(out, k1, k2, k3, k4)| {
+                    *out += (*k1 + (2.0 * *k2) + (2.0 * *k3) + *k4) * h6;
+                });
+            }
+            zip!(&mut y0.x, &k[0].x, &k[1].x, &k[2].x, &k[3].x).for_each(
+                |unzip!(out, k1, k2, k3, k4)| {
+                    *out = (*k1 + (2.0 * *k2) + (2.0 * *k3) + *k4) * h6;
+                },
+            );
+            self.record(t, &y0);
+        }
+        return self;
+    }
+
+    /// Store the state and the new infections of every run at index `t`.
+    fn record(&mut self, t: usize, y: &Columns) {
+        for run in 0..self.n_runs() {
+            self.s_popf[(t, run)] = y.s[run];
+            self.i_popf[(t, run)] = y.i[run];
+            self.r_popf[(t, run)] = y.r[run];
+            self.incidence[(t, run)] = y.x[run];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::batch::Batch;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use faer::mat;
+
+    #[test]
+    fn test_run_rk4() {
+        let parameters = mat![
+            [0.01, 0.0, 0.4, 0.1, 0.0],
+            [0.001, 0.1, 0.3, 0.2, 0.05],
+            [0.05, 0.0, 0.9, 0.05, 0.01],
+        ];
+        let mut batch = Batch::new();
+        batch.configure(40, 0.5, parameters.clone());
+        batch.run_rk4();
+        for run in 0..3 {
+            let p = |j: usize| parameters[(run, j)];
+            let mut model = sir::Model::new();
+            model.configure(
+                40,
+                0.5,
+                p(0),
+                p(1),
+                Rate::per_day(p(2)),
+                Rate::per_day(p(3)),
+                Rate::per_day(p(4)),
+            );
+            model.init_popf();
+            model.run_rk4();
+            for t in 0..model.s_popf.nrows() {
+                for (name, value, expected) in [
+                    ("s_popf", batch.s_popf[(t, run)], model.s_popf[(t, 0)]),
+                    ("i_popf", batch.i_popf[(t, run)], model.i_popf[(t, 0)]),
+                    ("r_popf", batch.r_popf[(t, run)], model.r_popf[(t, 0)]),
+                    (
+                        "incidence",
+                        batch.incidence[(t, run)],
+                        model.incidence[(t, 0)],
+                    ),
+                ] {
+                    assert_eq!(
+                        value, expected,
+                        "Bad {} of run {} at index {}, expected {} got {}",
+                        name, run, t, expected, value
+                    );
+                }
+            }
+        }
+    }
+}
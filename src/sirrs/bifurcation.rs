@@ -0,0 +1,181 @@
+//! Threshold scans for bifurcation diagrams.
+//!
+//! [`scan`] varies one rate of a [`crate::sir::Model`] over a range of
+//! values and solves the equilibria at each with
+//! [`crate::sir::Model::solve_equilibrium`]. The disease-free equilibrium
+//! loses stability where R0 crosses 1, which is also where the endemic
+//! equilibrium appears when there is no removal, and the scan locates that
+//! crossing between the scanned values. [`write_scan`] writes the table for
+//! plotting.
+use crate::sirrs::sir;
+use crate::sirrs::units::Rate;
+use std::io::{self, Write};
+
+/// Equilibria at one scanned value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanRow {
+    /// Value of the scanned rate.
+    pub value: f64,
+    /// Basic reproduction number, incidence_rate × S / (removal_rate +
+    /// recovery_rate) at the disease-free equilibrium.
+    pub r0: f64,
+    /// Growth rate of infection at the disease-free equilibrium.
+    pub growth_rate: f64,
+    /// Whether the disease-free equilibrium is stable.
+    pub disease_free_stable: bool,
+    /// Infectious fraction at the endemic equilibrium, NaN if there is none.
+    pub endemic_i: f64,
+    /// Whether the endemic equilibrium is stable, false if there is none.
+    pub endemic_stable: bool,
+}
+
+/// Equilibria over a range of values of one rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan {
+    /// Name of the scanned rate.
+    pub parameter: String,
+    /// One row per scanned value, in the order given.
+    pub rows: Vec<ScanRow>,
+    /// Value at which R0 = 1, interpolated linearly in R0 between the first
+    /// pair of consecutive values it falls between, if any.
+    pub threshold: Option<f64>,
+}
+
+/// Scan `parameter`, one of `incidence_rate`, `removal_rate` or
+/// `recovery_rate`, of `model` over `values`, other settings unchanged.
+pub fn scan(model: &sir::Model, parameter: &str, values: &[f64]) -> Scan {
+    let mut rows = Vec::with_capacity(values.len());
+    for &value in values.iter() {
+        let mut varied = sir::Model::new();
+        varied.configure(
+            model.length,
+            model.step_size,
+            model.i_popf_init,
+            model.r_popf_init,
+            Rate::per_day(model.incidence_rate),
+            Rate::per_day(model.removal_rate),
+            Rate::per_day(model.recovery_rate),
+        );
+        varied.incidence_rate_changes = model.incidence_rate_changes.clone();
+        match parameter {
+            "incidence_rate" => {
+                varied.changepoints(Vec::new());
+                varied.incidence_rate = value;
+            }
+            "removal_rate" => varied.removal_rate = value,
+            "recovery_rate" => varied.recovery_rate = value,
+            _ => panic!("{} is not a rate of the SIR model", parameter),
+        }
+        let equilibria = varied.solve_equilibrium();
+        let free = &equilibria[0];
+        let beta = varied.incidence_rate_at(f64::INFINITY);
+        let r0 = beta * free.s / (varied.removal_rate + varied.recovery_rate);
+        let growth_rate = (beta * free.s) - varied.removal_rate - varied.recovery_rate;
+        let endemic = equilibria.iter().find(|e| e.endemic);
+        rows.push(ScanRow {
+            value,
+            r0,
+            growth_rate,
+            disease_free_stable: free.stable,
+            endemic_i: endemic.map_or(f64::NAN, |e| e.i),
+            endemic_stable: endemic.is_some_and(|e| e.stable),
+        });
+    }
+    let threshold = rows.windows(2).find_map(|pair| {
+        let (a, b) = (&pair[0], &pair[1]);
+        if (a.r0 - 1.0) * (b.r0 - 1.0) > 0.0 {
+            return None;
+        }
+        if a.r0 == b.r0 {
+            return Some(a.value);
+        }
+        return Some(a.value + ((1.0 - a.r0) * (b.value - a.value) / (b.r0 - a.r0)));
+    });
+    return Scan {
+        parameter: parameter.to_string(),
+        rows,
+        threshold,
+    };
+}
+
+/// Write a scan as csv with columns `<parameter>,r0,growth_rate,
+/// disease_free_stable,endemic_i,endemic_stable`. Missing endemic
+/// equilibria are written as empty fields.
+pub fn write_scan<W: Write>(scan: &Scan, mut writer: W) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{},r0,growth_rate,disease_free_stable,endemic_i,endemic_stable",
+        scan.parameter
+    )?;
+    for row in scan.rows.iter() {
+        let endemic_i = if row.endemic_i.is_nan() {
+            String::new()
+        } else {
+            row.endemic_i.to_string()
+        };
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            row.value,
+            row.r0,
+            row.growth_rate,
+            row.disease_free_stable,
+            endemic_i,
+            row.endemic_stable
+        )?;
+    }
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::bifurcation::{scan, write_scan};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_scan() {
+        let mut model = sir::Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+            Rate::per_day(0.2),
+        );
+        let values: Vec<f64> = (0..=10).map(|k| 0.05 * (k as f64)).collect();
+        let scan = scan(&model, "incidence_rate", &values);
+        let threshold = scan.threshold.expect("Expected a threshold");
+        assert!(
+            (threshold - 0.2).abs() < 1e-12,
+            "Bad threshold, expected 0.2 got {}",
+            threshold
+        );
+        for row in scan.rows.iter() {
+            let endemic = row.r0 > 1.0 + 1e-9;
+            assert_eq!(
+                row.endemic_i.is_nan(),
+                !endemic,
+                "Bad endemic equilibrium at {}, got {:?}",
+                row.value,
+                row
+            );
+            assert_eq!(
+                row.disease_free_stable, !endemic,
+                "Bad disease-free stability at {}, got {:?}",
+                row.value, row
+            );
+        }
+        let mut buffer = Vec::new();
+        write_scan(&scan, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(
+            text.starts_with("incidence_rate,r0,growth_rate,"),
+            "Bad header, got {}",
+            text
+        );
+        assert_eq!(text.lines().count(), 12, "Bad number of lines");
+    }
+}
@@ -0,0 +1,302 @@
+//! Burden of disease in years lived with disability and years of life lost.
+//!
+//! Burden is counted as in the Global Burden of Disease study:
+//!  - YLD, years lived with disability, is prevalent person-time weighted
+//!    by the disability weight of the condition, from 0 (full health) to 1
+//!  - YLL, years of life lost, is each death from the condition times the
+//!    remaining life expectancy at the age it happens
+//!  - DALY, disability-adjusted life years, is their sum
+//!
+//! Future years may be discounted at a constant rate, weighting years `t`
+//! into the run by `exp(-r t)`; the GBD itself does not discount, the
+//! default. [`burden`] takes prevalent cases and deaths from any model, with
+//! one life expectancy for every death, and [`dismod_burden`] and
+//! [`age_time_burden`] the birth cohort of a solved DisMod model, with life
+//! expectancy by age from a [`LifeTable`].
+use crate::sirrs::dismod;
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::lifetable::LifeTable;
+use crate::sirrs::reproducible::exp;
+use faer::Mat;
+
+/// Burden accrued over the step ending at each index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burden {
+    /// Years lived with disability. 1D Array with one element per index.
+    pub yld: Mat<f64>,
+    /// Years of life lost. 1D Array with one element per index.
+    pub yll: Mat<f64>,
+}
+
+impl Burden {
+    /// Disability-adjusted life years, YLD + YLL, at each index.
+    pub fn daly(&self) -> Mat<f64> {
+        return &self.yld + &self.yll;
+    }
+
+    /// Total years lived with disability.
+    pub fn total_yld(&self) -> f64 {
+        return (0..self.yld.nrows()).map(|t| self.yld[(t, 0)]).sum();
+    }
+
+    /// Total years of life lost.
+    pub fn total_yll(&self) -> f64 {
+        return (0..self.yll.nrows()).map(|t| self.yll[(t, 0)]).sum();
+    }
+
+    /// Total disability-adjusted life years.
+    pub fn total_daly(&self) -> f64 {
+        return self.total_yld() + self.total_yll();
+    }
+}
+
+/// Check a disability weight and discount rate.
+fn check_weights(disability_weight: f64, discount_rate: f64) {
+    assert!(
+        (0.0..=1.0).contains(&disability_weight),
+        "disability_weight must be in [0, 1], got {}",
+        disability_weight
+    );
+    assert!(
+        discount_rate >= 0.0,
+        "discount_rate must be non-negative, got {}",
+        discount_rate
+    );
+}
+
+/// Burden from `prevalent` cases at each index and `deaths` from the
+/// condition over the step ending at each index, both columns of counts
+/// on a grid of `step_size`, with every death losing `life_expectancy`
+/// years.
+///
+/// Prevalent person-time over the step ending at index `t` is taken by the
+/// trapezoidal rule, so index 0 has no YLD.
+pub fn burden(
+    prevalent: &Mat<f64>,
+    deaths: &Mat<f64>,
+    step_size: f64,
+    disability_weight: f64,
+    life_expectancy: f64,
+    discount_rate: f64,
+) -> Burden {
+    assert_eq!(
+        prevalent.nrows(),
+        deaths.nrows(),
+        "prevalent and deaths must have one row per index"
+    );
+    return accrue(
+        prevalent,
+        deaths,
+        step_size,
+        disability_weight,
+        |_| life_expectancy,
+        discount_rate,
+    );
+}
+
+/// Burden of a solved [`dismod::Model`] in a birth cohort of `cohort_size`,
+/// the model's time being age. Deaths from the condition are excess
+/// mortality chi of those with it, each losing the life expectancy of
+/// `life_table` at the age of death. Discounting is from birth.
+pub fn dismod_burden(
+    model: &dismod::Model,
+    life_table: &LifeTable,
+    cohort_size: f64,
+    disability_weight: f64,
+    discount_rate: f64,
+) -> Burden {
+    return cohort_burden(
+        &model.c,
+        &model.grid(),
+        |_| model.chi,
+        life_table,
+        cohort_size,
+        disability_weight,
+        discount_rate,
+    );
+}
+
+/// Burden of a solved [`dismod::AgeTimeModel`] in a birth cohort of
+/// `cohort_size`, as [`dismod_burden`] with excess mortality from the
+/// model's rate grid along its solution.
+pub fn age_time_burden(
+    model: &dismod::AgeTimeModel,
+    life_table: &LifeTable,
+    cohort_size: f64,
+    disability_weight: f64,
+    discount_rate: f64,
+) -> Burden {
+    return cohort_burden(
+        &model.c,
+        &model.grid(),
+        |age| model.rates_at(age)[2],
+        life_table,
+        cohort_size,
+        disability_weight,
+        discount_rate,
+    );
+}
+
+/// Burden of a cohort with prevalence `c` by age on `grid` and excess
+/// mortality `chi(age)`. Deaths over each step are by the trapezoidal rule
+/// and lose the life expectancy at the middle of the step.
+fn cohort_burden(
+    c: &Mat<f64>,
+    grid: &TimeGrid,
+    chi: impl Fn(f64) -> f64,
+    life_table: &LifeTable,
+    cohort_size: f64,
+    disability_weight: f64,
+    discount_rate: f64,
+) -> Burden {
+    let n = c.nrows();
+    let h = grid.dt;
+    let prevalent = Mat::from_fn(n, 1, |t, _| cohort_size * c[(t, 0)]);
+    let deaths = Mat::from_fn(n, 1, |t, _| {
+        if t == 0 {
+            return 0.0;
+        }
+        let before = chi(grid.time(t - 1)) * prevalent[(t - 1, 0)];
+        0.5 * h * (before + (chi(grid.time(t)) * prevalent[(t, 0)]))
+    });
+    return accrue(
+        &prevalent,
+        &deaths,
+        h,
+        disability_weight,
+        |t| life_table.life_expectancy(grid.time(t) - (0.5 * h)),
+        discount_rate,
+    );
+}
+
+/// Burden from prevalent cases and deaths at each index, with deaths over
+/// the step ending at index `t` losing `life_expectancy(t)` years.
+fn accrue(
+    prevalent: &Mat<f64>,
+    deaths: &Mat<f64>,
+    step_size: f64,
+    disability_weight: f64,
+    life_expectancy: impl Fn(usize) -> f64,
+    discount_rate: f64,
+) -> Burden {
+    check_weights(disability_weight, discount_rate);
+    let n = prevalent.nrows();
+    let discount = |t: usize| exp(-discount_rate * step_size * ((t as f64) - 0.5));
+    let yld = Mat::from_fn(n, 1, |t, _| {
+        if t == 0 {
+            return 0.0;
+        }
+        let person_time = 0.5 * step_size * (prevalent[(t - 1, 0)] + prevalent[(t, 0)]);
+        disability_weight * person_time * discount(t)
+    });
+    let yll = Mat::from_fn(n, 1, |t, _| {
+        if t == 0 {
+            return 0.0;
+        }
+        deaths[(t, 0)] * life_expectancy(t) * discount(t)
+    });
+    return Burden { yld, yll };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::burden::{age_time_burden, burden, dismod_burden};
+    use crate::sirrs::dismod::{self, AgeTimeModel, RateGrid};
+    use crate::sirrs::lifetable::LifeTable;
+    use crate::sirrs::units::Rate;
+    use faer::Mat;
+
+    #[test]
+    fn test_burden() {
+        // 100 prevalent cases for 10 years with weight 0.2, and 3 deaths
+        // losing 30 years each.
+        let prevalent = Mat::from_fn(11, 1, |_, _| 100.0);
+        let deaths = Mat::from_fn(11, 1, |t, _| if t == 5 { 3.0 } else { 0.0 });
+        let result = burden(&prevalent, &deaths, 1.0, 0.2, 30.0, 0.0);
+        assert!(
+            (result.total_yld() - 200.0).abs() < 1e-9,
+            "Bad YLD, expected 200 got {}",
+            result.total_yld()
+        );
+        assert_eq!(result.total_yll(), 90.0, "Bad YLL");
+        assert!(
+            (result.total_daly() - 290.0).abs() < 1e-9,
+            "Bad DALY, expected 290 got {}",
+            result.total_daly()
+        );
+        let discounted = burden(&prevalent, &deaths, 1.0, 0.2, 30.0, 0.03);
+        assert!(
+            discounted.total_daly() < result.total_daly(),
+            "Expected discounting to reduce burden"
+        );
+    }
+
+    #[test]
+    fn test_dismod_burden() {
+        // Constant excess mortality and mortality: deaths from the condition
+        // are chi times the prevalent person-time.
+        let mut model = dismod::Model::new();
+        model.configure(
+            80,
+            0.1,
+            0.0,
+            Rate::per_year(0.02),
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.01),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let table = LifeTable::from_rates(vec![0.0], vec![0.01]);
+        let result = dismod_burden(&model, &table, 1000.0, 0.3, 0.0);
+        let person_time = result.total_yld() / 0.3;
+        assert!(
+            (result.total_yll() - (0.1 * person_time * 100.0)).abs() < 1e-6,
+            "Bad YLL, expected {} got {}",
+            0.1 * person_time * 100.0,
+            result.total_yll()
+        );
+        assert!(
+            result.daly()[(0, 0)] == 0.0,
+            "Expected no burden at birth without cases"
+        );
+    }
+
+    #[test]
+    fn test_age_time_burden() {
+        // Constant rate grids give the burden of the constant rate model.
+        let constant = |value: f64| RateGrid::new(vec![0.0], vec![0.0], value);
+        let mut model = AgeTimeModel::new();
+        model.configure(
+            60,
+            0.5,
+            0.0,
+            constant(0.03),
+            constant(0.1),
+            constant(0.2),
+            constant(0.01),
+        );
+        model.period(2000.0).run_rk4();
+        let mut reference = dismod::Model::new();
+        reference.configure(
+            60,
+            0.5,
+            0.0,
+            Rate::per_year(0.03),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.01),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        let table = LifeTable::from_rates(vec![0.0, 50.0], vec![0.01, 0.05]);
+        let result = age_time_burden(&model, &table, 1000.0, 0.3, 0.03);
+        let expected = dismod_burden(&reference, &table, 1000.0, 0.3, 0.03);
+        assert!(
+            (result.total_daly() - expected.total_daly()).abs() < 1e-9,
+            "Bad DALY, expected {} got {}",
+            expected.total_daly(),
+            result.total_daly()
+        );
+    }
+}
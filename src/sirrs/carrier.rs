@@ -0,0 +1,291 @@
+//! SAIR model with asymptomatic carriers.
+//!
+//! New infections start as carriers, who transmit at a reduced relative
+//! infectiousness. A configurable fraction of carriers goes on to develop
+//! symptoms, the rest recover without ever being symptomatic, as for
+//! COVID-like pathogens. Allows transition rates:
+//!  - S → A, infected by I and, at a reduced rate, by A
+//!  - A → I, the symptomatic fraction
+//!  - A → R, everyone else
+//!  - I → R
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run a model with an asymptomatic carrier compartment.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial symptomatic infectious population fraction.
+    pub i_popf_init: f64,
+    /// Initial carrier population fraction.
+    pub a_popf_init: f64,
+    /// Transition rate from S into A by contact with I. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Rate of leaving A, into I or R, the inverse of the mean duration of
+    /// carriage.
+    pub carrier_exit_rate: f64,
+    /// Infectiousness of carriers relative to the symptomatic. Must be in
+    /// [0, 1].
+    pub relative_infectiousness: f64,
+    /// Fraction of carriers who progress to I. Must be in [0, 1].
+    pub symptomatic_fraction: f64,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Asymptomatic carrier population fraction at each index. 1D Array with
+    /// one element per index of [`Model::grid`].
+    pub a_popf: Mat<f64>,
+    /// Symptomatic infectious population fraction at each index. 1D Array
+    /// with one element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            a_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            carrier_exit_rate: 0.0,
+            relative_infectiousness: 0.0,
+            symptomatic_fraction: 0.0,
+            s_popf: Mat::new(),
+            a_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. Rates are per day.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        a_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        carrier_exit_rate: Rate,
+        relative_infectiousness: Fraction,
+        symptomatic_fraction: Fraction,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.a_popf_init = a_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.carrier_exit_rate = carrier_exit_rate.per(TimeUnit::Day);
+        self.relative_infectiousness = relative_infectiousness.value();
+        self.symptomatic_fraction = symptomatic_fraction.value();
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.a_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let s_init = 1.0 - self.i_popf_init - self.a_popf_init; // Population fractions must sum to 1.
+        self.store(0, &[s_init, self.a_popf_init, self.i_popf_init, 0.0]);
+        return self;
+    }
+
+    /// Basic reproduction number, summing transmission during carriage and,
+    /// for the symptomatic fraction, during illness.
+    pub fn r0(&self) -> f64 {
+        return self.incidence_rate
+            * ((self.relative_infectiousness / self.carrier_exit_rate)
+                + (self.symptomatic_fraction / self.removal_rate));
+    }
+
+    /// Pack the state at index `t` into a vector ordered S, A, I, R.
+    fn load(&self, t: usize) -> [f64; 4] {
+        return [
+            self.s_popf[(t, 0)],
+            self.a_popf[(t, 0)],
+            self.i_popf[(t, 0)],
+            self.r_popf[(t, 0)],
+        ];
+    }
+
+    /// Unpack a state vector into index `t`.
+    fn store(&mut self, t: usize, y: &[f64; 4]) {
+        self.s_popf[(t, 0)] = y[0];
+        self.a_popf[(t, 0)] = y[1];
+        self.i_popf[(t, 0)] = y[2];
+        self.r_popf[(t, 0)] = y[3];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64; 4]) -> [f64; 4] {
+        let infection = self.incidence_rate * y[0] * (y[2] + (self.relative_infectiousness * y[1]));
+        let exit = self.carrier_exit_rate * y[1];
+        let progression = self.symptomatic_fraction * exit;
+        let removal = self.removal_rate * y[2];
+        return [
+            -infection,
+            infection - exit,
+            progression - removal,
+            (exit - progression) + removal,
+        ];
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            self.store(t + 1, &std::array::from_fn(|j| y[j] + (h * d[j])));
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let k2 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])));
+            let k3 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])));
+            let k4 = self.derivatives(&std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.store(
+                t + 1,
+                &std::array::from_fn(|j| {
+                    y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+                }),
+            );
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::carrier::Model;
+    use crate::sirrs::units::{Fraction, Rate};
+
+    #[test]
+    fn test_fully_infectious_carriers_match_sir() {
+        // Carriers as infectious as the symptomatic, recovering at the same
+        // rate, with nobody progressing, are the I of the SIR model.
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.0,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.1),
+            Fraction::of(1.0),
+            Fraction::of(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = crate::sirrs::sir::Model::new();
+        sir.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..model.a_popf.nrows() {
+            assert!(
+                (model.a_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad a_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.a_popf[(t, 0)]
+            );
+        }
+        assert!(
+            (model.r0() - 4.0).abs() < 1e-12,
+            "Bad r0, expected 4 got {}",
+            model.r0()
+        );
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.2),
+            Fraction::of(0.5),
+            Fraction::of(0.6),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let last = model.s_popf.nrows() - 1;
+        for t in 0..=last {
+            let total = model.s_popf[(t, 0)]
+                + model.a_popf[(t, 0)]
+                + model.i_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+        // Less transmissible carriers mean a smaller epidemic.
+        let mut quiet = Model::new();
+        quiet.configure(
+            100,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.2),
+            Fraction::of(0.1),
+            Fraction::of(0.6),
+        );
+        quiet.init_popf();
+        quiet.run_rk4();
+        assert!(
+            quiet.r_popf[(last, 0)] < model.r_popf[(last, 0)],
+            "Expected less infectious carriers to shrink the epidemic, got {} and {}",
+            quiet.r_popf[(last, 0)],
+            model.r_popf[(last, 0)]
+        );
+    }
+}
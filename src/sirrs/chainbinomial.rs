@@ -0,0 +1,209 @@
+//! Reed–Frost chain-binomial model and methods.
+//!
+//! A discrete-generation stochastic SIR model in counts. In each generation
+//! every susceptible independently escapes infection from each infective
+//! with probability `1 - p`, so the number of new infectives is
+//!
+//! ```text
+//! I[g + 1] ~ Binomial(S[g], 1 - (1 - p)^I[g])
+//! ```
+//!
+//! and infectives are removed after one generation. Many replicates are run
+//! at once, one column of each output per replicate.
+use crate::sirrs::reproducible::powf;
+use crate::sirrs::rng;
+use crate::sirrs::units::Fraction;
+use faer::Mat;
+use rand_distr::{Binomial, Distribution};
+
+/// Create and run a Reed–Frost chain-binomial model.
+pub struct Model {
+    /// Number of generations to simulate.
+    pub generations: usize,
+    /// Total population size.
+    pub population: u64,
+    /// Initial number of infectives.
+    pub i_init: u64,
+    /// Probability of effective contact between one infective and one
+    /// susceptible in a generation. Must be in [0, 1].
+    pub transmission_probability: f64,
+    /// Number of independent replicates.
+    pub replicates: usize,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Susceptible count of each replicate (column) at each generation (row).
+    pub s: Mat<f64>,
+    /// Infective count of each replicate (column) at each generation (row).
+    pub i: Mat<f64>,
+    /// Removed count of each replicate (column) at each generation (row).
+    pub r: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            generations: 0,
+            population: 0,
+            i_init: 0,
+            transmission_probability: 0.0,
+            replicates: 0,
+            seed: 0,
+            s: Mat::new(),
+            i: Mat::new(),
+            r: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        generations: usize,
+        population: u64,
+        i_init: u64,
+        transmission_probability: Fraction,
+        replicates: usize,
+        seed: u64,
+    ) -> &mut Self {
+        assert!(
+            i_init <= population,
+            "i_init must not exceed population, got {} > {}",
+            i_init,
+            population
+        );
+        self.generations = generations;
+        self.population = population;
+        self.i_init = i_init;
+        self.transmission_probability = transmission_probability.value();
+        self.replicates = replicates;
+        self.seed = seed;
+        self.s = Mat::zeros(generations + 1, replicates);
+        self.i = Mat::zeros(generations + 1, replicates);
+        self.r = Mat::zeros(generations + 1, replicates);
+        return self;
+    }
+
+    /// Initialize counts. Sets generation 0 of every replicate.
+    pub fn init_counts(&mut self) -> &mut Model {
+        for j in 0..self.replicates {
+            self.s[(0, j)] = (self.population - self.i_init) as f64;
+            self.i[(0, j)] = self.i_init as f64;
+            self.r[(0, j)] = 0.0;
+        }
+        return self;
+    }
+
+    /// Simulate every replicate.
+    pub fn run(&mut self) -> &Model {
+        let mut rng = rng::rng(self.seed);
+        let escape = 1.0 - self.transmission_probability;
+        for j in 0..self.replicates {
+            for g in 0..self.generations {
+                let s = self.s[(g, j)] as u64;
+                let i = self.i[(g, j)];
+                let p_infection = 1.0 - powf(escape, i);
+                let new_i = if (s == 0) | (i == 0.0) {
+                    0
+                } else {
+                    Binomial::new(s, p_infection.clamp(0.0, 1.0))
+                        .unwrap()
+                        .sample(&mut rng)
+                };
+                self.s[(g + 1, j)] = (s - new_i) as f64;
+                self.i[(g + 1, j)] = new_i as f64;
+                self.r[(g + 1, j)] = self.r[(g, j)] + i;
+            }
+        }
+        return self;
+    }
+
+    /// Final size of each replicate, the total number ever infected. Column
+    /// with `replicates` rows.
+    pub fn final_size(&self) -> Mat<f64> {
+        let last = self.generations;
+        return Mat::from_fn(self.replicates, 1, |j, _| {
+            (self.population as f64) - self.s[(last, j)]
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::chainbinomial::Model;
+    use crate::sirrs::units::Fraction;
+
+    #[test]
+    fn test_configure() {
+        let mut model = Model::new();
+        model.configure(10, 100, 1, Fraction::of(0.02), 5, 1);
+        assert_eq!(
+            model.s.shape(),
+            (11, 5),
+            "Bad s dimensions, expected {:?} got {:?}",
+            (11, 5),
+            model.s.shape()
+        );
+    }
+
+    #[test]
+    fn test_run_conserves_population() {
+        let mut model = Model::new();
+        model.configure(20, 200, 2, Fraction::of(0.01), 50, 7);
+        model.init_counts();
+        model.run();
+        for j in 0..50 {
+            for g in 0..=20 {
+                let total = model.s[(g, j)] + model.i[(g, j)] + model.r[(g, j)];
+                assert_eq!(
+                    total, 200.0,
+                    "Population not conserved in replicate {} generation {}, got {}",
+                    j, g, total
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_is_reproducible() {
+        let mut a = Model::new();
+        a.configure(20, 200, 2, Fraction::of(0.01), 10, 42);
+        a.init_counts();
+        a.run();
+        let mut b = Model::new();
+        b.configure(20, 200, 2, Fraction::of(0.01), 10, 42);
+        b.init_counts();
+        b.run();
+        assert_eq!(a.i, b.i, "Same seed gave different trajectories");
+    }
+
+    #[test]
+    fn test_no_transmission() {
+        let mut model = Model::new();
+        model.configure(5, 100, 3, Fraction::of(0.0), 4, 1);
+        model.init_counts();
+        model.run();
+        for j in 0..4 {
+            assert_eq!(
+                model.final_size()[(j, 0)],
+                3.0,
+                "Bad final size without transmission, expected 3 got {}",
+                model.final_size()[(j, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_mean_first_generation() {
+        let mut model = Model::new();
+        model.configure(1, 1000, 1, Fraction::of(0.002), 4000, 3);
+        model.init_counts();
+        model.run();
+        let mean: f64 = (0..4000).map(|j| model.i[(1, j)]).sum::<f64>() / 4000.0;
+        assert!(
+            (mean - (999.0 * 0.002)).abs() < 0.1,
+            "Bad mean first generation size, expected {} got {}",
+            999.0 * 0.002,
+            mean
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Outputs of the `sirrs` command line tool.
+//!
+//! The `sirrs` binary, built with the `cli` feature, runs one model per
+//! subcommand, for example `sirrs sir --incidence-rate 0.3`, and writes the
+//! run's [`SimulationResult`] to every output asked for at once by its
+//! [`Outputs`]:
+//!  - `--csv <path>`, wide csv, see [`SimulationResult::write_csv`]
+//!  - `--long-csv <path>`, long csv, see
+//!    [`SimulationResult::write_long_csv`]
+//!  - `--json <path>`, see [`SimulationResult::to_json`]
+//!  - `--summary`, the final and peak value of each series, see [`summary`]
+//!
+//! A path of `-` is standard output. With no outputs, wide csv goes to
+//! standard output. `sirrs diff` compares two runs saved as JSON, see
+//! [`crate::diff`]. With the `tui` feature, `--tui` plots the run live in
+//! the terminal before writing it, see the `tui` module. Writing is
+//! independent of argument parsing, so it is used and tested without the
+//! binary.
+use crate::sirrs::diff::metrics;
+use crate::sirrs::metadata::SimulationResult;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Outputs to write a run to, any number at once.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct Outputs {
+    /// Write the series as wide csv to this path, `-` for standard output.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub csv: Option<PathBuf>,
+    /// Write the series as long csv to this path, `-` for standard output.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub long_csv: Option<PathBuf>,
+    /// Write the run as JSON to this path, `-` for standard output.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PATH"))]
+    pub json: Option<PathBuf>,
+    /// Print the final and peak value of each series.
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub summary: bool,
+}
+
+/// Where one output goes.
+enum Destination<'a, W: Write> {
+    Stdout(&'a mut W),
+    File(BufWriter<File>),
+}
+
+impl<W: Write> Write for Destination<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Destination::Stdout(writer) => return writer.write(buf),
+            Destination::File(writer) => return writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Destination::Stdout(writer) => return writer.flush(),
+            Destination::File(writer) => return writer.flush(),
+        }
+    }
+}
+
+/// Open `path` for writing, or standard output `stdout` for `-`.
+fn open<'a, W: Write>(path: &PathBuf, stdout: &'a mut W) -> io::Result<Destination<'a, W>> {
+    if path.as_os_str() == "-" {
+        return Ok(Destination::Stdout(stdout));
+    }
+    return Ok(Destination::File(BufWriter::new(File::create(path)?)));
+}
+
+impl Outputs {
+    /// No outputs.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Whether no output was asked for.
+    pub fn is_empty(&self) -> bool {
+        return self.csv.is_none() & self.long_csv.is_none() & self.json.is_none() & !self.summary;
+    }
+
+    /// Write `result` to every output, with `stdout` as standard output.
+    /// With no outputs, wide csv goes to `stdout`.
+    pub fn write<W: Write>(&self, result: &SimulationResult, stdout: &mut W) -> io::Result<()> {
+        if self.is_empty() {
+            return result.write_csv(&mut *stdout);
+        }
+        if let Some(path) = &self.csv {
+            result.write_csv(open(path, stdout)?)?;
+        }
+        if let Some(path) = &self.long_csv {
+            result.write_long_csv(open(path, stdout)?)?;
+        }
+        if let Some(path) = &self.json {
+            let mut writer = open(path, stdout)?;
+            writeln!(writer, "{}", result.to_json())?;
+            writer.flush()?;
+        }
+        if self.summary {
+            write!(stdout, "{}", summary(result))?;
+            stdout.flush()?;
+        }
+        return Ok(());
+    }
+}
+
+/// The final value of each series of `result`, and its peak and the time
+/// of the peak, one line per series, from [`metrics`].
+pub fn summary(result: &SimulationResult) -> String {
+    let width = result
+        .names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut text = format!(
+        "{} run of {} steps, parameters {}\n",
+        result.metadata.solver,
+        result.values.nrows(),
+        result.metadata.parameters_hash
+    );
+    // Final, peak and peak time of each series in turn.
+    for (name, series) in result.names.iter().zip(metrics(result).chunks(3)) {
+        text.push_str(&format!(
+            "{:width$}  final {:.6}  peak {:.6} at t = {}\n",
+            name,
+            series[0].1,
+            series[1].1,
+            series[2].1,
+            width = width
+        ));
+    }
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::cli::{Outputs, summary};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_outputs() {
+        let mut model = sir::Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let result = model.result("rk4");
+        let mut stdout = Vec::new();
+        Outputs::new().write(&result, &mut stdout).unwrap();
+        let mut csv = Vec::new();
+        result.write_csv(&mut csv).unwrap();
+        assert_eq!(stdout, csv, "Expected wide csv without outputs");
+        // Several outputs at once, to a file and to standard output.
+        let path = std::env::temp_dir().join("sirrs_test_outputs.json");
+        let outputs = Outputs {
+            json: Some(path.clone()),
+            long_csv: Some(PathBuf::from("-")),
+            summary: true,
+            ..Outputs::new()
+        };
+        let mut stdout = Vec::new();
+        outputs.write(&result, &mut stdout).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json, format!("{}\n", result.to_json()), "Bad JSON file");
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert!(
+            stdout.contains("t,stratum,compartment,value") & stdout.ends_with(&summary(&result)),
+            "Expected long csv then the summary, got {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut model = sir::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let text = summary(&model.result("rk4"));
+        let i_line = text.lines().find(|line| line.starts_with("i ")).unwrap();
+        let peak = (0..model.i_popf.nrows())
+            .max_by(|&a, &b| model.i_popf[(a, 0)].total_cmp(&model.i_popf[(b, 0)]))
+            .unwrap();
+        assert!(
+            i_line.contains(&format!("peak {:.6}", model.i_popf[(peak, 0)])),
+            "Bad peak in {}",
+            i_line
+        );
+        assert_eq!(
+            text.lines().count(),
+            6,
+            "Expected a header and one line per series"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Models over structs of named compartments.
+//!
+//! A [`CompartmentModel`] is written in terms of a state struct with one
+//! field per compartment, rather than a state vector. Packing the struct to
+//! and from the solver's vector is the [`Compartments`] trait, derived with
+//! `#[derive(Compartments)]` when the `derive` feature is enabled:
+//!
+//! ```ignore
+//! #[derive(Compartments)]
+//! struct Seir {
+//!     s: f64,
+//!     e: f64,
+//!     i: f64,
+//!     r: f64,
+//! }
+//! ```
+//!
+//! [`Compartmental`] adapts the model to a [`System`], so it is solved by a
+//! [`Pipeline`] with any interventions and observation models.
+use crate::sirrs::pipeline::{Parameters, Pipeline, System};
+use faer::Mat;
+
+#[cfg(feature = "derive")]
+pub use sirrs_derive::Compartments;
+
+/// A struct of compartments, packed to a state vector.
+pub trait Compartments: Sized {
+    /// Names of the compartments, in state vector order.
+    const NAMES: &'static [&'static str];
+    /// The compartments as a state vector.
+    fn to_state(&self) -> Vec<f64>;
+    /// The compartments of state vector `y`.
+    fn from_state(y: &[f64]) -> Self;
+}
+
+/// Compartmental dynamics over a struct of compartments.
+pub trait CompartmentModel {
+    /// The compartments.
+    type State: Compartments;
+    /// State at t = 0.
+    fn initial(&self) -> Self::State;
+    /// Parameter values before any intervention.
+    fn parameters(&self) -> Parameters;
+    /// Derivative of every compartment at time `t`.
+    fn derivatives(&self, t: f64, state: &Self::State, parameters: &Parameters) -> Self::State;
+    /// Rate of new cases at time `t`.
+    fn incidence(&self, t: f64, state: &Self::State, parameters: &Parameters) -> f64;
+}
+
+/// A [`CompartmentModel`] as a [`System`].
+pub struct Compartmental<M>(pub M);
+
+impl<M: CompartmentModel + 'static> Compartmental<M> {
+    /// A pipeline solving the model, configured with `length` and
+    /// `step_size`.
+    pub fn pipeline(self, length: usize, step_size: f64) -> Pipeline {
+        let mut pipeline = Pipeline::new(Box::new(self));
+        pipeline.configure(length, step_size);
+        return pipeline;
+    }
+}
+
+impl<M: CompartmentModel> System for Compartmental<M> {
+    fn state_names(&self) -> Vec<String> {
+        return M::State::NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return self.0.initial().to_state();
+    }
+
+    fn parameters(&self) -> Parameters {
+        return self.0.parameters();
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let state = M::State::from_state(y);
+        return self.0.derivatives(t, &state, parameters).to_state();
+    }
+
+    fn incidence(&self, t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return self.0.incidence(t, &M::State::from_state(y), parameters);
+    }
+}
+
+/// Compartments at time index `k` of a solved state matrix, such as
+/// [`Pipeline::state`].
+pub fn unpack<C: Compartments>(state: &Mat<f64>, k: usize) -> C {
+    let y: Vec<f64> = (0..state.ncols()).map(|j| state[(k, j)]).collect();
+    return C::from_state(&y);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::compartments::{CompartmentModel, Compartmental, Compartments, unpack};
+    use crate::sirrs::pipeline::{Parameters, Pipeline, System};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    struct Sir {
+        s: f64,
+        i: f64,
+        r: f64,
+    }
+
+    impl Compartments for Sir {
+        const NAMES: &'static [&'static str] = &["s", "i", "r"];
+
+        fn to_state(&self) -> Vec<f64> {
+            return vec![self.s, self.i, self.r];
+        }
+
+        fn from_state(y: &[f64]) -> Self {
+            return Self {
+                s: y[0],
+                i: y[1],
+                r: y[2],
+            };
+        }
+    }
+
+    struct Model;
+
+    impl CompartmentModel for Model {
+        type State = Sir;
+
+        fn initial(&self) -> Sir {
+            return Sir {
+                s: 0.99,
+                i: 0.01,
+                r: 0.0,
+            };
+        }
+
+        fn parameters(&self) -> Parameters {
+            return Parameters::from([
+                ("incidence_rate".to_string(), 0.4),
+                ("removal_rate".to_string(), 0.1),
+            ]);
+        }
+
+        fn derivatives(&self, t: f64, x: &Sir, p: &Parameters) -> Sir {
+            let infection = self.incidence(t, x, p);
+            let removal = p["removal_rate"] * x.i;
+            return Sir {
+                s: -infection,
+                i: infection - removal,
+                r: removal,
+            };
+        }
+
+        fn incidence(&self, _t: f64, x: &Sir, p: &Parameters) -> f64 {
+            return p["incidence_rate"] * x.s * x.i;
+        }
+    }
+
+    #[test]
+    fn test_matches_sir() {
+        let system = Compartmental(Model);
+        assert_eq!(system.state_names(), vec!["s", "i", "r"], "Bad state names");
+        let mut pipeline = system.pipeline(50, 0.5);
+        pipeline.run_rk4();
+        let mut model = sir::Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut expected = Pipeline::new(Box::new(model));
+        expected.configure(50, 0.5);
+        expected.run_rk4();
+        for t in 0..expected.state.nrows() {
+            let x: Sir = unpack(&pipeline.state, t);
+            let y: Sir = unpack(&expected.state, t);
+            for (a, b) in [(x.s, y.s), (x.i, y.i), (x.r, y.r)] {
+                assert!(
+                    (a - b).abs() < 1e-12,
+                    "Bad state at index {}, expected {} got {}",
+                    t,
+                    b,
+                    a
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Optimal transmission-reduction schedules by forward-backward sweep.
+//!
+//! A control `u(t)` in `[0, u_max]` scales transmission of the SIR model
+//! without recovery by `1 - u`. The schedule minimizes
+//!
+//! ```text
+//! J(u) = ∫ (A I + B u² / 2) dt
+//! ```
+//!
+//! over the series, with `A` the weight of epidemic burden and `B` of
+//! intervention cost. By Pontryagin's maximum principle the optimal control
+//! satisfies the state equations forward in time, the adjoint equations
+//!
+//! ```text
+//! dλ_S/dt = β (1 - u) I (λ_S - λ_I)
+//! dλ_I/dt = -A + β (1 - u) S (λ_S - λ_I) + γ λ_I
+//! ```
+//!
+//! backward in time from `λ(T) = 0`, and
+//! `u = clamp(β S I (λ_I - λ_S) / B, 0, u_max)`. The sweep alternates
+//! solving the two with RK4 and relaxes the control towards the update
+//! until it stops changing.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Find an optimal transmission-reduction schedule for the SIR model.
+pub struct OptimalControl {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Transition rate from S into I without intervention. Must be in
+    /// [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Largest fraction of transmission the intervention can remove. Must be
+    /// in [0, 1].
+    pub max_control: f64,
+    /// Cost per unit time of each infectious population fraction, `A`.
+    pub burden_weight: f64,
+    /// Cost per unit time of the squared control, `B`, twice the cost of
+    /// full control. Must be positive.
+    pub control_weight: f64,
+    /// Fraction of transmission removed at each index. 1D Array with one
+    /// element per index of [`OptimalControl::grid`].
+    pub control: Mat<f64>,
+    /// Susceptible population fraction at each index, under `control`.
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index, under `control`.
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index, under `control`.
+    pub r_popf: Mat<f64>,
+    /// Adjoint of S at each index.
+    pub lambda_s: Mat<f64>,
+    /// Adjoint of I at each index.
+    pub lambda_i: Mat<f64>,
+    /// Cost functional at `control`.
+    pub cost: f64,
+    /// Number of sweeps used.
+    pub iterations: usize,
+}
+
+impl OptimalControl {
+    /// Create a new optimal control object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            max_control: 0.0,
+            burden_weight: 0.0,
+            control_weight: 0.0,
+            control: Mat::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            lambda_s: Mat::new(),
+            lambda_i: Mat::new(),
+            cost: f64::INFINITY,
+            iterations: 0,
+        };
+    }
+
+    /// Configure the problem, with rates per day. The control starts at zero.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        max_control: Fraction,
+        burden_weight: f64,
+        control_weight: f64,
+    ) -> &mut Self {
+        assert!(
+            control_weight > 0.0,
+            "control_weight must be positive, got {}",
+            control_weight
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.max_control = max_control.value();
+        self.burden_weight = burden_weight;
+        self.control_weight = control_weight;
+        self.control = Mat::zeros(n_steps, 1);
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.lambda_s = Mat::zeros(n_steps, 1);
+        self.lambda_i = Mat::zeros(n_steps, 1);
+        self.cost = f64::INFINITY;
+        self.iterations = 0;
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Derivatives of (S, I) under control `u`.
+    fn state_derivatives(&self, y: [f64; 2], u: f64) -> [f64; 2] {
+        let infection = self.incidence_rate * (1.0 - u) * y[0] * y[1];
+        return [-infection, infection - (self.removal_rate * y[1])];
+    }
+
+    /// Derivatives of (λ_S, λ_I) at state (S, I) under control `u`.
+    fn adjoint_derivatives(&self, lambda: [f64; 2], y: [f64; 2], u: f64) -> [f64; 2] {
+        let beta = self.incidence_rate * (1.0 - u);
+        let gap = lambda[0] - lambda[1];
+        return [
+            beta * y[1] * gap,
+            -self.burden_weight + (beta * y[0] * gap) + (self.removal_rate * lambda[1]),
+        ];
+    }
+
+    /// Solve the state forward under `control`, returning (S, I, R) at each
+    /// index. The control is linear between indices.
+    pub fn solve_state(&self, control: &Mat<f64>) -> [Mat<f64>; 3] {
+        let h = self.step_size;
+        let n = self.grid().n_steps;
+        let mut s = Mat::zeros(n, 1);
+        let mut i = Mat::zeros(n, 1);
+        s[(0, 0)] = 1.0 - self.i_popf_init;
+        i[(0, 0)] = self.i_popf_init;
+        for t in 0..n - 1 {
+            let (u0, u1) = (control[(t, 0)], control[(t + 1, 0)]);
+            let um = (u0 + u1) / 2.0;
+            let y = [s[(t, 0)], i[(t, 0)]];
+            let k1 = self.state_derivatives(y, u0);
+            let k2 = self.state_derivatives(std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])), um);
+            let k3 = self.state_derivatives(std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])), um);
+            let k4 = self.state_derivatives(std::array::from_fn(|j| y[j] + (h * k3[j])), u1);
+            let next: [f64; 2] = std::array::from_fn(|j| {
+                y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+            });
+            s[(t + 1, 0)] = next[0];
+            i[(t + 1, 0)] = next[1];
+        }
+        let r = Mat::from_fn(n, 1, |t, _| 1.0 - s[(t, 0)] - i[(t, 0)]);
+        return [s, i, r];
+    }
+
+    /// Solve the adjoint backward from zero at the end of the series, along
+    /// the stored state and `control`.
+    fn solve_adjoint(&mut self, control: &Mat<f64>) {
+        let h = self.step_size;
+        let n = self.grid().n_steps;
+        self.lambda_s[(n - 1, 0)] = 0.0;
+        self.lambda_i[(n - 1, 0)] = 0.0;
+        for t in (1..n).rev() {
+            let y1 = [self.s_popf[(t, 0)], self.i_popf[(t, 0)]];
+            let y0 = [self.s_popf[(t - 1, 0)], self.i_popf[(t - 1, 0)]];
+            let ym: [f64; 2] = std::array::from_fn(|j| (y0[j] + y1[j]) / 2.0);
+            let (u1, u0) = (control[(t, 0)], control[(t - 1, 0)]);
+            let um = (u0 + u1) / 2.0;
+            let l = [self.lambda_s[(t, 0)], self.lambda_i[(t, 0)]];
+            let k1 = self.adjoint_derivatives(l, y1, u1);
+            let k2 =
+                self.adjoint_derivatives(std::array::from_fn(|j| l[j] - (h / 2.0 * k1[j])), ym, um);
+            let k3 =
+                self.adjoint_derivatives(std::array::from_fn(|j| l[j] - (h / 2.0 * k2[j])), ym, um);
+            let k4 = self.adjoint_derivatives(std::array::from_fn(|j| l[j] - (h * k3[j])), y0, u0);
+            self.lambda_s[(t - 1, 0)] =
+                l[0] - ((k1[0] + (2.0 * k2[0]) + (2.0 * k3[0]) + k4[0]) * (h / 6.0));
+            self.lambda_i[(t - 1, 0)] =
+                l[1] - ((k1[1] + (2.0 * k2[1]) + (2.0 * k3[1]) + k4[1]) * (h / 6.0));
+        }
+    }
+
+    /// Cost functional of `control`, integrated by the trapezoidal rule.
+    pub fn cost_of(&self, control: &Mat<f64>) -> f64 {
+        let [_, i, _] = self.solve_state(control);
+        let running = |t: usize| {
+            (self.burden_weight * i[(t, 0)])
+                + (self.control_weight * control[(t, 0)] * control[(t, 0)] / 2.0)
+        };
+        let n = i.nrows();
+        return (0..n - 1)
+            .map(|t| (running(t) + running(t + 1)) * self.step_size / 2.0)
+            .sum();
+    }
+
+    /// Find the optimal control by forward-backward sweep, relaxing the
+    /// control halfway towards each update, until the largest change is
+    /// below `tolerance` or `max_iter` sweeps are used.
+    pub fn run_sweep(&mut self, max_iter: usize, tolerance: f64) -> &OptimalControl {
+        let n = self.grid().n_steps;
+        self.iterations = 0;
+        for _ in 0..max_iter {
+            self.iterations += 1;
+            let control = self.control.clone();
+            let [s, i, r] = self.solve_state(&control);
+            self.s_popf = s;
+            self.i_popf = i;
+            self.r_popf = r;
+            self.solve_adjoint(&control);
+            let mut change: f64 = 0.0;
+            for t in 0..n {
+                let gap = self.lambda_i[(t, 0)] - self.lambda_s[(t, 0)];
+                let update =
+                    (self.incidence_rate * self.s_popf[(t, 0)] * self.i_popf[(t, 0)] * gap
+                        / self.control_weight)
+                        .clamp(0.0, self.max_control);
+                let relaxed = (control[(t, 0)] + update) / 2.0;
+                change = change.max((relaxed - control[(t, 0)]).abs());
+                self.control[(t, 0)] = relaxed;
+            }
+            if change < tolerance {
+                break;
+            }
+        }
+        let [s, i, r] = self.solve_state(&self.control);
+        self.s_popf = s;
+        self.i_popf = i;
+        self.r_popf = r;
+        self.cost = self.cost_of(&self.control);
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::control::OptimalControl;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::Mat;
+
+    fn problem(control_weight: f64) -> OptimalControl {
+        let mut problem = OptimalControl::new();
+        problem.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Fraction::of(0.8),
+            1.0,
+            control_weight,
+        );
+        return problem;
+    }
+
+    #[test]
+    fn test_expensive_control_is_unused() {
+        let mut problem = problem(1e9);
+        problem.run_sweep(50, 1e-10);
+        let mut model = sir::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                problem.control[(t, 0)] < 1e-6,
+                "Expected no control at index {}, got {}",
+                t,
+                problem.control[(t, 0)]
+            );
+            assert!(
+                (problem.i_popf[(t, 0)] - model.i_popf[(t, 0)]).abs() < 1e-6,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                model.i_popf[(t, 0)],
+                problem.i_popf[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sweep_beats_constant_controls() {
+        let mut problem = problem(2.0);
+        problem.run_sweep(500, 1e-8);
+        assert!(
+            problem.iterations < 500,
+            "Expected the sweep to converge, used {} iterations",
+            problem.iterations
+        );
+        let n = problem.control.nrows();
+        for t in 0..n {
+            assert!(
+                (0.0..=0.8).contains(&problem.control[(t, 0)]),
+                "Control out of bounds at index {}, got {}",
+                t,
+                problem.control[(t, 0)]
+            );
+        }
+        assert_eq!(
+            problem.lambda_i[(n - 1, 0)],
+            0.0,
+            "Expected the adjoint to vanish at the end"
+        );
+        for level in [0.0, 0.2, 0.4, 0.6, 0.8] {
+            let constant = problem.cost_of(&Mat::from_fn(n, 1, |_, _| level));
+            assert!(
+                problem.cost < constant,
+                "Expected the optimal control to beat constant {}, got {} and {}",
+                level,
+                problem.cost,
+                constant
+            );
+        }
+        assert!(
+            problem.control[(n - 1, 0)] < 1e-9,
+            "Expected no control at the end, got {}",
+            problem.control[(n - 1, 0)]
+        );
+    }
+}
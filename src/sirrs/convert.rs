@@ -0,0 +1,168 @@
+//! Conversions from interpretable quantities to model rates.
+//!
+//! Models take transition rates per unit time, while studies report mean
+//! durations, probabilities over an interval, half-lives and reproduction
+//! numbers. The conventions are:
+//!  - a transition at constant `rate` takes an exponential time with mean
+//!    `1 / rate`, so the mean duration of a compartment left only by that
+//!    transition is `1 / rate`
+//!  - the probability of a transition within an interval `dt` is
+//!    `1 - exp(-rate dt)`, not `rate dt`, which only approximates it for
+//!    small `rate dt`
+//!  - durations and intervals are in the model's unit time, usually days
+//!
+//! For example, a 5 day infectious period and R0 of 2.5 give
+//! `removal_rate_from_infectious_period(5.0)` and
+//! `incidence_rate_from_r0(2.5, 5.0)` for [`crate::sir::Model::configure`].
+use crate::sirrs::reproducible::{exp, ln};
+
+/// Rate of a transition whose waiting time has mean `duration`.
+pub fn rate_from_duration(duration: f64) -> f64 {
+    assert!(
+        duration > 0.0,
+        "duration must be positive, got {}",
+        duration
+    );
+    return 1.0 / duration;
+}
+
+/// Mean waiting time of a transition at `rate`.
+pub fn duration_from_rate(rate: f64) -> f64 {
+    assert!(rate > 0.0, "rate must be positive, got {}", rate);
+    return 1.0 / rate;
+}
+
+/// I → R transition rate for a mean infectious period of `days`.
+pub fn removal_rate_from_infectious_period(days: f64) -> f64 {
+    return rate_from_duration(days);
+}
+
+/// S → I transition rate giving basic reproduction number `r0` with a mean
+/// infectious period of `days`, in the SIR model where `R0 = β / γ`.
+pub fn incidence_rate_from_r0(r0: f64, days: f64) -> f64 {
+    assert!(r0 >= 0.0, "r0 must be non-negative, got {}", r0);
+    return r0 * rate_from_duration(days);
+}
+
+/// Rate of a transition that happens with probability `p` within an
+/// interval `dt`.
+pub fn prob_to_rate(p: f64, dt: f64) -> f64 {
+    assert!((0.0..1.0).contains(&p), "p must be in [0, 1), got {}", p);
+    assert!(dt > 0.0, "dt must be positive, got {}", dt);
+    return -ln(1.0 - p) / dt;
+}
+
+/// Probability a transition at `rate` happens within an interval `dt`.
+pub fn rate_to_prob(rate: f64, dt: f64) -> f64 {
+    assert!(rate >= 0.0, "rate must be non-negative, got {}", rate);
+    assert!(dt >= 0.0, "dt must be non-negative, got {}", dt);
+    return 1.0 - exp(-rate * dt);
+}
+
+/// Probability `p` over an interval `dt` rescaled to an interval
+/// `new_dt`, assuming a constant rate.
+pub fn rescale_prob(p: f64, dt: f64, new_dt: f64) -> f64 {
+    return rate_to_prob(prob_to_rate(p, dt), new_dt);
+}
+
+/// Decay rate of a quantity that halves every `half_life`, such as waning
+/// immunity or pathogen in the environment.
+pub fn rate_from_half_life(half_life: f64) -> f64 {
+    assert!(
+        half_life > 0.0,
+        "half_life must be positive, got {}",
+        half_life
+    );
+    return ln(2.0) / half_life;
+}
+
+/// Exponential growth rate of incidence doubling every `doubling_time`.
+/// Negative doubling times are halving times.
+pub fn growth_rate_from_doubling_time(doubling_time: f64) -> f64 {
+    assert!(
+        doubling_time != 0.0,
+        "doubling_time must be non-zero, got {}",
+        doubling_time
+    );
+    return ln(2.0) / doubling_time;
+}
+
+/// Basic reproduction number of the SIR model with a mean infectious
+/// period of `days`, from the early exponential growth rate of incidence,
+/// `R0 = 1 + r D`.
+pub fn r0_from_growth_rate(growth_rate: f64, days: f64) -> f64 {
+    assert!(days > 0.0, "days must be positive, got {}", days);
+    return 1.0 + (growth_rate * days);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::convert::{
+        growth_rate_from_doubling_time, incidence_rate_from_r0, prob_to_rate, r0_from_growth_rate,
+        rate_from_half_life, rate_to_prob, removal_rate_from_infectious_period, rescale_prob,
+    };
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_probabilities() {
+        for (p, dt) in [(0.0, 1.0), (0.1, 1.0), (0.5, 7.0), (0.99, 0.25)] {
+            let back = rate_to_prob(prob_to_rate(p, dt), dt);
+            assert!(
+                (back - p).abs() < 1e-12,
+                "Bad round trip of {} over {}, got {}",
+                p,
+                dt,
+                back
+            );
+        }
+        // A weekly probability of 1 - 0.9^7 is a daily probability of 0.1.
+        let weekly = 1.0 - 0.9f64.powi(7);
+        assert!(
+            (rescale_prob(weekly, 7.0, 1.0) - 0.1).abs() < 1e-12,
+            "Bad daily probability, expected 0.1 got {}",
+            rescale_prob(weekly, 7.0, 1.0)
+        );
+        assert!(
+            (rate_from_half_life(3.0) * 3.0 - 2.0f64.ln()).abs() < 1e-12,
+            "Bad decay rate"
+        );
+    }
+
+    #[test]
+    fn test_sir_parameters() {
+        let removal_rate = removal_rate_from_infectious_period(5.0);
+        let incidence_rate = incidence_rate_from_r0(2.5, 5.0);
+        assert_eq!(removal_rate, 0.2, "Bad removal rate, got {}", removal_rate);
+        assert!(
+            (incidence_rate - 0.5).abs() < 1e-12,
+            "Bad incidence rate, expected 0.5 got {}",
+            incidence_rate
+        );
+        // The early growth of the SIR model gives back R0.
+        let mut model = sir::Model::new();
+        model.configure(
+            21,
+            0.01,
+            1e-8,
+            0.0,
+            Rate::per_day(incidence_rate),
+            Rate::per_day(removal_rate),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let growth = (model.i_popf[(2000, 0)] / model.i_popf[(1000, 0)]).ln() / 10.0;
+        let r0 = r0_from_growth_rate(growth, 5.0);
+        assert!(
+            (r0 - 2.5).abs() < 1e-3,
+            "Bad r0 from growth, expected 2.5 got {}",
+            r0
+        );
+        let doubling = 2.0f64.ln() / growth;
+        assert!(
+            (growth_rate_from_doubling_time(doubling) - growth).abs() < 1e-12,
+            "Bad growth rate from doubling time"
+        );
+    }
+}
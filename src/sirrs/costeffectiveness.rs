@@ -0,0 +1,482 @@
+//! Cost-effectiveness of scenarios.
+//!
+//! [`Costs`] attaches unit costs to a scenario's interventions, by
+//! intervention name, and to its health outcomes: each case, and each death
+//! from a constant infection fatality ratio. Cases and deaths also cost
+//! disability-adjusted life years, the effect measure. Costs and DALYs may
+//! be discounted at a constant rate, weighting those `t` into the run by
+//! `exp(-r t)`.
+//!
+//! [`Costs::evaluate`] totals one run, and [`Comparison`] sets a scenario
+//! against a baseline: the incremental cost, the DALYs averted and their
+//! ratio, the incremental cost-effectiveness ratio (ICER).
+//!
+//! [`probabilistic_sensitivity`] repeats the comparison over an ensemble of
+//! parameter draws, running every scenario with the same resampled draws so
+//! that differences are paired, as [`crate::ensemble::run_ensemble`] does.
+//! [`Psa::acceptability`] gives the probability that a scenario is cost
+//! effective at each willingness to pay per DALY, the cost-effectiveness
+//! acceptability curve.
+use crate::sirrs::ensemble::{self, Draws};
+use crate::sirrs::interventions::{Interval, Scenario, ScenarioResult};
+use crate::sirrs::pipeline::System;
+use crate::sirrs::reproducible::exp;
+use faer::Mat;
+use std::collections::BTreeMap;
+
+/// Unit costs of interventions and health outcomes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Costs {
+    /// Cost of each case.
+    pub per_case: f64,
+    /// DALYs lost with each case, from illness.
+    pub dalys_per_case: f64,
+    /// Fraction of cases that die.
+    pub fatality_ratio: f64,
+    /// Cost of each death.
+    pub per_death: f64,
+    /// DALYs lost with each death, usually the life expectancy at death.
+    pub dalys_per_death: f64,
+    /// Fixed cost on starting, and cost per unit time active, of each
+    /// intervention by name. Interventions without costs are free.
+    pub interventions: BTreeMap<String, (f64, f64)>,
+    /// Rate at which costs and DALYs are discounted.
+    pub discount_rate: f64,
+}
+
+/// Total costs and outcomes of one run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    /// Cost of the interventions.
+    pub intervention_cost: f64,
+    /// Cost of cases and deaths.
+    pub outcome_cost: f64,
+    /// Total cases, undiscounted.
+    pub cases: f64,
+    /// Total deaths, undiscounted.
+    pub deaths: f64,
+    /// DALYs lost to cases and deaths.
+    pub dalys: f64,
+}
+
+impl Evaluation {
+    /// Total cost.
+    pub fn cost(&self) -> f64 {
+        return self.intervention_cost + self.outcome_cost;
+    }
+}
+
+impl Costs {
+    /// Costs with every unit cost and DALY zero.
+    pub fn new() -> Self {
+        return Self {
+            per_case: 0.0,
+            dalys_per_case: 0.0,
+            fatality_ratio: 0.0,
+            per_death: 0.0,
+            dalys_per_death: 0.0,
+            interventions: BTreeMap::new(),
+            discount_rate: 0.0,
+        };
+    }
+
+    /// Set the cost and DALYs of each case.
+    pub fn case(&mut self, cost: f64, dalys: f64) -> &mut Self {
+        self.per_case = cost;
+        self.dalys_per_case = dalys;
+        return self;
+    }
+
+    /// Set the infection fatality ratio and the cost and DALYs of each
+    /// death.
+    pub fn death(&mut self, fatality_ratio: f64, cost: f64, dalys: f64) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&fatality_ratio),
+            "fatality_ratio must be in [0, 1], got {}",
+            fatality_ratio
+        );
+        self.fatality_ratio = fatality_ratio;
+        self.per_death = cost;
+        self.dalys_per_death = dalys;
+        return self;
+    }
+
+    /// Set the fixed cost and cost per unit time active of the intervention
+    /// named `name`.
+    pub fn intervention(&mut self, name: &str, fixed: f64, per_time: f64) -> &mut Self {
+        self.interventions
+            .insert(name.to_string(), (fixed, per_time));
+        return self;
+    }
+
+    /// Set the discount rate.
+    pub fn discount(&mut self, rate: f64) -> &mut Self {
+        assert!(rate >= 0.0, "rate must be non-negative, got {}", rate);
+        self.discount_rate = rate;
+        return self;
+    }
+
+    /// Discounted time spent in `[start, end)`.
+    fn discounted_time(&self, start: f64, end: f64) -> f64 {
+        if end <= start {
+            return 0.0;
+        }
+        let r = self.discount_rate;
+        if r == 0.0 {
+            return end - start;
+        }
+        return (exp(-r * start) - exp(-r * end)) / r;
+    }
+
+    /// Costs and outcomes of `scenario` given the new cases over the step
+    /// ending at each index of its run, `incidence`, a column on a grid of
+    /// `step_size` from t = 0. Interventions are costed while active within
+    /// the run, and cases at the middle of their step.
+    pub fn evaluate(
+        &self,
+        scenario: &Scenario,
+        incidence: &Mat<f64>,
+        step_size: f64,
+    ) -> Evaluation {
+        let n = incidence.nrows();
+        let end = (n as f64) * step_size;
+        let mut intervention_cost = 0.0;
+        for intervention in scenario.interventions.iter() {
+            let Some((fixed, per_time)) = self.interventions.get(&intervention.name) else {
+                continue;
+            };
+            if intervention.start >= end {
+                continue;
+            }
+            let start = intervention.start.max(0.0);
+            intervention_cost += fixed * exp(-self.discount_rate * start);
+            let active = self.discounted_time(start, intervention.end.min(end));
+            intervention_cost += per_time * active;
+        }
+        let mut cases = 0.0;
+        let mut discounted_cases = 0.0;
+        for t in 0..n {
+            let midpoint = ((t as f64) - 0.5).max(0.0) * step_size;
+            cases += incidence[(t, 0)];
+            discounted_cases += incidence[(t, 0)] * exp(-self.discount_rate * midpoint);
+        }
+        let discounted_deaths = self.fatality_ratio * discounted_cases;
+        return Evaluation {
+            intervention_cost,
+            outcome_cost: (self.per_case * discounted_cases) + (self.per_death * discounted_deaths),
+            cases,
+            deaths: self.fatality_ratio * cases,
+            dalys: (self.dalys_per_case * discounted_cases)
+                + (self.dalys_per_death * discounted_deaths),
+        };
+    }
+
+    /// Costs and outcomes of each scenario's result from
+    /// [`crate::interventions::compare`], by scenario name.
+    pub fn evaluate_all(
+        &self,
+        scenarios: &[Scenario],
+        results: &BTreeMap<String, ScenarioResult>,
+        step_size: f64,
+    ) -> BTreeMap<String, Evaluation> {
+        return scenarios
+            .iter()
+            .map(|scenario| {
+                let result = results
+                    .get(&scenario.name)
+                    .unwrap_or_else(|| panic!("no result for scenario {}", scenario.name));
+                let evaluation = self.evaluate(scenario, &result.incidence, step_size);
+                (scenario.name.clone(), evaluation)
+            })
+            .collect();
+    }
+}
+
+/// A scenario relative to a baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// Scenario cost minus baseline cost.
+    pub incremental_cost: f64,
+    /// Baseline DALYs minus scenario DALYs.
+    pub dalys_averted: f64,
+}
+
+impl Comparison {
+    /// Compare `scenario` against `baseline`.
+    pub fn new(baseline: &Evaluation, scenario: &Evaluation) -> Self {
+        return Self {
+            incremental_cost: scenario.cost() - baseline.cost(),
+            dalys_averted: baseline.dalys - scenario.dalys,
+        };
+    }
+
+    /// Incremental cost per DALY averted. Only meaningful when DALYs are
+    /// averted; see [`Comparison::dominated`] and
+    /// [`Comparison::dominant`].
+    pub fn icer(&self) -> f64 {
+        return self.incremental_cost / self.dalys_averted;
+    }
+
+    /// Whether the scenario costs more and averts no DALYs.
+    pub fn dominated(&self) -> bool {
+        return (self.incremental_cost > 0.0) & (self.dalys_averted <= 0.0);
+    }
+
+    /// Whether the scenario costs no more and averts DALYs.
+    pub fn dominant(&self) -> bool {
+        return (self.incremental_cost <= 0.0) & (self.dalys_averted > 0.0);
+    }
+
+    /// Net monetary benefit at `willingness_to_pay` per DALY averted,
+    /// positive if the scenario is cost effective at that threshold.
+    pub fn net_benefit(&self, willingness_to_pay: f64) -> f64 {
+        return (willingness_to_pay * self.dalys_averted) - self.incremental_cost;
+    }
+}
+
+/// Comparisons of each scenario with the baseline over an ensemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Psa {
+    /// Index of the draw used by each run.
+    pub draws: Vec<usize>,
+    /// Comparison in each run, by scenario name.
+    pub comparisons: BTreeMap<String, Vec<Comparison>>,
+}
+
+impl Psa {
+    /// Comparisons of the scenario named `name`.
+    fn runs(&self, name: &str) -> &Vec<Comparison> {
+        return self
+            .comparisons
+            .get(name)
+            .unwrap_or_else(|| panic!("no scenario named {}", name));
+    }
+
+    /// ICER of the scenario named `name` from the ensemble mean incremental
+    /// cost and DALYs averted.
+    pub fn icer(&self, name: &str) -> f64 {
+        let runs = self.runs(name);
+        let cost: f64 = runs.iter().map(|c| c.incremental_cost).sum();
+        let dalys: f64 = runs.iter().map(|c| c.dalys_averted).sum();
+        return cost / dalys;
+    }
+
+    /// Mean and central `level` intervals of the incremental cost and DALYs
+    /// averted of the scenario named `name`.
+    pub fn intervals(&self, name: &str, level: f64) -> (Interval, Interval) {
+        let runs = self.runs(name);
+        let cost: Vec<f64> = runs.iter().map(|c| c.incremental_cost).collect();
+        let dalys: Vec<f64> = runs.iter().map(|c| c.dalys_averted).collect();
+        return (
+            Interval::from_values(&cost, level),
+            Interval::from_values(&dalys, level),
+        );
+    }
+
+    /// Probability that the scenario named `name` is cost effective at each
+    /// willingness to pay per DALY, the fraction of runs with positive net
+    /// benefit.
+    pub fn acceptability(&self, name: &str, willingness_to_pay: &[f64]) -> Vec<f64> {
+        let runs = self.runs(name);
+        return willingness_to_pay
+            .iter()
+            .map(|wtp| {
+                let n = runs.iter().filter(|c| c.net_benefit(*wtp) > 0.0).count();
+                (n as f64) / (runs.len() as f64)
+            })
+            .collect();
+    }
+}
+
+/// Probabilistic sensitivity analysis of each of `scenarios` against
+/// `baseline`, over `n_runs` draws resampled from `draws` by
+/// [`ensemble::run_ensemble`] with `seed`, every scenario run on the same
+/// draws.
+pub fn probabilistic_sensitivity(
+    system: impl Fn() -> Box<dyn System>,
+    draws: &Draws,
+    baseline: &Scenario,
+    scenarios: &[Scenario],
+    costs: &Costs,
+    length: usize,
+    step_size: f64,
+    n_runs: usize,
+    seed: u64,
+) -> Psa {
+    let evaluate = |scenario: &Scenario| {
+        let runs =
+            ensemble::run_ensemble(&system, draws, scenario, length, step_size, n_runs, seed);
+        let evaluations: Vec<Evaluation> = (0..n_runs)
+            .map(|r| {
+                let incidence =
+                    Mat::from_fn(runs.incidence.nrows(), 1, |t, _| runs.incidence[(t, r)]);
+                costs.evaluate(scenario, &incidence, step_size)
+            })
+            .collect();
+        return (runs.draws, evaluations);
+    };
+    let (chosen, reference) = evaluate(baseline);
+    let mut comparisons = BTreeMap::new();
+    for scenario in scenarios.iter() {
+        let (_, evaluations) = evaluate(scenario);
+        let paired = reference
+            .iter()
+            .zip(evaluations.iter())
+            .map(|(b, s)| Comparison::new(b, s))
+            .collect();
+        comparisons.insert(scenario.name.clone(), paired);
+    }
+    return Psa {
+        draws: chosen,
+        comparisons,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::costeffectiveness::{
+        Comparison, Costs, Evaluation, probabilistic_sensitivity,
+    };
+    use crate::sirrs::ensemble::Draws;
+    use crate::sirrs::interventions::{Intervention, Scenario, compare};
+    use crate::sirrs::pipeline::System;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use faer::{Mat, mat};
+
+    fn system() -> Box<dyn System> {
+        let mut model = sir::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.001,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        return Box::new(model);
+    }
+
+    fn lockdown() -> Scenario {
+        let mut scenario = Scenario::new("lockdown");
+        scenario.intervention(Intervention::new(
+            "lockdown",
+            "incidence_rate",
+            10.0,
+            40.0,
+            0.3,
+        ));
+        return scenario;
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // 10 cases at each of 4 steps of 1, and a lockdown for the first 2.
+        let incidence = Mat::from_fn(5, 1, |t, _| if t == 0 { 0.0 } else { 10.0 });
+        let mut scenario = Scenario::new("lockdown");
+        scenario.intervention(Intervention::new(
+            "lockdown",
+            "incidence_rate",
+            0.0,
+            2.0,
+            0.5,
+        ));
+        let mut costs = Costs::new();
+        costs
+            .case(100.0, 0.1)
+            .death(0.01, 5000.0, 30.0)
+            .intervention("lockdown", 50.0, 1000.0);
+        let evaluation = costs.evaluate(&scenario, &incidence, 1.0);
+        assert_eq!(
+            evaluation.intervention_cost, 2050.0,
+            "Bad intervention cost"
+        );
+        assert!(
+            (evaluation.outcome_cost - 6000.0).abs() < 1e-9,
+            "Bad outcome cost, expected 6000 got {}",
+            evaluation.outcome_cost
+        );
+        assert!(
+            (evaluation.dalys - 16.0).abs() < 1e-9,
+            "Bad DALYs, expected 16 got {}",
+            evaluation.dalys
+        );
+        costs.discount(0.03);
+        let discounted = costs.evaluate(&scenario, &incidence, 1.0);
+        assert!(
+            discounted.cost() < evaluation.cost(),
+            "Expected discounting to reduce costs"
+        );
+        assert_eq!(discounted.cases, 40.0, "Expected undiscounted cases");
+    }
+
+    #[test]
+    fn test_comparison() {
+        let evaluation = |cost: f64, dalys: f64| Evaluation {
+            intervention_cost: cost,
+            outcome_cost: 0.0,
+            cases: 0.0,
+            deaths: 0.0,
+            dalys,
+        };
+        let comparison = Comparison::new(&evaluation(100.0, 50.0), &evaluation(600.0, 40.0));
+        assert_eq!(comparison.icer(), 50.0, "Bad ICER");
+        assert_eq!(comparison.net_benefit(80.0), 300.0, "Bad net benefit");
+        assert!(
+            !comparison.dominated() & !comparison.dominant(),
+            "Expected a trade-off"
+        );
+        let worse = Comparison::new(&evaluation(100.0, 50.0), &evaluation(200.0, 60.0));
+        assert!(worse.dominated(), "Expected a dominated scenario");
+        // Through compare, a lockdown averts DALYs at a cost.
+        let scenarios = [Scenario::new("baseline"), lockdown()];
+        let results = compare(system, 100, 0.5, &scenarios);
+        let mut costs = Costs::new();
+        costs.case(0.0, 0.01).intervention("lockdown", 0.0, 1.0);
+        let evaluations = costs.evaluate_all(&scenarios, &results, 0.5);
+        let comparison = Comparison::new(&evaluations["baseline"], &evaluations["lockdown"]);
+        assert_eq!(comparison.incremental_cost, 30.0, "Bad lockdown cost");
+        assert!(
+            comparison.dalys_averted > 0.0,
+            "Expected lockdown to avert DALYs, got {}",
+            comparison.dalys_averted
+        );
+    }
+
+    #[test]
+    fn test_probabilistic_sensitivity() {
+        let draws = Draws::new(
+            vec!["incidence_rate".to_string()],
+            mat![[0.3], [0.4], [0.5]],
+        );
+        let mut costs = Costs::new();
+        costs.case(10.0, 0.01).intervention("lockdown", 0.0, 1.0);
+        let psa = probabilistic_sensitivity(
+            system,
+            &draws,
+            &Scenario::new("baseline"),
+            &[lockdown()],
+            &costs,
+            100,
+            0.5,
+            30,
+            4,
+        );
+        assert_eq!(psa.comparisons["lockdown"].len(), 30, "Bad number of runs");
+        let (cost, dalys) = psa.intervals("lockdown", 0.9);
+        assert!(
+            (dalys.lower < dalys.upper) & (cost.lower < cost.upper),
+            "Expected uncertainty from the draws, got {:?} and {:?}",
+            dalys,
+            cost
+        );
+        let curve = psa.acceptability("lockdown", &[0.0, 1e3, 1e9]);
+        assert!(
+            (curve[0] <= curve[1]) & (curve[1] <= curve[2]) & (curve[2] == 1.0),
+            "Expected a rising acceptability curve, got {:?}",
+            curve
+        );
+        assert!(psa.icer("lockdown").is_finite(), "Expected a finite ICER");
+    }
+}
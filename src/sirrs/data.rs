@@ -0,0 +1,704 @@
+//! Readers for exogenous input data files.
+//!
+//! Files are plain comma separated text with a single header row. Dates may
+//! be given either as a number of days since the start of the model, or as
+//! ISO 8601 calendar dates (`YYYY-MM-DD`) which are converted to days since a
+//! caller supplied start date.
+//!
+//! Besides time series, structured model inputs are read: age group
+//! populations, contact matrices and rate schedules. Malformed input is an
+//! `InvalidData` error naming the offending line.
+use crate::sirrs::ensemble::Draws;
+use crate::sirrs::lifetable::LifeTable;
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::ssa::{Event, EventKind};
+use faer::Mat;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Observed cumulative vaccination coverage of one age group on one date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverageRecord {
+    /// Time of the observation, in days since the start of the model.
+    pub t: f64,
+    /// Index of the age group.
+    pub group: usize,
+    /// Fraction of the age group vaccinated by time `t`. Must be in [0, 1].
+    pub coverage: f64,
+}
+
+/// Build an `InvalidData` error pointing at a line of the input.
+fn invalid(line: usize, message: String) -> Error {
+    return Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    );
+}
+
+/// Days since 1970-01-01 of a proleptic gregorian calendar date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - (era * 400);
+    let mp = (month + 9) % 12;
+    let doy = ((153 * mp) + 2) / 5 + day - 1;
+    let doe = (yoe * 365) + (yoe / 4) - (yoe / 100) + doy;
+    return (era * 146097) + doe - 719468;
+}
+
+/// Number of days in `month` of `year`, in the proleptic gregorian calendar.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = ((year % 4 == 0) & (year % 100 != 0)) | (year % 400 == 0);
+    match month {
+        2 if leap => return 29,
+        2 => return 28,
+        4 | 6 | 9 | 11 => return 30,
+        _ => return 31,
+    }
+}
+
+/// Parse an ISO 8601 `YYYY-MM-DD` date into days since 1970-01-01.
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.trim().split('-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<i64>().ok()?;
+    let day = parts.next()?.parse::<i64>().ok()?;
+    if parts.next().is_some() | !(1..=12).contains(&month) {
+        return None;
+    }
+    if !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    return Some(days_from_civil(year, month, day));
+}
+
+/// Parse a time field, either a number of days or an ISO date relative to
+/// `start_date`.
+fn parse_time(field: &str, start_date: Option<&str>, line: usize) -> Result<f64, Error> {
+    if let Ok(t) = field.trim().parse::<f64>() {
+        return Ok(t);
+    }
+    let date =
+        parse_date(field).ok_or_else(|| invalid(line, format!("bad date '{}'", field.trim())))?;
+    let start = match start_date {
+        Some(s) => parse_date(s).ok_or_else(|| invalid(line, format!("bad start date '{}'", s)))?,
+        None => {
+            return Err(invalid(
+                line,
+                format!("calendar date '{}' requires a start date", field.trim()),
+            ));
+        }
+    };
+    return Ok((date - start) as f64);
+}
+
+/// Split a csv row into trimmed fields, checking the number of columns.
+fn split_row(row: &str, n_columns: usize, line: usize) -> Result<Vec<&str>, Error> {
+    let fields: Vec<&str> = row.split(',').map(|f| f.trim()).collect();
+    if fields.len() != n_columns {
+        return Err(invalid(
+            line,
+            format!("expected {} columns got {}", n_columns, fields.len()),
+        ));
+    }
+    return Ok(fields);
+}
+
+/// Parse vaccination coverage from csv text with columns
+/// `date,age_group,coverage`.
+///
+/// `start_date` is required only if the date column holds calendar dates.
+/// Records are returned sorted by time.
+pub fn parse_coverage_csv(
+    text: &str,
+    start_date: Option<&str>,
+) -> Result<Vec<CoverageRecord>, Error> {
+    let mut records = Vec::new();
+    for (n, row) in text.lines().enumerate().skip(1) {
+        let line = n + 1;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(row, 3, line)?;
+        let t = parse_time(fields[0], start_date, line)?;
+        let group = fields[1]
+            .parse::<usize>()
+            .map_err(|_| invalid(line, format!("bad age group '{}'", fields[1])))?;
+        let coverage = fields[2]
+            .parse::<f64>()
+            .map_err(|_| invalid(line, format!("bad coverage '{}'", fields[2])))?;
+        if !(0.0..=1.0).contains(&coverage) {
+            return Err(invalid(
+                line,
+                format!("coverage must be in [0, 1] got {}", coverage),
+            ));
+        }
+        records.push(CoverageRecord { t, group, coverage });
+    }
+    records.sort_by(|a, b| a.t.total_cmp(&b.t));
+    return Ok(records);
+}
+
+/// Read vaccination coverage from a csv file. See [`parse_coverage_csv`].
+pub fn read_coverage_csv(
+    path: impl AsRef<Path>,
+    start_date: Option<&str>,
+) -> Result<Vec<CoverageRecord>, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_coverage_csv(&text, start_date);
+}
+
+/// Parse a non-negative number from a field named `what`.
+fn parse_non_negative(field: &str, what: &str, line: usize) -> Result<f64, Error> {
+    let value = field
+        .parse::<f64>()
+        .map_err(|_| invalid(line, format!("bad {} '{}'", what, field)))?;
+    if (value < 0.0) | !value.is_finite() {
+        return Err(invalid(
+            line,
+            format!("{} must be finite and non-negative got {}", what, value),
+        ));
+    }
+    return Ok(value);
+}
+
+/// Parse age group populations from csv text with columns
+/// `age_group,population`, one row per group numbered from 0 in any order.
+///
+/// Populations may be counts or fractions. They are returned as fractions
+/// of the total, a column with one row per group as taken by the
+/// structured models, for example [`crate::age::Model`].
+pub fn parse_population_csv(text: &str) -> Result<Mat<f64>, Error> {
+    let mut rows: Vec<(usize, f64, usize)> = Vec::new();
+    for (n, row) in text.lines().enumerate().skip(1) {
+        let line = n + 1;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(row, 2, line)?;
+        let group = fields[0]
+            .parse::<usize>()
+            .map_err(|_| invalid(line, format!("bad age group '{}'", fields[0])))?;
+        let population = parse_non_negative(fields[1], "population", line)?;
+        rows.push((group, population, line));
+    }
+    rows.sort_by_key(|(group, _, _)| *group);
+    for (expected, (group, _, line)) in rows.iter().enumerate() {
+        if *group != expected {
+            return Err(invalid(
+                *line,
+                format!(
+                    "age groups must be numbered 0 to {} without gaps or repeats, got {}",
+                    rows.len() - 1,
+                    group
+                ),
+            ));
+        }
+    }
+    let total: f64 = rows.iter().map(|(_, population, _)| population).sum();
+    if total <= 0.0 {
+        return Err(invalid(
+            1,
+            format!("expected a positive total population got {}", total),
+        ));
+    }
+    return Ok(Mat::from_fn(rows.len(), 1, |g, _| rows[g].1 / total));
+}
+
+/// Read age group populations from a csv file. See [`parse_population_csv`].
+pub fn read_population_csv(path: impl AsRef<Path>) -> Result<Mat<f64>, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_population_csv(&text);
+}
+
+/// Parse a contact matrix from csv text with a header row naming each
+/// group, then one row per group of contacts per unit time with members of
+/// each group (column). The matrix must be square.
+pub fn parse_contact_matrix_csv(text: &str) -> Result<Mat<f64>, Error> {
+    let mut lines = text.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid(1, "missing header".to_string()))?;
+    let n_groups = header.split(',').count();
+    let mut values = Vec::with_capacity(n_groups * n_groups);
+    let mut n_rows = 0;
+    for (n, row) in lines.enumerate() {
+        let line = n + 2;
+        if row.trim().is_empty() {
+            continue;
+        }
+        if n_rows == n_groups {
+            return Err(invalid(
+                line,
+                format!("expected {} rows for {} groups", n_groups, n_groups),
+            ));
+        }
+        for field in split_row(row, n_groups, line)? {
+            values.push(parse_non_negative(field, "contact rate", line)?);
+        }
+        n_rows += 1;
+    }
+    if n_rows != n_groups {
+        return Err(invalid(
+            n_rows + 2,
+            format!(
+                "expected {} rows for {} groups got {}",
+                n_groups, n_groups, n_rows
+            ),
+        ));
+    }
+    return Ok(Mat::from_fn(n_groups, n_groups, |a, b| {
+        values[(a * n_groups) + b]
+    }));
+}
+
+/// Read a contact matrix from a csv file. See [`parse_contact_matrix_csv`].
+pub fn read_contact_matrix_csv(path: impl AsRef<Path>) -> Result<Mat<f64>, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_contact_matrix_csv(&text);
+}
+
+/// Parse a rate schedule from csv text with columns `date,rate`, the rate
+/// in effect from each date onward.
+///
+/// The earliest row must be at or before time 0 and gives the initial rate;
+/// the others are breakpoints. `start_date` is required only if the date
+/// column holds calendar dates.
+/// [`RateSchedule::rates`] gives the rates their unit for
+/// [`crate::sir::Model::incidence_rate_schedule`].
+pub fn parse_rate_schedule_csv(
+    text: &str,
+    start_date: Option<&str>,
+) -> Result<RateSchedule, Error> {
+    let mut rows: Vec<(f64, f64, usize)> = Vec::new();
+    for (n, row) in text.lines().enumerate().skip(1) {
+        let line = n + 1;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(row, 2, line)?;
+        let t = parse_time(fields[0], start_date, line)?;
+        let rate = parse_non_negative(fields[1], "rate", line)?;
+        rows.push((t, rate, line));
+    }
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (t0, initial, line) = *rows
+        .first()
+        .ok_or_else(|| invalid(2, "expected at least one rate".to_string()))?;
+    if t0 > 0.0 {
+        return Err(invalid(
+            line,
+            format!(
+                "the earliest rate must be at or before time 0, got time {}",
+                t0
+            ),
+        ));
+    }
+    let breakpoints = rows[1..].iter().map(|(t, rate, _)| (*t, *rate)).collect();
+    return Ok(RateSchedule::new(initial, breakpoints));
+}
+
+/// Read a rate schedule from a csv file. See [`parse_rate_schedule_csv`].
+pub fn read_rate_schedule_csv(
+    path: impl AsRef<Path>,
+    start_date: Option<&str>,
+) -> Result<RateSchedule, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_rate_schedule_csv(&text, start_date);
+}
+
+/// Parse a stochastic simulation event log from csv text with columns
+/// `t,kind`, where kind is `infection` or `removal`. Events are returned
+/// sorted by time. See [`crate::ssa::write_event_log`].
+pub fn parse_event_log(text: &str) -> Result<Vec<Event>, Error> {
+    let mut events = Vec::new();
+    for (n, row) in text.lines().enumerate().skip(1) {
+        let line = n + 1;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(row, 2, line)?;
+        let t = fields[0]
+            .parse::<f64>()
+            .map_err(|_| invalid(line, format!("bad time '{}'", fields[0])))?;
+        let kind = match fields[1] {
+            "infection" => EventKind::Infection,
+            "removal" => EventKind::Removal,
+            other => return Err(invalid(line, format!("bad event kind '{}'", other))),
+        };
+        events.push(Event { t, kind });
+    }
+    events.sort_by(|a, b| a.t.total_cmp(&b.t));
+    return Ok(events);
+}
+
+/// Read a stochastic simulation event log from a csv file. See
+/// [`parse_event_log`].
+pub fn read_event_log(path: impl AsRef<Path>) -> Result<Vec<Event>, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_event_log(&text);
+}
+
+/// Parse joint parameter draws from csv text with one column per parameter,
+/// named in the header row, and one row per draw, as written by most
+/// posterior sampling tools.
+pub fn parse_draws_csv(text: &str) -> Result<Draws, Error> {
+    let mut lines = text.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid(1, "missing header".to_string()))?;
+    let names: Vec<String> = header.split(',').map(|f| f.trim().to_string()).collect();
+    let mut values = Vec::new();
+    for (n, row) in lines.enumerate() {
+        let line = n + 2;
+        if row.trim().is_empty() {
+            continue;
+        }
+        for field in split_row(row, names.len(), line)? {
+            let value = field
+                .parse::<f64>()
+                .map_err(|_| invalid(line, format!("bad value '{}'", field)))?;
+            values.push(value);
+        }
+    }
+    if values.is_empty() {
+        return Err(invalid(2, "expected at least one draw".to_string()));
+    }
+    let n_params = names.len();
+    let matrix = Mat::from_fn(values.len() / n_params, n_params, |d, j| {
+        values[(d * n_params) + j]
+    });
+    return Ok(Draws::new(names, matrix));
+}
+
+/// Read joint parameter draws from a csv file. See [`parse_draws_csv`].
+pub fn read_draws_csv(path: impl AsRef<Path>) -> Result<Draws, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_draws_csv(&text);
+}
+
+/// Parse a life table from csv text with a header row naming its columns,
+/// in any order, among which `age` and either the mortality rate `mx` or
+/// `nmx`, or the probability of dying `qx` or `nqx` with life expectancy
+/// `ex` for the open-ended last interval. Other columns, such as `lx` or
+/// `nLx`, are ignored.
+///
+/// Ages are the start of each interval, written as a number or as the
+/// labels of abridged tables, such as `1-4` and `85+`.
+pub fn parse_life_table_csv(text: &str) -> Result<LifeTable, Error> {
+    let mut lines = text.lines();
+    let header: Vec<String> = lines
+        .next()
+        .ok_or_else(|| invalid(1, "missing header".to_string()))?
+        .split(',')
+        .map(|f| f.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let age_column =
+        column(&["age"]).ok_or_else(|| invalid(1, "missing age column".to_string()))?;
+    let mx_column = column(&["mx", "nmx"]);
+    let qx_column = column(&["qx", "nqx"]);
+    let ex_column = column(&["ex"]);
+    let value_column = mx_column
+        .or(qx_column)
+        .ok_or_else(|| invalid(1, "expected an mx, nmx, qx or nqx column".to_string()))?;
+    let mut rows: Vec<(f64, f64, Option<f64>, usize)> = Vec::new();
+    for (n, row) in lines.enumerate() {
+        let line = n + 2;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(row, header.len(), line)?;
+        let label = fields[age_column];
+        let age = label
+            .trim_end_matches('+')
+            .split('-')
+            .next()
+            .unwrap_or("")
+            .trim();
+        let age = parse_non_negative(age, "age", line)
+            .map_err(|_| invalid(line, format!("bad age '{}'", label)))?;
+        let value = parse_non_negative(fields[value_column], &header[value_column], line)?;
+        let ex = match ex_column {
+            Some(j) => Some(parse_non_negative(fields[j], "ex", line)?),
+            None => None,
+        };
+        rows.push((age, value, ex, line));
+    }
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if rows.is_empty() {
+        return Err(invalid(2, "expected at least one age interval".to_string()));
+    }
+    if rows[0].0 != 0.0 {
+        return Err(invalid(
+            rows[0].3,
+            format!("ages must start at 0, got {}", rows[0].0),
+        ));
+    }
+    for w in rows.windows(2) {
+        if w[0].0 == w[1].0 {
+            return Err(invalid(w[1].3, format!("repeated age {}", w[1].0)));
+        }
+    }
+    let ages: Vec<f64> = rows.iter().map(|r| r.0).collect();
+    if mx_column.is_some() {
+        return Ok(LifeTable::from_rates(
+            ages,
+            rows.iter().map(|r| r.1).collect(),
+        ));
+    }
+    let last = rows.last().unwrap();
+    let last_mx = match last.2 {
+        Some(ex) if ex > 0.0 => 1.0 / ex,
+        _ => {
+            return Err(invalid(
+                last.3,
+                "expected a positive ex for the open-ended interval of a qx table".to_string(),
+            ));
+        }
+    };
+    for r in rows[..rows.len() - 1].iter() {
+        if r.1 >= 1.0 {
+            return Err(invalid(r.3, format!("qx must be below 1, got {}", r.1)));
+        }
+    }
+    let qx: Vec<f64> = rows[..rows.len() - 1].iter().map(|r| r.1).collect();
+    return Ok(LifeTable::from_probabilities(ages, &qx, last_mx));
+}
+
+/// Read a life table from a csv file. See [`parse_life_table_csv`].
+pub fn read_life_table_csv(path: impl AsRef<Path>) -> Result<LifeTable, Error> {
+    let text = fs::read_to_string(path)?;
+    return parse_life_table_csv(&text);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::data::{
+        parse_contact_matrix_csv, parse_coverage_csv, parse_date, parse_draws_csv, parse_event_log,
+        parse_life_table_csv, parse_population_csv, parse_rate_schedule_csv,
+    };
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::ssa::EventKind;
+    use faer::mat;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11017));
+        assert_eq!(parse_date("2021-13-01"), None);
+        assert_eq!(parse_date("2024-02-31"), None);
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(parse_date("2000-02-29"), Some(11016));
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("not a date"), None);
+    }
+
+    #[test]
+    fn test_parse_coverage_csv() {
+        let text = "date,age_group,coverage\n2021-01-10,1,0.2\n2021-01-03,0,0.1\n";
+        let records = parse_coverage_csv(text, Some("2021-01-01")).unwrap();
+        assert_eq!(
+            records.len(),
+            2,
+            "Bad number of records, expected 2 got {}",
+            records.len()
+        );
+        assert_eq!(
+            records[0].t, 2.0,
+            "Bad records[0].t, expected 2.0 got {}",
+            records[0].t
+        );
+        assert_eq!(
+            records[1].group, 1,
+            "Bad records[1].group, expected 1 got {}",
+            records[1].group
+        );
+    }
+
+    #[test]
+    fn test_parse_coverage_csv_errors() {
+        let bad_value = parse_coverage_csv("date,age_group,coverage\n3,0,1.5\n", None);
+        assert!(
+            bad_value.unwrap_err().to_string().contains("line 2"),
+            "Expected error to name line 2"
+        );
+        let no_start = parse_coverage_csv("date,age_group,coverage\n2021-01-03,0,0.1\n", None);
+        assert!(no_start.is_err(), "Expected error without start date");
+        let bad_columns = parse_coverage_csv("date,age_group,coverage\n3,0\n", None);
+        assert!(bad_columns.is_err(), "Expected error on missing column");
+    }
+
+    #[test]
+    fn test_parse_event_log() {
+        let events = parse_event_log("t,kind\n1.5,removal\n0.25,infection\n").unwrap();
+        assert_eq!(
+            events.len(),
+            2,
+            "Bad number of events, got {}",
+            events.len()
+        );
+        assert_eq!(events[0].kind, EventKind::Infection, "Events not sorted");
+        let bad_kind = parse_event_log("t,kind\n1.0,recovery\n");
+        assert!(
+            bad_kind.unwrap_err().to_string().contains("line 2"),
+            "Expected error to name line 2"
+        );
+    }
+
+    #[test]
+    fn test_parse_population_csv() {
+        let population = parse_population_csv(
+            "age_group,population
+1,750
+0,250
+",
+        )
+        .unwrap();
+        assert_eq!(
+            population,
+            mat![[0.25], [0.75]],
+            "Bad population, got {:?}",
+            population
+        );
+        let gap = parse_population_csv(
+            "age_group,population
+0,250
+2,750
+",
+        );
+        assert!(
+            gap.unwrap_err().to_string().contains("line 3"),
+            "Expected error to name line 3"
+        );
+        let negative = parse_population_csv(
+            "age_group,population
+0,-1
+",
+        );
+        assert!(negative.is_err(), "Expected error on a negative population");
+    }
+
+    #[test]
+    fn test_parse_contact_matrix_csv() {
+        let contacts = parse_contact_matrix_csv(
+            "young,old
+12,1
+1,5
+",
+        )
+        .unwrap();
+        assert_eq!(
+            contacts,
+            mat![[12.0, 1.0], [1.0, 5.0]],
+            "Bad contact matrix, got {:?}",
+            contacts
+        );
+        let short = parse_contact_matrix_csv(
+            "young,old
+12,1
+",
+        );
+        assert!(
+            short.unwrap_err().to_string().contains("expected 2 rows"),
+            "Expected error on a missing row"
+        );
+        let long = parse_contact_matrix_csv(
+            "young,old
+12,1
+1,5
+1,1
+",
+        );
+        assert!(
+            long.unwrap_err().to_string().contains("line 4"),
+            "Expected error to name line 4"
+        );
+        let ragged = parse_contact_matrix_csv(
+            "young,old
+12,1,3
+1,5
+",
+        );
+        assert!(ragged.is_err(), "Expected error on a ragged row");
+    }
+
+    #[test]
+    fn test_parse_rate_schedule_csv() {
+        let text = "date,rate
+2020-03-23,0.1
+2020-03-01,0.4
+2020-05-01,0.25
+";
+        let schedule = parse_rate_schedule_csv(text, Some("2020-03-01")).unwrap();
+        assert_eq!(
+            schedule,
+            RateSchedule::new(0.4, vec![(22.0, 0.1), (61.0, 0.25)]),
+            "Bad schedule, got {:?}",
+            schedule
+        );
+        let late = parse_rate_schedule_csv(
+            "date,rate
+5,0.4
+",
+            None,
+        );
+        assert!(
+            late.unwrap_err()
+                .to_string()
+                .contains("at or before time 0"),
+            "Expected error on a schedule starting late"
+        );
+        let empty = parse_rate_schedule_csv(
+            "date,rate
+",
+            None,
+        );
+        assert!(empty.is_err(), "Expected error on an empty schedule");
+    }
+
+    #[test]
+    fn test_parse_draws_csv() {
+        let draws = parse_draws_csv("incidence_rate,removal_rate\n0.3,0.1\n0.35,0.12\n").unwrap();
+        assert_eq!(
+            draws.names,
+            vec!["incidence_rate", "removal_rate"],
+            "Bad names, got {:?}",
+            draws.names
+        );
+        assert_eq!(
+            draws.n_draws(),
+            2,
+            "Bad number of draws, got {}",
+            draws.n_draws()
+        );
+        assert_eq!(draws.values[(1, 1)], 0.12, "Bad value");
+        let bad_value = parse_draws_csv("incidence_rate\n0.3\nabc\n");
+        assert!(
+            bad_value.unwrap_err().to_string().contains("line 3"),
+            "Expected error to name line 3"
+        );
+    }
+
+    #[test]
+    fn test_parse_life_table_csv() {
+        let rates = parse_life_table_csv("age,mx\n5,0.001\n0,0.01\n1,0.002\n").unwrap();
+        assert_eq!(rates.ages, vec![0.0, 1.0, 5.0], "Bad ages");
+        assert_eq!(rates.mx, vec![0.01, 0.002, 0.001], "Bad rates");
+        let abridged = "Age,n,nqx,lx,ex\n0,1,0.05,100000,30\n1-4,4,0.02,95000,30\n5+,,1,93100,20\n";
+        let table = parse_life_table_csv(abridged).unwrap();
+        assert!(
+            (table.survival(1.0) - 0.95).abs() < 1e-12,
+            "Bad survival to age 1, got {}",
+            table.survival(1.0)
+        );
+        assert_eq!(table.mx[2], 0.05, "Bad open interval rate");
+        let missing = parse_life_table_csv("age,qx\n0,0.05\n1,1\n");
+        assert!(
+            missing.unwrap_err().to_string().contains("line 3"),
+            "Expected error to name line 3"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Seasonal and trend decomposition of time-varying rates.
+//!
+//! Splits a series, such as a fitted incidence rate β(t) sampled at unit
+//! times, into additive trend, seasonal and residual components by classical
+//! moving-average decomposition. Standard errors are reported for the trend
+//! and seasonal components so it is clear how well each is determined.
+//!
+//! For multiplicative effects on a positive rate, decompose its logarithm.
+use faer::Mat;
+
+/// Additive decomposition of a series, `series = trend + seasonal + residual`.
+pub struct Decomposition {
+    /// Period of the seasonal component, in samples.
+    pub period: usize,
+    /// Trend at each index. 1D Array with one element per sample.
+    pub trend: Mat<f64>,
+    /// Standard error of the trend at each index.
+    pub trend_se: Mat<f64>,
+    /// Seasonal component at each index. 1D Array with one element per sample.
+    pub seasonal: Mat<f64>,
+    /// Seasonal effect of each phase of the period. Sums to zero.
+    pub seasonal_effect: Mat<f64>,
+    /// Standard error of each phase's seasonal effect.
+    pub seasonal_se: Mat<f64>,
+    /// Residual at each index. 1D Array with one element per sample.
+    pub residual: Mat<f64>,
+}
+
+/// Centered moving average with window `period`. Even periods use the usual
+/// 2 × `period` weighting, with half weight on the two end points. Near the
+/// ends of the series the window is truncated to the available samples.
+/// Also returns the effective number of samples averaged at each index.
+fn moving_average(series: &Mat<f64>, period: usize) -> (Mat<f64>, Vec<f64>) {
+    let n = series.nrows();
+    let half = period / 2;
+    let mut trend = Mat::zeros(n, 1);
+    let mut counts: Vec<f64> = vec![0.0; n];
+    for t in 0..n {
+        let mut total = 0.0;
+        let mut weight = 0.0;
+        for offset in 0..=(2 * half) {
+            let Some(j) = (t + offset).checked_sub(half) else {
+                continue;
+            };
+            if j >= n {
+                continue;
+            }
+            let w = if period.is_multiple_of(2) & ((offset == 0) | (offset == 2 * half)) {
+                0.5
+            } else {
+                1.0
+            };
+            total += w * series[(j, 0)];
+            weight += w;
+        }
+        trend[(t, 0)] = total / weight;
+        counts[t] = weight;
+    }
+    return (trend, counts);
+}
+
+/// Decompose `series` (a column) into trend, seasonal and residual
+/// components with seasonal period `period` samples.
+pub fn decompose(series: &Mat<f64>, period: usize) -> Decomposition {
+    assert!(period >= 2, "period must be at least 2");
+    let n = series.nrows();
+    let (trend, counts) = moving_average(series, period);
+    // Seasonal effect of each phase is the mean detrended value at that phase.
+    let mut sums = vec![0.0; period];
+    let mut squares = vec![0.0; period];
+    let mut n_phase: Vec<f64> = vec![0.0; period];
+    for t in 0..n {
+        let x = series[(t, 0)] - trend[(t, 0)];
+        sums[t % period] += x;
+        squares[t % period] += x * x;
+        n_phase[t % period] += 1.0;
+    }
+    let means: Vec<f64> = (0..period).map(|p| sums[p] / n_phase[p].max(1.0)).collect();
+    let centre = means.iter().sum::<f64>() / (period as f64);
+    let mut seasonal_effect = Mat::zeros(period, 1);
+    let mut seasonal_se = Mat::zeros(period, 1);
+    for p in 0..period {
+        seasonal_effect[(p, 0)] = means[p] - centre;
+        if n_phase[p] > 1.0 {
+            let variance = (squares[p] - (n_phase[p] * means[p] * means[p])) / (n_phase[p] - 1.0);
+            seasonal_se[(p, 0)] = (variance.max(0.0) / n_phase[p]).sqrt();
+        } else {
+            seasonal_se[(p, 0)] = f64::NAN;
+        }
+    }
+    let seasonal = Mat::from_fn(n, 1, |t, _| seasonal_effect[(t % period, 0)]);
+    let residual = Mat::from_fn(n, 1, |t, _| {
+        series[(t, 0)] - trend[(t, 0)] - seasonal[(t, 0)]
+    });
+    let dof = (n as f64) - (period as f64);
+    let residual_sd = if dof > 0.0 {
+        ((0..n).map(|t| residual[(t, 0)].powi(2)).sum::<f64>() / dof).sqrt()
+    } else {
+        f64::NAN
+    };
+    let trend_se = Mat::from_fn(n, 1, |t, _| residual_sd / counts[t].sqrt());
+    return Decomposition {
+        period,
+        trend,
+        trend_se,
+        seasonal,
+        seasonal_effect,
+        seasonal_se,
+        residual,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::decompose::decompose;
+    use faer::Mat;
+
+    #[test]
+    fn test_recovers_trend_and_season() {
+        let period = 7;
+        let pattern = [0.3, -0.1, -0.2, 0.0, 0.1, 0.05, -0.15];
+        let series = Mat::from_fn(70, 1, |t, _| {
+            1.0 + (0.01 * (t as f64)) + pattern[t % period]
+        });
+        let d = decompose(&series, period);
+        for p in 0..period {
+            assert!(
+                (d.seasonal_effect[(p, 0)] - pattern[p]).abs() < 1e-2,
+                "Bad seasonal effect at phase {}, expected {} got {}",
+                p,
+                pattern[p],
+                d.seasonal_effect[(p, 0)]
+            );
+        }
+        for t in 10..60 {
+            let expected = 1.0 + (0.01 * (t as f64));
+            assert!(
+                (d.trend[(t, 0)] - expected).abs() < 1e-9,
+                "Bad trend at index {}, expected {} got {}",
+                t,
+                expected,
+                d.trend[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_components_sum_to_series() {
+        let series = Mat::from_fn(30, 1, |t, _| ((t * 7919) % 13) as f64);
+        let d = decompose(&series, 4);
+        for t in 0..30 {
+            let total = d.trend[(t, 0)] + d.seasonal[(t, 0)] + d.residual[(t, 0)];
+            assert!(
+                (total - series[(t, 0)]).abs() < 1e-12,
+                "Components do not sum to series at index {}",
+                t
+            );
+        }
+        let effect_sum: f64 = (0..4).map(|p| d.seasonal_effect[(p, 0)]).sum();
+        assert!(
+            effect_sum.abs() < 1e-12,
+            "Seasonal effects do not sum to zero, got {}",
+            effect_sum
+        );
+        assert!(
+            (0..4).all(|p| d.seasonal_se[(p, 0)] > 0.0),
+            "Expected positive seasonal standard errors"
+        );
+    }
+}
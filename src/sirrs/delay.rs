@@ -0,0 +1,295 @@
+//! SIR model with a fixed delay in the force of infection.
+//!
+//! New infections at time t depend on the infectious fraction at `t - delay`,
+//! a delay differential equation:
+//!
+//! ```text
+//! dS/dt = -β S(t) I(t - τ)
+//! dI/dt =  β S(t) I(t - τ) - γ I(t)
+//! dR/dt =  γ I(t)
+//! ```
+//!
+//! The system is solved by the method of steps: each Runge-Kutta stage reads
+//! the delayed infectious fraction from a history buffer of recent states,
+//! interpolated with cubic Hermite polynomials. Before t = 0 the infectious
+//! fraction is the constant `i_history`.
+use crate::sirrs::units::{Duration, Rate, TimeUnit};
+use faer::Mat;
+use std::collections::VecDeque;
+
+/// Bounded history of past infectious fractions and their derivatives.
+///
+/// Only the entries needed to look back `delay` from the current time are
+/// kept, so memory does not grow with the length of the run.
+pub struct History {
+    /// Stored `(t, i, di/dt)`, oldest first.
+    entries: VecDeque<(f64, f64, f64)>,
+    /// Value returned for any time before the first entry.
+    initial: f64,
+    /// Maximum look-back required.
+    delay: f64,
+}
+
+impl History {
+    /// Create an empty history, constant at `initial` before its first entry.
+    pub fn new(initial: f64, delay: f64) -> Self {
+        return Self {
+            entries: VecDeque::new(),
+            initial,
+            delay,
+        };
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    /// Whether the history holds no entries.
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    /// Append the value and derivative at time `t`, dropping entries too
+    /// old to be needed again.
+    pub fn push(&mut self, t: f64, value: f64, derivative: f64) {
+        self.entries.push_back((t, value, derivative));
+        while (self.entries.len() > 2) && (self.entries[1].0 < t - self.delay) {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Interpolated value at time `t`.
+    pub fn at(&self, t: f64) -> f64 {
+        let Some(first) = self.entries.front() else {
+            return self.initial;
+        };
+        if t < first.0 {
+            return self.initial;
+        }
+        let last = self.entries.back().unwrap();
+        if t >= last.0 {
+            return last.1;
+        }
+        let j = self.entries.partition_point(|e| e.0 <= t);
+        let (t0, y0, d0) = self.entries[j - 1];
+        let (t1, y1, d1) = self.entries[j];
+        let h = t1 - t0;
+        let s = (t - t0) / h;
+        let h00 = (2.0 * s * s * s) - (3.0 * s * s) + 1.0;
+        let h10 = (s * s * s) - (2.0 * s * s) + s;
+        let h01 = (-2.0 * s * s * s) + (3.0 * s * s);
+        let h11 = (s * s * s) - (s * s);
+        return (h00 * y0) + (h10 * h * d0) + (h01 * y1) + (h11 * h * d1);
+    }
+}
+
+/// Create and run an SIR model with delayed force of infection.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Infectious population fraction at all times before t = 0.
+    pub i_history: f64,
+    /// Transition rate from S into I. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Delay in the force of infection. Must be at least `step_size`.
+    pub delay: f64,
+    /// Susceptible population fraction at each index. 1D Array with `length` number of elements.
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with `length` number of elements.
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with `length` number of elements.
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            i_history: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            delay: 0.0,
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters, with rates per day and the delay in days.
+    /// The history before t = 0 defaults to `i_popf_init`.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        delay: Duration,
+    ) -> &mut Self {
+        let delay = delay.in_unit(TimeUnit::Day);
+        assert!(
+            delay >= step_size,
+            "delay must be at least step_size, got {} < {}",
+            delay,
+            step_size
+        );
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.i_history = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.delay = delay;
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        self.s_popf[(0, 0)] = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+        self.i_popf[(0, 0)] = self.i_popf_init;
+        self.r_popf[(0, 0)] = 0.0;
+        return self;
+    }
+
+    /// Derivatives of S, I and R given the delayed infectious fraction.
+    fn derivatives(&self, s: f64, i: f64, i_delayed: f64) -> [f64; 3] {
+        let infection = self.incidence_rate * s * i_delayed;
+        return [
+            -infection,
+            infection - (self.removal_rate * i),
+            self.removal_rate * i,
+        ];
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method of steps.
+    ///
+    /// Delayed values come from cubic interpolation of the history buffer,
+    /// which keeps the method fourth order for smooth solutions.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = ((self.length as f64) / h).ceil() as usize;
+        let mut history = History::new(self.i_history, self.delay + h);
+        for t in 0..n - 1 {
+            let time = (t as f64) * h;
+            let y = [
+                self.s_popf[(t, 0)],
+                self.i_popf[(t, 0)],
+                self.r_popf[(t, 0)],
+            ];
+            let k1 = self.derivatives(y[0], y[1], history.at(time - self.delay));
+            history.push(time, y[1], k1[1]);
+            let lag_half = history.at(time + (h / 2.0) - self.delay);
+            let lag_full = history.at(time + h - self.delay);
+            let y2: Vec<f64> = (0..3).map(|j| y[j] + (h / 2.0 * k1[j])).collect();
+            let k2 = self.derivatives(y2[0], y2[1], lag_half);
+            let y3: Vec<f64> = (0..3).map(|j| y[j] + (h / 2.0 * k2[j])).collect();
+            let k3 = self.derivatives(y3[0], y3[1], lag_half);
+            let y4: Vec<f64> = (0..3).map(|j| y[j] + (h * k3[j])).collect();
+            let k4 = self.derivatives(y4[0], y4[1], lag_full);
+            let w = h / 6.0;
+            self.s_popf[(t + 1, 0)] = y[0] + ((k1[0] + (2.0 * k2[0]) + (2.0 * k3[0]) + k4[0]) * w);
+            self.i_popf[(t + 1, 0)] = y[1] + ((k1[1] + (2.0 * k2[1]) + (2.0 * k3[1]) + k4[1]) * w);
+            self.r_popf[(t + 1, 0)] = y[2] + ((k1[2] + (2.0 * k2[2]) + (2.0 * k3[2]) + k4[2]) * w);
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::delay::{History, Model};
+    use crate::sirrs::units::{Duration, Rate};
+
+    #[test]
+    fn test_history_interpolation() {
+        let mut history = History::new(0.5, 10.0);
+        for t in 0..5 {
+            let x = t as f64;
+            history.push(x, x * x * x, 3.0 * x * x);
+        }
+        assert_eq!(history.at(-1.0), 0.5, "Bad value before first entry");
+        assert!(
+            (history.at(2.5) - 15.625).abs() < 1e-12,
+            "Bad cubic interpolation, expected 15.625 got {}",
+            history.at(2.5)
+        );
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = History::new(0.0, 1.0);
+        for t in 0..1000 {
+            history.push((t as f64) * 0.1, 0.0, 0.0);
+        }
+        assert!(
+            history.len() <= 13,
+            "History not bounded, got {} entries",
+            history.len()
+        );
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Duration::days(5.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)] + model.i_popf[(t, 0)] + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_delay_postpones_peak() {
+        let peak = |delay: f64| -> usize {
+            let mut model = Model::new();
+            model.configure(
+                150,
+                0.25,
+                0.001,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Duration::days(delay),
+            );
+            model.init_popf();
+            model.run_rk4();
+            return (0..model.i_popf.nrows())
+                .max_by(|&a, &b| model.i_popf[(a, 0)].total_cmp(&model.i_popf[(b, 0)]))
+                .unwrap();
+        };
+        assert!(
+            peak(8.0) > peak(1.0),
+            "Expected longer delay to postpone the peak, got {} <= {}",
+            peak(8.0),
+            peak(1.0)
+        );
+    }
+}
@@ -0,0 +1,332 @@
+//! Differences between two runs.
+//!
+//! Two runs that should be identical, such as the same configuration on two
+//! machines or before and after a change, can diverge through a parameter,
+//! the solver, the crate version or the seed. [`diff`] compares two
+//! [`SimulationResult`]s, for example runs saved by `sirrs sir --json` and
+//! read back by [`SimulationResult::from_json`], and reports:
+//!  - metadata that differs, except the timestamp
+//!  - parameters that differ or are set in only one run
+//!  - outcome [`metrics`] that differ: the final and peak value of each
+//!    series and the time of the peak
+//!  - where each shared series first diverges, and by how much at most
+//!
+//! Values differ when they are further apart than a relative tolerance,
+//! zero for exact comparison. [`RunDiff::report`] renders the differences
+//! as text, as printed by `sirrs diff`.
+use crate::sirrs::metadata::SimulationResult;
+
+/// A named value in either run, `None` where the run lacks it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    /// Name of the value.
+    pub name: String,
+    /// Value in the first run.
+    pub a: Option<T>,
+    /// Value in the second run.
+    pub b: Option<T>,
+}
+
+/// Where a series shared by both runs diverges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Name of the series.
+    pub name: String,
+    /// First time at which the runs differ.
+    pub first_time: f64,
+    /// Largest absolute difference over the shared times.
+    pub max_difference: f64,
+}
+
+/// Differences between two runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunDiff {
+    /// Metadata fields that differ.
+    pub metadata: Vec<Change<String>>,
+    /// Parameters that differ.
+    pub parameters: Vec<Change<f64>>,
+    /// Outcome metrics that differ.
+    pub metrics: Vec<Change<f64>>,
+    /// Shared series that diverge, compared at shared times.
+    pub series: Vec<Divergence>,
+}
+
+/// Whether `a` and `b` are within relative `tolerance` of each other. NaN
+/// equals NaN.
+fn same(a: f64, b: f64, tolerance: f64) -> bool {
+    if a.is_nan() | b.is_nan() {
+        return a.is_nan() & b.is_nan();
+    }
+    return (a == b) | ((a - b).abs() <= tolerance * a.abs().max(b.abs()));
+}
+
+/// Outcome metrics of `result`: for each series, `<name>.final`,
+/// `<name>.peak` and `<name>.peak_time`.
+pub fn metrics(result: &SimulationResult) -> Vec<(String, f64)> {
+    let n = result.values.nrows();
+    let mut metrics = Vec::new();
+    if n == 0 {
+        return metrics;
+    }
+    for (j, name) in result.names.iter().enumerate() {
+        let peak = (0..n)
+            .max_by(|&a, &b| result.values[(a, j)].total_cmp(&result.values[(b, j)]))
+            .unwrap();
+        metrics.push((format!("{}.final", name), result.values[(n - 1, j)]));
+        metrics.push((format!("{}.peak", name), result.values[(peak, j)]));
+        metrics.push((format!("{}.peak_time", name), result.times[peak]));
+    }
+    return metrics;
+}
+
+/// Numeric values differing between `a` and `b` beyond `tolerance`, in the
+/// order of `a` then of those only in `b`.
+fn changes(a: &[(String, f64)], b: &[(String, f64)], tolerance: f64) -> Vec<Change<f64>> {
+    let find = |values: &[(String, f64)], name: &str| {
+        values.iter().find(|(n, _)| n == name).map(|(_, x)| *x)
+    };
+    let mut changes = Vec::new();
+    for (name, x) in a.iter() {
+        let y = find(b, name);
+        if y.is_none_or(|y| !same(*x, y, tolerance)) {
+            changes.push(Change {
+                name: name.clone(),
+                a: Some(*x),
+                b: y,
+            });
+        }
+    }
+    for (name, y) in b.iter() {
+        if find(a, name).is_none() {
+            changes.push(Change {
+                name: name.clone(),
+                a: None,
+                b: Some(*y),
+            });
+        }
+    }
+    return changes;
+}
+
+/// Differences between runs `a` and `b`, with values compared to relative
+/// `tolerance`.
+pub fn diff(a: &SimulationResult, b: &SimulationResult, tolerance: f64) -> RunDiff {
+    assert!(
+        tolerance >= 0.0,
+        "tolerance must be non-negative, got {}",
+        tolerance
+    );
+    let mut metadata: Vec<Change<String>> = a
+        .metadata
+        .pairs()
+        .into_iter()
+        .zip(b.metadata.pairs())
+        .filter(|((key, x), (_, y))| (*key != "timestamp") & (x != y))
+        .map(|((key, x), (_, y))| Change {
+            name: key.to_string(),
+            a: Some(x),
+            b: Some(y),
+        })
+        .collect();
+    if a.times.len() != b.times.len() {
+        metadata.push(Change {
+            name: "n_times".to_string(),
+            a: Some(a.times.len().to_string()),
+            b: Some(b.times.len().to_string()),
+        });
+    }
+    let parameters = |result: &SimulationResult| -> Vec<(String, f64)> {
+        return result
+            .metadata
+            .parameters
+            .iter()
+            .map(|(name, x)| (name.clone(), *x))
+            .collect();
+    };
+    let mut series = Vec::new();
+    for (j, name) in a.names.iter().enumerate() {
+        let Some(k) = b.names.iter().position(|n| n == name) else {
+            continue;
+        };
+        let shared: Vec<usize> = (0..a.times.len().min(b.times.len()))
+            .filter(|t| a.times[*t] == b.times[*t])
+            .collect();
+        let differing: Vec<usize> = shared
+            .iter()
+            .copied()
+            .filter(|t| !same(a.values[(*t, j)], b.values[(*t, k)], tolerance))
+            .collect();
+        if let Some(first) = differing.first() {
+            let max_difference = differing
+                .iter()
+                .map(|t| (a.values[(*t, j)] - b.values[(*t, k)]).abs())
+                .fold(0.0, f64::max);
+            series.push(Divergence {
+                name: name.clone(),
+                first_time: a.times[*first],
+                max_difference,
+            });
+        }
+    }
+    return RunDiff {
+        metadata,
+        parameters: changes(&parameters(a), &parameters(b), tolerance),
+        metrics: changes(&metrics(a), &metrics(b), tolerance),
+        series,
+    };
+}
+
+/// `x` as text, `-` where missing.
+fn show<T: ToString>(x: &Option<T>) -> String {
+    return x.as_ref().map_or("-".to_string(), |x| x.to_string());
+}
+
+impl RunDiff {
+    /// Whether the runs are the same.
+    pub fn is_empty(&self) -> bool {
+        return self.metadata.is_empty()
+            & self.parameters.is_empty()
+            & self.metrics.is_empty()
+            & self.series.is_empty();
+    }
+
+    /// The differences as text, a section per kind of difference with one
+    /// `name: a -> b` line per difference.
+    pub fn report(&self) -> String {
+        if self.is_empty() {
+            return "runs are identical\n".to_string();
+        }
+        let mut text = String::new();
+        if !self.metadata.is_empty() {
+            text.push_str("metadata\n");
+            for change in self.metadata.iter() {
+                text.push_str(&format!(
+                    "  {}: {} -> {}\n",
+                    change.name,
+                    show(&change.a),
+                    show(&change.b)
+                ));
+            }
+        }
+        for (section, changes) in [("parameters", &self.parameters), ("metrics", &self.metrics)] {
+            if changes.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("{}\n", section));
+            for change in changes.iter() {
+                let delta = match (change.a, change.b) {
+                    (Some(a), Some(b)) => format!(" ({:+})", b - a),
+                    _ => String::new(),
+                };
+                text.push_str(&format!(
+                    "  {}: {} -> {}{}\n",
+                    change.name,
+                    show(&change.a),
+                    show(&change.b),
+                    delta
+                ));
+            }
+        }
+        if !self.series.is_empty() {
+            text.push_str("series\n");
+            for divergence in self.series.iter() {
+                text.push_str(&format!(
+                    "  {}: diverges at t = {}, max difference {}\n",
+                    divergence.name, divergence.first_time, divergence.max_difference
+                ));
+            }
+        }
+        return text;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::diff::diff;
+    use crate::sirrs::metadata::SimulationResult;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    fn run(incidence_rate: f64, changepoint: Option<(f64, f64)>) -> SimulationResult {
+        let mut model = sir::Model::new();
+        model.configure(
+            40,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(incidence_rate),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        if let Some(change) = changepoint {
+            model.changepoints(vec![(change.0, Rate::per_day(change.1))]);
+        }
+        model.init_popf();
+        model.run_rk4();
+        return model.result("rk4");
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = run(0.3, None);
+        let mut b = SimulationResult::from_json(&a.to_json()).unwrap();
+        b.metadata.timestamp += 60;
+        let result = diff(&a, &b, 0.0);
+        assert!(
+            result.is_empty(),
+            "Expected no differences, got {}",
+            result.report()
+        );
+        assert_eq!(result.report(), "runs are identical\n", "Bad report");
+    }
+
+    #[test]
+    fn test_diverging() {
+        // A changepoint at t = 10 leaves the runs identical until the step
+        // reaching it.
+        let a = run(0.3, None);
+        let b = run(0.3, Some((10.0, 0.1)));
+        let result = diff(&a, &b, 1e-12);
+        assert_eq!(
+            result
+                .metadata
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["parameters_hash"],
+            "Expected only the parameters hash to differ"
+        );
+        assert_eq!(result.parameters.len(), 1, "Bad parameter changes");
+        assert_eq!(
+            (
+                result.parameters[0].name.as_str(),
+                result.parameters[0].a,
+                result.parameters[0].b
+            ),
+            ("incidence_rate@10", None, Some(0.1)),
+            "Bad parameter change"
+        );
+        let i = result.series.iter().find(|d| d.name == "i").unwrap();
+        assert!(
+            (i.first_time > 9.5) & (i.first_time <= 10.5),
+            "Expected divergence at the changepoint, got t = {}",
+            i.first_time
+        );
+        assert!(
+            result.metrics.iter().any(|c| c.name == "i.final"),
+            "Expected the final infected fraction to differ"
+        );
+        let report = result.report();
+        assert!(
+            report.contains("parameters\n  incidence_rate@10: - -> 0.1\n")
+                & report.contains("\nseries\n  s: diverges at t = "),
+            "Bad report, got {}",
+            report
+        );
+        let loose = diff(&a, &b, 1.0);
+        assert!(
+            loose.series.is_empty() & loose.metrics.is_empty(),
+            "Expected every value within a relative tolerance of 1"
+        );
+    }
+}
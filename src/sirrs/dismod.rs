@@ -7,53 +7,220 @@
 //!  - C → Rc
 //!  - C → Ro
 //!
+//! Incidence (the S → C flux) is recorded per step and cumulatively, and the
+//! standard measures reported by DisMod-AT (prevalence, incidence, remission
+//! and mortality rates) are derived from the solved trajectories.
+//!
+//! Measurements are [`DataPoint`]s, each a [`Measure`] averaged over an
+//! interval of ages. [`Model::predict`] averages the solved measure over
+//! each interval, and [`Model::log_likelihood`] scores the data against those
+//! averages, as DisMod-AT does when estimating rates. The model's time axis
+//! is age in years, following a cohort from birth, and rates are per year.
+//!
+//! A [`Hierarchy`] of locations, such as world, regions and countries, gives
+//! each child location its parent's rates times exponentiated random
+//! effects, as in DisMod-AT's parent/child model, and solves a model for
+//! every location.
+//!
+//! Rates varying by age and calendar time are a [`RateGrid`], interpolated
+//! bilinearly between knots. [`fit_rates`] estimates a grid from rate
+//! measurements, regularized by a [`SmoothingPrior`] on the values and on
+//! the differences between neighbouring knots in age and in time, as
+//! DisMod-AT smooths its rate surfaces.
+//!
+//! [`AgeTimeModel`] solves the equations with every rate a [`RateGrid`],
+//! along age by one of two [`Solution`]s: a period solution holds calendar
+//! time fixed, the synthetic cohort experiencing one year's rates at every
+//! age, while a cohort solution follows those born in one year, whose rates
+//! at each age are those of the year they reach it.
+//!
+//! [`back_calculate`] inverts the equations, finding the incidence rate by
+//! age consistent with a prevalence curve and given remission and excess
+//! mortality.
+//!
 //! See [DisMod's latest documentation](https://dismod-at.readthedocs.io/latest/diff_eq.html#diff-eq-title).
+use crate::sirrs::fit::{Convergence, lbfgs};
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::rng;
+use crate::sirrs::scalar::{Scalar, from_f64};
+use crate::sirrs::schema::ModelSchema;
+use crate::sirrs::stability::Stability;
+use crate::sirrs::units::{Rate, TimeUnit};
 use faer::Mat;
+use rand_distr::{Distribution, Normal};
+use std::io::{Error, ErrorKind};
 
 /// Numerical integrator variables
 ///
 /// This private struct exists to make indexing k and y during integration
 /// simpler.
-struct SystemVars {
-    s: f64,
-    c: f64,
+#[derive(Clone, Copy)]
+struct SystemVars<T> {
+    s: T,
+    c: T,
+    x: T,
+}
+
+/// Measures reported by DisMod-AT, named as in its data table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+    /// C / (S + C), see [`Model::prevalence`].
+    Prevalence,
+    /// `Sincidence`, see [`Model::susceptible_incidence_rate`].
+    Sincidence,
+    /// `Tincidence`, see [`Model::total_incidence_rate`].
+    Tincidence,
+    /// `remission`, see [`Model::remission`].
+    Remission,
+    /// `mtexcess`, see [`Model::excess_mortality`].
+    Mtexcess,
+    /// `mtwith`, see [`Model::with_condition_mortality`].
+    Mtwith,
+    /// `mtall`, see [`Model::all_cause_mortality`].
+    Mtall,
+}
+
+/// A measurement of a measure averaged over an interval of ages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataPoint {
+    /// What was measured.
+    pub measure: Measure,
+    /// Start of the age interval.
+    pub age_lower: f64,
+    /// End of the age interval, equal to `age_lower` for a measurement at
+    /// one age.
+    pub age_upper: f64,
+    /// Measured value.
+    pub value: f64,
+    /// Standard deviation of the measurement error. Must be positive.
+    pub sd: f64,
+    /// Calendar time of the measurement. [`Model`] rates are the same at
+    /// every time, so only [`fit_rates`] uses it.
+    pub time: f64,
 }
 
 /// Create and run a DisMod-type model.
-pub struct Model {
+///
+/// Population fractions and rates are stored and integrated in the scalar
+/// type `T`, `f64` unless chosen otherwise, see [`crate::scalar`]. Outputs
+/// and analyses, such as [`Model::result`] and [`Model::predict`], are
+/// `f64` whatever `T`.
+pub struct Model<T: Scalar = f64> {
     /// Number of indices to generate and solve. The length of the series.
     pub length: usize,
     /// Size of integration step.
     pub step_size: f64,
     /// Initial with-condition population fraction.
-    pub c_init: f64,
-    /// Transition rate from S into C. Must be in [0, 1].
-    pub iota: f64,
-    /// Transition rate from C into S. Must be in [0, 1].
-    pub rho: f64,
-    /// Transition rate from C into Rc. Must be in [0, 1].
-    pub chi: f64,
-    /// Transition rate from S, C into Ro. Must be in [0, 1].
-    pub omega: f64,
-    /// Susceptible population fraction at each index. 1D Array with `length` number of elements.
-    pub s: Mat<f64>,
-    /// With-condition population fraction at each index. 1D Array with `length` number of elements.
-    pub c: Mat<f64>,
+    pub c_init: T,
+    /// Transition rate from S into C, per year. Must be in [0, 1].
+    pub iota: T,
+    /// Transition rate from C into S, per year. Must be in [0, 1].
+    pub rho: T,
+    /// Transition rate from C into Rc, per year. Must be in [0, 1].
+    pub chi: T,
+    /// Transition rate from S, C into Ro, per year. Must be in [0, 1].
+    pub omega: T,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s: Mat<T>,
+    /// With-condition population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub c: Mat<T>,
+    /// New cases, as a population fraction, over the step ending at each
+    /// index. 1D Array with one element per index of [`Model::grid`], zero
+    /// at index 0.
+    pub incidence: Mat<T>,
+    /// Cumulative cases, as a population fraction, up to each index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub cumulative_incidence: Mat<T>,
 }
 
 impl Model {
     /// Create an empty model object.
     pub fn new() -> Self {
+        return Self::zeroed();
+    }
+
+    /// Compartments, flows and parameters of the model. Rc and Ro are
+    /// absorbing and not tracked in the solution.
+    pub fn schema() -> ModelSchema {
+        let mut schema = ModelSchema::new("dismod", "Four compartment DisMod model");
+        schema
+            .compartment("S")
+            .compartment("C")
+            .compartment("Rc")
+            .compartment("Ro")
+            .flow("S", "C", "iota")
+            .flow("S", "Ro", "omega")
+            .flow("C", "S", "rho")
+            .flow("C", "Rc", "chi")
+            .flow("C", "Ro", "omega")
+            .parameter("length", "Length of the series", 1.0, f64::INFINITY, "time")
+            .parameter(
+                "step_size",
+                "Size of integration step",
+                0.0,
+                f64::INFINITY,
+                "time",
+            )
+            .parameter(
+                "c_init",
+                "Initial with-condition population fraction",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "iota",
+                "Incidence, transition rate from S into C",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "rho",
+                "Remission, transition rate from C into S",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "chi",
+                "Excess mortality, transition rate from C into Rc",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "omega",
+                "Other cause mortality, transition rate into Ro",
+                0.0,
+                1.0,
+                "1/time",
+            );
+        return schema;
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Create an empty model object in the scalar type `T`, for example
+    /// `Model::<f32>::zeroed()`. See [`Model::new`] for `f64`.
+    pub fn zeroed() -> Self {
         return Self {
             length: 0,
             step_size: 0.0,
-            c_init: 0.0,
-            iota: 0.0,
-            rho: 0.0,
-            chi: 0.0,
-            omega: 0.0,
+            c_init: T::zero_impl(),
+            iota: T::zero_impl(),
+            rho: T::zero_impl(),
+            chi: T::zero_impl(),
+            omega: T::zero_impl(),
             s: Mat::new(),
             c: Mat::new(),
+            incidence: Mat::new(),
+            cumulative_incidence: Mat::new(),
         };
     }
 
@@ -62,86 +229,125 @@ impl Model {
         &mut self,
         length: usize,
         step_size: f64,
-        c_init: f64,
-        iota: f64,
-        rho: f64,
-        chi: f64,
-        omega: f64,
+        c_init: T,
+        iota: Rate,
+        rho: Rate,
+        chi: Rate,
+        omega: Rate,
     ) -> &mut Self {
-        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
         self.length = length;
         self.step_size = step_size;
         self.c_init = c_init;
-        self.iota = iota;
-        self.rho = rho;
-        self.chi = chi;
-        self.omega = omega;
+        self.iota = from_f64(iota.per(TimeUnit::Year));
+        self.rho = from_f64(rho.per(TimeUnit::Year));
+        self.chi = from_f64(chi.per(TimeUnit::Year));
+        self.omega = from_f64(omega.per(TimeUnit::Year));
         self.s = Mat::zeros(n_steps, 1);
         self.c = Mat::zeros(n_steps, 1);
+        self.incidence = Mat::zeros(n_steps, 1);
+        self.cumulative_incidence = Mat::zeros(n_steps, 1);
         return self;
     }
 
-    /// Initialize population fractions. Creates arrays of length `self.length`
-    /// to store the population fractions at each index and sets the 0th index
-    /// of each equal to the corresponding initial population fraction.
-    pub fn init_popf(&mut self) -> &mut Model {
-        let s_init = 1.0 - self.c_init; // Population fractions must sum to 1.
+    /// Times of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each output
+    /// equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Self {
+        let s_init = T::one_impl() - self.c_init; // Population fractions must sum to 1.
         self.s[(0, 0)] = s_init;
         self.c[(0, 0)] = self.c_init;
+        self.incidence[(0, 0)] = T::zero_impl();
+        self.cumulative_incidence[(0, 0)] = T::zero_impl();
         return self;
     }
 
-    fn dsdt(&self, s: f64, c: f64) -> f64 {
+    /// Rate of change of the susceptible fraction.
+    pub(crate) fn dsdt(&self, s: T, c: T) -> T {
         return -((self.iota + self.omega) * s) + (self.rho * c);
     }
 
-    fn dcdt(&self, s: f64, c: f64) -> f64 {
+    /// Rate of change of the with-condition fraction.
+    pub(crate) fn dcdt(&self, s: T, c: T) -> T {
         return (self.iota * s) - ((self.rho + self.chi + self.omega) * c);
     }
 
+    /// Rate of new cases, the S → C flux.
+    fn dxdt(&self, s: T) -> T {
+        return self.iota * s;
+    }
+
+    /// Record `dx` new cases over the step ending at index `t`.
+    fn record_incidence(&mut self, t: usize, dx: T) {
+        self.incidence[(t, 0)] = dx;
+        self.cumulative_incidence[(t, 0)] = self.cumulative_incidence[(t - 1, 0)] + dx;
+    }
+
+    /// Emit the state at index `t` as a debug event.
+    fn log_step(&self, t: usize) {
+        tracing::debug!(
+            t = self.grid().time(t),
+            s = ?self.s[(t, 0)],
+            c = ?self.c[(t, 0)],
+            "step"
+        );
+    }
+
+    /// Emit a summary of a finished run as an info event.
+    fn log_summary(&self) {
+        let last = self.s.nrows() - 1;
+        tracing::info!(
+            n_steps = self.s.nrows(),
+            step_size = self.step_size,
+            s = ?self.s[(last, 0)],
+            c = ?self.c[(last, 0)],
+            cumulative_incidence = ?self.cumulative_incidence[(last, 0)],
+            "finished"
+        );
+    }
+
     /// Run the DisMod differential equations by the first-order euler method.
     ///
     /// This solution method is very rough and only suitable for demonstration.
-    pub fn run_euler(&mut self) -> &Model {
-        let h = self.step_size;
-        let n = ((self.length as f64) / h).ceil() as usize;
-        for t in 1..n - 1 {
+    pub fn run_euler(&mut self) -> &Self {
+        let _span = tracing::info_span!("run", model = "dismod", solver = "euler").entered();
+        let h: T = from_f64(self.step_size);
+        let n = self.grid().n_steps;
+        for t in 0..n - 1 {
             let ds = self.dsdt(self.s[(t, 0)], self.c[(t, 0)]);
             let dc = self.dcdt(self.s[(t, 0)], self.c[(t, 0)]);
+            let dx = self.dxdt(self.s[(t, 0)]);
             self.s[(t + 1, 0)] = self.s[(t, 0)] + (h * ds);
             self.c[(t + 1, 0)] = self.c[(t, 0)] + (h * dc);
-            if t % 10 == 0 {
-                println!(
-                    "t={:.1} s={:.6} c={:.6}",
-                    (t as f64) * self.step_size,
-                    self.s[(t, 0)],
-                    self.c[(t, 0)],
-                );
-            }
+            self.record_incidence(t + 1, h * dx);
+            self.log_step(t + 1);
         }
+        self.log_summary();
         return self;
     }
 
     /// Construct array of runge-kutta intermediate values for each variable.
-    fn init_y(&self) -> [SystemVars; 5] {
-        return [
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-        ];
+    fn init_y(&self) -> [SystemVars<T>; 5] {
+        let zero = SystemVars {
+            s: T::zero_impl(),
+            c: T::zero_impl(),
+            x: T::zero_impl(),
+        };
+        return [zero; 5];
     }
 
     /// Construct array of runge-kutta constants for each function.
-    fn init_k(&self) -> [SystemVars; 5] {
-        return [
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-            SystemVars { s: 0.0, c: 0.0 },
-        ];
+    fn init_k(&self) -> [SystemVars<T>; 5] {
+        let zero = SystemVars {
+            s: T::zero_impl(),
+            c: T::zero_impl(),
+            x: T::zero_impl(),
+        };
+        return [zero; 5];
     }
 
     /// Construct array of step sizes corresponding to each runge-kutta order.
@@ -155,12 +361,12 @@ impl Model {
     }
 
     /// Compute a runge-kutta approximate function value.
-    fn next_y(&self, y: f64, k: f64, h: f64) -> f64 {
-        return y + (k * h);
+    fn next_y(&self, y: T, k: T, h: f64) -> T {
+        return y + (k * from_f64(h));
     }
 
     /// Compute a 4th order runge-kutta time step for the system.
-    fn rk4_step(&self, t: usize) -> [SystemVars; 5] {
+    fn rk4_step(&self, t: usize) -> [SystemVars<T>; 5] {
         let mut y = self.init_y();
         let mut k = self.init_k();
         let h = self.init_h();
@@ -169,6 +375,7 @@ impl Model {
         for i in 0..4 {
             k[i + 1].s = self.dsdt(y[i].s, y[i].c);
             k[i + 1].c = self.dcdt(y[i].s, y[i].c);
+            k[i + 1].x = self.dxdt(y[i].s);
             y[i + 1].s = self.next_y(y[0].s, k[i + 1].s, h[i]);
             y[i + 1].c = self.next_y(y[0].c, k[i + 1].c, h[i]);
         }
@@ -178,31 +385,945 @@ impl Model {
     /// Run the DisMod differential equations by the 4th order Runge-Kutta method.
     ///
     /// This method is suitable for general purposes.
-    pub fn run_rk4(&mut self) -> &Model {
-        let n = ((self.length as f64) / self.step_size).ceil() as usize;
+    pub fn run_rk4(&mut self) -> &Self {
+        let _span = tracing::info_span!("run", model = "dismod", solver = "rk4").entered();
+        let n = self.grid().n_steps;
+        let two: T = from_f64(2.0);
+        let h: T = from_f64(self.step_size / 6.0);
         for t in 0..n - 1 {
             let k = self.rk4_step(t);
-            let ds = (k[1].s + (2.0 * k[2].s) + (2.0 * k[3].s) + k[4].s) * (self.step_size / 6.0);
-            let dc = (k[1].c + (2.0 * k[2].c) + (2.0 * k[3].c) + k[4].c) * (self.step_size / 6.0);
+            let ds = (k[1].s + (two * k[2].s) + (two * k[3].s) + k[4].s) * h;
+            let dc = (k[1].c + (two * k[2].c) + (two * k[3].c) + k[4].c) * h;
+            let dx = (k[1].x + (two * k[2].x) + (two * k[3].x) + k[4].x) * h;
             self.s[(t + 1, 0)] = self.s[(t, 0)] + ds;
             self.c[(t + 1, 0)] = self.c[(t, 0)] + dc;
-            if t % 10 == 0 {
-                println!(
-                    "t={:.1} s={:.6} c={:.6}",
-                    (t as f64) * self.step_size,
-                    self.s[(t, 0)],
-                    self.c[(t, 0)],
-                );
+            self.record_incidence(t + 1, dx);
+            self.log_step(t + 1);
+        }
+        self.log_summary();
+        return self;
+    }
+
+    /// The solved series with metadata of a run of `solver`, for example
+    /// `rk4`.
+    pub fn result(&self, solver: &str) -> SimulationResult {
+        let parameters = Parameters::from([
+            ("length".to_string(), self.length as f64),
+            ("c_init".to_string(), self.c_init.to_f64()),
+            ("iota".to_string(), self.iota.to_f64()),
+            ("rho".to_string(), self.rho.to_f64()),
+            ("chi".to_string(), self.chi.to_f64()),
+            ("omega".to_string(), self.omega.to_f64()),
+        ]);
+        let columns = [
+            &self.s,
+            &self.c,
+            &self.incidence,
+            &self.cumulative_incidence,
+        ];
+        let n_steps = self.s.nrows();
+        return SimulationResult {
+            metadata: RunMetadata::new(solver, self.step_size, &parameters),
+            names: ["s", "c", "incidence", "cumulative_incidence"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            times: self.grid().times(),
+            values: Mat::from_fn(n_steps, columns.len(), |t, j| columns[j][(t, 0)].to_f64()),
+        };
+    }
+
+    /// The rates iota, rho, chi and omega as `f64`.
+    fn rates(&self) -> [f64; 4] {
+        return [
+            self.iota.to_f64(),
+            self.rho.to_f64(),
+            self.chi.to_f64(),
+            self.omega.to_f64(),
+        ];
+    }
+
+    /// Rate matrix of the linear system d/dt (S, C, X) = A (S, C, X), where X
+    /// is cumulative incidence.
+    pub fn rate_matrix(&self) -> Mat<f64> {
+        let [iota, rho, chi, omega] = self.rates();
+        return faer::mat![
+            [-(iota + omega), rho, 0.0],
+            [iota, -(rho + chi + omega), 0.0],
+            [iota, 0.0, 0.0],
+        ];
+    }
+
+    /// Run the DisMod differential equations exactly, by the matrix
+    /// exponential of the rate matrix.
+    ///
+    /// With constant rates the equations are linear, so one step is
+    /// multiplication by exp(A h), computed once. Results are exact to
+    /// rounding at any step size, for validating other solvers, and cost
+    /// one 3 × 3 product per step. The exponential is computed in `f64`
+    /// and the steps taken in `T`.
+    pub fn run_exponential(&mut self) -> &Self {
+        let _span = tracing::info_span!("run", model = "dismod", solver = "exponential").entered();
+        let n = self.grid().n_steps;
+        let step = expm(&(faer::Scale(self.step_size) * self.rate_matrix()));
+        let step: Mat<T> = Mat::from_fn(3, 3, |i, j| from_f64(step[(i, j)]));
+        let mut y = [
+            self.s[(0, 0)],
+            self.c[(0, 0)],
+            self.cumulative_incidence[(0, 0)],
+        ];
+        for t in 0..n - 1 {
+            let next: Vec<T> = (0..3)
+                .map(|i| (0..3).fold(T::zero_impl(), |sum, j| sum + (step[(i, j)] * y[j])))
+                .collect();
+            self.s[(t + 1, 0)] = next[0];
+            self.c[(t + 1, 0)] = next[1];
+            self.record_incidence(t + 1, next[2] - y[2]);
+            self.log_step(t + 1);
+            y = [next[0], next[1], next[2]];
+        }
+        self.log_summary();
+        return self;
+    }
+
+    /// Jacobian of the equations for (S, C), with rows the derivatives of S
+    /// and C. The equations are linear, so it is the same at every time and
+    /// state.
+    pub fn jacobian(&self, _t: f64, _state: &[f64]) -> Mat<f64> {
+        let [iota, rho, chi, omega] = self.rates();
+        return faer::mat![[-(iota + omega), rho], [iota, -(rho + chi + omega)],];
+    }
+
+    /// Eigenvalues of the Jacobian, see [`Model::jacobian`].
+    pub fn stability_at(&self, t: f64, state: &[f64]) -> Stability {
+        return Stability::of(&self.jacobian(t, state));
+    }
+
+    /// Prevalence, C / (S + C), at each index.
+    pub fn prevalence(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |t, _| {
+            (self.c[(t, 0)] / (self.s[(t, 0)] + self.c[(t, 0)])).to_f64()
+        });
+    }
+
+    /// Incidence rate among susceptibles, DisMod-AT's `Sincidence`, at each
+    /// index.
+    pub fn susceptible_incidence_rate(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |_, _| self.iota.to_f64());
+    }
+
+    /// Incidence rate in the whole living population, DisMod-AT's
+    /// `Tincidence`, iota S / (S + C), at each index.
+    pub fn total_incidence_rate(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |t, _| {
+            (self.iota * self.s[(t, 0)] / (self.s[(t, 0)] + self.c[(t, 0)])).to_f64()
+        });
+    }
+
+    /// Remission rate, DisMod-AT's `remission`, at each index.
+    pub fn remission(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |_, _| self.rho.to_f64());
+    }
+
+    /// Excess mortality rate of those with the condition, DisMod-AT's
+    /// `mtexcess`, at each index.
+    pub fn excess_mortality(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |_, _| self.chi.to_f64());
+    }
+
+    /// Mortality rate of those with the condition, DisMod-AT's `mtwith`,
+    /// at each index.
+    pub fn with_condition_mortality(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |_, _| (self.omega + self.chi).to_f64());
+    }
+
+    /// All-cause mortality rate, DisMod-AT's `mtall`, omega + chi × prevalence,
+    /// at each index.
+    pub fn all_cause_mortality(&self) -> Mat<f64> {
+        let [_, _, chi, omega] = self.rates();
+        let prevalence = self.prevalence();
+        return Mat::from_fn(self.s.nrows(), 1, |t, _| omega + (chi * prevalence[(t, 0)]));
+    }
+
+    /// Series of `measure` at each index.
+    pub fn measure(&self, measure: Measure) -> Mat<f64> {
+        match measure {
+            Measure::Prevalence => return self.prevalence(),
+            Measure::Sincidence => return self.susceptible_incidence_rate(),
+            Measure::Tincidence => return self.total_incidence_rate(),
+            Measure::Remission => return self.remission(),
+            Measure::Mtexcess => return self.excess_mortality(),
+            Measure::Mtwith => return self.with_condition_mortality(),
+            Measure::Mtall => return self.all_cause_mortality(),
+        }
+    }
+
+    /// Average of `series`, one value per index, over ages `[lower, upper]`,
+    /// interpolating linearly between indices. The value at `lower` when
+    /// the interval is a single age.
+    fn interval_average(&self, series: &Mat<f64>, lower: f64, upper: f64) -> f64 {
+        let grid = self.grid();
+        let last = grid.time(grid.n_steps - 1);
+        assert!(
+            (0.0 <= lower) & (lower <= upper) & (upper <= last),
+            "age interval must be ordered within [0, {}], got [{}, {}]",
+            last,
+            lower,
+            upper
+        );
+        let at = |age: f64| -> f64 {
+            if grid.n_steps < 2 {
+                return series[(0, 0)];
             }
+            let k = (((age - grid.t0) / grid.dt).floor() as usize).min(grid.n_steps - 2);
+            let w = (age - grid.time(k)) / grid.dt;
+            return ((1.0 - w) * series[(k, 0)]) + (w * series[(k + 1, 0)]);
+        };
+        if upper == lower {
+            return at(lower);
+        }
+        let mut ages = vec![lower];
+        ages.extend(
+            (0..grid.n_steps)
+                .map(|k| grid.time(k))
+                .filter(|t| (*t > lower) & (*t < upper)),
+        );
+        ages.push(upper);
+        let area: f64 = ages
+            .windows(2)
+            .map(|w| 0.5 * (w[1] - w[0]) * (at(w[0]) + at(w[1])))
+            .sum();
+        return area / (upper - lower);
+    }
+
+    /// Model prediction of each data point, its measure averaged over its
+    /// age interval. The model must be solved.
+    pub fn predict(&self, data: &[DataPoint]) -> Vec<f64> {
+        return data
+            .iter()
+            .map(|d| self.interval_average(&self.measure(d.measure), d.age_lower, d.age_upper))
+            .collect();
+    }
+
+    /// Weighted residual of each data point, `(value - prediction) / sd`.
+    pub fn residuals(&self, data: &[DataPoint]) -> Vec<f64> {
+        return data
+            .iter()
+            .zip(self.predict(data))
+            .map(|(d, predicted)| (d.value - predicted) / d.sd)
+            .collect();
+    }
+
+    /// Gaussian log-likelihood of `data` given the solved model. Errors on
+    /// a data point without a positive, finite `sd`.
+    pub fn log_likelihood(&self, data: &[DataPoint]) -> Result<f64, Error> {
+        check_sd(data)?;
+        return Ok(data
+            .iter()
+            .zip(self.residuals(data))
+            .map(|(d, r)| -((0.5 * r * r) + ln(d.sd) + (0.5 * ln(2.0 * std::f64::consts::PI))))
+            .sum());
+    }
+}
+
+/// Log-scale random effects of a location on each rate. A child's rate is
+/// its parent's times `exp` of the effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RandomEffects {
+    pub iota: f64,
+    pub rho: f64,
+    pub chi: f64,
+    pub omega: f64,
+}
+
+impl RandomEffects {
+    /// No difference from the parent.
+    pub fn zero() -> Self {
+        return Self {
+            iota: 0.0,
+            rho: 0.0,
+            chi: 0.0,
+            omega: 0.0,
+        };
+    }
+}
+
+/// A location of a [`Hierarchy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// Unique name of the location.
+    pub name: String,
+    /// Index of the parent location, `None` for the root.
+    pub parent: Option<usize>,
+    /// Random effects relative to the parent, zero for the root.
+    pub effects: RandomEffects,
+}
+
+/// Tree of locations with rates by random effects on the parent's rates.
+pub struct Hierarchy {
+    /// Locations, the root first and every parent before its children.
+    pub locations: Vec<Location>,
+    /// Rates of the root location, `[iota, rho, chi, omega]`, per year.
+    pub root_rates: [f64; 4],
+}
+
+impl Hierarchy {
+    /// Create a hierarchy of the root location `name` with its rates, stored
+    /// per year.
+    pub fn new(name: &str, iota: Rate, rho: Rate, chi: Rate, omega: Rate) -> Self {
+        return Self {
+            locations: vec![Location {
+                name: name.to_string(),
+                parent: None,
+                effects: RandomEffects::zero(),
+            }],
+            root_rates: [iota, rho, chi, omega].map(|rate| rate.per(TimeUnit::Year)),
+        };
+    }
+
+    /// Index of location `name`.
+    fn index(&self, name: &str) -> usize {
+        return self
+            .locations
+            .iter()
+            .position(|l| l.name == name)
+            .unwrap_or_else(|| panic!("unknown location {}", name));
+    }
+
+    /// Add location `name` as a child of `parent`, with `effects` on its
+    /// parent's rates.
+    pub fn add(&mut self, name: &str, parent: &str, effects: RandomEffects) -> &mut Self {
+        assert!(
+            !self.locations.iter().any(|l| l.name == name),
+            "location {} already exists",
+            name
+        );
+        let parent = self.index(parent);
+        self.locations.push(Location {
+            name: name.to_string(),
+            parent: Some(parent),
+            effects,
+        });
+        return self;
+    }
+
+    /// Names of the children of location `name`.
+    pub fn children(&self, name: &str) -> Vec<&str> {
+        let index = self.index(name);
+        return self
+            .locations
+            .iter()
+            .filter(|l| l.parent == Some(index))
+            .map(|l| l.name.as_str())
+            .collect();
+    }
+
+    /// Draw every non-root location's random effects independently from a
+    /// normal distribution with mean 0 and standard deviation `sd`.
+    pub fn draw_effects(&mut self, sd: f64, seed: u64) -> &mut Self {
+        assert!(sd >= 0.0, "sd must be non-negative, got {}", sd);
+        let mut rng = rng::rng(seed);
+        let normal = Normal::new(0.0, sd).unwrap();
+        for location in self.locations.iter_mut().skip(1) {
+            location.effects = RandomEffects {
+                iota: normal.sample(&mut rng),
+                rho: normal.sample(&mut rng),
+                chi: normal.sample(&mut rng),
+                omega: normal.sample(&mut rng),
+            };
+        }
+        return self;
+    }
+
+    /// Rates of location `name`, `[iota, rho, chi, omega]`, the root's rates
+    /// times the exponentiated effects of every location on the path to it.
+    pub fn rates(&self, name: &str) -> [f64; 4] {
+        let mut total = RandomEffects::zero();
+        let mut at = Some(self.index(name));
+        while let Some(index) = at {
+            let effects = &self.locations[index].effects;
+            total.iota += effects.iota;
+            total.rho += effects.rho;
+            total.chi += effects.chi;
+            total.omega += effects.omega;
+            at = self.locations[index].parent;
+        }
+        let [iota, rho, chi, omega] = self.root_rates;
+        return [
+            iota * exp(total.iota),
+            rho * exp(total.rho),
+            chi * exp(total.chi),
+            omega * exp(total.omega),
+        ];
+    }
+
+    /// A model of location `name`, configured but not solved.
+    pub fn model(&self, name: &str, length: usize, step_size: f64, c_init: f64) -> Model {
+        let [iota, rho, chi, omega] = self.rates(name);
+        let mut model = Model::new();
+        model.configure(
+            length,
+            step_size,
+            c_init,
+            Rate::per_year(iota),
+            Rate::per_year(rho),
+            Rate::per_year(chi),
+            Rate::per_year(omega),
+        );
+        return model;
+    }
+
+    /// Solve a model of every location, in the order of
+    /// [`Hierarchy::locations`], by [`Model::run_exponential`].
+    pub fn simulate(&self, length: usize, step_size: f64, c_init: f64) -> Vec<Model> {
+        return self
+            .locations
+            .iter()
+            .map(|l| {
+                let mut model = self.model(&l.name, length, step_size, c_init);
+                model.init_popf();
+                model.run_exponential();
+                model
+            })
+            .collect();
+    }
+}
+
+/// Position of `x` among ascending `knots`, as the knots either side and the
+/// weight of the upper one. Constant beyond the first and last knots.
+fn bracket(knots: &[f64], x: f64) -> (usize, usize, f64) {
+    let last = knots.len() - 1;
+    if x <= knots[0] {
+        return (0, 0, 0.0);
+    }
+    if x >= knots[last] {
+        return (last, last, 0.0);
+    }
+    let i = knots.iter().rposition(|k| *k <= x).unwrap();
+    return (i, i + 1, (x - knots[i]) / (knots[i + 1] - knots[i]));
+}
+
+/// A rate at each age (row) and calendar time (column) knot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateGrid {
+    /// Age knots, ascending.
+    pub ages: Vec<f64>,
+    /// Time knots, ascending.
+    pub times: Vec<f64>,
+    /// Rate at each knot, one row per age and one column per time.
+    pub values: Mat<f64>,
+}
+
+impl RateGrid {
+    /// Grid over `ages` and `times` with `value` at every knot.
+    pub fn new(ages: Vec<f64>, times: Vec<f64>, value: f64) -> Self {
+        for (name, knots) in [("ages", &ages), ("times", &times)] {
+            assert!(
+                !knots.is_empty() & knots.windows(2).all(|w| w[0] < w[1]),
+                "{} must be non-empty and strictly ascending, got {:?}",
+                name,
+                knots
+            );
+        }
+        let values = Mat::from_fn(ages.len(), times.len(), |_, _| value);
+        return Self {
+            ages,
+            times,
+            values,
+        };
+    }
+
+    /// Weight of each knot in the rate at `age` and `time`.
+    fn point_weights(&self, age: f64, time: f64) -> Mat<f64> {
+        let (a0, a1, wa) = bracket(&self.ages, age);
+        let (t0, t1, wt) = bracket(&self.times, time);
+        let mut weights = Mat::zeros(self.ages.len(), self.times.len());
+        weights[(a0, t0)] += (1.0 - wa) * (1.0 - wt);
+        weights[(a1, t0)] += wa * (1.0 - wt);
+        weights[(a0, t1)] += (1.0 - wa) * wt;
+        weights[(a1, t1)] += wa * wt;
+        return weights;
+    }
+
+    /// Weight of each knot in the rate averaged over ages `[lower, upper]`
+    /// at `time`. The rate is linear in age between knots, so the
+    /// trapezoidal rule over the knots is exact.
+    fn interval_weights(&self, lower: f64, upper: f64, time: f64) -> Mat<f64> {
+        assert!(
+            lower <= upper,
+            "age interval must be ordered, got [{}, {}]",
+            lower,
+            upper
+        );
+        if upper == lower {
+            return self.point_weights(lower, time);
+        }
+        let mut ages = vec![lower];
+        ages.extend(self.ages.iter().filter(|a| (**a > lower) & (**a < upper)));
+        ages.push(upper);
+        let mut weights = Mat::zeros(self.ages.len(), self.times.len());
+        for w in ages.windows(2) {
+            let scale = 0.5 * (w[1] - w[0]) / (upper - lower);
+            weights += faer::Scale(scale) * self.point_weights(w[0], time);
+            weights += faer::Scale(scale) * self.point_weights(w[1], time);
+        }
+        return weights;
+    }
+
+    /// Rate at `age` and `time`, interpolated bilinearly between knots.
+    pub fn at(&self, age: f64, time: f64) -> f64 {
+        return dot(&self.point_weights(age, time), &self.values);
+    }
+
+    /// Rate averaged over ages `[lower, upper]` at `time`.
+    pub fn average(&self, lower: f64, upper: f64, time: f64) -> f64 {
+        return dot(&self.interval_weights(lower, upper, time), &self.values);
+    }
+}
+
+/// Sum of the elementwise products of `a` and `b`.
+fn dot(a: &Mat<f64>, b: &Mat<f64>) -> f64 {
+    let mut total = 0.0;
+    for j in 0..a.ncols() {
+        for i in 0..a.nrows() {
+            total += a[(i, j)] * b[(i, j)];
+        }
+    }
+    return total;
+}
+
+/// Gaussian smoothing priors on the knots of a [`RateGrid`]: on each value,
+/// on the difference between neighbouring ages and on the difference
+/// between neighbouring times. An infinite standard deviation turns a
+/// prior off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothingPrior {
+    /// Mean of the prior on each value.
+    pub value_mean: f64,
+    /// Standard deviation of the prior on each value.
+    pub value_sd: f64,
+    /// Standard deviation of the difference between neighbouring ages.
+    pub dage_sd: f64,
+    /// Standard deviation of the difference between neighbouring times.
+    pub dtime_sd: f64,
+}
+
+impl SmoothingPrior {
+    /// Negative log density of `values`, up to a constant, and its gradient.
+    fn penalty(&self, values: &Mat<f64>) -> (f64, Mat<f64>) {
+        let (n_ages, n_times) = values.shape();
+        let mut penalty = 0.0;
+        let mut gradient = Mat::zeros(n_ages, n_times);
+        let value_weight = 1.0 / (self.value_sd * self.value_sd);
+        let (dage_weight, dtime_weight) = (
+            1.0 / (self.dage_sd * self.dage_sd),
+            1.0 / (self.dtime_sd * self.dtime_sd),
+        );
+        for j in 0..n_times {
+            for i in 0..n_ages {
+                let z = values[(i, j)] - self.value_mean;
+                penalty += 0.5 * value_weight * z * z;
+                gradient[(i, j)] += value_weight * z;
+                if i + 1 < n_ages {
+                    let d = values[(i + 1, j)] - values[(i, j)];
+                    penalty += 0.5 * dage_weight * d * d;
+                    gradient[(i + 1, j)] += dage_weight * d;
+                    gradient[(i, j)] -= dage_weight * d;
+                }
+                if j + 1 < n_times {
+                    let d = values[(i, j + 1)] - values[(i, j)];
+                    penalty += 0.5 * dtime_weight * d * d;
+                    gradient[(i, j + 1)] += dtime_weight * d;
+                    gradient[(i, j)] -= dtime_weight * d;
+                }
+            }
+        }
+        return (penalty, gradient);
+    }
+
+    /// Log density of the values of `grid`, up to a constant.
+    pub fn log_density(&self, grid: &RateGrid) -> f64 {
+        return -self.penalty(&grid.values).0;
+    }
+}
+
+/// Check every data point has a positive, finite `sd`, naming the first that
+/// does not.
+fn check_sd(data: &[DataPoint]) -> Result<(), Error> {
+    for (k, d) in data.iter().enumerate() {
+        if !((d.sd > 0.0) & d.sd.is_finite()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "data point {}: sd must be positive and finite, got {}",
+                    k, d.sd
+                ),
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// Estimate the rate surface of `grid` from measurements of that rate,
+/// `Sincidence`, `remission` or `mtexcess`, each averaged over its age
+/// interval at its time. Maximizes the Gaussian likelihood of `data` plus
+/// the log density of `prior` by [`lbfgs`], with rates non-negative and the
+/// values of `grid` as the start. Errors on a data point without a positive,
+/// finite `sd`.
+pub fn fit_rates(
+    grid: &RateGrid,
+    data: &[DataPoint],
+    prior: &SmoothingPrior,
+    max_iter: usize,
+) -> Result<(RateGrid, Convergence), Error> {
+    let measure = data.first().map(|d| d.measure);
+    assert!(
+        data.iter().all(|d| Some(d.measure) == measure)
+            & matches!(
+                measure,
+                Some(Measure::Sincidence | Measure::Remission | Measure::Mtexcess)
+            ),
+        "data must measure one of Sincidence, remission or mtexcess"
+    );
+    check_sd(data)?;
+    let (n_ages, n_times) = grid.values.shape();
+    let n = n_ages * n_times;
+    let weights: Vec<Vec<f64>> = data
+        .iter()
+        .map(|d| {
+            let w = grid.interval_weights(d.age_lower, d.age_upper, d.time);
+            (0..n).map(|k| w[(k % n_ages, k / n_ages)]).collect()
+        })
+        .collect();
+    let objective = |x: &[f64]| -> (f64, Vec<f64>) {
+        let values = Mat::from_fn(n_ages, n_times, |i, j| x[i + (j * n_ages)]);
+        let (mut total, penalty_gradient) = prior.penalty(&values);
+        let mut gradient: Vec<f64> = (0..n)
+            .map(|k| penalty_gradient[(k % n_ages, k / n_ages)])
+            .collect();
+        for (d, w) in data.iter().zip(weights.iter()) {
+            let predicted: f64 = w.iter().zip(x).map(|(w, x)| w * x).sum();
+            let r = (predicted - d.value) / (d.sd * d.sd);
+            total += 0.5 * r * (predicted - d.value);
+            for k in 0..n {
+                gradient[k] += r * w[k];
+            }
+        }
+        return (total, gradient);
+    };
+    let x0: Vec<f64> = (0..n)
+        .map(|k| grid.values[(k % n_ages, k / n_ages)])
+        .collect();
+    let (x, _, convergence) = lbfgs(
+        objective,
+        &x0,
+        &vec![0.0; n],
+        &vec![f64::INFINITY; n],
+        max_iter,
+        1e-12,
+    );
+    let mut fitted = grid.clone();
+    fitted.values = Mat::from_fn(n_ages, n_times, |i, j| x[i + (j * n_ages)]);
+    return Ok((fitted, convergence));
+}
+
+/// How an [`AgeTimeModel`] moves through calendar time as age increases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Solution {
+    /// Rates of calendar time `time` at every age, the cross-section of a
+    /// population in one year.
+    Period { time: f64 },
+    /// Rates along the characteristic of those born at `birth_time`, at age
+    /// `a` those of time `birth_time + a`.
+    Cohort { birth_time: f64 },
+}
+
+/// Create and run a DisMod-type model with rates varying by age and time.
+pub struct AgeTimeModel {
+    /// Number of indices to generate and solve. The oldest age.
+    pub length: usize,
+    /// Size of integration step, in years of age.
+    pub step_size: f64,
+    /// With-condition population fraction at birth.
+    pub c_init: f64,
+    /// Transition rate from S into C.
+    pub iota: RateGrid,
+    /// Transition rate from C into S.
+    pub rho: RateGrid,
+    /// Transition rate from C into Rc.
+    pub chi: RateGrid,
+    /// Transition rate from S, C into Ro.
+    pub omega: RateGrid,
+    /// Period or cohort solution, a period solution at time 0 unless set.
+    pub solution: Solution,
+    /// Susceptible population fraction at each index, of those alive at
+    /// birth. 1D Array with one element per index of [`AgeTimeModel::grid`].
+    pub s: Mat<f64>,
+    /// With-condition population fraction at each index, of those alive at
+    /// birth. 1D Array with one element per index of [`AgeTimeModel::grid`].
+    pub c: Mat<f64>,
+}
+
+impl AgeTimeModel {
+    /// Create an empty model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            c_init: 0.0,
+            iota: RateGrid::new(vec![0.0], vec![0.0], 0.0),
+            rho: RateGrid::new(vec![0.0], vec![0.0], 0.0),
+            chi: RateGrid::new(vec![0.0], vec![0.0], 0.0),
+            omega: RateGrid::new(vec![0.0], vec![0.0], 0.0),
+            solution: Solution::Period { time: 0.0 },
+            s: Mat::new(),
+            c: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. The solution is a period solution at
+    /// time 0 until set with [`AgeTimeModel::period`] or
+    /// [`AgeTimeModel::cohort`].
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        c_init: f64,
+        iota: RateGrid,
+        rho: RateGrid,
+        chi: RateGrid,
+        omega: RateGrid,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.c_init = c_init;
+        self.iota = iota;
+        self.rho = rho;
+        self.chi = chi;
+        self.omega = omega;
+        self.solution = Solution::Period { time: 0.0 };
+        self.s = Mat::zeros(n_steps, 1);
+        self.c = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Solve for the population at calendar time `time`.
+    pub fn period(&mut self, time: f64) -> &mut Self {
+        self.solution = Solution::Period { time };
+        return self;
+    }
+
+    /// Solve for the cohort born at `birth_time`.
+    pub fn cohort(&mut self, birth_time: f64) -> &mut Self {
+        self.solution = Solution::Cohort { birth_time };
+        return self;
+    }
+
+    /// Ages of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Calendar time at `age` under the solution.
+    pub fn time_at(&self, age: f64) -> f64 {
+        match self.solution {
+            Solution::Period { time } => return time,
+            Solution::Cohort { birth_time } => return birth_time + age,
+        }
+    }
+
+    /// Rates `[iota, rho, chi, omega]` at `age` under the solution.
+    pub fn rates_at(&self, age: f64) -> [f64; 4] {
+        let time = self.time_at(age);
+        return [
+            self.iota.at(age, time),
+            self.rho.at(age, time),
+            self.chi.at(age, time),
+            self.omega.at(age, time),
+        ];
+    }
+
+    /// Compute the derivatives of S and C at `age`.
+    fn derivatives(&self, age: f64, y: &[f64; 2]) -> [f64; 2] {
+        let [iota, rho, chi, omega] = self.rates_at(age);
+        return [
+            -((iota + omega) * y[0]) + (rho * y[1]),
+            (iota * y[0]) - ((rho + chi + omega) * y[1]),
+        ];
+    }
+
+    /// Solve the differential equations along age by the 4th order
+    /// Runge-Kutta method, from the population at birth.
+    pub fn run_rk4(&mut self) -> &AgeTimeModel {
+        let grid = self.grid();
+        let h = self.step_size;
+        self.s[(0, 0)] = 1.0 - self.c_init;
+        self.c[(0, 0)] = self.c_init;
+        for t in 0..grid.n_steps - 1 {
+            let age = grid.time(t);
+            let y = [self.s[(t, 0)], self.c[(t, 0)]];
+            let k1 = self.derivatives(age, &y);
+            let k2 = self.derivatives(
+                age + (h / 2.0),
+                &std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])),
+            );
+            let k3 = self.derivatives(
+                age + (h / 2.0),
+                &std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])),
+            );
+            let k4 = self.derivatives(age + h, &std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.s[(t + 1, 0)] =
+                y[0] + ((k1[0] + (2.0 * k2[0]) + (2.0 * k3[0]) + k4[0]) * (h / 6.0));
+            self.c[(t + 1, 0)] =
+                y[1] + ((k1[1] + (2.0 * k2[1]) + (2.0 * k3[1]) + k4[1]) * (h / 6.0));
         }
         return self;
     }
+
+    /// Prevalence, C / (S + C), at each index.
+    pub fn prevalence(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |t, _| {
+            self.c[(t, 0)] / (self.s[(t, 0)] + self.c[(t, 0)])
+        });
+    }
+
+    /// Fraction of those alive at birth still alive, S + C, at each index.
+    pub fn survival(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s.nrows(), 1, |t, _| self.s[(t, 0)] + self.c[(t, 0)]);
+    }
+}
+
+/// Rate of change of prevalence with age, `iota (1 - P) - rho P - chi P
+/// (1 - P)`. Other-cause mortality affects S and C alike, so drops out.
+fn dpda(iota: f64, rho: f64, chi: f64, p: f64) -> f64 {
+    return (iota * (1.0 - p)) - (rho * p) - (chi * p * (1.0 - p));
+}
+
+/// Incidence rate on each interval between consecutive `ages`, constant
+/// over the interval, that carries `prevalence` at the start of the interval
+/// to `prevalence` at its end, with remission `rho` and excess mortality
+/// `chi` as functions of age. For example `|a| grid.at(a, 2000.0)` takes
+/// the rates of a [`RateGrid`] in 2000.
+///
+/// Prevalence is integrated across each interval by the 4th order
+/// Runge-Kutta method in `substeps` steps, and the rate found by
+/// bisection. Where prevalence falls faster than remission and excess
+/// mortality alone allow, no non-negative rate is consistent and the rate
+/// is 0. Fewer than two ages have no intervals and give no rates.
+///
+/// The integrated prevalence only rises with the rate while `iota h` stays
+/// below about 1.6, `h` the substep, so rates are searched below `1.5 / h`.
+/// A prevalence out of reach there needs more `substeps`, and is an
+/// `InvalidData` error naming its age, as is a prevalence outside [0, 1).
+pub fn back_calculate(
+    ages: &[f64],
+    prevalence: &[f64],
+    rho: impl Fn(f64) -> f64,
+    chi: impl Fn(f64) -> f64,
+    substeps: usize,
+) -> Result<Vec<f64>, Error> {
+    assert!(
+        (ages.len() == prevalence.len()) & ages.windows(2).all(|w| w[0] < w[1]),
+        "ages must be strictly ascending with one prevalence each, got {} ages and {} prevalences",
+        ages.len(),
+        prevalence.len()
+    );
+    assert!(substeps > 0, "substeps must be positive, got {}", substeps);
+    let invalid = |age: f64, message: String| -> Error {
+        return Error::new(ErrorKind::InvalidData, format!("age {}: {}", age, message));
+    };
+    for (age, p) in ages.iter().zip(prevalence) {
+        if !(0.0..1.0).contains(p) {
+            return Err(invalid(
+                *age,
+                format!("prevalence must be in [0, 1), got {}", p),
+            ));
+        }
+    }
+    let carry = |iota: f64, start: usize| -> f64 {
+        let h = (ages[start + 1] - ages[start]) / (substeps as f64);
+        let mut p = prevalence[start];
+        for k in 0..substeps {
+            let a = ages[start] + ((k as f64) * h);
+            let f = |a: f64, p: f64| dpda(iota, rho(a), chi(a), p);
+            let k1 = f(a, p);
+            let k2 = f(a + (h / 2.0), p + (h / 2.0 * k1));
+            let k3 = f(a + (h / 2.0), p + (h / 2.0 * k2));
+            let k4 = f(a + h, p + (h * k3));
+            p += (k1 + (2.0 * k2) + (2.0 * k3) + k4) * (h / 6.0);
+        }
+        return p;
+    };
+    let mut rates = Vec::with_capacity(ages.len().saturating_sub(1));
+    for k in 0..ages.len().saturating_sub(1) {
+        let target = prevalence[k + 1];
+        if carry(0.0, k) >= target {
+            rates.push(0.0);
+            continue;
+        }
+        let limit = 1.5 * (substeps as f64) / (ages[k + 1] - ages[k]);
+        let mut upper = limit.min(1.0);
+        while carry(upper, k) < target {
+            if upper >= limit {
+                return Err(invalid(
+                    ages[k + 1],
+                    format!(
+                        "prevalence {} is out of reach in {} substeps",
+                        target, substeps
+                    ),
+                ));
+            }
+            upper = limit.min(2.0 * upper);
+        }
+        let mut lower = 0.0;
+        for _ in 0..100 {
+            let middle = 0.5 * (lower + upper);
+            if carry(middle, k) < target {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        rates.push(0.5 * (lower + upper));
+    }
+    return Ok(rates);
+}
+
+/// Matrix exponential of `a`, by scaling and squaring a Taylor series.
+///
+/// `a` is scaled by a power of 2 to a norm below 1/2, where 20 terms of
+/// the series are accurate to rounding, and the result squared back.
+fn expm(a: &Mat<f64>) -> Mat<f64> {
+    let n = a.nrows();
+    let norm = (0..n)
+        .map(|i| (0..n).map(|j| a[(i, j)].abs()).sum::<f64>())
+        .fold(0.0, f64::max);
+    let mut squarings = 0;
+    while norm / 2f64.powi(squarings) > 0.5 {
+        squarings += 1;
+    }
+    let scaled = faer::Scale(1.0 / 2f64.powi(squarings)) * a;
+    let mut result = Mat::<f64>::identity(n, n);
+    let mut term = Mat::<f64>::identity(n, n);
+    for k in 1..=20 {
+        term = faer::Scale(1.0 / (k as f64)) * (&term * &scaled);
+        result += &term;
+    }
+    for _ in 0..squarings {
+        result = &result * &result;
+    }
+    return result;
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::sirrs::dismod::Model;
-    use faer::Mat;
+    use crate::sirrs::dismod::{
+        AgeTimeModel, DataPoint, Hierarchy, Measure, Model, RandomEffects, RateGrid,
+        SmoothingPrior, back_calculate, fit_rates,
+    };
+    use crate::sirrs::units::Rate;
+    use faer::{Mat, mat};
 
     #[test]
     fn test_new() {
@@ -242,7 +1363,15 @@ mod tests {
     #[test]
     fn test_configure() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         let n_steps = ((model.length as f64) / model.step_size).ceil() as usize;
         assert_eq!(
             model.length, 10,
@@ -283,7 +1412,15 @@ mod tests {
     #[test]
     fn test_init_popf() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         model.init_popf();
         assert_eq!(
             model.s.shape(),
@@ -332,7 +1469,15 @@ mod tests {
     #[test]
     fn test_run_euler() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         model.init_popf();
         model.run_euler();
         for t in 1..model.length {
@@ -376,7 +1521,15 @@ mod tests {
     #[test]
     fn test_init_h() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         let h = model.init_h();
         assert!(
             h.len() == 4,
@@ -408,7 +1561,15 @@ mod tests {
     #[test]
     fn test_init_y() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         let y = model.init_y();
         assert!(
             y.len() == 5,
@@ -434,7 +1595,15 @@ mod tests {
     #[test]
     fn test_init_k() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         let k = model.init_k();
         assert!(
             k.len() == 5,
@@ -460,7 +1629,15 @@ mod tests {
     #[test]
     fn test_run_rk4() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            Rate::per_year(0.01),
+            Rate::per_year(0.02),
+            Rate::per_year(0.03),
+            Rate::per_year(0.04),
+        );
         model.init_popf();
         model.run_rk4();
         let h = model.step_size;
@@ -509,4 +1686,623 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_incidence() {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.0,
+            Rate::per_year(0.05),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        // With incidence the only flow out of S, cumulative incidence is the
+        // fall in S.
+        for t in 0..model.s.nrows() {
+            let expected = 1.0 - model.s[(t, 0)];
+            assert!(
+                (model.cumulative_incidence[(t, 0)] - expected).abs() < 1e-12,
+                "Bad cumulative_incidence at index {}, expected {} got {}",
+                t,
+                expected,
+                model.cumulative_incidence[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_measures() {
+        let mut model = Model::new();
+        model.configure(
+            40,
+            0.5,
+            0.1,
+            Rate::per_year(0.05),
+            Rate::per_year(0.02),
+            Rate::per_year(0.3),
+            Rate::per_year(0.01),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let prevalence = model.prevalence();
+        let total_incidence = model.total_incidence_rate();
+        let mtall = model.all_cause_mortality();
+        for t in 0..model.s.nrows() {
+            let living = model.s[(t, 0)] + model.c[(t, 0)];
+            assert!(
+                (prevalence[(t, 0)] - (model.c[(t, 0)] / living)).abs() < 1e-12,
+                "Bad prevalence at index {}, got {}",
+                t,
+                prevalence[(t, 0)]
+            );
+            assert!(
+                (total_incidence[(t, 0)] - (0.05 * (1.0 - prevalence[(t, 0)]))).abs() < 1e-12,
+                "Bad total incidence rate at index {}, got {}",
+                t,
+                total_incidence[(t, 0)]
+            );
+            assert!(
+                (mtall[(t, 0)] - (0.01 + (0.3 * prevalence[(t, 0)]))).abs() < 1e-12,
+                "Bad all-cause mortality at index {}, got {}",
+                t,
+                mtall[(t, 0)]
+            );
+        }
+        assert_eq!(model.susceptible_incidence_rate()[(3, 0)], 0.05);
+        assert_eq!(model.remission()[(3, 0)], 0.02);
+        assert_eq!(model.excess_mortality()[(3, 0)], 0.3);
+        assert_eq!(model.with_condition_mortality()[(3, 0)], 0.31);
+    }
+
+    #[test]
+    fn test_run_euler_first_step() {
+        let mut model = Model::new();
+        model.configure(
+            20,
+            0.5,
+            0.0,
+            Rate::per_year(0.05),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        model.init_popf();
+        model.run_euler();
+        let expected = 1.0 - (0.5 * 0.05);
+        assert_eq!(
+            model.s[(1, 0)],
+            expected,
+            "Bad s at index 1, expected {} got {}",
+            expected,
+            model.s[(1, 0)]
+        );
+        for t in 0..model.grid().n_steps {
+            let expected = 1.0 - model.s[(t, 0)];
+            assert!(
+                (model.cumulative_incidence[(t, 0)] - expected).abs() < 1e-12,
+                "Bad cumulative_incidence at index {}, expected {} got {}",
+                t,
+                expected,
+                model.cumulative_incidence[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_exponential() {
+        let mut model = Model::new();
+        model.configure(
+            40,
+            2.0,
+            0.0,
+            Rate::per_year(0.05),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        model.init_popf();
+        model.run_exponential();
+        // With incidence the only flow, S decays exponentially.
+        for t in 0..model.grid().n_steps {
+            let expected = (-0.05 * model.grid().time(t)).exp();
+            assert!(
+                (model.s[(t, 0)] - expected).abs() < 1e-14,
+                "Bad s at index {}, expected {} got {}",
+                t,
+                expected,
+                model.s[(t, 0)]
+            );
+        }
+        let mut exact = Model::new();
+        exact.configure(
+            40,
+            0.5,
+            0.01,
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        exact.init_popf();
+        exact.run_exponential();
+        let mut rk4 = Model::new();
+        rk4.configure(
+            40,
+            0.05,
+            0.01,
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        rk4.init_popf();
+        rk4.run_rk4();
+        for t in 0..exact.grid().n_steps {
+            for (name, value, expected) in [
+                ("c", exact.c[(t, 0)], rk4.c[(10 * t, 0)]),
+                (
+                    "cumulative_incidence",
+                    exact.cumulative_incidence[(t, 0)],
+                    rk4.cumulative_incidence[(10 * t, 0)],
+                ),
+            ] {
+                assert!(
+                    (value - expected).abs() < 1e-10,
+                    "Bad {} at index {}, expected {} got {}",
+                    name,
+                    t,
+                    expected,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scalar_types() {
+        let mut reference = Model::new();
+        reference.configure(
+            40,
+            0.5,
+            0.01,
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        let mut single = Model::<f32>::zeroed();
+        single.configure(
+            40,
+            0.5,
+            0.01,
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        single.init_popf();
+        single.run_rk4();
+        let mut quad = Model::<faer::fx128>::zeroed();
+        quad.configure(
+            40,
+            0.5,
+            faer::fx128::from_f64(0.01),
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        quad.init_popf();
+        quad.run_exponential();
+        let mut exact = Model::new();
+        exact.configure(
+            40,
+            0.5,
+            0.01,
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.03),
+        );
+        exact.init_popf();
+        exact.run_exponential();
+        for t in 0..reference.c.nrows() {
+            let expected = reference.c[(t, 0)];
+            assert!(
+                ((single.c[(t, 0)] as f64) - expected).abs() < 1e-5,
+                "Bad f32 c at index {}, expected {} got {}",
+                t,
+                expected,
+                single.c[(t, 0)]
+            );
+            let expected = exact.c[(t, 0)];
+            assert!(
+                (quad.c[(t, 0)].0 - expected).abs() < 1e-14,
+                "Bad fx128 c at index {}, expected {} got {}",
+                t,
+                expected,
+                quad.c[(t, 0)].0
+            );
+        }
+        let expected = reference.prevalence();
+        let prevalence = single.prevalence();
+        assert!(
+            (prevalence[(79, 0)] - expected[(79, 0)]).abs() < 1e-5,
+            "Bad f32 prevalence, expected {} got {}",
+            expected[(79, 0)],
+            prevalence[(79, 0)]
+        );
+    }
+
+    #[test]
+    fn test_stability_at() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.0,
+            Rate::per_year(0.05),
+            Rate::per_year(0.0),
+            Rate::per_year(0.2),
+            Rate::per_year(0.01),
+        );
+        let stability = model.stability_at(0.0, &[1.0, 0.0]);
+        assert!(
+            stability.stable(),
+            "Expected a stable system, got {:?}",
+            stability
+        );
+        assert!(
+            (stability.stiffness_ratio - (0.21 / 0.06)).abs() < 1e-9,
+            "Bad stiffness ratio, expected {} got {}",
+            0.21 / 0.06,
+            stability.stiffness_ratio
+        );
+    }
+
+    #[test]
+    fn test_predict_data() {
+        // Incidence alone, prevalence 1 - exp(-iota a).
+        let iota: f64 = 0.05;
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.1,
+            0.0,
+            Rate::per_year(iota),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        model.init_popf();
+        model.run_exponential();
+        let point = |measure: Measure, age_lower: f64, age_upper: f64, value: f64| DataPoint {
+            measure,
+            age_lower,
+            age_upper,
+            value,
+            sd: 0.01,
+            time: 2000.0,
+        };
+        let average = 1.0 - (((-iota * 10.0).exp() - (-iota * 30.0).exp()) / (iota * 20.0));
+        let data = [
+            point(Measure::Prevalence, 10.0, 30.0, average),
+            point(
+                Measure::Prevalence,
+                25.05,
+                25.05,
+                1.0 - (-iota * 25.05).exp(),
+            ),
+            point(Measure::Sincidence, 0.0, 50.0, iota),
+        ];
+        let predicted = model.predict(&data);
+        for (d, p) in data.iter().zip(predicted.iter()) {
+            assert!(
+                (p - d.value).abs() < 1e-4,
+                "Bad prediction of {:?}, expected {} got {}",
+                d.measure,
+                d.value,
+                p
+            );
+        }
+        let expected = -3.0 * (0.01f64.ln() + (0.5 * (2.0 * std::f64::consts::PI).ln()));
+        let ll = model.log_likelihood(&data).unwrap();
+        assert!(
+            (ll - expected).abs() < 1e-3,
+            "Bad log likelihood, expected {} got {}",
+            expected,
+            ll
+        );
+        // Data away from the model score worse.
+        let off = [point(Measure::Prevalence, 10.0, 30.0, average + 0.05)];
+        assert!(
+            (model.residuals(&off)[0] - 5.0).abs() < 1e-2,
+            "Bad residual, expected 5 got {}",
+            model.residuals(&off)[0]
+        );
+    }
+
+    #[test]
+    fn test_one_step_prediction() {
+        let mut model = Model::new();
+        model.configure(
+            1,
+            1.0,
+            0.2,
+            Rate::per_year(0.1),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let point = DataPoint {
+            measure: Measure::Prevalence,
+            age_lower: 0.0,
+            age_upper: 0.0,
+            value: 0.2,
+            sd: 0.0,
+            time: 2000.0,
+        };
+        let predicted = model.predict(std::slice::from_ref(&point))[0];
+        assert!(
+            (predicted - 0.2).abs() < 1e-12,
+            "Bad prediction, expected 0.2 got {}",
+            predicted
+        );
+        assert!(
+            model.log_likelihood(&[point]).is_err(),
+            "Expected error on a zero sd"
+        );
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let mut hierarchy = Hierarchy::new(
+            "world",
+            Rate::per_year(0.02),
+            Rate::per_year(0.1),
+            Rate::per_year(0.05),
+            Rate::per_year(0.01),
+        );
+        let up = RandomEffects {
+            iota: 0.5,
+            ..RandomEffects::zero()
+        };
+        let down = RandomEffects {
+            iota: -0.2,
+            chi: 0.1,
+            ..RandomEffects::zero()
+        };
+        hierarchy
+            .add("north", "world", up)
+            .add("south", "world", RandomEffects::zero())
+            .add("capital", "north", down);
+        assert_eq!(hierarchy.children("world"), vec!["north", "south"]);
+        let rates = hierarchy.rates("capital");
+        let expected = [0.02 * 0.3f64.exp(), 0.1, 0.05 * 0.1f64.exp(), 0.01];
+        for r in 0..4 {
+            assert!(
+                (rates[r] - expected[r]).abs() < 1e-12,
+                "Bad rate {}, expected {} got {}",
+                r,
+                expected[r],
+                rates[r]
+            );
+        }
+        let models = hierarchy.simulate(50, 0.5, 0.0);
+        assert_eq!(models.len(), 4, "Expected one model per location");
+        let last = models[0].c.nrows() - 1;
+        assert!(
+            (models[2].c[(last, 0)] - models[0].c[(last, 0)]).abs() < 1e-15,
+            "Expected a location without effects to match its parent"
+        );
+        assert!(
+            models[1].prevalence()[(last, 0)] > models[0].prevalence()[(last, 0)],
+            "Expected higher incidence to raise prevalence"
+        );
+        hierarchy.draw_effects(0.3, 4);
+        assert_eq!(
+            hierarchy.locations[0].effects,
+            RandomEffects::zero(),
+            "Expected no effects at the root"
+        );
+        assert_ne!(
+            hierarchy.rates("capital"),
+            expected,
+            "Expected drawn effects to change rates"
+        );
+    }
+
+    #[test]
+    fn test_rate_grid() {
+        let mut grid = RateGrid::new(vec![0.0, 20.0, 60.0], vec![1990.0, 2010.0], 0.0);
+        grid.values = mat![[0.0, 0.2], [0.4, 0.6], [0.4, 0.6]];
+        assert!(
+            (grid.at(10.0, 2000.0) - 0.3).abs() < 1e-12,
+            "Bad interpolated rate, expected 0.3 got {}",
+            grid.at(10.0, 2000.0)
+        );
+        assert_eq!(grid.at(80.0, 2030.0), 0.6, "Expected constant beyond knots");
+        assert!(
+            (grid.average(0.0, 40.0, 1990.0) - 0.3).abs() < 1e-12,
+            "Bad average rate, expected 0.3 got {}",
+            grid.average(0.0, 40.0, 1990.0)
+        );
+    }
+
+    #[test]
+    fn test_fit_rates() {
+        // Remission rising with age, measured at a few ages in two years.
+        let truth = |age: f64| 0.1 + (0.004 * age);
+        let mut data = Vec::new();
+        for time in [2000.0, 2010.0] {
+            for age in [5.0, 15.0, 35.0, 55.0, 75.0] {
+                data.push(DataPoint {
+                    measure: Measure::Remission,
+                    age_lower: age - 5.0,
+                    age_upper: age + 5.0,
+                    value: truth(age),
+                    sd: 0.01,
+                    time,
+                });
+            }
+        }
+        let grid = RateGrid::new(vec![0.0, 20.0, 40.0, 60.0, 80.0], vec![2000.0, 2010.0], 0.2);
+        let loose = SmoothingPrior {
+            value_mean: 0.0,
+            value_sd: f64::INFINITY,
+            dage_sd: 1.0,
+            dtime_sd: 1.0,
+        };
+        let (fitted, convergence) = fit_rates(&grid, &data, &loose, 500).unwrap();
+        assert!(convergence.converged, "Expected the fit to converge");
+        for age in [10.0, 30.0, 50.0, 70.0] {
+            assert!(
+                (fitted.at(age, 2005.0) - truth(age)).abs() < 1e-3,
+                "Bad fitted rate at age {}, expected {} got {}",
+                age,
+                truth(age),
+                fitted.at(age, 2005.0)
+            );
+        }
+        // A tight age-difference prior flattens the surface.
+        let tight = SmoothingPrior {
+            dage_sd: 1e-4,
+            ..loose
+        };
+        let (flat, _) = fit_rates(&grid, &data, &tight, 500).unwrap();
+        let spread = flat.at(80.0, 2000.0) - flat.at(0.0, 2000.0);
+        assert!(
+            spread.abs() < 0.01,
+            "Expected a flat surface under a tight prior, got spread {}",
+            spread
+        );
+        assert!(
+            tight.log_density(&flat) > tight.log_density(&fitted),
+            "Expected the flat surface to be more likely under the tight prior"
+        );
+    }
+
+    #[test]
+    fn test_period_and_cohort() {
+        let constant = |value: f64| RateGrid::new(vec![0.0], vec![0.0], value);
+        let mut model = AgeTimeModel::new();
+        model.configure(
+            60,
+            0.5,
+            0.0,
+            constant(0.03),
+            constant(0.1),
+            constant(0.2),
+            constant(0.01),
+        );
+        model.cohort(1950.0).run_rk4();
+        let mut reference = Model::new();
+        reference.configure(
+            60,
+            0.5,
+            0.0,
+            Rate::per_year(0.03),
+            Rate::per_year(0.1),
+            Rate::per_year(0.2),
+            Rate::per_year(0.01),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        for t in 0..model.s.nrows() {
+            assert!(
+                ((model.s[(t, 0)] - reference.s[(t, 0)]).abs() < 1e-12)
+                    & ((model.c[(t, 0)] - reference.c[(t, 0)]).abs() < 1e-12),
+                "Bad solution at index {} with constant rates",
+                t
+            );
+        }
+        // Incidence rising over calendar time: a cohort born in 1950 lived
+        // through the lower rates of earlier years.
+        let mut iota = RateGrid::new(vec![0.0], vec![1950.0, 2010.0], 0.0);
+        iota.values = mat![[0.0, 0.06]];
+        model.iota = iota;
+        model.period(2010.0).run_rk4();
+        let period = model.prevalence();
+        model.cohort(1950.0).run_rk4();
+        let cohort = model.prevalence();
+        assert_eq!(model.time_at(50.0), 2000.0, "Bad time along the cohort");
+        let last = period.nrows() - 1;
+        assert!(
+            cohort[(last, 0)] < period[(last, 0)],
+            "Expected lower cohort prevalence, got {} and {}",
+            cohort[(last, 0)],
+            period[(last, 0)]
+        );
+        // Along the cohort iota = 0.001 a, so without other rates
+        // prevalence is 1 - exp(-0.0005 a²).
+        model.rho = constant(0.0);
+        model.chi = constant(0.0);
+        model.omega = constant(0.0);
+        model.run_rk4();
+        let age = model.grid().time(last);
+        let expected = 1.0 - (-0.0005 * age * age).exp();
+        assert!(
+            (model.prevalence()[(last, 0)] - expected).abs() < 1e-9,
+            "Bad cohort prevalence, expected {} got {}",
+            expected,
+            model.prevalence()[(last, 0)]
+        );
+    }
+
+    #[test]
+    fn test_back_calculate() {
+        let mut model = Model::new();
+        model.configure(
+            80,
+            0.1,
+            0.0,
+            Rate::per_year(0.02),
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+            Rate::per_year(0.01),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let prevalence = model.prevalence();
+        let ages: Vec<f64> = (0..8).map(|k| 10.0 * (k as f64)).collect();
+        let observed: Vec<f64> = (0..8).map(|k| prevalence[(100 * k, 0)]).collect();
+        let iota = back_calculate(&ages, &observed, |_| 0.05, |_| 0.1, 50).unwrap();
+        for (k, rate) in iota.iter().enumerate() {
+            assert!(
+                (rate - 0.02).abs() < 1e-6,
+                "Bad incidence rate on interval {}, expected 0.02 got {}",
+                k,
+                rate
+            );
+        }
+        // Falling prevalence without remission or mortality is inconsistent.
+        let falling = back_calculate(&[0.0, 1.0], &[0.2, 0.1], |_| 0.0, |_| 0.0, 10).unwrap();
+        assert_eq!(falling, vec![0.0], "Expected a zero rate");
+        let none = back_calculate(&[], &[], |_| 0.0, |_| 0.0, 10).unwrap();
+        assert!(none.is_empty(), "Expected no rates without intervals");
+        // A steep rise over a long interval stays on the monotone branch.
+        let steep = back_calculate(&[0.0, 50.0], &[0.0, 0.999], |_| 0.0, |_| 0.0, 10).unwrap();
+        let exact = -(0.001f64).ln() / 50.0;
+        assert!(
+            (steep[0] - exact).abs() < 0.05 * exact,
+            "Bad steep incidence rate, expected {} got {}",
+            exact,
+            steep[0]
+        );
+        // Too few substeps to reach a steep rise is an error naming the age.
+        let short = back_calculate(&[0.0, 1.0, 2.0], &[0.0, 0.1, 0.999], |_| 0.0, |_| 0.0, 1);
+        assert!(
+            short.is_err_and(|error| error.to_string().starts_with("age 2:")),
+            "Expected an error at age 2"
+        );
+    }
 }
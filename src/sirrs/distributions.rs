@@ -0,0 +1,302 @@
+//! Delay distributions, such as generation and serial intervals.
+//!
+//! A [`Delay`] is a continuous distribution on positive times, parametrized
+//! naturally or from the mean and standard deviation usually reported, with
+//! [`Delay::gamma`], [`Delay::lognormal`] and [`Delay::weibull`]. Models take
+//! delays as probabilities of whole unit times, which [`Delay::discretize`]
+//! produces: the generation interval of
+//! [`crate::reproduction::Cori::configure`], the reporting delay of
+//! [`crate::observation::ReportingModel::configure`], and the isolation delay
+//! of [`crate::isolation::Model::configure`].
+use crate::sirrs::fit::normal_cdf;
+use crate::sirrs::reproducible::{exp, ln, powf};
+
+/// Regularized lower incomplete gamma function P(a, x), by its series for
+/// `x < a + 1` and its continued fraction otherwise.
+pub fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let log_prefix = (a * ln(x)) - x - libm::lgamma(a);
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        return sum * exp(log_prefix);
+    }
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * ((i as f64) - a);
+        b += 2.0;
+        d = (an * d) + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + (an / c);
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    return 1.0 - (exp(log_prefix) * h);
+}
+
+/// Distribution of a delay between two events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delay {
+    /// Gamma with `shape` and `scale`.
+    Gamma { shape: f64, scale: f64 },
+    /// Log-normal, with `log_mean` and `log_sd` on the log scale.
+    LogNormal { log_mean: f64, log_sd: f64 },
+    /// Weibull with `shape` and `scale`.
+    Weibull { shape: f64, scale: f64 },
+}
+
+impl Delay {
+    /// Gamma distribution with `mean` and standard deviation `sd`.
+    pub fn gamma(mean: f64, sd: f64) -> Self {
+        check_moments(mean, sd);
+        return Delay::Gamma {
+            shape: (mean / sd).powi(2),
+            scale: sd * sd / mean,
+        };
+    }
+
+    /// Log-normal distribution with `mean` and standard deviation `sd`.
+    pub fn lognormal(mean: f64, sd: f64) -> Self {
+        check_moments(mean, sd);
+        let log_variance = ln(1.0 + (sd / mean).powi(2));
+        return Delay::LogNormal {
+            log_mean: ln(mean) - (0.5 * log_variance),
+            log_sd: log_variance.sqrt(),
+        };
+    }
+
+    /// Weibull distribution with `mean` and standard deviation `sd`. The
+    /// shape is found by bisection on the coefficient of variation.
+    pub fn weibull(mean: f64, sd: f64) -> Self {
+        check_moments(mean, sd);
+        let cv = |k: f64| {
+            let g1 = libm::tgamma(1.0 + (1.0 / k));
+            return (libm::tgamma(1.0 + (2.0 / k)) / (g1 * g1) - 1.0).sqrt();
+        };
+        // The coefficient of variation decreases with the shape.
+        let (mut lower, mut upper) = (0.05f64, 500.0f64);
+        for _ in 0..200 {
+            let middle = (lower * upper).sqrt();
+            if cv(middle) > sd / mean {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        let shape = (lower * upper).sqrt();
+        return Delay::Weibull {
+            shape,
+            scale: mean / libm::tgamma(1.0 + (1.0 / shape)),
+        };
+    }
+
+    /// Mean of the distribution.
+    pub fn mean(&self) -> f64 {
+        match *self {
+            Delay::Gamma { shape, scale } => return shape * scale,
+            Delay::LogNormal { log_mean, log_sd } => return exp(log_mean + (0.5 * log_sd * log_sd)),
+            Delay::Weibull { shape, scale } => return scale * libm::tgamma(1.0 + (1.0 / shape)),
+        }
+    }
+
+    /// Standard deviation of the distribution.
+    pub fn sd(&self) -> f64 {
+        match *self {
+            Delay::Gamma { shape, scale } => return shape.sqrt() * scale,
+            Delay::LogNormal { log_sd, .. } => {
+                return self.mean() * (exp(log_sd * log_sd) - 1.0).sqrt();
+            }
+            Delay::Weibull { shape, scale } => {
+                let g1 = libm::tgamma(1.0 + (1.0 / shape));
+                return scale * (libm::tgamma(1.0 + (2.0 / shape)) - (g1 * g1)).sqrt();
+            }
+        }
+    }
+
+    /// Probability the delay is at most `x`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+        match *self {
+            Delay::Gamma { shape, scale } => return gamma_p(shape, x / scale),
+            Delay::LogNormal { log_mean, log_sd } => {
+                return normal_cdf((ln(x) - log_mean) / log_sd);
+            }
+            Delay::Weibull { shape, scale } => return 1.0 - exp(-powf(x / scale, shape)),
+        }
+    }
+
+    /// Delay below which a fraction `p` of the distribution lies, by
+    /// bisection on [`Delay::cdf`].
+    pub fn quantile(&self, p: f64) -> f64 {
+        assert!((p > 0.0) & (p < 1.0), "p must be in (0, 1), got {}", p);
+        let mut upper = self.mean() + self.sd();
+        while self.cdf(upper) < p {
+            upper *= 2.0;
+        }
+        let mut lower = 0.0;
+        for _ in 0..200 {
+            let middle = 0.5 * (lower + upper);
+            if self.cdf(middle) < p {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        return 0.5 * (lower + upper);
+    }
+
+    /// Probability of each of `n` whole unit times, the delay falling in
+    /// `[d, d + 1)` for element `d`. Normalized to sum to 1 over the `n`
+    /// unit times.
+    pub fn discretize(&self, n: usize) -> Vec<f64> {
+        return normalize((0..n).map(|d| self.cdf((d + 1) as f64) - self.cdf(d as f64)));
+    }
+
+    /// Probability of each of `n` whole unit times, the delay rounding to
+    /// `d` for element `d`. Normalized to sum to 1 over the `n` unit times.
+    pub fn discretize_nearest(&self, n: usize) -> Vec<f64> {
+        return normalize((0..n).map(|d| {
+            let d = d as f64;
+            self.cdf(d + 0.5) - self.cdf(d - 0.5)
+        }));
+    }
+}
+
+/// Check a mean and standard deviation describe a positive delay.
+fn check_moments(mean: f64, sd: f64) {
+    assert!(
+        (mean > 0.0) & (sd > 0.0),
+        "mean and sd must be positive, got {} and {}",
+        mean,
+        sd
+    );
+}
+
+/// Probabilities scaled to sum to 1.
+fn normalize(probabilities: impl Iterator<Item = f64>) -> Vec<f64> {
+    let probabilities: Vec<f64> = probabilities.collect();
+    let total: f64 = probabilities.iter().sum();
+    assert!(
+        total > 0.0,
+        "delay has no probability within the unit times"
+    );
+    return probabilities.iter().map(|p| p / total).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::distributions::{Delay, gamma_p};
+
+    #[test]
+    fn test_gamma_p() {
+        for (a, x, expected) in [
+            (1.0, 2.0, 1.0 - (-2.0f64).exp()),
+            (
+                3.0,
+                1.5,
+                1.0 - ((-1.5f64).exp() * (1.0 + 1.5 + (1.5 * 1.5 / 2.0))),
+            ),
+            (2.0, 10.0, 1.0 - ((-10.0f64).exp() * 11.0)),
+        ] {
+            assert!(
+                (gamma_p(a, x) - expected).abs() < 1e-12,
+                "Bad P({}, {}), expected {} got {}",
+                a,
+                x,
+                expected,
+                gamma_p(a, x)
+            );
+        }
+    }
+
+    #[test]
+    fn test_moments() {
+        for delay in [
+            Delay::gamma(6.5, 4.0),
+            Delay::lognormal(6.5, 4.0),
+            Delay::weibull(6.5, 4.0),
+        ] {
+            assert!(
+                ((delay.mean() - 6.5).abs() < 1e-9) & ((delay.sd() - 4.0).abs() < 1e-9),
+                "Bad moments of {:?}, expected (6.5, 4) got ({}, {})",
+                delay,
+                delay.mean(),
+                delay.sd()
+            );
+            let median = delay.quantile(0.5);
+            assert!(
+                (delay.cdf(median) - 0.5).abs() < 1e-12,
+                "Bad median of {:?}, got {}",
+                delay,
+                median
+            );
+            let discrete = delay.discretize(100);
+            let mean: f64 = discrete
+                .iter()
+                .enumerate()
+                .map(|(d, p)| ((d as f64) + 0.5) * p)
+                .sum();
+            assert!(
+                (mean - 6.5).abs() < 0.05,
+                "Bad mean of discretized {:?}, expected 6.5 got {}",
+                delay,
+                mean
+            );
+            let nearest = delay.discretize_nearest(100);
+            let mean: f64 = nearest
+                .iter()
+                .enumerate()
+                .map(|(d, p)| (d as f64) * p)
+                .sum();
+            assert!(
+                (mean - 6.5).abs() < 0.05,
+                "Bad mean of rounded {:?}, expected 6.5 got {}",
+                delay,
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn test_exponential() {
+        let delay = Delay::Weibull {
+            shape: 1.0,
+            scale: 2.0,
+        };
+        let expected = Delay::Gamma {
+            shape: 1.0,
+            scale: 2.0,
+        };
+        let (a, b) = (delay.discretize(5), expected.discretize(5));
+        for d in 0..5 {
+            assert!(
+                (a[d] - b[d]).abs() < 1e-12,
+                "Bad probability of {}, expected {} got {}",
+                d,
+                b[d],
+                a[d]
+            );
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Uncertainty propagation from posterior draws.
+//!
+//! [`Draws`] holds joint draws of named parameters, from a fit in this
+//! crate ([`Draws::from_chains`]) or from another tool
+//! ([`crate::data::parse_draws_csv`]). [`run_ensemble`] resamples the draws
+//! and runs a scenario once per resampled draw, so the spread of the
+//! outputs reflects the joint uncertainty of the upstream estimates,
+//! including correlations between parameters.
+//!
+//! [`Ensemble::incidence_quantiles`] summarizes the runs into time-wise
+//! quantiles, by default [`QUANTILES`], the bands of a fan chart and the
+//! values a forecast submission reports. [`Quantiles`] are written as a wide
+//! csv, or reshaped to long records by [`Quantiles::to_long`].
+use crate::sirrs::export::{self, LongRecord};
+use crate::sirrs::interventions::{Interval, Scenario};
+use crate::sirrs::mcmc::ChainSummary;
+use crate::sirrs::pipeline::{Intervention, Parameters, Pipeline, System};
+use crate::sirrs::rng;
+use faer::Mat;
+use rand::Rng;
+use std::io::{self, Write};
+
+/// Quantile levels of a fan chart: the median, and the central 50% and 90%
+/// intervals.
+pub const QUANTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+/// Joint draws of named parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Draws {
+    /// Name of each parameter, in column order.
+    pub names: Vec<String>,
+    /// Value of each parameter (column) in each draw (row).
+    pub values: Mat<f64>,
+}
+
+impl Draws {
+    /// Draws of `names` with one row per draw.
+    pub fn new(names: Vec<String>, values: Mat<f64>) -> Self {
+        assert_eq!(
+            names.len(),
+            values.ncols(),
+            "expected one column per name, got {} names and {} columns",
+            names.len(),
+            values.ncols()
+        );
+        assert!(values.nrows() > 0, "expected at least one draw");
+        return Self { names, values };
+    }
+
+    /// Pool the draws of every chain. Chains sample on whatever scale the
+    /// target used, for example log rates for [`crate::mcmc::sample_fit`];
+    /// see [`Draws::map`].
+    pub fn from_chains(summary: &ChainSummary, names: &[&str]) -> Self {
+        let n_params = names.len();
+        let n_draws = summary.chains.iter().map(|c| c.samples.nrows()).sum();
+        let mut values = Mat::zeros(n_draws, n_params);
+        let mut row = 0;
+        for chain in summary.chains.iter() {
+            for d in 0..chain.samples.nrows() {
+                for j in 0..n_params {
+                    values[(row, j)] = chain.samples[(d, j)];
+                }
+                row += 1;
+            }
+        }
+        return Self::new(names.iter().map(|n| n.to_string()).collect(), values);
+    }
+
+    /// Apply `f` to every value, for example `f64::exp` to turn log rates
+    /// into rates.
+    pub fn map(&mut self, f: impl Fn(f64) -> f64) -> &mut Self {
+        self.values = Mat::from_fn(self.values.nrows(), self.values.ncols(), |d, j| {
+            f(self.values[(d, j)])
+        });
+        return self;
+    }
+
+    /// Number of draws.
+    pub fn n_draws(&self) -> usize {
+        return self.values.nrows();
+    }
+
+    /// Parameters of draw `d`.
+    pub fn parameters(&self, d: usize) -> Parameters {
+        return self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| (name.clone(), self.values[(d, j)]))
+            .collect();
+    }
+
+    /// Indices of `n` draws chosen uniformly with replacement.
+    pub fn resample(&self, n: usize, seed: u64) -> Vec<usize> {
+        let mut rng = rng::rng(seed);
+        return (0..n).map(|_| rng.gen_range(0..self.n_draws())).collect();
+    }
+}
+
+/// Set parameters to the values of one draw.
+pub struct DrawParameters {
+    /// Values to set, by name.
+    pub parameters: Parameters,
+}
+
+impl Intervention for DrawParameters {
+    fn apply(&self, _t: f64, parameters: &mut Parameters) {
+        for (name, value) in self.parameters.iter() {
+            if let Some(p) = parameters.get_mut(name) {
+                *p = *value;
+            }
+        }
+    }
+}
+
+/// Outputs of an ensemble run.
+pub struct Ensemble {
+    /// Index of the draw used by each run.
+    pub draws: Vec<usize>,
+    /// New cases over the step ending at each index (row) of each run
+    /// (column).
+    pub incidence: Mat<f64>,
+    /// Total new cases of each run.
+    pub total_incidence: Vec<f64>,
+    /// Time between indices.
+    pub step_size: f64,
+}
+
+/// Time-wise quantiles of an ensemble output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantiles {
+    /// Level of each quantile, in column order.
+    pub levels: Vec<f64>,
+    /// Value of each quantile (column) at each index (row).
+    pub values: Mat<f64>,
+    /// Time between indices.
+    pub step_size: f64,
+}
+
+impl Quantiles {
+    /// Records of `name`, one per index and level, with the level as the
+    /// stratum. See [`export::to_long`].
+    pub fn to_long(&self, name: &str) -> Vec<LongRecord> {
+        let levels: Vec<String> = self.levels.iter().map(|p| p.to_string()).collect();
+        return export::to_long(self.step_size, &levels, &[(name, &self.values)]);
+    }
+
+    /// Write the quantiles as csv, with a time column `t` then one column
+    /// per level, such as `q0.05`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let header: Vec<String> = self.levels.iter().map(|p| format!("q{}", p)).collect();
+        writeln!(writer, "t,{}", header.join(","))?;
+        for t in 0..self.values.nrows() {
+            let row: Vec<String> = (0..self.values.ncols())
+                .map(|j| self.values[(t, j)].to_string())
+                .collect();
+            writeln!(writer, "{},{}", (t as f64) * self.step_size, row.join(","))?;
+        }
+        return writer.flush();
+    }
+}
+
+/// Quantiles at each of `levels` of every row of `values`, across its
+/// columns, by linear interpolation between order statistics.
+pub fn quantiles(values: &Mat<f64>, levels: &[f64]) -> Mat<f64> {
+    assert!(values.ncols() > 0, "values must have at least one column");
+    for p in levels.iter() {
+        assert!(
+            (*p >= 0.0) & (*p <= 1.0),
+            "quantile levels must be in [0, 1], got {}",
+            p
+        );
+    }
+    let mut result = Mat::zeros(values.nrows(), levels.len());
+    for t in 0..values.nrows() {
+        let mut sorted: Vec<f64> = (0..values.ncols()).map(|r| values[(t, r)]).collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        for (j, p) in levels.iter().enumerate() {
+            let x = p * ((sorted.len() - 1) as f64);
+            let k = x.floor() as usize;
+            let next = (k + 1).min(sorted.len() - 1);
+            result[(t, j)] = sorted[k] + ((x - (k as f64)) * (sorted[next] - sorted[k]));
+        }
+    }
+    return result;
+}
+
+impl Ensemble {
+    /// Mean and central `level` interval of incidence at each index, as
+    /// columns mean, lower and upper.
+    pub fn incidence_band(&self, level: f64) -> Mat<f64> {
+        let (n_steps, n_runs) = self.incidence.shape();
+        let mut band = Mat::zeros(n_steps, 3);
+        for t in 0..n_steps {
+            let values: Vec<f64> = (0..n_runs).map(|r| self.incidence[(t, r)]).collect();
+            let interval = Interval::from_values(&values, level);
+            band[(t, 0)] = interval.mean;
+            band[(t, 1)] = interval.lower;
+            band[(t, 2)] = interval.upper;
+        }
+        return band;
+    }
+
+    /// Total incidence of each run (column) over each of `n_horizons`
+    /// consecutive periods of `horizon_length` (row) after time `start`,
+    /// such as weeks after the forecast date. Summarize with [`quantiles`]
+    /// for [`crate::export::to_hub`].
+    pub fn incidence_by_horizon(
+        &self,
+        start: f64,
+        horizon_length: f64,
+        n_horizons: usize,
+    ) -> Mat<f64> {
+        let (n_steps, n_runs) = self.incidence.shape();
+        let end = start + ((n_horizons as f64) * horizon_length);
+        let last = ((n_steps as f64) - 1.0) * self.step_size;
+        assert!(
+            end <= last + (0.5 * self.step_size),
+            "horizons end at t = {}, after the last index at t = {}",
+            end,
+            last
+        );
+        let mut totals = Mat::zeros(n_horizons, n_runs);
+        for k in 0..n_steps {
+            // New cases over the step ending at index k.
+            let t = (k as f64) * self.step_size;
+            let h = (((t - start) / horizon_length) - 1e-9).ceil() - 1.0;
+            if (h < 0.0) | (h >= n_horizons as f64) {
+                continue;
+            }
+            for r in 0..n_runs {
+                totals[(h as usize, r)] += self.incidence[(k, r)];
+            }
+        }
+        return totals;
+    }
+
+    /// Quantiles of incidence at each index, at `levels` such as
+    /// [`QUANTILES`].
+    pub fn incidence_quantiles(&self, levels: &[f64]) -> Quantiles {
+        return Quantiles {
+            levels: levels.to_vec(),
+            values: quantiles(&self.incidence, levels),
+            step_size: self.step_size,
+        };
+    }
+}
+
+/// Run `scenario` on fresh systems from `system` once for each of `n_runs`
+/// draws resampled from `draws`.
+///
+/// Drawn values replace the system's parameters of the same name before
+/// the scenario's interventions act on them. Every drawn name must be a
+/// parameter of the system.
+pub fn run_ensemble(
+    system: impl Fn() -> Box<dyn System>,
+    draws: &Draws,
+    scenario: &Scenario,
+    length: usize,
+    step_size: f64,
+    n_runs: usize,
+    seed: u64,
+) -> Ensemble {
+    let chosen = draws.resample(n_runs, seed);
+    return run_draws(system, draws, chosen, scenario, length, step_size);
+}
+
+/// Run `scenario` once for every draw in order, for draws that are already
+/// a designed sample such as [`crate::sampling::latin_hypercube`]. See
+/// [`run_ensemble`].
+pub fn run_each_draw(
+    system: impl Fn() -> Box<dyn System>,
+    draws: &Draws,
+    scenario: &Scenario,
+    length: usize,
+    step_size: f64,
+) -> Ensemble {
+    let chosen = (0..draws.n_draws()).collect();
+    return run_draws(system, draws, chosen, scenario, length, step_size);
+}
+
+/// Run `scenario` once for each draw index in `chosen`.
+fn run_draws(
+    system: impl Fn() -> Box<dyn System>,
+    draws: &Draws,
+    chosen: Vec<usize>,
+    scenario: &Scenario,
+    length: usize,
+    step_size: f64,
+) -> Ensemble {
+    let known = system().parameters();
+    for name in draws.names.iter() {
+        assert!(
+            known.contains_key(name),
+            "{} is not a parameter of the system",
+            name
+        );
+    }
+    let n_runs = chosen.len();
+    let mut runs = Vec::with_capacity(n_runs);
+    for &d in chosen.iter() {
+        let mut pipeline = Pipeline::new(system());
+        pipeline.configure(length, step_size);
+        pipeline.intervention(Box::new(DrawParameters {
+            parameters: draws.parameters(d),
+        }));
+        for intervention in scenario.interventions.iter() {
+            pipeline.intervention(Box::new(intervention.clone()));
+        }
+        pipeline.run_rk4();
+        runs.push(pipeline.incidence);
+    }
+    let n_steps = runs.first().map_or(0, |r| r.nrows());
+    let incidence = Mat::from_fn(n_steps, n_runs, |t, r| runs[r][(t, 0)]);
+    let total_incidence = (0..n_runs)
+        .map(|r| (0..n_steps).map(|t| incidence[(t, r)]).sum())
+        .collect();
+    return Ensemble {
+        draws: chosen,
+        incidence,
+        total_incidence,
+        step_size,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::ensemble::{Draws, QUANTILES, quantiles, run_ensemble};
+    use crate::sirrs::interventions::Scenario;
+    use crate::sirrs::pipeline::System;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use faer::mat;
+
+    fn system() -> Box<dyn System> {
+        let mut model = sir::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.001,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        return Box::new(model);
+    }
+
+    #[test]
+    fn test_run_ensemble() {
+        let draws = Draws::new(
+            vec!["incidence_rate".to_string(), "removal_rate".to_string()],
+            mat![[0.3, 0.1], [0.5, 0.1]],
+        );
+        let ensemble = run_ensemble(system, &draws, &Scenario::new("baseline"), 100, 0.5, 20, 1);
+        assert_eq!(
+            ensemble.incidence.shape(),
+            (200, 20),
+            "Bad incidence dimensions, got {:?}",
+            ensemble.incidence.shape()
+        );
+        for (r, &d) in ensemble.draws.iter().enumerate() {
+            let mut model = sir::Model::new();
+            let beta = draws.values[(d, 0)];
+            model.configure(
+                100,
+                0.5,
+                0.001,
+                0.0,
+                Rate::per_day(beta),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            model.init_popf();
+            model.run_rk4();
+            let expected = model.r_popf[(199, 0)] + model.i_popf[(199, 0)] - 0.001;
+            assert!(
+                (ensemble.total_incidence[r] - expected).abs() < 1e-6,
+                "Bad total incidence of run {}, expected {} got {}",
+                r,
+                expected,
+                ensemble.total_incidence[r]
+            );
+        }
+        let band = ensemble.incidence_band(0.9);
+        assert!(
+            band[(60, 1)] < band[(60, 2)],
+            "Expected a non-degenerate band, got {:?}",
+            (band[(60, 1)], band[(60, 2)])
+        );
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let values = mat![[4.0, 0.0, 2.0, 1.0, 3.0], [1.0, 1.0, 1.0, 1.0, 1.0]];
+        let result = quantiles(&values, &[0.0, 0.5, 0.9, 1.0]);
+        for (j, expected) in [0.0, 2.0, 3.6, 4.0].iter().enumerate() {
+            assert!(
+                (result[(0, j)] - expected).abs() < 1e-12,
+                "Bad quantile {}, expected {} got {}",
+                j,
+                expected,
+                result[(0, j)]
+            );
+            assert_eq!(result[(1, j)], 1.0, "Bad quantile of a constant row");
+        }
+        let draws = Draws::new(
+            vec!["incidence_rate".to_string()],
+            mat![[0.3], [0.4], [0.5]],
+        );
+        let ensemble = run_ensemble(system, &draws, &Scenario::new("baseline"), 100, 0.5, 30, 2);
+        let bands = ensemble.incidence_quantiles(&QUANTILES);
+        assert_eq!(bands.values.shape(), (200, 5), "Bad quantile dimensions");
+        for t in 0..200 {
+            for j in 1..5 {
+                assert!(
+                    bands.values[(t, j - 1)] <= bands.values[(t, j)],
+                    "Expected ordered quantiles at index {}",
+                    t
+                );
+            }
+        }
+        let weekly = ensemble.incidence_by_horizon(20.0, 7.0, 4);
+        let expected: f64 = (41..=54).map(|k| ensemble.incidence[(k, 3)]).sum();
+        assert!(
+            (weekly[(0, 3)] - expected).abs() < 1e-12,
+            "Bad horizon total, expected {} got {}",
+            expected,
+            weekly[(0, 3)]
+        );
+        let records = bands.to_long("incidence");
+        assert_eq!(records.len(), 1000, "Bad number of records");
+        assert_eq!(records[7].stratum, "0.5", "Bad stratum");
+        assert_eq!(records[7].t, 0.5, "Bad time");
+        let mut csv = Vec::new();
+        bands.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().next(),
+            Some("t,q0.05,q0.25,q0.5,q0.75,q0.95"),
+            "Bad header"
+        );
+        assert_eq!(csv.lines().count(), 201, "Bad number of lines");
+    }
+
+    #[test]
+    fn test_map() {
+        let mut draws = Draws::new(vec!["removal_rate".to_string()], mat![[0.0], [1.0]]);
+        draws.map(f64::exp);
+        assert_eq!(
+            draws.parameters(1)["removal_rate"],
+            1f64.exp(),
+            "Bad mapped draw"
+        );
+    }
+}
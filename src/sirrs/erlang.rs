@@ -0,0 +1,394 @@
+//! SEIR model with Erlang distributed latent and infectious periods.
+//!
+//! The latent (E) and infectious (I) compartments are each split into a chain
+//! of sequential sub-stages, the linear chain trick, so the time spent in
+//! them is gamma (Erlang) distributed with shape equal to the number of
+//! stages rather than exponential. Allows transition rates:
+//!  - S → E_1, or S → I_1 when there are no latent stages
+//!  - E_j → E_j+1, E_k → I_1
+//!  - I_j → I_j+1, I_k → R
+//!
+//! With a single infectious stage and no latent stages this is the SIR model.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run an SEIR model with Erlang distributed periods.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction, placed in the first
+    /// infectious stage.
+    pub i_popf_init: f64,
+    /// Transition rate from S into E (or I). Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Inverse of the mean latent period. Each latent stage is left at
+    /// `latent_stages * latent_rate`.
+    pub latent_rate: f64,
+    /// Inverse of the mean infectious period. Each infectious stage is left
+    /// at `infectious_stages * removal_rate`.
+    pub removal_rate: f64,
+    /// Number of latent sub-stages. Zero removes the latent compartment.
+    pub latent_stages: usize,
+    /// Number of infectious sub-stages. Must be at least 1.
+    pub infectious_stages: usize,
+    /// Susceptible population fraction at each index. 1D Array with `length` number of elements.
+    pub s_popf: Mat<f64>,
+    /// Latent population fraction, summed over stages, at each index. 1D Array with `length` number of elements.
+    pub e_popf: Mat<f64>,
+    /// Infectious population fraction, summed over stages, at each index. 1D Array with `length` number of elements.
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with `length` number of elements.
+    pub r_popf: Mat<f64>,
+    /// Population fraction in each latent stage (column) at each index (row).
+    pub e_stages: Mat<f64>,
+    /// Population fraction in each infectious stage (column) at each index (row).
+    pub i_stages: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            latent_rate: 0.0,
+            removal_rate: 0.0,
+            latent_stages: 0,
+            infectious_stages: 1,
+            s_popf: Mat::new(),
+            e_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            e_stages: Mat::new(),
+            i_stages: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. Rates are per day.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        latent_rate: Rate,
+        removal_rate: Rate,
+        latent_stages: usize,
+        infectious_stages: usize,
+    ) -> &mut Self {
+        assert!(
+            infectious_stages >= 1,
+            "infectious_stages must be at least 1"
+        );
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.latent_rate = latent_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.latent_stages = latent_stages;
+        self.infectious_stages = infectious_stages;
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.e_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.e_stages = Mat::zeros(n_steps, latent_stages);
+        self.i_stages = Mat::zeros(n_steps, infectious_stages);
+        return self;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let y = {
+            let mut y = vec![0.0; self.n_vars()];
+            y[0] = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+            y[1 + self.latent_stages] = self.i_popf_init;
+            y
+        };
+        self.store(0, &y);
+        return self;
+    }
+
+    /// Number of state variables: S, each stage, and R.
+    fn n_vars(&self) -> usize {
+        return 2 + self.latent_stages + self.infectious_stages;
+    }
+
+    /// Pack the state at index `t` into a vector ordered S, E stages,
+    /// I stages, R.
+    fn load(&self, t: usize) -> Vec<f64> {
+        let mut y = Vec::with_capacity(self.n_vars());
+        y.push(self.s_popf[(t, 0)]);
+        for j in 0..self.latent_stages {
+            y.push(self.e_stages[(t, j)]);
+        }
+        for j in 0..self.infectious_stages {
+            y.push(self.i_stages[(t, j)]);
+        }
+        y.push(self.r_popf[(t, 0)]);
+        return y;
+    }
+
+    /// Unpack a state vector into index `t`, updating the stage totals.
+    fn store(&mut self, t: usize, y: &[f64]) {
+        let ke = self.latent_stages;
+        let ki = self.infectious_stages;
+        self.s_popf[(t, 0)] = y[0];
+        for j in 0..ke {
+            self.e_stages[(t, j)] = y[1 + j];
+        }
+        for j in 0..ki {
+            self.i_stages[(t, j)] = y[1 + ke + j];
+        }
+        self.e_popf[(t, 0)] = y[1..1 + ke].iter().sum();
+        self.i_popf[(t, 0)] = y[1 + ke..1 + ke + ki].iter().sum();
+        self.r_popf[(t, 0)] = y[1 + ke + ki];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64]) -> Vec<f64> {
+        return self.derivatives_at(y, self.incidence_rate, self.latent_rate, self.removal_rate);
+    }
+
+    /// Compute the derivative of every state variable with the given rates
+    /// in place of the configured ones.
+    pub(crate) fn derivatives_at(
+        &self,
+        y: &[f64],
+        incidence_rate: f64,
+        latent_rate: f64,
+        removal_rate: f64,
+    ) -> Vec<f64> {
+        let ke = self.latent_stages;
+        let ki = self.infectious_stages;
+        let infectious: f64 = y[1 + ke..1 + ke + ki].iter().sum();
+        let infection = incidence_rate * y[0] * infectious;
+        let e_rate = (ke as f64) * latent_rate;
+        let i_rate = (ki as f64) * removal_rate;
+        let mut d = vec![0.0; y.len()];
+        d[0] = -infection;
+        // Flow into each stage is the outflow of the previous one, starting
+        // from new infections.
+        let mut inflow = infection;
+        for j in 0..ke {
+            let outflow = e_rate * y[1 + j];
+            d[1 + j] = inflow - outflow;
+            inflow = outflow;
+        }
+        for j in 0..ki {
+            let outflow = i_rate * y[1 + ke + j];
+            d[1 + ke + j] = inflow - outflow;
+            inflow = outflow;
+        }
+        d[1 + ke + ki] = inflow;
+        return d;
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = ((self.length as f64) / h).ceil() as usize;
+        for t in 0..n - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            let next: Vec<f64> = (0..y.len()).map(|j| y[j] + (h * d[j])).collect();
+            self.store(t + 1, &next);
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = ((self.length as f64) / h).ceil() as usize;
+        for t in 0..n - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let y2: Vec<f64> = (0..y.len()).map(|j| y[j] + (h / 2.0 * k1[j])).collect();
+            let k2 = self.derivatives(&y2);
+            let y3: Vec<f64> = (0..y.len()).map(|j| y[j] + (h / 2.0 * k2[j])).collect();
+            let k3 = self.derivatives(&y3);
+            let y4: Vec<f64> = (0..y.len()).map(|j| y[j] + (h * k3[j])).collect();
+            let k4 = self.derivatives(&y4);
+            let next: Vec<f64> = (0..y.len())
+                .map(|j| y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0)))
+                .collect();
+            self.store(t + 1, &next);
+        }
+        return self;
+    }
+
+    /// The solved series with metadata of a run of `solver`, for example
+    /// `rk4`, with compartments summed over stages.
+    pub fn result(&self, solver: &str) -> SimulationResult {
+        let parameters = Parameters::from([
+            ("length".to_string(), self.length as f64),
+            ("i_popf_init".to_string(), self.i_popf_init),
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("latent_rate".to_string(), self.latent_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("latent_stages".to_string(), self.latent_stages as f64),
+            (
+                "infectious_stages".to_string(),
+                self.infectious_stages as f64,
+            ),
+        ]);
+        let columns = [&self.s_popf, &self.e_popf, &self.i_popf, &self.r_popf];
+        let n_steps = self.s_popf.nrows();
+        return SimulationResult {
+            metadata: RunMetadata::new(solver, self.step_size, &parameters),
+            names: ["s", "e", "i", "r"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            times: TimeGrid::from_length(self.length, self.step_size).times(),
+            values: Mat::from_fn(n_steps, columns.len(), |t, j| columns[j][(t, 0)]),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::erlang::Model;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_configure() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            0.5,
+            0.01,
+            Rate::per_day(0.5),
+            Rate::per_day(0.2),
+            Rate::per_day(0.1),
+            2,
+            3,
+        );
+        assert_eq!(
+            model.e_stages.shape(),
+            (20, 2),
+            "Bad e_stages dimensions, expected {:?} got {:?}",
+            (20, 2),
+            model.e_stages.shape()
+        );
+        assert_eq!(
+            model.i_stages.shape(),
+            (20, 3),
+            "Bad i_stages dimensions, expected {:?} got {:?}",
+            (20, 3),
+            model.i_stages.shape()
+        );
+    }
+
+    #[test]
+    fn test_single_stage_matches_sir() {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            1.0,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.0),
+            Rate::per_day(0.1),
+            0,
+            1,
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = crate::sirrs::sir::Model::new();
+        sir.configure(
+            50,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..50 {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.5),
+            Rate::per_day(0.2),
+            Rate::per_day(0.1),
+            3,
+            4,
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)]
+                + model.e_popf[(t, 0)]
+                + model.i_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_erlang_removal_distribution() {
+        // With no new infections, the cohort in I leaves after a gamma
+        // distributed time, so R(t) is the Erlang cumulative distribution.
+        let mut model = Model::new();
+        model.configure(
+            20,
+            0.01,
+            1.0,
+            Rate::per_day(0.0),
+            Rate::per_day(0.0),
+            Rate::per_day(0.5),
+            0,
+            2,
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in [100, 500, 1000, 1500] {
+            let x = (t as f64) * model.step_size;
+            let rate = 2.0 * model.removal_rate;
+            let expected = 1.0 - ((-rate * x).exp() * (1.0 + (rate * x)));
+            assert!(
+                (model.r_popf[(t, 0)] - expected).abs() < 1e-8,
+                "Bad r_popf at t = {}, expected {} got {}",
+                x,
+                expected,
+                model.r_popf[(t, 0)]
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Long (tidy) format export of stratified outputs.
+//!
+//! Structured models store each compartment as a wide matrix, one column per
+//! stratum. Plotting and data frame libraries expect one row per
+//! observation instead, `(t, stratum, compartment, value)`, which is what
+//! [`to_long`] produces.
+//!
+//! Forecasts are submitted to forecast hubs as quantiles of each target at
+//! each horizon, one row per `(location, target, horizon, quantile)`.
+//! [`to_hub`] produces these records from quantiles by horizon, such as
+//! [`crate::ensemble::Ensemble::incidence_by_horizon`] summarized by
+//! [`crate::ensemble::quantiles`], and [`write_hub_csv`] writes the csv a hub
+//! accepts.
+use faer::Mat;
+use std::io::{self, Write};
+
+/// One value of one compartment in one stratum at one time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongRecord {
+    /// Time of the value.
+    pub t: f64,
+    /// Name of the stratum, `all` for unstratified compartments.
+    pub stratum: String,
+    /// Name of the compartment.
+    pub compartment: String,
+    /// The value.
+    pub value: f64,
+}
+
+/// One quantile of one forecast target at one horizon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubRecord {
+    /// Location code of the forecast, for example a FIPS or ISO code.
+    pub location: String,
+    /// Name of the target, for example `wk inc case`.
+    pub target: String,
+    /// Number of horizon lengths ahead, from 1.
+    pub horizon: usize,
+    /// Level of the quantile.
+    pub quantile: f64,
+    /// The forecast value.
+    pub value: f64,
+}
+
+/// Forecast-hub records of `target` at `location`, from the value of each
+/// quantile (column) at `levels` for each horizon (row), from horizon 1.
+/// Records are ordered by horizon, then quantile.
+pub fn to_hub(location: &str, target: &str, values: &Mat<f64>, levels: &[f64]) -> Vec<HubRecord> {
+    assert_eq!(
+        values.ncols(),
+        levels.len(),
+        "expected one column per level, got {} levels and {} columns",
+        levels.len(),
+        values.ncols()
+    );
+    let mut records = Vec::with_capacity(values.nrows() * levels.len());
+    for h in 0..values.nrows() {
+        for (j, quantile) in levels.iter().enumerate() {
+            records.push(HubRecord {
+                location: location.to_string(),
+                target: target.to_string(),
+                horizon: h + 1,
+                quantile: *quantile,
+                value: values[(h, j)],
+            });
+        }
+    }
+    return records;
+}
+
+/// Write forecast-hub records as csv with columns
+/// `location,target,horizon,quantile,value`.
+pub fn write_hub_csv<W: Write>(records: &[HubRecord], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "location,target,horizon,quantile,value")?;
+    for record in records.iter() {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            record.location, record.target, record.horizon, record.quantile, record.value
+        )?;
+    }
+    return writer.flush();
+}
+
+/// Reshape wide compartment matrices into long records.
+///
+/// Each compartment is `(name, matrix)` with one row per index, sampled
+/// every `step_size`, and either one column per stratum in `strata` or a
+/// single column for an unstratified compartment. Records are ordered by
+/// time, then compartment, then stratum.
+pub fn to_long(
+    step_size: f64,
+    strata: &[String],
+    compartments: &[(&str, &Mat<f64>)],
+) -> Vec<LongRecord> {
+    let all = ["all".to_string()];
+    for (name, matrix) in compartments.iter() {
+        assert!(
+            (matrix.ncols() == 1) | (matrix.ncols() == strata.len()),
+            "{} has {} columns, expected 1 or {}",
+            name,
+            matrix.ncols(),
+            strata.len()
+        );
+    }
+    let n = compartments
+        .iter()
+        .map(|(_, m)| m.nrows())
+        .min()
+        .unwrap_or(0);
+    let mut records = Vec::with_capacity(n * compartments.len() * strata.len().max(1));
+    for t in 0..n {
+        for (name, matrix) in compartments.iter() {
+            let names: &[String] = if (matrix.ncols() == 1) & (strata.len() != 1) {
+                &all
+            } else {
+                strata
+            };
+            for (j, stratum) in names.iter().enumerate() {
+                records.push(LongRecord {
+                    t: (t as f64) * step_size,
+                    stratum: stratum.clone(),
+                    compartment: name.to_string(),
+                    value: matrix[(t, j)],
+                });
+            }
+        }
+    }
+    return records;
+}
+
+/// Default stratum names, the index of each of `n` strata.
+pub fn index_names(n: usize) -> Vec<String> {
+    return (0..n).map(|j| j.to_string()).collect();
+}
+
+/// Write long records as csv with columns `t,stratum,compartment,value`.
+pub fn write_long_csv<W: Write>(records: &[LongRecord], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "t,stratum,compartment,value")?;
+    for record in records.iter() {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            record.t, record.stratum, record.compartment, record.value
+        )?;
+    }
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::export::{index_names, to_hub, to_long, write_hub_csv, write_long_csv};
+    use faer::mat;
+
+    #[test]
+    fn test_to_long() {
+        let s = mat![[0.9], [0.8]];
+        let i = mat![[0.1, 0.0], [0.15, 0.05]];
+        let records = to_long(0.5, &index_names(2), &[("s", &s), ("i", &i)]);
+        assert_eq!(
+            records.len(),
+            6,
+            "Bad number of records, got {}",
+            records.len()
+        );
+        assert_eq!(records[0].stratum, "all", "Bad unstratified stratum name");
+        assert_eq!(
+            (
+                records[5].t,
+                records[5].stratum.as_str(),
+                records[5].compartment.as_str()
+            ),
+            (0.5, "1", "i"),
+            "Bad last record, got {:?}",
+            records[5]
+        );
+        assert_eq!(records[5].value, 0.05, "Bad last value");
+    }
+
+    #[test]
+    fn test_write_long_csv() {
+        let i = mat![[0.1, 0.2]];
+        let records = to_long(1.0, &["young".to_string(), "old".to_string()], &[("i", &i)]);
+        let mut buffer = Vec::new();
+        write_long_csv(&records, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "t,stratum,compartment,value\n0,young,i,0.1\n0,old,i,0.2\n",
+            "Bad csv output"
+        );
+    }
+
+    #[test]
+    fn test_write_hub_csv() {
+        let values = mat![[10.0, 12.0], [20.0, 25.0]];
+        let records = to_hub("US", "wk inc case", &values, &[0.5, 0.75]);
+        assert_eq!(records.len(), 4, "Bad number of records");
+        assert_eq!(
+            (records[2].horizon, records[2].quantile, records[2].value),
+            (2, 0.5, 20.0),
+            "Bad record, got {:?}",
+            records[2]
+        );
+        let mut buffer = Vec::new();
+        write_hub_csv(&records[..2], &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "location,target,horizon,quantile,value\nUS,wk inc case,1,0.5,10\nUS,wk inc case,1,0.75,12\n",
+            "Bad csv output"
+        );
+    }
+}
@@ -0,0 +1,1919 @@
+//! Fit SIR model parameters to observed data.
+//!
+//! Observations are infectious population fractions at unit time intervals,
+//! starting at t = 0. Any observation may be missing (or NaN), or censored to
+//! an interval, so real surveillance series with gaps and detection limits
+//! can be used directly. Parameters are estimated by maximum likelihood under
+//! Gaussian observation noise, using the derivative-free Nelder-Mead method,
+//! or with [`Fit::run_lbfgs`], a box-constrained L-BFGS method using exact
+//! gradients from the forward sensitivities of the model, which reports its
+//! convergence diagnostics.
+//!
+//! Reported counts are better described by a count distribution than by
+//! Gaussian noise of constant variance, which gives low-count periods the
+//! same weight as the peak. With [`Fit::likelihood`], observations are
+//! instead Poisson or negative binomial counts around a multiple of the
+//! predicted fraction, with the negative binomial dispersion estimated
+//! alongside the rates.
+//!
+//! The incidence rate may change at changepoints. Known changepoints are
+//! held fixed, or the times and values of a given number of changepoints can
+//! be estimated jointly with the other parameters.
+//!
+//! For retrospective reconstruction of transmission, the incidence rate can
+//! instead follow a random walk: a separate value on each of a series of
+//! evenly spaced knots, with a penalty on the change in log rate between
+//! consecutive knots.
+//!
+//! For the age-structured model, where contact surveys are often unavailable
+//! for the population being modeled, the assortativity of preferential mixing
+//! can be estimated from age-stratified case counts with [`MixingFit`].
+//!
+//! After fitting, [`Fit::profile`] gives likelihood-based confidence
+//! intervals, and shows when a rate is not identifiable from the data.
+//!
+//! The same rates can be fitted to any [`Pipeline`] whose system has them,
+//! with its interventions and observation layer, by [`Fit::pipeline`].
+//!
+//! With the `polars` feature, observations can be read from a data frame
+//! by [`from_dataframe`].
+use crate::sirrs::age::{self, assortative_contacts};
+use crate::sirrs::forward;
+use crate::sirrs::pipeline::{Intervention, Pipeline, ScheduleParameter};
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::sampling::normal_quantile;
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::sir;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// A single, possibly partial, observation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observation {
+    /// An observed value. A NaN value is treated as missing.
+    Value(f64),
+    /// No observation for this period.
+    Missing,
+    /// The true value lies in `[lower, upper]`. Either bound may be infinite
+    /// for left or right censoring.
+    Interval(f64, f64),
+}
+
+/// Observations from two columns of a polars data frame, whole unit times
+/// `time` from 0 and observed values `value`, as taken by [`Fit`].
+///
+/// Times may be in any order and have gaps. Unit times without a row, and
+/// null values, are [`Observation::Missing`]. A negative, fractional or
+/// repeated time is an error.
+#[cfg(feature = "polars")]
+pub fn from_dataframe(
+    df: &polars::prelude::DataFrame,
+    time: &str,
+    value: &str,
+) -> polars::prelude::PolarsResult<Vec<Observation>> {
+    use polars::prelude::{DataType, polars_bail};
+    let times = df.column(time)?.cast(&DataType::Float64)?;
+    let values = df.column(value)?.cast(&DataType::Float64)?;
+    let mut rows: Vec<(usize, Option<f64>)> = Vec::with_capacity(df.height());
+    for (t, v) in times.f64()?.iter().zip(values.f64()?.iter()) {
+        let t = match t {
+            Some(t) if (t >= 0.0) & (t.fract() == 0.0) => t as usize,
+            _ => polars_bail!(ComputeError: "times must be whole and non-negative, got {:?}", t),
+        };
+        rows.push((t, v));
+    }
+    let n = rows.iter().map(|(t, _)| t + 1).max().unwrap_or(0);
+    let mut observed = vec![None; n];
+    for (t, v) in rows {
+        if observed[t].is_some() {
+            polars_bail!(ComputeError: "repeated time {}", t);
+        }
+        observed[t] = Some(v.map_or(Observation::Missing, Observation::Value));
+    }
+    return Ok(observed
+        .into_iter()
+        .map(|o| o.unwrap_or(Observation::Missing))
+        .collect());
+}
+
+/// Complementary error function. Fractional error is less than 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + (0.5 * z));
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let r = t * exp(poly);
+    if x >= 0.0 {
+        return r;
+    }
+    return 2.0 - r;
+}
+
+/// Standard normal cumulative distribution function.
+pub fn normal_cdf(x: f64) -> f64 {
+    return 0.5 * erfc(-x / std::f64::consts::SQRT_2);
+}
+
+/// Log-likelihood of one observation given the predicted value, under
+/// Gaussian noise with standard deviation `sigma`.
+pub fn observation_log_likelihood(observation: Observation, predicted: f64, sigma: f64) -> f64 {
+    match observation {
+        Observation::Value(y) if y.is_nan() => return 0.0,
+        Observation::Value(y) => {
+            let z = (y - predicted) / sigma;
+            return -0.5 * z * z - ln(sigma) - (0.5 * ln(2.0 * std::f64::consts::PI));
+        }
+        Observation::Missing => return 0.0,
+        Observation::Interval(lower, upper) => {
+            let p =
+                normal_cdf((upper - predicted) / sigma) - normal_cdf((lower - predicted) / sigma);
+            return ln(p.max(f64::MIN_POSITIVE));
+        }
+    }
+}
+
+/// Derivative of [`observation_log_likelihood`] with respect to the
+/// predicted value.
+fn observation_score(observation: Observation, predicted: f64, sigma: f64) -> f64 {
+    let density = |z: f64| exp(-0.5 * z * z) / (2.0 * std::f64::consts::PI).sqrt();
+    match observation {
+        Observation::Value(y) if y.is_nan() => return 0.0,
+        Observation::Value(y) => return (y - predicted) / (sigma * sigma),
+        Observation::Missing => return 0.0,
+        Observation::Interval(lower, upper) => {
+            let (zl, zu) = ((lower - predicted) / sigma, (upper - predicted) / sigma);
+            let p = normal_cdf(zu) - normal_cdf(zl);
+            if p < f64::MIN_POSITIVE {
+                // The log-likelihood is clamped, so flat.
+                return 0.0;
+            }
+            return (density(zl) - density(zu)) / (sigma * p);
+        }
+    }
+}
+
+/// Log-probability of `count` given mean `expected`, and its derivative with
+/// respect to `expected`. Counts are negative binomial with variance
+/// `μ + μ² / k` for dispersion `Some(k)`, or Poisson for `None`.
+fn count_log_pmf(count: f64, expected: f64, dispersion: Option<f64>) -> (f64, f64) {
+    let mu = expected.max(1e-12);
+    let log_factorial = libm::lgamma(count + 1.0);
+    match dispersion {
+        None => return ((count * ln(mu)) - mu - log_factorial, (count / mu) - 1.0),
+        Some(k) => {
+            let log_pmf = libm::lgamma(count + k) - libm::lgamma(k) - log_factorial
+                + (k * ln(k / (k + mu)))
+                + (count * ln(mu / (k + mu)));
+            return (log_pmf, (count / mu) - ((count + k) / (k + mu)));
+        }
+    }
+}
+
+/// Log-likelihood of one count observation, see [`count_log_likelihood`],
+/// and its derivative with respect to `expected`.
+fn count_terms(observation: Observation, expected: f64, dispersion: Option<f64>) -> (f64, f64) {
+    match observation {
+        Observation::Value(count) if count.is_nan() => return (0.0, 0.0),
+        Observation::Value(count) => return count_log_pmf(count.round(), expected, dispersion),
+        Observation::Missing => return (0.0, 0.0),
+        Observation::Interval(lower, upper) => {
+            let (first, last) = (lower.ceil().max(0.0), upper.floor());
+            // Sums of the probability of each count, and of its derivative.
+            let sums = |from: f64, to: f64| -> (f64, f64) {
+                let (mut p, mut dp) = (0.0, 0.0);
+                let mut count = from;
+                while count <= to {
+                    let (log_pmf, score) = count_log_pmf(count, expected, dispersion);
+                    p += exp(log_pmf);
+                    dp += exp(log_pmf) * score;
+                    count += 1.0;
+                }
+                return (p, dp);
+            };
+            let (p, dp) = if last.is_infinite() {
+                let (below, d_below) = sums(0.0, first - 1.0);
+                (1.0 - below, -d_below)
+            } else {
+                sums(first, last)
+            };
+            if p < f64::MIN_POSITIVE {
+                return (ln(f64::MIN_POSITIVE), 0.0);
+            }
+            return (ln(p), dp / p);
+        }
+    }
+}
+
+/// Log-likelihood of one observed count given its expected value, under
+/// Poisson noise, or negative binomial noise with variance `μ + μ² / k` for
+/// dispersion `Some(k)`. Values are rounded to whole counts, and an interval
+/// is the probability of any whole count within it.
+pub fn count_log_likelihood(
+    observation: Observation,
+    expected: f64,
+    dispersion: Option<f64>,
+) -> f64 {
+    return count_terms(observation, expected, dispersion).0;
+}
+
+/// Log-likelihood of a series of observations, one per unit time, against a
+/// predicted trajectory sampled every `step_size`.
+pub fn log_likelihood(
+    observed: &[Observation],
+    predicted: &Mat<f64>,
+    step_size: f64,
+    sigma: f64,
+) -> f64 {
+    let mut ll = 0.0;
+    for (t, observation) in observed.iter().enumerate() {
+        let index = ((t as f64) / step_size).round() as usize;
+        if index >= predicted.nrows() {
+            break;
+        }
+        ll += observation_log_likelihood(*observation, predicted[(index, 0)], sigma);
+    }
+    return ll;
+}
+
+/// Minimize `f` by the Nelder-Mead simplex method, starting from `x0` with
+/// initial simplex edge length `step`. Returns the minimizer, the minimum,
+/// and the number of iterations used.
+pub fn nelder_mead(
+    f: impl Fn(&[f64]) -> f64,
+    x0: &[f64],
+    step: f64,
+    max_iter: usize,
+    tolerance: f64,
+) -> (Vec<f64>, f64, usize) {
+    let n = x0.len();
+    let mut simplex: Vec<Vec<f64>> = vec![x0.to_vec()];
+    for i in 0..n {
+        let mut x = x0.to_vec();
+        x[i] += step;
+        simplex.push(x);
+    }
+    let mut values: Vec<f64> = simplex.iter().map(|x| f(x)).collect();
+    let mut iteration = 0;
+    while iteration < max_iter {
+        iteration += 1;
+        let mut order: Vec<usize> = (0..=n).collect();
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+        simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+        values = order.iter().map(|&i| values[i]).collect();
+        if (values[n] - values[0]).abs() <= tolerance {
+            break;
+        }
+        let centroid: Vec<f64> = (0..n)
+            .map(|j| simplex[..n].iter().map(|x| x[j]).sum::<f64>() / (n as f64))
+            .collect();
+        let along = |c: f64| -> Vec<f64> {
+            return (0..n)
+                .map(|j| centroid[j] + (c * (simplex[n][j] - centroid[j])))
+                .collect();
+        };
+        let reflected = along(-1.0);
+        let f_reflected = f(&reflected);
+        if f_reflected < values[0] {
+            let expanded = along(-2.0);
+            let f_expanded = f(&expanded);
+            if f_expanded < f_reflected {
+                simplex[n] = expanded;
+                values[n] = f_expanded;
+            } else {
+                simplex[n] = reflected;
+                values[n] = f_reflected;
+            }
+        } else if f_reflected < values[n - 1] {
+            simplex[n] = reflected;
+            values[n] = f_reflected;
+        } else {
+            let contracted = if f_reflected < values[n] {
+                along(-0.5)
+            } else {
+                along(0.5)
+            };
+            let f_contracted = f(&contracted);
+            if f_contracted < values[n].min(f_reflected) {
+                simplex[n] = contracted;
+                values[n] = f_contracted;
+            } else {
+                for i in 1..=n {
+                    simplex[i] = (0..n)
+                        .map(|j| simplex[0][j] + (0.5 * (simplex[i][j] - simplex[0][j])))
+                        .collect();
+                    values[i] = f(&simplex[i]);
+                }
+            }
+        }
+    }
+    let best = (0..=n)
+        .min_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap();
+    return (simplex[best].clone(), values[best], iteration);
+}
+
+/// Maximize `f` over `[lower, upper]` by golden-section search, assuming it
+/// is unimodal there.
+fn golden_section(f: impl Fn(f64) -> f64, lower: f64, upper: f64, iterations: usize) -> f64 {
+    let ratio = (5.0f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (lower, upper);
+    let (mut c, mut d) = (b - (ratio * (b - a)), a + (ratio * (b - a)));
+    let (mut fc, mut fd) = (f(c), f(d));
+    for _ in 0..iterations {
+        if fc > fd {
+            (b, d, fd) = (d, c, fc);
+            c = b - (ratio * (b - a));
+            fc = f(c);
+        } else {
+            (a, c, fc) = (c, d, fd);
+            d = a + (ratio * (b - a));
+            fd = f(d);
+        }
+    }
+    return (a + b) / 2.0;
+}
+
+/// Convergence diagnostics of a gradient-based optimizer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convergence {
+    /// Number of iterations used.
+    pub iterations: usize,
+    /// Number of evaluations of the objective and its gradient.
+    pub evaluations: usize,
+    /// Largest component of the projected gradient at the minimizer. Zero at
+    /// a minimum within the bounds, or on them.
+    pub gradient_norm: f64,
+    /// Whether a stopping criterion was met before `max_iter` iterations, or
+    /// the line search failing.
+    pub converged: bool,
+    /// Objective at the start and after each iteration.
+    pub history: Vec<f64>,
+}
+
+/// Dot product of `a` and `b` over the coordinates in `free`.
+fn masked_dot(a: &[f64], b: &[f64], free: &[bool]) -> f64 {
+    return (0..a.len()).filter(|&j| free[j]).map(|j| a[j] * b[j]).sum();
+}
+
+/// Minimize `f` within the box `[lower, upper]` by the projected L-BFGS
+/// method, starting from `x0`. `f` returns the objective and its gradient.
+///
+/// Directions are found from the last 10 steps on the coordinates not held
+/// at a bound, and steps are projected onto the box with a backtracking line
+/// search. Stops when the largest component of the projected gradient, or
+/// the relative decrease in `f` over an iteration, falls to `tolerance`.
+/// Returns the minimizer, the minimum, and convergence diagnostics.
+pub fn lbfgs(
+    f: impl Fn(&[f64]) -> (f64, Vec<f64>),
+    x0: &[f64],
+    lower: &[f64],
+    upper: &[f64],
+    max_iter: usize,
+    tolerance: f64,
+) -> (Vec<f64>, f64, Convergence) {
+    let n = x0.len();
+    let project = |x: Vec<f64>| -> Vec<f64> {
+        return (0..n).map(|j| x[j].clamp(lower[j], upper[j])).collect();
+    };
+    let projected_gradient = |x: &[f64], g: &[f64]| -> f64 {
+        return (0..n)
+            .map(|j| ((x[j] - g[j]).clamp(lower[j], upper[j]) - x[j]).abs())
+            .fold(0.0, f64::max);
+    };
+    let mut x = project(x0.to_vec());
+    let (mut fx, mut g) = f(&x);
+    let mut convergence = Convergence {
+        iterations: 0,
+        evaluations: 1,
+        gradient_norm: projected_gradient(&x, &g),
+        converged: false,
+        history: vec![fx],
+    };
+    let mut memory: Vec<(Vec<f64>, Vec<f64>)> = Vec::new();
+    while convergence.iterations < max_iter {
+        if convergence.gradient_norm <= tolerance {
+            convergence.converged = true;
+            break;
+        }
+        convergence.iterations += 1;
+        let free: Vec<bool> = (0..n)
+            .map(|j| !(((x[j] <= lower[j]) & (g[j] > 0.0)) | ((x[j] >= upper[j]) & (g[j] < 0.0))))
+            .collect();
+        let scale = 1.0 / (0..n).map(|j| g[j].abs()).fold(0.0, f64::max);
+        let mut q: Vec<f64> = (0..n).map(|j| if free[j] { g[j] } else { 0.0 }).collect();
+        let mut alphas = Vec::with_capacity(memory.len());
+        for (s, y) in memory.iter().rev() {
+            let alpha = masked_dot(s, &q, &free) / masked_dot(y, s, &free);
+            for j in 0..n {
+                q[j] -= alpha * y[j];
+            }
+            alphas.push(alpha);
+        }
+        let gamma = match memory.last() {
+            Some((s, y)) => masked_dot(s, y, &free) / masked_dot(y, y, &free),
+            None => scale,
+        };
+        let mut r: Vec<f64> = q.iter().map(|q| gamma * q).collect();
+        for ((s, y), alpha) in memory.iter().zip(alphas.iter().rev()) {
+            let beta = masked_dot(y, &r, &free) / masked_dot(y, s, &free);
+            for j in 0..n {
+                r[j] += s[j] * (alpha - beta);
+            }
+        }
+        let mut direction: Vec<f64> = (0..n).map(|j| if free[j] { -r[j] } else { 0.0 }).collect();
+        let slope = masked_dot(&direction, &g, &free);
+        if (slope >= 0.0) | slope.is_nan() | direction.iter().any(|d| !d.is_finite()) {
+            // Not a descent direction, fall back to steepest descent.
+            direction = (0..n)
+                .map(|j| if free[j] { -scale * g[j] } else { 0.0 })
+                .collect();
+            memory.clear();
+        }
+        let mut step = 1.0;
+        let mut accepted = None;
+        for _ in 0..40 {
+            let candidate = project((0..n).map(|j| x[j] + (step * direction[j])).collect());
+            let (f_candidate, g_candidate) = f(&candidate);
+            convergence.evaluations += 1;
+            let decrease: f64 = (0..n).map(|j| g[j] * (candidate[j] - x[j])).sum();
+            if f_candidate <= fx + (1e-4 * decrease) {
+                accepted = Some((candidate, f_candidate, g_candidate));
+                break;
+            }
+            step *= 0.5;
+        }
+        let Some((x_new, f_new, g_new)) = accepted else {
+            // No decrease along the direction, so x is as close to a minimum
+            // as the objective can resolve.
+            convergence.converged = true;
+            break;
+        };
+        let s: Vec<f64> = (0..n).map(|j| x_new[j] - x[j]).collect();
+        let y: Vec<f64> = (0..n).map(|j| g_new[j] - g[j]).collect();
+        let curvature: f64 = s.iter().zip(&y).map(|(s, y)| s * y).sum();
+        if curvature > 1e-10 * y.iter().map(|y| y * y).sum::<f64>() {
+            if memory.len() == 10 {
+                memory.remove(0);
+            }
+            memory.push((s, y));
+        }
+        let relative = (fx - f_new) / fx.abs().max(1.0);
+        (x, fx, g) = (x_new, f_new, g_new);
+        convergence.history.push(fx);
+        convergence.gradient_norm = projected_gradient(&x, &g);
+        if relative <= tolerance {
+            convergence.converged = true;
+            break;
+        }
+    }
+    return (x, fx, convergence);
+}
+
+/// Distribution of observations around the predicted infectious series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Likelihood {
+    /// Gaussian noise with standard deviation [`Fit::sigma`].
+    Gaussian,
+    /// Poisson counts with mean [`Fit::scale`] times the predicted fraction.
+    Poisson,
+    /// Negative binomial counts with mean `μ`, [`Fit::scale`] times the
+    /// predicted fraction, and variance `μ + μ² / k`. The dispersion `k` is
+    /// estimated, see [`Fit::dispersion`].
+    NegativeBinomial,
+}
+
+/// Fit SIR incidence and removal rates to an observed infectious series, or
+/// those of a pipeline to its reports.
+pub struct Fit {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Standard deviation of the observation noise.
+    pub sigma: f64,
+    /// Observed infectious population fraction at each unit time, or counts
+    /// for count likelihoods.
+    pub observed: Vec<Observation>,
+    /// Distribution of the observations. Gaussian by default.
+    pub likelihood: Likelihood,
+    /// Expected count per unit of infectious population fraction, for count
+    /// likelihoods. For example the population size times the fraction of
+    /// infections reported.
+    pub scale: f64,
+    /// Negative binomial dispersion at the estimate. Maximizes the
+    /// likelihood at every evaluation, so it is estimated jointly with the
+    /// rates. NaN for other likelihoods.
+    pub dispersion: f64,
+    /// Transition rate from S into I. The initial guess, and after fitting
+    /// the estimate.
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. The initial guess, and after fitting
+    /// the estimate.
+    pub removal_rate: f64,
+    /// Changepoints in the incidence rate as `(t, incidence_rate)`. Held
+    /// fixed by [`Fit::run_nelder_mead`], estimated by
+    /// [`Fit::run_changepoints`].
+    pub changepoints: Vec<(f64, f64)>,
+    /// Log-likelihood at the estimate.
+    pub log_likelihood: f64,
+    /// Number of optimizer iterations used.
+    pub iterations: usize,
+    /// Convergence diagnostics of [`Fit::run_lbfgs`]. None after other
+    /// optimizers.
+    pub convergence: Option<Convergence>,
+    /// Builds the pipeline fitted in place of the SIR model, if set, see
+    /// [`Fit::pipeline`].
+    pub pipeline: Option<Box<dyn Fn() -> Pipeline + Send + Sync>>,
+}
+
+/// Profile likelihood of one fitted parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Name of the profiled parameter.
+    pub parameter: String,
+    /// Values of the parameter at which the profile was computed, ascending.
+    pub values: Vec<f64>,
+    /// Log-likelihood maximized over the other parameters at each value.
+    pub log_likelihood: Vec<f64>,
+    /// Lower confidence limit. Negative infinity if the profile does not
+    /// fall below the threshold within the grid, a sign the parameter is not
+    /// identifiable from the data.
+    pub lower: f64,
+    /// Upper confidence limit. Infinity if the profile does not fall below
+    /// the threshold within the grid.
+    pub upper: f64,
+}
+
+impl Fit {
+    /// Create a new fit object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            sigma: 0.0,
+            observed: Vec::new(),
+            likelihood: Likelihood::Gaussian,
+            scale: 1.0,
+            dispersion: f64::NAN,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            changepoints: Vec::new(),
+            log_likelihood: f64::NEG_INFINITY,
+            iterations: 0,
+            convergence: None,
+            pipeline: None,
+        };
+    }
+
+    /// Configure the fit, with Gaussian observation noise. `incidence_rate`
+    /// and `removal_rate` are the initial guesses, stored per day.
+    pub fn configure(
+        &mut self,
+        step_size: f64,
+        i_popf_init: f64,
+        sigma: f64,
+        observed: Vec<Observation>,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+    ) -> &mut Self {
+        self.length = observed.len();
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.sigma = sigma;
+        self.observed = observed;
+        self.likelihood = Likelihood::Gaussian;
+        self.scale = 1.0;
+        self.dispersion = f64::NAN;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.changepoints = Vec::new();
+        return self;
+    }
+
+    /// Set the distribution of the observations. For count likelihoods,
+    /// observations are counts with mean `scale` times the predicted
+    /// infectious fraction.
+    pub fn likelihood(&mut self, likelihood: Likelihood, scale: f64) -> &mut Self {
+        assert!(scale > 0.0, "scale must be positive, got {}", scale);
+        self.likelihood = likelihood;
+        self.scale = scale;
+        return self;
+    }
+
+    /// Fit a pipeline built by `build` in place of the SIR model.
+    ///
+    /// The system's `incidence_rate`, with any changepoints, and its
+    /// `removal_rate` are set at every time before the pipeline's own
+    /// interventions apply, and the pipeline is solved over the observed
+    /// series with the fit's step size. Observations are then the pipeline's
+    /// expected reports over each unit time, from its observation layer, and
+    /// its initial state replaces `i_popf_init`. A fresh pipeline is built
+    /// for every evaluation, so fits may be shared across threads, as by
+    /// [`crate::mcmc::sample_fit`]. [`Fit::run_lbfgs`] needs the SIR model's
+    /// sensitivities, so is not available.
+    pub fn pipeline(&mut self, build: impl Fn() -> Pipeline + Send + Sync + 'static) -> &mut Self {
+        let parameters = build().system.parameters();
+        for name in ["incidence_rate", "removal_rate"] {
+            assert!(
+                parameters.contains_key(name),
+                "pipeline system must have parameter {}",
+                name
+            );
+        }
+        self.pipeline = Some(Box::new(build));
+        return self;
+    }
+
+    /// Set known changepoints in the incidence rate as `(t, incidence_rate)`.
+    pub fn changepoints(&mut self, changepoints: Vec<(f64, Rate)>) -> &mut Self {
+        self.changepoints = changepoints
+            .into_iter()
+            .map(|(t, rate)| (t, rate.per(TimeUnit::Day)))
+            .collect();
+        return self;
+    }
+
+    /// Solve the SIR model for the given rates and incidence rate
+    /// changepoints.
+    pub fn simulate(
+        &self,
+        incidence_rate: f64,
+        removal_rate: f64,
+        changepoints: &[(f64, f64)],
+    ) -> sir::Model {
+        let mut model = self.model(incidence_rate, removal_rate, changepoints);
+        model.init_popf();
+        model.run_rk4();
+        return model;
+    }
+
+    /// The SIR model for the given rates and incidence rate changepoints,
+    /// configured but not solved.
+    fn model(
+        &self,
+        incidence_rate: f64,
+        removal_rate: f64,
+        changepoints: &[(f64, f64)],
+    ) -> sir::Model {
+        let mut model = sir::Model::new();
+        model.configure(
+            self.length,
+            self.step_size,
+            self.i_popf_init,
+            0.0,
+            Rate::per_day(incidence_rate),
+            Rate::per_day(removal_rate),
+            Rate::per_day(0.0),
+        );
+        model.changepoints(
+            changepoints
+                .iter()
+                .map(|(t, rate)| (*t, Rate::per_day(*rate)))
+                .collect(),
+        );
+        return model;
+    }
+
+    /// The pipeline from [`Fit::pipeline`] with the given rates and
+    /// incidence rate changepoints, solved.
+    fn solve_pipeline(
+        &self,
+        build: &dyn Fn() -> Pipeline,
+        incidence_rate: f64,
+        removal_rate: f64,
+        changepoints: &[(f64, f64)],
+    ) -> Pipeline {
+        let mut pipeline = build();
+        let mut interventions: Vec<Box<dyn Intervention>> = vec![
+            Box::new(ScheduleParameter::new(
+                "incidence_rate",
+                RateSchedule::new(incidence_rate, changepoints.to_vec()),
+            )),
+            Box::new(ScheduleParameter::new(
+                "removal_rate",
+                RateSchedule::constant(removal_rate),
+            )),
+        ];
+        interventions.append(&mut pipeline.interventions);
+        pipeline.interventions = interventions;
+        pipeline.configure(self.length + 1, self.step_size);
+        pipeline.run_rk4();
+        return pipeline;
+    }
+
+    /// The series observations are compared with for the given rates and
+    /// incidence rate changepoints, and the time between its rows: the
+    /// infectious fraction at every step, or the expected reports over each
+    /// unit time of a pipeline.
+    fn predicted(
+        &self,
+        incidence_rate: f64,
+        removal_rate: f64,
+        changepoints: &[(f64, f64)],
+    ) -> (Mat<f64>, f64) {
+        if let Some(build) = &self.pipeline {
+            let pipeline = self.solve_pipeline(build, incidence_rate, removal_rate, changepoints);
+            return (pipeline.expected_reports(), 1.0);
+        }
+        let model = self.simulate(incidence_rate, removal_rate, changepoints);
+        return (model.i_popf, self.step_size);
+    }
+
+    /// Log-likelihood of the observations for the given rates and incidence
+    /// rate changepoints.
+    pub fn log_likelihood_at(
+        &self,
+        incidence_rate: f64,
+        removal_rate: f64,
+        changepoints: &[(f64, f64)],
+    ) -> f64 {
+        let (predicted, spacing) = self.predicted(incidence_rate, removal_rate, changepoints);
+        return self
+            .series_log_likelihood(predicted.nrows(), spacing, |k| predicted[(k, 0)])
+            .0;
+    }
+
+    /// Each observation and the index of its time, for the first `n_rows`
+    /// indices `spacing` apart.
+    fn matched(&self, n_rows: usize, spacing: f64) -> Vec<(Observation, usize)> {
+        return self
+            .observed
+            .iter()
+            .enumerate()
+            .map(|(t, observation)| (*observation, ((t as f64) / spacing).round() as usize))
+            .take_while(|(_, index)| *index < n_rows)
+            .collect();
+    }
+
+    /// Log-likelihood of the observations given the predicted series at each
+    /// of `n_rows` indices `spacing` apart, and the dispersion maximizing it
+    /// for the negative binomial likelihood.
+    fn series_log_likelihood(
+        &self,
+        n_rows: usize,
+        spacing: f64,
+        predicted: impl Fn(usize) -> f64,
+    ) -> (f64, f64) {
+        let matched = self.matched(n_rows, spacing);
+        let counts = |dispersion: Option<f64>| -> f64 {
+            return matched
+                .iter()
+                .map(|(o, k)| count_log_likelihood(*o, self.scale * predicted(*k), dispersion))
+                .sum();
+        };
+        match self.likelihood {
+            Likelihood::Gaussian => {
+                let ll = matched
+                    .iter()
+                    .map(|(o, k)| observation_log_likelihood(*o, predicted(*k), self.sigma))
+                    .sum();
+                return (ll, f64::NAN);
+            }
+            Likelihood::Poisson => return (counts(None), f64::NAN),
+            Likelihood::NegativeBinomial => {
+                let log_k = golden_section(|x| counts(Some(exp(x))), ln(1e-3), ln(1e6), 80);
+                return (counts(Some(exp(log_k))), exp(log_k));
+            }
+        }
+    }
+
+    /// Derivative of the log-likelihood of `observation` with respect to the
+    /// predicted infectious fraction.
+    fn score(&self, observation: Observation, predicted: f64, dispersion: f64) -> f64 {
+        match self.likelihood {
+            Likelihood::Gaussian => return observation_score(observation, predicted, self.sigma),
+            Likelihood::Poisson => {
+                return self.scale * count_terms(observation, self.scale * predicted, None).1;
+            }
+            Likelihood::NegativeBinomial => {
+                let expected = self.scale * predicted;
+                return self.scale * count_terms(observation, expected, Some(dispersion)).1;
+            }
+        }
+    }
+
+    /// Set the dispersion at the current estimate.
+    fn update_dispersion(&mut self) {
+        let (predicted, spacing) =
+            self.predicted(self.incidence_rate, self.removal_rate, &self.changepoints);
+        self.dispersion = self
+            .series_log_likelihood(predicted.nrows(), spacing, |k| predicted[(k, 0)])
+            .1;
+    }
+
+    /// Decode optimizer coordinates into changepoints. Each changepoint is a
+    /// pair of coordinates: the time on the logit scale of `[0, length]`, and
+    /// the incidence rate on the log scale.
+    fn decode_changepoints(&self, x: &[f64]) -> Vec<(f64, f64)> {
+        let mut changepoints: Vec<(f64, f64)> = x
+            .chunks(2)
+            .map(|c| {
+                let t = (self.length as f64) / (1.0 + exp(-c[0]));
+                (t, exp(c[1]))
+            })
+            .collect();
+        changepoints.sort_by(|a, b| a.0.total_cmp(&b.0));
+        return changepoints;
+    }
+
+    /// Estimate the rates by maximum likelihood. Rates are optimized on the
+    /// log scale so they stay positive.
+    pub fn run_nelder_mead(&mut self, max_iter: usize) -> &Fit {
+        let x0 = [ln(self.incidence_rate), ln(self.removal_rate)];
+        let (x, nll, iterations) = nelder_mead(
+            |x| -self.log_likelihood_at(exp(x[0]), exp(x[1]), &self.changepoints),
+            &x0,
+            0.5,
+            max_iter,
+            1e-10,
+        );
+        self.incidence_rate = exp(x[0]);
+        self.removal_rate = exp(x[1]);
+        self.log_likelihood = -nll;
+        self.iterations = iterations;
+        self.convergence = None;
+        self.update_dispersion();
+        return self;
+    }
+
+    /// Log-likelihood of the observations for the given rates, and its
+    /// gradient with respect to them from the forward sensitivities of I.
+    pub fn log_likelihood_gradient(
+        &self,
+        incidence_rate: f64,
+        removal_rate: f64,
+    ) -> (f64, [f64; 2]) {
+        assert!(
+            self.pipeline.is_none(),
+            "gradients need the SIR model, not a pipeline"
+        );
+        let result =
+            forward::sensitivities(&self.model(incidence_rate, removal_rate, &self.changepoints));
+        let series = [
+            result.series("i", "incidence_rate"),
+            result.series("i", "removal_rate"),
+        ];
+        let n_rows = result.state.nrows();
+        // The dispersion maximizes the likelihood, so its own change does not
+        // contribute to the gradient.
+        let (ll, dispersion) =
+            self.series_log_likelihood(n_rows, self.step_size, |k| result.state[(k, 1)]);
+        let mut gradient = [0.0; 2];
+        for (observation, index) in self.matched(n_rows, self.step_size) {
+            let score = self.score(observation, result.state[(index, 1)], dispersion);
+            for p in 0..2 {
+                gradient[p] += score * series[p][index];
+            }
+        }
+        return (ll, gradient);
+    }
+
+    /// Estimate the rates by maximum likelihood with [`lbfgs`], using exact
+    /// gradients rather than a derivative-free search. Rates are bounded to
+    /// `[1e-6, 10]`, and changepoints held fixed. Diagnostics are kept in
+    /// [`Fit::convergence`].
+    pub fn run_lbfgs(&mut self, max_iter: usize) -> &Fit {
+        let (x, nll, convergence) = lbfgs(
+            |x| {
+                let (ll, gradient) = self.log_likelihood_gradient(x[0], x[1]);
+                return (-ll, vec![-gradient[0], -gradient[1]]);
+            },
+            &[self.incidence_rate, self.removal_rate],
+            &[1e-6, 1e-6],
+            &[10.0, 10.0],
+            max_iter,
+            1e-12,
+        );
+        self.incidence_rate = x[0];
+        self.removal_rate = x[1];
+        self.log_likelihood = -nll;
+        self.iterations = convergence.iterations;
+        self.convergence = Some(convergence);
+        self.update_dispersion();
+        return self;
+    }
+
+    /// Estimate `n_changepoints` incidence rate changepoints, their times and
+    /// values, jointly with the initial incidence rate and the removal rate.
+    ///
+    /// Changepoints start evenly spaced over the series at the initial
+    /// incidence rate. The optimizer is restarted from its best point
+    /// `restarts` times, which helps it escape the flat regions created by
+    /// changepoint times falling between integration steps.
+    pub fn run_changepoints(
+        &mut self,
+        n_changepoints: usize,
+        restarts: usize,
+        max_iter: usize,
+    ) -> &Fit {
+        let mut x = vec![ln(self.incidence_rate), ln(self.removal_rate)];
+        for k in 0..n_changepoints {
+            let p = ((k + 1) as f64) / ((n_changepoints + 1) as f64);
+            x.push(ln(p / (1.0 - p)));
+            x.push(ln(self.incidence_rate));
+        }
+        let objective = |x: &[f64]| -> f64 {
+            let changepoints = self.decode_changepoints(&x[2..]);
+            return -self.log_likelihood_at(exp(x[0]), exp(x[1]), &changepoints);
+        };
+        let mut nll = f64::INFINITY;
+        let mut iterations = 0;
+        for _ in 0..=restarts {
+            let (x_next, nll_next, used) = nelder_mead(objective, &x, 0.5, max_iter, 1e-10);
+            iterations += used;
+            x = x_next;
+            nll = nll_next;
+        }
+        self.incidence_rate = exp(x[0]);
+        self.removal_rate = exp(x[1]);
+        self.changepoints = self.decode_changepoints(&x[2..]);
+        self.log_likelihood = -nll;
+        self.iterations = iterations;
+        self.convergence = None;
+        self.update_dispersion();
+        return self;
+    }
+
+    /// Estimate the incidence rate as a random walk on knots every
+    /// `knot_spacing` time units, jointly with the removal rate.
+    ///
+    /// The negative log-likelihood is penalized by `penalty` times the sum of
+    /// squared differences of log incidence rate between consecutive knots,
+    /// so larger penalties give smoother reconstructions. The fitted knots
+    /// are stored in `changepoints`, with the first knot's rate in
+    /// `incidence_rate`.
+    pub fn run_random_walk(
+        &mut self,
+        knot_spacing: f64,
+        penalty: f64,
+        restarts: usize,
+        max_iter: usize,
+    ) -> &Fit {
+        let n_knots = ((self.length as f64) / knot_spacing).ceil().max(1.0) as usize;
+        let knots: Vec<f64> = (1..n_knots).map(|k| (k as f64) * knot_spacing).collect();
+        let mut x = vec![ln(self.removal_rate)];
+        x.extend(std::iter::repeat_n(ln(self.incidence_rate), n_knots));
+        let objective = |x: &[f64]| -> f64 {
+            let changepoints: Vec<(f64, f64)> = knots
+                .iter()
+                .zip(x[2..].iter())
+                .map(|(t, log_rate)| (*t, exp(*log_rate)))
+                .collect();
+            let roughness: f64 = x[1..].windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+            return -self.log_likelihood_at(exp(x[1]), exp(x[0]), &changepoints)
+                + (penalty * roughness);
+        };
+        let mut objective_value = f64::INFINITY;
+        let mut iterations = 0;
+        for _ in 0..=restarts {
+            let (x_next, value, used) = nelder_mead(objective, &x, 0.5, max_iter, 1e-10);
+            iterations += used;
+            x = x_next;
+            objective_value = value;
+        }
+        let roughness: f64 = x[1..].windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        self.removal_rate = exp(x[0]);
+        self.incidence_rate = exp(x[1]);
+        self.changepoints = knots
+            .iter()
+            .zip(x[2..].iter())
+            .map(|(t, log_rate)| (*t, exp(*log_rate)))
+            .collect();
+        self.log_likelihood = -(objective_value - (penalty * roughness));
+        self.iterations = iterations;
+        self.convergence = None;
+        self.update_dispersion();
+        return self;
+    }
+
+    /// Profile likelihood of `parameter`, `incidence_rate` or
+    /// `removal_rate`, over `grid`, refitting the other rate at each value.
+    ///
+    /// The confidence interval at `level` is where the profile lies within
+    /// half the chi-square(1) quantile of the best log-likelihood found,
+    /// interpolated linearly between grid values. Call after fitting, so the
+    /// refits start from the estimate.
+    pub fn profile(&self, parameter: &str, grid: &[f64], level: f64, max_iter: usize) -> Profile {
+        let profiled_incidence = match parameter {
+            "incidence_rate" => true,
+            "removal_rate" => false,
+            other => panic!(
+                "cannot profile {}, expected incidence_rate or removal_rate",
+                other
+            ),
+        };
+        let mut values = grid.to_vec();
+        values.sort_by(|a, b| a.total_cmp(b));
+        let nuisance = if profiled_incidence {
+            self.removal_rate
+        } else {
+            self.incidence_rate
+        };
+        let log_likelihood: Vec<f64> = values
+            .iter()
+            .map(|&value| {
+                let objective = |x: &[f64]| -> f64 {
+                    let (incidence_rate, removal_rate) = if profiled_incidence {
+                        (value, exp(x[0]))
+                    } else {
+                        (exp(x[0]), value)
+                    };
+                    return -self.log_likelihood_at(
+                        incidence_rate,
+                        removal_rate,
+                        &self.changepoints,
+                    );
+                };
+                let (_, nll, _) = nelder_mead(objective, &[ln(nuisance)], 0.5, max_iter, 1e-10);
+                return -nll;
+            })
+            .collect();
+        let best = log_likelihood
+            .iter()
+            .cloned()
+            .fold(self.log_likelihood, f64::max);
+        let z = normal_quantile(0.5 * (1.0 + level));
+        let threshold = best - (0.5 * z * z);
+        let inside: Vec<usize> = (0..values.len())
+            .filter(|&k| log_likelihood[k] >= threshold)
+            .collect();
+        let crossing = |a: usize, b: usize| {
+            let w = (threshold - log_likelihood[a]) / (log_likelihood[b] - log_likelihood[a]);
+            return values[a] + (w * (values[b] - values[a]));
+        };
+        let (lower, upper) = match (inside.first(), inside.last()) {
+            (Some(&first), Some(&last)) => (
+                if first == 0 {
+                    f64::NEG_INFINITY
+                } else {
+                    crossing(first - 1, first)
+                },
+                if last == values.len() - 1 {
+                    f64::INFINITY
+                } else {
+                    crossing(last + 1, last)
+                },
+            ),
+            _ => (f64::NAN, f64::NAN),
+        };
+        return Profile {
+            parameter: parameter.to_string(),
+            values,
+            log_likelihood,
+            lower,
+            upper,
+        };
+    }
+
+    /// Profiles of both rates on geometric grids of `n_points` values from
+    /// the estimate divided by `span` to the estimate times `span`.
+    pub fn profiles(
+        &self,
+        n_points: usize,
+        span: f64,
+        level: f64,
+        max_iter: usize,
+    ) -> Vec<Profile> {
+        assert!(n_points >= 2, "n_points must be at least 2");
+        let grid = |estimate: f64| -> Vec<f64> {
+            return (0..n_points)
+                .map(|k| {
+                    let u = (2.0 * (k as f64) / ((n_points - 1) as f64)) - 1.0;
+                    return estimate * exp(u * ln(span));
+                })
+                .collect();
+        };
+        return vec![
+            self.profile(
+                "incidence_rate",
+                &grid(self.incidence_rate),
+                level,
+                max_iter,
+            ),
+            self.profile("removal_rate", &grid(self.removal_rate), level, max_iter),
+        ];
+    }
+
+    /// Fitted incidence rate at each unit time. Suitable for seasonal and
+    /// trend decomposition with [`crate::decompose::decompose`].
+    pub fn incidence_rate_series(&self) -> Mat<f64> {
+        let mut model = sir::Model::new();
+        model.incidence_rate = self.incidence_rate;
+        model.incidence_rate_changes = self.changepoints.clone();
+        return Mat::from_fn(self.length, 1, |t, _| model.incidence_rate_at(t as f64));
+    }
+}
+
+/// Fit the assortativity of age-structured mixing, and the incidence rate, to
+/// age-stratified case counts.
+///
+/// The contact matrix is built by [`assortative_contacts`] from each group's
+/// contact activity, so only the split between within-group and shared
+/// contacts is estimated. Counts are Poisson around the predicted number of
+/// new infections in each group over each unit time.
+pub struct MixingFit {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Total population size, converting population fractions into counts.
+    pub total_population: f64,
+    /// Population fraction in each age group. Column with one row per group.
+    pub population: Mat<f64>,
+    /// Contacts per unit time made by each age group. Column with one row
+    /// per group.
+    pub activity: Mat<f64>,
+    /// Initial infectious fraction within each age group.
+    pub i_init: f64,
+    /// Transition rate from I into R, held fixed.
+    pub removal_rate: f64,
+    /// Observed new cases in each age group (column) over each unit time
+    /// from t (row) to t + 1. NaN counts are missing.
+    pub cases: Mat<f64>,
+    /// Probability of transmission per contact. The initial guess, and after
+    /// fitting the estimate.
+    pub incidence_rate: f64,
+    /// Fraction of contacts reserved for the own age group. The initial
+    /// guess, and after fitting the estimate. Must be in (0, 1).
+    pub assortativity: f64,
+    /// Log-likelihood at the estimate.
+    pub log_likelihood: f64,
+    /// Number of optimizer iterations used.
+    pub iterations: usize,
+}
+
+impl MixingFit {
+    /// Create a new fit object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            total_population: 0.0,
+            population: Mat::new(),
+            activity: Mat::new(),
+            i_init: 0.0,
+            removal_rate: 0.0,
+            cases: Mat::new(),
+            incidence_rate: 0.0,
+            assortativity: 0.0,
+            log_likelihood: f64::NEG_INFINITY,
+            iterations: 0,
+        };
+    }
+
+    /// Configure the fit. `incidence_rate` and `assortativity` are the
+    /// initial guesses.
+    pub fn configure(
+        &mut self,
+        step_size: f64,
+        total_population: f64,
+        population: Mat<f64>,
+        activity: Mat<f64>,
+        i_init: f64,
+        removal_rate: Rate,
+        cases: Mat<f64>,
+        incidence_rate: Fraction,
+        assortativity: Fraction,
+    ) -> &mut Self {
+        assert_eq!(
+            cases.ncols(),
+            population.nrows(),
+            "cases must have one column per group"
+        );
+        self.length = cases.nrows() + 1;
+        self.step_size = step_size;
+        self.total_population = total_population;
+        self.population = population;
+        self.activity = activity;
+        self.i_init = i_init;
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.cases = cases;
+        self.incidence_rate = incidence_rate.value();
+        self.assortativity = assortativity.value();
+        return self;
+    }
+
+    /// Solve the age-structured model for the given incidence rate and
+    /// assortativity.
+    pub fn simulate(&self, incidence_rate: f64, assortativity: f64) -> age::Model {
+        let contacts = assortative_contacts(&self.population, &self.activity, assortativity);
+        let mut model = age::Model::new();
+        model.configure(
+            self.length,
+            self.step_size,
+            self.population.clone(),
+            contacts,
+            self.i_init,
+            Fraction::of(incidence_rate),
+            Rate::per_day(self.removal_rate),
+            Fraction::of(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        return model;
+    }
+
+    /// Predicted new cases in each age group (column) over each unit time
+    /// (row), the fall in susceptible and vaccinated counts.
+    pub fn predicted_cases(&self, model: &age::Model) -> Mat<f64> {
+        let index = |t: usize| ((t as f64) / self.step_size).round() as usize;
+        return Mat::from_fn(self.cases.nrows(), self.cases.ncols(), |t, g| {
+            let before = model.s_popf[(index(t), g)] + model.v_popf[(index(t), g)];
+            let after = model.s_popf[(index(t + 1), g)] + model.v_popf[(index(t + 1), g)];
+            self.total_population * (before - after)
+        });
+    }
+
+    /// Log-likelihood of the case counts for the given incidence rate and
+    /// assortativity.
+    pub fn log_likelihood_at(&self, incidence_rate: f64, assortativity: f64) -> f64 {
+        let predicted = self.predicted_cases(&self.simulate(incidence_rate, assortativity));
+        let mut ll = 0.0;
+        for t in 0..self.cases.nrows() {
+            for g in 0..self.cases.ncols() {
+                let cases = Observation::Value(self.cases[(t, g)]);
+                ll += count_log_likelihood(cases, predicted[(t, g)], None);
+            }
+        }
+        return ll;
+    }
+
+    /// Estimate the incidence rate and assortativity by maximum likelihood.
+    /// Both are probabilities and are optimized on the logit scale.
+    pub fn run_nelder_mead(&mut self, max_iter: usize) -> &MixingFit {
+        let logistic = |x: f64| 1.0 / (1.0 + exp(-x));
+        let x0 = [
+            ln(self.incidence_rate / (1.0 - self.incidence_rate)),
+            ln(self.assortativity / (1.0 - self.assortativity)),
+        ];
+        let (x, nll, iterations) = nelder_mead(
+            |x| -self.log_likelihood_at(logistic(x[0]), logistic(x[1])),
+            &x0,
+            0.5,
+            max_iter,
+            1e-10,
+        );
+        self.incidence_rate = logistic(x[0]);
+        self.assortativity = logistic(x[1]);
+        self.log_likelihood = -nll;
+        self.iterations = iterations;
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::fit::{
+        Fit, Likelihood, MixingFit, Observation, count_log_likelihood, lbfgs, log_likelihood,
+        nelder_mead, normal_cdf, observation_log_likelihood,
+    };
+    use crate::sirrs::hospital;
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::pipeline::{Pipeline, ScaleParameter};
+    use crate::sirrs::rng;
+    use crate::sirrs::units::{Duration, Fraction, Rate};
+    use faer::{Mat, mat};
+    use rand::Rng;
+    use rand_distr::{Distribution, Gamma, Poisson, StandardNormal};
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn test_from_dataframe() {
+        use crate::sirrs::fit::from_dataframe;
+        use polars::prelude::{Column, DataFrame};
+        let df = DataFrame::new(vec![
+            Column::new("day".into(), [3i64, 0, 1]),
+            Column::new("i".into(), [Some(0.04), Some(0.01), None]),
+        ])
+        .unwrap();
+        let observed = from_dataframe(&df, "day", "i").unwrap();
+        assert_eq!(
+            observed,
+            vec![
+                Observation::Value(0.01),
+                Observation::Missing,
+                Observation::Missing,
+                Observation::Value(0.04)
+            ],
+            "Bad observations, got {:?}",
+            observed
+        );
+        let repeated = DataFrame::new(vec![
+            Column::new("day".into(), [0i64, 0]),
+            Column::new("i".into(), [0.01, 0.02]),
+        ])
+        .unwrap();
+        assert!(
+            from_dataframe(&repeated, "day", "i").is_err(),
+            "Expected error on a repeated time"
+        );
+    }
+
+    #[test]
+    fn test_normal_cdf() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.959964) - 0.975).abs() < 1e-6);
+        assert!((normal_cdf(-1.959964) - 0.025).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_missing_observations_ignored() {
+        let predicted = mat![[0.1], [0.2], [0.3]];
+        let full = log_likelihood(
+            &[
+                Observation::Value(0.1),
+                Observation::Value(0.2),
+                Observation::Value(0.3),
+            ],
+            &predicted,
+            1.0,
+            0.1,
+        );
+        let partial = log_likelihood(
+            &[
+                Observation::Value(0.1),
+                Observation::Missing,
+                Observation::Value(f64::NAN),
+            ],
+            &predicted,
+            1.0,
+            0.1,
+        );
+        assert!(
+            (partial - (full / 3.0)).abs() < 1e-12,
+            "Bad partial log-likelihood, expected {} got {}",
+            full / 3.0,
+            partial
+        );
+    }
+
+    #[test]
+    fn test_interval_observation() {
+        let inside = observation_log_likelihood(Observation::Interval(0.0, 1.0), 0.5, 0.01);
+        let outside = observation_log_likelihood(Observation::Interval(0.0, 1.0), 2.0, 0.01);
+        let censored =
+            observation_log_likelihood(Observation::Interval(f64::NEG_INFINITY, 0.0), 0.0, 0.01);
+        assert!(inside.abs() < 1e-9, "Bad inside interval, got {}", inside);
+        assert!(outside < -100.0, "Bad outside interval, got {}", outside);
+        assert!(
+            (censored - 0.5_f64.ln()).abs() < 1e-6,
+            "Bad censored observation, got {}",
+            censored
+        );
+    }
+
+    #[test]
+    fn test_count_log_likelihood() {
+        let ll = count_log_likelihood(Observation::Value(3.0), 2.0, None);
+        let expected = (8.0f64 * (-2.0f64).exp() / 6.0).ln();
+        assert!(
+            (ll - expected).abs() < 1e-12,
+            "Bad Poisson log-likelihood, expected {} got {}",
+            expected,
+            ll
+        );
+        let ll = count_log_likelihood(Observation::Value(3.0), 2.0, Some(1e6));
+        assert!(
+            (ll - expected).abs() < 1e-4,
+            "Expected the Poisson limit for large dispersion, expected {} got {}",
+            expected,
+            ll
+        );
+        // With dispersion 1 counts are geometric, P(c) = p (1 - p)^c.
+        let ll = count_log_likelihood(Observation::Value(3.0), 2.0, Some(1.0));
+        let expected = ((1.0f64 / 3.0) * (2.0f64 / 3.0).powi(3)).ln();
+        assert!(
+            (ll - expected).abs() < 1e-12,
+            "Bad negative binomial log-likelihood, expected {} got {}",
+            expected,
+            ll
+        );
+        let below = count_log_likelihood(Observation::Interval(f64::NEG_INFINITY, 1.5), 2.0, None);
+        let above = count_log_likelihood(Observation::Interval(2.0, f64::INFINITY), 2.0, None);
+        assert!(
+            (below.exp() + above.exp() - 1.0).abs() < 1e-12,
+            "Expected complementary intervals to sum to 1, got {}",
+            below.exp() + above.exp()
+        );
+        assert_eq!(
+            count_log_likelihood(Observation::Value(f64::NAN), 2.0, Some(1.0)),
+            0.0,
+            "Expected a NaN count to be ignored"
+        );
+    }
+
+    #[test]
+    fn test_nelder_mead() {
+        let (x, fx, _) = nelder_mead(
+            |x| ((x[0] - 1.0).powi(2)) + (10.0 * (x[1] + 2.0).powi(2)),
+            &[0.0, 0.0],
+            1.0,
+            500,
+            1e-14,
+        );
+        assert!((x[0] - 1.0).abs() < 1e-4, "Bad x[0], got {}", x[0]);
+        assert!((x[1] + 2.0).abs() < 1e-4, "Bad x[1], got {}", x[1]);
+        assert!(fx < 1e-8, "Bad minimum, got {}", fx);
+    }
+
+    #[test]
+    fn test_fit_pipeline() {
+        let build = || {
+            let mut system = hospital::Model::new();
+            system.configure(
+                40,
+                0.5,
+                0.01,
+                Rate::per_day(0.0),
+                Rate::per_day(0.0),
+                Fraction::of(0.1),
+                Fraction::of(0.2),
+                Duration::days(7.0),
+                Duration::days(10.0),
+            );
+            let mut pipeline = Pipeline::new(Box::new(system));
+            pipeline.intervention(Box::new(ScaleParameter::new(
+                "incidence_rate",
+                15.0,
+                25.0,
+                0.5,
+            )));
+            let mut reporting = ReportingModel::new();
+            reporting.configure(Fraction::of(0.4), vec![1.0], None, 0);
+            pipeline.observation(reporting);
+            return pipeline;
+        };
+        let mut truth = Fit::new();
+        truth.configure(
+            0.5,
+            0.0,
+            1e-4,
+            vec![Observation::Missing; 40],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        truth.pipeline(build);
+        let (reports, _) = truth.predicted(0.5, 0.1, &[]);
+        assert_eq!(reports.nrows(), 40, "Bad number of predicted reports");
+        let observed: Vec<Observation> = (0..40)
+            .map(|t| Observation::Value(reports[(t, 0)]))
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            0.5,
+            0.0,
+            1e-4,
+            observed,
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+        );
+        fit.pipeline(build);
+        fit.run_nelder_mead(500);
+        assert!(
+            (fit.incidence_rate - 0.5).abs() < 1e-3,
+            "Bad incidence_rate estimate, expected 0.5 got {}",
+            fit.incidence_rate
+        );
+        assert!(
+            (fit.removal_rate - 0.1).abs() < 1e-3,
+            "Bad removal_rate estimate, expected 0.1 got {}",
+            fit.removal_rate
+        );
+    }
+
+    #[test]
+    fn test_fit_with_gaps() {
+        let mut truth = Fit::new();
+        truth.configure(
+            1.0,
+            0.01,
+            0.001,
+            vec![Observation::Missing; 60],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.4, 0.1, &[]);
+        let observed: Vec<Observation> = (0..60)
+            .map(|t| match t % 7 {
+                0 | 1 => Observation::Missing,
+                2 => {
+                    Observation::Interval(model.i_popf[(t, 0)] - 0.01, model.i_popf[(t, 0)] + 0.01)
+                }
+                _ => Observation::Value(model.i_popf[(t, 0)]),
+            })
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            1.0,
+            0.01,
+            0.001,
+            observed,
+            Rate::per_day(0.2),
+            Rate::per_day(0.2),
+        );
+        fit.run_nelder_mead(500);
+        assert!(
+            (fit.incidence_rate - 0.4).abs() < 1e-3,
+            "Bad incidence_rate estimate, expected 0.4 got {}",
+            fit.incidence_rate
+        );
+        assert!(
+            (fit.removal_rate - 0.1).abs() < 1e-3,
+            "Bad removal_rate estimate, expected 0.1 got {}",
+            fit.removal_rate
+        );
+    }
+
+    #[test]
+    fn test_lbfgs() {
+        let rosenbrock = |x: &[f64]| {
+            let value = (1.0 - x[0]).powi(2) + (100.0 * (x[1] - (x[0] * x[0])).powi(2));
+            let gradient = vec![
+                (-2.0 * (1.0 - x[0])) - (400.0 * x[0] * (x[1] - (x[0] * x[0]))),
+                200.0 * (x[1] - (x[0] * x[0])),
+            ];
+            return (value, gradient);
+        };
+        let (x, _, convergence) = lbfgs(
+            rosenbrock,
+            &[-1.2, 1.0],
+            &[-2.0, -2.0],
+            &[0.5, 2.0],
+            200,
+            1e-12,
+        );
+        assert!(
+            ((x[0] - 0.5).abs() < 1e-6) & ((x[1] - 0.25).abs() < 1e-6),
+            "Bad minimizer, expected [0.5, 0.25] got {:?}",
+            x
+        );
+        assert!(convergence.converged, "Expected convergence");
+        assert_eq!(
+            convergence.history.len(),
+            convergence.iterations + 1,
+            "Bad history length"
+        );
+    }
+
+    #[test]
+    fn test_fit_lbfgs() {
+        let mut truth = Fit::new();
+        truth.configure(
+            1.0,
+            0.01,
+            0.001,
+            vec![Observation::Missing; 60],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.4, 0.1, &[]);
+        let observed: Vec<Observation> = (0..60)
+            .map(|t| match t % 7 {
+                0 | 1 => Observation::Missing,
+                2 => {
+                    Observation::Interval(model.i_popf[(t, 0)] - 0.01, model.i_popf[(t, 0)] + 0.01)
+                }
+                _ => Observation::Value(model.i_popf[(t, 0)]),
+            })
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            1.0,
+            0.01,
+            0.001,
+            observed,
+            Rate::per_day(0.2),
+            Rate::per_day(0.2),
+        );
+        let (ll, gradient) = fit.log_likelihood_gradient(0.3, 0.15);
+        let delta = 1e-6;
+        for (p, expected) in [
+            (fit.log_likelihood_at(0.3 + delta, 0.15, &[])
+                - fit.log_likelihood_at(0.3 - delta, 0.15, &[]))
+                / (2.0 * delta),
+            (fit.log_likelihood_at(0.3, 0.15 + delta, &[])
+                - fit.log_likelihood_at(0.3, 0.15 - delta, &[]))
+                / (2.0 * delta),
+        ]
+        .iter()
+        .enumerate()
+        {
+            assert!(
+                (gradient[p] - expected).abs() < 1e-4 * expected.abs().max(1.0),
+                "Bad gradient, expected {} got {}",
+                expected,
+                gradient[p]
+            );
+        }
+        assert!(
+            (ll - fit.log_likelihood_at(0.3, 0.15, &[])).abs() < 1e-6,
+            "Bad log-likelihood"
+        );
+        fit.run_lbfgs(200);
+        assert!(
+            (fit.incidence_rate - 0.4).abs() < 1e-4,
+            "Bad incidence_rate estimate, expected 0.4 got {}",
+            fit.incidence_rate
+        );
+        assert!(
+            (fit.removal_rate - 0.1).abs() < 1e-4,
+            "Bad removal_rate estimate, expected 0.1 got {}",
+            fit.removal_rate
+        );
+        let convergence = fit.convergence.as_ref().unwrap();
+        assert!(convergence.converged, "Expected convergence");
+        assert!(
+            convergence.history.windows(2).all(|w| w[1] <= w[0]),
+            "Expected a non-increasing objective"
+        );
+    }
+
+    #[test]
+    fn test_fit_negative_binomial() {
+        let mut truth = Fit::new();
+        truth.configure(
+            1.0,
+            0.001,
+            0.0,
+            vec![Observation::Missing; 60],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.4, 0.1, &[]);
+        let mut rng = rng::rng(3);
+        let observed: Vec<Observation> = (0..60)
+            .map(|t| {
+                let mean = 1e4 * model.i_popf[(t, 0)];
+                let rate = Gamma::new(10.0, mean / 10.0).unwrap().sample(&mut rng);
+                Observation::Value(Poisson::new(rate).unwrap().sample(&mut rng))
+            })
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            1.0,
+            0.001,
+            0.0,
+            observed,
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+        )
+        .likelihood(Likelihood::NegativeBinomial, 1e4);
+        let (_, gradient) = fit.log_likelihood_gradient(0.35, 0.12);
+        let delta = 1e-6;
+        let expected = (fit.log_likelihood_at(0.35 + delta, 0.12, &[])
+            - fit.log_likelihood_at(0.35 - delta, 0.12, &[]))
+            / (2.0 * delta);
+        assert!(
+            (gradient[0] - expected).abs() < 1e-4 * expected.abs().max(1.0),
+            "Bad gradient, expected {} got {}",
+            expected,
+            gradient[0]
+        );
+        fit.run_nelder_mead(500);
+        assert!(
+            (fit.incidence_rate - 0.4).abs() < 0.02,
+            "Bad incidence_rate estimate, expected 0.4 got {}",
+            fit.incidence_rate
+        );
+        assert!(
+            (fit.removal_rate - 0.1).abs() < 0.02,
+            "Bad removal_rate estimate, expected 0.1 got {}",
+            fit.removal_rate
+        );
+        assert!(
+            (fit.dispersion > 4.0) & (fit.dispersion < 25.0),
+            "Bad dispersion estimate, expected about 10 got {}",
+            fit.dispersion
+        );
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut truth = Fit::new();
+        truth.configure(
+            1.0,
+            0.01,
+            0.01,
+            vec![Observation::Missing; 60],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.4, 0.1, &[]);
+        let mut rng = rng::rng(2);
+        let observed: Vec<Observation> = (0..60)
+            .map(|t| {
+                let noise: f64 = rng.sample(StandardNormal);
+                Observation::Value(model.i_popf[(t, 0)] + (0.01 * noise))
+            })
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            1.0,
+            0.01,
+            0.01,
+            observed,
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+        );
+        fit.run_nelder_mead(500);
+        let profiles = fit.profiles(41, 1.05, 0.95, 200);
+        for (profile, truth) in profiles.iter().zip([0.4, 0.1]) {
+            assert!(
+                (profile.lower < truth) & (truth < profile.upper),
+                "Expected {} interval to cover {}, got [{}, {}]",
+                profile.parameter,
+                truth,
+                profile.lower,
+                profile.upper
+            );
+            assert!(
+                profile.lower.is_finite() & profile.upper.is_finite(),
+                "Expected finite {} interval",
+                profile.parameter
+            );
+        }
+        // Early growth alone only identifies the difference of the rates.
+        let early: Vec<Observation> = (0..60)
+            .map(|t| {
+                if t < 4 {
+                    Observation::Value(model.i_popf[(t, 0)])
+                } else {
+                    Observation::Missing
+                }
+            })
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            1.0,
+            0.01,
+            0.002,
+            early,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        fit.run_nelder_mead(500);
+        let profile = fit.profile("removal_rate", &[0.05, 0.1, 0.15], 0.95, 200);
+        assert!(
+            profile.lower.is_infinite() & profile.upper.is_infinite(),
+            "Expected removal_rate not identifiable, got [{}, {}]",
+            profile.lower,
+            profile.upper
+        );
+    }
+
+    #[test]
+    fn test_fit_changepoint() {
+        let mut truth = Fit::new();
+        truth.configure(
+            0.25,
+            0.001,
+            0.001,
+            vec![Observation::Missing; 80],
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.3, 0.1, &[(20.0, 0.08)]);
+        let observed: Vec<Observation> = (0..80)
+            .map(|t| Observation::Value(model.i_popf[(4 * t, 0)]))
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            0.25,
+            0.001,
+            0.001,
+            observed,
+            Rate::per_day(0.2),
+            Rate::per_day(0.2),
+        );
+        fit.run_changepoints(1, 3, 2000);
+        assert_eq!(
+            fit.changepoints.len(),
+            1,
+            "Bad number of changepoints, expected 1 got {}",
+            fit.changepoints.len()
+        );
+        let (t, rate) = fit.changepoints[0];
+        assert!(
+            (t - 20.0).abs() < 1.0,
+            "Bad changepoint time, expected 20.0 got {}",
+            t
+        );
+        assert!(
+            (rate - 0.08).abs() < 0.01,
+            "Bad changepoint incidence_rate, expected 0.08 got {}",
+            rate
+        );
+        assert!(
+            (fit.incidence_rate - 0.3).abs() < 0.01,
+            "Bad incidence_rate estimate, expected 0.3 got {}",
+            fit.incidence_rate
+        );
+        let series = fit.incidence_rate_series();
+        assert_eq!(
+            series.nrows(),
+            80,
+            "Bad incidence_rate_series length, expected 80 got {}",
+            series.nrows()
+        );
+        assert_eq!(
+            series[(79, 0)],
+            rate,
+            "Bad incidence_rate_series after changepoint, expected {} got {}",
+            rate,
+            series[(79, 0)]
+        );
+    }
+
+    #[test]
+    fn test_fit_random_walk() {
+        let mut truth = Fit::new();
+        truth.configure(
+            0.5,
+            0.001,
+            0.001,
+            vec![Observation::Missing; 60],
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.3, 0.1, &[(30.0, 0.1)]);
+        let observed: Vec<Observation> = (0..60)
+            .map(|t| Observation::Value(model.i_popf[(2 * t, 0)]))
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            0.5,
+            0.001,
+            0.001,
+            observed,
+            Rate::per_day(0.2),
+            Rate::per_day(0.1),
+        );
+        fit.run_random_walk(15.0, 0.01, 5, 3000);
+        assert_eq!(
+            fit.changepoints.len(),
+            3,
+            "Bad number of knots after the first, expected 3 got {}",
+            fit.changepoints.len()
+        );
+        let series = fit.incidence_rate_series();
+        assert!(
+            (series[(10, 0)] - 0.3).abs() < 0.03,
+            "Bad early incidence rate, expected 0.3 got {}",
+            series[(10, 0)]
+        );
+        assert!(
+            (series[(50, 0)] - 0.1).abs() < 0.03,
+            "Bad late incidence rate, expected 0.1 got {}",
+            series[(50, 0)]
+        );
+    }
+
+    #[test]
+    fn test_fit_assortativity() {
+        let mut truth = MixingFit::new();
+        truth.configure(
+            0.25,
+            1e6,
+            mat![[0.3], [0.5], [0.2]],
+            mat![[12.0], [8.0], [4.0]],
+            1e-4,
+            Rate::per_day(0.2),
+            Mat::zeros(60, 3),
+            Fraction::of(0.05),
+            Fraction::of(0.5),
+        );
+        let cases = truth.predicted_cases(&truth.simulate(0.05, 0.6));
+        let mut fit = MixingFit::new();
+        fit.configure(
+            0.25,
+            1e6,
+            mat![[0.3], [0.5], [0.2]],
+            mat![[12.0], [8.0], [4.0]],
+            1e-4,
+            Rate::per_day(0.2),
+            Mat::from_fn(60, 3, |t, g| cases[(t, g)].round()),
+            Fraction::of(0.03),
+            Fraction::of(0.2),
+        );
+        fit.run_nelder_mead(500);
+        assert!(
+            (fit.assortativity - 0.6).abs() < 0.02,
+            "Bad assortativity, expected 0.6 got {}",
+            fit.assortativity
+        );
+        assert!(
+            (fit.incidence_rate - 0.05).abs() < 1e-3,
+            "Bad incidence_rate, expected 0.05 got {}",
+            fit.incidence_rate
+        );
+    }
+}
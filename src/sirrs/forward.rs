@@ -0,0 +1,280 @@
+//! Forward sensitivities of SIR outcomes to parameters.
+//!
+//! [`sensitivities`] solves the variational equations of a
+//! [`sir::Model`] alongside its state: for each parameter θ the
+//! derivatives `Z = ∂y/∂θ` of the state `y` satisfy `Z' = J Z + ∂f/∂θ`,
+//! with `J` the Jacobian of the equations, see [`sir::Model::jacobian`].
+//! Both are integrated by the 4th order Runge-Kutta method on the model's
+//! grid, so derivatives are as accurate as the solution itself, without the
+//! step size tuning of finite differences.
+//!
+//! Derivatives are taken with respect to every parameter in [`PARAMETERS`],
+//! and summarized for the outcomes in [`OUTCOMES`], for example
+//! `sensitivities(&model).sensitivity("peak_i", "incidence_rate")`. The
+//! derivative of the whole series, [`Sensitivities::series`], gives the
+//! gradient of any loss on the solution, for gradient-based fitting.
+use crate::sirrs::sir;
+use faer::Mat;
+
+/// Parameters derivatives are taken with respect to. `incidence_rate` is
+/// the rate before the first changepoint, with later changepoints held
+/// fixed.
+pub const PARAMETERS: [&str; 5] = [
+    "i_popf_init",
+    "r_popf_init",
+    "incidence_rate",
+    "removal_rate",
+    "recovery_rate",
+];
+
+/// Outcomes summarizing a run:
+///  - `peak_i`, the largest I at any index
+///  - `final_s`, `final_i` and `final_r`, the compartments at the last index
+///  - `cumulative_incidence`, infections up to the last index
+pub const OUTCOMES: [&str; 5] = [
+    "peak_i",
+    "final_s",
+    "final_i",
+    "final_r",
+    "cumulative_incidence",
+];
+
+/// Series of the state, in column order of [`Sensitivities::state`].
+const SERIES: [&str; 4] = ["s", "i", "r", "cumulative_incidence"];
+
+/// A solution and its derivatives with respect to each of [`PARAMETERS`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensitivities {
+    /// Time of each index (row).
+    pub times: Vec<f64>,
+    /// S, I, R and cumulative incidence at each index.
+    pub state: Mat<f64>,
+    /// Derivative of each column of `state` with respect to each parameter,
+    /// in the order of [`PARAMETERS`].
+    pub derivatives: Vec<Mat<f64>>,
+}
+
+impl Sensitivities {
+    /// Column of series `name` in `state`.
+    fn column(name: &str) -> usize {
+        return SERIES.iter().position(|s| *s == name).unwrap_or_else(|| {
+            panic!(
+                "unknown series {}, expected one of {}",
+                name,
+                SERIES.join(", ")
+            )
+        });
+    }
+
+    /// Index of `parameter` in [`PARAMETERS`].
+    fn parameter(parameter: &str) -> usize {
+        return PARAMETERS
+            .iter()
+            .position(|p| *p == parameter)
+            .unwrap_or_else(|| {
+                panic!(
+                    "unknown parameter {}, expected one of {}",
+                    parameter,
+                    PARAMETERS.join(", ")
+                )
+            });
+    }
+
+    /// Row and column of `state` an outcome is read from.
+    fn locate(&self, outcome: &str) -> (usize, usize) {
+        let last = self.state.nrows() - 1;
+        return match outcome {
+            "peak_i" => {
+                let peak = (0..self.state.nrows())
+                    .max_by(|a, b| self.state[(*a, 1)].total_cmp(&self.state[(*b, 1)]))
+                    .unwrap();
+                (peak, 1)
+            }
+            "final_s" => (last, 0),
+            "final_i" => (last, 1),
+            "final_r" => (last, 2),
+            "cumulative_incidence" => (last, 3),
+            _ => panic!(
+                "unknown outcome {}, expected one of {}",
+                outcome,
+                OUTCOMES.join(", ")
+            ),
+        };
+    }
+
+    /// Value of `outcome`, one of [`OUTCOMES`].
+    pub fn outcome(&self, outcome: &str) -> f64 {
+        return self.state[self.locate(outcome)];
+    }
+
+    /// Derivative of `outcome` with respect to `parameter`. The derivative
+    /// of `peak_i` is that of I at the index of the peak.
+    pub fn sensitivity(&self, outcome: &str, parameter: &str) -> f64 {
+        return self.derivatives[Self::parameter(parameter)][self.locate(outcome)];
+    }
+
+    /// Derivative of `outcome` with respect to every parameter, in the
+    /// order of [`PARAMETERS`].
+    pub fn gradient(&self, outcome: &str) -> Vec<f64> {
+        let at = self.locate(outcome);
+        return self.derivatives.iter().map(|d| d[at]).collect();
+    }
+
+    /// Derivative of series `name`, `s`, `i`, `r` or
+    /// `cumulative_incidence`, at every index with respect to `parameter`.
+    pub fn series(&self, name: &str, parameter: &str) -> Vec<f64> {
+        let (j, d) = (
+            Self::column(name),
+            &self.derivatives[Self::parameter(parameter)],
+        );
+        return (0..d.nrows()).map(|k| d[(k, j)]).collect();
+    }
+}
+
+/// Rates of change of the state and its derivatives, packed as the state
+/// followed by the derivatives for each parameter in turn.
+fn augmented(model: &sir::Model, t: f64, u: &[f64]) -> Vec<f64> {
+    let (s, i) = (u[0], u[1]);
+    let beta = model.incidence_rate_at(t) / model.population;
+    let iota = model.importation_at(t);
+    let jacobian = model.jacobian(t, &u[0..3]);
+    let mut du = vec![0.0; u.len()];
+    du[0] = model.dsdt(t, s, i);
+    du[1] = model.didt(t, s, i);
+    du[2] = model.drdt(i);
+    du[3] = model.dxdt(t, s, i);
+    let base_rate = model
+        .incidence_rate_changes
+        .first()
+        .is_none_or(|(start, _)| *start > t);
+    for p in 0..PARAMETERS.len() {
+        let z = &u[(4 * (p + 1))..(4 * (p + 2))];
+        let dz = &mut du[(4 * (p + 1))..(4 * (p + 2))];
+        for row in 0..3 {
+            dz[row] = (0..3).map(|col| jacobian[(row, col)] * z[col]).sum();
+        }
+        dz[3] = (((beta * i) + iota) * z[0]) + (beta * s * z[1]);
+        match PARAMETERS[p] {
+            "incidence_rate" if base_rate => {
+                let flux = model.seasonal_factor(t) * s * i / model.population;
+                dz[0] -= flux;
+                dz[1] += flux;
+                dz[3] += flux;
+            }
+            "removal_rate" => {
+                dz[1] -= i;
+                dz[2] += i;
+            }
+            "recovery_rate" => {
+                dz[0] += i;
+                dz[1] -= i;
+            }
+            _ => {}
+        }
+    }
+    return du;
+}
+
+/// Solve `model` and its sensitivities to [`PARAMETERS`] on its grid. The
+/// model must be configured; its own outputs are not changed.
+pub fn sensitivities(model: &sir::Model) -> Sensitivities {
+    let grid = model.grid();
+    let n = 4 * (PARAMETERS.len() + 1);
+    let mut u = vec![0.0; n];
+    u[0] = model.population - model.i_popf_init - model.r_popf_init;
+    u[1] = model.i_popf_init;
+    u[2] = model.r_popf_init;
+    // i_popf_init and r_popf_init move population out of S.
+    u[4..8].copy_from_slice(&[-1.0, 1.0, 0.0, 0.0]);
+    u[8..12].copy_from_slice(&[-1.0, 0.0, 1.0, 0.0]);
+    let mut rows = vec![u.clone()];
+    let h = model.step_size;
+    for k in 0..grid.n_steps - 1 {
+        let t = grid.time(k);
+        let stage = |y: &[f64], k: &[f64], scale: f64| -> Vec<f64> {
+            return y.iter().zip(k).map(|(y, k)| y + (scale * k)).collect();
+        };
+        let k1 = augmented(model, t, &u);
+        let k2 = augmented(model, t + (h / 2.0), &stage(&u, &k1, h / 2.0));
+        let k3 = augmented(model, t + (h / 2.0), &stage(&u, &k2, h / 2.0));
+        let k4 = augmented(model, t + h, &stage(&u, &k3, h));
+        for j in 0..n {
+            u[j] += (h / 6.0) * (k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]);
+        }
+        rows.push(u.clone());
+    }
+    let column = |offset: usize| Mat::from_fn(rows.len(), 4, |k, j| rows[k][offset + j]);
+    return Sensitivities {
+        times: grid.times(),
+        state: column(0),
+        derivatives: (1..=PARAMETERS.len()).map(|p| column(4 * p)).collect(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::forward::{PARAMETERS, sensitivities};
+    use crate::sirrs::sir::Model;
+    use crate::sirrs::units::Rate;
+
+    /// Configured model with `parameter` shifted by `delta`.
+    fn model(parameter: &str, delta: f64) -> Model {
+        let mut values = [0.01, 0.05, 0.5, 0.1, 0.02];
+        values[PARAMETERS.iter().position(|p| *p == parameter).unwrap()] += delta;
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.1,
+            values[0],
+            values[1],
+            Rate::per_day(values[2]),
+            Rate::per_day(values[3]),
+            Rate::per_day(values[4]),
+        );
+        model.changepoints(vec![(40.0, Rate::per_day(0.3))]);
+        return model;
+    }
+
+    #[test]
+    fn test_sensitivities() {
+        let base = model("removal_rate", 0.0);
+        let result = sensitivities(&base);
+        let mut solved = model("removal_rate", 0.0);
+        solved.init_popf();
+        solved.run_rk4();
+        let last = solved.grid().n_steps - 1;
+        assert!(
+            (result.outcome("final_r") - solved.r_popf[(last, 0)]).abs() < 1e-12,
+            "Bad final_r, expected {} got {}",
+            solved.r_popf[(last, 0)],
+            result.outcome("final_r")
+        );
+        let delta = 1e-6;
+        for parameter in PARAMETERS {
+            let up = sensitivities(&model(parameter, delta));
+            let down = sensitivities(&model(parameter, -delta));
+            for outcome in ["peak_i", "final_s", "final_r", "cumulative_incidence"] {
+                let expected = (up.outcome(outcome) - down.outcome(outcome)) / (2.0 * delta);
+                let actual = result.sensitivity(outcome, parameter);
+                assert!(
+                    (actual - expected).abs() < 1e-5 * (1.0 + expected.abs()),
+                    "Bad d{}/d{}, expected {} got {}",
+                    outcome,
+                    parameter,
+                    expected,
+                    actual
+                );
+            }
+        }
+        assert_eq!(
+            result.gradient("peak_i")[2],
+            result.sensitivity("peak_i", "incidence_rate"),
+            "Bad gradient"
+        );
+        assert_eq!(
+            result.series("i", "removal_rate")[0],
+            0.0,
+            "Expected no initial sensitivity to a rate"
+        );
+    }
+}
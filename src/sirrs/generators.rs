@@ -0,0 +1,264 @@
+//! Random contact network generators.
+//!
+//! Each generator returns an undirected, unweighted [`Network`] without
+//! self-loops or repeated edges, drawn from the generator's seed, so network
+//! epidemics can be run without preparing graph files:
+//!  - [`erdos_renyi`], every pair connected independently
+//!  - [`barabasi_albert`], preferential attachment, with a heavy-tailed
+//!    degree distribution
+//!  - [`watts_strogatz`], a rewired ring lattice, clustered with short paths
+//!  - [`configuration_model`], random wiring of a given degree sequence
+use crate::sirrs::network::Network;
+use crate::sirrs::rng;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+
+/// Undirected edge with the smaller node first.
+fn ordered(a: usize, b: usize) -> (usize, usize) {
+    return (a.min(b), a.max(b));
+}
+
+/// Network of `n_nodes` nodes from a set of undirected edges, added in
+/// sorted order so the result does not depend on hashing.
+fn from_edge_set(n_nodes: usize, edges: HashSet<(usize, usize)>) -> Network {
+    let mut edges: Vec<(usize, usize)> = edges.into_iter().collect();
+    edges.sort();
+    return Network::from_edges(n_nodes, &edges);
+}
+
+/// Erdős–Rényi random graph G(n, p): each of the `n_nodes (n_nodes - 1) / 2`
+/// pairs is connected independently with probability `p`, for a mean degree
+/// of `p (n_nodes - 1)`.
+pub fn erdos_renyi(n_nodes: usize, p: f64, seed: u64) -> Network {
+    assert!((0.0..=1.0).contains(&p), "p must be in [0, 1], got {}", p);
+    let mut rng = rng::rng(seed);
+    let mut network = Network::new(n_nodes);
+    for a in 0..n_nodes {
+        for b in (a + 1)..n_nodes {
+            if rng.r#gen::<f64>() < p {
+                network.add_edge(a, b, 1.0);
+            }
+        }
+    }
+    return network;
+}
+
+/// Barabási–Albert preferential attachment graph. Starts from a complete
+/// graph of `m + 1` nodes, then adds nodes one at a time, each connecting
+/// to `m` distinct existing nodes chosen with probability proportional to
+/// their degree.
+pub fn barabasi_albert(n_nodes: usize, m: usize, seed: u64) -> Network {
+    assert!(
+        (m >= 1) & (m < n_nodes),
+        "m must be in [1, n_nodes), got {} for {} nodes",
+        m,
+        n_nodes
+    );
+    let mut rng = rng::rng(seed);
+    let mut network = Network::new(n_nodes);
+    // Every edge end, so a uniform draw picks a node in proportion to its
+    // degree.
+    let mut ends: Vec<usize> = Vec::new();
+    for a in 0..=m {
+        for b in (a + 1)..=m {
+            network.add_edge(a, b, 1.0);
+            ends.push(a);
+            ends.push(b);
+        }
+    }
+    for node in (m + 1)..n_nodes {
+        let mut targets: Vec<usize> = Vec::with_capacity(m);
+        while targets.len() < m {
+            let target = ends[rng.gen_range(0..ends.len())];
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        for target in targets {
+            network.add_edge(node, target, 1.0);
+            ends.push(node);
+            ends.push(target);
+        }
+    }
+    return network;
+}
+
+/// Watts–Strogatz small-world graph. Starts from a ring lattice where each
+/// node connects to its `k / 2` nearest neighbours on each side, then
+/// rewires the far end of each edge with probability `beta` to a node
+/// chosen uniformly, avoiding self-loops and repeated edges. `k` must be
+/// even.
+pub fn watts_strogatz(n_nodes: usize, k: usize, beta: f64, seed: u64) -> Network {
+    assert!(
+        k.is_multiple_of(2) & (k < n_nodes),
+        "k must be even and less than n_nodes, got {} for {} nodes",
+        k,
+        n_nodes
+    );
+    assert!(
+        (0.0..=1.0).contains(&beta),
+        "beta must be in [0, 1], got {}",
+        beta
+    );
+    let mut rng = rng::rng(seed);
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    for a in 0..n_nodes {
+        for j in 1..=(k / 2) {
+            edges.insert(ordered(a, (a + j) % n_nodes));
+        }
+    }
+    let mut degrees = vec![k; n_nodes];
+    for j in 1..=(k / 2) {
+        for a in 0..n_nodes {
+            let b = (a + j) % n_nodes;
+            if rng.r#gen::<f64>() >= beta {
+                continue;
+            }
+            // A node already connected to everyone has nowhere to rewire to.
+            if degrees[a] >= n_nodes - 1 {
+                continue;
+            }
+            let mut c = rng.gen_range(0..n_nodes);
+            while (c == a) | edges.contains(&ordered(a, c)) {
+                c = rng.gen_range(0..n_nodes);
+            }
+            edges.remove(&ordered(a, b));
+            edges.insert(ordered(a, c));
+            degrees[b] -= 1;
+            degrees[c] += 1;
+        }
+    }
+    return from_edge_set(n_nodes, edges);
+}
+
+/// Configuration model: a random graph with the given degree of each node.
+/// Edge ends are paired uniformly at random, then self-loops and repeated
+/// edges are dropped, so some nodes may end up with a slightly lower degree,
+/// a fraction which vanishes in large sparse networks. The degrees must sum
+/// to an even number.
+pub fn configuration_model(degrees: &[usize], seed: u64) -> Network {
+    let total: usize = degrees.iter().sum();
+    assert!(
+        total.is_multiple_of(2),
+        "degrees must sum to an even number, got {}",
+        total
+    );
+    let mut rng = rng::rng(seed);
+    let mut ends: Vec<usize> = Vec::with_capacity(total);
+    for (node, degree) in degrees.iter().enumerate() {
+        ends.extend(std::iter::repeat_n(node, *degree));
+    }
+    ends.shuffle(&mut rng);
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    for pair in ends.chunks(2) {
+        if pair[0] != pair[1] {
+            edges.insert(ordered(pair[0], pair[1]));
+        }
+    }
+    return from_edge_set(degrees.len(), edges);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::generators::{
+        barabasi_albert, configuration_model, erdos_renyi, watts_strogatz,
+    };
+    use crate::sirrs::network::Network;
+    use std::collections::HashSet;
+
+    /// Check there are no self-loops or repeated edges, returning the
+    /// number of edges.
+    fn simple_edges(network: &Network) -> usize {
+        let mut edges = HashSet::new();
+        for (a, neighbours) in network.adjacency.iter().enumerate() {
+            for (b, _) in neighbours.iter() {
+                assert_ne!(a, *b, "Unexpected self-loop at node {}", a);
+                assert!(
+                    edges.insert((a, *b)),
+                    "Unexpected repeated edge ({}, {})",
+                    a,
+                    b
+                );
+            }
+        }
+        return edges.len() / 2;
+    }
+
+    #[test]
+    fn test_erdos_renyi() {
+        let network = erdos_renyi(1000, 0.01, 1);
+        simple_edges(&network);
+        let degrees = network.degrees();
+        let mean = (degrees.iter().sum::<usize>() as f64) / 1000.0;
+        assert!(
+            (mean - 9.99).abs() < 0.5,
+            "Bad mean degree, expected 9.99 got {}",
+            mean
+        );
+        assert_eq!(
+            network,
+            erdos_renyi(1000, 0.01, 1),
+            "Same seed gave different networks"
+        );
+    }
+
+    #[test]
+    fn test_barabasi_albert() {
+        let network = barabasi_albert(500, 3, 2);
+        let n_edges = simple_edges(&network);
+        assert_eq!(
+            n_edges,
+            6 + (3 * 496),
+            "Bad number of edges, got {}",
+            n_edges
+        );
+        let degrees = network.degrees();
+        assert!(
+            degrees.iter().all(|d| *d >= 3),
+            "Expected every degree to be at least m"
+        );
+        let max = *degrees.iter().max().unwrap();
+        assert!(max > 30, "Expected hubs, got a largest degree of {}", max);
+    }
+
+    #[test]
+    fn test_watts_strogatz() {
+        let lattice = watts_strogatz(100, 4, 0.0, 3);
+        assert!(
+            lattice.degrees().iter().all(|d| *d == 4),
+            "Expected a ring lattice without rewiring"
+        );
+        assert!(
+            lattice.adjacency[0].iter().any(|(b, _)| *b == 98),
+            "Expected the ring to wrap around"
+        );
+        let rewired = watts_strogatz(100, 4, 0.3, 3);
+        let n_edges = simple_edges(&rewired);
+        assert_eq!(
+            n_edges, 200,
+            "Expected rewiring to keep the number of edges, got {}",
+            n_edges
+        );
+        assert_ne!(rewired, lattice, "Expected some edges to be rewired");
+    }
+
+    #[test]
+    fn test_configuration_model() {
+        let degrees: Vec<usize> = (0..1000).map(|k| 2 + (k % 4)).collect();
+        let network = configuration_model(&degrees, 4);
+        simple_edges(&network);
+        let realized = network.degrees();
+        let mut lost = 0;
+        for (wanted, got) in degrees.iter().zip(realized.iter()) {
+            assert!(
+                got <= wanted,
+                "Degree above the sequence, wanted {} got {}",
+                wanted,
+                got
+            );
+            lost += wanted - got;
+        }
+        assert!(lost < 20, "Expected few dropped edge ends, lost {}", lost);
+    }
+}
@@ -0,0 +1,89 @@
+//! Time grids of solved series.
+//!
+//! Models are configured with a `length` and a `step_size`, and store one
+//! row per step: index `i` is time `t0 + (i * dt)`, for every step that
+//! starts before `t_end`. A [`TimeGrid`] holds that relationship in one
+//! place, so allocation, solver loops and reported times agree on the
+//! number of steps by construction.
+
+/// Evenly spaced times `t0 + (i * dt)` for `i` in `0..n_steps`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeGrid {
+    /// Time of index 0.
+    pub t0: f64,
+    /// End of the series. Every index is at a time before `t_end`.
+    pub t_end: f64,
+    /// Step between consecutive indices.
+    pub dt: f64,
+    /// Number of indices, `ceil((t_end - t0) / dt)`.
+    pub n_steps: usize,
+}
+
+impl TimeGrid {
+    /// Grid from `t0` up to, not including, `t_end` in steps of `dt`. The
+    /// grid always has at least one step, so `t_end` must be after `t0`.
+    pub fn new(t0: f64, t_end: f64, dt: f64) -> Self {
+        assert!(dt > 0.0, "dt must be positive, got {}", dt);
+        assert!(
+            t_end > t0,
+            "t_end must be after t0, got {} and {}",
+            t0,
+            t_end
+        );
+        let n_steps = ((t_end - t0) / dt).ceil() as usize;
+        return Self {
+            t0,
+            t_end,
+            dt,
+            n_steps,
+        };
+    }
+
+    /// Grid of a model configured with `length` and `step_size`, starting
+    /// at 0.
+    pub fn from_length(length: usize, step_size: f64) -> Self {
+        return Self::new(0.0, length as f64, step_size);
+    }
+
+    /// Time of index `i`.
+    pub fn time(&self, i: usize) -> f64 {
+        return self.t0 + ((i as f64) * self.dt);
+    }
+
+    /// Time of every index.
+    pub fn times(&self) -> Vec<f64> {
+        return (0..self.n_steps).map(|i| self.time(i)).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::grid::TimeGrid;
+
+    #[test]
+    fn test_from_length() {
+        let grid = TimeGrid::from_length(10, 1.0);
+        assert_eq!(
+            grid.n_steps, 10,
+            "Bad n_steps, expected 10 got {}",
+            grid.n_steps
+        );
+        assert_eq!(grid.time(9), 9.0, "Bad last time, got {}", grid.time(9));
+        let grid = TimeGrid::from_length(10, 0.3);
+        assert_eq!(
+            grid.n_steps, 34,
+            "Bad n_steps, expected 34 got {}",
+            grid.n_steps
+        );
+        assert!(
+            grid.times().iter().all(|t| *t < 10.0),
+            "Expected every time before t_end"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "t_end must be after t0")]
+    fn test_empty_grid() {
+        TimeGrid::from_length(0, 1.0);
+    }
+}
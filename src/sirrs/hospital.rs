@@ -0,0 +1,779 @@
+//! Five compartment SIR model with hospital and ICU compartments.
+//!
+//! Allows transition rates:
+//!  - S → I
+//!  - I → R
+//!  - I → H
+//!  - H → U
+//!  - H → R
+//!  - U → R
+//!
+//! Hospital (H) and intensive care (U) occupancy are checked against
+//! configurable capacities after every step, and each crossing is recorded as
+//! a [`CapacityEvent`].
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::schema::ModelSchema;
+use crate::sirrs::stability::Stability;
+use crate::sirrs::units::{Duration, Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Numerical integrator variables
+///
+/// This private struct exists to make indexing k and y during integration
+/// simpler.
+struct SystemVars {
+    s: f64,
+    i: f64,
+    h: f64,
+    u: f64,
+    r: f64,
+}
+
+/// Kind of capacity threshold crossing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CapacityEventKind {
+    /// Hospital occupancy rose above `hospital_capacity`.
+    HospitalExceeded,
+    /// Hospital occupancy fell back to or below `hospital_capacity`.
+    HospitalRecovered,
+    /// ICU occupancy rose above `icu_capacity`.
+    IcuExceeded,
+    /// ICU occupancy fell back to or below `icu_capacity`.
+    IcuRecovered,
+}
+
+/// A capacity threshold crossing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapacityEvent {
+    /// Time at which the crossing was first observed.
+    pub t: f64,
+    /// Which threshold was crossed, and in which direction.
+    pub kind: CapacityEventKind,
+}
+
+/// Create and run an SIR model with hospital and ICU compartments.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Transition rate from S into I, per day. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate out of I, into either H or R, per day. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Fraction of removals from I which are hospitalized. Must be in [0, 1].
+    pub hospitalized_fraction: f64,
+    /// Fraction of hospital discharges which move into ICU. Must be in [0, 1].
+    pub icu_fraction: f64,
+    /// Mean length of stay in hospital before discharge or ICU admission, in
+    /// days.
+    pub hospital_stay: f64,
+    /// Mean length of stay in ICU, in days.
+    pub icu_stay: f64,
+    /// Hospital capacity as a population fraction.
+    pub hospital_capacity: f64,
+    /// ICU capacity as a population fraction.
+    pub icu_capacity: f64,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Hospitalized population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub h_popf: Mat<f64>,
+    /// ICU population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub u_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// Capacity threshold crossings, in time order.
+    pub events: Vec<CapacityEvent>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            hospitalized_fraction: 0.0,
+            icu_fraction: 0.0,
+            hospital_stay: 0.0,
+            icu_stay: 0.0,
+            hospital_capacity: 0.0,
+            icu_capacity: 0.0,
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            h_popf: Mat::new(),
+            u_popf: Mat::new(),
+            r_popf: Mat::new(),
+            events: Vec::new(),
+        };
+    }
+
+    /// Compartments, flows and parameters of the model. Capacities are
+    /// thresholds for [`CapacityEvent`]s and do not affect the flows.
+    pub fn schema() -> ModelSchema {
+        let mut schema = ModelSchema::new(
+            "hospital",
+            "Five compartment SIR model with hospital and ICU compartments",
+        );
+        schema
+            .compartment("S")
+            .compartment("I")
+            .compartment("H")
+            .compartment("U")
+            .compartment("R")
+            .flow("S", "I", "incidence_rate * I")
+            .flow("I", "H", "hospitalized_fraction * removal_rate")
+            .flow("I", "R", "(1 - hospitalized_fraction) * removal_rate")
+            .flow("H", "U", "icu_fraction / hospital_stay")
+            .flow("H", "R", "(1 - icu_fraction) / hospital_stay")
+            .flow("U", "R", "1 / icu_stay")
+            .parameter("length", "Length of the series", 1.0, f64::INFINITY, "time")
+            .parameter(
+                "step_size",
+                "Size of integration step",
+                0.0,
+                f64::INFINITY,
+                "time",
+            )
+            .parameter(
+                "i_popf_init",
+                "Initial infectious population fraction",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "incidence_rate",
+                "Transition rate from S into I",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "removal_rate",
+                "Transition rate out of I",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "hospitalized_fraction",
+                "Fraction of removals hospitalized",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "icu_fraction",
+                "Fraction of hospital discharges into ICU",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "hospital_stay",
+                "Mean length of stay in hospital",
+                0.0,
+                f64::INFINITY,
+                "time",
+            )
+            .parameter(
+                "icu_stay",
+                "Mean length of stay in ICU",
+                0.0,
+                f64::INFINITY,
+                "time",
+            )
+            .parameter(
+                "hospital_capacity",
+                "Hospital capacity",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter("icu_capacity", "ICU capacity", 0.0, 1.0, "fraction");
+        return schema;
+    }
+
+    /// Configure model parameters. Time is in days, and rates and stays are
+    /// stored per day and in days.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        hospitalized_fraction: Fraction,
+        icu_fraction: Fraction,
+        hospital_stay: Duration,
+        icu_stay: Duration,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.hospitalized_fraction = hospitalized_fraction.value();
+        self.icu_fraction = icu_fraction.value();
+        self.hospital_stay = hospital_stay.in_unit(TimeUnit::Day);
+        self.icu_stay = icu_stay.in_unit(TimeUnit::Day);
+        self.hospital_capacity = f64::INFINITY;
+        self.icu_capacity = f64::INFINITY;
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.h_popf = Mat::zeros(n_steps, 1);
+        self.u_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.events = Vec::new();
+        return self;
+    }
+
+    /// Times of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Set hospital and ICU capacities, as population fractions. Both
+    /// default to infinity, which never produces events.
+    pub fn capacity(&mut self, hospital_capacity: f64, icu_capacity: f64) -> &mut Self {
+        assert!(
+            (hospital_capacity >= 0.0) & (icu_capacity >= 0.0),
+            "capacities must be non-negative, got {} and {}",
+            hospital_capacity,
+            icu_capacity
+        );
+        self.hospital_capacity = hospital_capacity;
+        self.icu_capacity = icu_capacity;
+        return self;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        self.s_popf[(0, 0)] = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+        self.i_popf[(0, 0)] = self.i_popf_init;
+        self.h_popf[(0, 0)] = 0.0;
+        self.u_popf[(0, 0)] = 0.0;
+        self.r_popf[(0, 0)] = 0.0;
+        self.events = Vec::new();
+        return self;
+    }
+
+    /// Hospital discharge rate, the inverse of the mean length of stay.
+    fn hospital_exit_rate(&self) -> f64 {
+        return 1.0 / self.hospital_stay;
+    }
+
+    /// ICU discharge rate, the inverse of the mean length of stay.
+    fn icu_exit_rate(&self) -> f64 {
+        return 1.0 / self.icu_stay;
+    }
+
+    /// Rate of change of the susceptible fraction.
+    pub(crate) fn dsdt(&self, s: f64, i: f64) -> f64 {
+        return -self.incidence_rate * s * i;
+    }
+
+    /// Rate of change of the infectious fraction.
+    pub(crate) fn didt(&self, s: f64, i: f64) -> f64 {
+        return (self.incidence_rate * s * i) - (self.removal_rate * i);
+    }
+
+    /// Rate of change of the hospitalized fraction.
+    pub(crate) fn dhdt(&self, i: f64, h: f64) -> f64 {
+        return (self.hospitalized_fraction * self.removal_rate * i)
+            - (self.hospital_exit_rate() * h);
+    }
+
+    /// Rate of change of the ICU fraction.
+    pub(crate) fn dudt(&self, h: f64, u: f64) -> f64 {
+        return (self.icu_fraction * self.hospital_exit_rate() * h) - (self.icu_exit_rate() * u);
+    }
+
+    /// Rate of change of the removed fraction.
+    pub(crate) fn drdt(&self, i: f64, h: f64, u: f64) -> f64 {
+        return ((1.0 - self.hospitalized_fraction) * self.removal_rate * i)
+            + ((1.0 - self.icu_fraction) * self.hospital_exit_rate() * h)
+            + (self.icu_exit_rate() * u);
+    }
+
+    /// Jacobian of the equations at `state`, (S, I, H, U, R) population
+    /// fractions, with rows the derivatives of S, I, H, U and R. The rates
+    /// are constant, so `t` is unused.
+    pub fn jacobian(&self, _t: f64, state: &[f64]) -> Mat<f64> {
+        let (s, i) = (state[0], state[1]);
+        let beta = self.incidence_rate;
+        let gamma = self.removal_rate;
+        let hosp = self.hospital_exit_rate();
+        let icu = self.icu_exit_rate();
+        let f_h = self.hospitalized_fraction;
+        let f_u = self.icu_fraction;
+        return faer::mat![
+            [-beta * i, -beta * s, 0.0, 0.0, 0.0],
+            [beta * i, (beta * s) - gamma, 0.0, 0.0, 0.0],
+            [0.0, f_h * gamma, -hosp, 0.0, 0.0],
+            [0.0, 0.0, f_u * hosp, -icu, 0.0],
+            [0.0, (1.0 - f_h) * gamma, (1.0 - f_u) * hosp, icu, 0.0],
+        ];
+    }
+
+    /// Eigenvalues of the Jacobian, see [`Model::jacobian`].
+    pub fn stability_at(&self, t: f64, state: &[f64]) -> Stability {
+        return Stability::of(&self.jacobian(t, state));
+    }
+
+    /// Compare occupancy at index `t` with index `t - 1` and record any
+    /// capacity threshold crossings.
+    fn check_capacity(&mut self, t: usize) {
+        let time = (t as f64) * self.step_size;
+        let h_prev = self.h_popf[(t - 1, 0)];
+        let h_next = self.h_popf[(t, 0)];
+        if (h_prev <= self.hospital_capacity) & (h_next > self.hospital_capacity) {
+            self.events.push(CapacityEvent {
+                t: time,
+                kind: CapacityEventKind::HospitalExceeded,
+            });
+        } else if (h_prev > self.hospital_capacity) & (h_next <= self.hospital_capacity) {
+            self.events.push(CapacityEvent {
+                t: time,
+                kind: CapacityEventKind::HospitalRecovered,
+            });
+        }
+        let u_prev = self.u_popf[(t - 1, 0)];
+        let u_next = self.u_popf[(t, 0)];
+        if (u_prev <= self.icu_capacity) & (u_next > self.icu_capacity) {
+            self.events.push(CapacityEvent {
+                t: time,
+                kind: CapacityEventKind::IcuExceeded,
+            });
+        } else if (u_prev > self.icu_capacity) & (u_next <= self.icu_capacity) {
+            self.events.push(CapacityEvent {
+                t: time,
+                kind: CapacityEventKind::IcuRecovered,
+            });
+        }
+    }
+
+    /// Emit the state at index `t` as a debug event.
+    fn log_step(&self, t: usize) {
+        tracing::debug!(
+            t = (t as f64) * self.step_size,
+            s = self.s_popf[(t, 0)],
+            i = self.i_popf[(t, 0)],
+            h = self.h_popf[(t, 0)],
+            u = self.u_popf[(t, 0)],
+            r = self.r_popf[(t, 0)],
+            "step"
+        );
+    }
+
+    /// Emit a summary of a finished run as an info event.
+    fn log_summary(&self) {
+        let last = self.s_popf.nrows() - 1;
+        let peak = |m: &Mat<f64>| (0..m.nrows()).map(|t| m[(t, 0)]).fold(0.0, f64::max);
+        tracing::info!(
+            n_steps = self.s_popf.nrows(),
+            step_size = self.step_size,
+            s = self.s_popf[(last, 0)],
+            r = self.r_popf[(last, 0)],
+            peak_h = peak(&self.h_popf),
+            peak_u = peak(&self.u_popf),
+            capacity_events = self.events.len(),
+            "finished"
+        );
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let _span = tracing::info_span!("run", model = "hospital", solver = "euler").entered();
+        let h = self.step_size;
+        let n = self.grid().n_steps;
+        for t in 0..n - 1 {
+            let s = self.s_popf[(t, 0)];
+            let i = self.i_popf[(t, 0)];
+            let hosp = self.h_popf[(t, 0)];
+            let u = self.u_popf[(t, 0)];
+            let ds = self.dsdt(s, i);
+            let di = self.didt(s, i);
+            let dh = self.dhdt(i, hosp);
+            let du = self.dudt(hosp, u);
+            let dr = self.drdt(i, hosp, u);
+            self.s_popf[(t + 1, 0)] = s + (h * ds);
+            self.i_popf[(t + 1, 0)] = i + (h * di);
+            self.h_popf[(t + 1, 0)] = hosp + (h * dh);
+            self.u_popf[(t + 1, 0)] = u + (h * du);
+            self.r_popf[(t + 1, 0)] = self.r_popf[(t, 0)] + (h * dr);
+            self.check_capacity(t + 1);
+            self.log_step(t + 1);
+        }
+        self.log_summary();
+        return self;
+    }
+
+    /// Construct array of runge-kutta intermediate values for each variable.
+    fn init_y(&self) -> [SystemVars; 5] {
+        return [
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+        ];
+    }
+
+    /// Construct array of runge-kutta constants for each variable.
+    fn init_k(&self) -> [SystemVars; 5] {
+        return [
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+            SystemVars {
+                s: 0.0,
+                i: 0.0,
+                h: 0.0,
+                u: 0.0,
+                r: 0.0,
+            },
+        ];
+    }
+
+    /// Construct array of step sizes corresponding to each runge-kutta order.
+    fn init_h(&self) -> [f64; 4] {
+        return [
+            self.step_size / 2.0,
+            self.step_size / 2.0,
+            self.step_size,
+            self.step_size,
+        ];
+    }
+
+    /// Compute a runge-kutta approximate function value.
+    fn next_y(&self, y: f64, k: f64, h: f64) -> f64 {
+        return y + (k * h);
+    }
+
+    /// Compute a 4th order runge-kutta time step for the system.
+    fn rk4_step(&self, t: usize) -> [SystemVars; 5] {
+        let mut y = self.init_y();
+        let mut k = self.init_k();
+        let h = self.init_h();
+        y[0].s = self.s_popf[(t, 0)];
+        y[0].i = self.i_popf[(t, 0)];
+        y[0].h = self.h_popf[(t, 0)];
+        y[0].u = self.u_popf[(t, 0)];
+        y[0].r = self.r_popf[(t, 0)];
+        for i in 0..4 {
+            k[i + 1].s = self.dsdt(y[i].s, y[i].i);
+            k[i + 1].i = self.didt(y[i].s, y[i].i);
+            k[i + 1].h = self.dhdt(y[i].i, y[i].h);
+            k[i + 1].u = self.dudt(y[i].h, y[i].u);
+            k[i + 1].r = self.drdt(y[i].i, y[i].h, y[i].u);
+            y[i + 1].s = self.next_y(y[0].s, k[i + 1].s, h[i]);
+            y[i + 1].i = self.next_y(y[0].i, k[i + 1].i, h[i]);
+            y[i + 1].h = self.next_y(y[0].h, k[i + 1].h, h[i]);
+            y[i + 1].u = self.next_y(y[0].u, k[i + 1].u, h[i]);
+            y[i + 1].r = self.next_y(y[0].r, k[i + 1].r, h[i]);
+        }
+        return k;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let _span = tracing::info_span!("run", model = "hospital", solver = "rk4").entered();
+        let n = self.grid().n_steps;
+        let w = self.step_size / 6.0;
+        for t in 0..n - 1 {
+            let k = self.rk4_step(t);
+            let ds = (k[1].s + (2.0 * k[2].s) + (2.0 * k[3].s) + k[4].s) * w;
+            let di = (k[1].i + (2.0 * k[2].i) + (2.0 * k[3].i) + k[4].i) * w;
+            let dh = (k[1].h + (2.0 * k[2].h) + (2.0 * k[3].h) + k[4].h) * w;
+            let du = (k[1].u + (2.0 * k[2].u) + (2.0 * k[3].u) + k[4].u) * w;
+            let dr = (k[1].r + (2.0 * k[2].r) + (2.0 * k[3].r) + k[4].r) * w;
+            self.s_popf[(t + 1, 0)] = self.s_popf[(t, 0)] + ds;
+            self.i_popf[(t + 1, 0)] = self.i_popf[(t, 0)] + di;
+            self.h_popf[(t + 1, 0)] = self.h_popf[(t, 0)] + dh;
+            self.u_popf[(t + 1, 0)] = self.u_popf[(t, 0)] + du;
+            self.r_popf[(t + 1, 0)] = self.r_popf[(t, 0)] + dr;
+            self.check_capacity(t + 1);
+            self.log_step(t + 1);
+        }
+        self.log_summary();
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::hospital::{CapacityEventKind, Model};
+    use crate::sirrs::units::{Duration, Fraction, Rate};
+    use faer::Mat;
+
+    #[test]
+    fn test_new() {
+        let model = Model::new();
+        assert_eq!(
+            model.length, 0,
+            "Bad length, expected 0 got {}",
+            model.length
+        );
+        assert_eq!(
+            model.h_popf,
+            Mat::new(),
+            "Bad h_popf, expected Mat::new() got {:?}",
+            model.h_popf,
+        );
+        assert_eq!(
+            model.u_popf,
+            Mat::new(),
+            "Bad u_popf, expected Mat::new() got {:?}",
+            model.u_popf,
+        );
+        assert!(
+            model.events.is_empty(),
+            "Bad events, expected none got {:?}",
+            model.events
+        );
+    }
+
+    #[test]
+    fn test_configure() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        assert_eq!(
+            model.h_popf.shape(),
+            (20, 1),
+            "Bad h_popf dimensions, expected {:?} got {:?}",
+            (20, 1),
+            model.h_popf.shape()
+        );
+        assert_eq!(
+            model.hospital_capacity,
+            f64::INFINITY,
+            "Bad hospital_capacity, expected inf got {}",
+            model.hospital_capacity
+        );
+        assert_eq!(
+            model.icu_stay, 10.0,
+            "Bad icu_stay, expected 10.0 got {}",
+            model.icu_stay
+        );
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)]
+                + model.i_popf[(t, 0)]
+                + model.h_popf[(t, 0)]
+                + model.u_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_capacity_events() {
+        let mut model = Model::new();
+        model.configure(
+            200,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        let peak_h = {
+            model.init_popf();
+            model.run_rk4();
+            (0..model.h_popf.nrows())
+                .map(|t| model.h_popf[(t, 0)])
+                .fold(0.0, f64::max)
+        };
+        model.capacity(peak_h / 2.0, f64::INFINITY);
+        model.init_popf();
+        model.run_rk4();
+        assert_eq!(
+            model.events.len(),
+            2,
+            "Bad number of events, expected 2 got {:?}",
+            model.events
+        );
+        assert_eq!(
+            model.events[0].kind,
+            CapacityEventKind::HospitalExceeded,
+            "Bad first event, expected HospitalExceeded got {:?}",
+            model.events[0].kind
+        );
+        assert_eq!(
+            model.events[1].kind,
+            CapacityEventKind::HospitalRecovered,
+            "Bad second event, expected HospitalRecovered got {:?}",
+            model.events[1].kind
+        );
+        assert!(
+            model.events[0].t < model.events[1].t,
+            "Events out of order, got {:?}",
+            model.events
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "capacities must be non-negative")]
+    fn test_capacity_rejects_nan() {
+        let mut model = Model::new();
+        model.capacity(f64::NAN, 0.01);
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        let y = [0.7, 0.1, 0.05, 0.02, 0.13];
+        let rhs = |y: &[f64]| {
+            return [
+                model.dsdt(y[0], y[1]),
+                model.didt(y[0], y[1]),
+                model.dhdt(y[1], y[2]),
+                model.dudt(y[2], y[3]),
+                model.drdt(y[1], y[2], y[3]),
+            ];
+        };
+        let jacobian = model.jacobian(0.0, &y);
+        let eps = 1e-7;
+        for j in 0..5 {
+            let mut up = y;
+            up[j] += eps;
+            let mut down = y;
+            down[j] -= eps;
+            let (f_up, f_down) = (rhs(&up), rhs(&down));
+            for i in 0..5 {
+                let expected = (f_up[i] - f_down[i]) / (2.0 * eps);
+                assert!(
+                    (jacobian[(i, j)] - expected).abs() < 1e-7,
+                    "Bad jacobian at {:?}, expected {} got {}",
+                    (i, j),
+                    expected,
+                    jacobian[(i, j)]
+                );
+            }
+        }
+        assert!(
+            !model.stability_at(0.0, &[1.0, 0.0, 0.0, 0.0, 0.0]).stable(),
+            "Expected an unstable disease-free state with R0 > 1"
+        );
+    }
+}
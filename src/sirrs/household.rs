@@ -0,0 +1,326 @@
+//! Stochastic household model with within-household and community
+//! transmission.
+//!
+//! The population is a set of households of given sizes. Every susceptible
+//! is exposed to two forces of infection over a step: a community force,
+//! shared by everyone, proportional to the infectious fraction of the whole
+//! population, and a household force proportional to the number of
+//! infectious members of their own household. In counts, for household `h`
+//! over a step of size `dt`:
+//!
+//! ```text
+//! new infections ~ Binomial(S[h], 1 - exp(-(β_c I / N + β_h I[h]) dt))
+//! removals       ~ Binomial(I[h], 1 - exp(-γ dt))
+//! ```
+//!
+//! New infections are attributed to the household or the community in
+//! proportion to the two forces, so household secondary attack rates can be
+//! compared with household transmission studies.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::reproducible::exp;
+use crate::sirrs::rng;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+use rand_distr::{Binomial, Distribution};
+
+/// Create and run a stochastic household model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Number of members of each household.
+    pub household_sizes: Vec<u64>,
+    /// Number of households starting with one infectious member, the first
+    /// `i_init` of `household_sizes`.
+    pub i_init: usize,
+    /// Rate at which one infectious member infects each susceptible member
+    /// of their household.
+    pub household_rate: f64,
+    /// Rate at which a susceptible is infected in the community by an
+    /// entirely infectious population.
+    pub community_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Susceptible count at each index. 1D Array with one element per index
+    /// of [`Model::grid`].
+    pub s: Mat<f64>,
+    /// Infectious count at each index. 1D Array with one element per index
+    /// of [`Model::grid`].
+    pub i: Mat<f64>,
+    /// Removed count at each index. 1D Array with one element per index of
+    /// [`Model::grid`].
+    pub r: Mat<f64>,
+    /// Cumulative infections acquired in the household up to each index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub household_infections: Mat<f64>,
+    /// Cumulative infections acquired in the community up to each index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub community_infections: Mat<f64>,
+    /// Number of members of each household ever infected, after a run.
+    pub final_sizes: Vec<u64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            household_sizes: Vec::new(),
+            i_init: 0,
+            household_rate: 0.0,
+            community_rate: 0.0,
+            removal_rate: 0.0,
+            seed: 0,
+            s: Mat::new(),
+            i: Mat::new(),
+            r: Mat::new(),
+            household_infections: Mat::new(),
+            community_infections: Mat::new(),
+            final_sizes: Vec::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        household_sizes: Vec<u64>,
+        i_init: usize,
+        household_rate: Rate,
+        community_rate: Rate,
+        removal_rate: Rate,
+        seed: u64,
+    ) -> &mut Self {
+        assert!(
+            i_init <= household_sizes.len(),
+            "i_init must not exceed the number of households, got {} > {}",
+            i_init,
+            household_sizes.len()
+        );
+        assert!(
+            household_sizes.iter().all(|n| *n > 0),
+            "Every household must have at least one member"
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.household_sizes = household_sizes;
+        self.i_init = i_init;
+        self.household_rate = household_rate.per(TimeUnit::Day);
+        self.community_rate = community_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.seed = seed;
+        self.s = Mat::zeros(n_steps, 1);
+        self.i = Mat::zeros(n_steps, 1);
+        self.r = Mat::zeros(n_steps, 1);
+        self.household_infections = Mat::zeros(n_steps, 1);
+        self.community_infections = Mat::zeros(n_steps, 1);
+        self.final_sizes = Vec::new();
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Total population size.
+    pub fn population(&self) -> u64 {
+        return self.household_sizes.iter().sum();
+    }
+
+    /// Simulate the epidemic.
+    pub fn run(&mut self) -> &Model {
+        let mut rng = rng::rng(self.seed);
+        let h = self.step_size;
+        let n_households = self.household_sizes.len();
+        let population = self.population() as f64;
+        let mut s: Vec<u64> = self.household_sizes.clone();
+        let mut i: Vec<u64> = vec![0; n_households];
+        for k in 0..self.i_init {
+            s[k] -= 1;
+            i[k] = 1;
+        }
+        let p_removal = 1.0 - exp(-self.removal_rate * h);
+        let mut from_household = 0.0;
+        let mut from_community = 0.0;
+        let mut removed = 0.0;
+        for t in 0..self.grid().n_steps {
+            let i_total: u64 = i.iter().sum();
+            self.s[(t, 0)] = s.iter().sum::<u64>() as f64;
+            self.i[(t, 0)] = i_total as f64;
+            self.r[(t, 0)] = removed;
+            self.household_infections[(t, 0)] = from_household;
+            self.community_infections[(t, 0)] = from_community;
+            if t == self.grid().n_steps - 1 {
+                break;
+            }
+            let community = self.community_rate * (i_total as f64) / population;
+            for k in 0..n_households {
+                let household = self.household_rate * (i[k] as f64);
+                let hazard = community + household;
+                let new_i = if (s[k] == 0) | (hazard <= 0.0) {
+                    0
+                } else {
+                    Binomial::new(s[k], 1.0 - exp(-hazard * h))
+                        .unwrap()
+                        .sample(&mut rng)
+                };
+                let new_r = if i[k] == 0 {
+                    0
+                } else {
+                    Binomial::new(i[k], p_removal).unwrap().sample(&mut rng)
+                };
+                if new_i > 0 {
+                    let in_household = Binomial::new(new_i, household / hazard)
+                        .unwrap()
+                        .sample(&mut rng);
+                    from_household += in_household as f64;
+                    from_community += (new_i - in_household) as f64;
+                }
+                s[k] -= new_i;
+                i[k] = i[k] + new_i - new_r;
+                removed += new_r as f64;
+            }
+        }
+        self.final_sizes = self
+            .household_sizes
+            .iter()
+            .zip(s.iter())
+            .map(|(n, s)| n - s)
+            .collect();
+        return self;
+    }
+
+    /// Household secondary attack rate: the fraction of members other than
+    /// the first case who were ever infected, pooled over households with
+    /// at least one infection and more than one member. NaN if there are
+    /// none.
+    pub fn secondary_attack_rate(&self) -> f64 {
+        let mut secondary = 0.0;
+        let mut contacts = 0.0;
+        for (n, infected) in self.household_sizes.iter().zip(self.final_sizes.iter()) {
+            if (*infected == 0) | (*n < 2) {
+                continue;
+            }
+            secondary += (infected - 1) as f64;
+            contacts += (n - 1) as f64;
+        }
+        return secondary / contacts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::household::Model;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_run_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.5,
+            [1, 2, 3, 4, 5].repeat(40),
+            5,
+            Rate::per_day(0.3),
+            Rate::per_day(0.4),
+            Rate::per_day(0.2),
+            3,
+        );
+        model.run();
+        let population = model.population() as f64;
+        for t in 0..model.s.nrows() {
+            let total = model.s[(t, 0)] + model.i[(t, 0)] + model.r[(t, 0)];
+            assert_eq!(
+                total, population,
+                "Population not conserved at index {}, got {}",
+                t, total
+            );
+        }
+        let last = model.s.nrows() - 1;
+        let infections =
+            model.household_infections[(last, 0)] + model.community_infections[(last, 0)];
+        assert_eq!(
+            infections + 5.0,
+            population - model.s[(last, 0)],
+            "Attributed infections do not match the susceptibles lost"
+        );
+        let mut again = Model::new();
+        again.configure(
+            60,
+            0.5,
+            [1, 2, 3, 4, 5].repeat(40),
+            5,
+            Rate::per_day(0.3),
+            Rate::per_day(0.4),
+            Rate::per_day(0.2),
+            3,
+        );
+        again.run();
+        assert_eq!(model.i, again.i, "Same seed gave different trajectories");
+    }
+
+    #[test]
+    fn test_no_community_transmission() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            vec![4; 50],
+            10,
+            Rate::per_day(0.5),
+            Rate::per_day(0.0),
+            Rate::per_day(0.2),
+            1,
+        );
+        model.run();
+        for (k, size) in model.final_sizes.iter().enumerate().skip(10) {
+            assert_eq!(
+                *size, 0,
+                "Expected no infections outside seeded households, household {} got {}",
+                k, size
+            );
+        }
+        let last = model.s.nrows() - 1;
+        assert_eq!(
+            model.community_infections[(last, 0)],
+            0.0,
+            "Expected no community infections"
+        );
+    }
+
+    #[test]
+    fn test_secondary_attack_rate() {
+        // In pairs the contact is infected before the case is removed with
+        // probability p / (1 - (1 - p)(1 - q)), p and q the per-step
+        // infection and removal probabilities.
+        let mut model = Model::new();
+        model.configure(
+            200,
+            0.1,
+            vec![2; 5000],
+            5000,
+            Rate::per_day(0.3),
+            Rate::per_day(0.0),
+            Rate::per_day(0.1),
+            11,
+        );
+        model.run();
+        let p = 1.0 - (-0.03_f64).exp();
+        let q = 1.0 - (-0.01_f64).exp();
+        let expected = p / (1.0 - ((1.0 - p) * (1.0 - q)));
+        let sar = model.secondary_attack_rate();
+        assert!(
+            (sar - expected).abs() < 0.03,
+            "Bad secondary attack rate, expected {} got {}",
+            expected,
+            sar
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Exogenous input series on the solver's time grid.
+//!
+//! An [`InputSeries`] holds observations of an external quantity, such as a
+//! daily mobility index, testing rate or vaccine coverage, and evaluates it
+//! at any time by [`Interpolation`]. [`InputSeries::on_grid`] puts the
+//! series on a solver grid, averaging over each step so that steps longer
+//! than a day aggregate the data rather than sample it. An
+//! [`InputParameter`] feeds a series into a [`crate::pipeline::Pipeline`]
+//! parameter.
+use crate::sirrs::data::parse_date;
+use crate::sirrs::pipeline::{Intervention, Parameters};
+use faer::Mat;
+use std::io::{Error, ErrorKind};
+
+/// How values between observations are filled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Hold each value until the next observation.
+    Step,
+    /// Straight lines between observations.
+    Linear,
+    /// Natural cubic spline through the observations.
+    Spline,
+}
+
+/// An input observed at a sequence of times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSeries {
+    /// Observation times, strictly increasing.
+    pub times: Vec<f64>,
+    /// Value observed at each time.
+    pub values: Vec<f64>,
+    /// How values between observations are filled in.
+    pub interpolation: Interpolation,
+    /// Spline second derivative at each time, empty unless
+    /// `interpolation` is [`Interpolation::Spline`].
+    curvature: Vec<f64>,
+}
+
+impl InputSeries {
+    /// A series of `values` observed at `times`, which must be strictly
+    /// increasing. Outside the observed times the first or last value is
+    /// held.
+    pub fn new(times: Vec<f64>, values: Vec<f64>, interpolation: Interpolation) -> Self {
+        assert_eq!(
+            times.len(),
+            values.len(),
+            "times and values must have the same length"
+        );
+        assert!(!times.is_empty(), "series must have at least one value");
+        assert!(
+            times.windows(2).all(|w| w[0] < w[1]),
+            "times must be strictly increasing"
+        );
+        let curvature = match interpolation {
+            Interpolation::Spline => natural_spline(&times, &values),
+            _ => Vec::new(),
+        };
+        return Self {
+            times,
+            values,
+            interpolation,
+            curvature,
+        };
+    }
+
+    /// A series of one value per day, starting at `t = 0`.
+    pub fn daily(values: Vec<f64>, interpolation: Interpolation) -> Self {
+        let times = (0..values.len()).map(|d| d as f64).collect();
+        return Self::new(times, values, interpolation);
+    }
+
+    /// A series of `(date, value)` observations, where dates are ISO 8601
+    /// `YYYY-MM-DD` and converted to days since `start_date`.
+    pub fn from_dates(
+        observations: &[(&str, f64)],
+        start_date: &str,
+        interpolation: Interpolation,
+    ) -> Result<Self, Error> {
+        let bad_date =
+            |date: &str| Error::new(ErrorKind::InvalidInput, format!("bad date '{}'", date));
+        let start = parse_date(start_date).ok_or_else(|| bad_date(start_date))?;
+        let mut sorted = Vec::with_capacity(observations.len());
+        for (date, value) in observations.iter() {
+            let day = parse_date(date).ok_or_else(|| bad_date(date))?;
+            sorted.push(((day - start) as f64, *value));
+        }
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if sorted.is_empty() | sorted.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "expected at least one observation and no repeated dates",
+            ));
+        }
+        let (times, values) = sorted.into_iter().unzip();
+        return Ok(Self::new(times, values, interpolation));
+    }
+
+    /// Index of the interval `[times[k], times[k + 1])` containing `t`.
+    /// Only valid for `t` within the observed times.
+    fn interval(&self, t: f64) -> usize {
+        let k = self.times.partition_point(|&x| x <= t);
+        return k.saturating_sub(1).min(self.times.len().saturating_sub(2));
+    }
+
+    /// Value of the interpolant on interval `k` at time `t`.
+    fn segment(&self, k: usize, t: f64) -> f64 {
+        if self.times.len() == 1 {
+            return self.values[0];
+        }
+        let (t0, t1) = (self.times[k], self.times[k + 1]);
+        let (y0, y1) = (self.values[k], self.values[k + 1]);
+        let h = t1 - t0;
+        let a = (t1 - t) / h;
+        let b = (t - t0) / h;
+        match self.interpolation {
+            Interpolation::Step => return y0,
+            Interpolation::Linear => return (a * y0) + (b * y1),
+            Interpolation::Spline => {
+                let (m0, m1) = (self.curvature[k], self.curvature[k + 1]);
+                return (a * y0)
+                    + (b * y1)
+                    + ((((a.powi(3) - a) * m0) + ((b.powi(3) - b) * m1)) * h.powi(2) / 6.0);
+            }
+        }
+    }
+
+    /// Value of the series at time `t`.
+    pub fn at(&self, t: f64) -> f64 {
+        let n = self.times.len();
+        if t <= self.times[0] {
+            return self.values[0];
+        }
+        if t >= self.times[n - 1] {
+            return self.values[n - 1];
+        }
+        return self.segment(self.interval(t), t);
+    }
+
+    /// Mean value of the series over `[a, b)`.
+    ///
+    /// The interpolant is a polynomial of degree at most three between
+    /// observations, so Simpson's rule on each piece is exact.
+    pub fn mean(&self, a: f64, b: f64) -> f64 {
+        assert!(b > a, "b must be greater than a");
+        let n = self.times.len();
+        let mut knots = vec![a];
+        knots.extend(self.times.iter().copied().filter(|&x| (x > a) & (x < b)));
+        knots.push(b);
+        let mut integral = 0.0;
+        for w in knots.windows(2) {
+            let (lo, hi) = (w[0], w[1]);
+            let mid = 0.5 * (lo + hi);
+            let value = |t: f64| {
+                if (mid <= self.times[0]) | (mid >= self.times[n - 1]) {
+                    return self.at(mid);
+                }
+                return self.segment(self.interval(mid), t);
+            };
+            integral += (hi - lo) * (value(lo) + (4.0 * value(mid)) + value(hi)) / 6.0;
+        }
+        return integral / (b - a);
+    }
+
+    /// The series on a solver grid of `length` indices spaced `step_size`
+    /// apart, one row per index. Each row is the mean over the step
+    /// starting at that index.
+    pub fn on_grid(&self, length: usize, step_size: f64) -> Mat<f64> {
+        let n_steps = (length as f64 / step_size).ceil() as usize;
+        return Mat::from_fn(n_steps, 1, |t, _| {
+            let start = (t as f64) * step_size;
+            return self.mean(start, start + step_size);
+        });
+    }
+}
+
+/// Second derivatives of the natural cubic spline through `(times, values)`.
+fn natural_spline(times: &[f64], values: &[f64]) -> Vec<f64> {
+    let n = times.len();
+    let mut curvature = vec![0.0; n];
+    if n < 3 {
+        return curvature;
+    }
+    // Tridiagonal system for the interior points, solved by the Thomas
+    // algorithm.
+    let mut diagonal = vec![0.0; n];
+    let mut rhs = vec![0.0; n];
+    for k in 1..(n - 1) {
+        let h0 = times[k] - times[k - 1];
+        let h1 = times[k + 1] - times[k];
+        diagonal[k] = 2.0 * (h0 + h1);
+        rhs[k] = 6.0 * (((values[k + 1] - values[k]) / h1) - ((values[k] - values[k - 1]) / h0));
+        if k > 1 {
+            let factor = h0 / diagonal[k - 1];
+            diagonal[k] -= factor * h0;
+            rhs[k] -= factor * rhs[k - 1];
+        }
+    }
+    for k in (1..(n - 1)).rev() {
+        let h1 = times[k + 1] - times[k];
+        curvature[k] = (rhs[k] - (h1 * curvature[k + 1])) / diagonal[k];
+    }
+    return curvature;
+}
+
+/// Set or scale one pipeline parameter by an input series.
+pub struct InputParameter {
+    /// Name of the parameter.
+    pub name: String,
+    /// Input over time.
+    pub series: InputSeries,
+    /// Multiply the parameter by the input rather than replace it.
+    pub scale: bool,
+}
+
+impl InputParameter {
+    /// Replace the parameter with the value of `series`.
+    pub fn set(name: &str, series: InputSeries) -> Self {
+        return Self {
+            name: name.to_string(),
+            series,
+            scale: false,
+        };
+    }
+
+    /// Multiply the parameter by the value of `series`, for example an
+    /// incidence rate by a mobility index.
+    pub fn scale(name: &str, series: InputSeries) -> Self {
+        return Self {
+            name: name.to_string(),
+            series,
+            scale: true,
+        };
+    }
+}
+
+impl Intervention for InputParameter {
+    fn apply(&self, t: f64, parameters: &mut Parameters) {
+        if let Some(value) = parameters.get_mut(&self.name) {
+            let input = self.series.at(t);
+            *value = if self.scale { *value * input } else { input };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::input::{InputSeries, Interpolation};
+
+    #[test]
+    fn test_at() {
+        let values = vec![1.0, 3.0, 2.0, 2.0];
+        for (interpolation, expected) in [
+            (Interpolation::Step, 1.0),
+            (Interpolation::Linear, 2.0),
+            (Interpolation::Spline, 2.325),
+        ] {
+            let series = InputSeries::daily(values.clone(), interpolation);
+            assert!(
+                (series.at(0.5) - expected).abs() < 1e-12,
+                "Bad {:?} value at 0.5, expected {} got {}",
+                interpolation,
+                expected,
+                series.at(0.5)
+            );
+            for (d, value) in values.iter().enumerate() {
+                assert_eq!(
+                    series.at(d as f64),
+                    *value,
+                    "Bad {:?} value at observation {}",
+                    interpolation,
+                    d
+                );
+            }
+            assert_eq!(series.at(-1.0), 1.0, "Bad value before first observation");
+            assert_eq!(series.at(10.0), 2.0, "Bad value after last observation");
+        }
+    }
+
+    #[test]
+    fn test_on_grid() {
+        let series = InputSeries::daily(vec![1.0, 3.0, 5.0, 7.0], Interpolation::Linear);
+        let fine = series.on_grid(2, 0.5);
+        assert_eq!(fine.nrows(), 4, "Bad number of rows, got {}", fine.nrows());
+        assert!(
+            (fine[(1, 0)] - 2.5).abs() < 1e-12,
+            "Bad mean over [0.5, 1), expected 2.5 got {}",
+            fine[(1, 0)]
+        );
+        let step = InputSeries::daily(vec![1.0, 3.0, 5.0, 7.0], Interpolation::Step);
+        let coarse = step.on_grid(4, 2.0);
+        assert_eq!(
+            (coarse[(0, 0)], coarse[(1, 0)]),
+            (2.0, 6.0),
+            "Bad aggregated means, got {:?}",
+            coarse
+        );
+    }
+
+    #[test]
+    fn test_from_dates() {
+        let series = InputSeries::from_dates(
+            &[("2020-03-03", 0.5), ("2020-03-01", 1.0)],
+            "2020-03-01",
+            Interpolation::Linear,
+        )
+        .unwrap();
+        assert_eq!(
+            series.times,
+            vec![0.0, 2.0],
+            "Bad times, got {:?}",
+            series.times
+        );
+        assert_eq!(series.at(1.0), 0.75, "Bad interpolated value");
+        assert!(
+            InputSeries::from_dates(&[], "2020-03-01", Interpolation::Step).is_err(),
+            "Expected error on empty series"
+        );
+    }
+}
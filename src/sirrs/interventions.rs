@@ -0,0 +1,395 @@
+//! Named interventions and scenario comparison.
+//!
+//! An [`Intervention`] multiplies one named rate while it is active, for
+//! example halving `incidence_rate` during a lockdown. Interventions are
+//! grouped into named [`Scenario`]s, and [`compare`] runs every scenario on
+//! the same system through a [`Pipeline`], returning results keyed by
+//! scenario name. [`counterfactual`] reports what each scenario averts
+//! relative to a baseline, and [`counterfactual_ensemble`] repeats that over
+//! an ensemble of systems to give uncertainty intervals.
+use crate::sirrs::pipeline::{self, Parameters, Pipeline, System};
+use faer::Mat;
+use std::collections::BTreeMap;
+
+/// A named, time-bounded multiplicative effect on one rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intervention {
+    /// Name of the intervention.
+    pub name: String,
+    /// Name of the rate it acts on, as given by [`System::parameters`].
+    pub rate: String,
+    /// Time the intervention starts.
+    pub start: f64,
+    /// Time the intervention ends.
+    pub end: f64,
+    /// Factor applied to the rate while `start <= t < end`.
+    pub effect: f64,
+}
+
+impl Intervention {
+    /// Create a new intervention.
+    pub fn new(name: &str, rate: &str, start: f64, end: f64, effect: f64) -> Self {
+        return Self {
+            name: name.to_string(),
+            rate: rate.to_string(),
+            start,
+            end,
+            effect,
+        };
+    }
+}
+
+impl pipeline::Intervention for Intervention {
+    fn apply(&self, t: f64, parameters: &mut Parameters) {
+        if (self.start <= t) & (t < self.end)
+            && let Some(value) = parameters.get_mut(&self.rate)
+        {
+            *value *= self.effect;
+        }
+    }
+}
+
+/// A named set of interventions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    /// Name of the scenario.
+    pub name: String,
+    /// Interventions in effect, applied in order.
+    pub interventions: Vec<Intervention>,
+}
+
+impl Scenario {
+    /// Create a scenario with no interventions.
+    pub fn new(name: &str) -> Self {
+        return Self {
+            name: name.to_string(),
+            interventions: Vec::new(),
+        };
+    }
+
+    /// Attach an intervention.
+    pub fn intervention(&mut self, intervention: Intervention) -> &mut Self {
+        self.interventions.push(intervention);
+        return self;
+    }
+}
+
+/// Result of running one scenario.
+pub struct ScenarioResult {
+    /// Each state variable (column) at each index (row).
+    pub state: Mat<f64>,
+    /// New cases over the step ending at each index.
+    pub incidence: Mat<f64>,
+    /// Total new cases over the run.
+    pub total_incidence: f64,
+    /// Time and value of the largest per-step incidence.
+    pub peak_incidence: (f64, f64),
+}
+
+/// Run every scenario on a fresh system from `system` and collect the
+/// results by scenario name.
+pub fn compare(
+    system: impl Fn() -> Box<dyn System>,
+    length: usize,
+    step_size: f64,
+    scenarios: &[Scenario],
+) -> BTreeMap<String, ScenarioResult> {
+    let mut results = BTreeMap::new();
+    for scenario in scenarios.iter() {
+        let mut pipeline = Pipeline::new(system());
+        pipeline.configure(length, step_size);
+        for intervention in scenario.interventions.iter() {
+            pipeline.intervention(Box::new(intervention.clone()));
+        }
+        pipeline.run_rk4();
+        let n = pipeline.incidence.nrows();
+        let peak = (0..n)
+            .max_by(|&a, &b| pipeline.incidence[(a, 0)].total_cmp(&pipeline.incidence[(b, 0)]))
+            .unwrap();
+        let result = ScenarioResult {
+            total_incidence: (0..n).map(|t| pipeline.incidence[(t, 0)]).sum(),
+            peak_incidence: ((peak as f64) * step_size, pipeline.incidence[(peak, 0)]),
+            state: pipeline.state,
+            incidence: pipeline.incidence,
+        };
+        results.insert(scenario.name.clone(), result);
+    }
+    return results;
+}
+
+/// Outcomes of a scenario relative to a baseline. Positive values are
+/// improvements over the baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Averted {
+    /// Baseline total incidence minus scenario total incidence.
+    pub cases: f64,
+    /// Baseline peak incidence minus scenario peak incidence.
+    pub peak: f64,
+    /// Cases averted times the infection fatality ratio.
+    pub deaths: f64,
+}
+
+/// Outcomes averted by each scenario in `results` relative to the scenario
+/// named `baseline`, with deaths from a constant infection fatality ratio.
+pub fn counterfactual(
+    results: &BTreeMap<String, ScenarioResult>,
+    baseline: &str,
+    fatality_ratio: f64,
+) -> BTreeMap<String, Averted> {
+    let reference = results
+        .get(baseline)
+        .unwrap_or_else(|| panic!("no scenario named {}", baseline));
+    let mut averted = BTreeMap::new();
+    for (name, result) in results.iter() {
+        if name == baseline {
+            continue;
+        }
+        let cases = reference.total_incidence - result.total_incidence;
+        averted.insert(
+            name.clone(),
+            Averted {
+                cases,
+                peak: reference.peak_incidence.1 - result.peak_incidence.1,
+                deaths: fatality_ratio * cases,
+            },
+        );
+    }
+    return averted;
+}
+
+/// Mean and central interval of one outcome over an ensemble.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// Ensemble mean.
+    pub mean: f64,
+    /// Lower quantile.
+    pub lower: f64,
+    /// Upper quantile.
+    pub upper: f64,
+}
+
+impl Interval {
+    /// Mean and central `level` interval of `values`, by linear
+    /// interpolation between order statistics.
+    pub fn from_values(values: &[f64], level: f64) -> Self {
+        assert!(!values.is_empty(), "values must not be empty");
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let quantile = |p: f64| {
+            let x = p * ((sorted.len() - 1) as f64);
+            let k = x.floor() as usize;
+            let next = (k + 1).min(sorted.len() - 1);
+            return sorted[k] + ((x - (k as f64)) * (sorted[next] - sorted[k]));
+        };
+        return Self {
+            mean: sorted.iter().sum::<f64>() / (sorted.len() as f64),
+            lower: quantile(0.5 * (1.0 - level)),
+            upper: quantile(0.5 * (1.0 + level)),
+        };
+    }
+}
+
+/// Uncertainty intervals of the outcomes averted by a scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AvertedIntervals {
+    /// Cases averted.
+    pub cases: Interval,
+    /// Peak incidence reduction.
+    pub peak: Interval,
+    /// Deaths averted.
+    pub deaths: Interval,
+}
+
+/// Outcomes averted by each scenario relative to `baseline`, over an
+/// ensemble of `n_members` systems.
+///
+/// `system(m)` builds ensemble member `m`, for example with rates drawn
+/// from a posterior, and every scenario is run on every member so the
+/// differences are paired. Intervals are central with coverage `level`.
+pub fn counterfactual_ensemble(
+    system: impl Fn(usize) -> Box<dyn System>,
+    n_members: usize,
+    length: usize,
+    step_size: f64,
+    baseline: &Scenario,
+    scenarios: &[Scenario],
+    fatality_ratio: f64,
+    level: f64,
+) -> BTreeMap<String, AvertedIntervals> {
+    let mut all = vec![baseline.clone()];
+    all.extend(scenarios.iter().cloned());
+    let mut draws: BTreeMap<String, Vec<Averted>> = BTreeMap::new();
+    for m in 0..n_members {
+        let results = compare(|| system(m), length, step_size, &all);
+        for (name, averted) in counterfactual(&results, &baseline.name, fatality_ratio) {
+            draws.entry(name).or_default().push(averted);
+        }
+    }
+    let mut intervals = BTreeMap::new();
+    for (name, values) in draws.iter() {
+        let outcome = |f: fn(&Averted) -> f64| {
+            let x: Vec<f64> = values.iter().map(f).collect();
+            return Interval::from_values(&x, level);
+        };
+        intervals.insert(
+            name.clone(),
+            AvertedIntervals {
+                cases: outcome(|a| a.cases),
+                peak: outcome(|a| a.peak),
+                deaths: outcome(|a| a.deaths),
+            },
+        );
+    }
+    return intervals;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::interventions::{
+        Interval, Intervention, Scenario, compare, counterfactual, counterfactual_ensemble,
+    };
+    use crate::sirrs::pipeline::System;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    fn system() -> Box<dyn System> {
+        let mut model = sir::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.001,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        return Box::new(model);
+    }
+
+    #[test]
+    fn test_compare_scenarios() {
+        let baseline = Scenario::new("baseline");
+        let mut lockdown = Scenario::new("lockdown");
+        lockdown.intervention(Intervention::new(
+            "lockdown",
+            "incidence_rate",
+            10.0,
+            40.0,
+            0.3,
+        ));
+        let mut combined = lockdown.clone();
+        combined.name = "lockdown and treatment".to_string();
+        combined.intervention(Intervention::new(
+            "treatment",
+            "removal_rate",
+            0.0,
+            100.0,
+            1.5,
+        ));
+        let results = compare(system, 100, 0.5, &[baseline, lockdown, combined]);
+        assert_eq!(
+            results.len(),
+            3,
+            "Bad number of results, got {}",
+            results.len()
+        );
+        let size = |name: &str| results[name].total_incidence;
+        assert!(
+            size("lockdown") < size("baseline"),
+            "Expected lockdown to reduce infections, got {} vs {}",
+            size("lockdown"),
+            size("baseline")
+        );
+        assert!(
+            size("lockdown and treatment") < size("lockdown"),
+            "Expected treatment to reduce infections further, got {} vs {}",
+            size("lockdown and treatment"),
+            size("lockdown")
+        );
+        assert!(
+            results["lockdown"].peak_incidence.0 > results["baseline"].peak_incidence.0,
+            "Expected lockdown to delay the peak"
+        );
+    }
+
+    #[test]
+    fn test_counterfactual() {
+        let baseline = Scenario::new("baseline");
+        let mut lockdown = Scenario::new("lockdown");
+        lockdown.intervention(Intervention::new(
+            "lockdown",
+            "incidence_rate",
+            10.0,
+            40.0,
+            0.3,
+        ));
+        let results = compare(system, 100, 0.5, &[baseline, lockdown]);
+        let averted = counterfactual(&results, "baseline", 0.01);
+        assert_eq!(averted.len(), 1, "Expected only non-baseline scenarios");
+        let lockdown = averted["lockdown"];
+        assert!(
+            (lockdown.cases > 0.0) & (lockdown.peak > 0.0),
+            "Expected lockdown to avert cases and reduce the peak, got {:?}",
+            lockdown
+        );
+        assert!(
+            (lockdown.deaths - (0.01 * lockdown.cases)).abs() < 1e-15,
+            "Bad deaths averted, expected {} got {}",
+            0.01 * lockdown.cases,
+            lockdown.deaths
+        );
+    }
+
+    #[test]
+    fn test_counterfactual_ensemble() {
+        let member = |m: usize| -> Box<dyn System> {
+            let mut model = sir::Model::new();
+            let beta = 0.3 + (0.05 * (m as f64));
+            model.configure(
+                100,
+                0.5,
+                0.001,
+                0.0,
+                Rate::per_day(beta),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            return Box::new(model);
+        };
+        let mut lockdown = Scenario::new("lockdown");
+        lockdown.intervention(Intervention::new(
+            "lockdown",
+            "incidence_rate",
+            10.0,
+            40.0,
+            0.3,
+        ));
+        let intervals = counterfactual_ensemble(
+            member,
+            5,
+            100,
+            0.5,
+            &Scenario::new("baseline"),
+            &[lockdown],
+            0.01,
+            0.9,
+        );
+        let cases = intervals["lockdown"].cases;
+        assert!(
+            (cases.lower < cases.mean) & (cases.mean < cases.upper),
+            "Expected mean inside a non-degenerate interval, got {:?}",
+            cases
+        );
+    }
+
+    #[test]
+    fn test_interval() {
+        let interval = Interval::from_values(&[4.0, 0.0, 2.0, 1.0, 3.0], 0.5);
+        assert_eq!(
+            (interval.mean, interval.lower, interval.upper),
+            (2.0, 1.0, 3.0),
+            "Bad interval, got {:?}",
+            interval
+        );
+    }
+}
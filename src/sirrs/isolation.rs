@@ -0,0 +1,342 @@
+//! SIR model with a delay from infection to case isolation.
+//!
+//! A fraction `p` of cases, for example those found by testing, are isolated
+//! some time after infection and stop transmitting, while every case is
+//! removed at rate γ. The delay from infection to isolation is a discretized
+//! kernel: `delay[d]` is the probability of isolation between `d` and
+//! `d + 1` unit times after infection, spread uniformly within the unit, so
+//! testing turnaround times can be compared directly.
+//!
+//! The model is solved in age of infection. A cohort infected τ ago is
+//! infectious with weight `e^(-γτ) (1 - p F(τ))`, isolated with weight
+//! `e^(-γτ) p F(τ)` and removed with weight `1 - e^(-γτ)`, with `F` the
+//! cumulative delay distribution, so the isolated (Q) fraction is tracked
+//! without extra stages. With `p = 0` this is the SIR model with removal
+//! only, I → R.
+//!
+//! Isolation shortens the effective infectious period from `1 / γ` to
+//! `(1 - p P(isolated before removal)) / γ`, and the reproduction number and
+//! growth rate follow in closed form from the Laplace transform of the delay
+//! kernel.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::reproducible::exp;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run an SIR model with delayed case isolation.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction, infected at time 0.
+    pub i_popf_init: f64,
+    /// Transition rate from S into I.
+    pub incidence_rate: f64,
+    /// Transition rate from I (or Q) into R.
+    pub removal_rate: f64,
+    /// Fraction of cases that are isolated if not removed first. Must be in
+    /// [0, 1].
+    pub isolated_fraction: f64,
+    /// Probability of isolation between `d` and `d + 1` unit times after
+    /// infection, for each `d`. Normalized to sum to 1.
+    pub delay: Vec<f64>,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious, not yet isolated, population fraction at each index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Isolated population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub q_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// New infections, as a population fraction, over the step ending at
+    /// each index. 1D Array with one element per index of [`Model::grid`],
+    /// the initial infections at index 0.
+    pub incidence: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            isolated_fraction: 0.0,
+            delay: vec![1.0],
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            q_popf: Mat::new(),
+            r_popf: Mat::new(),
+            incidence: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. The delay kernel is normalized to sum to
+    /// 1.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        isolated_fraction: Fraction,
+        delay: Vec<f64>,
+    ) -> &mut Self {
+        let total: f64 = delay.iter().sum();
+        assert!(
+            total > 0.0,
+            "delay kernel must have positive total, got {}",
+            total
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.isolated_fraction = isolated_fraction.value();
+        self.delay = delay.iter().map(|p| p / total).collect();
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.q_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.incidence = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment, with the initial infectious fraction just infected.
+    pub fn init_popf(&mut self) -> &mut Model {
+        self.s_popf[(0, 0)] = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+        self.i_popf[(0, 0)] = self.i_popf_init;
+        self.q_popf[(0, 0)] = 0.0;
+        self.r_popf[(0, 0)] = 0.0;
+        self.incidence[(0, 0)] = self.i_popf_init;
+        return self;
+    }
+
+    /// Cumulative probability of a scheduled isolation by `age` unit times
+    /// after infection.
+    pub fn delay_cdf(&self, age: f64) -> f64 {
+        let whole = age.floor() as usize;
+        let mut cdf: f64 = self.delay.iter().take(whole).sum();
+        if whole < self.delay.len() {
+            cdf += (age - (whole as f64)) * self.delay[whole];
+        }
+        return cdf.min(1.0);
+    }
+
+    /// Laplace transform `∫ e^(-ατ) f(τ) dτ` of the delay density at
+    /// `alpha > 0`, the density being constant within each unit.
+    fn delay_transform(&self, alpha: f64) -> f64 {
+        let unit = (1.0 - exp(-alpha)) / alpha;
+        return self
+            .delay
+            .iter()
+            .enumerate()
+            .map(|(d, p)| p * exp(-alpha * (d as f64)) * unit)
+            .sum();
+    }
+
+    /// Probability that a case is isolated before it is removed.
+    pub fn isolated_before_removal(&self) -> f64 {
+        return self.isolated_fraction * self.delay_transform(self.removal_rate);
+    }
+
+    /// Mean time a case spends infectious and not isolated, `1 / γ`
+    /// shortened by isolation.
+    pub fn effective_infectious_period(&self) -> f64 {
+        return (1.0 - self.isolated_before_removal()) / self.removal_rate;
+    }
+
+    /// Reproduction number under isolation, `β` times the effective
+    /// infectious period.
+    pub fn reproduction_number(&self) -> f64 {
+        return self.incidence_rate * self.effective_infectious_period();
+    }
+
+    /// Initial exponential growth rate `r`, the root of the Euler-Lotka
+    /// equation `β ∫ e^(-(r + γ)τ) (1 - p F(τ)) dτ = 1`, found by
+    /// bisection. Negative when the epidemic declines, and at least `-γ`.
+    pub fn growth_rate(&self) -> f64 {
+        let beta = self.incidence_rate;
+        let gamma = self.removal_rate;
+        if beta <= 0.0 {
+            return -gamma;
+        }
+        // β ∫ e^(-ατ) (1 - p F(τ)) dτ, decreasing in α and at most β / α.
+        let lotka = |alpha: f64| -> f64 {
+            return beta * (1.0 - (self.isolated_fraction * self.delay_transform(alpha))) / alpha;
+        };
+        let (mut lo, mut hi) = (0.0, beta);
+        for _ in 0..200 {
+            let mid = 0.5 * (lo + hi);
+            if lotka(mid) > 1.0 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        return (0.5 * (lo + hi)) - gamma;
+    }
+
+    /// Infectious, isolated and removed weights of a cohort infected `age`
+    /// unit times ago.
+    fn cohort_weights(&self, age: f64) -> [f64; 3] {
+        let remaining = exp(-self.removal_rate * age);
+        let isolated = self.isolated_fraction * self.delay_cdf(age);
+        return [
+            remaining * (1.0 - isolated),
+            remaining * isolated,
+            1.0 - remaining,
+        ];
+    }
+
+    /// Run the model in age of infection. Infections over each step are
+    /// `S (1 - e^(-β I h))`, with `I` at the start of the step, and join a
+    /// cohort aged from the end of the step.
+    pub fn run(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = self.s_popf.nrows();
+        let weights: Vec<[f64; 3]> = (0..n)
+            .map(|age| self.cohort_weights((age as f64) * h))
+            .collect();
+        for t in 1..n {
+            let (s, i) = (self.s_popf[(t - 1, 0)], self.i_popf[(t - 1, 0)]);
+            let infections = s * (1.0 - exp(-self.incidence_rate * i * h));
+            self.incidence[(t, 0)] = infections;
+            self.s_popf[(t, 0)] = s - infections;
+            let mut state = [0.0; 3];
+            for k in 0..=t {
+                let cohort = self.incidence[(k, 0)];
+                for (x, w) in state.iter_mut().zip(weights[t - k].iter()) {
+                    *x += cohort * w;
+                }
+            }
+            self.i_popf[(t, 0)] = state[0];
+            self.q_popf[(t, 0)] = state[1];
+            self.r_popf[(t, 0)] = state[2];
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::isolation::Model;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::{Fraction, Rate};
+
+    fn model(isolated_fraction: f64, delay: Vec<f64>) -> Model {
+        let mut model = Model::new();
+        model.configure(
+            120,
+            0.05,
+            0.001,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Fraction::of(isolated_fraction),
+            delay,
+        );
+        model.init_popf();
+        model.run();
+        return model;
+    }
+
+    #[test]
+    fn test_no_isolation_matches_sir() {
+        let model = model(0.0, vec![1.0]);
+        let mut sir = sir::Model::new();
+        sir.configure(
+            120,
+            0.05,
+            0.001,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 5e-3,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+            let total = model.s_popf[(t, 0)]
+                + model.i_popf[(t, 0)]
+                + model.q_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-12,
+                "Population not conserved at index {}, got {}",
+                t,
+                total
+            );
+        }
+        assert!(
+            (model.growth_rate() - 0.3).abs() < 1e-9,
+            "Bad growth rate, expected 0.3 got {}",
+            model.growth_rate()
+        );
+        assert!(
+            (model.reproduction_number() - 4.0).abs() < 1e-12,
+            "Bad reproduction number, expected 4 got {}",
+            model.reproduction_number()
+        );
+    }
+
+    #[test]
+    fn test_isolation_delay() {
+        // Isolation between 1 and 2 unit times after infection, or between
+        // 4 and 5.
+        let fast = model(0.8, vec![0.0, 1.0]);
+        let slow = model(0.8, vec![0.0, 0.0, 0.0, 0.0, 1.0]);
+        let expected = 0.8 * (1.0 - (-0.1f64).exp()) / 0.1 * (-0.1f64).exp();
+        assert!(
+            (fast.isolated_before_removal() - expected).abs() < 1e-12,
+            "Bad probability of isolation, expected {} got {}",
+            expected,
+            fast.isolated_before_removal()
+        );
+        assert!(
+            fast.growth_rate() < slow.growth_rate(),
+            "Expected faster isolation to slow growth, got {} and {}",
+            fast.growth_rate(),
+            slow.growth_rate()
+        );
+        let last = fast.r_popf.nrows() - 1;
+        let final_size = |m: &Model| m.q_popf[(last, 0)] + m.r_popf[(last, 0)];
+        assert!(
+            final_size(&fast) < final_size(&slow),
+            "Expected faster isolation to shrink the epidemic, got {} and {}",
+            final_size(&fast),
+            final_size(&slow)
+        );
+        // Early on, prevalence grows at the growth rate.
+        let (t0, t1) = (100, 200);
+        let observed = (slow.i_popf[(t1, 0)] / slow.i_popf[(t0, 0)]).ln() / 5.0;
+        assert!(
+            (observed - slow.growth_rate()).abs() < 0.02,
+            "Bad growth, expected {} got {}",
+            slow.growth_rate(),
+            observed
+        );
+    }
+}
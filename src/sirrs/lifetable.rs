@@ -0,0 +1,225 @@
+//! Life tables of all-cause mortality by age.
+//!
+//! A [`LifeTable`] holds the mortality rate `nmx` of each age interval, the
+//! last open-ended, in the layout of both complete (single year) and
+//! abridged (0, 1-4, 5-9, ..., 85+) tables. The rate is constant within an
+//! interval, so probabilities of dying `nqx` convert exactly to rates, and
+//! survival and life expectancy follow in closed form.
+//!
+//! The table supplies other-cause mortality, omega, to the DisMod models:
+//! [`LifeTable::on_grid`] averages it over each step of a solver grid, and
+//! [`LifeTable::rate_grid`] gives it as a [`RateGrid`] for
+//! [`crate::dismod::AgeTimeModel`]. Tables are read from csv by
+//! [`crate::data::parse_life_table_csv`].
+use crate::sirrs::dismod::RateGrid;
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::reproducible::{exp, ln};
+use faer::Mat;
+
+/// Mortality rates by age interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifeTable {
+    /// Start of each age interval, ascending from 0. Each interval ends
+    /// where the next starts, and the last is open-ended.
+    pub ages: Vec<f64>,
+    /// Mortality rate in each interval, `nmx`.
+    pub mx: Vec<f64>,
+}
+
+impl LifeTable {
+    /// Life table of mortality rates `mx` in intervals starting at `ages`.
+    pub fn from_rates(ages: Vec<f64>, mx: Vec<f64>) -> Self {
+        assert!(
+            (ages.len() == mx.len()) & !ages.is_empty(),
+            "expected one rate per age interval, got {} ages and {} rates",
+            ages.len(),
+            mx.len()
+        );
+        assert!(
+            (ages[0] == 0.0) & ages.windows(2).all(|w| w[0] < w[1]),
+            "ages must ascend strictly from 0, got {:?}",
+            ages
+        );
+        assert!(
+            mx.iter().all(|m| (*m >= 0.0) & m.is_finite()),
+            "mortality rates must be finite and non-negative, got {:?}",
+            mx
+        );
+        return Self { ages, mx };
+    }
+
+    /// Life table from the probability of dying within each closed
+    /// interval, `nqx`, and the mortality rate of the open-ended last
+    /// interval, whose probability is 1. Often `last_mx` is `1 / e_x` of the
+    /// last interval.
+    pub fn from_probabilities(ages: Vec<f64>, qx: &[f64], last_mx: f64) -> Self {
+        assert_eq!(
+            qx.len() + 1,
+            ages.len(),
+            "expected a probability for every interval but the last"
+        );
+        let mut mx: Vec<f64> = qx
+            .iter()
+            .enumerate()
+            .map(|(k, q)| {
+                assert!(
+                    (0.0..1.0).contains(q),
+                    "probabilities of dying must be in [0, 1), got {}",
+                    q
+                );
+                -ln(1.0 - q) / (ages[k + 1] - ages[k])
+            })
+            .collect();
+        mx.push(last_mx);
+        return Self::from_rates(ages, mx);
+    }
+
+    /// Index of the interval containing `age`.
+    fn interval(&self, age: f64) -> usize {
+        return self.ages.iter().rposition(|a| *a <= age).unwrap_or(0);
+    }
+
+    /// Mortality rate at `age`.
+    pub fn mortality(&self, age: f64) -> f64 {
+        return self.mx[self.interval(age)];
+    }
+
+    /// Cumulative mortality hazard from birth to `age`.
+    fn hazard(&self, age: f64) -> f64 {
+        let mut hazard = 0.0;
+        for k in 0..self.ages.len() {
+            let end = self.ages.get(k + 1).copied().unwrap_or(f64::INFINITY);
+            if age <= self.ages[k] {
+                break;
+            }
+            hazard += self.mx[k] * (age.min(end) - self.ages[k]);
+        }
+        return hazard;
+    }
+
+    /// Fraction of those born surviving to `age`, `l(x) / l(0)`.
+    pub fn survival(&self, age: f64) -> f64 {
+        return exp(-self.hazard(age));
+    }
+
+    /// Remaining life expectancy at `age`. Infinite if the open-ended
+    /// interval has no mortality.
+    pub fn life_expectancy(&self, age: f64) -> f64 {
+        let first = self.interval(age);
+        let mut years = 0.0;
+        let mut alive = 1.0;
+        for k in first..self.ages.len() {
+            let start = if k == first { age } else { self.ages[k] };
+            let width = self
+                .ages
+                .get(k + 1)
+                .map_or(f64::INFINITY, |end| end - start);
+            let m = self.mx[k];
+            years += if m > 0.0 {
+                alive * (1.0 - exp(-m * width)) / m
+            } else {
+                alive * width
+            };
+            alive *= exp(-m * width);
+        }
+        return years;
+    }
+
+    /// Mortality rate averaged over each step of `grid`, one row per index,
+    /// the step from each index to the next. The last index takes the rate
+    /// at its age.
+    pub fn on_grid(&self, grid: &TimeGrid) -> Mat<f64> {
+        return Mat::from_fn(grid.n_steps, 1, |i, _| {
+            let start = grid.time(i);
+            if i + 1 == grid.n_steps {
+                return self.mortality(start);
+            }
+            let end = grid.time(i + 1);
+            (self.hazard(end) - self.hazard(start)) / (end - start)
+        });
+    }
+
+    /// Mortality as a [`RateGrid`] constant over calendar time, with knots
+    /// at the start and just before the end of each interval so that
+    /// interpolation keeps the rate constant within intervals.
+    pub fn rate_grid(&self) -> RateGrid {
+        let mut ages = Vec::new();
+        let mut values = Vec::new();
+        for k in 0..self.ages.len() {
+            ages.push(self.ages[k]);
+            values.push(self.mx[k]);
+            if let Some(end) = self.ages.get(k + 1) {
+                ages.push(end - (1e-6 * (end - self.ages[k])));
+                values.push(self.mx[k]);
+            }
+        }
+        let mut grid = RateGrid::new(ages, vec![0.0], 0.0);
+        grid.values = Mat::from_fn(values.len(), 1, |i, _| values[i]);
+        return grid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::grid::TimeGrid;
+    use crate::sirrs::lifetable::LifeTable;
+
+    #[test]
+    fn test_constant_mortality() {
+        let table = LifeTable::from_rates(vec![0.0, 1.0, 5.0], vec![0.02, 0.02, 0.02]);
+        assert!(
+            (table.life_expectancy(0.0) - 50.0).abs() < 1e-9,
+            "Bad life expectancy, expected 50 got {}",
+            table.life_expectancy(0.0)
+        );
+        assert!(
+            (table.life_expectancy(30.0) - 50.0).abs() < 1e-9,
+            "Expected memoryless life expectancy, got {}",
+            table.life_expectancy(30.0)
+        );
+        assert!(
+            (table.survival(10.0) - (-0.2f64).exp()).abs() < 1e-12,
+            "Bad survival, got {}",
+            table.survival(10.0)
+        );
+    }
+
+    #[test]
+    fn test_abridged_table() {
+        let ages = vec![0.0, 1.0, 5.0, 10.0];
+        let table = LifeTable::from_probabilities(ages, &[0.05, 0.02, 0.01], 0.1);
+        // The probability of dying in [1, 5) comes back from survival.
+        let q = 1.0 - (table.survival(5.0) / table.survival(1.0));
+        assert!((q - 0.02).abs() < 1e-12, "Bad 4q1, expected 0.02 got {}", q);
+        assert!(
+            (table.life_expectancy(10.0) - 10.0).abs() < 1e-9,
+            "Bad life expectancy in the open interval, expected 10 got {}",
+            table.life_expectancy(10.0)
+        );
+        let grid = TimeGrid::from_length(12, 0.5);
+        let omega = table.on_grid(&grid);
+        let expected = 0.5 * (table.mx[0] + table.mx[1]);
+        assert!(
+            (omega[(1, 0)] - table.mx[0]).abs() < 1e-12,
+            "Bad rate within an interval, got {}",
+            omega[(1, 0)]
+        );
+        let straddling = TimeGrid::new(0.5, 2.5, 1.0);
+        assert!(
+            (table.on_grid(&straddling)[(0, 0)] - expected).abs() < 1e-12,
+            "Bad rate over a step straddling intervals, expected {} got {}",
+            expected,
+            table.on_grid(&straddling)[(0, 0)]
+        );
+        let rates = table.rate_grid();
+        for age in [0.5, 3.0, 9.99, 40.0] {
+            assert!(
+                (rates.at(age, 2000.0) - table.mortality(age)).abs() < 1e-12,
+                "Bad rate grid at age {}, expected {} got {}",
+                age,
+                table.mortality(age),
+                rates.at(age, 2000.0)
+            );
+        }
+    }
+}
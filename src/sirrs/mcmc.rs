@@ -0,0 +1,352 @@
+//! Markov chain Monte Carlo sampling of model parameters.
+//!
+//! Chains are random-walk Metropolis samplers of a log-density over
+//! unconstrained parameters. Several chains run concurrently, one thread
+//! each, all reading the same target through an [`Arc`] so observations and
+//! other data are shared rather than copied. [`run_chains`] joins them and
+//! summarizes the pooled draws, with the Gelman-Rubin statistic to check the
+//! chains agree.
+use crate::sirrs::fit::Fit;
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::rng::{self, SimRng};
+use faer::Mat;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use std::sync::Arc;
+use std::thread;
+
+/// Draws from a single chain.
+pub struct Chain {
+    /// Parameter values of each draw (row) for each parameter (column).
+    pub samples: Mat<f64>,
+    /// Log-density at each draw. Column with one row per draw.
+    pub log_density: Mat<f64>,
+    /// Fraction of proposals accepted, including burn-in.
+    pub acceptance_rate: f64,
+}
+
+/// Pooled draws and per-parameter summaries from several chains.
+pub struct ChainSummary {
+    /// Each chain's draws, in the order the chains were started.
+    pub chains: Vec<Chain>,
+    /// Posterior mean of each parameter over all chains. Column with one row
+    /// per parameter.
+    pub mean: Mat<f64>,
+    /// Posterior standard deviation of each parameter over all chains.
+    pub sd: Mat<f64>,
+    /// Gelman-Rubin potential scale reduction of each parameter. Values near
+    /// 1 indicate the chains have mixed. NaN with a single chain.
+    pub r_hat: Mat<f64>,
+}
+
+/// Run one random-walk Metropolis chain of `log_density` from `x0`.
+///
+/// Proposals add independent normal steps with standard deviation `step`
+/// to every parameter. The first `burn_in` draws are discarded and
+/// `n_samples` are kept.
+pub fn run_chain(
+    log_density: &dyn Fn(&[f64]) -> f64,
+    x0: &[f64],
+    step: f64,
+    burn_in: usize,
+    n_samples: usize,
+    seed: u64,
+) -> Chain {
+    return sample_chain(log_density, x0, step, burn_in, n_samples, rng::rng(seed));
+}
+
+/// Run one chain as [`run_chain`] does, drawing from `rng`.
+fn sample_chain(
+    log_density: &dyn Fn(&[f64]) -> f64,
+    x0: &[f64],
+    step: f64,
+    burn_in: usize,
+    n_samples: usize,
+    mut rng: SimRng,
+) -> Chain {
+    let n = x0.len();
+    let mut x = x0.to_vec();
+    let mut current = log_density(&x);
+    let mut samples = Mat::zeros(n_samples, n);
+    let mut log_densities = Mat::zeros(n_samples, 1);
+    let mut accepted = 0;
+    for iteration in 0..(burn_in + n_samples) {
+        let proposal: Vec<f64> = x
+            .iter()
+            .map(|xj| xj + (step * rng.sample::<f64, _>(StandardNormal)))
+            .collect();
+        let proposed = log_density(&proposal);
+        if ln(rng.r#gen::<f64>()) < proposed - current {
+            x = proposal;
+            current = proposed;
+            accepted += 1;
+        }
+        if iteration >= burn_in {
+            let row = iteration - burn_in;
+            for j in 0..n {
+                samples[(row, j)] = x[j];
+            }
+            log_densities[(row, 0)] = current;
+        }
+    }
+    return Chain {
+        samples,
+        log_density: log_densities,
+        acceptance_rate: (accepted as f64) / ((burn_in + n_samples) as f64),
+    };
+}
+
+/// Summarize draws pooled over chains of equal length.
+pub fn summarize(chains: Vec<Chain>) -> ChainSummary {
+    let m = chains.len() as f64;
+    let n_params = chains[0].samples.ncols();
+    let n = chains[0].samples.nrows() as f64;
+    let mut mean = Mat::zeros(n_params, 1);
+    let mut sd = Mat::zeros(n_params, 1);
+    let mut r_hat = Mat::zeros(n_params, 1);
+    for j in 0..n_params {
+        let chain_means: Vec<f64> = chains
+            .iter()
+            .map(|c| {
+                (0..c.samples.nrows())
+                    .map(|t| c.samples[(t, j)])
+                    .sum::<f64>()
+                    / n
+            })
+            .collect();
+        let chain_vars: Vec<f64> = chains
+            .iter()
+            .zip(chain_means.iter())
+            .map(|(c, mu)| {
+                (0..c.samples.nrows())
+                    .map(|t| (c.samples[(t, j)] - mu).powi(2))
+                    .sum::<f64>()
+                    / (n - 1.0)
+            })
+            .collect();
+        let grand = chain_means.iter().sum::<f64>() / m;
+        let total: f64 = chains
+            .iter()
+            .map(|c| {
+                (0..c.samples.nrows())
+                    .map(|t| (c.samples[(t, j)] - grand).powi(2))
+                    .sum::<f64>()
+            })
+            .sum();
+        mean[(j, 0)] = grand;
+        sd[(j, 0)] = (total / ((m * n) - 1.0)).sqrt();
+        let within = chain_vars.iter().sum::<f64>() / m;
+        let between = n * chain_means
+            .iter()
+            .map(|mu| (mu - grand).powi(2))
+            .sum::<f64>()
+            / (m - 1.0);
+        let pooled = (((n - 1.0) / n) * within) + (between / n);
+        r_hat[(j, 0)] = if m > 1.0 {
+            (pooled / within).sqrt()
+        } else {
+            f64::NAN
+        };
+    }
+    return ChainSummary {
+        chains,
+        mean,
+        sd,
+        r_hat,
+    };
+}
+
+/// Run `n_chains` chains of `log_density` concurrently and summarize them.
+///
+/// Chain `c` starts from `x0` and draws from stream `c` of `seed`, see
+/// [`rng::stream`], so results do not depend on thread scheduling.
+pub fn run_chains<F>(
+    log_density: Arc<F>,
+    x0: &[f64],
+    step: f64,
+    burn_in: usize,
+    n_samples: usize,
+    n_chains: usize,
+    seed: u64,
+) -> ChainSummary
+where
+    F: Fn(&[f64]) -> f64 + Send + Sync + 'static,
+{
+    assert!(n_chains >= 1, "n_chains must be at least 1");
+    let handles: Vec<thread::JoinHandle<Chain>> = (0..n_chains)
+        .map(|c| {
+            let log_density = Arc::clone(&log_density);
+            let x0 = x0.to_vec();
+            thread::spawn(move || {
+                return sample_chain(
+                    &*log_density,
+                    &x0,
+                    step,
+                    burn_in,
+                    n_samples,
+                    rng::stream(seed, c as u64),
+                );
+            })
+        })
+        .collect();
+    let chains: Vec<Chain> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("chain thread panicked"))
+        .collect();
+    return summarize(chains);
+}
+
+/// Sample the posterior of a configured fit's log incidence and log removal
+/// rates, of the SIR model or of a pipeline, see [`Fit::pipeline`], under
+/// flat priors on the log scale, starting every chain from the fit's current
+/// rates.
+pub fn sample_fit(
+    fit: Arc<Fit>,
+    step: f64,
+    burn_in: usize,
+    n_samples: usize,
+    n_chains: usize,
+    seed: u64,
+) -> ChainSummary {
+    let x0 = [ln(fit.incidence_rate), ln(fit.removal_rate)];
+    let target = Arc::new(move |x: &[f64]| -> f64 {
+        return fit.log_likelihood_at(exp(x[0]), exp(x[1]), &fit.changepoints);
+    });
+    return run_chains(target, &x0, step, burn_in, n_samples, n_chains, seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::fit::{Fit, Observation};
+    use crate::sirrs::mcmc::{run_chains, sample_fit};
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::pipeline::Pipeline;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::{Fraction, Rate};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_run_chains_normal_target() {
+        let data = Arc::new(vec![1.0, -2.0]);
+        let target = {
+            let data = Arc::clone(&data);
+            Arc::new(move |x: &[f64]| -> f64 {
+                return -0.5 * ((x[0] - data[0]).powi(2) + ((x[1] - data[1]) / 0.5).powi(2));
+            })
+        };
+        let summary = run_chains(target, &[0.0, 0.0], 0.8, 1000, 10000, 4, 1);
+        assert_eq!(summary.chains.len(), 4, "Bad number of chains");
+        for (j, (mu, sigma)) in [(1.0, 1.0), (-2.0, 0.5)].iter().enumerate() {
+            assert!(
+                (summary.mean[(j, 0)] - mu).abs() < 0.1,
+                "Bad mean of parameter {}, expected {} got {}",
+                j,
+                mu,
+                summary.mean[(j, 0)]
+            );
+            assert!(
+                (summary.sd[(j, 0)] - sigma).abs() < 0.1,
+                "Bad sd of parameter {}, expected {} got {}",
+                j,
+                sigma,
+                summary.sd[(j, 0)]
+            );
+            assert!(
+                summary.r_hat[(j, 0)] < 1.05,
+                "Chains did not mix for parameter {}, r_hat {}",
+                j,
+                summary.r_hat[(j, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_chains_is_reproducible() {
+        let target = Arc::new(|x: &[f64]| -> f64 { -0.5 * x[0] * x[0] });
+        let a = run_chains(Arc::clone(&target), &[0.0], 1.0, 10, 100, 3, 7);
+        let b = run_chains(target, &[0.0], 1.0, 10, 100, 3, 7);
+        for c in 0..3 {
+            assert_eq!(
+                a.chains[c].samples, b.chains[c].samples,
+                "Chain {} differs between runs with the same seed",
+                c
+            );
+        }
+    }
+
+    #[test]
+    fn test_sample_fit() {
+        let mut truth = Fit::new();
+        truth.configure(
+            0.5,
+            0.01,
+            0.005,
+            vec![Observation::Missing; 40],
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let model = truth.simulate(0.4, 0.1, &[]);
+        let observed: Vec<Observation> = (0..40)
+            .map(|t| Observation::Value(model.i_popf[(2 * t, 0)]))
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            0.5,
+            0.01,
+            0.005,
+            observed,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        let summary = sample_fit(Arc::new(fit), 0.02, 200, 400, 2, 3);
+        assert!(
+            (summary.mean[(0, 0)].exp() - 0.4).abs() < 0.02,
+            "Bad posterior incidence rate, expected 0.4 got {}",
+            summary.mean[(0, 0)].exp()
+        );
+    }
+
+    #[test]
+    fn test_sample_pipeline_fit() {
+        let build = || {
+            let mut system = sir::Model::new();
+            system.configure(
+                40,
+                0.5,
+                0.01,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            let mut pipeline = Pipeline::new(Box::new(system));
+            let mut reporting = ReportingModel::new();
+            reporting.configure(Fraction::of(0.5), vec![1.0], None, 0);
+            pipeline.observation(reporting);
+            return pipeline;
+        };
+        let mut truth = build();
+        truth.configure(41, 0.5);
+        truth.run_rk4();
+        let reports = truth.expected_reports();
+        let observed: Vec<Observation> = (0..40)
+            .map(|t| Observation::Value(reports[(t, 0)]))
+            .collect();
+        let mut fit = Fit::new();
+        fit.configure(
+            0.5,
+            0.0,
+            0.002,
+            observed,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+        );
+        fit.pipeline(build);
+        let summary = sample_fit(Arc::new(fit), 0.02, 200, 400, 2, 3);
+        assert!(
+            (summary.mean[(0, 0)].exp() - 0.4).abs() < 0.02,
+            "Bad posterior incidence rate, expected 0.4 got {}",
+            summary.mean[(0, 0)].exp()
+        );
+    }
+}
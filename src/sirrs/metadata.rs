@@ -0,0 +1,512 @@
+//! Self-describing simulation results.
+//!
+//! A [`SimulationResult`] carries the solved series together with a
+//! [`RunMetadata`] recording how it was produced: crate version, solver,
+//! step size, a hash of the parameters, the RNG seed of stochastic runs and
+//! when it ran. Every export format includes the metadata, as `# key: value`
+//! comment lines ahead of csv headers and as schema metadata in Arrow, so a
+//! results file can be traced back to the run that wrote it.
+//!
+//! [`SimulationResult::to_json`] writes the whole run, metadata, parameters
+//! and series, as a single JSON document for web dashboards.
+//!
+//! With the `polars` feature, [`SimulationResult::to_dataframe`] converts
+//! the series to a polars data frame.
+use crate::sirrs::export::{self, LongRecord};
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::schema::{JsonValue, json_number, json_string, parse_json};
+use faer::Mat;
+use std::io::{self, Error, ErrorKind, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How a result was produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunMetadata {
+    /// Version of this crate.
+    pub crate_version: String,
+    /// Name of the solver, for example `rk4`.
+    pub solver: String,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// The run's parameters.
+    pub parameters: Parameters,
+    /// [`parameters_hash`] of the run's parameters.
+    pub parameters_hash: String,
+    /// Seed of the random number generator, for stochastic runs.
+    pub seed: Option<u64>,
+    /// When the run finished, in seconds since 1970-01-01 UTC.
+    pub timestamp: u64,
+}
+
+impl RunMetadata {
+    /// Metadata of a run of `solver` now, with this crate's version and no
+    /// seed.
+    pub fn new(solver: &str, step_size: f64, parameters: &Parameters) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        return Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            solver: solver.to_string(),
+            step_size,
+            parameters: parameters.clone(),
+            parameters_hash: parameters_hash(parameters),
+            seed: None,
+            timestamp,
+        };
+    }
+
+    /// Record the seed of a stochastic run.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = Some(seed);
+        return self;
+    }
+
+    /// Each field as `(key, value)` text, in declaration order. A missing
+    /// seed is an empty value.
+    pub fn pairs(&self) -> Vec<(&'static str, String)> {
+        return vec![
+            ("crate_version", self.crate_version.clone()),
+            ("solver", self.solver.clone()),
+            ("step_size", self.step_size.to_string()),
+            ("parameters_hash", self.parameters_hash.clone()),
+            ("seed", self.seed.map_or(String::new(), |s| s.to_string())),
+            ("timestamp", self.timestamp.to_string()),
+        ];
+    }
+
+    /// Write the metadata as `# key: value` lines.
+    pub fn write_comments<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (key, value) in self.pairs() {
+            writeln!(writer, "# {}: {}", key, value)?;
+        }
+        return Ok(());
+    }
+
+    /// The metadata as a JSON object. A missing seed is `null`.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self
+            .pairs()
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match key {
+                    "crate_version" | "solver" | "parameters_hash" => json_string(&value),
+                    _ if value.is_empty() => "null".to_string(),
+                    _ => value,
+                };
+                format!("{}:{}", json_string(key), value)
+            })
+            .collect();
+        return format!("{{{}}}", fields.join(","));
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of `parameters`, as 16 hex digits. Names are
+/// hashed in sorted order with the exact bits of each value, so the hash
+/// is the same across runs, platforms and compiler versions.
+pub fn parameters_hash(parameters: &Parameters) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes.iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    for (name, value) in parameters.iter() {
+        feed(name.as_bytes());
+        feed(&[0]);
+        feed(&value.to_bits().to_le_bytes());
+    }
+    return format!("{:016x}", hash);
+}
+
+/// Comma separated JSON numbers, without brackets.
+fn json_array(values: impl Iterator<Item = f64>) -> String {
+    return values.map(json_number).collect::<Vec<String>>().join(",");
+}
+
+/// A solved series with the metadata of the run that produced it.
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    /// How the series was produced.
+    pub metadata: RunMetadata,
+    /// Name of each value (column).
+    pub names: Vec<String>,
+    /// Time of each index (row).
+    pub times: Vec<f64>,
+    /// Each value (column) at each index (row).
+    pub values: Mat<f64>,
+}
+
+impl SimulationResult {
+    /// Write the result as csv, metadata comments first, then a header
+    /// `t,<names>` and one row per index.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.metadata.write_comments(&mut writer)?;
+        writeln!(writer, "t,{}", self.names.join(","))?;
+        for (k, t) in self.times.iter().enumerate() {
+            write!(writer, "{}", t)?;
+            for j in 0..self.values.ncols() {
+                write!(writer, ",{}", self.values[(k, j)])?;
+            }
+            writeln!(writer)?;
+        }
+        return writer.flush();
+    }
+
+    /// Iterate over `(t, compartment, value)` triples, ordered by time,
+    /// then compartment, without collecting them.
+    pub fn iter_long(&self) -> impl Iterator<Item = (f64, &str, f64)> + '_ {
+        return self.times.iter().enumerate().flat_map(move |(k, t)| {
+            self.names
+                .iter()
+                .enumerate()
+                .map(move |(j, name)| (*t, name.as_str(), self.values[(k, j)]))
+        });
+    }
+
+    /// The result as long records, each value its own compartment in the
+    /// stratum `all`, see [`export::to_long`] and [`SimulationResult::iter_long`].
+    pub fn to_long(&self) -> Vec<LongRecord> {
+        return self
+            .iter_long()
+            .map(|(t, compartment, value)| LongRecord {
+                t,
+                stratum: "all".to_string(),
+                compartment: compartment.to_string(),
+                value,
+            })
+            .collect();
+    }
+
+    /// The whole run as a JSON object:
+    ///
+    /// ```text
+    /// {
+    ///   "metadata": {"crate_version": ..., "solver": ..., ...},
+    ///   "parameters": {"<name>": <value>, ...},
+    ///   "times": [<t>, ...],
+    ///   "series": {"<name>": [<value>, ...], ...}
+    /// }
+    /// ```
+    ///
+    /// `metadata` is [`RunMetadata::to_json`], parameters are sorted by
+    /// name, and each series has one value per time, in the order of
+    /// `names`. Values that are not finite are `null`.
+    pub fn to_json(&self) -> String {
+        let parameters: Vec<String> = self
+            .metadata
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), json_number(*value)))
+            .collect();
+        let series: Vec<String> = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| {
+                let column = json_array((0..self.values.nrows()).map(|k| self.values[(k, j)]));
+                format!("{}:[{}]", json_string(name), column)
+            })
+            .collect();
+        return format!(
+            "{{\"metadata\":{},\"parameters\":{{{}}},\"times\":[{}],\"series\":{{{}}}}}",
+            self.metadata.to_json(),
+            parameters.join(","),
+            json_array(self.times.iter().copied()),
+            series.join(",")
+        );
+    }
+
+    /// Read a result written by [`SimulationResult::to_json`], for example
+    /// a run saved by the `sirrs` binary. `null` values are NaN.
+    pub fn from_json(text: &str) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let value = parse_json(text)?;
+        let object = |key: &str| match value.get(key) {
+            Some(JsonValue::Object(members)) => Ok(members),
+            _ => Err(invalid(&format!("{} must be an object", key))),
+        };
+        let numbers = |item: &JsonValue, key: &str| match item {
+            JsonValue::Array(items) => items
+                .iter()
+                .map(|x| x.as_f64())
+                .collect::<Option<Vec<f64>>>()
+                .ok_or_else(|| invalid(&format!("{} must hold only numbers", key))),
+            _ => Err(invalid(&format!("{} must be an array", key))),
+        };
+        let metadata = value
+            .get("metadata")
+            .ok_or_else(|| invalid("missing field 'metadata'"))?;
+        let text_field = |key: &str| match metadata.get(key) {
+            Some(JsonValue::String(text)) => Ok(text.clone()),
+            _ => Err(invalid(&format!("metadata {} must be a string", key))),
+        };
+        let number_field = |key: &str| match metadata.get(key) {
+            Some(JsonValue::Number(x)) => Ok(*x),
+            _ => Err(invalid(&format!("metadata {} must be a number", key))),
+        };
+        let mut parameters = Parameters::new();
+        for (name, x) in object("parameters")?.iter() {
+            let x = x
+                .as_f64()
+                .ok_or_else(|| invalid(&format!("parameter {} must be a number", name)))?;
+            parameters.insert(name.clone(), x);
+        }
+        let times = numbers(
+            value
+                .get("times")
+                .ok_or_else(|| invalid("missing field 'times'"))?,
+            "times",
+        )?;
+        let mut names = Vec::new();
+        let mut columns = Vec::new();
+        for (name, column) in object("series")?.iter() {
+            let column = numbers(column, name)?;
+            if column.len() != times.len() {
+                return Err(invalid(&format!(
+                    "series {} has {} values for {} times",
+                    name,
+                    column.len(),
+                    times.len()
+                )));
+            }
+            names.push(name.clone());
+            columns.push(column);
+        }
+        let seed = match metadata.get("seed") {
+            Some(JsonValue::Number(x)) => Some(*x as u64),
+            Some(JsonValue::Null) | None => None,
+            _ => return Err(invalid("metadata seed must be a number or null")),
+        };
+        return Ok(Self {
+            metadata: RunMetadata {
+                crate_version: text_field("crate_version")?,
+                solver: text_field("solver")?,
+                step_size: number_field("step_size")?,
+                parameters,
+                parameters_hash: text_field("parameters_hash")?,
+                seed,
+                timestamp: number_field("timestamp")? as u64,
+            },
+            names,
+            values: Mat::from_fn(times.len(), columns.len(), |t, j| columns[j][t]),
+            times,
+        });
+    }
+
+    /// Write the result as long csv, metadata comments first, see
+    /// [`export::write_long_csv`].
+    pub fn write_long_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.metadata.write_comments(&mut writer)?;
+        return export::write_long_csv(&self.to_long(), writer);
+    }
+
+    /// The result as an Arrow record batch with a `t` column and one column
+    /// per name, with the metadata as schema metadata.
+    #[cfg(feature = "arrow")]
+    pub fn to_arrow(&self) -> io::Result<arrow_array::RecordBatch> {
+        use crate::sirrs::sink::{ArrowSink, OutputSink};
+        let mut sink = ArrowSink::new();
+        sink.metadata(&self.metadata)?;
+        sink.start(&self.names)?;
+        for (k, t) in self.times.iter().enumerate() {
+            let values: Vec<f64> = (0..self.values.ncols())
+                .map(|j| self.values[(k, j)])
+                .collect();
+            sink.write_step(*t, &values)?;
+        }
+        sink.finish()?;
+        return Ok(sink.batch.expect("finished sink has a batch"));
+    }
+
+    /// The result as a polars data frame with a `t` column and one column
+    /// per name. Data frames carry no metadata, which stays on the result.
+    #[cfg(feature = "polars")]
+    pub fn to_dataframe(&self) -> polars::prelude::PolarsResult<polars::prelude::DataFrame> {
+        use polars::prelude::{Column, DataFrame};
+        let mut columns = Vec::with_capacity(self.names.len() + 1);
+        columns.push(Column::new("t".into(), self.times.as_slice()));
+        for (j, name) in self.names.iter().enumerate() {
+            let values: Vec<f64> = (0..self.values.nrows())
+                .map(|k| self.values[(k, j)])
+                .collect();
+            columns.push(Column::new(name.as_str().into(), values));
+        }
+        return DataFrame::new(columns);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::metadata::{RunMetadata, SimulationResult, parameters_hash};
+    use crate::sirrs::pipeline::Parameters;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn test_to_dataframe() {
+        let mut model = sir::Model::new();
+        model.configure(
+            10,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let df = model.result("rk4").to_dataframe().unwrap();
+        assert_eq!(df.shape(), (20, 6), "Bad shape, got {:?}", df.shape());
+        let i = df.column("i").unwrap().f64().unwrap().get(3).unwrap();
+        assert_eq!(
+            i,
+            model.i_popf[(3, 0)],
+            "Bad i at index 3, expected {} got {}",
+            model.i_popf[(3, 0)],
+            i
+        );
+    }
+
+    #[test]
+    fn test_parameters_hash() {
+        let a = Parameters::from([("beta".to_string(), 0.3), ("gamma".to_string(), 0.1)]);
+        let b = Parameters::from([("gamma".to_string(), 0.1), ("beta".to_string(), 0.3)]);
+        let c = Parameters::from([("beta".to_string(), 0.3), ("gamma".to_string(), 0.2)]);
+        assert_eq!(
+            parameters_hash(&a),
+            parameters_hash(&b),
+            "Expected order to not matter"
+        );
+        assert_ne!(
+            parameters_hash(&a),
+            parameters_hash(&c),
+            "Expected values to matter"
+        );
+        assert_eq!(
+            parameters_hash(&Parameters::new()),
+            "cbf29ce484222325",
+            "Bad hash of no parameters, got {}",
+            parameters_hash(&Parameters::new())
+        );
+        let mut metadata = RunMetadata::new("ssa", 0.5, &a);
+        metadata.seed(7);
+        let json = metadata.to_json();
+        assert!(
+            json.starts_with(&format!(
+                "{{\"crate_version\":\"{}\",\"solver\":\"ssa\",\"step_size\":0.5,",
+                env!("CARGO_PKG_VERSION")
+            )) & json.contains("\"seed\":7,"),
+            "Bad json, got {}",
+            json
+        );
+    }
+
+    #[test]
+    fn test_simulation_result() {
+        let mut model = sir::Model::new();
+        model.configure(
+            10,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let result = model.result("rk4");
+        let mut changed = sir::Model::new();
+        changed.configure(
+            10,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        changed.changepoints(vec![(5.0, Rate::per_day(0.2))]);
+        assert_ne!(
+            result.metadata.parameters_hash,
+            changed.result("rk4").metadata.parameters_hash,
+            "Expected changepoints to change the hash"
+        );
+        let mut buffer = Vec::new();
+        result.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("# crate_version: {}", env!("CARGO_PKG_VERSION")),
+            "Bad first line, got {}",
+            lines[0]
+        );
+        assert_eq!(
+            lines[1], "# solver: rk4",
+            "Bad solver line, got {}",
+            lines[1]
+        );
+        assert_eq!(
+            lines[6], "t,s,i,r,incidence,cumulative_incidence",
+            "Bad header, got {}",
+            lines[6]
+        );
+        assert_eq!(lines.len(), 27, "Bad number of lines, got {}", lines.len());
+        let triples: Vec<(f64, &str, f64)> = result.iter_long().collect();
+        assert_eq!(triples.len(), 100, "Bad number of triples");
+        assert_eq!(
+            triples[6],
+            (0.5, "i", model.i_popf[(1, 0)]),
+            "Bad triple, got {:?}",
+            triples[6]
+        );
+        let records = result.to_long();
+        assert!(
+            records.iter().zip(triples.iter()).all(|(record, triple)| (
+                record.t,
+                record.compartment.as_str(),
+                record.value
+            ) == *triple),
+            "Expected records to match the triples"
+        );
+        let json = result.to_json();
+        assert!(
+            json.starts_with("{\"metadata\":{\"crate_version\":")
+                & json.contains("},\"parameters\":{\"i_popf_init\":0.01,\"incidence_rate\":0.4,")
+                & json.contains("\"times\":[0,0.5,1,")
+                & json.contains(&format!("\"r\":[0,{},", model.r_popf[(1, 0)]))
+                & json.ends_with("]}}"),
+            "Bad json, got {}",
+            json
+        );
+        let back = SimulationResult::from_json(&json).unwrap();
+        assert_eq!(back.metadata, result.metadata, "Bad metadata read back");
+        assert_eq!(
+            (back.names.clone(), back.times.clone(), back.values.clone()),
+            (
+                result.names.clone(),
+                result.times.clone(),
+                result.values.clone()
+            ),
+            "Bad series read back"
+        );
+        assert!(
+            SimulationResult::from_json("{\"metadata\":{}}").is_err(),
+            "Expected an incomplete run rejected"
+        );
+        let mut buffer = Vec::new();
+        result.write_long_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(
+            text.starts_with("# crate_version: ")
+                & text.contains("\nt,stratum,compartment,value\n"),
+            "Bad long csv, got {}",
+            text
+        );
+    }
+}
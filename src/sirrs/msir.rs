@@ -0,0 +1,256 @@
+//! MSIR model with maternal immunity and vital dynamics.
+//!
+//! Newborns of immune mothers are protected by maternal antibodies for a
+//! while before becoming susceptible, as for measles. Births balance deaths,
+//! so the population is constant. Allows transition rates:
+//!  - births → M, from the removed (immune) fraction
+//!  - births → S, from everyone else
+//!  - M → S
+//!  - S → I
+//!  - I → R
+//!  - every compartment → death, at the birth rate
+//!
+//! With no births this is the SIR model without recovery.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run an MSIR model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Initial removed (immune) population fraction.
+    pub r_popf_init: f64,
+    /// Transition rate from S into I. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Per capita birth rate, equal to the death rate, the inverse of life
+    /// expectancy.
+    pub birth_rate: f64,
+    /// Transition rate from M into S, the inverse of the mean duration of
+    /// maternal immunity.
+    pub maternal_waning_rate: f64,
+    /// Maternally immune population fraction at each index. 1D Array with
+    /// one element per index of [`Model::grid`].
+    pub m_popf: Mat<f64>,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            r_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            birth_rate: 0.0,
+            maternal_waning_rate: 0.0,
+            m_popf: Mat::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters, with rates per day.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        r_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        birth_rate: Rate,
+        maternal_waning_rate: Rate,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.r_popf_init = r_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.birth_rate = birth_rate.per(TimeUnit::Day);
+        self.maternal_waning_rate = maternal_waning_rate.per(TimeUnit::Day);
+        self.m_popf = Mat::zeros(n_steps, 1);
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    /// Nobody starts maternally immune.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let s_init = 1.0 - self.i_popf_init - self.r_popf_init; // Population fractions must sum to 1.
+        self.store(0, &[0.0, s_init, self.i_popf_init, self.r_popf_init]);
+        return self;
+    }
+
+    /// Basic reproduction number, incidence_rate / (removal_rate +
+    /// birth_rate).
+    pub fn r0(&self) -> f64 {
+        return self.incidence_rate / (self.removal_rate + self.birth_rate);
+    }
+
+    /// Pack the state at index `t` into a vector ordered M, S, I, R.
+    fn load(&self, t: usize) -> [f64; 4] {
+        return [
+            self.m_popf[(t, 0)],
+            self.s_popf[(t, 0)],
+            self.i_popf[(t, 0)],
+            self.r_popf[(t, 0)],
+        ];
+    }
+
+    /// Unpack a state vector into index `t`.
+    fn store(&mut self, t: usize, y: &[f64; 4]) {
+        self.m_popf[(t, 0)] = y[0];
+        self.s_popf[(t, 0)] = y[1];
+        self.i_popf[(t, 0)] = y[2];
+        self.r_popf[(t, 0)] = y[3];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64; 4]) -> [f64; 4] {
+        let mu = self.birth_rate;
+        let protected_births = mu * y[3];
+        let susceptible_births = mu * (y[0] + y[1] + y[2]);
+        let waning = self.maternal_waning_rate * y[0];
+        let infection = self.incidence_rate * y[1] * y[2];
+        let removal = self.removal_rate * y[2];
+        return [
+            protected_births - waning - (mu * y[0]),
+            susceptible_births + waning - infection - (mu * y[1]),
+            infection - removal - (mu * y[2]),
+            removal - (mu * y[3]),
+        ];
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            self.store(t + 1, &std::array::from_fn(|j| y[j] + (h * d[j])));
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let k2 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])));
+            let k3 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])));
+            let k4 = self.derivatives(&std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.store(
+                t + 1,
+                &std::array::from_fn(|j| {
+                    y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+                }),
+            );
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::msir::Model;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            200,
+            0.5,
+            0.01,
+            0.3,
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+            Rate::per_day(0.5),
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let total = model.m_popf[(t, 0)]
+                + model.s_popf[(t, 0)]
+                + model.i_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_endemic_equilibrium() {
+        let mut model = Model::new();
+        model.configure(
+            3000,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+            Rate::per_day(0.5),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let last = model.s_popf.nrows() - 1;
+        // dI/dt = 0 with I > 0 requires S = 1 / R0.
+        let expected_s = 1.0 / model.r0();
+        assert!(
+            (model.s_popf[(last, 0)] - expected_s).abs() < 1e-4,
+            "Bad endemic s_popf, expected {} got {}",
+            expected_s,
+            model.s_popf[(last, 0)]
+        );
+        // Births into M balance waning and deaths.
+        let expected_m = model.birth_rate * model.r_popf[(last, 0)]
+            / (model.maternal_waning_rate + model.birth_rate);
+        assert!(
+            (model.m_popf[(last, 0)] - expected_m).abs() < 1e-6,
+            "Bad endemic m_popf, expected {} got {}",
+            expected_m,
+            model.m_popf[(last, 0)]
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Multi-strain SIR model with cross-immunity.
+//!
+//! Each strain `k` has its own infectious compartment I_k and removed
+//! compartment R_k, holding those whose most recent infection was with that
+//! strain. Allows transition rates:
+//!  - S → I_k
+//!  - I_k → R_k
+//!  - R_j → I_k, reduced by the cross-immunity of strain `j` against `k`
+//!
+//! A strain may be introduced partway through a run to study replacement.
+use crate::sirrs::export::{LongRecord, index_names, to_long};
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+
+/// Numerical integrator variables
+///
+/// This private struct exists to make indexing k and y during integration
+/// simpler. `i` and `r` hold one value per strain.
+struct SystemVars {
+    s: f64,
+    i: Vec<f64>,
+    r: Vec<f64>,
+}
+
+/// Create and run a multi-strain SIR model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Number of strains.
+    pub n_strains: usize,
+    /// Initial infectious population fraction of each strain. Column with
+    /// `n_strains` rows.
+    pub i_popf_init: Mat<f64>,
+    /// Transition rate from S into I_k for each strain, per day. Column with
+    /// `n_strains` rows.
+    pub incidence_rate: Mat<f64>,
+    /// Transition rate from I_k into R_k for each strain, per day. Column
+    /// with `n_strains` rows.
+    pub removal_rate: Mat<f64>,
+    /// Protection against strain `k` (column) given by recovery from strain
+    /// `j` (row). `n_strains` × `n_strains`, each in [0, 1], where 1 is
+    /// complete protection.
+    pub cross_immunity: Mat<f64>,
+    /// Strain introductions as `(t, strain, popf)`. At time `t` a population
+    /// fraction `popf` moves from S into I of `strain`.
+    pub introductions: Vec<(f64, usize, f64)>,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction of each strain (column) at each index (row).
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction of each strain (column) at each index (row).
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            n_strains: 0,
+            i_popf_init: Mat::new(),
+            incidence_rate: Mat::new(),
+            removal_rate: Mat::new(),
+            cross_immunity: Mat::new(),
+            introductions: Vec::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters, one rate per strain, stored per day.
+    /// Initial fractions must be non-negative and sum to at most 1, and
+    /// cross-immunities in [0, 1].
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: Mat<f64>,
+        incidence_rate: Vec<Rate>,
+        removal_rate: Vec<Rate>,
+        cross_immunity: Mat<f64>,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        let n_strains = i_popf_init.nrows();
+        assert_eq!(
+            incidence_rate.len(),
+            n_strains,
+            "incidence_rate must have {} strains",
+            n_strains
+        );
+        assert_eq!(
+            removal_rate.len(),
+            n_strains,
+            "removal_rate must have {} strains",
+            n_strains
+        );
+        assert_eq!(
+            cross_immunity.shape(),
+            (n_strains, n_strains),
+            "cross_immunity must be {} x {}",
+            n_strains,
+            n_strains
+        );
+        let init: Vec<f64> = (0..n_strains).map(|k| i_popf_init[(k, 0)]).collect();
+        assert!(
+            init.iter().all(|i| *i >= 0.0) & (init.iter().sum::<f64>() <= 1.0),
+            "i_popf_init must be non-negative and sum to at most 1, got {:?}",
+            init
+        );
+        assert!(
+            (0..n_strains)
+                .all(|j| (0..n_strains).all(|k| (0.0..=1.0).contains(&cross_immunity[(j, k)]))),
+            "cross_immunity must be in [0, 1]"
+        );
+        self.length = length;
+        self.step_size = step_size;
+        self.n_strains = n_strains;
+        self.i_popf_init = i_popf_init;
+        let per_day = |rates: Vec<Rate>| -> Mat<f64> {
+            return Mat::from_fn(n_strains, 1, |k, _| rates[k].per(TimeUnit::Day));
+        };
+        self.incidence_rate = per_day(incidence_rate);
+        self.removal_rate = per_day(removal_rate);
+        self.cross_immunity = cross_immunity;
+        self.introductions = Vec::new();
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, n_strains);
+        self.r_popf = Mat::zeros(n_steps, n_strains);
+        return self;
+    }
+
+    /// Times of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Introduce `popf` infectious of `strain` at time `t`.
+    pub fn introduce(&mut self, t: f64, strain: usize, popf: f64) -> &mut Self {
+        assert!(
+            strain < self.n_strains,
+            "strain must be less than {}, got {}",
+            self.n_strains,
+            strain
+        );
+        assert!(popf >= 0.0, "popf must be non-negative, got {}", popf);
+        self.introductions.push((t, strain, popf));
+        self.introductions.sort_by(|a, b| a.0.total_cmp(&b.0));
+        return self;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let mut s_init = 1.0; // Population fractions must sum to 1.
+        for k in 0..self.n_strains {
+            self.i_popf[(0, k)] = self.i_popf_init[(k, 0)];
+            self.r_popf[(0, k)] = 0.0;
+            s_init -= self.i_popf_init[(k, 0)];
+        }
+        self.s_popf[(0, 0)] = s_init;
+        return self;
+    }
+
+    /// Compute the derivative of every compartment.
+    fn derivatives(&self, y: &SystemVars) -> SystemVars {
+        let mut d = self.init_vars();
+        for k in 0..self.n_strains {
+            let force = self.incidence_rate[(k, 0)] * y.i[k];
+            d.s -= force * y.s;
+            d.i[k] += (force * y.s) - (self.removal_rate[(k, 0)] * y.i[k]);
+            d.r[k] += self.removal_rate[(k, 0)] * y.i[k];
+            for j in 0..self.n_strains {
+                let reinfection = force * (1.0 - self.cross_immunity[(j, k)]) * y.r[j];
+                d.i[k] += reinfection;
+                d.r[j] -= reinfection;
+            }
+        }
+        return d;
+    }
+
+    /// Construct a zeroed set of integrator variables.
+    fn init_vars(&self) -> SystemVars {
+        return SystemVars {
+            s: 0.0,
+            i: vec![0.0; self.n_strains],
+            r: vec![0.0; self.n_strains],
+        };
+    }
+
+    /// Read the integrator variables stored at index `t`.
+    fn vars_at(&self, t: usize) -> SystemVars {
+        let mut y = self.init_vars();
+        y.s = self.s_popf[(t, 0)];
+        for k in 0..self.n_strains {
+            y.i[k] = self.i_popf[(t, k)];
+            y.r[k] = self.r_popf[(t, k)];
+        }
+        return y;
+    }
+
+    /// Apply every introduction due at or before index `t`. `next` is the
+    /// index of the first introduction not yet applied.
+    fn introduce_at(&mut self, t: usize, next: &mut usize) {
+        let time = self.grid().time(t);
+        while (*next < self.introductions.len()) && (self.introductions[*next].0 <= time) {
+            let (_, strain, popf) = self.introductions[*next];
+            *next += 1;
+            let moved = popf.min(self.s_popf[(t, 0)]);
+            self.s_popf[(t, 0)] -= moved;
+            self.i_popf[(t, strain)] += moved;
+        }
+    }
+
+    /// Compute a 4th order runge-kutta time step for the system.
+    fn rk4_step(&self, t: usize) -> [SystemVars; 4] {
+        let h = [self.step_size / 2.0, self.step_size / 2.0, self.step_size];
+        let y0 = self.vars_at(t);
+        let k1 = self.derivatives(&y0);
+        let mut k = [k1, self.init_vars(), self.init_vars(), self.init_vars()];
+        for i in 0..3 {
+            let mut y = self.init_vars();
+            y.s = y0.s + (h[i] * k[i].s);
+            for j in 0..self.n_strains {
+                y.i[j] = y0.i[j] + (h[i] * k[i].i[j]);
+                y.r[j] = y0.r[j] + (h[i] * k[i].r[j]);
+            }
+            k[i + 1] = self.derivatives(&y);
+        }
+        return k;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let n = self.grid().n_steps;
+        let w = self.step_size / 6.0;
+        let mut next = 0;
+        for t in 0..n - 1 {
+            self.introduce_at(t, &mut next);
+            let k = self.rk4_step(t);
+            let ds = (k[0].s + (2.0 * k[1].s) + (2.0 * k[2].s) + k[3].s) * w;
+            self.s_popf[(t + 1, 0)] = self.s_popf[(t, 0)] + ds;
+            for j in 0..self.n_strains {
+                let di = (k[0].i[j] + (2.0 * k[1].i[j]) + (2.0 * k[2].i[j]) + k[3].i[j]) * w;
+                let dr = (k[0].r[j] + (2.0 * k[1].r[j]) + (2.0 * k[2].r[j]) + k[3].r[j]) * w;
+                self.i_popf[(t + 1, j)] = self.i_popf[(t, j)] + di;
+                self.r_popf[(t + 1, j)] = self.r_popf[(t, j)] + dr;
+            }
+        }
+        return self;
+    }
+
+    /// Compartments in long format, one record per time, strain and
+    /// compartment. Strains are named by `strain_names`, or by index if it
+    /// is empty. S is shared by all strains and has stratum `all`.
+    pub fn to_long(&self, strain_names: &[String]) -> Vec<LongRecord> {
+        let names = if strain_names.is_empty() {
+            index_names(self.n_strains)
+        } else {
+            strain_names.to_vec()
+        };
+        return to_long(
+            self.step_size,
+            &names,
+            &[
+                ("s", &self.s_popf),
+                ("i", &self.i_popf),
+                ("r", &self.r_popf),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::multistrain::Model;
+    use crate::sirrs::units::Rate;
+    use faer::{Mat, mat};
+
+    #[test]
+    fn test_new() {
+        let model = Model::new();
+        assert_eq!(
+            model.n_strains, 0,
+            "Bad n_strains, expected 0 got {}",
+            model.n_strains
+        );
+        assert_eq!(
+            model.cross_immunity,
+            Mat::new(),
+            "Bad cross_immunity, expected Mat::new() got {:?}",
+            model.cross_immunity
+        );
+    }
+
+    #[test]
+    fn test_run_rk4_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            mat![[0.01], [0.001]],
+            vec![Rate::per_day(0.4), Rate::per_day(0.6)],
+            vec![Rate::per_day(0.1), Rate::per_day(0.1)],
+            mat![[1.0, 0.5], [0.5, 1.0]],
+        );
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let mut total = model.s_popf[(t, 0)];
+            for k in 0..2 {
+                total += model.i_popf[(t, k)] + model.r_popf[(t, k)];
+            }
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_strain_matches_sir() {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            1.0,
+            mat![[0.01]],
+            vec![Rate::per_day(0.3)],
+            vec![Rate::per_day(0.1)],
+            mat![[1.0]],
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = crate::sirrs::sir::Model::new();
+        sir.configure(
+            50,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..50 {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_strain_replacement() {
+        let mut model = Model::new();
+        model.configure(
+            400,
+            0.5,
+            mat![[0.01], [0.0]],
+            vec![Rate::per_day(0.3), Rate::per_day(0.6)],
+            vec![Rate::per_day(0.1), Rate::per_day(0.1)],
+            mat![[1.0, 0.2], [1.0, 1.0]],
+        );
+        model.introduce(100.0, 1, 0.001);
+        model.init_popf();
+        model.run_rk4();
+        assert_eq!(
+            model.i_popf[(199, 1)],
+            0.0,
+            "Bad strain 1 before introduction, expected 0.0 got {}",
+            model.i_popf[(199, 1)]
+        );
+        let r_second: f64 = model.r_popf[(799, 1)];
+        assert!(
+            r_second > model.r_popf[(799, 0)],
+            "Expected escape strain to replace the first, got r = {:?}",
+            (model.r_popf[(799, 0)], r_second)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cross_immunity must be in [0, 1]")]
+    fn test_configure_rejects_cross_immunity() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            mat![[0.01], [0.0]],
+            vec![Rate::per_day(0.3), Rate::per_day(0.6)],
+            vec![Rate::per_day(0.1), Rate::per_day(0.1)],
+            mat![[1.0, 1.5], [1.0, 1.0]],
+        );
+    }
+
+    #[test]
+    fn test_to_long() {
+        let mut model = Model::new();
+        model.configure(
+            4,
+            1.0,
+            mat![[0.01], [0.001]],
+            vec![Rate::per_day(0.4), Rate::per_day(0.6)],
+            vec![Rate::per_day(0.1), Rate::per_day(0.1)],
+            mat![[1.0, 0.5], [0.5, 1.0]],
+        );
+        model.init_popf();
+        model.run_rk4();
+        let records = model.to_long(&["alpha".to_string(), "delta".to_string()]);
+        assert_eq!(
+            records.len(),
+            4 * 5,
+            "Bad number of records, expected {} got {}",
+            4 * 5,
+            records.len()
+        );
+        let last = records.last().unwrap();
+        assert_eq!(
+            (last.stratum.as_str(), last.compartment.as_str()),
+            ("delta", "r"),
+            "Bad last record, got {:?}",
+            last
+        );
+        assert_eq!(last.value, model.r_popf[(3, 1)], "Bad last value");
+        assert_eq!(records[0].stratum, "all", "Bad stratum for s");
+    }
+}
@@ -0,0 +1,533 @@
+//! Stochastic SIR simulation on a contact network.
+//!
+//! Nodes are individuals and edges their contacts, optionally weighted.
+//! Transmission runs per edge in continuous time: an infectious node infects
+//! each susceptible neighbour at rate `β w`, `w` the weight of the edge, and
+//! is removed at rate `γ`. The simulation is event driven: when a node is
+//! infected it draws its removal time and a transmission time along each
+//! of its edges, and only transmissions before removal are scheduled. A
+//! scheduled transmission to a node that has already been infected does
+//! nothing.
+//!
+//! Besides aggregate curves in the columns of [`crate::sir::Model::result`],
+//! every node's infection time, removal time and infector are reported.
+//! With the `petgraph` feature a network can be built from a
+//! `petgraph::Graph`.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::reproducible::ln;
+use crate::sirrs::rng::{self, SimRng};
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// A contact network as adjacency lists of `(neighbour, weight)`.
+/// Transmission runs from a node to each of its neighbours, so an undirected
+/// edge is listed in both nodes' adjacency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// Neighbours of each node, with the weight of the edge to them.
+    pub adjacency: Vec<Vec<(usize, f64)>>,
+}
+
+impl Network {
+    /// Network of `n_nodes` nodes and no edges.
+    pub fn new(n_nodes: usize) -> Self {
+        return Self {
+            adjacency: vec![Vec::new(); n_nodes],
+        };
+    }
+
+    /// Network of `n_nodes` nodes from undirected edges of weight 1.
+    pub fn from_edges(n_nodes: usize, edges: &[(usize, usize)]) -> Self {
+        let mut network = Self::new(n_nodes);
+        for (a, b) in edges.iter() {
+            network.add_edge(*a, *b, 1.0);
+        }
+        return network;
+    }
+
+    /// Network of `n_nodes` nodes from undirected edges `(a, b, weight)`.
+    pub fn from_weighted_edges(n_nodes: usize, edges: &[(usize, usize, f64)]) -> Self {
+        let mut network = Self::new(n_nodes);
+        for (a, b, weight) in edges.iter() {
+            network.add_edge(*a, *b, *weight);
+        }
+        return network;
+    }
+
+    /// Network from unweighted adjacency lists, taken as given: each list
+    /// holds the nodes its node can infect.
+    pub fn from_adjacency(adjacency: Vec<Vec<usize>>) -> Self {
+        let n_nodes = adjacency.len();
+        for neighbours in adjacency.iter() {
+            for b in neighbours.iter() {
+                assert!(
+                    *b < n_nodes,
+                    "Neighbour {} is not a node of a network of {} nodes",
+                    b,
+                    n_nodes
+                );
+            }
+        }
+        return Self {
+            adjacency: adjacency
+                .into_iter()
+                .map(|neighbours| neighbours.into_iter().map(|b| (b, 1.0)).collect())
+                .collect(),
+        };
+    }
+
+    /// Network from a petgraph graph, with edge weights given by `weight`.
+    /// Edges of directed graphs transmit from source to target only.
+    #[cfg(feature = "petgraph")]
+    pub fn from_graph<N, E, Ty, Ix>(
+        graph: &petgraph::Graph<N, E, Ty, Ix>,
+        weight: impl Fn(&E) -> f64,
+    ) -> Self
+    where
+        Ty: petgraph::EdgeType,
+        Ix: petgraph::graph::IndexType,
+    {
+        use petgraph::visit::EdgeRef;
+        let mut network = Self::new(graph.node_count());
+        for edge in graph.edge_references() {
+            let (a, b) = (edge.source().index(), edge.target().index());
+            let w = weight(edge.weight());
+            network.adjacency[a].push((b, w));
+            if !graph.is_directed() && (a != b) {
+                network.adjacency[b].push((a, w));
+            }
+        }
+        return network;
+    }
+
+    /// Add an undirected edge between `a` and `b`.
+    pub fn add_edge(&mut self, a: usize, b: usize, weight: f64) -> &mut Self {
+        let n_nodes = self.n_nodes();
+        assert!(
+            (a < n_nodes) & (b < n_nodes),
+            "Edge ({}, {}) is not within a network of {} nodes",
+            a,
+            b,
+            n_nodes
+        );
+        self.adjacency[a].push((b, weight));
+        if a != b {
+            self.adjacency[b].push((a, weight));
+        }
+        return self;
+    }
+
+    /// Number of nodes.
+    pub fn n_nodes(&self) -> usize {
+        return self.adjacency.len();
+    }
+
+    /// Number of neighbours of each node.
+    pub fn degrees(&self) -> Vec<usize> {
+        return self.adjacency.iter().map(|n| n.len()).collect();
+    }
+}
+
+/// Kind of a scheduled event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EventKind {
+    Removal,
+    Transmission { from: usize },
+}
+
+/// A scheduled event, ordered by time.
+struct Event {
+    t: f64,
+    node: usize,
+    kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .t
+            .total_cmp(&other.t)
+            .then(self.node.cmp(&other.node))
+            .then(self.kind.cmp(&other.kind));
+    }
+}
+
+/// Create and run an SIR simulation on a contact network.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Spacing of the recorded series.
+    pub step_size: f64,
+    /// Contact network.
+    pub network: Network,
+    /// Nodes infectious at the start.
+    pub initial_infected: Vec<usize>,
+    /// Transmission rate along an edge of weight 1.
+    pub transmission_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Infection time of each node, if it was infected.
+    pub infection_times: Vec<Option<f64>>,
+    /// Removal time of each node, if it was infected. May be after the end
+    /// of the recorded series.
+    pub removal_times: Vec<Option<f64>>,
+    /// Node that infected each node, if it was infected by another node.
+    pub infectors: Vec<Option<usize>>,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// Infections, as a population fraction, since the previous index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub incidence: Mat<f64>,
+    /// Infections, as a population fraction, up to each index. 1D Array
+    /// with one element per index of [`Model::grid`].
+    pub cumulative_incidence: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            network: Network::new(0),
+            initial_infected: Vec::new(),
+            transmission_rate: 0.0,
+            removal_rate: 0.0,
+            seed: 0,
+            infection_times: Vec::new(),
+            removal_times: Vec::new(),
+            infectors: Vec::new(),
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            incidence: Mat::new(),
+            cumulative_incidence: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        network: Network,
+        initial_infected: Vec<usize>,
+        transmission_rate: Rate,
+        removal_rate: Rate,
+        seed: u64,
+    ) -> &mut Self {
+        for node in initial_infected.iter() {
+            assert!(
+                *node < network.n_nodes(),
+                "Initially infected node {} is not a node of a network of {} nodes",
+                node,
+                network.n_nodes()
+            );
+        }
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.network = network;
+        self.initial_infected = initial_infected;
+        self.transmission_rate = transmission_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.seed = seed;
+        self.infection_times = Vec::new();
+        self.removal_times = Vec::new();
+        self.infectors = Vec::new();
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.incidence = Mat::zeros(n_steps, 1);
+        self.cumulative_incidence = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the recorded series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Waiting time to the next event of a clock with `rate`.
+    fn waiting_time(rng: &mut SimRng, rate: f64) -> f64 {
+        if rate <= 0.0 {
+            return f64::INFINITY;
+        }
+        return -ln(1.0 - rng.r#gen::<f64>()) / rate;
+    }
+
+    /// Infect `node` at time `t`, scheduling its removal and every
+    /// transmission along its edges before removal.
+    fn infect(
+        &mut self,
+        node: usize,
+        t: f64,
+        infector: Option<usize>,
+        rng: &mut SimRng,
+        events: &mut BinaryHeap<Reverse<Event>>,
+    ) {
+        let removal = t + Self::waiting_time(rng, self.removal_rate);
+        self.infection_times[node] = Some(t);
+        self.removal_times[node] = Some(removal);
+        self.infectors[node] = infector;
+        events.push(Reverse(Event {
+            t: removal,
+            node,
+            kind: EventKind::Removal,
+        }));
+        for k in 0..self.network.adjacency[node].len() {
+            let (neighbour, weight) = self.network.adjacency[node][k];
+            let transmission = t + Self::waiting_time(rng, self.transmission_rate * weight);
+            if transmission < removal {
+                events.push(Reverse(Event {
+                    t: transmission,
+                    node: neighbour,
+                    kind: EventKind::Transmission { from: node },
+                }));
+            }
+        }
+    }
+
+    /// Record node counts at index `k`.
+    fn record(&mut self, k: usize, counts: &[usize; 3], infections: usize) {
+        let n = self.network.n_nodes() as f64;
+        self.s_popf[(k, 0)] = (counts[0] as f64) / n;
+        self.i_popf[(k, 0)] = (counts[1] as f64) / n;
+        self.r_popf[(k, 0)] = (counts[2] as f64) / n;
+        self.cumulative_incidence[(k, 0)] = (infections as f64) / n;
+        if k > 0 {
+            self.incidence[(k, 0)] =
+                self.cumulative_incidence[(k, 0)] - self.cumulative_incidence[(k - 1, 0)];
+        }
+    }
+
+    /// Simulate the epidemic.
+    pub fn run(&mut self) -> &Model {
+        let mut rng = rng::rng(self.seed);
+        let grid = self.grid();
+        let n_nodes = self.network.n_nodes();
+        self.infection_times = vec![None; n_nodes];
+        self.removal_times = vec![None; n_nodes];
+        self.infectors = vec![None; n_nodes];
+        let mut events = BinaryHeap::new();
+        let mut counts = [n_nodes, 0, 0];
+        for k in 0..self.initial_infected.len() {
+            let node = self.initial_infected[k];
+            if self.infection_times[node].is_none() {
+                self.infect(node, 0.0, None, &mut rng, &mut events);
+                counts[0] -= 1;
+                counts[1] += 1;
+            }
+        }
+        let mut infections = 0;
+        let mut k = 0;
+        while let Some(Reverse(event)) = events.pop() {
+            while (k < grid.n_steps) && (grid.time(k) < event.t) {
+                self.record(k, &counts, infections);
+                k += 1;
+            }
+            match event.kind {
+                EventKind::Removal => {
+                    counts[1] -= 1;
+                    counts[2] += 1;
+                }
+                EventKind::Transmission { from } => {
+                    if self.infection_times[event.node].is_some() {
+                        continue;
+                    }
+                    self.infect(event.node, event.t, Some(from), &mut rng, &mut events);
+                    counts[0] -= 1;
+                    counts[1] += 1;
+                    infections += 1;
+                }
+            }
+        }
+        while k < grid.n_steps {
+            self.record(k, &counts, infections);
+            k += 1;
+        }
+        return self;
+    }
+
+    /// The recorded series with metadata of the run, in the columns of
+    /// [`crate::sir::Model::result`].
+    pub fn result(&self) -> SimulationResult {
+        let parameters = Parameters::from([
+            ("length".to_string(), self.length as f64),
+            ("n_nodes".to_string(), self.network.n_nodes() as f64),
+            (
+                "n_neighbours".to_string(),
+                self.network.degrees().iter().sum::<usize>() as f64,
+            ),
+            (
+                "initial_infected".to_string(),
+                self.initial_infected.len() as f64,
+            ),
+            ("transmission_rate".to_string(), self.transmission_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+        let mut metadata = RunMetadata::new("network", self.step_size, &parameters);
+        metadata.seed(self.seed);
+        let columns = [
+            &self.s_popf,
+            &self.i_popf,
+            &self.r_popf,
+            &self.incidence,
+            &self.cumulative_incidence,
+        ];
+        let n_steps = self.s_popf.nrows();
+        return SimulationResult {
+            metadata,
+            names: ["s", "i", "r", "incidence", "cumulative_incidence"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            times: self.grid().times(),
+            values: Mat::from_fn(n_steps, columns.len(), |t, j| columns[j][(t, 0)]),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::network::{Model, Network};
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_path_and_isolated_component() {
+        // A path 0-1-2-3-4 and a separate edge 5-6. Without removal every
+        // node on the path is infected in order, and the other edge never.
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 4), (5, 6)];
+        let mut model = Model::new();
+        model.configure(
+            50,
+            1.0,
+            Network::from_edges(7, &edges),
+            vec![0],
+            Rate::per_day(2.0),
+            Rate::per_day(0.0),
+            4,
+        );
+        model.run();
+        for node in 1..5 {
+            let (before, after) = (
+                model.infection_times[node - 1].unwrap(),
+                model.infection_times[node].unwrap(),
+            );
+            assert!(
+                before < after,
+                "Expected node {} infected after node {}, got {} and {}",
+                node,
+                node - 1,
+                after,
+                before
+            );
+            assert_eq!(
+                model.infectors[node],
+                Some(node - 1),
+                "Bad infector of node {}",
+                node
+            );
+        }
+        assert_eq!(
+            model.infection_times[5], None,
+            "Expected the separate component to escape"
+        );
+        let last = model.s_popf.nrows() - 1;
+        assert_eq!(
+            model.i_popf[(last, 0)],
+            5.0 / 7.0,
+            "Bad final i_popf, got {}",
+            model.i_popf[(last, 0)]
+        );
+        assert_eq!(
+            model.cumulative_incidence[(last, 0)],
+            4.0 / 7.0,
+            "Bad cumulative incidence, got {}",
+            model.cumulative_incidence[(last, 0)]
+        );
+    }
+
+    #[test]
+    fn test_edge_transmission_probability() {
+        // Along a single edge the infection reaches the neighbour before
+        // removal with probability β w / (β w + γ).
+        let n_pairs = 4000;
+        let edges: Vec<(usize, usize, f64)> =
+            (0..n_pairs).map(|k| (2 * k, (2 * k) + 1, 0.5)).collect();
+        let network = Network::from_weighted_edges(2 * n_pairs, &edges);
+        let seeds = (0..n_pairs).map(|k| 2 * k).collect();
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            network,
+            seeds,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            9,
+        );
+        model.run();
+        let infected = (0..n_pairs)
+            .filter(|k| model.infection_times[(2 * k) + 1].is_some())
+            .count();
+        let share = (infected as f64) / (n_pairs as f64);
+        assert!(
+            (share - (2.0 / 3.0)).abs() < 0.03,
+            "Bad transmission probability, expected 2/3 got {}",
+            share
+        );
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)] + model.i_popf[(t, 0)] + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-12,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+    }
+
+    #[cfg(feature = "petgraph")]
+    #[test]
+    fn test_from_graph() {
+        let mut graph = petgraph::graph::UnGraph::<(), f64>::new_undirected();
+        let nodes: Vec<_> = (0..3).map(|_| graph.add_node(())).collect();
+        graph.add_edge(nodes[0], nodes[1], 2.0);
+        graph.add_edge(nodes[1], nodes[2], 0.5);
+        let network = Network::from_graph(&graph, |w| *w);
+        assert_eq!(
+            network,
+            Network::from_weighted_edges(3, &[(0, 1, 2.0), (1, 2, 0.5)]),
+            "Bad network from graph"
+        );
+    }
+}
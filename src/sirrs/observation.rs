@@ -0,0 +1,151 @@
+//! Observation model mapping true incidence to reported cases.
+//!
+//! Only a fraction of infections are ever reported, and those that are
+//! reported arrive after a delay. Given true incidence per unit time, the
+//! expected reported cases at time t are
+//!
+//! ```text
+//! E[C(t)] = ρ Σ_d delay[d] incidence(t - d)
+//! ```
+//!
+//! with reporting fraction ρ and delay kernel `delay`. Reported cases may be
+//! drawn around this expectation with negative binomial noise, producing
+//! synthetic surveillance data from simulations.
+use crate::sirrs::rng;
+use crate::sirrs::units::Fraction;
+use faer::Mat;
+use rand_distr::{Distribution, Gamma, Poisson};
+
+/// Create and apply a reporting model.
+pub struct ReportingModel {
+    /// Fraction of infections that are eventually reported. Must be in [0, 1].
+    pub reporting_fraction: f64,
+    /// Probability that a reported case is reported `d` unit times after
+    /// infection, for each delay `d`. Normalized to sum to 1.
+    pub delay: Vec<f64>,
+    /// Negative binomial dispersion `k`, with variance `μ + μ² / k`. `None`
+    /// for Poisson noise.
+    pub dispersion: Option<f64>,
+    /// Seed for the random number generator.
+    pub seed: u64,
+}
+
+impl ReportingModel {
+    /// Create a new reporting model. Every infection is reported without
+    /// delay.
+    pub fn new() -> Self {
+        return Self {
+            reporting_fraction: 1.0,
+            delay: vec![1.0],
+            dispersion: None,
+            seed: 0,
+        };
+    }
+
+    /// Configure the reporting model. The delay kernel is normalized to sum
+    /// to 1.
+    pub fn configure(
+        &mut self,
+        reporting_fraction: Fraction,
+        delay: Vec<f64>,
+        dispersion: Option<f64>,
+        seed: u64,
+    ) -> &mut Self {
+        let total: f64 = delay.iter().sum();
+        assert!(
+            total > 0.0,
+            "delay kernel must have positive total, got {}",
+            total
+        );
+        if let Some(k) = dispersion {
+            assert!(
+                (k > 0.0) & k.is_finite(),
+                "dispersion must be positive and finite, got {}",
+                k
+            );
+        }
+        self.reporting_fraction = reporting_fraction.value();
+        self.delay = delay.iter().map(|p| p / total).collect();
+        self.dispersion = dispersion;
+        self.seed = seed;
+        return self;
+    }
+
+    /// Expected reported cases at each unit time (row) of each series
+    /// (column) of true incidence.
+    pub fn expected(&self, incidence: &Mat<f64>) -> Mat<f64> {
+        return Mat::from_fn(incidence.nrows(), incidence.ncols(), |t, j| {
+            let delayed: f64 = (0..self.delay.len().min(t + 1))
+                .map(|d| self.delay[d] * incidence[(t - d, j)])
+                .sum();
+            self.reporting_fraction * delayed
+        });
+    }
+
+    /// Draw reported cases at each unit time (row) of each series (column)
+    /// of true incidence.
+    ///
+    /// Negative binomial counts are drawn as a gamma mixture of Poissons.
+    pub fn sample(&self, incidence: &Mat<f64>) -> Mat<f64> {
+        let mut rng = rng::rng(self.seed);
+        let expected = self.expected(incidence);
+        let mut reported = Mat::zeros(expected.nrows(), expected.ncols());
+        for j in 0..expected.ncols() {
+            for t in 0..expected.nrows() {
+                let mean = expected[(t, j)].max(0.0);
+                let rate = match self.dispersion {
+                    Some(k) if mean > 0.0 => Gamma::new(k, mean / k).unwrap().sample(&mut rng),
+                    _ => mean,
+                };
+                reported[(t, j)] = if rate > 0.0 {
+                    Poisson::new(rate).unwrap().sample(&mut rng)
+                } else {
+                    0.0
+                };
+            }
+        }
+        return reported;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::units::Fraction;
+    use faer::{Mat, mat};
+
+    #[test]
+    fn test_expected_applies_fraction_and_delay() {
+        let mut model = ReportingModel::new();
+        model.configure(Fraction::of(0.5), vec![2.0, 1.0, 1.0], None, 0);
+        let expected = model.expected(&mat![[100.0], [0.0], [0.0], [40.0]]);
+        assert_eq!(
+            expected,
+            mat![[25.0], [12.5], [12.5], [10.0]],
+            "Bad expected reports, got {:?}",
+            expected
+        );
+    }
+
+    #[test]
+    fn test_sample_negative_binomial_moments() {
+        let mut model = ReportingModel::new();
+        model.configure(Fraction::of(0.4), vec![1.0], Some(2.0), 9);
+        let reported = model.sample(&Mat::from_fn(20000, 1, |_, _| 50.0));
+        let mean = (0..20000).map(|t| reported[(t, 0)]).sum::<f64>() / 20000.0;
+        let variance = (0..20000)
+            .map(|t| (reported[(t, 0)] - mean).powi(2))
+            .sum::<f64>()
+            / 19999.0;
+        assert!(
+            (mean - 20.0).abs() < 0.5,
+            "Bad mean reported cases, expected 20 got {}",
+            mean
+        );
+        assert!(
+            (variance - 220.0).abs() < 15.0,
+            "Bad variance of reported cases, expected 220 got {}",
+            variance
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Which steps of a run to store.
+//!
+//! Solvers integrate at a fine step size for accuracy, but storing every
+//! step of a long run costs memory in proportion to its length. An
+//! [`Output`] picks the steps worth keeping, for example
+//! [`crate::sir::Model::run_rk4_output`], while integration still proceeds
+//! at every step.
+
+/// Steps of a run to store.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// Every k-th step, starting from index 0. `Every(1)` is every step.
+    Every(usize),
+    /// The first step at or after each report time, sorted ascending.
+    /// Several report times within one step share a single stored step.
+    Times(Vec<f64>),
+}
+
+impl Output {
+    /// Store the first step at or after each of `times`, in any order.
+    pub fn times(mut times: Vec<f64>) -> Self {
+        times.sort_by(|a, b| a.total_cmp(b));
+        return Output::Times(times);
+    }
+
+    /// Whether the step at `index` of a run with step size `step_size` is
+    /// stored.
+    pub fn keeps(&self, index: usize, step_size: f64) -> bool {
+        match self {
+            Output::Every(stride) => {
+                assert!(*stride >= 1, "stride must be at least 1");
+                return index.is_multiple_of(*stride);
+            }
+            Output::Times(times) => {
+                let t = (index as f64) * step_size;
+                let reached = times.partition_point(|r| *r <= t);
+                let before = times.partition_point(|r| *r <= t - step_size);
+                return reached > before;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::output::Output;
+
+    #[test]
+    fn test_keeps() {
+        let every: Vec<usize> = (0..10)
+            .filter(|i| Output::Every(4).keeps(*i, 0.5))
+            .collect();
+        assert_eq!(every, vec![0, 4, 8], "Bad strided steps, got {:?}", every);
+        let output = Output::times(vec![2.2, 0.0, 1.0, 2.4]);
+        let kept: Vec<usize> = (0..10).filter(|i| output.keeps(*i, 0.5)).collect();
+        assert_eq!(kept, vec![0, 2, 5], "Bad report steps, got {:?}", kept);
+    }
+}
@@ -0,0 +1,224 @@
+//! Pair-approximation SIR model for networks.
+//!
+//! A deterministic counterpart to [`crate::network`]: on a network where
+//! every node has `k` neighbours, the dynamics are written in terms of
+//! singles and of `[SS]` and `[SI]`, the number of susceptible–susceptible
+//! and susceptible–infectious pairs of neighbours per node, counted in both
+//! directions. Triples are closed by the pair approximation
+//! `[ABC] ≈ (k - 1) / k [AB][BC] / [B]`, giving
+//!
+//! ```text
+//! dS/dt    = -τ [SI]
+//! dI/dt    = τ [SI] - γ I
+//! d[SS]/dt = -2 τ [SSI]
+//! d[SI]/dt = τ ([SSI] - [ISI] - [SI]) - γ [SI]
+//! ```
+//!
+//! with `τ` the transmission rate per edge and `γ` the removal rate. Unlike
+//! the SIR model, infection is limited by the local depletion of
+//! susceptible neighbours, and as `k` grows with `τ k` fixed it approaches
+//! the SIR model with incidence rate `τ k`.
+//!
+//! The model is a [`System`], so it is solved by a [`crate::pipeline::Pipeline`]
+//! with its interventions, observation models and output sinks.
+use crate::sirrs::pipeline::{Parameters, System};
+use crate::sirrs::units::{Rate, TimeUnit};
+
+/// Pair-approximation SIR model on a network of mean degree `k`.
+pub struct Model {
+    /// Number of neighbours of every node.
+    pub mean_degree: f64,
+    /// Initial infectious population fraction, seeded at random.
+    pub i_popf_init: f64,
+    /// Transmission rate along an edge from I to S.
+    pub transmission_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            mean_degree: 0.0,
+            i_popf_init: 0.0,
+            transmission_rate: 0.0,
+            removal_rate: 0.0,
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        mean_degree: f64,
+        i_popf_init: f64,
+        transmission_rate: Rate,
+        removal_rate: Rate,
+    ) -> &mut Self {
+        assert!(
+            mean_degree > 1.0,
+            "mean_degree must exceed 1, got {}",
+            mean_degree
+        );
+        self.mean_degree = mean_degree;
+        self.i_popf_init = i_popf_init;
+        self.transmission_rate = transmission_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        return self;
+    }
+
+    /// Basic reproduction number of the pair approximation,
+    /// `τ (k - 1) / (τ + γ)`: an infected node has `k - 1` susceptible
+    /// neighbours besides its infector, each infected before removal with
+    /// probability `τ / (τ + γ)`.
+    pub fn r0(&self) -> f64 {
+        return self.transmission_rate * (self.mean_degree - 1.0)
+            / (self.transmission_rate + self.removal_rate);
+    }
+}
+
+/// State (S, I, R) population fractions followed by `[SS]` and `[SI]` pairs
+/// per node. Infections are seeded at random, so initially
+/// `[SS] = k S²` and `[SI] = k S I`.
+impl System for Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i", "r", "ss", "si"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let k = self.mean_degree;
+        let i = self.i_popf_init;
+        let s = 1.0 - i;
+        return vec![s, i, 0.0, k * s * s, k * s * i];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("mean_degree".to_string(), self.mean_degree),
+            ("transmission_rate".to_string(), self.transmission_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let (s, i, ss, si) = (y[0], y[1], y[3], y[4]);
+        let k = parameters["mean_degree"];
+        let tau = parameters["transmission_rate"];
+        let gamma = parameters["removal_rate"];
+        let closure = (k - 1.0) / k;
+        let (ssi, isi) = if s > 0.0 {
+            (closure * ss * si / s, closure * si * si / s)
+        } else {
+            (0.0, 0.0)
+        };
+        let infection = self.incidence(t, y, parameters);
+        return vec![
+            -infection,
+            infection - (gamma * i),
+            gamma * i,
+            -2.0 * tau * ssi,
+            (tau * (ssi - isi - si)) - (gamma * si),
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return parameters["transmission_rate"] * y[4];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::generators::configuration_model;
+    use crate::sirrs::pairwise::Model;
+    use crate::sirrs::pipeline::Pipeline;
+    use crate::sirrs::units::Rate;
+    use crate::sirrs::{network, sir};
+
+    #[test]
+    fn test_large_degree_matches_sir() {
+        let mut model = Model::new();
+        model.configure(
+            2000.0,
+            0.01,
+            Rate::per_day(0.4 / 2000.0),
+            Rate::per_day(0.1),
+        );
+        let mut pipeline = Pipeline::new(Box::new(model));
+        pipeline.configure(60, 0.5);
+        pipeline.run_rk4();
+        let mut sir = sir::Model::new();
+        sir.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..sir.i_popf.nrows() {
+            assert!(
+                (pipeline.state[(t, 1)] - sir.i_popf[(t, 0)]).abs() < 1e-3,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                pipeline.state[(t, 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_random_regular_network() {
+        let n_nodes = 5000;
+        let mut simulation = network::Model::new();
+        simulation.configure(
+            80,
+            1.0,
+            configuration_model(&vec![5; n_nodes], 1),
+            (0..50).collect(),
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+            2,
+        );
+        simulation.run();
+        let mut model = Model::new();
+        model.configure(5.0, 0.01, Rate::per_day(0.3), Rate::per_day(0.2));
+        assert!(
+            (model.r0() - 2.4).abs() < 1e-12,
+            "Bad r0, expected 2.4 got {}",
+            model.r0()
+        );
+        let mut pipeline = Pipeline::new(Box::new(model));
+        pipeline.configure(80, 0.5);
+        pipeline.run_rk4();
+        let last = pipeline.state.nrows() - 1;
+        let expected = simulation.r_popf[(simulation.r_popf.nrows() - 1, 0)];
+        assert!(
+            (pipeline.state[(last, 2)] - expected).abs() < 0.05,
+            "Bad final size, expected {} got {}",
+            expected,
+            pipeline.state[(last, 2)]
+        );
+        // Pairs can only be lost: every SS and SI pair is counted in S's
+        // k neighbours.
+        for t in 0..=last {
+            let (s, ss, si) = (
+                pipeline.state[(t, 0)],
+                pipeline.state[(t, 3)],
+                pipeline.state[(t, 4)],
+            );
+            assert!(
+                ss + si <= (5.0 * s) + 1e-9,
+                "Too many pairs at index {}, got {} for s {}",
+                t,
+                ss + si,
+                s
+            );
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Bootstrap particle filter for nowcasting a stochastic SIR.
+//!
+//! Each particle is a stochastic SIR in counts whose incidence rate follows
+//! a random walk on the log scale, so transmission may change over time.
+//! Between observations particles are advanced by binomial tau-leaping, the
+//! same transitions as [`crate::chainbinomial`] in continuous time:
+//!
+//! ```text
+//! new infections ~ Binomial(S, 1 - exp(-beta * I / N * dt))
+//! new removals   ~ Binomial(I, 1 - exp(-gamma * dt))
+//! ```
+//!
+//! Every observed case count reweights the particles by its Poisson
+//! likelihood given the reported fraction of their new infections, and the
+//! particles are then resampled. Observations can be fed one at a time as
+//! they arrive with [`ParticleFilter::step`], giving filtered estimates of
+//! the current S, I and R and the effective reproduction number
+//! `R_eff = beta / gamma * S / N`.
+use crate::sirrs::interventions::Interval;
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::rng::{self, SimRng};
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use rand::Rng;
+use rand_distr::{Binomial, Distribution, StandardNormal};
+
+/// State of one particle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Particle {
+    s: u64,
+    i: u64,
+    r: u64,
+    log_beta: f64,
+}
+
+/// Filtered estimates after assimilating one observation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Time of the observation.
+    pub t: f64,
+    /// Susceptible count.
+    pub s: Interval,
+    /// Infectious count.
+    pub i: Interval,
+    /// Removed count.
+    pub r: Interval,
+    /// Effective reproduction number.
+    pub r_eff: Interval,
+    /// Effective sample size of the weights before resampling.
+    pub ess: f64,
+}
+
+/// Sequential Monte Carlo estimation of a stochastic SIR from case counts.
+pub struct ParticleFilter {
+    /// Total population size.
+    pub population: u64,
+    /// Initial number of infectious individuals.
+    pub i_init: u64,
+    /// Initial guess of the S → I transition rate.
+    pub incidence_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+    /// Fraction of new infections reported as cases. Must be in (0, 1].
+    pub reporting_fraction: f64,
+    /// Standard deviation of the log incidence rate random walk per unit
+    /// time.
+    pub volatility: f64,
+    /// Time between observations.
+    pub interval: f64,
+    /// Number of tau-leaping steps between observations.
+    pub substeps: usize,
+    /// Number of particles.
+    pub n_particles: usize,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Time of the last assimilated observation.
+    pub t: f64,
+    /// Log marginal likelihood of the observations so far.
+    pub log_likelihood: f64,
+    /// Particles, equally weighted after each step.
+    particles: Vec<Particle>,
+    /// Random number generator, carried across steps.
+    rng: SimRng,
+}
+
+impl ParticleFilter {
+    /// Create a new filter object.
+    pub fn new() -> Self {
+        return Self {
+            population: 0,
+            i_init: 0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            reporting_fraction: 1.0,
+            volatility: 0.0,
+            interval: 1.0,
+            substeps: 1,
+            n_particles: 0,
+            seed: 0,
+            t: 0.0,
+            log_likelihood: 0.0,
+            particles: Vec::new(),
+            rng: rng::rng(0),
+        };
+    }
+
+    /// Configure filter parameters. Observations are one unit of time
+    /// apart, advanced in ten tau-leaping steps; see
+    /// [`ParticleFilter::timing`] to change either.
+    pub fn configure(
+        &mut self,
+        population: u64,
+        i_init: u64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        reporting_fraction: Fraction,
+        volatility: f64,
+        n_particles: usize,
+        seed: u64,
+    ) -> &mut Self {
+        assert!(
+            i_init <= population,
+            "i_init must not exceed population, got {} > {}",
+            i_init,
+            population
+        );
+        assert!(
+            reporting_fraction.value() > 0.0,
+            "reporting_fraction must be positive"
+        );
+        assert!(n_particles >= 1, "n_particles must be at least 1");
+        self.population = population;
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.reporting_fraction = reporting_fraction.value();
+        self.volatility = volatility;
+        self.n_particles = n_particles;
+        self.seed = seed;
+        self.interval = 1.0;
+        self.substeps = 10;
+        return self;
+    }
+
+    /// Set the time between observations and the number of tau-leaping
+    /// steps taken between them.
+    pub fn timing(&mut self, interval: f64, substeps: usize) -> &mut Self {
+        assert!(substeps >= 1, "substeps must be at least 1");
+        self.interval = interval;
+        self.substeps = substeps;
+        return self;
+    }
+
+    /// Draw the initial particles. Log incidence rates are spread around
+    /// the initial guess with standard deviation `volatility`.
+    pub fn init_particles(&mut self) -> &mut Self {
+        self.rng = rng::rng(self.seed);
+        self.t = 0.0;
+        self.log_likelihood = 0.0;
+        let log_beta = ln(self.incidence_rate);
+        self.particles = (0..self.n_particles)
+            .map(|_| {
+                let z: f64 = self.rng.sample(StandardNormal);
+                return Particle {
+                    s: self.population - self.i_init,
+                    i: self.i_init,
+                    r: 0,
+                    log_beta: log_beta + (self.volatility * z),
+                };
+            })
+            .collect();
+        return self;
+    }
+
+    /// Advance one particle by one observation interval, returning its new
+    /// infections over the interval.
+    fn propagate(&mut self, k: usize) -> u64 {
+        let dt = self.interval / (self.substeps as f64);
+        let n_pop = self.population as f64;
+        let mut p = self.particles[k];
+        let mut infections = 0;
+        for _ in 0..self.substeps {
+            let z: f64 = self.rng.sample(StandardNormal);
+            p.log_beta += self.volatility * dt.sqrt() * z;
+            let beta = exp(p.log_beta);
+            let p_infection = 1.0 - exp(-beta * (p.i as f64) / n_pop * dt);
+            let p_removal = 1.0 - exp(-self.removal_rate * dt);
+            let new_i = draw_binomial(p.s, p_infection, &mut self.rng);
+            let new_r = draw_binomial(p.i, p_removal, &mut self.rng);
+            p.s -= new_i;
+            p.i = p.i + new_i - new_r;
+            p.r += new_r;
+            infections += new_i;
+        }
+        self.particles[k] = p;
+        return infections;
+    }
+
+    /// Assimilate the number of cases observed over the next interval and
+    /// return the filtered estimates. NaN counts are treated as missing and
+    /// only advance the particles.
+    pub fn step(&mut self, cases: f64) -> Estimate {
+        assert!(
+            !self.particles.is_empty(),
+            "particles must be initialized with init_particles"
+        );
+        let n = self.n_particles;
+        let mut log_weights = Vec::with_capacity(n);
+        for k in 0..n {
+            let infections = self.propagate(k);
+            let expected = self.reporting_fraction * (infections as f64);
+            log_weights.push(poisson_log_pmf(cases, expected));
+        }
+        self.t += self.interval;
+        let max = log_weights
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = if max.is_finite() {
+            log_weights.iter().map(|w| exp(w - max)).collect()
+        } else {
+            // No particle can explain the observation; fall back to equal
+            // weights rather than losing the filter.
+            vec![1.0; n]
+        };
+        let total: f64 = weights.iter().sum();
+        if max.is_finite() {
+            self.log_likelihood += max + ln(total / (n as f64));
+        }
+        let ess = total * total / weights.iter().map(|w| w * w).sum::<f64>();
+        self.resample(&weights, total);
+        return self.estimate(ess);
+    }
+
+    /// Assimilate a series of observations, one per interval.
+    pub fn run(&mut self, cases: &[f64]) -> Vec<Estimate> {
+        return cases.iter().map(|c| self.step(*c)).collect();
+    }
+
+    /// Systematic resampling in proportion to `weights`.
+    fn resample(&mut self, weights: &[f64], total: f64) {
+        let n = self.n_particles;
+        let step = total / (n as f64);
+        let mut target = self.rng.r#gen::<f64>() * step;
+        let mut cumulative = weights[0];
+        let mut k = 0;
+        let mut resampled = Vec::with_capacity(n);
+        for _ in 0..n {
+            while (cumulative < target) & (k < n - 1) {
+                k += 1;
+                cumulative += weights[k];
+            }
+            resampled.push(self.particles[k]);
+            target += step;
+        }
+        self.particles = resampled;
+    }
+
+    /// Summaries of the equally weighted particles.
+    fn estimate(&self, ess: f64) -> Estimate {
+        let summarize = |f: &dyn Fn(&Particle) -> f64| {
+            let values: Vec<f64> = self.particles.iter().map(f).collect();
+            return Interval::from_values(&values, 0.95);
+        };
+        let n_pop = self.population as f64;
+        return Estimate {
+            t: self.t,
+            s: summarize(&|p| p.s as f64),
+            i: summarize(&|p| p.i as f64),
+            r: summarize(&|p| p.r as f64),
+            r_eff: summarize(&|p| exp(p.log_beta) / self.removal_rate * (p.s as f64) / n_pop),
+            ess,
+        };
+    }
+}
+
+/// Draw from Binomial(n, p), allowing `n = 0`.
+fn draw_binomial(n: u64, p: f64, rng: &mut SimRng) -> u64 {
+    if (n == 0) | (p <= 0.0) {
+        return 0;
+    }
+    return Binomial::new(n, p.min(1.0)).unwrap().sample(rng);
+}
+
+/// Poisson log-probability of `count` given mean `expected`, zero for NaN
+/// counts.
+fn poisson_log_pmf(count: f64, expected: f64) -> f64 {
+    if count.is_nan() {
+        return 0.0;
+    }
+    if expected <= 0.0 {
+        return if count == 0.0 { 0.0 } else { f64::NEG_INFINITY };
+    }
+    let log_factorial: f64 = (2..=(count.round() as u64)).map(|k| ln(k as f64)).sum();
+    return (count * ln(expected)) - expected - log_factorial;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::particle::ParticleFilter;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::{Fraction, Rate};
+
+    /// Daily case counts of a deterministic SIR in a population of 100000.
+    fn cases(changepoints: Vec<(f64, f64)>) -> Vec<f64> {
+        let mut model = sir::Model::new();
+        model.configure(
+            80,
+            1.0,
+            1e-4,
+            0.0,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.changepoints(
+            changepoints
+                .into_iter()
+                .map(|(t, rate)| (t, Rate::per_day(rate)))
+                .collect(),
+        );
+        model.init_popf();
+        model.run_rk4();
+        return (1..80)
+            .map(|t| (model.incidence[(t, 0)] * 100000.0).round())
+            .collect();
+    }
+
+    fn filter() -> ParticleFilter {
+        let mut filter = ParticleFilter::new();
+        filter.configure(
+            100000,
+            10,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(1.0),
+            0.1,
+            1000,
+            4,
+        );
+        filter.init_particles();
+        return filter;
+    }
+
+    #[test]
+    fn test_tracks_r_eff() {
+        let observations = cases(Vec::new());
+        let mut filter = filter();
+        let estimates = filter.run(&observations[..40]);
+        let last = estimates.last().unwrap();
+        let truth = {
+            let mut model = sir::Model::new();
+            model.configure(
+                41,
+                1.0,
+                1e-4,
+                0.0,
+                Rate::per_day(0.3),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            model.init_popf();
+            model.run_rk4();
+            3.0 * model.s_popf[(40, 0)]
+        };
+        assert!(
+            (last.r_eff.mean - truth).abs() < 0.3,
+            "Bad filtered R_eff, expected about {} got {:?}",
+            truth,
+            last.r_eff
+        );
+        assert!(
+            filter.log_likelihood.is_finite(),
+            "Bad log likelihood, got {}",
+            filter.log_likelihood
+        );
+    }
+
+    #[test]
+    fn test_detects_change() {
+        let observations = cases(vec![(30.0, 0.05)]);
+        let mut filter = filter();
+        let estimates = filter.run(&observations);
+        assert!(
+            estimates[25].r_eff.mean > 1.5,
+            "Expected growth before the change, got {:?}",
+            estimates[25].r_eff
+        );
+        assert!(
+            estimates[60].r_eff.mean < 1.0,
+            "Expected decline after the change, got {:?}",
+            estimates[60].r_eff
+        );
+    }
+
+    #[test]
+    fn test_missing_observations() {
+        let mut filter = filter();
+        let estimate = filter.step(f64::NAN);
+        assert_eq!(
+            estimate.ess, 1000.0,
+            "Expected equal weights for a missing observation, got ess {}",
+            estimate.ess
+        );
+    }
+}
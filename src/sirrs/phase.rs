@@ -0,0 +1,162 @@
+//! (S, I) phase-plane output for teaching materials.
+//!
+//! A [`PhasePlane`] holds a solved trajectory of a [`crate::sir::Model`] in
+//! the (S, I) plane together with the nullclines of its equations, where
+//! dS/dt or dI/dt is zero, and a direction field over the plane, all
+//! computed from the model's parameters and derivative functions.
+//! [`write_phase_plane`] writes them as one long csv for plotting.
+use crate::sirrs::sir;
+use std::io::{self, Write};
+
+/// One arrow of a direction field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arrow {
+    /// Susceptible fraction at the arrow's base.
+    pub s: f64,
+    /// Infectious fraction at the arrow's base.
+    pub i: f64,
+    /// dS/dt at the base.
+    pub ds: f64,
+    /// dI/dt at the base.
+    pub di: f64,
+}
+
+/// Trajectory, nullclines and direction field in the (S, I) plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhasePlane {
+    /// Solved (S, I) at each index.
+    pub trajectory: Vec<(f64, f64)>,
+    /// Points along the dS/dt = 0 nullcline inside the plane: I = 0, and the
+    /// line S = recovery_rate / incidence_rate.
+    pub s_nullcline: Vec<(f64, f64)>,
+    /// Points along the dI/dt = 0 nullcline inside the plane: I = 0, and the
+    /// line S = (removal_rate + recovery_rate) / incidence_rate.
+    pub i_nullcline: Vec<(f64, f64)>,
+    /// Arrows on an even grid over S + I ≤ 1.
+    pub direction_field: Vec<Arrow>,
+}
+
+impl PhasePlane {
+    /// Phase plane of a solved `model`, with nullclines and direction field
+    /// at the incidence rate in effect at time `t`, and `n` points along
+    /// each nullcline and each side of the direction field grid.
+    pub fn new(model: &sir::Model, t: f64, n: usize) -> Self {
+        assert!(n >= 2, "n must be at least 2");
+        let trajectory = (0..model.s_popf.nrows())
+            .map(|k| (model.s_popf[(k, 0)], model.i_popf[(k, 0)]))
+            .collect();
+        let beta = model.incidence_rate_at(t);
+        let axis: Vec<(f64, f64)> = (0..n)
+            .map(|k| ((k as f64) / ((n - 1) as f64), 0.0))
+            .collect();
+        let vertical = |s: f64| -> Vec<(f64, f64)> {
+            if !((beta > 0.0) & (0.0..=1.0).contains(&s)) {
+                return Vec::new();
+            }
+            return (0..n)
+                .map(|k| (s, (1.0 - s) * (k as f64) / ((n - 1) as f64)))
+                .collect();
+        };
+        let mut s_nullcline = axis.clone();
+        s_nullcline.extend(vertical(model.recovery_rate / beta));
+        let mut i_nullcline = axis;
+        i_nullcline.extend(vertical((model.removal_rate + model.recovery_rate) / beta));
+        let mut direction_field = Vec::new();
+        for a in 0..n {
+            for b in 0..n - a {
+                let s = (a as f64) / ((n - 1) as f64);
+                let i = (b as f64) / ((n - 1) as f64);
+                direction_field.push(Arrow {
+                    s,
+                    i,
+                    ds: model.dsdt(t, s, i),
+                    di: model.didt(t, s, i),
+                });
+            }
+        }
+        return Self {
+            trajectory,
+            s_nullcline,
+            i_nullcline,
+            direction_field,
+        };
+    }
+}
+
+/// Write a phase plane as csv with columns `curve,s,i,ds,di`, where `curve`
+/// is `trajectory`, `s_nullcline`, `i_nullcline` or `direction_field`.
+/// `ds` and `di` are empty except on the direction field.
+pub fn write_phase_plane<W: Write>(plane: &PhasePlane, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "curve,s,i,ds,di")?;
+    for (curve, points) in [
+        ("trajectory", &plane.trajectory),
+        ("s_nullcline", &plane.s_nullcline),
+        ("i_nullcline", &plane.i_nullcline),
+    ] {
+        for (s, i) in points.iter() {
+            writeln!(writer, "{},{},{},,", curve, s, i)?;
+        }
+    }
+    for arrow in plane.direction_field.iter() {
+        writeln!(
+            writer,
+            "direction_field,{},{},{},{}",
+            arrow.s, arrow.i, arrow.ds, arrow.di
+        )?;
+    }
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::phase::{PhasePlane, write_phase_plane};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_phase_plane() {
+        let mut model = sir::Model::new();
+        model.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let plane = PhasePlane::new(&model, 0.0, 11);
+        // Infection peaks where the trajectory crosses the dI/dt nullcline.
+        let peak = (0..model.i_popf.nrows())
+            .max_by(|a, b| model.i_popf[(*a, 0)].total_cmp(&model.i_popf[(*b, 0)]))
+            .unwrap();
+        let (s_peak, _) = plane.trajectory[peak];
+        assert!(
+            (s_peak - 0.25).abs() < 0.01,
+            "Bad S at the peak, expected 0.25 got {}",
+            s_peak
+        );
+        assert!(
+            plane
+                .i_nullcline
+                .iter()
+                .any(|(s, i)| (*s == 0.25) & (*i > 0.0)),
+            "Expected the vertical dI/dt nullcline at S = 0.25"
+        );
+        assert_eq!(
+            plane.direction_field.len(),
+            66,
+            "Bad number of arrows, expected 66 got {}",
+            plane.direction_field.len()
+        );
+        let mut buffer = Vec::new();
+        write_phase_plane(&plane, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(
+            text.lines().any(|l| l.starts_with("i_nullcline,0.25,")),
+            "Expected nullcline rows in the csv"
+        );
+    }
+}
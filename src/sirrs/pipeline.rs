@@ -0,0 +1,1926 @@
+//! Composable dynamics, intervention and observation layers.
+//!
+//! A [`Pipeline`] stacks three independent layers:
+//!  - a [`System`], the compartmental dynamics, with named parameters
+//!  - any number of [`Intervention`]s, which modify parameters over time
+//!  - an optional [`ReportingModel`], mapping true incidence to reports
+//!
+//! The pipeline's solver integrates the system with the parameters in effect
+//! at each stage time, tracking incidence alongside the state, so an
+//! intervention or observation model written once works with every system.
+//! Every compartmental model solved by differential equations is a system,
+//! using its configured rates and initial fractions: [`crate::sir::Model`],
+//! [`crate::erlang::Model`], [`crate::dismod::Model`],
+//! [`crate::hospital::Model`], [`crate::age::Model`],
+//! [`crate::carrier::Model`], [`crate::msir::Model`], [`crate::sirs::Model`],
+//! [`crate::waterborne::Model`], [`crate::resistance::Model`],
+//! [`crate::riskgroup::Model`], [`crate::multistrain::Model`],
+//! [`crate::delay::Model`] and [`crate::pairwise::Model`]. The stochastic
+//! [`crate::sde::Model`] and [`crate::ssa::Model`] are systems through their
+//! deterministic equations.
+//!
+//! Time-dependent parameters configured on a model, such as the SIR model's
+//! changepoints, seasonality and importation, are the system's own
+//! intervention layers, see [`System::interventions`], applied before those
+//! added to the pipeline. A system with a delay reads its past state from
+//! the pipeline's history of the solve, see [`System::delayed_derivatives`].
+//!
+//! Steps can be streamed to any [`OutputSink`] as they are solved, see
+//! [`Pipeline::run_rk4_into`], so the pipeline is the stepping path through
+//! which every model system is written to a sink. A solved pipeline is
+//! packaged with its run metadata by [`Pipeline::result`], and its rates are
+//! fitted to reports by [`crate::fit::Fit::pipeline`].
+use crate::sirrs::age;
+use crate::sirrs::carrier;
+use crate::sirrs::delay::{self, History};
+use crate::sirrs::dismod;
+use crate::sirrs::erlang;
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::hospital;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::msir;
+use crate::sirrs::multistrain;
+use crate::sirrs::observation::ReportingModel;
+use crate::sirrs::resistance;
+use crate::sirrs::riskgroup;
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::sde;
+use crate::sirrs::sink::OutputSink;
+use crate::sirrs::sir::{self, Seasonality};
+use crate::sirrs::sirs;
+use crate::sirrs::ssa;
+use crate::sirrs::waterborne;
+use faer::Mat;
+use std::collections::BTreeMap;
+use std::io;
+
+/// Named model parameters.
+pub type Parameters = BTreeMap<String, f64>;
+
+/// Compartmental dynamics.
+pub trait System {
+    /// Names of the state variables, in state vector order.
+    fn state_names(&self) -> Vec<String>;
+    /// State at t = 0.
+    fn initial_state(&self) -> Vec<f64>;
+    /// Parameter values before any intervention.
+    fn parameters(&self) -> Parameters;
+    /// Derivative of every state variable at time `t`.
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64>;
+    /// Rate of new cases at time `t`, the flux counted as incidence.
+    fn incidence(&self, t: f64, y: &[f64], parameters: &Parameters) -> f64;
+    /// Layers modifying the system's own parameters over time, such as
+    /// configured changepoints. [`Pipeline::new`] applies them before any
+    /// other intervention. None by default.
+    fn interventions(&self) -> Vec<Box<dyn Intervention>> {
+        return Vec::new();
+    }
+    /// Delay at which the dynamics read the past state, zero for none, see
+    /// [`System::delayed_derivatives`].
+    fn delay(&self) -> f64 {
+        return 0.0;
+    }
+    /// Derivative of every state variable at time `t`, with `lagged` the
+    /// state at `t - delay`, or None while that is before t = 0. Systems
+    /// without a delay ignore `lagged`, as the default does.
+    fn delayed_derivatives(
+        &self,
+        t: f64,
+        y: &[f64],
+        _lagged: Option<&[f64]>,
+        parameters: &Parameters,
+    ) -> Vec<f64> {
+        return self.derivatives(t, y, parameters);
+    }
+    /// Rate of new cases at time `t`, with `lagged` as for
+    /// [`System::delayed_derivatives`].
+    fn delayed_incidence(
+        &self,
+        t: f64,
+        y: &[f64],
+        _lagged: Option<&[f64]>,
+        parameters: &Parameters,
+    ) -> f64 {
+        return self.incidence(t, y, parameters);
+    }
+}
+
+/// A time-dependent modification of parameters.
+pub trait Intervention {
+    /// Modify `parameters` in place for time `t`.
+    fn apply(&self, t: f64, parameters: &mut Parameters);
+}
+
+/// Scale one parameter by `multiplier` while `start <= t < end`.
+pub struct ScaleParameter {
+    /// Name of the parameter to scale.
+    pub name: String,
+    /// Time the intervention starts.
+    pub start: f64,
+    /// Time the intervention ends.
+    pub end: f64,
+    /// Factor applied to the parameter.
+    pub multiplier: f64,
+}
+
+impl ScaleParameter {
+    /// Create a new parameter scaling intervention.
+    pub fn new(name: &str, start: f64, end: f64, multiplier: f64) -> Self {
+        return Self {
+            name: name.to_string(),
+            start,
+            end,
+            multiplier,
+        };
+    }
+}
+
+impl Intervention for ScaleParameter {
+    fn apply(&self, t: f64, parameters: &mut Parameters) {
+        if (self.start <= t) & (t < self.end)
+            && let Some(value) = parameters.get_mut(&self.name)
+        {
+            *value *= self.multiplier;
+        }
+    }
+}
+
+/// Set one parameter from a rate schedule at every time.
+pub struct ScheduleParameter {
+    /// Name of the parameter to set.
+    pub name: String,
+    /// Value of the parameter over time.
+    pub schedule: RateSchedule,
+}
+
+impl ScheduleParameter {
+    /// Create a new scheduled parameter intervention.
+    pub fn new(name: &str, schedule: RateSchedule) -> Self {
+        return Self {
+            name: name.to_string(),
+            schedule,
+        };
+    }
+}
+
+impl Intervention for ScheduleParameter {
+    fn apply(&self, t: f64, parameters: &mut Parameters) {
+        if let Some(value) = parameters.get_mut(&self.name) {
+            *value = self.schedule.at(t);
+        }
+    }
+}
+
+/// Force one parameter seasonally, multiplying it by the factor of a
+/// [`Seasonality`] at every time.
+pub struct SeasonalForcing {
+    /// Name of the parameter to force.
+    pub name: String,
+    /// The forcing.
+    pub seasonality: Seasonality,
+}
+
+impl SeasonalForcing {
+    /// Create a new seasonal forcing intervention.
+    pub fn new(name: &str, seasonality: Seasonality) -> Self {
+        return Self {
+            name: name.to_string(),
+            seasonality,
+        };
+    }
+}
+
+impl Intervention for SeasonalForcing {
+    fn apply(&self, t: f64, parameters: &mut Parameters) {
+        if let Some(value) = parameters.get_mut(&self.name) {
+            *value *= self.seasonality.factor(t);
+        }
+    }
+}
+
+/// Incidence rate changepoints and importation breakpoints on the model
+/// become [`ScheduleParameter`]s on `incidence_rate` and `importation`, and
+/// its seasonality a [`SeasonalForcing`] of the scheduled `incidence_rate`.
+impl System for sir::Model {
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "i".to_string(), "r".to_string()];
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let s_init = self.population - self.i_popf_init - self.r_popf_init;
+        return vec![s_init, self.i_popf_init, self.r_popf_init];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("recovery_rate".to_string(), self.recovery_rate),
+            ("importation".to_string(), self.importation.initial),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        let recovery = parameters["recovery_rate"] * y[1];
+        return vec![
+            -infection + recovery,
+            infection - removal - recovery,
+            removal,
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let beta = parameters["incidence_rate"];
+        return (beta * y[0] * y[1] / self.population) + (parameters["importation"] * y[0]);
+    }
+
+    fn interventions(&self) -> Vec<Box<dyn Intervention>> {
+        let mut layers: Vec<Box<dyn Intervention>> = Vec::new();
+        if !self.incidence_rate_changes.is_empty() {
+            let schedule =
+                RateSchedule::new(self.incidence_rate, self.incidence_rate_changes.clone());
+            layers.push(Box::new(ScheduleParameter::new("incidence_rate", schedule)));
+        }
+        if !self.importation.breakpoints.is_empty() {
+            layers.push(Box::new(ScheduleParameter::new(
+                "importation",
+                self.importation.clone(),
+            )));
+        }
+        if let Some(seasonality) = self.seasonality {
+            layers.push(Box::new(SeasonalForcing::new(
+                "incidence_rate",
+                seasonality,
+            )));
+        }
+        return layers;
+    }
+}
+
+impl System for dismod::Model {
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "c".to_string()];
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.c_init, self.c_init];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("iota".to_string(), self.iota),
+            ("rho".to_string(), self.rho),
+            ("chi".to_string(), self.chi),
+            ("omega".to_string(), self.omega),
+        ]);
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let (iota, rho) = (parameters["iota"], parameters["rho"]);
+        let (chi, omega) = (parameters["chi"], parameters["omega"]);
+        return vec![
+            -((iota + omega) * y[0]) + (rho * y[1]),
+            (iota * y[0]) - ((rho + chi + omega) * y[1]),
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return parameters["iota"] * y[0];
+    }
+}
+
+/// State variables are S, then each latent stage `e_<j>` and infectious
+/// stage `i_<j>` counting from 1, then R.
+impl System for erlang::Model {
+    fn state_names(&self) -> Vec<String> {
+        let mut names = vec!["s".to_string()];
+        names.extend((1..=self.latent_stages).map(|j| format!("e_{}", j)));
+        names.extend((1..=self.infectious_stages).map(|j| format!("i_{}", j)));
+        names.push("r".to_string());
+        return names;
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let mut y = vec![0.0; self.state_names().len()];
+        y[0] = 1.0 - self.i_popf_init;
+        y[1 + self.latent_stages] = self.i_popf_init;
+        return y;
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("latent_rate".to_string(), self.latent_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        return self.derivatives_at(
+            y,
+            parameters["incidence_rate"],
+            parameters["latent_rate"],
+            parameters["removal_rate"],
+        );
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let ke = self.latent_stages;
+        let infectious: f64 = y[1 + ke..1 + ke + self.infectious_stages].iter().sum();
+        return parameters["incidence_rate"] * y[0] * infectious;
+    }
+}
+
+/// Capacities only mark events on the model, so are not parameters.
+impl System for hospital::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i", "h", "u", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.i_popf_init, self.i_popf_init, 0.0, 0.0, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            (
+                "hospitalized_fraction".to_string(),
+                self.hospitalized_fraction,
+            ),
+            ("icu_fraction".to_string(), self.icu_fraction),
+            ("hospital_stay".to_string(), self.hospital_stay),
+            ("icu_stay".to_string(), self.icu_stay),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        let hospitalized = parameters["hospitalized_fraction"] * removal;
+        let discharge = y[2] / parameters["hospital_stay"];
+        let admission = parameters["icu_fraction"] * discharge;
+        let icu_discharge = y[3] / parameters["icu_stay"];
+        return vec![
+            -infection,
+            infection - removal,
+            hospitalized - discharge,
+            admission - icu_discharge,
+            (removal - hospitalized) + (discharge - admission) + icu_discharge,
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return parameters["incidence_rate"] * y[0] * y[1];
+    }
+}
+
+/// State variables are `s_<g>`, `v_<g>`, `i_<g>` and `r_<g>` for each age
+/// group `g`, counting from 0, one compartment after another. Contacts are
+/// those in effect at each stage time. Vaccination coverage on the model is
+/// not applied.
+impl System for age::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "v", "i", "r"]
+            .iter()
+            .flat_map(|name| (0..self.n_groups).map(move |g| format!("{}_{}", name, g)))
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let n = self.n_groups;
+        let mut y = vec![0.0; 4 * n];
+        for g in 0..n {
+            y[g] = self.population[(g, 0)] * (1.0 - self.i_init);
+            y[(2 * n) + g] = self.population[(g, 0)] * self.i_init;
+        }
+        return y;
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("vaccine_efficacy".to_string(), self.vaccine_efficacy),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let n = self.n_groups;
+        let contacts = self.contact_matrix_at(t);
+        let foi =
+            self.force_of_infection(&y[2 * n..3 * n], &contacts, parameters["incidence_rate"]);
+        let leak = 1.0 - parameters["vaccine_efficacy"];
+        let removal_rate = parameters["removal_rate"];
+        let mut d = vec![0.0; 4 * n];
+        for g in 0..n {
+            let (s, v, i) = (y[g], y[n + g], y[(2 * n) + g]);
+            d[g] = -foi[g] * s;
+            d[n + g] = -leak * foi[g] * v;
+            d[(2 * n) + g] = (foi[g] * (s + (leak * v))) - (removal_rate * i);
+            d[(3 * n) + g] = removal_rate * i;
+        }
+        return d;
+    }
+
+    fn incidence(&self, t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let n = self.n_groups;
+        let contacts = self.contact_matrix_at(t);
+        let foi =
+            self.force_of_infection(&y[2 * n..3 * n], &contacts, parameters["incidence_rate"]);
+        let leak = 1.0 - parameters["vaccine_efficacy"];
+        return (0..n).map(|g| foi[g] * (y[g] + (leak * y[n + g]))).sum();
+    }
+}
+
+/// State variables are S, A (carriers), I and R.
+impl System for carrier::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "a", "i", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let s_init = 1.0 - self.i_popf_init - self.a_popf_init;
+        return vec![s_init, self.a_popf_init, self.i_popf_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("carrier_exit_rate".to_string(), self.carrier_exit_rate),
+            (
+                "relative_infectiousness".to_string(),
+                self.relative_infectiousness,
+            ),
+            (
+                "symptomatic_fraction".to_string(),
+                self.symptomatic_fraction,
+            ),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let exit = parameters["carrier_exit_rate"] * y[1];
+        let progression = parameters["symptomatic_fraction"] * exit;
+        let removal = parameters["removal_rate"] * y[2];
+        return vec![
+            -infection,
+            infection - exit,
+            progression - removal,
+            (exit - progression) + removal,
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let infectious = y[2] + (parameters["relative_infectiousness"] * y[1]);
+        return parameters["incidence_rate"] * y[0] * infectious;
+    }
+}
+
+/// State variables are M, S, I and R.
+impl System for msir::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["m", "s", "i", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let s_init = 1.0 - self.i_popf_init - self.r_popf_init;
+        return vec![0.0, s_init, self.i_popf_init, self.r_popf_init];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("birth_rate".to_string(), self.birth_rate),
+            (
+                "maternal_waning_rate".to_string(),
+                self.maternal_waning_rate,
+            ),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let mu = parameters["birth_rate"];
+        let protected_births = mu * y[3];
+        let susceptible_births = mu * (y[0] + y[1] + y[2]);
+        let waning = parameters["maternal_waning_rate"] * y[0];
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[2];
+        return vec![
+            protected_births - waning - (mu * y[0]),
+            susceptible_births + waning - infection - (mu * y[1]),
+            infection - removal - (mu * y[2]),
+            removal - (mu * y[3]),
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return parameters["incidence_rate"] * y[1] * y[2];
+    }
+}
+
+/// State variables are S_naive, S_waned, I and R. Incidence counts first
+/// infections and reinfections together.
+impl System for sirs::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s_naive", "s_waned", "i", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.i_popf_init, 0.0, self.i_popf_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("waning_rate".to_string(), self.waning_rate),
+            (
+                "reinfection_susceptibility".to_string(),
+                self.reinfection_susceptibility,
+            ),
+        ]);
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let first = parameters["incidence_rate"] * y[0] * y[2];
+        let reinfection =
+            parameters["reinfection_susceptibility"] * parameters["incidence_rate"] * y[1] * y[2];
+        let removal = parameters["removal_rate"] * y[2];
+        let waning = parameters["waning_rate"] * y[3];
+        return vec![
+            -first,
+            waning - reinfection,
+            first + reinfection - removal,
+            removal - waning,
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let susceptible = y[0] + (parameters["reinfection_susceptibility"] * y[1]);
+        return parameters["incidence_rate"] * susceptible * y[2];
+    }
+}
+
+/// State variables are S, I, R and the reservoir level W.
+impl System for waterborne::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i", "r", "w"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.i_popf_init, self.i_popf_init, 0.0, self.w_init];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("ingestion_rate".to_string(), self.ingestion_rate),
+            ("shedding_rate".to_string(), self.shedding_rate),
+            ("decay_rate".to_string(), self.decay_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        return vec![
+            -infection,
+            infection - removal,
+            removal,
+            (parameters["shedding_rate"] * y[1]) - (parameters["decay_rate"] * y[3]),
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let contact = parameters["incidence_rate"] * y[1];
+        let ingestion = parameters["ingestion_rate"] * y[3];
+        return (contact + ingestion) * y[0];
+    }
+}
+
+/// State variables are S, I_sensitive, I_resistant and R. Incidence counts
+/// new infections with either strain.
+impl System for resistance::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i_sensitive", "i_resistant", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let s_init = 1.0 - self.i_sensitive_init - self.i_resistant_init;
+        return vec![s_init, self.i_sensitive_init, self.i_resistant_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("fitness_cost".to_string(), self.fitness_cost),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("recovery_rate".to_string(), self.recovery_rate),
+            ("treatment_coverage".to_string(), self.treatment_coverage),
+            ("treatment_rate".to_string(), self.treatment_rate),
+            (
+                "resistance_probability".to_string(),
+                self.resistance_probability,
+            ),
+        ]);
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let beta = parameters["incidence_rate"];
+        let sensitive = beta * y[0] * y[1];
+        let resistant = beta * (1.0 - parameters["fitness_cost"]) * y[0] * y[2];
+        let treated = parameters["treatment_coverage"] * parameters["treatment_rate"] * y[1];
+        let acquired = parameters["resistance_probability"] * treated;
+        let (removal_rate, recovery_rate) =
+            (parameters["removal_rate"], parameters["recovery_rate"]);
+        let cleared = (recovery_rate * (y[1] + y[2])) + (treated - acquired);
+        let removal = removal_rate * (y[1] + y[2]);
+        return vec![
+            cleared - sensitive - resistant,
+            sensitive - ((removal_rate + recovery_rate) * y[1]) - treated,
+            resistant + acquired - ((removal_rate + recovery_rate) * y[2]),
+            removal,
+        ];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let infectious = y[1] + ((1.0 - parameters["fitness_cost"]) * y[2]);
+        return parameters["incidence_rate"] * y[0] * infectious;
+    }
+}
+
+/// State variables are `s_<g>` and `i_<g>` for each group `g`, counting
+/// from 0, one compartment after another. A transmission matrix set by
+/// [`riskgroup::Model::transmission`] is used in place of `incidence_rate`.
+impl System for riskgroup::Model {
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i"]
+            .iter()
+            .flat_map(|name| (0..self.n_groups).map(move |g| format!("{}_{}", name, g)))
+            .collect();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let n = self.n_groups;
+        let mut y = vec![0.0; 2 * n];
+        for g in 0..n {
+            y[g] = self.population[(g, 0)] * (1.0 - self.i_init);
+            y[n + g] = self.population[(g, 0)] * self.i_init;
+        }
+        return y;
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("recovery_rate".to_string(), self.recovery_rate),
+        ]);
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let n = self.n_groups;
+        let infection = self.group_infection(y, parameters["incidence_rate"]);
+        let recovery_rate = parameters["recovery_rate"];
+        let mut d = vec![0.0; 2 * n];
+        for g in 0..n {
+            let recovery = recovery_rate * y[n + g];
+            d[g] = recovery - infection[g];
+            d[n + g] = infection[g] - recovery;
+        }
+        return d;
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return self
+            .group_infection(y, parameters["incidence_rate"])
+            .iter()
+            .sum();
+    }
+}
+
+/// State variables are S, then `i_<k>` and then `r_<k>` for each strain
+/// `k`, counting from 0. Each strain has its own `incidence_rate_<k>` and
+/// `removal_rate_<k>`. Introductions on the model move population between
+/// compartments at an instant rather than modify parameters, so are not
+/// applied.
+impl System for multistrain::Model {
+    fn state_names(&self) -> Vec<String> {
+        let mut names = vec!["s".to_string()];
+        for name in ["i", "r"] {
+            names.extend((0..self.n_strains).map(|k| format!("{}_{}", name, k)));
+        }
+        return names;
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let n = self.n_strains;
+        let mut y = vec![0.0; 1 + (2 * n)];
+        y[0] = 1.0;
+        for k in 0..n {
+            y[0] -= self.i_popf_init[(k, 0)];
+            y[1 + k] = self.i_popf_init[(k, 0)];
+        }
+        return y;
+    }
+
+    fn parameters(&self) -> Parameters {
+        let mut parameters = Parameters::new();
+        for k in 0..self.n_strains {
+            parameters.insert(format!("incidence_rate_{}", k), self.incidence_rate[(k, 0)]);
+            parameters.insert(format!("removal_rate_{}", k), self.removal_rate[(k, 0)]);
+        }
+        return parameters;
+    }
+
+    fn derivatives(&self, _t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let n = self.n_strains;
+        let mut d = vec![0.0; 1 + (2 * n)];
+        for k in 0..n {
+            let force = parameters[&format!("incidence_rate_{}", k)] * y[1 + k];
+            let removal = parameters[&format!("removal_rate_{}", k)] * y[1 + k];
+            d[0] -= force * y[0];
+            d[1 + k] += (force * y[0]) - removal;
+            d[1 + n + k] += removal;
+            for j in 0..n {
+                let reinfection = force * (1.0 - self.cross_immunity[(j, k)]) * y[1 + n + j];
+                d[1 + k] += reinfection;
+                d[1 + n + j] -= reinfection;
+            }
+        }
+        return d;
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let n = self.n_strains;
+        return (0..n)
+            .map(|k| {
+                let force = parameters[&format!("incidence_rate_{}", k)] * y[1 + k];
+                let reinfectable: f64 = (0..n)
+                    .map(|j| (1.0 - self.cross_immunity[(j, k)]) * y[1 + n + j])
+                    .sum();
+                force * (y[0] + reinfectable)
+            })
+            .sum();
+    }
+}
+
+/// State variables are S, I and R. The force of infection reads the
+/// infectious fraction `delay` earlier, `i_history` before t = 0, so the
+/// pipeline's step must be no longer than the delay. Without a history, as
+/// from [`System::derivatives`], it reads `i_history`.
+impl System for delay::Model {
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "i".to_string(), "r".to_string()];
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.i_popf_init, self.i_popf_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        return self.delayed_derivatives(t, y, None, parameters);
+    }
+
+    fn incidence(&self, t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return self.delayed_incidence(t, y, None, parameters);
+    }
+
+    fn delay(&self) -> f64 {
+        return self.delay;
+    }
+
+    fn delayed_derivatives(
+        &self,
+        t: f64,
+        y: &[f64],
+        lagged: Option<&[f64]>,
+        parameters: &Parameters,
+    ) -> Vec<f64> {
+        let infection = self.delayed_incidence(t, y, lagged, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        return vec![-infection, infection - removal, removal];
+    }
+
+    fn delayed_incidence(
+        &self,
+        _t: f64,
+        y: &[f64],
+        lagged: Option<&[f64]>,
+        parameters: &Parameters,
+    ) -> f64 {
+        let i_delayed = lagged.map_or(self.i_history, |past| past[1]);
+        return parameters["incidence_rate"] * y[0] * i_delayed;
+    }
+}
+
+/// State variables are S, I and R. The pipeline solves the drift of the
+/// equations, without demographic noise, so gives their deterministic
+/// limit rather than a replicate.
+impl System for sde::Model {
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "i".to_string(), "r".to_string()];
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return vec![1.0 - self.i_popf_init, self.i_popf_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        return vec![-infection, infection - removal, removal];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return parameters["incidence_rate"] * y[0] * y[1];
+    }
+}
+
+/// State variables are the counts S, I and R. The pipeline solves the mean
+/// field equations of the process, its expected trajectory in a large
+/// population, rather than simulating events. Importation breakpoints on
+/// the model become a [`ScheduleParameter`] on `importation`.
+impl System for ssa::Model {
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "i".to_string(), "r".to_string()];
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        let (population, i_init) = (self.population as f64, self.i_init as f64);
+        return vec![population - i_init, i_init, 0.0];
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("incidence_rate".to_string(), self.incidence_rate),
+            ("removal_rate".to_string(), self.removal_rate),
+            ("importation".to_string(), self.importation.initial),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let infection = self.incidence(t, y, parameters);
+        let removal = parameters["removal_rate"] * y[1];
+        return vec![-infection, infection - removal, removal];
+    }
+
+    fn incidence(&self, _t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        let beta = parameters["incidence_rate"] / (self.population as f64);
+        return (beta * y[0] * y[1]) + (parameters["importation"] * y[0]);
+    }
+
+    fn interventions(&self) -> Vec<Box<dyn Intervention>> {
+        if self.importation.breakpoints.is_empty() {
+            return Vec::new();
+        }
+        return vec![Box::new(ScheduleParameter::new(
+            "importation",
+            self.importation.clone(),
+        ))];
+    }
+}
+
+/// Dynamics, interventions and observation composed into one model.
+pub struct Pipeline {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// The dynamics.
+    pub system: Box<dyn System>,
+    /// The system's own parameter modifiers, from
+    /// [`System::interventions`], applied in order before `interventions`.
+    pub system_interventions: Vec<Box<dyn Intervention>>,
+    /// Parameter modifiers, applied in order.
+    pub interventions: Vec<Box<dyn Intervention>>,
+    /// Maps incidence per unit time to reported cases, if set.
+    pub observation: Option<ReportingModel>,
+    /// Each state variable (column) at each index (row).
+    pub state: Mat<f64>,
+    /// New cases over the step ending at each index, zero at index 0.
+    pub incidence: Mat<f64>,
+}
+
+impl Pipeline {
+    /// Create a pipeline around `system`, with only the system's own
+    /// interventions and no observation model.
+    pub fn new(system: Box<dyn System>) -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            system_interventions: system.interventions(),
+            system,
+            interventions: Vec::new(),
+            observation: None,
+            state: Mat::new(),
+            incidence: Mat::new(),
+        };
+    }
+
+    /// Configure the series length and integration step. A system with a
+    /// delay needs a step no longer than the delay.
+    pub fn configure(&mut self, length: usize, step_size: f64) -> &mut Self {
+        let delay = self.system.delay();
+        assert!(
+            (delay == 0.0) | (delay >= step_size),
+            "step_size must be at most the system's delay, got {} > {}",
+            step_size,
+            delay
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.state = Mat::zeros(n_steps, self.system.state_names().len());
+        self.incidence = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Times of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Add an intervention layer.
+    pub fn intervention(&mut self, intervention: Box<dyn Intervention>) -> &mut Self {
+        self.interventions.push(intervention);
+        return self;
+    }
+
+    /// Set the observation layer.
+    pub fn observation(&mut self, observation: ReportingModel) -> &mut Self {
+        self.observation = Some(observation);
+        return self;
+    }
+
+    /// Parameters in effect at time `t`, after every intervention.
+    pub fn parameters_at(&self, t: f64) -> Parameters {
+        let mut parameters = self.system.parameters();
+        let layers = self.system_interventions.iter();
+        for intervention in layers.chain(self.interventions.iter()) {
+            intervention.apply(t, &mut parameters);
+        }
+        return parameters;
+    }
+
+    /// Derivatives of the state, extended with incidence as a final
+    /// variable. `history` holds each state variable's past, read when the
+    /// system has a delay.
+    fn derivatives(&self, t: f64, y: &[f64], history: &[History]) -> Vec<f64> {
+        let parameters = self.parameters_at(t);
+        let n = y.len() - 1;
+        let lag = t - self.system.delay();
+        let lagged: Option<Vec<f64>> = if (self.system.delay() > 0.0) & (lag >= 0.0) {
+            Some(history.iter().map(|past| past.at(lag)).collect())
+        } else {
+            None
+        };
+        let lagged = lagged.as_deref();
+        let mut d = self
+            .system
+            .delayed_derivatives(t, &y[..n], lagged, &parameters);
+        d.push(
+            self.system
+                .delayed_incidence(t, &y[..n], lagged, &parameters),
+        );
+        return d;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method, with parameters
+    /// evaluated at each stage time.
+    pub fn run_rk4(&mut self) -> &Pipeline {
+        self.solve_rk4(None)
+            .expect("solving without a sink cannot fail");
+        return self;
+    }
+
+    /// Solve the system as [`Pipeline::run_rk4`] does, also writing the run
+    /// metadata and then every step's state and incidence to `sink` as it
+    /// is computed.
+    pub fn run_rk4_into(&mut self, sink: &mut dyn OutputSink) -> io::Result<&Pipeline> {
+        sink.metadata(&self.metadata())?;
+        self.solve_rk4(Some(sink))?;
+        return Ok(self);
+    }
+
+    /// Configured parameters of the system and its initial state, as
+    /// `<name>_init`, before any intervention.
+    fn run_parameters(&self) -> Parameters {
+        let mut parameters = self.system.parameters();
+        let names = self.system.state_names();
+        for (name, value) in names.iter().zip(self.system.initial_state()) {
+            parameters.insert(format!("{}_init", name), value);
+        }
+        parameters.insert("length".to_string(), self.length as f64);
+        return parameters;
+    }
+
+    /// Metadata of an RK4 run of the pipeline as configured.
+    pub fn metadata(&self) -> RunMetadata {
+        return RunMetadata::new("rk4", self.step_size, &self.run_parameters());
+    }
+
+    /// The solved state and incidence with the run metadata.
+    pub fn result(&self) -> SimulationResult {
+        let mut names = self.system.state_names();
+        names.push("incidence".to_string());
+        let n_vars = self.state.ncols();
+        let values = Mat::from_fn(self.state.nrows(), n_vars + 1, |t, j| {
+            if j < n_vars {
+                return self.state[(t, j)];
+            }
+            return self.incidence[(t, 0)];
+        });
+        return SimulationResult {
+            metadata: self.metadata(),
+            names,
+            times: self.grid().times(),
+            values,
+        };
+    }
+
+    /// Write index `t` of the state and incidence to `sink`.
+    fn write_step(&self, t: usize, sink: &mut Option<&mut dyn OutputSink>) -> io::Result<()> {
+        let Some(sink) = sink else {
+            return Ok(());
+        };
+        let mut values: Vec<f64> = (0..self.state.ncols())
+            .map(|j| self.state[(t, j)])
+            .collect();
+        values.push(self.incidence[(t, 0)]);
+        return sink.write_step(self.grid().time(t), &values);
+    }
+
+    fn solve_rk4(&mut self, mut sink: Option<&mut dyn OutputSink>) -> io::Result<()> {
+        if let Some(sink) = sink.as_mut() {
+            let mut names = self.system.state_names();
+            names.push("incidence".to_string());
+            sink.start(&names)?;
+        }
+        let h = self.step_size;
+        let grid = self.grid();
+        let mut y = self.system.initial_state();
+        y.push(0.0);
+        let n_vars = y.len() - 1;
+        let delay = self.system.delay();
+        let mut history: Vec<History> = (0..n_vars).map(|_| History::new(0.0, delay + h)).collect();
+        for j in 0..n_vars {
+            self.state[(0, j)] = y[j];
+        }
+        self.incidence[(0, 0)] = 0.0;
+        self.write_step(0, &mut sink)?;
+        for t in 0..grid.n_steps - 1 {
+            let time = grid.time(t);
+            y[n_vars] = 0.0;
+            let k1 = self.derivatives(time, &y, &history);
+            if delay > 0.0 {
+                for (j, past) in history.iter_mut().enumerate() {
+                    past.push(time, y[j], k1[j]);
+                }
+            }
+            let y2: Vec<f64> = (0..y.len()).map(|j| y[j] + (h / 2.0 * k1[j])).collect();
+            let k2 = self.derivatives(time + (h / 2.0), &y2, &history);
+            let y3: Vec<f64> = (0..y.len()).map(|j| y[j] + (h / 2.0 * k2[j])).collect();
+            let k3 = self.derivatives(time + (h / 2.0), &y3, &history);
+            let y4: Vec<f64> = (0..y.len()).map(|j| y[j] + (h * k3[j])).collect();
+            let k4 = self.derivatives(time + h, &y4, &history);
+            for j in 0..y.len() {
+                y[j] += (k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0);
+            }
+            for j in 0..n_vars {
+                self.state[(t + 1, j)] = y[j];
+            }
+            self.incidence[(t + 1, 0)] = y[n_vars];
+            self.write_step(t + 1, &mut sink)?;
+        }
+        if let Some(sink) = sink.as_mut() {
+            sink.finish()?;
+        }
+        return Ok(());
+    }
+
+    /// New cases over each unit time, from t to t + 1.
+    pub fn incidence_per_unit_time(&self) -> Mat<f64> {
+        let per_unit = (1.0 / self.step_size).round() as usize;
+        let n = (self.incidence.nrows() - 1) / per_unit;
+        return Mat::from_fn(n, 1, |t, _| {
+            (1..=per_unit)
+                .map(|k| self.incidence[((t * per_unit) + k, 0)])
+                .sum()
+        });
+    }
+
+    /// Expected reported cases over each unit time under the observation
+    /// layer, or the true incidence without one.
+    pub fn expected_reports(&self) -> Mat<f64> {
+        let incidence = self.incidence_per_unit_time();
+        return match &self.observation {
+            Some(observation) => observation.expected(&incidence),
+            None => incidence,
+        };
+    }
+
+    /// Reported cases over each unit time drawn from the observation layer,
+    /// or the true incidence without one.
+    pub fn sample_reports(&self) -> Mat<f64> {
+        let incidence = self.incidence_per_unit_time();
+        return match &self.observation {
+            Some(observation) => observation.sample(&incidence),
+            None => incidence,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::pipeline::{Pipeline, ScaleParameter, ScheduleParameter};
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::sink::{Downsample, MemorySink};
+    use crate::sirrs::units::{Duration, Fraction, Rate, TimeUnit};
+    use crate::sirrs::{
+        age, carrier, delay, dismod, erlang, hospital, msir, multistrain, resistance, riskgroup,
+        sde, sir, sirs, ssa, waterborne,
+    };
+    use faer::{Mat, mat};
+
+    /// Check every column of the pipeline's state against the matching
+    /// series, each a column of one of `series` in turn.
+    fn assert_state_matches(name: &str, pipeline: &Pipeline, series: &[&Mat<f64>], tol: f64) {
+        let columns: Vec<(&Mat<f64>, usize)> = series
+            .iter()
+            .flat_map(|m| (0..m.ncols()).map(move |j| (*m, j)))
+            .collect();
+        assert_eq!(
+            columns.len(),
+            pipeline.state.ncols(),
+            "Bad number of {} state variables",
+            name
+        );
+        for (j, (m, k)) in columns.iter().enumerate() {
+            for t in 0..m.nrows() {
+                assert!(
+                    (pipeline.state[(t, j)] - m[(t, *k)]).abs() < tol,
+                    "Bad {} {} at index {}, expected {} got {}",
+                    name,
+                    pipeline.system.state_names()[j],
+                    t,
+                    m[(t, *k)],
+                    pipeline.state[(t, j)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sir_layers_match_model() {
+        let build = || {
+            let mut model = sir::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.02),
+            );
+            model
+                .changepoints(vec![(15.0, Rate::per_day(0.2)), (30.0, Rate::per_day(0.5))])
+                .seasonality(0.3, 0.5, 20.0)
+                .importation(RateSchedule::new(
+                    Rate::per_day(0.001),
+                    vec![(10.0, Rate::per_day(0.0))],
+                ));
+            return model;
+        };
+        let mut model = build();
+        model.init_popf();
+        model.run_rk4();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        pipeline.configure(60, 0.5);
+        assert_eq!(
+            pipeline.system_interventions.len(),
+            3,
+            "Expected a layer each for changepoints, importation and seasonality"
+        );
+        pipeline.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (pipeline.state[(t, 1)] - model.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                model.i_popf[(t, 0)],
+                pipeline.state[(t, 1)]
+            );
+        }
+        // Interventions added to the pipeline apply on top of the system's.
+        pipeline.intervention(Box::new(ScaleParameter::new(
+            "incidence_rate",
+            0.0,
+            60.0,
+            0.5,
+        )));
+        let expected = 0.5 * model.incidence_rate_at(40.0);
+        assert!(
+            (pipeline.parameters_at(40.0)["incidence_rate"] - expected).abs() < 1e-15,
+            "Bad incidence_rate, expected {} got {}",
+            expected,
+            pipeline.parameters_at(40.0)["incidence_rate"]
+        );
+    }
+
+    #[test]
+    fn test_compartment_pipelines_match_models() {
+        let build = || {
+            let mut model = carrier::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                0.005,
+                Rate::per_day(0.5),
+                Rate::per_day(0.2),
+                Rate::per_day(0.3),
+                Fraction::of(0.5),
+                Fraction::of(0.6),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [&model.s_popf, &model.a_popf, &model.i_popf, &model.r_popf];
+        assert_state_matches("carrier", &pipeline, &series, 1e-12);
+
+        let build = || {
+            let mut model = msir::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                0.3,
+                Rate::per_day(0.5),
+                Rate::per_day(0.2),
+                Rate::per_day(0.01),
+                Rate::per_day(0.05),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [&model.m_popf, &model.s_popf, &model.i_popf, &model.r_popf];
+        assert_state_matches("msir", &pipeline, &series, 1e-12);
+
+        let build = || {
+            let mut model = sirs::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                Rate::per_day(0.5),
+                Rate::per_day(0.2),
+                Rate::per_day(0.05),
+                Fraction::of(0.5),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [
+            &model.s_naive_popf,
+            &model.s_waned_popf,
+            &model.i_popf,
+            &model.r_popf,
+        ];
+        assert_state_matches("sirs", &pipeline, &series, 1e-12);
+        let last = model.i_popf.nrows() - 1;
+        let infections =
+            model.cumulative_first_infections[(last, 0)] + model.cumulative_reinfections[(last, 0)];
+        let cumulative: f64 = (0..=last).map(|t| pipeline.incidence[(t, 0)]).sum();
+        assert!(
+            (cumulative - infections).abs() < 1e-9,
+            "Bad sirs cumulative incidence, expected {} got {}",
+            infections,
+            cumulative
+        );
+
+        let build = || {
+            let mut model = waterborne::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                0.1,
+                Rate::per_day(0.2),
+                Rate::per_day(0.3),
+                Rate::per_day(0.1),
+                Rate::per_day(0.2),
+                Rate::per_day(0.1),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [&model.s_popf, &model.i_popf, &model.r_popf, &model.w_level];
+        assert_state_matches("waterborne", &pipeline, &series, 1e-12);
+
+        let build = || {
+            let mut model = resistance::Model::new();
+            model.configure(
+                60,
+                0.5,
+                0.01,
+                0.001,
+                Rate::per_day(0.5),
+                Fraction::of(0.1),
+                Rate::per_day(0.1),
+                Rate::per_day(0.05),
+            );
+            model.treatment(0.5, 0.2, 0.1);
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [
+            &model.s_popf,
+            &model.i_sensitive_popf,
+            &model.i_resistant_popf,
+            &model.r_popf,
+        ];
+        assert_state_matches("resistance", &pipeline, &series, 1e-12);
+
+        let build = || {
+            let mut model = riskgroup::Model::new();
+            model.configure(
+                60,
+                0.5,
+                mat![[0.2], [0.8]],
+                mat![[5.0], [1.0]],
+                Fraction::of(0.5),
+                0.01,
+                Fraction::of(0.1),
+                Rate::per_day(0.2),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        assert_state_matches(
+            "riskgroup",
+            &pipeline,
+            &[&model.s_popf, &model.i_popf],
+            1e-12,
+        );
+
+        let build = || {
+            let mut model = multistrain::Model::new();
+            model.configure(
+                60,
+                0.5,
+                mat![[0.01], [0.001]],
+                vec![Rate::per_day(0.4), Rate::per_day(0.6)],
+                vec![Rate::per_day(0.1), Rate::per_day(0.2)],
+                mat![[1.0, 0.5], [0.3, 1.0]],
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(60, 0.5).run_rk4();
+        assert_eq!(
+            pipeline.system.state_names(),
+            vec!["s", "i_0", "i_1", "r_0", "r_1"],
+            "Bad state names"
+        );
+        assert_state_matches(
+            "multistrain",
+            &pipeline,
+            &[&model.s_popf, &model.i_popf, &model.r_popf],
+            1e-12,
+        );
+    }
+
+    #[test]
+    fn test_delay_pipeline_matches_model() {
+        let build = || {
+            let mut model = delay::Model::new();
+            model.configure(
+                80,
+                0.5,
+                0.01,
+                Rate::per_day(0.5),
+                Rate::per_day(0.2),
+                Duration::days(3.0),
+            );
+            return model;
+        };
+        let mut model = build();
+        let mut pipeline = Pipeline::new(Box::new(build()));
+        model.init_popf();
+        model.run_rk4();
+        pipeline.configure(80, 0.5).run_rk4();
+        let series = [&model.s_popf, &model.i_popf, &model.r_popf];
+        assert_state_matches("delay", &pipeline, &series, 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "step_size must be at most the system's delay")]
+    fn test_delay_pipeline_rejects_long_steps() {
+        let mut model = delay::Model::new();
+        model.configure(
+            80,
+            0.5,
+            0.01,
+            Rate::per_day(0.5),
+            Rate::per_day(0.2),
+            Duration::days(1.0),
+        );
+        Pipeline::new(Box::new(model)).configure(80, 2.0);
+    }
+
+    #[test]
+    fn test_stochastic_pipelines_match_sir() {
+        let mut reference = sir::Model::new();
+        reference.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        let mut model = sde::Model::new();
+        model.configure(
+            60,
+            0.5,
+            1000.0,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            1,
+            1,
+        );
+        let mut pipeline = Pipeline::new(Box::new(model));
+        pipeline.configure(60, 0.5).run_rk4();
+        let series = [&reference.s_popf, &reference.i_popf, &reference.r_popf];
+        assert_state_matches("sde", &pipeline, &series, 1e-12);
+        let mut model = ssa::Model::new();
+        model.configure(60, 0.5, 1000, 10, Rate::per_day(0.4), Rate::per_day(0.1), 1);
+        let mut pipeline = Pipeline::new(Box::new(model));
+        pipeline.configure(60, 0.5).run_rk4();
+        for t in 0..reference.i_popf.nrows() {
+            let expected = 1000.0 * reference.i_popf[(t, 0)];
+            assert!(
+                (pipeline.state[(t, 1)] - expected).abs() < 1e-9,
+                "Bad ssa i at index {}, expected {} got {}",
+                t,
+                expected,
+                pipeline.state[(t, 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sir_pipeline_matches_model() {
+        let mut model = sir::Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut system = sir::Model::new();
+        system.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(50, 0.5);
+        pipeline.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (pipeline.state[(t, 1)] - model.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                model.i_popf[(t, 0)],
+                pipeline.state[(t, 1)]
+            );
+            assert!(
+                (pipeline.incidence[(t, 0)] - model.incidence[(t, 0)]).abs() < 1e-12,
+                "Bad incidence at index {}, expected {} got {}",
+                t,
+                model.incidence[(t, 0)],
+                pipeline.incidence[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_erlang_pipeline_matches_model() {
+        let mut model = erlang::Model::new();
+        model.configure(
+            60,
+            0.25,
+            0.01,
+            Rate::per_day(0.5),
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+            2,
+            3,
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut system = erlang::Model::new();
+        system.configure(
+            60,
+            0.25,
+            0.01,
+            Rate::per_day(0.5),
+            Rate::per_day(0.3),
+            Rate::per_day(0.2),
+            2,
+            3,
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(60, 0.25);
+        pipeline.run_rk4();
+        let names = pipeline.system.state_names();
+        assert_eq!(
+            names,
+            vec!["s", "e_1", "e_2", "i_1", "i_2", "i_3", "r"],
+            "Bad state names"
+        );
+        for t in 0..model.i_popf.nrows() {
+            let i: f64 = (3..6).map(|j| pipeline.state[(t, j)]).sum();
+            assert!(
+                (i - model.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                model.i_popf[(t, 0)],
+                i
+            );
+        }
+        let last = model.s_popf.nrows() - 1;
+        let infected = model.s_popf[(0, 0)] - model.s_popf[(last, 0)];
+        let cumulative: f64 = (0..=last).map(|t| pipeline.incidence[(t, 0)]).sum();
+        assert!(
+            (cumulative - infected).abs() < 1e-9,
+            "Bad cumulative incidence, expected {} got {}",
+            infected,
+            cumulative
+        );
+    }
+
+    #[test]
+    fn test_hospital_pipeline_matches_model() {
+        let mut model = hospital::Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut system = hospital::Model::new();
+        system.configure(
+            100,
+            0.5,
+            0.01,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.05),
+            Fraction::of(0.2),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(100, 0.5);
+        let mut sink = MemorySink::new();
+        pipeline.run_rk4_into(&mut sink).unwrap();
+        let written = sink.to_mat();
+        let columns = [
+            &model.s_popf,
+            &model.i_popf,
+            &model.h_popf,
+            &model.u_popf,
+            &model.r_popf,
+        ];
+        for t in 0..model.s_popf.nrows() {
+            for (j, column) in columns.iter().enumerate() {
+                assert!(
+                    (written[(t, j)] - column[(t, 0)]).abs() < 1e-12,
+                    "Bad {} at index {}, expected {} got {}",
+                    sink.names[j],
+                    t,
+                    column[(t, 0)],
+                    written[(t, j)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_age_pipeline_matches_model() {
+        let mut model = age::Model::new();
+        model.configure(
+            50,
+            0.5,
+            mat![[0.6], [0.4]],
+            mat![[8.0, 2.0], [3.0, 5.0]],
+            0.01,
+            Fraction::of(0.05),
+            Rate::per_day(0.2),
+            Fraction::of(0.9),
+        );
+        let mut system = age::Model::new();
+        system.configure(
+            50,
+            0.5,
+            mat![[0.6], [0.4]],
+            mat![[8.0, 2.0], [3.0, 5.0]],
+            0.01,
+            Fraction::of(0.05),
+            Rate::per_day(0.2),
+            Fraction::of(0.9),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(50, 0.5);
+        pipeline.run_rk4();
+        assert_eq!(
+            pipeline.system.state_names(),
+            vec!["s_0", "s_1", "v_0", "v_1", "i_0", "i_1", "r_0", "r_1"],
+            "Bad state names"
+        );
+        for t in 0..model.i_popf.nrows() {
+            for g in 0..2 {
+                assert!(
+                    (pipeline.state[(t, 4 + g)] - model.i_popf[(t, g)]).abs() < 1e-12,
+                    "Bad i_{} at index {}, expected {} got {}",
+                    g,
+                    t,
+                    model.i_popf[(t, g)],
+                    pipeline.state[(t, 4 + g)]
+                );
+            }
+        }
+        let last = model.s_popf.nrows() - 1;
+        let infected: f64 = (0..2)
+            .map(|g| model.s_popf[(0, g)] - model.s_popf[(last, g)])
+            .sum();
+        let cumulative: f64 = (0..=last).map(|t| pipeline.incidence[(t, 0)]).sum();
+        assert!(
+            (cumulative - infected).abs() < 1e-9,
+            "Bad cumulative incidence, expected {} got {}",
+            infected,
+            cumulative
+        );
+    }
+
+    #[test]
+    fn test_intervention_layer_is_reusable() {
+        let lockdown = || Box::new(ScaleParameter::new("incidence_rate", 10.0, 30.0, 0.0));
+        let mut system = sir::Model::new();
+        system.configure(
+            40,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(40, 0.5);
+        pipeline.intervention(lockdown());
+        pipeline.run_rk4();
+        assert_eq!(
+            pipeline.incidence[(40, 0)],
+            0.0,
+            "Expected no new infections during lockdown, got {}",
+            pipeline.incidence[(40, 0)]
+        );
+        // The same intervention names no DisMod parameter, so has no effect.
+        let mut system = dismod::Model::new();
+        system.configure(
+            40,
+            0.5,
+            0.0,
+            Rate::per_year(0.05),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+            Rate::per_year(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(40, 0.5);
+        pipeline.intervention(lockdown());
+        pipeline.intervention(Box::new(ScaleParameter::new("iota", 0.0, 40.0, 2.0)));
+        assert_eq!(
+            pipeline.parameters_at(20.0)["iota"],
+            0.1,
+            "Bad iota during intervention, got {}",
+            pipeline.parameters_at(20.0)["iota"]
+        );
+    }
+
+    #[test]
+    fn test_observation_layer() {
+        let mut system = sir::Model::new();
+        system.configure(
+            30,
+            0.25,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(30, 0.25);
+        let mut reporting = ReportingModel::new();
+        reporting.configure(Fraction::of(0.5), vec![1.0], None, 0);
+        pipeline.observation(reporting);
+        pipeline.run_rk4();
+        let incidence = pipeline.incidence_per_unit_time();
+        let reports = pipeline.expected_reports();
+        for t in 0..incidence.nrows() {
+            assert!(
+                (reports[(t, 0)] - (0.5 * incidence[(t, 0)])).abs() < 1e-15,
+                "Bad expected reports at time {}, got {}",
+                t,
+                reports[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_rk4_into_sink() {
+        let mut system = sir::Model::new();
+        system.configure(
+            20,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(20, 0.5);
+        let mut sink = Downsample::new(MemorySink::new(), 2);
+        pipeline.run_rk4_into(&mut sink).unwrap();
+        let written = sink.inner.to_mat();
+        let metadata = sink.inner.metadata.as_ref().expect("Expected metadata");
+        assert_eq!(
+            (metadata.solver.as_str(), metadata.step_size),
+            ("rk4", 0.5),
+            "Bad metadata, got {:?}",
+            metadata
+        );
+        assert_eq!(
+            pipeline.result().values.ncols(),
+            4,
+            "Bad number of result columns"
+        );
+        assert_eq!(
+            sink.inner.names,
+            vec!["s", "i", "r", "incidence"],
+            "Bad column names, got {:?}",
+            sink.inner.names
+        );
+        assert_eq!(
+            written.nrows(),
+            20,
+            "Bad number of rows, got {}",
+            written.nrows()
+        );
+        for t in 0..20 {
+            assert_eq!(
+                written[(t, 1)],
+                pipeline.state[(2 * t, 1)],
+                "Bad written i at unit time {}",
+                t
+            );
+        }
+    }
+
+    #[test]
+    fn test_schedule_matches_sir_changepoints() {
+        let phases = RateSchedule::new(0.4, vec![(10.0, 0.1), (25.0, 0.3)]);
+        let mut model = sir::Model::new();
+        model.configure(
+            40,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.0),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.incidence_rate_schedule(phases.rates(TimeUnit::Day).unwrap());
+        model.init_popf();
+        model.run_rk4();
+        let mut system = sir::Model::new();
+        system.configure(
+            40,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(40, 0.5);
+        pipeline.intervention(Box::new(ScheduleParameter::new("incidence_rate", phases)));
+        pipeline.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (pipeline.state[(t, 1)] - model.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i at index {}, expected {} got {}",
+                t,
+                model.i_popf[(t, 0)],
+                pipeline.state[(t, 1)]
+            );
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Renewal equation model of incidence.
+//!
+//! Instead of compartments, incidence is generated from past incidence by
+//! the renewal equation in unit time steps,
+//!
+//! ```text
+//! I(t) = R(t) S(t - 1) / N Σ_s w(s) I(t - s)
+//! ```
+//!
+//! with infectiousness profile `w`, the relative infectiousness of an
+//! infection at each age of infection, and reproduction number `R(t)` in a
+//! fully susceptible population. Any generation interval may be used, where
+//! compartmental models imply an exponential or Erlang one. A profile given
+//! as a mean and standard deviation is discretized by
+//! [`crate::distributions::Delay::discretize`].
+//!
+//! Incidence is in the convention of [`Cori`], so it may be passed to a
+//! [`ReportingModel`] for reported cases, and R_t estimated from it with the
+//! same profile by [`Model::estimator`].
+use crate::sirrs::observation::ReportingModel;
+use crate::sirrs::reproduction::Cori;
+use crate::sirrs::rng;
+use crate::sirrs::schedule::RateSchedule;
+use faer::Mat;
+use rand_distr::{Distribution, Poisson};
+
+/// Create and run a renewal equation model.
+pub struct Model {
+    /// Number of unit times to solve. The length of the series.
+    pub length: usize,
+    /// Population size N.
+    pub population: f64,
+    /// Infections at t = 0.
+    pub i_init: f64,
+    /// Reproduction number in a fully susceptible population at each time.
+    pub reproduction_number: RateSchedule,
+    /// Relative infectiousness at each age of infection, from 1 unit time.
+    /// Normalized to sum to 1.
+    pub infectiousness: Vec<f64>,
+    /// New infections at each unit time. 1D Array with `length` elements.
+    pub incidence: Mat<f64>,
+    /// Susceptible count at the end of each unit time. 1D Array with
+    /// `length` elements.
+    pub susceptible: Mat<f64>,
+    /// Effective reproduction number `R(t) S(t - 1) / N` generating the
+    /// infections at each unit time. 1D Array with `length` elements.
+    pub r_eff: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            population: 0.0,
+            i_init: 0.0,
+            reproduction_number: RateSchedule::constant(0.0),
+            infectiousness: vec![1.0],
+            incidence: Mat::new(),
+            susceptible: Mat::new(),
+            r_eff: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. The infectiousness profile is normalized
+    /// to sum to 1. Element `s - 1` is the infectiousness at age `s`.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        population: f64,
+        i_init: f64,
+        reproduction_number: RateSchedule,
+        infectiousness: Vec<f64>,
+    ) -> &mut Self {
+        let total: f64 = infectiousness.iter().sum();
+        assert!(
+            total > 0.0,
+            "infectiousness profile must have positive total, got {}",
+            total
+        );
+        assert!(
+            (i_init >= 0.0) & (i_init <= population),
+            "i_init must be in [0, population], got {}",
+            i_init
+        );
+        self.length = length;
+        self.population = population;
+        self.i_init = i_init;
+        self.reproduction_number = reproduction_number;
+        self.infectiousness = infectiousness.iter().map(|w| w / total).collect();
+        self.incidence = Mat::zeros(length, 1);
+        self.susceptible = Mat::zeros(length, 1);
+        self.r_eff = Mat::zeros(length, 1);
+        return self;
+    }
+
+    /// Total infectiousness at unit time `t` of the infections before it.
+    fn total_infectiousness(&self, t: usize) -> f64 {
+        return (1..=self.infectiousness.len().min(t))
+            .map(|s| self.infectiousness[s - 1] * self.incidence[(t - s, 0)])
+            .sum();
+    }
+
+    /// Solve the renewal equation, drawing each unit time's infections with
+    /// `draw` from their expectation. Infections never exceed the remaining
+    /// susceptibles.
+    fn solve(&mut self, mut draw: impl FnMut(f64) -> f64) -> &Model {
+        let mut s = self.population;
+        for t in 0..self.length {
+            let r_eff = self.reproduction_number.at(t as f64) * s / self.population;
+            let infections = if t == 0 {
+                self.i_init
+            } else {
+                draw(r_eff * self.total_infectiousness(t)).min(s)
+            };
+            s -= infections;
+            self.incidence[(t, 0)] = infections;
+            self.susceptible[(t, 0)] = s;
+            self.r_eff[(t, 0)] = r_eff;
+        }
+        return self;
+    }
+
+    /// Solve the renewal equation deterministically.
+    pub fn run(&mut self) -> &Model {
+        return self.solve(|expected| expected);
+    }
+
+    /// Solve the renewal equation with Poisson infections around the
+    /// expectation at each unit time, from `seed`.
+    pub fn run_poisson(&mut self, seed: u64) -> &Model {
+        let mut rng = rng::rng(seed);
+        return self.solve(|expected| {
+            if expected > 0.0 {
+                Poisson::new(expected).unwrap().sample(&mut rng)
+            } else {
+                0.0
+            }
+        });
+    }
+
+    /// Expected reported cases at each unit time under `reporting`.
+    pub fn reported(&self, reporting: &ReportingModel) -> Mat<f64> {
+        return reporting.expected(&self.incidence);
+    }
+
+    /// A [`Cori`] estimator of R_t with this model's infectiousness profile
+    /// as its generation interval, over windows of `window` unit times.
+    pub fn estimator(&self, window: usize) -> Cori {
+        let mut cori = Cori::new();
+        cori.configure(self.infectiousness.clone(), window);
+        return cori;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::renewal::Model;
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::units::Fraction;
+
+    #[test]
+    fn test_growth_rate() {
+        // Without depletion incidence grows at the rate r solving the
+        // Euler-Lotka equation 1 = R Σ w(s) e^(-r s).
+        let w = vec![0.25, 0.5, 0.25];
+        let mut model = Model::new();
+        model.configure(150, 1e60, 1.0, RateSchedule::constant(2.0), w.clone());
+        model.run();
+        let r = (model.incidence[(149, 0)] / model.incidence[(148, 0)]).ln();
+        let lotka: f64 = (1..=3)
+            .map(|s| 2.0 * w[s - 1] * (-r * s as f64).exp())
+            .sum();
+        assert!(
+            (lotka - 1.0).abs() < 1e-9,
+            "Bad growth rate {}, Euler-Lotka sum expected 1 got {}",
+            r,
+            lotka
+        );
+    }
+
+    #[test]
+    fn test_depletion_and_estimation() {
+        let w = vec![0.1, 0.3, 0.3, 0.2, 0.1];
+        let mut model = Model::new();
+        model.configure(120, 1e6, 10.0, RateSchedule::new(2.5, vec![(40.0, 1.2)]), w);
+        model.run();
+        let last = model.length - 1;
+        let total: f64 = (0..=last).map(|t| model.incidence[(t, 0)]).sum();
+        assert!(
+            (total + model.susceptible[(last, 0)] - 1e6).abs() < 1e-6,
+            "Infections and susceptibles do not sum to the population, got {}",
+            total + model.susceptible[(last, 0)]
+        );
+        assert!(
+            model.r_eff[(last, 0)] < 1.0,
+            "Expected depletion to bring r_eff below 1, got {}",
+            model.r_eff[(last, 0)]
+        );
+        // The Cori estimator recovers the effective reproduction number.
+        let mut cori = model.estimator(1);
+        cori.prior(1.0, 1e6);
+        let incidence: Vec<f64> = (0..=last).map(|t| model.incidence[(t, 0)]).collect();
+        let estimates = cori.estimate(&incidence);
+        for (k, t) in estimates.times.iter().enumerate() {
+            // The prior still matters where incidence has died out.
+            if (*t < 5) | (model.incidence[(*t, 0)] < 1.0) {
+                continue;
+            }
+            assert!(
+                (estimates.mean[k] - model.r_eff[(*t, 0)]).abs() < 1e-3,
+                "Bad R at t = {}, expected {} got {}",
+                t,
+                model.r_eff[(*t, 0)],
+                estimates.mean[k]
+            );
+        }
+        let mut reporting = ReportingModel::new();
+        reporting.configure(Fraction::of(0.5), vec![1.0], None, 0);
+        assert_eq!(
+            model.reported(&reporting)[(30, 0)],
+            0.5 * model.incidence[(30, 0)],
+            "Bad reported cases"
+        );
+    }
+
+    #[test]
+    fn test_run_poisson() {
+        let mut model = Model::new();
+        model.configure(
+            80,
+            1e4,
+            5.0,
+            RateSchedule::constant(1.8),
+            vec![0.3, 0.4, 0.3],
+        );
+        model.run_poisson(3);
+        let first = model.incidence.clone();
+        model.run_poisson(3);
+        assert_eq!(
+            first, model.incidence,
+            "Expected the same draws from a seed"
+        );
+        for t in 0..80 {
+            assert!(
+                (model.incidence[(t, 0)].fract() == 0.0) & (model.susceptible[(t, 0)] >= 0.0),
+                "Bad draw at t = {}, got {} infections and {} susceptible",
+                t,
+                model.incidence[(t, 0)],
+                model.susceptible[(t, 0)]
+            );
+        }
+        assert!(
+            model.susceptible[(79, 0)] < 5e3,
+            "Expected a large epidemic, got {} susceptible",
+            model.susceptible[(79, 0)]
+        );
+    }
+}
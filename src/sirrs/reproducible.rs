@@ -0,0 +1,141 @@
+//! Strict floating-point reproducibility mode.
+//!
+//! By default results are reproducible run to run on one machine, but not
+//! necessarily bitwise across machines: `f64::exp` and `f64::ln` call the
+//! platform's math library, and faer may split matrix work over a varying
+//! number of threads and use fused multiply-add where the CPU supports it.
+//! With [`set_strict`] on:
+//!  - `exp`, `ln` and `powf` in the solvers, samplers and likelihoods use the
+//!    portable pure Rust implementations of the `libm` crate
+//!  - faer runs sequentially, so its kernels see a fixed thread count
+//!  - parallel runners such as [`crate::sensitivity::run_parallel`] use one
+//!    thread
+//!
+//! The crate's own sums are always accumulated left to right in index order,
+//! and it never fuses multiply-add, so with these settings outputs are
+//! bitwise identical across runs and machines. Squares and other small
+//! integer powers use `powi`, which the compiler lowers to multiplications.
+//! A test keeps other calls to the platform's math library out of the
+//! crate's non-test code. Draws from `rand_distr` distributions such as
+//! [`rand_distr::Binomial`] use the platform's math library internally and
+//! are outside this guarantee.
+use faer::Par;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether strict mode is on.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// faer's global parallelism before strict mode was turned on.
+static PREVIOUS: Mutex<Option<Par>> = Mutex::new(None);
+
+/// Turn strict mode on or off for the whole process. Turning it on saves
+/// faer's global parallelism, and turning it off restores it.
+pub fn set_strict(strict: bool) {
+    let mut previous = PREVIOUS.lock().unwrap();
+    if strict {
+        if previous.is_none() {
+            *previous = Some(faer::get_global_parallelism());
+        }
+        faer::set_global_parallelism(Par::Seq);
+    } else if let Some(par) = previous.take() {
+        faer::set_global_parallelism(par);
+    }
+    STRICT.store(strict, Ordering::SeqCst);
+}
+
+/// Whether strict mode is on.
+pub fn is_strict() -> bool {
+    return STRICT.load(Ordering::SeqCst);
+}
+
+/// Number of threads to use when `requested` are asked for: one in strict
+/// mode, otherwise `requested`.
+pub fn threads(requested: usize) -> usize {
+    if is_strict() {
+        return 1;
+    }
+    return requested;
+}
+
+/// Exponential of `x`.
+pub fn exp(x: f64) -> f64 {
+    if is_strict() {
+        return libm::exp(x);
+    }
+    return x.exp();
+}
+
+/// Natural logarithm of `x`.
+pub fn ln(x: f64) -> f64 {
+    if is_strict() {
+        return libm::log(x);
+    }
+    return x.ln();
+}
+
+/// `x` raised to the power `y`.
+pub fn powf(x: f64, y: f64) -> f64 {
+    if is_strict() {
+        return libm::pow(x, y);
+    }
+    return x.powf(y);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_no_platform_math() {
+        // Outside tests, transcendental functions go through this module.
+        let calls = [
+            ".exp()",
+            ".exp2()",
+            ".exp_m1()",
+            ".ln()",
+            ".ln_1p()",
+            ".log(",
+            ".log2()",
+            ".log10()",
+            ".powf(",
+            ".sin()",
+            ".cos()",
+            ".tan()",
+            ".sinh()",
+            ".cosh()",
+            ".tanh()",
+            ".atan(",
+            ".atan2(",
+            ".cbrt()",
+            ".hypot(",
+            "f64::exp",
+            "f64::ln",
+            "f64::powf",
+        ];
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("sirrs");
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.file_name().unwrap() == "reproducible.rs" {
+                continue;
+            }
+            let text = fs::read_to_string(&path).unwrap();
+            let code = text.split("#[cfg(test)]\nmod tests").next().unwrap();
+            for (n, line) in code.lines().enumerate() {
+                let line = line.split("//").next().unwrap();
+                for call in calls.iter() {
+                    assert!(
+                        !line.contains(call),
+                        "Expected no {} outside reproducible.rs, got {}:{}",
+                        call,
+                        path.display(),
+                        n + 1
+                    );
+                }
+            }
+        }
+    }
+}
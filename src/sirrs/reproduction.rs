@@ -0,0 +1,246 @@
+//! Instantaneous reproduction number from incidence.
+//!
+//! [`Cori`] estimates R_t, the expected number of infections caused by each
+//! infection around time t, from an incidence series by the method of Cori
+//! et al. (2013), as in EpiEstim. Incidence follows the renewal equation
+//!
+//! ```text
+//! E[I(t)] = R_t Λ(t),   Λ(t) = Σ_s w(s) I(t - s)
+//! ```
+//!
+//! with generation interval `w`, the probability an infector infects at a lag
+//! of `s` unit times. R_t is held constant over a window of unit times ending
+//! at t, and with Poisson incidence and a gamma prior of shape `a` and scale
+//! `b` its posterior is gamma with shape `a + Σ I` and rate `1 / b + Σ Λ`,
+//! summed over the window.
+//!
+//! A generation interval given as a mean and standard deviation is
+//! discretized by [`Delay::discretize`], for example
+//! `Delay::gamma(6.5, 4.0).discretize(30)`.
+//!
+//! Incidence may be observed counts, or simulated, converted from a solved
+//! model's cumulative incidence by [`incidence_per_unit_time`].
+use crate::sirrs::distributions::Delay;
+use faer::Mat;
+
+/// New cases over each unit time, from cumulative incidence sampled every
+/// `step_size`, such as [`crate::sir::Model::cumulative_incidence`]. The
+/// first element is the cumulative incidence at t = 0.
+pub fn incidence_per_unit_time(cumulative: &Mat<f64>, step_size: f64) -> Vec<f64> {
+    let n = (((cumulative.nrows() - 1) as f64) * step_size).floor() as usize;
+    let at = |t: usize| cumulative[(((t as f64) / step_size).round() as usize, 0)];
+    let mut incidence = vec![at(0)];
+    incidence.extend((1..=n).map(|t| at(t) - at(t - 1)));
+    return incidence;
+}
+
+/// Posterior summaries of R_t at the end of each window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimates {
+    /// Last unit time of each window, the time each estimate is for.
+    pub times: Vec<usize>,
+    /// Posterior mean.
+    pub mean: Vec<f64>,
+    /// Posterior standard deviation.
+    pub sd: Vec<f64>,
+    /// Lower limit of the central credible interval.
+    pub lower: Vec<f64>,
+    /// Posterior median.
+    pub median: Vec<f64>,
+    /// Upper limit of the central credible interval.
+    pub upper: Vec<f64>,
+}
+
+/// Create and apply a Cori et al. estimator of R_t.
+pub struct Cori {
+    /// Probability of each lag of the generation interval, from a lag of
+    /// 1 unit time. Normalized to sum to 1.
+    pub generation_interval: Vec<f64>,
+    /// Number of unit times in each window.
+    pub window: usize,
+    /// Mean of the gamma prior on R_t.
+    pub prior_mean: f64,
+    /// Standard deviation of the gamma prior on R_t.
+    pub prior_sd: f64,
+    /// Probability of the central credible interval.
+    pub level: f64,
+}
+
+impl Cori {
+    /// Create a new estimator, with EpiEstim's defaults: weekly windows and
+    /// a prior with mean and standard deviation 5. Every infection is
+    /// transmitted after 1 unit time until configured.
+    pub fn new() -> Self {
+        return Self {
+            generation_interval: vec![1.0],
+            window: 7,
+            prior_mean: 5.0,
+            prior_sd: 5.0,
+            level: 0.95,
+        };
+    }
+
+    /// Configure the estimator. The generation interval is normalized to
+    /// sum to 1. Element `s - 1` is the probability of a lag of `s`, or with
+    /// [`Delay::discretize`] of a lag in `[s - 1, s)`.
+    pub fn configure(&mut self, generation_interval: Vec<f64>, window: usize) -> &mut Self {
+        let total: f64 = generation_interval.iter().sum();
+        assert!(
+            total > 0.0,
+            "generation interval must have positive total, got {}",
+            total
+        );
+        assert!(window > 0, "window must be positive, got {}", window);
+        self.generation_interval = generation_interval.iter().map(|p| p / total).collect();
+        self.window = window;
+        return self;
+    }
+
+    /// Set the mean and standard deviation of the gamma prior on R_t.
+    pub fn prior(&mut self, mean: f64, sd: f64) -> &mut Self {
+        assert!(
+            (mean > 0.0) & (sd > 0.0),
+            "prior mean and sd must be positive, got {} and {}",
+            mean,
+            sd
+        );
+        self.prior_mean = mean;
+        self.prior_sd = sd;
+        return self;
+    }
+
+    /// Total infectiousness Λ(t) at each unit time of `incidence`.
+    pub fn infectiousness(&self, incidence: &[f64]) -> Vec<f64> {
+        return (0..incidence.len())
+            .map(|t| {
+                (1..=self.generation_interval.len().min(t))
+                    .map(|s| self.generation_interval[s - 1] * incidence[t - s])
+                    .sum()
+            })
+            .collect();
+    }
+
+    /// Estimate R_t from new cases over each unit time, `incidence`, for
+    /// every window from the one starting at t = 1 to the one ending at the
+    /// last time.
+    pub fn estimate(&self, incidence: &[f64]) -> Estimates {
+        let lambda = self.infectiousness(incidence);
+        let shape = (self.prior_mean / self.prior_sd).powi(2);
+        let scale = self.prior_sd * self.prior_sd / self.prior_mean;
+        let mut estimates = Estimates {
+            times: Vec::new(),
+            mean: Vec::new(),
+            sd: Vec::new(),
+            lower: Vec::new(),
+            median: Vec::new(),
+            upper: Vec::new(),
+        };
+        for end in self.window..incidence.len() {
+            let start = end + 1 - self.window;
+            let a = shape + incidence[start..=end].iter().sum::<f64>();
+            let rate = (1.0 / scale) + lambda[start..=end].iter().sum::<f64>();
+            let posterior = Delay::Gamma {
+                shape: a,
+                scale: 1.0 / rate,
+            };
+            estimates.times.push(end);
+            estimates.mean.push(a / rate);
+            estimates.sd.push(a.sqrt() / rate);
+            estimates
+                .lower
+                .push(posterior.quantile(0.5 * (1.0 - self.level)));
+            estimates.median.push(posterior.quantile(0.5));
+            estimates
+                .upper
+                .push(posterior.quantile(0.5 * (1.0 + self.level)));
+        }
+        return estimates;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::distributions::Delay;
+    use crate::sirrs::reproduction::{Cori, incidence_per_unit_time};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_renewal_process() {
+        let w = vec![0.2, 0.5, 0.3];
+        let mut incidence = vec![10.0];
+        for t in 1..60 {
+            let r = if t < 30 { 1.5 } else { 0.8 };
+            let lambda: f64 = (1..=3.min(t)).map(|s| w[s - 1] * incidence[t - s]).sum();
+            incidence.push(r * lambda);
+        }
+        let mut cori = Cori::new();
+        cori.configure(w, 7).prior(1.0, 1e6);
+        let estimates = cori.estimate(&incidence);
+        assert_eq!(estimates.times.first(), Some(&7), "Bad first window");
+        for (k, t) in estimates.times.iter().enumerate() {
+            let expected = if *t < 30 {
+                1.5
+            } else if *t >= 36 {
+                0.8
+            } else {
+                continue;
+            };
+            assert!(
+                (estimates.mean[k] - expected).abs() < 1e-3,
+                "Bad R at t = {}, expected {} got {}",
+                t,
+                expected,
+                estimates.mean[k]
+            );
+            assert!(
+                (estimates.lower[k] < estimates.median[k])
+                    & (estimates.median[k] < estimates.upper[k]),
+                "Expected ordered credible interval at t = {}",
+                t
+            );
+        }
+    }
+
+    #[test]
+    fn test_simulated_sir() {
+        let mut model = sir::Model::new();
+        model.configure(
+            40,
+            0.1,
+            100.0,
+            0.0,
+            Rate::per_day(0.5),
+            Rate::per_day(0.25),
+            Rate::per_day(0.0),
+        );
+        model.counts(1e6);
+        model.init_popf();
+        model.run_rk4();
+        let incidence = incidence_per_unit_time(&model.cumulative_incidence, 0.1);
+        assert_eq!(incidence.len(), 40, "Bad incidence length");
+        // Infections are transmitted at rate β S / N while infectious, for an
+        // exponential time with rate γ.
+        let generation_interval = Delay::Gamma {
+            shape: 1.0,
+            scale: 4.0,
+        };
+        let mut cori = Cori::new();
+        cori.configure(generation_interval.discretize(40), 5);
+        let estimates = cori.estimate(&incidence);
+        for (k, t) in estimates.times.iter().enumerate() {
+            let index = ((*t as f64 - 2.0) / 0.1).round() as usize;
+            let expected = 2.0 * model.s_popf[(index, 0)] / 1e6;
+            if *t < 15 {
+                continue;
+            }
+            assert!(
+                (estimates.mean[k] - expected).abs() < 0.15 * expected,
+                "Bad R at t = {}, expected {} got {}",
+                t,
+                expected,
+                estimates.mean[k]
+            );
+        }
+    }
+}
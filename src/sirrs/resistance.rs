@@ -0,0 +1,399 @@
+//! Two-strain model of drug resistance under treatment.
+//!
+//! Infections are with a drug-sensitive or a drug-resistant strain. A
+//! fraction of sensitive infections, the treatment coverage, is treated,
+//! which clears them faster, returning them to S as treatment gives no
+//! immunity. Treatment cannot clear resistant infections, and a fraction of
+//! treated sensitive infections acquire resistance instead of clearing.
+//! Resistance carries a fitness cost, reducing transmission of the
+//! resistant strain. Allows transition rates:
+//!  - S → I_s and S → I_r
+//!  - I_s → S, by recovery and by treatment
+//!  - I_s → I_r, treatment failing with acquired resistance
+//!  - I_s → R and I_r → R
+//!  - I_r → S
+//!
+//! With no resistant infections and no treatment this is the SIR model.
+//! Treatment shortens sensitive infections and so selects for resistance:
+//! above [`Model::critical_coverage`] the resistant strain outcompetes the
+//! sensitive one.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run a model of sensitive and resistant strains.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial drug-sensitive infectious population fraction.
+    pub i_sensitive_init: f64,
+    /// Initial drug-resistant infectious population fraction.
+    pub i_resistant_init: f64,
+    /// Transition rate from S into I_s by contact with I_s. Must be in
+    /// [0, 1].
+    pub incidence_rate: f64,
+    /// Relative reduction in transmission of the resistant strain. Must be
+    /// in [0, 1].
+    pub fitness_cost: f64,
+    /// Transition rate from either I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Transition rate from either I into S without treatment. Must be in
+    /// [0, 1].
+    pub recovery_rate: f64,
+    /// Fraction of sensitive infections treated. Must be in [0, 1]. Zero
+    /// unless set with [`Model::treatment`].
+    pub treatment_coverage: f64,
+    /// Additional rate treated sensitive infections are cleared.
+    pub treatment_rate: f64,
+    /// Probability a treated infection acquires resistance rather than
+    /// clearing. Must be in [0, 1].
+    pub resistance_probability: f64,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Drug-sensitive infectious population fraction at each index. 1D Array
+    /// with one element per index of [`Model::grid`].
+    pub i_sensitive_popf: Mat<f64>,
+    /// Drug-resistant infectious population fraction at each index. 1D Array
+    /// with one element per index of [`Model::grid`].
+    pub i_resistant_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_sensitive_init: 0.0,
+            i_resistant_init: 0.0,
+            incidence_rate: 0.0,
+            fitness_cost: 0.0,
+            removal_rate: 0.0,
+            recovery_rate: 0.0,
+            treatment_coverage: 0.0,
+            treatment_rate: 0.0,
+            resistance_probability: 0.0,
+            s_popf: Mat::new(),
+            i_sensitive_popf: Mat::new(),
+            i_resistant_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. Nobody is treated until
+    /// [`Model::treatment`] is set.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_sensitive_init: f64,
+        i_resistant_init: f64,
+        incidence_rate: Rate,
+        fitness_cost: Fraction,
+        removal_rate: Rate,
+        recovery_rate: Rate,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_sensitive_init = i_sensitive_init;
+        self.i_resistant_init = i_resistant_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.fitness_cost = fitness_cost.value();
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.recovery_rate = recovery_rate.per(TimeUnit::Day);
+        self.treatment_coverage = 0.0;
+        self.treatment_rate = 0.0;
+        self.resistance_probability = 0.0;
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_sensitive_popf = Mat::zeros(n_steps, 1);
+        self.i_resistant_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Treat a fraction `coverage` of sensitive infections, clearing them at
+    /// an additional `rate`, with each treated infection acquiring
+    /// resistance with `resistance_probability`.
+    pub fn treatment(
+        &mut self,
+        coverage: f64,
+        rate: f64,
+        resistance_probability: f64,
+    ) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&coverage),
+            "coverage must be in [0, 1], got {}",
+            coverage
+        );
+        assert!(rate >= 0.0, "rate must be non-negative, got {}", rate);
+        assert!(
+            (0.0..=1.0).contains(&resistance_probability),
+            "resistance_probability must be in [0, 1], got {}",
+            resistance_probability
+        );
+        self.treatment_coverage = coverage;
+        self.treatment_rate = rate;
+        self.resistance_probability = resistance_probability;
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let s_init = 1.0 - self.i_sensitive_init - self.i_resistant_init; // Population fractions must sum to 1.
+        self.store(
+            0,
+            &[s_init, self.i_sensitive_init, self.i_resistant_init, 0.0],
+        );
+        return self;
+    }
+
+    /// Basic reproduction number of the sensitive strain under treatment.
+    pub fn r0_sensitive(&self) -> f64 {
+        return self.incidence_rate
+            / (self.removal_rate
+                + self.recovery_rate
+                + (self.treatment_coverage * self.treatment_rate));
+    }
+
+    /// Basic reproduction number of the resistant strain.
+    pub fn r0_resistant(&self) -> f64 {
+        return self.incidence_rate * (1.0 - self.fitness_cost)
+            / (self.removal_rate + self.recovery_rate);
+    }
+
+    /// Treatment coverage above which the resistant strain has the larger
+    /// reproduction number. May exceed 1 when treatment cannot select for
+    /// resistance, and is infinite without treatment.
+    pub fn critical_coverage(&self) -> f64 {
+        return self.fitness_cost * (self.removal_rate + self.recovery_rate)
+            / ((1.0 - self.fitness_cost) * self.treatment_rate);
+    }
+
+    /// Fraction of infections resistant at each index, NaN with nobody
+    /// infectious.
+    pub fn resistant_fraction(&self) -> Mat<f64> {
+        return Mat::from_fn(self.i_resistant_popf.nrows(), 1, |t, _| {
+            let resistant = self.i_resistant_popf[(t, 0)];
+            let total = self.i_sensitive_popf[(t, 0)] + resistant;
+            if total > 0.0 {
+                resistant / total
+            } else {
+                f64::NAN
+            }
+        });
+    }
+
+    /// Pack the state at index `t` into a vector ordered S, I_s, I_r, R.
+    fn load(&self, t: usize) -> [f64; 4] {
+        return [
+            self.s_popf[(t, 0)],
+            self.i_sensitive_popf[(t, 0)],
+            self.i_resistant_popf[(t, 0)],
+            self.r_popf[(t, 0)],
+        ];
+    }
+
+    /// Unpack a state vector into index `t`.
+    fn store(&mut self, t: usize, y: &[f64; 4]) {
+        self.s_popf[(t, 0)] = y[0];
+        self.i_sensitive_popf[(t, 0)] = y[1];
+        self.i_resistant_popf[(t, 0)] = y[2];
+        self.r_popf[(t, 0)] = y[3];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64; 4]) -> [f64; 4] {
+        let sensitive = self.incidence_rate * y[0] * y[1];
+        let resistant = self.incidence_rate * (1.0 - self.fitness_cost) * y[0] * y[2];
+        let treated = self.treatment_coverage * self.treatment_rate * y[1];
+        let acquired = self.resistance_probability * treated;
+        let cleared = (self.recovery_rate * (y[1] + y[2])) + (treated - acquired);
+        let removal = self.removal_rate * (y[1] + y[2]);
+        return [
+            cleared - sensitive - resistant,
+            sensitive - ((self.removal_rate + self.recovery_rate) * y[1]) - treated,
+            resistant + acquired - ((self.removal_rate + self.recovery_rate) * y[2]),
+            removal,
+        ];
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            self.store(t + 1, &std::array::from_fn(|j| y[j] + (h * d[j])));
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let k2 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])));
+            let k3 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])));
+            let k4 = self.derivatives(&std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.store(
+                t + 1,
+                &std::array::from_fn(|j| {
+                    y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+                }),
+            );
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::resistance::Model;
+    use crate::sirrs::sir;
+    use crate::sirrs::units::{Fraction, Rate};
+
+    #[test]
+    fn test_untreated_sensitive_matches_sir() {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Fraction::of(0.2),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = sir::Model::new();
+        sir.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            assert!(
+                (model.i_sensitive_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i_sensitive_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_sensitive_popf[(t, 0)]
+            );
+        }
+        assert_eq!(
+            model.i_resistant_popf[(model.s_popf.nrows() - 1, 0)],
+            0.0,
+            "Expected no resistance without treatment"
+        );
+    }
+
+    #[test]
+    fn test_treatment_selects_for_resistance() {
+        // SIS dynamics, endemic without removal.
+        let run = |coverage: f64| {
+            let mut model = Model::new();
+            model.configure(
+                2000,
+                0.5,
+                0.01,
+                0.001,
+                Rate::per_day(0.5),
+                Fraction::of(0.2),
+                Rate::per_day(0.0),
+                Rate::per_day(0.1),
+            );
+            model.treatment(coverage, 0.2, 0.0);
+            model.init_popf();
+            model.run_rk4();
+            return model;
+        };
+        let critical = run(0.0).critical_coverage();
+        // c γ / ((1 - c) κ) = 0.2 * 0.1 / (0.8 * 0.2).
+        assert!(
+            (critical - 0.125).abs() < 1e-12,
+            "Bad critical coverage, expected 0.125 got {}",
+            critical
+        );
+        let (low, high) = (run(0.05), run(0.5));
+        let last = low.s_popf.nrows() - 1;
+        assert!(
+            low.resistant_fraction()[(last, 0)] < 0.01,
+            "Expected sensitive strain to win below critical coverage, got resistant fraction {}",
+            low.resistant_fraction()[(last, 0)]
+        );
+        assert!(
+            high.resistant_fraction()[(last, 0)] > 0.99,
+            "Expected resistant strain to win above critical coverage, got resistant fraction {}",
+            high.resistant_fraction()[(last, 0)]
+        );
+        // The resistant strain alone settles at S = 1 / R0.
+        let expected = 1.0 / high.r0_resistant();
+        assert!(
+            (high.s_popf[(last, 0)] - expected).abs() < 1e-6,
+            "Bad endemic s_popf, expected {} got {}",
+            expected,
+            high.s_popf[(last, 0)]
+        );
+    }
+
+    #[test]
+    fn test_acquired_resistance_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            200,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.5),
+            Fraction::of(0.1),
+            Rate::per_day(0.02),
+            Rate::per_day(0.1),
+        );
+        model.treatment(0.3, 0.5, 0.1);
+        model.init_popf();
+        model.run_rk4();
+        for t in 0..model.s_popf.nrows() {
+            let total = model.s_popf[(t, 0)]
+                + model.i_sensitive_popf[(t, 0)]
+                + model.i_resistant_popf[(t, 0)]
+                + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+        assert!(
+            model.i_resistant_popf[(100, 0)] > 0.0,
+            "Expected resistance acquired under treatment"
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! Risk-group structured SIS model for sexually transmitted infections.
+//!
+//! The population is split into groups, for example high and low activity,
+//! each acquiring new partners at its own rate. Partnerships form under
+//! preferential mixing, see [`assortative_contacts`], so a small, highly
+//! active core group can sustain transmission that the rest of the
+//! population could not. Recovery gives no immunity.
+//!
+//! Other mixing patterns, and transmission probabilities that depend on the
+//! direction of transmission, are set with [`Model::contacts`] and
+//! [`Model::transmission`], for example the bipartite mixing of
+//! [`crate::twosex`]. Allows transition rates:
+//!  - S → I
+//!  - I → S
+use crate::sirrs::age::assortative_contacts;
+use crate::sirrs::export::{LongRecord, index_names, to_long};
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run a risk-group structured SIS model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Number of risk groups.
+    pub n_groups: usize,
+    /// Population fraction in each group. Column with `n_groups` rows which
+    /// must sum to 1.
+    pub population: Mat<f64>,
+    /// New partners per unit time of an individual in each group. Column
+    /// with `n_groups` rows.
+    pub activity: Mat<f64>,
+    /// Fraction of partnerships reserved for the own group. 0 is
+    /// proportionate mixing, 1 fully assortative. Must be in [0, 1].
+    pub assortativity: f64,
+    /// Initial infectious fraction within each group.
+    pub i_init: f64,
+    /// Probability of transmission per partnership with an infectious
+    /// individual. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I back into S. Must be in [0, 1].
+    pub recovery_rate: f64,
+    /// Partners per unit time an individual in group `a` (row) acquires in
+    /// group `b` (column), replacing preferential mixing. None unless set.
+    pub contacts: Option<Mat<f64>>,
+    /// Probability of transmission per partnership from group `b` (column)
+    /// to group `a` (row), replacing `incidence_rate`. None unless set.
+    pub transmission: Option<Mat<f64>>,
+    /// Susceptible population fraction of each group (column) at each index
+    /// (row).
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction of each group (column) at each index
+    /// (row).
+    pub i_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            n_groups: 0,
+            population: Mat::new(),
+            activity: Mat::new(),
+            assortativity: 0.0,
+            i_init: 0.0,
+            incidence_rate: 0.0,
+            recovery_rate: 0.0,
+            contacts: None,
+            transmission: None,
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: Mat<f64>,
+        activity: Mat<f64>,
+        assortativity: Fraction,
+        i_init: f64,
+        incidence_rate: Fraction,
+        recovery_rate: Rate,
+    ) -> &mut Self {
+        let n_groups = population.nrows();
+        assert_eq!(
+            activity.nrows(),
+            n_groups,
+            "activity must have one row per group, got {} for {} groups",
+            activity.nrows(),
+            n_groups
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.n_groups = n_groups;
+        self.population = population;
+        self.activity = activity;
+        self.assortativity = assortativity.value();
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.value();
+        self.recovery_rate = recovery_rate.per(TimeUnit::Day);
+        self.contacts = None;
+        self.transmission = None;
+        self.s_popf = Mat::zeros(n_steps, n_groups);
+        self.i_popf = Mat::zeros(n_steps, n_groups);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Set the partners per unit time an individual in group `a` (row)
+    /// acquires in group `b` (column), in place of preferential mixing.
+    pub fn contacts(&mut self, contacts: Mat<f64>) -> &mut Self {
+        assert_eq!(
+            contacts.shape(),
+            (self.n_groups, self.n_groups),
+            "contacts must be {} by {}, got {:?}",
+            self.n_groups,
+            self.n_groups,
+            contacts.shape()
+        );
+        self.contacts = Some(contacts);
+        return self;
+    }
+
+    /// Set the probability of transmission per partnership from group `b`
+    /// (column) to group `a` (row), in place of `incidence_rate`.
+    pub fn transmission(&mut self, transmission: Mat<f64>) -> &mut Self {
+        assert_eq!(
+            transmission.shape(),
+            (self.n_groups, self.n_groups),
+            "transmission must be {} by {}, got {:?}",
+            self.n_groups,
+            self.n_groups,
+            transmission.shape()
+        );
+        self.transmission = Some(transmission);
+        return self;
+    }
+
+    /// Partners per unit time an individual in group `a` (row) acquires in
+    /// group `b` (column), see [`assortative_contacts`], unless set by
+    /// [`Model::contacts`].
+    pub fn contact_matrix(&self) -> Mat<f64> {
+        if let Some(contacts) = &self.contacts {
+            return contacts.clone();
+        }
+        return assortative_contacts(&self.population, &self.activity, self.assortativity);
+    }
+
+    /// Probability of transmission per partnership from group `b` (column)
+    /// to group `a` (row), `incidence_rate` unless set by
+    /// [`Model::transmission`].
+    pub fn transmission_matrix(&self) -> Mat<f64> {
+        if let Some(transmission) = &self.transmission {
+            return transmission.clone();
+        }
+        return Mat::from_fn(self.n_groups, self.n_groups, |_, _| self.incidence_rate);
+    }
+
+    /// Basic reproduction number, the spectral radius of the next generation
+    /// matrix `β[a, b] C[a, b] N[a] / (γ N[b])`.
+    pub fn r0(&self) -> f64 {
+        let contacts = self.contact_matrix();
+        let transmission = self.transmission_matrix();
+        let n = self.n_groups;
+        let next_generation = Mat::from_fn(n, n, |a, b| {
+            transmission[(a, b)] * contacts[(a, b)] * self.population[(a, 0)]
+                / (self.recovery_rate * self.population[(b, 0)])
+        });
+        return next_generation
+            .eigenvalues()
+            .expect("eigenvalues of a real square matrix")
+            .iter()
+            .map(|e| e.norm())
+            .fold(0.0, f64::max);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment in each group from `population` and `i_init`.
+    pub fn init_popf(&mut self) -> &mut Model {
+        for g in 0..self.n_groups {
+            let n = self.population[(g, 0)];
+            self.s_popf[(0, g)] = n * (1.0 - self.i_init);
+            self.i_popf[(0, g)] = n * self.i_init;
+        }
+        return self;
+    }
+
+    /// Prevalence, the infectious fraction within each group (column), at
+    /// each index (row).
+    pub fn prevalence(&self) -> Mat<f64> {
+        return Mat::from_fn(self.i_popf.nrows(), self.n_groups, |t, g| {
+            self.i_popf[(t, g)] / self.population[(g, 0)]
+        });
+    }
+
+    /// Partnerships per unit time of group `a` (row) with group `b`
+    /// (column) that transmit, each contact weighted by its probability of
+    /// transmission.
+    fn transmitting_contacts(&self) -> Mat<f64> {
+        let (contacts, transmission) = (self.contact_matrix(), self.transmission_matrix());
+        return Mat::from_fn(self.n_groups, self.n_groups, |a, b| {
+            transmission[(a, b)] * contacts[(a, b)]
+        });
+    }
+
+    /// Compute the derivative of infectious fraction in every group from
+    /// the transmitting contacts. The susceptible derivative is its
+    /// negative.
+    fn derivatives(&self, i: &[f64], contacts: &Mat<f64>) -> Vec<f64> {
+        return (0..self.n_groups)
+            .map(|a| {
+                let n = self.population[(a, 0)];
+                let force: f64 = (0..self.n_groups)
+                    .filter(|&b| self.population[(b, 0)] > 0.0)
+                    .map(|b| contacts[(a, b)] * i[b] / self.population[(b, 0)])
+                    .sum();
+                (force * (n - i[a])) - (self.recovery_rate * i[a])
+            })
+            .collect();
+    }
+
+    /// New infections per unit time in each group, with `y` the susceptible
+    /// and then the infectious fraction of each group, and `incidence_rate`
+    /// in place of the configured one unless [`Model::transmission`] is
+    /// set.
+    pub(crate) fn group_infection(&self, y: &[f64], incidence_rate: f64) -> Vec<f64> {
+        let n = self.n_groups;
+        let contacts = self.contact_matrix();
+        let transmission = match &self.transmission {
+            Some(transmission) => transmission.clone(),
+            None => Mat::from_fn(n, n, |_, _| incidence_rate),
+        };
+        return (0..n)
+            .map(|a| {
+                let force: f64 = (0..n)
+                    .filter(|&b| self.population[(b, 0)] > 0.0)
+                    .map(|b| {
+                        transmission[(a, b)] * contacts[(a, b)] * y[n + b] / self.population[(b, 0)]
+                    })
+                    .sum();
+                force * y[a]
+            })
+            .collect();
+    }
+
+    /// Store infectious fractions `i` at index `t`, with the rest of each
+    /// group susceptible.
+    fn store(&mut self, t: usize, i: &[f64]) {
+        for g in 0..self.n_groups {
+            self.i_popf[(t, g)] = i[g];
+            self.s_popf[(t, g)] = self.population[(g, 0)] - i[g];
+        }
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        let contacts = self.transmitting_contacts();
+        for t in 0..self.grid().n_steps - 1 {
+            let y: Vec<f64> = (0..self.n_groups).map(|g| self.i_popf[(t, g)]).collect();
+            let d = self.derivatives(&y, &contacts);
+            let next: Vec<f64> = (0..self.n_groups).map(|g| y[g] + (h * d[g])).collect();
+            self.store(t + 1, &next);
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = self.n_groups;
+        let contacts = self.transmitting_contacts();
+        for t in 0..self.grid().n_steps - 1 {
+            let y: Vec<f64> = (0..n).map(|g| self.i_popf[(t, g)]).collect();
+            let k1 = self.derivatives(&y, &contacts);
+            let y2: Vec<f64> = (0..n).map(|g| y[g] + (h / 2.0 * k1[g])).collect();
+            let k2 = self.derivatives(&y2, &contacts);
+            let y3: Vec<f64> = (0..n).map(|g| y[g] + (h / 2.0 * k2[g])).collect();
+            let k3 = self.derivatives(&y3, &contacts);
+            let y4: Vec<f64> = (0..n).map(|g| y[g] + (h * k3[g])).collect();
+            let k4 = self.derivatives(&y4, &contacts);
+            let next: Vec<f64> = (0..n)
+                .map(|g| y[g] + ((k1[g] + (2.0 * k2[g]) + (2.0 * k3[g]) + k4[g]) * (h / 6.0)))
+                .collect();
+            self.store(t + 1, &next);
+        }
+        return self;
+    }
+
+    /// Compartments in long format, one record per time, group and
+    /// compartment. Groups are named by `group_names`, or by index if it is
+    /// empty.
+    pub fn to_long(&self, group_names: &[String]) -> Vec<LongRecord> {
+        let names = if group_names.is_empty() {
+            index_names(self.n_groups)
+        } else {
+            group_names.to_vec()
+        };
+        return to_long(
+            self.step_size,
+            &names,
+            &[("s", &self.s_popf), ("i", &self.i_popf)],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::riskgroup::Model;
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::mat;
+
+    fn core_group_model(assortativity: f64) -> Model {
+        let mut model = Model::new();
+        model.configure(
+            400,
+            0.5,
+            mat![[0.1], [0.9]],
+            mat![[10.0], [1.0]],
+            Fraction::of(assortativity),
+            0.01,
+            Fraction::of(0.05),
+            Rate::per_day(0.2),
+        );
+        model.init_popf();
+        return model;
+    }
+
+    #[test]
+    fn test_r0() {
+        // Proportionate mixing gives R0 = β Σ c² N / (γ Σ c N).
+        let model = core_group_model(0.0);
+        let expected = 0.05 * ((100.0 * 0.1) + 0.9) / (0.2 * ((10.0 * 0.1) + 0.9));
+        assert!(
+            (model.r0() - expected).abs() < 1e-9,
+            "Bad proportionate r0, expected {} got {}",
+            expected,
+            model.r0()
+        );
+        // Fully assortative groups are separate epidemics, the core group's
+        // the largest.
+        let model = core_group_model(1.0);
+        assert!(
+            (model.r0() - 2.5).abs() < 1e-9,
+            "Bad assortative r0, expected 2.5 got {}",
+            model.r0()
+        );
+    }
+
+    #[test]
+    fn test_run_rk4_homogeneous_equilibrium() {
+        // Equal activity is a single well-mixed SIS with prevalence
+        // 1 - γ / (β c) at equilibrium.
+        let mut model = Model::new();
+        model.configure(
+            400,
+            0.5,
+            mat![[0.3], [0.7]],
+            mat![[4.0], [4.0]],
+            Fraction::of(0.5),
+            0.01,
+            Fraction::of(0.1),
+            Rate::per_day(0.2),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let last = model.i_popf.nrows() - 1;
+        let prevalence = model.prevalence();
+        for g in 0..2 {
+            let total = model.s_popf[(last, g)] + model.i_popf[(last, g)];
+            assert!(
+                (total - model.population[(g, 0)]).abs() < 1e-12,
+                "Group {} population not conserved, got {}",
+                g,
+                total
+            );
+            assert!(
+                (prevalence[(last, g)] - 0.5).abs() < 1e-6,
+                "Bad group {} prevalence, expected 0.5 got {}",
+                g,
+                prevalence[(last, g)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_core_group_sustains_transmission() {
+        let mut mixed = core_group_model(0.0);
+        mixed.run_rk4();
+        let mut assortative = core_group_model(0.8);
+        assortative.run_rk4();
+        let last = mixed.i_popf.nrows() - 1;
+        let mixed_prevalence = mixed.prevalence();
+        let assortative_prevalence = assortative.prevalence();
+        assert!(
+            mixed_prevalence[(last, 0)] > mixed_prevalence[(last, 1)],
+            "Expected higher prevalence in the core group, got {} and {}",
+            mixed_prevalence[(last, 0)],
+            mixed_prevalence[(last, 1)]
+        );
+        // Keeping partnerships within the core concentrates infection there.
+        assert!(
+            assortative_prevalence[(last, 1)] < mixed_prevalence[(last, 1)],
+            "Expected assortative mixing to protect the low activity group, got {} and {}",
+            assortative_prevalence[(last, 1)],
+            mixed_prevalence[(last, 1)]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Latin hypercube sampling of parameter sets.
+//!
+//! Each parameter's range is split into `n` intervals of equal probability
+//! under its [`Marginal`] distribution, and every interval is sampled exactly
+//! once, with the intervals of different parameters paired at random. This
+//! covers the marginals far more evenly than independent sampling at the same
+//! number of model runs. [`latin_hypercube`] returns [`Draws`], so samples go
+//! straight to [`crate::ensemble::run_each_draw`] for probabilistic
+//! sensitivity analysis.
+use crate::sirrs::ensemble::Draws;
+use crate::sirrs::reproducible::{exp, ln};
+use crate::sirrs::rng;
+use faer::Mat;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+/// Distribution of a single parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Marginal {
+    /// Uniform between `lower` and `upper`.
+    Uniform { lower: f64, upper: f64 },
+    /// Uniform on the log scale between `lower` and `upper`, both positive.
+    LogUniform { lower: f64, upper: f64 },
+    /// Normal with mean `mean` and standard deviation `sd`.
+    Normal { mean: f64, sd: f64 },
+    /// Log-normal, with `log_mean` and `log_sd` on the log scale.
+    LogNormal { log_mean: f64, log_sd: f64 },
+    /// Triangular between `lower` and `upper` with peak at `mode`.
+    Triangular { lower: f64, mode: f64, upper: f64 },
+}
+
+impl Marginal {
+    /// Value below which a fraction `p` of the distribution lies.
+    pub fn quantile(&self, p: f64) -> f64 {
+        match *self {
+            Marginal::Uniform { lower, upper } => return lower + (p * (upper - lower)),
+            Marginal::LogUniform { lower, upper } => {
+                return exp(ln(lower) + (p * (ln(upper) - ln(lower))));
+            }
+            Marginal::Normal { mean, sd } => return mean + (sd * normal_quantile(p)),
+            Marginal::LogNormal { log_mean, log_sd } => {
+                return exp(log_mean + (log_sd * normal_quantile(p)));
+            }
+            Marginal::Triangular { lower, mode, upper } => {
+                let split = (mode - lower) / (upper - lower);
+                if p < split {
+                    return lower + (p * (upper - lower) * (mode - lower)).sqrt();
+                }
+                return upper - ((1.0 - p) * (upper - lower) * (upper - mode)).sqrt();
+            }
+        }
+    }
+}
+
+/// Standard normal quantile function, by Acklam's rational approximation
+/// (relative error below 1.2e-9).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        return (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0);
+    };
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    if p < 0.02425 {
+        return tail((-2.0 * ln(p)).sqrt());
+    }
+    if p > 1.0 - 0.02425 {
+        return -tail((-2.0 * ln(1.0 - p)).sqrt());
+    }
+    let q = p - 0.5;
+    let r = q * q;
+    return (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+        / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0);
+}
+
+/// `n` Latin hypercube samples of the named parameters, one draw per row.
+pub fn latin_hypercube(parameters: &[(&str, Marginal)], n: usize, seed: u64) -> Draws {
+    assert!(n >= 1, "n must be at least 1");
+    let mut rng = rng::rng(seed);
+    let mut values = Mat::zeros(n, parameters.len());
+    for (j, (_, marginal)) in parameters.iter().enumerate() {
+        let mut strata: Vec<usize> = (0..n).collect();
+        strata.shuffle(&mut rng);
+        for (i, stratum) in strata.iter().enumerate() {
+            let p = ((*stratum as f64) + rng.r#gen::<f64>()) / (n as f64);
+            values[(i, j)] = marginal.quantile(p);
+        }
+    }
+    let names = parameters
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    return Draws::new(names, values);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sampling::{Marginal, latin_hypercube, normal_quantile};
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_normal_quantile() {
+        for (p, expected) in [
+            (0.5, 0.0),
+            (0.975, 1.959963984540054),
+            (0.001, -3.090232306167813),
+        ] {
+            assert!(
+                (normal_quantile(p) - expected).abs() < 1e-8,
+                "Bad normal quantile at {}, expected {} got {}",
+                p,
+                expected,
+                normal_quantile(p)
+            );
+        }
+    }
+
+    #[test]
+    fn test_latin_hypercube_strata() {
+        let n = 20;
+        let draws = latin_hypercube(
+            &[
+                (
+                    "incidence_rate",
+                    Marginal::Uniform {
+                        lower: 0.2,
+                        upper: 0.6,
+                    },
+                ),
+                (
+                    "removal_rate",
+                    Marginal::LogNormal {
+                        log_mean: -2.3,
+                        log_sd: 0.2,
+                    },
+                ),
+            ],
+            n,
+            3,
+        );
+        assert_eq!(
+            draws.n_draws(),
+            n,
+            "Bad number of draws, got {}",
+            draws.n_draws()
+        );
+        let mut counts = vec![0; n];
+        for d in 0..n {
+            let u = (draws.values[(d, 0)] - 0.2) / 0.4;
+            counts[(u * (n as f64)) as usize] += 1;
+            assert!(
+                draws.values[(d, 1)] > 0.0,
+                "Expected positive log-normal draw"
+            );
+        }
+        assert!(
+            counts.iter().all(|&c| c == 1),
+            "Expected one draw per stratum, got {:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn test_triangular_quantile() {
+        let marginal = Marginal::Triangular {
+            lower: 0.0,
+            mode: 1.0,
+            upper: 4.0,
+        };
+        assert_eq!(marginal.quantile(0.25), 1.0, "Bad quantile at the mode");
+        assert_eq!(marginal.quantile(0.0), 0.0, "Bad lower quantile");
+        assert_eq!(marginal.quantile(1.0), 4.0, "Bad upper quantile");
+    }
+
+    #[test]
+    fn test_latin_hypercube_ensemble() {
+        use crate::sirrs::ensemble::run_each_draw;
+        use crate::sirrs::interventions::Scenario;
+        use crate::sirrs::pipeline::System;
+        use crate::sirrs::sir;
+        let system = || -> Box<dyn System> {
+            let mut model = sir::Model::new();
+            model.configure(
+                50,
+                1.0,
+                0.001,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            return Box::new(model);
+        };
+        let draws = latin_hypercube(
+            &[(
+                "incidence_rate",
+                Marginal::Uniform {
+                    lower: 0.2,
+                    upper: 0.6,
+                },
+            )],
+            10,
+            1,
+        );
+        let ensemble = run_each_draw(system, &draws, &Scenario::new("baseline"), 50, 1.0);
+        assert_eq!(
+            ensemble.draws,
+            (0..10).collect::<Vec<usize>>(),
+            "Expected every draw to run once, got {:?}",
+            ensemble.draws
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! Floating point types for the generic models.
+//!
+//! [`crate::sir::Model`] and [`crate::dismod::Model`] are generic over a
+//! [`Scalar`]: they store their state and rates in that type, integrate in
+//! it with every solver, and convert to `f64` only for their outputs and
+//! analyses, such as [`crate::sir::Model::result`],
+//! [`crate::sir::Model::jacobian`] and [`crate::dismod::Model::predict`].
+//! `f64` is the default. `f32` halves the memory of every trajectory, for
+//! huge ensembles on memory-limited hardware, and [`faer::fx128`]
+//! (double-double, about 32 significant digits) is available where
+//! accuracy matters more than speed. Time and step sizes stay `f64`
+//! whatever the scalar. The other models are `f64` only.
+use faer::traits::RealField;
+
+/// A real floating point type usable in faer matrices.
+pub trait Scalar: RealField + Copy {
+    /// The value rounded to the nearest `f64`.
+    fn to_f64(self) -> f64;
+}
+
+impl Scalar for f32 {
+    fn to_f64(self) -> f64 {
+        return self as f64;
+    }
+}
+
+impl Scalar for f64 {
+    fn to_f64(self) -> f64 {
+        return self;
+    }
+}
+
+impl Scalar for faer::fx128 {
+    fn to_f64(self) -> f64 {
+        return self.0 + self.1;
+    }
+}
+
+/// `x` in the scalar type `T`, rounded to nearest.
+pub fn from_f64<T: Scalar>(x: f64) -> T {
+    return T::from_f64_impl(x);
+}
@@ -0,0 +1,456 @@
+//! Batches of named scenarios with a manifest.
+//!
+//! [`run_scenarios`] runs a list of named SIR configurations, optionally
+//! over several threads, and writes one csv per scenario into a directory
+//! together with `manifest.json`, which records the crate version and every
+//! scenario's parameters and output file. Pipelines can rerun or audit a
+//! batch from the manifest alone.
+use crate::sirrs::reproducible;
+use crate::sirrs::schema::{JsonValue, json_number, json_string, parse_json};
+use crate::sirrs::sir;
+use crate::sirrs::units::{Rate, TimeUnit};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Name of the manifest file written alongside the scenario outputs.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// A named configuration of [`sir::Model`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedConfig {
+    /// Name of the scenario, also the stem of its output file. Letters,
+    /// digits, `-` and `_` only.
+    pub name: String,
+    /// See [`sir::Model::length`].
+    pub length: usize,
+    /// See [`sir::Model::step_size`].
+    pub step_size: f64,
+    /// See [`sir::Model::i_popf_init`].
+    pub i_popf_init: f64,
+    /// See [`sir::Model::r_popf_init`].
+    pub r_popf_init: f64,
+    /// See [`sir::Model::incidence_rate`].
+    pub incidence_rate: f64,
+    /// See [`sir::Model::removal_rate`].
+    pub removal_rate: f64,
+    /// See [`sir::Model::recovery_rate`].
+    pub recovery_rate: f64,
+    /// See [`sir::Model::incidence_rate_changes`].
+    pub incidence_rate_changes: Vec<(f64, f64)>,
+}
+
+impl NamedConfig {
+    /// Scenario `name` with the arguments of [`sir::Model::configure`] and
+    /// no changepoints.
+    pub fn new(
+        name: &str,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        r_popf_init: f64,
+        incidence_rate: f64,
+        removal_rate: f64,
+        recovery_rate: f64,
+    ) -> Self {
+        return Self {
+            name: name.to_string(),
+            length,
+            step_size,
+            i_popf_init,
+            r_popf_init,
+            incidence_rate,
+            removal_rate,
+            recovery_rate,
+            incidence_rate_changes: Vec::new(),
+        };
+    }
+
+    /// Set changepoints of the incidence rate, see
+    /// [`sir::Model::changepoints`].
+    pub fn changepoints(&mut self, changes: Vec<(f64, f64)>) -> &mut Self {
+        self.incidence_rate_changes = changes;
+        return self;
+    }
+
+    /// A model configured and initialized from the scenario, ready to run.
+    pub fn model(&self) -> sir::Model {
+        let mut model = sir::Model::new();
+        model.configure(
+            self.length,
+            self.step_size,
+            self.i_popf_init,
+            self.r_popf_init,
+            Rate::per_day(self.incidence_rate),
+            Rate::per_day(self.removal_rate),
+            Rate::per_day(self.recovery_rate),
+        );
+        model.changepoints(
+            self.incidence_rate_changes
+                .iter()
+                .map(|(t, rate)| (*t, Rate::per_day(*rate)))
+                .collect(),
+        );
+        model.init_popf();
+        return model;
+    }
+
+    /// Parse a scenario from a JSON object as written by
+    /// [`NamedConfig::to_json`]. `length`, `step_size`, `i_popf_init`,
+    /// `incidence_rate` and `removal_rate` are required; `name` defaults to
+    /// `scenario`, the other rates and `r_popf_init` to 0 and
+    /// `incidence_rate_changes` to none.
+    pub fn from_json(text: &str) -> Result<Self, Error> {
+        let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+        let value = parse_json(text)?;
+        let JsonValue::Object(members) = &value else {
+            return Err(invalid("expected a JSON object".to_string()));
+        };
+        let known = [
+            "name",
+            "length",
+            "step_size",
+            "i_popf_init",
+            "r_popf_init",
+            "incidence_rate",
+            "removal_rate",
+            "recovery_rate",
+            "incidence_rate_changes",
+        ];
+        if let Some((key, _)) = members
+            .iter()
+            .find(|(key, _)| !known.contains(&key.as_str()))
+        {
+            return Err(invalid(format!("unknown field '{}'", key)));
+        }
+        let number = |key: &str, default: Option<f64>| -> Result<f64, Error> {
+            return match value.get(key) {
+                Some(JsonValue::Number(x)) => Ok(*x),
+                Some(_) => Err(invalid(format!("{} must be a number", key))),
+                None => default.ok_or_else(|| invalid(format!("missing field '{}'", key))),
+            };
+        };
+        let rate = |key: &str, default: Option<f64>| -> Result<f64, Error> {
+            let x = number(key, default)?;
+            Rate::new(x, TimeUnit::Day).map_err(|error| invalid(format!("{}: {}", key, error)))?;
+            return Ok(x);
+        };
+        let name = match value.get("name") {
+            Some(JsonValue::String(name)) => name.clone(),
+            Some(_) => return Err(invalid("name must be a string".to_string())),
+            None => "scenario".to_string(),
+        };
+        let length = number("length", None)?;
+        if (length < 1.0) | (length.fract() != 0.0) {
+            return Err(invalid(format!(
+                "length must be a positive whole number got {}",
+                length
+            )));
+        }
+        let step_size = number("step_size", None)?;
+        if step_size <= 0.0 {
+            return Err(invalid(format!(
+                "step_size must be positive got {}",
+                step_size
+            )));
+        }
+        let mut config = Self::new(
+            &name,
+            length as usize,
+            step_size,
+            number("i_popf_init", None)?,
+            number("r_popf_init", Some(0.0))?,
+            rate("incidence_rate", None)?,
+            rate("removal_rate", None)?,
+            rate("recovery_rate", Some(0.0))?,
+        );
+        let changes = match value.get("incidence_rate_changes") {
+            Some(JsonValue::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    JsonValue::Array(pair) if pair.len() == 2 => {
+                        match (pair[0].as_f64(), pair[1].as_f64()) {
+                            (Some(t), Some(rate)) if Rate::new(rate, TimeUnit::Day).is_ok() => {
+                                Ok((t, rate))
+                            }
+                            _ => Err(()),
+                        }
+                    }
+                    _ => Err(()),
+                })
+                .collect::<Result<Vec<(f64, f64)>, ()>>()
+                .map_err(|_| {
+                    invalid(
+                        "incidence_rate_changes must be [t, rate] pairs with non-negative rates"
+                            .to_string(),
+                    )
+                })?,
+            Some(_) => {
+                return Err(invalid(
+                    "incidence_rate_changes must be an array".to_string(),
+                ));
+            }
+            None => Vec::new(),
+        };
+        config.changepoints(changes);
+        return Ok(config);
+    }
+
+    /// The scenario as a JSON object.
+    pub fn to_json(&self) -> String {
+        let changes: Vec<String> = self
+            .incidence_rate_changes
+            .iter()
+            .map(|(t, rate)| format!("[{},{}]", json_number(*t), json_number(*rate)))
+            .collect();
+        return format!(
+            "{{\"name\":{},\"length\":{},\"step_size\":{},\"i_popf_init\":{},\"r_popf_init\":{},\"incidence_rate\":{},\"removal_rate\":{},\"recovery_rate\":{},\"incidence_rate_changes\":[{}]}}",
+            json_string(&self.name),
+            self.length,
+            json_number(self.step_size),
+            json_number(self.i_popf_init),
+            json_number(self.r_popf_init),
+            json_number(self.incidence_rate),
+            json_number(self.removal_rate),
+            json_number(self.recovery_rate),
+            changes.join(",")
+        );
+    }
+}
+
+/// Record of a batch of scenarios written by [`run_scenarios`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// Version of this crate that produced the outputs.
+    pub crate_version: String,
+    /// The scenarios, in the order given.
+    pub scenarios: Vec<NamedConfig>,
+    /// Output file of each scenario, relative to the output directory.
+    pub files: Vec<String>,
+}
+
+impl Manifest {
+    /// The manifest as a JSON object.
+    pub fn to_json(&self) -> String {
+        let scenarios: Vec<String> = self
+            .scenarios
+            .iter()
+            .zip(self.files.iter())
+            .map(|(scenario, file)| {
+                format!(
+                    "{{\"file\":{},\"config\":{}}}",
+                    json_string(file),
+                    scenario.to_json()
+                )
+            })
+            .collect();
+        return format!(
+            "{{\"crate\":\"sirrs\",\"crate_version\":{},\"scenarios\":[{}]}}",
+            json_string(&self.crate_version),
+            scenarios.join(",")
+        );
+    }
+}
+
+/// Run every scenario by RK4 over `n_threads` threads and write each to
+/// `<name>.csv` in `dir`, with columns `t,s,i,r,incidence,
+/// cumulative_incidence`, then write the manifest to [`MANIFEST_FILE`].
+///
+/// `dir` is created if missing. Scenario names must be unique and usable
+/// as file names. Outputs do not depend on the number of threads, and
+/// strict reproducibility mode uses a single thread.
+pub fn run_scenarios(
+    scenarios: &[NamedConfig],
+    dir: &Path,
+    n_threads: usize,
+) -> io::Result<Manifest> {
+    assert!(n_threads >= 1, "n_threads must be at least 1");
+    let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+    let mut seen = HashSet::new();
+    for scenario in scenarios.iter() {
+        let usable = scenario
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() | (c == '-') | (c == '_'));
+        if scenario.name.is_empty() | !usable {
+            return Err(invalid(format!(
+                "scenario name '{}' is not a usable file name",
+                scenario.name
+            )));
+        }
+        if !seen.insert(scenario.name.as_str()) {
+            return Err(invalid(format!(
+                "scenario name '{}' is not unique",
+                scenario.name
+            )));
+        }
+    }
+    std::fs::create_dir_all(dir)?;
+    let files: Vec<String> = scenarios
+        .iter()
+        .map(|scenario| format!("{}.csv", scenario.name))
+        .collect();
+    let n = scenarios.len();
+    let chunk = n.div_ceil(reproducible::threads(n_threads)).max(1);
+    let handles: Vec<thread::JoinHandle<io::Result<()>>> = (0..n)
+        .step_by(chunk)
+        .map(|start| {
+            let jobs: Vec<(NamedConfig, PathBuf)> = (start..(start + chunk).min(n))
+                .map(|k| (scenarios[k].clone(), dir.join(&files[k])))
+                .collect();
+            thread::spawn(move || {
+                for (scenario, path) in jobs.iter() {
+                    write_scenario(scenario, path)?;
+                }
+                return Ok(());
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("scenario thread panicked")?;
+    }
+    let manifest = Manifest {
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        scenarios: scenarios.to_vec(),
+        files,
+    };
+    let mut writer = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    writeln!(writer, "{}", manifest.to_json())?;
+    writer.flush()?;
+    return Ok(manifest);
+}
+
+/// Run `scenario` and write its output to `path`.
+fn write_scenario(scenario: &NamedConfig, path: &Path) -> io::Result<()> {
+    let mut model = scenario.model();
+    model.run_rk4();
+    let grid = model.grid();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "t,s,i,r,incidence,cumulative_incidence")?;
+    for k in 0..grid.n_steps {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            grid.time(k),
+            model.s_popf[(k, 0)],
+            model.i_popf[(k, 0)],
+            model.r_popf[(k, 0)],
+            model.incidence[(k, 0)],
+            model.cumulative_incidence[(k, 0)]
+        )?;
+    }
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::scenarios::{MANIFEST_FILE, NamedConfig, run_scenarios};
+    use std::fs;
+
+    #[test]
+    fn test_run_scenarios() {
+        let dir = std::env::temp_dir().join(format!("sirrs-scenarios-{}", std::process::id()));
+        let mut lockdown = NamedConfig::new("lockdown", 20, 0.5, 0.01, 0.0, 0.4, 0.1, 0.0);
+        lockdown.changepoints(vec![(5.0, 0.1)]);
+        let scenarios = vec![
+            NamedConfig::new("baseline", 20, 0.5, 0.01, 0.0, 0.4, 0.1, 0.0),
+            lockdown,
+            NamedConfig::new("slow", 20, 0.5, 0.01, 0.0, 0.2, 0.1, 0.0),
+        ];
+        let manifest = run_scenarios(&scenarios, &dir, 2).unwrap();
+        assert_eq!(
+            manifest.files,
+            vec!["baseline.csv", "lockdown.csv", "slow.csv"],
+            "Bad output files, got {:?}",
+            manifest.files
+        );
+        let baseline = fs::read_to_string(dir.join("baseline.csv")).unwrap();
+        assert_eq!(
+            baseline.lines().count(),
+            41,
+            "Bad number of lines, expected 41 got {}",
+            baseline.lines().count()
+        );
+        let lockdown = fs::read_to_string(dir.join("lockdown.csv")).unwrap();
+        assert_eq!(
+            baseline.lines().nth(10),
+            lockdown.lines().nth(10),
+            "Expected scenarios to agree before the changepoint"
+        );
+        assert_ne!(
+            baseline.lines().last(),
+            lockdown.lines().last(),
+            "Expected scenarios to differ after the changepoint"
+        );
+        let json = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        assert!(
+            json.contains(&format!(
+                "\"crate_version\":\"{}\"",
+                env!("CARGO_PKG_VERSION")
+            )),
+            "Bad manifest, got {}",
+            json
+        );
+        assert!(
+            json.contains("\"incidence_rate_changes\":[[5,0.1]]"),
+            "Bad manifest changepoints, got {}",
+            json
+        );
+        let sequential =
+            std::env::temp_dir().join(format!("sirrs-scenarios-sequential-{}", std::process::id()));
+        run_scenarios(&scenarios, &sequential, 1).unwrap();
+        assert_eq!(
+            fs::read_to_string(sequential.join("lockdown.csv")).unwrap(),
+            lockdown,
+            "Expected outputs independent of the number of threads"
+        );
+        let duplicate = vec![scenarios[0].clone(), scenarios[0].clone()];
+        assert!(
+            run_scenarios(&duplicate, &dir, 1).is_err(),
+            "Expected duplicate names to be rejected"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&sequential).unwrap();
+    }
+
+    #[test]
+    fn test_from_json() {
+        let mut lockdown = NamedConfig::new("lockdown", 20, 0.5, 0.01, 0.0, 0.4, 0.1, 0.0);
+        lockdown.changepoints(vec![(5.0, 0.1)]);
+        assert_eq!(
+            NamedConfig::from_json(&lockdown.to_json()).unwrap(),
+            lockdown,
+            "Expected to_json to round trip"
+        );
+        let minimal = NamedConfig::from_json(
+            r#"{"length": 20, "step_size": 0.5, "i_popf_init": 0.01, "incidence_rate": 0.4, "removal_rate": 0.1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            minimal,
+            NamedConfig::new("scenario", 20, 0.5, 0.01, 0.0, 0.4, 0.1, 0.0),
+            "Bad defaults, got {:?}",
+            minimal
+        );
+        for (bad, message) in [
+            (r#"{"length": 20}"#, "missing field 'step_size'"),
+            (
+                r#"{"length": 2.5}"#,
+                "length must be a positive whole number",
+            ),
+            (r#"{"lenght": 20}"#, "unknown field 'lenght'"),
+            ("[1]", "expected a JSON object"),
+        ] {
+            let error = NamedConfig::from_json(bad).unwrap_err().to_string();
+            assert!(
+                error.contains(message),
+                "Bad error for {}, expected {} got {}",
+                bad,
+                message,
+                error
+            );
+        }
+    }
+}
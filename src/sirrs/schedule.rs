@@ -0,0 +1,171 @@
+//! Piecewise-constant rate schedules.
+//!
+//! A [`RateSchedule`] is a step function: an initial value, then a new value
+//! from each breakpoint onward. Policy phases such as pre-lockdown, lockdown
+//! and reopening are written as a schedule of the incidence rate, with
+//! breakpoints given either as times or as calendar dates. A schedule of
+//! [`Rate`]s, from [`RateSchedule::rates`], carries its unit, and is what
+//! the models take.
+use crate::sirrs::data::parse_date;
+use crate::sirrs::units::{Rate, TimeUnit};
+use std::io::{Error, ErrorKind};
+
+/// A rate that is constant between breakpoints. Values are bare `f64`
+/// unless given another type, such as [`Rate`] for a schedule that carries
+/// its unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateSchedule<V = f64> {
+    /// Value before the first breakpoint.
+    pub initial: V,
+    /// Breakpoints as `(t, value)`, sorted by time. From each `t` onward the
+    /// rate takes the new value.
+    pub breakpoints: Vec<(f64, V)>,
+}
+
+impl<V: Copy> RateSchedule<V> {
+    /// A schedule with breakpoints as `(t, value)`. They are sorted by time.
+    pub fn new(initial: V, mut breakpoints: Vec<(f64, V)>) -> Self {
+        breakpoints.sort_by(|a, b| a.0.total_cmp(&b.0));
+        return Self {
+            initial,
+            breakpoints,
+        };
+    }
+
+    /// A schedule that never changes.
+    pub fn constant(value: V) -> Self {
+        return Self::new(value, Vec::new());
+    }
+
+    /// A schedule with breakpoints as `(date, value)`, where dates are ISO
+    /// 8601 `YYYY-MM-DD` and converted to days since `start_date`.
+    pub fn from_dates(
+        initial: V,
+        breakpoints: &[(&str, V)],
+        start_date: &str,
+    ) -> Result<Self, Error> {
+        let bad_date =
+            |date: &str| Error::new(ErrorKind::InvalidInput, format!("bad date '{}'", date));
+        let start = parse_date(start_date).ok_or_else(|| bad_date(start_date))?;
+        let mut times = Vec::with_capacity(breakpoints.len());
+        for (date, value) in breakpoints.iter() {
+            let day = parse_date(date).ok_or_else(|| bad_date(date))?;
+            times.push(((day - start) as f64, *value));
+        }
+        return Ok(Self::new(initial, times));
+    }
+
+    /// Value of the rate at time `t`.
+    pub fn at(&self, t: f64) -> V {
+        let mut rate = self.initial;
+        for (start, value) in self.breakpoints.iter() {
+            if *start > t {
+                break;
+            }
+            rate = *value;
+        }
+        return rate;
+    }
+
+    /// The same breakpoints with every value converted by `f`, or the first
+    /// error it gives.
+    pub fn try_map<W: Copy>(
+        &self,
+        f: impl Fn(V) -> Result<W, Error>,
+    ) -> Result<RateSchedule<W>, Error> {
+        let mut breakpoints = Vec::with_capacity(self.breakpoints.len());
+        for (t, value) in self.breakpoints.iter() {
+            breakpoints.push((*t, f(*value)?));
+        }
+        return Ok(RateSchedule::new(f(self.initial)?, breakpoints));
+    }
+}
+
+impl RateSchedule {
+    /// The schedule's values as rates per `unit`, or an error on the first
+    /// negative or non-finite value.
+    pub fn rates(&self, unit: TimeUnit) -> Result<RateSchedule<Rate>, Error> {
+        return self.try_map(|value| Rate::new(value, unit));
+    }
+}
+
+impl RateSchedule<Rate> {
+    /// The schedule's rates per `unit`.
+    pub fn per(&self, unit: TimeUnit) -> RateSchedule {
+        return self
+            .try_map(|rate| Ok(rate.per(unit)))
+            .expect("converting a rate cannot fail");
+    }
+}
+
+impl From<f64> for RateSchedule {
+    fn from(value: f64) -> Self {
+        return Self::constant(value);
+    }
+}
+
+impl From<Rate> for RateSchedule<Rate> {
+    fn from(value: Rate) -> Self {
+        return Self::constant(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::units::TimeUnit;
+
+    #[test]
+    fn test_at() {
+        let schedule = RateSchedule::new(0.4, vec![(30.0, 0.25), (10.0, 0.1)]);
+        for (t, expected) in [
+            (0.0, 0.4),
+            (9.9, 0.4),
+            (10.0, 0.1),
+            (29.0, 0.1),
+            (30.0, 0.25),
+        ] {
+            assert_eq!(
+                schedule.at(t),
+                expected,
+                "Bad rate at t = {}, expected {} got {}",
+                t,
+                expected,
+                schedule.at(t)
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_dates() {
+        let schedule = RateSchedule::from_dates(0.4, &[("2020-03-23", 0.1)], "2020-03-01").unwrap();
+        assert_eq!(
+            schedule.breakpoints,
+            vec![(22.0, 0.1)],
+            "Bad breakpoints, got {:?}",
+            schedule.breakpoints
+        );
+        assert!(
+            RateSchedule::from_dates(0.4, &[("2020-13-01", 0.1)], "2020-03-01").is_err(),
+            "Expected error on bad date"
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        let schedule = RateSchedule::new(7.0, vec![(14.0, 3.5)]);
+        let weekly = schedule.rates(TimeUnit::Week).unwrap().per(TimeUnit::Day);
+        assert_eq!(
+            weekly,
+            RateSchedule::new(1.0, vec![(14.0, 0.5)]),
+            "Bad daily rates, got {:?}",
+            weekly
+        );
+        assert!(
+            RateSchedule::new(0.1, vec![(3.0, -0.1)])
+                .rates(TimeUnit::Day)
+                .is_err(),
+            "Expected error on a negative rate"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! Machine-readable descriptions of models.
+//!
+//! A [`ModelSchema`] lists a model's compartments, the flows between them
+//! and its parameters with bounds and units. Models provide theirs through a
+//! `schema()` function, for example [`crate::sir::Model::schema`], and
+//! [`ModelSchema::to_json`] renders it for GUIs and external pipelines,
+//! which can build forms from it and check configurations with
+//! [`ModelSchema::validate`] before running anything.
+use crate::sirrs::pipeline::Parameters;
+use std::io::{Error, ErrorKind};
+
+/// A flow of population from one compartment to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowSchema {
+    /// Source compartment.
+    pub from: String,
+    /// Destination compartment.
+    pub to: String,
+    /// Per capita rate of the flow, as an expression in the parameters.
+    pub rate: String,
+}
+
+/// A model parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterSchema {
+    /// Name of the parameter, as the model's field.
+    pub name: String,
+    /// What the parameter means.
+    pub description: String,
+    /// Smallest allowed value.
+    pub lower: f64,
+    /// Largest allowed value, possibly infinite.
+    pub upper: f64,
+    /// Units of the parameter.
+    pub units: String,
+}
+
+/// Description of a model's structure and parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSchema {
+    /// Name of the model.
+    pub name: String,
+    /// What the model is.
+    pub description: String,
+    /// Compartment names, in state order.
+    pub compartments: Vec<String>,
+    /// Flows between compartments.
+    pub flows: Vec<FlowSchema>,
+    /// Parameters, in `configure` argument order followed by any set by
+    /// other methods.
+    pub parameters: Vec<ParameterSchema>,
+}
+
+impl ModelSchema {
+    /// An empty schema.
+    pub fn new(name: &str, description: &str) -> Self {
+        return Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            compartments: Vec::new(),
+            flows: Vec::new(),
+            parameters: Vec::new(),
+        };
+    }
+
+    /// Add a compartment.
+    pub fn compartment(&mut self, name: &str) -> &mut Self {
+        self.compartments.push(name.to_string());
+        return self;
+    }
+
+    /// Add a flow from one compartment to another at `rate`.
+    pub fn flow(&mut self, from: &str, to: &str, rate: &str) -> &mut Self {
+        self.flows.push(FlowSchema {
+            from: from.to_string(),
+            to: to.to_string(),
+            rate: rate.to_string(),
+        });
+        return self;
+    }
+
+    /// Add a parameter.
+    pub fn parameter(
+        &mut self,
+        name: &str,
+        description: &str,
+        lower: f64,
+        upper: f64,
+        units: &str,
+    ) -> &mut Self {
+        self.parameters.push(ParameterSchema {
+            name: name.to_string(),
+            description: description.to_string(),
+            lower,
+            upper,
+            units: units.to_string(),
+        });
+        return self;
+    }
+
+    /// Check `values` name exactly the schema's parameters, each within its
+    /// bounds.
+    pub fn validate(&self, values: &Parameters) -> Result<(), Error> {
+        let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+        for name in values.keys() {
+            if !self.parameters.iter().any(|p| &p.name == name) {
+                return Err(invalid(format!("unknown parameter '{}'", name)));
+            }
+        }
+        for parameter in self.parameters.iter() {
+            let value = values
+                .get(&parameter.name)
+                .ok_or_else(|| invalid(format!("missing parameter '{}'", parameter.name)))?;
+            if !((parameter.lower <= *value) & (*value <= parameter.upper)) {
+                return Err(invalid(format!(
+                    "{} must be in [{}, {}] got {}",
+                    parameter.name, parameter.lower, parameter.upper, value
+                )));
+            }
+        }
+        return Ok(());
+    }
+
+    /// The schema as a JSON object. Infinite bounds are written as `null`.
+    pub fn to_json(&self) -> String {
+        let compartments: Vec<String> = self.compartments.iter().map(|c| json_string(c)).collect();
+        let flows: Vec<String> = self
+            .flows
+            .iter()
+            .map(|f| {
+                format!(
+                    "{{\"from\":{},\"to\":{},\"rate\":{}}}",
+                    json_string(&f.from),
+                    json_string(&f.to),
+                    json_string(&f.rate)
+                )
+            })
+            .collect();
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|p| {
+                format!(
+                    "{{\"name\":{},\"description\":{},\"lower\":{},\"upper\":{},\"units\":{}}}",
+                    json_string(&p.name),
+                    json_string(&p.description),
+                    json_number(p.lower),
+                    json_number(p.upper),
+                    json_string(&p.units)
+                )
+            })
+            .collect();
+        return format!(
+            "{{\"name\":{},\"description\":{},\"compartments\":[{}],\"flows\":[{}],\"parameters\":[{}]}}",
+            json_string(&self.name),
+            json_string(&self.description),
+            compartments.join(","),
+            flows.join(","),
+            parameters.join(",")
+        );
+    }
+}
+
+/// `s` as a quoted JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    return quoted;
+}
+
+/// `x` as a JSON number, or `null` if it is not finite.
+pub(crate) fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        return format!("{}", x);
+    }
+    return "null".to_string();
+}
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Members in the order written.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Value of member `key` of an object, `None` if missing or not an
+    /// object.
+    pub(crate) fn get(&self, key: &str) -> Option<&JsonValue> {
+        return match self {
+            JsonValue::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        };
+    }
+
+    /// The value as a number, `null` being NaN.
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        return match self {
+            JsonValue::Number(x) => Some(*x),
+            JsonValue::Null => Some(f64::NAN),
+            _ => None,
+        };
+    }
+}
+
+/// Deepest nesting of arrays and objects accepted by [`parse_json`], which
+/// recurses once per level.
+const MAX_DEPTH: usize = 128;
+
+/// Parse a JSON document, the inverse of the writers above.
+pub(crate) fn parse_json(text: &str) -> Result<JsonValue, Error> {
+    let mut parser = JsonParser {
+        chars: text.chars().collect(),
+        at: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.at < parser.chars.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    return Ok(value);
+}
+
+/// Recursive descent JSON parser over the characters of a document.
+struct JsonParser {
+    chars: Vec<char>,
+    at: usize,
+    /// Arrays and objects open at the current character.
+    depth: usize,
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> Error {
+        return Error::new(
+            ErrorKind::InvalidData,
+            format!("bad json at character {}: {}", self.at, message),
+        );
+    }
+
+    fn skip_whitespace(&mut self) {
+        while (self.at < self.chars.len()) && self.chars[self.at].is_whitespace() {
+            self.at += 1;
+        }
+    }
+
+    /// Consume `expected` after any whitespace.
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        if self.chars.get(self.at) != Some(&expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.at += 1;
+        return Ok(());
+    }
+
+    /// Consume the literal `word`, returning `value`.
+    fn literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, Error> {
+        for c in word.chars() {
+            if self.chars.get(self.at) != Some(&c) {
+                return Err(self.error(&format!("expected '{}'", word)));
+            }
+            self.at += 1;
+        }
+        return Ok(value);
+    }
+
+    fn value(&mut self) -> Result<JsonValue, Error> {
+        self.skip_whitespace();
+        return match self.chars.get(self.at) {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(JsonValue::String(self.string()?)),
+            Some('t') => self.literal("true", JsonValue::Bool(true)),
+            Some('f') => self.literal("false", JsonValue::Bool(false)),
+            Some('n') => self.literal("null", JsonValue::Null),
+            Some(_) => self.number(),
+            None => Err(self.error("unexpected end")),
+        };
+    }
+
+    /// Parse an array or object by `parse`, one level deeper.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<JsonValue, Error>,
+    ) -> Result<JsonValue, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested deeper than {}", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        return value;
+    }
+
+    fn object(&mut self) -> Result<JsonValue, Error> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.at) == Some(&'}') {
+            self.at += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.get(self.at) {
+                Some(',') => self.at += 1,
+                Some('}') => {
+                    self.at += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, Error> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.at) == Some(&']') {
+            self.at += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.get(self.at) {
+                Some(',') => self.at += 1,
+                Some(']') => {
+                    self.at += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        if self.chars.get(self.at) != Some(&'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.at += 1;
+        let mut s = String::new();
+        loop {
+            let c = *self
+                .chars
+                .get(self.at)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.at += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escaped = *self
+                        .chars
+                        .get(self.at)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.at += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => s.push(escaped),
+                        'n' => s.push('\n'),
+                        't' => s.push('\t'),
+                        'r' => s.push('\r'),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.at).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16)
+                                .map_err(|_| self.error("bad unicode escape"))?;
+                            s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                            self.at += 4;
+                        }
+                        _ => return Err(self.error("bad escape")),
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, Error> {
+        let start = self.at;
+        while (self.at < self.chars.len())
+            && (self.chars[self.at].is_ascii_digit() | "+-.eE".contains(self.chars[self.at]))
+        {
+            self.at += 1;
+        }
+        let text: String = self.chars[start..self.at].iter().collect();
+        return text
+            .parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error(&format!("bad value '{}'", text)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::pipeline::Parameters;
+    use crate::sirrs::schema::{JsonValue, ModelSchema, parse_json};
+
+    fn schema() -> ModelSchema {
+        let mut schema = ModelSchema::new("si", "Susceptible \"infected\"");
+        schema
+            .compartment("S")
+            .compartment("I")
+            .flow("S", "I", "beta * I")
+            .parameter("beta", "Transmission rate", 0.0, f64::INFINITY, "1/time");
+        return schema;
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            schema().to_json(),
+            concat!(
+                "{\"name\":\"si\",\"description\":\"Susceptible \\\"infected\\\"\",",
+                "\"compartments\":[\"S\",\"I\"],",
+                "\"flows\":[{\"from\":\"S\",\"to\":\"I\",\"rate\":\"beta * I\"}],",
+                "\"parameters\":[{\"name\":\"beta\",\"description\":\"Transmission rate\",",
+                "\"lower\":0,\"upper\":null,\"units\":\"1/time\"}]}"
+            ),
+            "Bad json"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut values = Parameters::new();
+        assert!(
+            schema().validate(&values).is_err(),
+            "Expected missing error"
+        );
+        values.insert("beta".to_string(), -1.0);
+        assert!(schema().validate(&values).is_err(), "Expected bounds error");
+        values.insert("beta".to_string(), 0.3);
+        assert!(schema().validate(&values).is_ok(), "Expected valid values");
+        values.insert("gamma".to_string(), 0.1);
+        assert!(
+            schema().validate(&values).is_err(),
+            "Expected unknown error"
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let value = parse_json(
+            " {\"name\": \"a \\\"b\\\"\", \"x\": [1, -2.5e-1, null], \"ok\": true, \"o\": {}} ",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            JsonValue::Object(vec![
+                ("name".to_string(), JsonValue::String("a \"b\"".to_string())),
+                (
+                    "x".to_string(),
+                    JsonValue::Array(vec![
+                        JsonValue::Number(1.0),
+                        JsonValue::Number(-0.25),
+                        JsonValue::Null
+                    ])
+                ),
+                ("ok".to_string(), JsonValue::Bool(true)),
+                ("o".to_string(), JsonValue::Object(Vec::new())),
+            ]),
+            "Bad parsed value, got {:?}",
+            value
+        );
+        assert_eq!(
+            parse_json(&schema().to_json()).unwrap().get("name"),
+            Some(&JsonValue::String("si".to_string())),
+            "Expected the written schema to parse"
+        );
+        for bad in ["{\"a\": 1,}", "[1 2]", "{\"a\" 1}", "\"open", "1 2", "nul"] {
+            assert!(parse_json(bad).is_err(), "Expected error parsing {}", bad);
+        }
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(
+            parse_json(&nested(128)).is_ok(),
+            "Expected 128 levels to parse"
+        );
+        assert!(
+            parse_json(&nested(200_000)).is_err(),
+            "Expected an error for deep nesting, not a stack overflow"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Stochastic differential equation SIR model with demographic noise.
+//!
+//! The diffusion approximation to the stochastic SIR process in a population
+//! of size N. Each flow carries Gaussian noise with variance equal to its
+//! rate divided by N:
+//!
+//! ```text
+//! dS = -β S I dt - sqrt(β S I / N) dW₁
+//! dI = (β S I - γ I) dt + sqrt(β S I / N) dW₁ - sqrt(γ I / N) dW₂
+//! dR = γ I dt + sqrt(γ I / N) dW₂
+//! ```
+//!
+//! so noise vanishes as N grows, bridging the ODE and fully stochastic
+//! simulations. Solved by the Euler–Maruyama method, with flows clamped so no
+//! compartment goes negative. Many replicates are run at once, one column of
+//! each output per replicate.
+use crate::sirrs::rng;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+use rand_distr::{Distribution, StandardNormal};
+
+/// Create and run a stochastic differential equation SIR model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Total population size, which sets the noise scale.
+    pub population: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Transition rate from S into I. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Number of independent replicates.
+    pub replicates: usize,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Susceptible population fraction of each replicate (column) at each index (row).
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction of each replicate (column) at each index (row).
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction of each replicate (column) at each index (row).
+    pub r_popf: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            population: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            replicates: 0,
+            seed: 0,
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        replicates: usize,
+        seed: u64,
+    ) -> &mut Self {
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        self.length = length;
+        self.step_size = step_size;
+        self.population = population;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.replicates = replicates;
+        self.seed = seed;
+        self.s_popf = Mat::zeros(n_steps, replicates);
+        self.i_popf = Mat::zeros(n_steps, replicates);
+        self.r_popf = Mat::zeros(n_steps, replicates);
+        return self;
+    }
+
+    /// Initialize population fractions. Sets the 0th index of every
+    /// replicate equal to the initial population fractions.
+    pub fn init_popf(&mut self) -> &mut Model {
+        for j in 0..self.replicates {
+            self.s_popf[(0, j)] = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+            self.i_popf[(0, j)] = self.i_popf_init;
+            self.r_popf[(0, j)] = 0.0;
+        }
+        return self;
+    }
+
+    /// Solve the system by the Euler–Maruyama method.
+    pub fn run_euler_maruyama(&mut self) -> &Model {
+        let h = self.step_size;
+        let n = ((self.length as f64) / h).ceil() as usize;
+        let mut rng = rng::rng(self.seed);
+        for j in 0..self.replicates {
+            for t in 0..n - 1 {
+                let s = self.s_popf[(t, j)];
+                let i = self.i_popf[(t, j)];
+                let infection_rate = self.incidence_rate * s * i;
+                let removal_rate = self.removal_rate * i;
+                let dw1 = Distribution::<f64>::sample(&StandardNormal, &mut rng) * h.sqrt();
+                let dw2 = Distribution::<f64>::sample(&StandardNormal, &mut rng) * h.sqrt();
+                let infections = ((infection_rate * h)
+                    + ((infection_rate / self.population).sqrt() * dw1))
+                    .clamp(0.0, s);
+                let removals = ((removal_rate * h)
+                    + ((removal_rate / self.population).sqrt() * dw2))
+                    .clamp(0.0, i + infections);
+                self.s_popf[(t + 1, j)] = s - infections;
+                self.i_popf[(t + 1, j)] = i + infections - removals;
+                self.r_popf[(t + 1, j)] = self.r_popf[(t, j)] + removals;
+            }
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sde::Model;
+    use crate::sirrs::units::Rate;
+
+    #[test]
+    fn test_run_conserves_population() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            1000.0,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            20,
+            1,
+        );
+        model.init_popf();
+        model.run_euler_maruyama();
+        for j in 0..20 {
+            for t in 0..model.s_popf.nrows() {
+                let total = model.s_popf[(t, j)] + model.i_popf[(t, j)] + model.r_popf[(t, j)];
+                assert!(
+                    (total - 1.0).abs() < 1e-12,
+                    "Population not conserved in replicate {} index {}, got {}",
+                    j,
+                    t,
+                    total
+                );
+                assert!(
+                    model.i_popf[(t, j)] >= 0.0,
+                    "Negative i_popf in replicate {} index {}",
+                    j,
+                    t
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_population_approaches_ode() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.1,
+            1e12,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            1,
+            2,
+        );
+        model.init_popf();
+        model.run_euler_maruyama();
+        let mut sir = crate::sirrs::sir::Model::new();
+        sir.configure(
+            60,
+            0.1,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_euler();
+        for t in 0..600 {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-4,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_small_population_varies() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.5,
+            100.0,
+            0.05,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            2,
+            3,
+        );
+        model.init_popf();
+        model.run_euler_maruyama();
+        let last = model.r_popf.nrows() - 1;
+        assert_ne!(
+            model.r_popf[(last, 0)],
+            model.r_popf[(last, 1)],
+            "Expected replicates to differ in a small population"
+        );
+    }
+}
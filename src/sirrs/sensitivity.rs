@@ -0,0 +1,327 @@
+//! Global sensitivity analysis by Sobol indices.
+//!
+//! Parameters are sampled uniformly over given ranges with Saltelli's
+//! scheme built on a Sobol low-discrepancy sequence. The model runs once
+//! per sample, spread over several threads, and returns one value per
+//! outcome metric of interest. [`sobol_indices`] then estimates, for each
+//! parameter and outcome, the first-order index (the share of output
+//! variance explained by the parameter alone) and the total-effect index
+//! (the share including all its interactions), following Saltelli et al.
+//! (2010) and Jansen (1999).
+use crate::sirrs::reproducible;
+use faer::Mat;
+use std::sync::Arc;
+use std::thread;
+
+/// Primitive polynomial degree, coefficients and initial direction numbers
+/// for dimensions 2 onward, from Joe and Kuo (2008). Dimension 1 is the
+/// van der Corput sequence.
+const DIRECTIONS: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// Bits of precision of the Sobol sequence.
+const BITS: usize = 32;
+
+/// Direction numbers of one dimension, scaled to `BITS` bits.
+fn direction_numbers(dim: usize) -> Vec<u32> {
+    let mut v = vec![0u32; BITS];
+    if dim == 0 {
+        for k in 0..BITS {
+            v[k] = 1 << (BITS - 1 - k);
+        }
+        return v;
+    }
+    let (s, a, m) = DIRECTIONS[dim - 1];
+    let s = s as usize;
+    for k in 0..BITS {
+        if k < s {
+            v[k] = m[k] << (BITS - 1 - k);
+        } else {
+            v[k] = v[k - s] ^ (v[k - s] >> s);
+            for l in 1..s {
+                if ((a >> (s - 1 - l)) & 1) == 1 {
+                    v[k] ^= v[k - l];
+                }
+            }
+        }
+    }
+    return v;
+}
+
+/// The first `n` points of the `dims`-dimensional Sobol sequence in
+/// `[0, 1)`, one point per row, skipping the initial point at the origin.
+pub fn sobol_points(n: usize, dims: usize) -> Mat<f64> {
+    assert!(
+        dims <= DIRECTIONS.len() + 1,
+        "at most {} dimensions are supported",
+        DIRECTIONS.len() + 1
+    );
+    let directions: Vec<Vec<u32>> = (0..dims).map(direction_numbers).collect();
+    let scale = 2f64.powi(BITS as i32);
+    let mut x = vec![0u32; dims];
+    let mut points = Mat::zeros(n, dims);
+    for i in 0..n {
+        // Gray code ordering: point i + 1 differs from point i in the
+        // direction number of the lowest zero bit of i.
+        let c = (!i).trailing_zeros() as usize;
+        for j in 0..dims {
+            x[j] ^= directions[j][c];
+            points[(i, j)] = (x[j] as f64) / scale;
+        }
+    }
+    return points;
+}
+
+/// A parameter sampled uniformly between `lower` and `upper`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    /// Name of the parameter.
+    pub name: String,
+    /// Smallest value.
+    pub lower: f64,
+    /// Largest value.
+    pub upper: f64,
+}
+
+impl Range {
+    /// Create a new parameter range.
+    pub fn new(name: &str, lower: f64, upper: f64) -> Self {
+        assert!(upper > lower, "upper must be greater than lower");
+        return Self {
+            name: name.to_string(),
+            lower,
+            upper,
+        };
+    }
+}
+
+/// Saltelli's base sample matrices `A` and `B`, each `n` parameter sets
+/// (rows) of every parameter (column), from the first and second halves of
+/// a Sobol sequence with twice as many dimensions as parameters.
+pub fn saltelli_samples(ranges: &[Range], n: usize) -> (Mat<f64>, Mat<f64>) {
+    let d = ranges.len();
+    let points = sobol_points(n, 2 * d);
+    let value = |u: f64, range: &Range| range.lower + (u * (range.upper - range.lower));
+    let a = Mat::from_fn(n, d, |i, j| value(points[(i, j)], &ranges[j]));
+    let b = Mat::from_fn(n, d, |i, j| value(points[(i, d + j)], &ranges[j]));
+    return (a, b);
+}
+
+/// Run `model` on every row of `inputs` using `n_threads` threads,
+/// returning each run's outcomes (columns) in row order. Strict
+/// reproducibility mode uses a single thread.
+pub fn run_parallel<F>(model: Arc<F>, inputs: &Mat<f64>, n_threads: usize) -> Mat<f64>
+where
+    F: Fn(&[f64]) -> Vec<f64> + Send + Sync + 'static,
+{
+    assert!(n_threads >= 1, "n_threads must be at least 1");
+    let n = inputs.nrows();
+    let chunk = n.div_ceil(reproducible::threads(n_threads)).max(1);
+    let handles: Vec<thread::JoinHandle<Vec<Vec<f64>>>> = (0..n)
+        .step_by(chunk)
+        .map(|start| {
+            let model = Arc::clone(&model);
+            let rows: Vec<Vec<f64>> = (start..(start + chunk).min(n))
+                .map(|i| (0..inputs.ncols()).map(|j| inputs[(i, j)]).collect())
+                .collect();
+            thread::spawn(move || {
+                return rows.iter().map(|x| model(x)).collect();
+            })
+        })
+        .collect();
+    let outputs: Vec<Vec<f64>> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().expect("model thread panicked"))
+        .collect();
+    let n_outputs = outputs.first().map_or(0, |y| y.len());
+    return Mat::from_fn(n, n_outputs, |i, k| outputs[i][k]);
+}
+
+/// First-order and total-effect Sobol indices.
+pub struct SobolIndices {
+    /// Parameter names, in row order.
+    pub names: Vec<String>,
+    /// First-order index of each parameter (row) for each outcome (column).
+    pub first_order: Mat<f64>,
+    /// Total-effect index of each parameter (row) for each outcome
+    /// (column).
+    pub total_effect: Mat<f64>,
+}
+
+/// Estimate Sobol indices of every outcome returned by `model` with
+/// respect to every parameter in `ranges`.
+///
+/// `model` takes one value per range, in order, and returns one value per
+/// outcome metric. It is run `n * (ranges.len() + 2)` times over
+/// `n_threads` threads. Results do not depend on the number of threads.
+pub fn sobol_indices<F>(model: Arc<F>, ranges: &[Range], n: usize, n_threads: usize) -> SobolIndices
+where
+    F: Fn(&[f64]) -> Vec<f64> + Send + Sync + 'static,
+{
+    let d = ranges.len();
+    let (a, b) = saltelli_samples(ranges, n);
+    // Rows: A, then B, then A with column i taken from B for each i.
+    let inputs = Mat::from_fn(n * (d + 2), d, |row, j| {
+        let (block, i) = (row / n, row % n);
+        return match block {
+            0 => a[(i, j)],
+            1 => b[(i, j)],
+            _ if j == block - 2 => b[(i, j)],
+            _ => a[(i, j)],
+        };
+    });
+    let outputs = run_parallel(model, &inputs, n_threads);
+    let n_outputs = outputs.ncols();
+    let mut first_order = Mat::zeros(d, n_outputs);
+    let mut total_effect = Mat::zeros(d, n_outputs);
+    for k in 0..n_outputs {
+        let f_a = |i: usize| outputs[(i, k)];
+        let f_b = |i: usize| outputs[(n + i, k)];
+        let mean = (0..n).map(|i| f_a(i) + f_b(i)).sum::<f64>() / ((2 * n) as f64);
+        let variance = (0..n)
+            .map(|i| (f_a(i) - mean).powi(2) + (f_b(i) - mean).powi(2))
+            .sum::<f64>()
+            / ((2 * n) as f64);
+        for j in 0..d {
+            let f_ab = |i: usize| outputs[(((j + 2) * n) + i, k)];
+            let first = (0..n).map(|i| f_b(i) * (f_ab(i) - f_a(i))).sum::<f64>() / (n as f64);
+            let total = (0..n).map(|i| (f_a(i) - f_ab(i)).powi(2)).sum::<f64>() / (2 * n) as f64;
+            first_order[(j, k)] = first / variance;
+            total_effect[(j, k)] = total / variance;
+        }
+    }
+    return SobolIndices {
+        names: ranges.iter().map(|r| r.name.clone()).collect(),
+        first_order,
+        total_effect,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sensitivity::{Range, sobol_indices, sobol_points};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sobol_points() {
+        let points = sobol_points(7, 2);
+        let expected: [[f64; 2]; 7] = [
+            [0.5, 0.5],
+            [0.75, 0.25],
+            [0.25, 0.75],
+            [0.375, 0.375],
+            [0.875, 0.875],
+            [0.625, 0.125],
+            [0.125, 0.625],
+        ];
+        for (i, row) in expected.iter().enumerate() {
+            for j in 0..2 {
+                assert_eq!(
+                    points[(i, j)],
+                    row[j],
+                    "Bad point {} dimension {}, expected {} got {}",
+                    i,
+                    j,
+                    row[j],
+                    points[(i, j)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ishigami() {
+        let model = Arc::new(|x: &[f64]| -> Vec<f64> {
+            return vec![
+                x[0].sin() + (7.0 * x[1].sin().powi(2)) + (0.1 * x[2].powi(4) * x[0].sin()),
+            ];
+        });
+        let ranges = [
+            Range::new("x1", -PI, PI),
+            Range::new("x2", -PI, PI),
+            Range::new("x3", -PI, PI),
+        ];
+        let indices = sobol_indices(model, &ranges, 1 << 14, 4);
+        let expected_first = [0.3139, 0.4424, 0.0];
+        let expected_total = [0.5576, 0.4424, 0.2437];
+        for j in 0..3 {
+            assert!(
+                (indices.first_order[(j, 0)] - expected_first[j]).abs() < 0.02,
+                "Bad first-order index of {}, expected {} got {}",
+                indices.names[j],
+                expected_first[j],
+                indices.first_order[(j, 0)]
+            );
+            assert!(
+                (indices.total_effect[(j, 0)] - expected_total[j]).abs() < 0.02,
+                "Bad total-effect index of {}, expected {} got {}",
+                indices.names[j],
+                expected_total[j],
+                indices.total_effect[(j, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sir_final_size() {
+        let model = Arc::new(|x: &[f64]| -> Vec<f64> {
+            let mut model = sir::Model::new();
+            model.configure(
+                200,
+                1.0,
+                0.001,
+                0.0,
+                Rate::per_day(x[0]),
+                Rate::per_day(x[1]),
+                Rate::per_day(0.0),
+            );
+            model.init_popf();
+            model.run_rk4();
+            return vec![model.r_popf[(199, 0)]];
+        });
+        let ranges = [
+            Range::new("incidence_rate", 0.2, 0.5),
+            Range::new("removal_rate", 0.05, 0.15),
+            Range::new("unused", 0.0, 1.0),
+        ];
+        let serial = sobol_indices(Arc::clone(&model), &ranges, 256, 1);
+        let parallel = sobol_indices(model, &ranges, 256, 3);
+        assert_eq!(
+            serial.total_effect, parallel.total_effect,
+            "Expected results independent of the number of threads"
+        );
+        assert!(
+            parallel.total_effect[(2, 0)].abs() < 1e-12,
+            "Expected no effect of unused parameter, got {}",
+            parallel.total_effect[(2, 0)]
+        );
+        assert!(
+            parallel.first_order[(0, 0)] > 0.1,
+            "Expected incidence rate to matter, got {}",
+            parallel.first_order[(0, 0)]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! JSON over HTTP simulation service.
+//!
+//! The `sirrs-serve` binary, built with the `serve` feature, answers HTTP
+//! requests so web front ends and other languages can run the solver
+//! without bindings:
+//!  - `GET /health`, `{"status":"ok"}`
+//!  - `GET /schema`, the SIR model's [`crate::schema::ModelSchema`]
+//!  - `POST /simulate`, a [`NamedConfig`] JSON object in, the run's
+//!    [`crate::metadata::SimulationResult::to_json`] out. The solver is
+//!    chosen by a `solver` query parameter, `euler`, `heun`, `midpoint`,
+//!    `patankar` or `rk4` (the default), for example
+//!    `/simulate?solver=heun`.
+//!
+//! Configurations are checked against the model schema before anything
+//! runs, and errors are returned as `{"error": "..."}` with status 400.
+//! Requests are bounded so that a client cannot exhaust the server: bodies
+//! by [`MAX_BODY`], lines and headers by [`MAX_LINE`] and [`MAX_HEADERS`],
+//! runs by [`MAX_STEPS`], and the binary answers at most
+//! [`MAX_CONNECTIONS`] connections at once, each waiting at most
+//! [`READ_TIMEOUT`] for the client.
+//! Request handling is independent of sockets, see [`respond`] and
+//! [`handle_connection`], so it is used and tested without a network.
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::scenarios::NamedConfig;
+use crate::sirrs::schema::json_string;
+use crate::sirrs::sir;
+use std::io::{self, BufRead, Read, Write};
+use std::time::Duration;
+
+/// Largest accepted request body, in bytes.
+pub const MAX_BODY: usize = 1 << 20;
+
+/// Largest accepted number of steps of a simulation.
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// Longest accepted request or header line, in bytes.
+pub const MAX_LINE: usize = 8 << 10;
+
+/// Most accepted header lines of a request.
+pub const MAX_HEADERS: usize = 100;
+
+/// Most connections `sirrs-serve` answers at once.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// Longest wait for a client to send the next part of its request.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP response with a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// HTTP status code.
+    pub status: u16,
+    /// JSON body.
+    pub body: String,
+}
+
+impl Response {
+    fn ok(body: String) -> Self {
+        return Self { status: 200, body };
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        return Self {
+            status,
+            body: format!("{{\"error\":{}}}", json_string(message)),
+        };
+    }
+
+    /// Reason phrase of the status code.
+    pub fn reason(&self) -> &'static str {
+        return match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        };
+    }
+}
+
+/// Respond to a request for `target`, a path with an optional query, with
+/// request body `body`.
+pub fn respond(method: &str, target: &str, body: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let allowed = match path {
+        "/health" | "/schema" => "GET",
+        "/simulate" => "POST",
+        _ => return Response::error(404, &format!("no endpoint {}", path)),
+    };
+    if method != allowed {
+        return Response::error(405, &format!("{} expects {}", path, allowed));
+    }
+    return match path {
+        "/health" => Response::ok("{\"status\":\"ok\"}".to_string()),
+        "/schema" => Response::ok(sir::Model::schema().to_json()),
+        _ => simulate(query, body),
+    };
+}
+
+/// Run the configuration in `body` by the solver named in `query`.
+fn simulate(query: &str, body: &str) -> Response {
+    let mut solver = "rk4";
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some(("solver", value)) => solver = value,
+            _ => return Response::error(400, &format!("unknown query parameter '{}'", pair)),
+        }
+    }
+    let config = match NamedConfig::from_json(body) {
+        Ok(config) => config,
+        Err(error) => return Response::error(400, &error.to_string()),
+    };
+    let parameters = Parameters::from([
+        ("length".to_string(), config.length as f64),
+        ("step_size".to_string(), config.step_size),
+        ("i_popf_init".to_string(), config.i_popf_init),
+        ("r_popf_init".to_string(), config.r_popf_init),
+        ("incidence_rate".to_string(), config.incidence_rate),
+        ("removal_rate".to_string(), config.removal_rate),
+        ("recovery_rate".to_string(), config.recovery_rate),
+    ]);
+    if let Err(error) = sir::Model::schema().validate(&parameters) {
+        return Response::error(400, &error.to_string());
+    }
+    if (config.length as f64) / config.step_size > (MAX_STEPS as f64) {
+        return Response::error(400, &format!("more than {} steps", MAX_STEPS));
+    }
+    let mut model = config.model();
+    match solver {
+        "euler" => model.run_euler(),
+        "heun" => model.run_heun(),
+        "midpoint" => model.run_midpoint(),
+        "patankar" => model.run_patankar(),
+        "rk4" => model.run_rk4(),
+        other => return Response::error(400, &format!("unknown solver '{}'", other)),
+    };
+    return Response::ok(model.result(solver).to_json());
+}
+
+/// Read one HTTP/1.1 request from `reader`, respond to it, and write the
+/// response to `writer`. The connection is not kept alive.
+pub fn handle_connection(mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+    let response = match read_request(&mut reader) {
+        Ok((method, target, body)) => respond(&method, &target, &body),
+        Err(response) => response,
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.body.len(),
+        response.body
+    )?;
+    return writer.flush();
+}
+
+/// Read a line of at most [`MAX_LINE`] bytes from `reader` into `line`.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<(), Response> {
+    line.clear();
+    reader
+        .take((MAX_LINE + 1) as u64)
+        .read_line(line)
+        .map_err(|_| Response::error(400, "unreadable request"))?;
+    if line.len() > MAX_LINE {
+        return Err(Response::error(
+            431,
+            &format!("line longer than {} bytes", MAX_LINE),
+        ));
+    }
+    return Ok(());
+}
+
+/// Method, target and body of a request, or the response to a malformed
+/// one.
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, String), Response> {
+    let bad = |message: &str| Response::error(400, message);
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(bad("bad request line")),
+    };
+    let mut content_length = 0;
+    for n_headers in 0.. {
+        read_line(reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if n_headers == MAX_HEADERS {
+            return Err(Response::error(
+                431,
+                &format!("more than {} headers", MAX_HEADERS),
+            ));
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| bad("bad Content-Length"))?;
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(Response::error(
+            413,
+            &format!("body larger than {} bytes", MAX_BODY),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad("body shorter than Content-Length"))?;
+    let body = String::from_utf8(body).map_err(|_| bad("body is not utf-8"))?;
+    return Ok((method, target, body));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::serve::{MAX_HEADERS, MAX_LINE, handle_connection, respond};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+
+    const CONFIG: &str = r#"{"length": 10, "step_size": 0.5, "i_popf_init": 0.01, "incidence_rate": 0.4, "removal_rate": 0.1}"#;
+
+    #[test]
+    fn test_respond() {
+        let response = respond("POST", "/simulate?solver=heun", CONFIG);
+        assert_eq!(response.status, 200, "Bad status, got {:?}", response);
+        let mut model = sir::Model::new();
+        model.configure(
+            10,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_heun();
+        let expected = format!("\"i\":[0.01,{},", model.i_popf[(1, 0)]);
+        assert!(
+            response.body.contains(&expected) & response.body.contains("\"solver\":\"heun\""),
+            "Bad body, expected {} in {}",
+            expected,
+            response.body
+        );
+        for (method, target, body, status) in [
+            ("GET", "/health", "", 200),
+            ("GET", "/schema", "", 200),
+            ("GET", "/simulate", "", 405),
+            ("GET", "/missing", "", 404),
+            ("POST", "/simulate?solver=magic", CONFIG, 400),
+            ("POST", "/simulate", "{\"length\": 10}", 400),
+            ("POST", "/simulate", &CONFIG.replace("0.01", "1.5"), 400),
+            ("POST", "/simulate", &"[".repeat(200_000), 400),
+        ] {
+            let response = respond(method, target, body);
+            assert_eq!(
+                response.status, status,
+                "Bad status for {} {}, got {:?}",
+                method, target, response
+            );
+        }
+    }
+
+    #[test]
+    fn test_handle_connection() {
+        let request = format!(
+            "POST /simulate HTTP/1.1\r\nHost: localhost\r\ncontent-length: {}\r\n\r\n{}",
+            CONFIG.len(),
+            CONFIG
+        );
+        let mut written = Vec::new();
+        handle_connection(request.as_bytes(), &mut written).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        let (head, body) = text.split_once("\r\n\r\n").unwrap();
+        assert!(
+            head.starts_with("HTTP/1.1 200 OK\r\n")
+                & head.contains(&format!("Content-Length: {}", body.len())),
+            "Bad response head, got {}",
+            head
+        );
+        assert!(
+            body.starts_with("{\"metadata\":"),
+            "Bad response body, got {}",
+            body
+        );
+        let mut written = Vec::new();
+        handle_connection("garbage\r\n\r\n".as_bytes(), &mut written).unwrap();
+        assert!(
+            String::from_utf8(written)
+                .unwrap()
+                .starts_with("HTTP/1.1 400 Bad Request"),
+            "Expected a malformed request to be rejected"
+        );
+        let long = format!(
+            "GET /health HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_LINE)
+        );
+        let many = format!(
+            "GET /health HTTP/1.1\r\n{}\r\n",
+            "X: a\r\n".repeat(MAX_HEADERS + 1)
+        );
+        for request in [long, many] {
+            let mut written = Vec::new();
+            handle_connection(request.as_bytes(), &mut written).unwrap();
+            let text = String::from_utf8(written).unwrap();
+            assert!(
+                text.starts_with("HTTP/1.1 431 Request Header Fields Too Large"),
+                "Expected oversized headers to be rejected, got {}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Output sinks written to by solvers at every step.
+//!
+//! A solver announces its column names once, then hands each step's time
+//! and values to an [`OutputSink`], so new output formats are added by
+//! implementing the trait rather than by touching integrator code. Provided
+//! sinks:
+//!  - [`MemorySink`], collecting steps into a matrix
+//!  - [`CsvSink`], writing CSV to any writer: a file, a buffer, or a network
+//!    socket such as [`std::net::TcpStream`]
+//!  - [`Downsample`], passing every n-th step on to another sink
+//!  - `ArrowSink`, building an Arrow record batch, with the `arrow` feature
+//!
+//! Every solver of [`crate::sir::Model`] writes to a sink through
+//! [`crate::sir::Model::run_into`], and the other model systems through
+//! [`crate::pipeline::Pipeline::run_rk4_into`]. Both pass a [`RunMetadata`]
+//! before starting, which the provided sinks keep or write out.
+use crate::sirrs::metadata::RunMetadata;
+use faer::Mat;
+use std::io::{self, Write};
+
+/// Destination for solver output.
+pub trait OutputSink {
+    /// Called at most once, before [`OutputSink::start`], with metadata of
+    /// the run. Ignored by default.
+    fn metadata(&mut self, _metadata: &RunMetadata) -> io::Result<()> {
+        return Ok(());
+    }
+    /// Called once before the first step with the name of each value.
+    fn start(&mut self, names: &[String]) -> io::Result<()>;
+    /// Called once per step with the time and one value per name.
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()>;
+    /// Called once after the last step.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Collects steps in memory.
+pub struct MemorySink {
+    /// Name of each value.
+    pub names: Vec<String>,
+    /// Time of each step.
+    pub times: Vec<f64>,
+    /// Values of each step, one row per step, flattened row by row.
+    values: Vec<f64>,
+    /// Metadata of the run, if the solver gave any.
+    pub metadata: Option<RunMetadata>,
+}
+
+impl MemorySink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        return Self {
+            names: Vec::new(),
+            times: Vec::new(),
+            values: Vec::new(),
+            metadata: None,
+        };
+    }
+
+    /// Values of each name (column) at each step (row).
+    pub fn to_mat(&self) -> Mat<f64> {
+        let n = self.names.len();
+        return Mat::from_fn(self.times.len(), n, |t, j| self.values[(t * n) + j]);
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn metadata(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        self.metadata = Some(metadata.clone());
+        return Ok(());
+    }
+
+    fn start(&mut self, names: &[String]) -> io::Result<()> {
+        self.names = names.to_vec();
+        self.times.clear();
+        self.values.clear();
+        return Ok(());
+    }
+
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()> {
+        self.times.push(t);
+        self.values.extend_from_slice(values);
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// Writes steps as CSV rows, `t` first, with a header row preceded by any
+/// metadata as `# key: value` lines.
+pub struct CsvSink<W: Write> {
+    /// Destination of the CSV text.
+    pub writer: W,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Create a sink writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        return Self { writer };
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn metadata(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        return metadata.write_comments(&mut self.writer);
+    }
+
+    fn start(&mut self, names: &[String]) -> io::Result<()> {
+        return writeln!(self.writer, "t,{}", names.join(","));
+    }
+
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()> {
+        write!(self.writer, "{}", t)?;
+        for value in values.iter() {
+            write!(self.writer, ",{}", value)?;
+        }
+        return writeln!(self.writer);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        return self.writer.flush();
+    }
+}
+
+/// Passes the first step and every `every`-th step after it on to another
+/// sink.
+pub struct Downsample<S: OutputSink> {
+    /// Sink receiving the kept steps.
+    pub inner: S,
+    /// Keep one step in this many.
+    pub every: usize,
+    /// Steps seen so far.
+    seen: usize,
+}
+
+impl<S: OutputSink> Downsample<S> {
+    /// Keep one step in `every` and write it to `inner`.
+    pub fn new(inner: S, every: usize) -> Self {
+        assert!(every >= 1, "every must be at least 1");
+        return Self {
+            inner,
+            every,
+            seen: 0,
+        };
+    }
+}
+
+impl<S: OutputSink> OutputSink for Downsample<S> {
+    fn metadata(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        return self.inner.metadata(metadata);
+    }
+
+    fn start(&mut self, names: &[String]) -> io::Result<()> {
+        self.seen = 0;
+        return self.inner.start(names);
+    }
+
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()> {
+        let keep = self.seen.is_multiple_of(self.every);
+        self.seen += 1;
+        if keep {
+            return self.inner.write_step(t, values);
+        }
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        return self.inner.finish();
+    }
+}
+
+/// Builds an Arrow record batch with a `t` column and one column per name,
+/// and any metadata as schema metadata.
+#[cfg(feature = "arrow")]
+pub struct ArrowSink {
+    /// Metadata of the run, if the solver gave any.
+    metadata: Option<RunMetadata>,
+    /// Column names, `t` first.
+    names: Vec<String>,
+    /// One builder per column, `t` first.
+    builders: Vec<arrow_array::builder::Float64Builder>,
+    /// The finished batch, available after [`OutputSink::finish`].
+    pub batch: Option<arrow_array::RecordBatch>,
+}
+
+#[cfg(feature = "arrow")]
+impl ArrowSink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        return Self {
+            metadata: None,
+            names: Vec::new(),
+            builders: Vec::new(),
+            batch: None,
+        };
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl OutputSink for ArrowSink {
+    fn metadata(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        self.metadata = Some(metadata.clone());
+        return Ok(());
+    }
+
+    fn start(&mut self, names: &[String]) -> io::Result<()> {
+        self.names = std::iter::once("t".to_string())
+            .chain(names.iter().cloned())
+            .collect();
+        self.builders = self
+            .names
+            .iter()
+            .map(|_| arrow_array::builder::Float64Builder::new())
+            .collect();
+        self.batch = None;
+        return Ok(());
+    }
+
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()> {
+        self.builders[0].append_value(t);
+        for (builder, value) in self.builders[1..].iter_mut().zip(values.iter()) {
+            builder.append_value(*value);
+        }
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        use arrow_schema::{DataType, Field, Schema};
+        use std::sync::Arc;
+        let fields: Vec<Field> = self
+            .names
+            .iter()
+            .map(|name| Field::new(name, DataType::Float64, false))
+            .collect();
+        let columns: Vec<arrow_array::ArrayRef> = self
+            .builders
+            .iter_mut()
+            .map(|builder| Arc::new(builder.finish()) as arrow_array::ArrayRef)
+            .collect();
+        let metadata = self.metadata.as_ref().map_or(Default::default(), |m| {
+            m.pairs()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect()
+        });
+        let schema = Schema::new_with_metadata(fields, metadata);
+        let batch = arrow_array::RecordBatch::try_new(Arc::new(schema), columns)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.batch = Some(batch);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sink::{CsvSink, Downsample, MemorySink, OutputSink};
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use faer::mat;
+    use std::io::{BufReader, Read};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn write_steps(sink: &mut dyn OutputSink) {
+        sink.start(&["s".to_string(), "i".to_string()]).unwrap();
+        for t in 0..5 {
+            let x = t as f64;
+            sink.write_step(x, &[1.0 - (0.1 * x), 0.1 * x]).unwrap();
+        }
+        sink.finish().unwrap();
+    }
+
+    #[test]
+    fn test_memory_sink() {
+        let mut sink = MemorySink::new();
+        write_steps(&mut sink);
+        assert_eq!(sink.times, vec![0.0, 1.0, 2.0, 3.0, 4.0], "Bad times");
+        assert_eq!(
+            sink.to_mat().shape(),
+            (5, 2),
+            "Bad matrix dimensions, got {:?}",
+            sink.to_mat().shape()
+        );
+        assert_eq!(sink.to_mat()[(2, 1)], 0.2, "Bad value");
+    }
+
+    #[test]
+    fn test_csv_sink() {
+        let mut sink = CsvSink::new(Vec::new());
+        write_steps(&mut sink);
+        let text = String::from_utf8(sink.writer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "t,s,i", "Bad header, got {}", lines[0]);
+        assert_eq!(lines[1], "0,1,0", "Bad first row, got {}", lines[1]);
+        assert_eq!(lines.len(), 6, "Bad number of lines, got {}", lines.len());
+    }
+
+    #[test]
+    fn test_csv_sink_over_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut model = sir::Model::new();
+            model.configure(
+                10,
+                1.0,
+                0.01,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            model.init_popf();
+            let mut sink = CsvSink::new(TcpStream::connect(address).unwrap());
+            model.run_into("rk4", &mut sink).unwrap();
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut text = String::new();
+        BufReader::new(stream).read_to_string(&mut text).unwrap();
+        client.join().unwrap();
+        let rows: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(
+            rows[0], "t,s,i,r,incidence,cumulative_incidence",
+            "Bad header, got {}",
+            rows[0]
+        );
+        assert_eq!(rows.len(), 11, "Bad number of rows, got {}", rows.len());
+        assert!(
+            text.lines().any(|line| line == "# solver: rk4"),
+            "Expected solver metadata, got {}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut sink = Downsample::new(MemorySink::new(), 2);
+        write_steps(&mut sink);
+        assert_eq!(sink.inner.times, vec![0.0, 2.0, 4.0], "Bad kept times");
+        assert_eq!(
+            sink.inner.to_mat(),
+            mat![[1.0, 0.0], [0.8, 0.2], [0.6, 0.4]],
+            "Bad kept values"
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_arrow_sink() {
+        use crate::sirrs::sink::ArrowSink;
+        let mut sink = ArrowSink::new();
+        write_steps(&mut sink);
+        let batch = sink.batch.unwrap();
+        assert_eq!(batch.num_rows(), 5, "Bad number of rows");
+        assert_eq!(batch.num_columns(), 3, "Bad number of columns");
+        assert_eq!(batch.schema().field(1).name(), "s", "Bad column name");
+    }
+}
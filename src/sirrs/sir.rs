@@ -4,191 +4,678 @@
 //!  - S → I  
 //!  - I → R  
 //!  - R → S  
-use faer::Mat;
+//!
+//! The S → I rate may change at any number of changepoints, see
+//! [`Model::changepoints`] and [`Model::incidence_rate_schedule`].
+//!
+//! Transmission may be seasonally forced, see [`Model::seasonality`], with
+//! `β(t) = β₀ (1 + a cos(2πt / T + φ))` for the piecewise constant `β₀`.
+//!
+//! Infections may also be imported from outside the population, at a
+//! per-susceptible rate independent of local prevalence, see
+//! [`Model::importation`]. With importation the S → I flux is
+//! `(β(t) I + ι(t)) S`, so an epidemic can be seeded from `I = 0`.
+//!
+//! By default compartments are population fractions. After
+//! [`Model::counts`] they are counts in a population of size N instead,
+//! initial values included, with transmission `β S I / N`, so results need
+//! no conversion to compare with case data.
+//!
+//! Besides prevalence, incidence (the S → I flux) is recorded per step and
+//! cumulatively, for comparison with surveillance case counts.
+//!
+//! Whether a step size is small enough is checked by step doubling, see
+//! [`Model::estimate_error`] and [`Model::refine_step_size`].
+//!
+//! Solvers report progress as `tracing` events inside a `run` span naming
+//! the model and solver: a `debug` event with the state at every step and an
+//! `info` summary when the run finishes. Nothing is printed unless the
+//! application installs a subscriber, as the `sirrs` binary does with
+//! `--log info` and `sirrs-serve` does by default.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::metadata::{RunMetadata, SimulationResult};
+use crate::sirrs::output::Output;
+use crate::sirrs::pipeline::Parameters;
+use crate::sirrs::scalar::{Scalar, from_f64};
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::schema::ModelSchema;
+use crate::sirrs::sink::OutputSink;
+use crate::sirrs::stability::Stability;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::{Mat, c64};
+use std::io;
+
+/// Names of the output columns, in [`Model::result`] and sink order.
+const COLUMNS: [&str; 5] = ["s", "i", "r", "incidence", "cumulative_incidence"];
 
 /// Numerical integrator variables
 ///
 /// This private struct exists to make indexing k and y during integration
 /// simpler.
-struct SystemVars {
-    s: f64,
-    i: f64,
-    r: f64,
+#[derive(Debug, Clone, Copy)]
+struct SystemVars<T> {
+    s: T,
+    i: T,
+    r: T,
+    x: T,
+}
+
+/// State of an SIR model at one index, advanced in place by
+/// [`Model::step_into`]. Holds the solver's working buffers, so stepping
+/// needs no allocation or initialization.
+#[derive(Debug, Clone, Copy)]
+pub struct State<T: Scalar = f64> {
+    /// Index of the state. Its time is `index * step_size`.
+    pub index: usize,
+    /// Susceptible population fraction.
+    pub s: T,
+    /// Infectious population fraction.
+    pub i: T,
+    /// Removed population fraction.
+    pub r: T,
+    /// New infections, as a population fraction, over the step ending at
+    /// `index`.
+    pub incidence: T,
+    /// Cumulative infections, as a population fraction, up to `index`.
+    pub cumulative_incidence: T,
+    y: [SystemVars<T>; 5],
+    k: [SystemVars<T>; 5],
+}
+
+/// Iterator over the states of a model, see [`Model::iter_steps`].
+pub struct Steps<'a, T: Scalar = f64> {
+    model: &'a Model<T>,
+    state: State<T>,
+    n_steps: usize,
+    started: bool,
+}
+
+impl<T: Scalar> Iterator for Steps<'_, T> {
+    type Item = (f64, State<T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            if self.state.index + 1 >= self.n_steps {
+                return None;
+            }
+            self.model.step_into(&mut self.state);
+        } else if self.n_steps == 0 {
+            return None;
+        }
+        self.started = true;
+        let t = self.model.grid().time(self.state.index);
+        return Some((t, self.state));
+    }
+}
+
+/// Stored steps of a run, see [`Model::run_rk4_output`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory<T: Scalar = f64> {
+    /// Time of each stored step.
+    pub times: Vec<f64>,
+    /// Susceptible population fraction at each stored step.
+    pub s_popf: Vec<T>,
+    /// Infectious population fraction at each stored step.
+    pub i_popf: Vec<T>,
+    /// Removed population fraction at each stored step.
+    pub r_popf: Vec<T>,
+    /// Cumulative infections, as a population fraction, up to each stored
+    /// step. Differences give the infections between stored steps.
+    pub cumulative_incidence: Vec<T>,
+}
+
+/// An equilibrium of an SIR model, see [`Model::solve_equilibrium`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equilibrium {
+    /// Whether infection persists, I > 0.
+    pub endemic: bool,
+    /// Susceptible population fraction.
+    pub s: f64,
+    /// Infectious population fraction.
+    pub i: f64,
+    /// Removed population fraction.
+    pub r: f64,
+    /// Eigenvalues of the Jacobian of (S, I, R) at the equilibrium.
+    pub eigenvalues: Vec<c64>,
+    /// Whether the equilibrium is locally stable: no eigenvalue has a
+    /// positive real part. Conservation of the population and any line of
+    /// equilibria each contribute a zero eigenvalue.
+    pub stable: bool,
+}
+
+/// Step-doubling estimate of a solver's global error, see
+/// [`Model::estimate_error`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEstimate {
+    /// Step size the estimate is for.
+    pub step_size: f64,
+    /// Largest estimated error in S over the series.
+    pub s: f64,
+    /// Largest estimated error in I over the series.
+    pub i: f64,
+    /// Largest estimated error in R over the series.
+    pub r: f64,
+}
+
+impl ErrorEstimate {
+    /// Largest estimated error of any compartment.
+    pub fn max(&self) -> f64 {
+        return self.s.max(self.i).max(self.r);
+    }
+}
+
+/// Sinusoidal forcing of transmission, see [`Model::seasonality`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Seasonality {
+    /// Relative amplitude of the forcing. Must be in [0, 1].
+    pub amplitude: f64,
+    /// Phase of the forcing in radians. Transmission peaks where
+    /// `2πt / period + phase` is a multiple of 2π.
+    pub phase: f64,
+    /// Period of the forcing, for example 365 for an annual cycle in days.
+    pub period: f64,
+}
+
+impl Seasonality {
+    /// Factor multiplying the incidence rate at time `t`. At infinite `t`
+    /// it is its average over a period, 1, so long-run analyses such as
+    /// [`Model::solve_equilibrium`] use the mean rate.
+    pub fn factor(&self, t: f64) -> f64 {
+        if !t.is_finite() {
+            return 1.0;
+        }
+        let angle = (2.0 * std::f64::consts::PI * t / self.period) + self.phase;
+        return 1.0 + (self.amplitude * libm::cos(angle));
+    }
 }
 
 /// Create and run an SIR model.
-pub struct Model {
+///
+/// Population fractions and rates are stored and integrated in the scalar
+/// type `T`, `f64` unless chosen otherwise, see [`crate::scalar`]. Outputs
+/// and analyses, such as [`Model::result`], are `f64` whatever `T`.
+pub struct Model<T: Scalar = f64> {
     /// Number of indices to generate and solve. The length of the series.
     pub length: usize,
     /// Size of integration step.
     pub step_size: f64,
     /// Initial infectious population fraction.
-    pub i_popf_init: f64,
+    pub i_popf_init: T,
     /// Initial removed population fraction.
-    pub r_popf_init: f64,
-    /// Transition rate from S into I. Must be in [0, 1].
-    pub incidence_rate: f64,
-    /// Transition rate from I into R. Must be in [0, 1].
-    pub removal_rate: f64,
-    /// Transition rate from I into S. Must be in [0, 1].
-    pub recovery_rate: f64,
-    /// Susceptible population fraction at each index. 1D Array with `length` number of elements.
-    pub s_popf: Mat<f64>,
-    /// Inectious population fraction at each index. 1D Array with `length` number of elements.
-    pub i_popf: Mat<f64>,
-    /// Removed population fraction at each index. 1D Array with `length` number of elements.
-    pub r_popf: Mat<f64>,
+    pub r_popf_init: T,
+    /// Transition rate from S into I, per day. Must be in [0, 1].
+    pub incidence_rate: T,
+    /// Transition rate from I into R, per day. Must be in [0, 1].
+    pub removal_rate: T,
+    /// Transition rate from I into S, per day. Must be in [0, 1].
+    pub recovery_rate: T,
+    /// Changes to the S → I transition rate as `(t, incidence_rate)`, sorted
+    /// by time. From each `t` onward the incidence rate takes the new value.
+    pub incidence_rate_changes: Vec<(f64, T)>,
+    /// Rate at which each susceptible is infected from outside the
+    /// population, independent of prevalence. Zero unless set.
+    pub importation: RateSchedule,
+    /// Seasonal forcing of the S → I transition rate. None unless set.
+    pub seasonality: Option<Seasonality>,
+    /// Total population size N. 1 unless set by [`Model::counts`], in which
+    /// case initial values and outputs are counts rather than fractions.
+    pub population: T,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<T>,
+    /// Inectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<T>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<T>,
+    /// New infections, as a population fraction, over the step ending at
+    /// each index. 1D Array with one element per index of [`Model::grid`],
+    /// zero at index 0.
+    pub incidence: Mat<T>,
+    /// Cumulative infections, as a population fraction, up to each index.
+    /// 1D Array with one element per index of [`Model::grid`].
+    pub cumulative_incidence: Mat<T>,
 }
 
-impl Model {
-    /// Create a new model object.
-    pub fn new() -> Self {
+impl<T: Scalar> Model<T> {
+    /// Create a new model object in the scalar type `T`, for example
+    /// `Model::<f32>::zeroed()`. See [`Model::new`] for `f64`.
+    pub fn zeroed() -> Self {
         return Self {
             length: 0,
             step_size: 0.0,
-            i_popf_init: 0.0,
-            r_popf_init: 0.0,
-            incidence_rate: 0.0,
-            removal_rate: 0.0,
-            recovery_rate: 0.0,
+            i_popf_init: T::zero_impl(),
+            r_popf_init: T::zero_impl(),
+            incidence_rate: T::zero_impl(),
+            removal_rate: T::zero_impl(),
+            recovery_rate: T::zero_impl(),
+            incidence_rate_changes: Vec::new(),
+            importation: RateSchedule::constant(0.0),
+            seasonality: None,
+            population: T::one_impl(),
             s_popf: Mat::new(),
             i_popf: Mat::new(),
             r_popf: Mat::new(),
+            incidence: Mat::new(),
+            cumulative_incidence: Mat::new(),
         };
     }
 
-    /// Configure model parameters.
+    /// Configure model parameters. Time is in days, and rates are stored
+    /// per day.
     pub fn configure(
         &mut self,
         length: usize,
         step_size: f64,
-        i_popf_init: f64,
-        r_popf_init: f64,
-        incidence_rate: f64,
-        removal_rate: f64,
-        recovery_rate: f64,
+        i_popf_init: T,
+        r_popf_init: T,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        recovery_rate: Rate,
     ) -> &mut Self {
-        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
         self.length = length;
         self.step_size = step_size;
         self.i_popf_init = i_popf_init;
         self.r_popf_init = r_popf_init;
-        self.incidence_rate = incidence_rate;
-        self.removal_rate = removal_rate;
-        self.recovery_rate = recovery_rate;
+        self.incidence_rate = from_f64(incidence_rate.per(TimeUnit::Day));
+        self.removal_rate = from_f64(removal_rate.per(TimeUnit::Day));
+        self.recovery_rate = from_f64(recovery_rate.per(TimeUnit::Day));
+        self.incidence_rate_changes = Vec::new();
+        self.importation = RateSchedule::constant(0.0);
+        self.seasonality = None;
+        self.population = T::one_impl();
         self.s_popf = Mat::zeros(n_steps, 1);
         self.i_popf = Mat::zeros(n_steps, 1);
         self.r_popf = Mat::zeros(n_steps, 1);
+        self.incidence = Mat::zeros(n_steps, 1);
+        self.cumulative_incidence = Mat::zeros(n_steps, 1);
         return self;
     }
 
-    /// Initialize population fractions. Creates arrays of length `self.length`
-    /// to store the population fractions at each index and sets the 0th index
-    /// of each equal to the corresponding initial population fraction.
-    pub fn init_popf(&mut self) -> &mut Model {
-        let s_init = 1.0 - self.i_popf_init - self.r_popf_init; // Population fractions must sum to 1.
+    /// Times of the solved series, one per row of the outputs.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each output
+    /// equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Self {
+        let s_init = self.population - self.i_popf_init - self.r_popf_init; // Compartments must sum to the population.
         self.s_popf[(0, 0)] = s_init;
         self.i_popf[(0, 0)] = self.i_popf_init;
         self.r_popf[(0, 0)] = self.r_popf_init;
+        self.incidence[(0, 0)] = T::zero_impl();
+        self.cumulative_incidence[(0, 0)] = T::zero_impl();
+        return self;
+    }
+
+    /// Set changepoints in the S → I transition rate as
+    /// `(t, incidence_rate)` pairs. They are sorted by time.
+    pub fn changepoints(&mut self, changes: Vec<(f64, Rate)>) -> &mut Self {
+        let mut changes: Vec<(f64, T)> = changes
+            .into_iter()
+            .map(|(t, rate)| (t, from_f64(rate.per(TimeUnit::Day))))
+            .collect();
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.incidence_rate_changes = changes;
+        return self;
+    }
+
+    /// Set the S → I transition rate from a schedule, replacing
+    /// `incidence_rate` with its initial value and any changepoints with its
+    /// breakpoints.
+    pub fn incidence_rate_schedule(&mut self, schedule: RateSchedule<Rate>) -> &mut Self {
+        self.incidence_rate = from_f64(schedule.initial.per(TimeUnit::Day));
+        return self.changepoints(schedule.breakpoints);
+    }
+
+    /// Force the S → I transition rate sinusoidally, multiplying it by
+    /// `1 + amplitude cos(2πt / period + phase)`. Changepoints set the rate
+    /// being forced.
+    pub fn seasonality(&mut self, amplitude: f64, phase: f64, period: f64) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&amplitude),
+            "amplitude must be in [0, 1], got {}",
+            amplitude
+        );
+        assert!(period > 0.0, "period must be positive, got {}", period);
+        self.seasonality = Some(Seasonality {
+            amplitude,
+            phase,
+            period,
+        });
+        return self;
+    }
+
+    /// Seasonal factor multiplying the S → I transition rate at time `t`, 1
+    /// without seasonality.
+    pub fn seasonal_factor(&self, t: f64) -> T {
+        return from_f64(self.seasonality.map_or(1.0, |s| s.factor(t)));
+    }
+
+    /// Transition rate from S into I in effect at time `t`.
+    pub fn incidence_rate_at(&self, t: f64) -> T {
+        let mut rate = self.incidence_rate;
+        for (start, value) in self.incidence_rate_changes.iter() {
+            if *start > t {
+                break;
+            }
+            rate = *value;
+        }
+        return rate * self.seasonal_factor(t);
+    }
+
+    /// Run in absolute counts in a population of size `population`. The
+    /// initial infectious and removed values and every output are then
+    /// counts, and transmission is scaled by `1 / population`, so rates
+    /// are per capita as in fraction mode.
+    pub fn counts(&mut self, population: T) -> &mut Self {
+        assert!(
+            population > T::zero_impl(),
+            "population must be positive, got {:?}",
+            population
+        );
+        self.population = population;
+        return self;
+    }
+
+    /// Set the rate at which each susceptible is infected from outside the
+    /// population, constant or time-varying, for example travel
+    /// importation. It is stored per day.
+    pub fn importation(&mut self, schedule: impl Into<RateSchedule<Rate>>) -> &mut Self {
+        self.importation = schedule.into().per(TimeUnit::Day);
         return self;
     }
 
-    fn dsdt(&self, susceptible: f64, infectious: f64) -> f64 {
-        return (-self.incidence_rate * susceptible * infectious)
-            + (self.recovery_rate * infectious);
+    /// Importation rate in effect at time `t`.
+    pub fn importation_at(&self, t: f64) -> T {
+        return from_f64(self.importation.at(t));
+    }
+
+    /// Rate of change of the susceptible fraction at time `t`.
+    pub fn dsdt(&self, t: f64, susceptible: T, infectious: T) -> T {
+        return -self.dxdt(t, susceptible, infectious) + (self.recovery_rate * infectious);
     }
 
-    fn didt(&self, susceptible: f64, infectious: f64) -> f64 {
-        return (self.incidence_rate * susceptible * infectious)
+    /// Rate of change of the infectious fraction at time `t`.
+    pub fn didt(&self, t: f64, susceptible: T, infectious: T) -> T {
+        return self.dxdt(t, susceptible, infectious)
             - ((self.recovery_rate + self.removal_rate) * infectious);
     }
 
-    fn drdt(&self, infectious: f64) -> f64 {
+    /// Rate of change of the removed fraction.
+    pub fn drdt(&self, infectious: T) -> T {
         return self.removal_rate * infectious;
     }
 
+    /// Rate of new infections, the S → I flux, at time `t`, local
+    /// transmission plus importation.
+    pub fn dxdt(&self, t: f64, susceptible: T, infectious: T) -> T {
+        return (self.incidence_rate_at(t) * susceptible * infectious / self.population)
+            + (self.importation_at(t) * susceptible);
+    }
+
+    /// Record `dx` new infections over the step ending at index `t`.
+    fn record_incidence(&mut self, t: usize, dx: T) {
+        self.incidence[(t, 0)] = dx;
+        self.cumulative_incidence[(t, 0)] = self.cumulative_incidence[(t - 1, 0)] + dx;
+    }
+
+    /// Emit the state at index `t` as a debug event.
+    fn log_step(&self, t: usize) {
+        tracing::debug!(
+            t = self.grid().time(t),
+            s = ?self.s_popf[(t, 0)],
+            i = ?self.i_popf[(t, 0)],
+            r = ?self.r_popf[(t, 0)],
+            "step"
+        );
+    }
+
+    /// Emit a summary of a finished run as an info event.
+    fn log_summary(&self) {
+        let last = self.s_popf.nrows() - 1;
+        tracing::info!(
+            n_steps = self.s_popf.nrows(),
+            step_size = self.step_size,
+            s = ?self.s_popf[(last, 0)],
+            i = ?self.i_popf[(last, 0)],
+            r = ?self.r_popf[(last, 0)],
+            cumulative_incidence = ?self.cumulative_incidence[(last, 0)],
+            "finished"
+        );
+    }
+
     /// Run the SIR differential equations by the first-order euler method.
     ///
     /// This solution method is very rough and only suitable for demonstration.
-    pub fn run_euler(&mut self) -> &Model {
-        let h = self.step_size;
-        let n = ((self.length as f64) / h).ceil() as usize;
-        for i in 0..n - 1 {
-            let ds = self.dsdt(self.s_popf[(i, 0)], self.i_popf[(i, 0)]);
-            let di = self.didt(self.s_popf[(i, 0)], self.i_popf[(i, 0)]);
-            let dr = self.drdt(self.i_popf[(i, 0)]);
-            self.s_popf[(i + 1, 0)] = self.s_popf[(i, 0)] + (h * ds);
-            self.i_popf[(i + 1, 0)] = self.i_popf[(i, 0)] + (h * di);
-            self.r_popf[(i + 1, 0)] = self.r_popf[(i, 0)] + (h * dr);
-            println!(
-                "t={}: s={:.6} i={:.6} r={:.6}",
-                i,
-                self.s_popf[(i, 0)],
-                self.i_popf[(i, 0)],
-                self.r_popf[(i, 0)]
-            );
+    pub fn run_euler(&mut self) -> &Self {
+        self.solve("euler", None)
+            .expect("solving without a sink cannot fail");
+        return self;
+    }
+
+    /// Rates of change of each variable at `y` and time `t`. `x` is the
+    /// rate of new infections; `y.x` is not used.
+    fn derivatives(&self, t: f64, y: &SystemVars<T>) -> SystemVars<T> {
+        return SystemVars {
+            s: self.dsdt(t, y.s, y.i),
+            i: self.didt(t, y.s, y.i),
+            r: self.drdt(y.i),
+            x: self.dxdt(t, y.s, y.i),
+        };
+    }
+
+    /// `y + (k * h)` for each variable.
+    fn advance(&self, y: &SystemVars<T>, k: &SystemVars<T>, h: f64) -> SystemVars<T> {
+        return SystemVars {
+            s: self.next_y(y.s, k.s, h),
+            i: self.next_y(y.i, k.i, h),
+            r: self.next_y(y.r, k.r, h),
+            x: self.next_y(y.x, k.x, h),
+        };
+    }
+
+    /// `k * h` for each variable.
+    fn scale(&self, k: &SystemVars<T>, h: f64) -> SystemVars<T> {
+        let h: T = from_f64(h);
+        return SystemVars {
+            s: k.s * h,
+            i: k.i * h,
+            r: k.r * h,
+            x: k.x * h,
+        };
+    }
+
+    /// Write index `t` of every output column to `sink`.
+    fn write_step(&self, t: usize, sink: &mut Option<&mut dyn OutputSink>) -> io::Result<()> {
+        let Some(sink) = sink else {
+            return Ok(());
+        };
+        let values = [
+            self.s_popf[(t, 0)].to_f64(),
+            self.i_popf[(t, 0)].to_f64(),
+            self.r_popf[(t, 0)].to_f64(),
+            self.incidence[(t, 0)].to_f64(),
+            self.cumulative_incidence[(t, 0)].to_f64(),
+        ];
+        return sink.write_step(self.grid().time(t), &values);
+    }
+
+    /// Solve the system with a one-step method, writing every step to
+    /// `sink`, if given. `increment` gives the change of each variable over
+    /// the step from time `t` at `y`, with `x` the new infections over the
+    /// step.
+    fn run_one_step(
+        &mut self,
+        mut sink: Option<&mut dyn OutputSink>,
+        mut increment: impl FnMut(&Self, f64, &SystemVars<T>) -> SystemVars<T>,
+    ) -> io::Result<()> {
+        if let Some(sink) = sink.as_mut() {
+            let names: Vec<String> = COLUMNS.iter().map(|name| name.to_string()).collect();
+            sink.start(&names)?;
+        }
+        self.write_step(0, &mut sink)?;
+        let grid = self.grid();
+        for t in 0..grid.n_steps - 1 {
+            let y = SystemVars {
+                s: self.s_popf[(t, 0)],
+                i: self.i_popf[(t, 0)],
+                r: self.r_popf[(t, 0)],
+                x: T::zero_impl(),
+            };
+            let d = increment(self, grid.time(t), &y);
+            self.s_popf[(t + 1, 0)] = y.s + d.s;
+            self.i_popf[(t + 1, 0)] = y.i + d.i;
+            self.r_popf[(t + 1, 0)] = y.r + d.r;
+            self.record_incidence(t + 1, d.x);
+            self.log_step(t + 1);
+            self.write_step(t + 1, &mut sink)?;
         }
+        self.log_summary();
+        if let Some(sink) = sink.as_mut() {
+            sink.finish()?;
+        }
+        return Ok(());
+    }
+
+    /// Solve the system by Heun's method, the explicit trapezoidal rule.
+    ///
+    /// Second order, with two evaluations of the equations per step: a
+    /// middle ground between [`Model::run_euler`] and [`Model::run_rk4`].
+    pub fn run_heun(&mut self) -> &Self {
+        self.solve("heun", None)
+            .expect("solving without a sink cannot fail");
+        return self;
+    }
+
+    /// Solve the system by the explicit midpoint method.
+    ///
+    /// Second order, with two evaluations of the equations per step, see
+    /// [`Model::run_heun`].
+    pub fn run_midpoint(&mut self) -> &Self {
+        self.solve("midpoint", None)
+            .expect("solving without a sink cannot fail");
+        return self;
+    }
+
+    /// S → I, I → R and I → S fluxes at time `t`.
+    fn fluxes(&self, t: f64, susceptible: T, infectious: T) -> [T; 3] {
+        return [
+            self.dxdt(t, susceptible, infectious),
+            self.drdt(infectious),
+            self.recovery_rate * infectious,
+        ];
+    }
+
+    /// State after a step from `y` in which the S → I, I → R and I → S
+    /// transfers are `infection * S'`, `removal * I'` and `recovery * I'`,
+    /// each proportional to the new value of its source compartment. Solves
+    /// the linear system exactly, so every compartment stays non-negative and
+    /// the total is conserved. `x` is the S → I transfer.
+    fn patankar_solve(
+        &self,
+        y: &SystemVars<T>,
+        infection: T,
+        removal: T,
+        recovery: T,
+    ) -> SystemVars<T> {
+        let one = T::one_impl();
+        let det = ((one + infection) * (one + removal + recovery)) - (infection * recovery);
+        let s = ((y.s * (one + removal + recovery)) + (recovery * y.i)) / det;
+        let i = ((y.i * (one + infection)) + (infection * y.s)) / det;
+        return SystemVars {
+            s,
+            i,
+            r: y.r + (removal * i),
+            x: infection * s,
+        };
+    }
+
+    /// Solve the system by the second order modified Patankar-Runge-Kutta
+    /// method, MPRK22 of Kopecz and Meister (2018).
+    ///
+    /// Each flux of Heun's method is weighted by the new value of its source
+    /// compartment over a stage value, which makes each stage a small linear
+    /// system. Compartments stay non-negative and sum to the population at
+    /// any step size, so coarse steps matching a reporting interval cannot
+    /// produce negative populations, unlike the explicit methods.
+    pub fn run_patankar(&mut self) -> &Self {
+        self.solve("patankar", None)
+            .expect("solving without a sink cannot fail");
         return self;
     }
 
     /// Construct array of runge-kutta intermediate values for each variable.
-    fn init_y(&self) -> [SystemVars; 5] {
+    fn init_y(&self) -> [SystemVars<T>; 5] {
         return [
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
         ];
     }
 
     /// Construct array of runge-kutta constants for each variable.
-    fn init_k(&self) -> [SystemVars; 5] {
+    fn init_k(&self) -> [SystemVars<T>; 5] {
         return [
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
             SystemVars {
-                s: 0.0,
-                i: 0.0,
-                r: 0.0,
+                s: T::zero_impl(),
+                i: T::zero_impl(),
+                r: T::zero_impl(),
+                x: T::zero_impl(),
             },
         ];
     }
@@ -204,59 +691,509 @@ impl Model {
     }
 
     /// Compute a runge-kutta approximate function value.
-    fn next_y(&self, y: f64, k: f64, h: f64) -> f64 {
-        return y + (k * h);
+    fn next_y(&self, y: T, k: T, h: f64) -> T {
+        return y + (k * from_f64(h));
     }
 
-    /// Compute a 4th order runge-kutta time step for the system.
-    fn rk4_step(&self, t: usize) -> [SystemVars; 5] {
-        let mut y = self.init_y();
-        let mut k = self.init_k();
+    /// Compute a 4th order runge-kutta time step for the system from the
+    /// variables in `y[0]` at `time`. The stage values are written into `y`
+    /// and the runge-kutta constants into `k`, so the caller's buffers are
+    /// reused across steps. Returns the increment of each variable.
+    fn rk4_step(
+        &self,
+        time: f64,
+        y: &mut [SystemVars<T>; 5],
+        k: &mut [SystemVars<T>; 5],
+    ) -> SystemVars<T> {
         let h = self.init_h();
-        y[0].s = self.s_popf[(t, 0)];
-        y[0].i = self.i_popf[(t, 0)];
-        y[0].r = self.r_popf[(t, 0)];
+        let stage_time = [time, time + h[0], time + h[1], time + h[2]];
         for i in 0..4 {
-            k[i + 1].s = self.dsdt(y[i].s, y[i].i);
-            k[i + 1].i = self.didt(y[i].s, y[i].i);
+            k[i + 1].s = self.dsdt(stage_time[i], y[i].s, y[i].i);
+            k[i + 1].i = self.didt(stage_time[i], y[i].s, y[i].i);
             k[i + 1].r = self.drdt(y[i].i);
+            k[i + 1].x = self.dxdt(stage_time[i], y[i].s, y[i].i);
             y[i + 1].s = self.next_y(y[0].s, k[i + 1].s, h[i]);
             y[i + 1].i = self.next_y(y[0].i, k[i + 1].i, h[i]);
             y[i + 1].r = self.next_y(y[0].r, k[i + 1].r, h[i]);
         }
-        return k;
+        let two: T = from_f64(2.0);
+        let h6: T = from_f64(self.step_size / 6.0);
+        return SystemVars {
+            s: (k[1].s + (two * k[2].s) + (two * k[3].s) + k[4].s) * h6,
+            i: (k[1].i + (two * k[2].i) + (two * k[3].i) + k[4].i) * h6,
+            r: (k[1].r + (two * k[2].r) + (two * k[3].r) + k[4].r) * h6,
+            x: (k[1].x + (two * k[2].x) + (two * k[3].x) + k[4].x) * h6,
+        };
     }
 
     /// Solve the system by the 4th order Runge-Kutta method.
     ///
     /// This method is suitable for general purposes.
-    pub fn run_rk4(&mut self) -> &Model {
-        let n = (self.length as f64 / self.step_size).ceil() as usize;
-        for t in 0..n - 1 {
-            let k = self.rk4_step(t);
-            let ds = (k[1].s + (2.0 * k[2].s) + (2.0 * k[3].s) + k[4].s) * (self.step_size / 6.0);
-            let di = (k[1].i + (2.0 * k[2].i) + (2.0 * k[3].i) + k[4].i) * (self.step_size / 6.0);
-            let dr = (k[1].r + (2.0 * k[2].r) + (2.0 * k[3].r) + k[4].r) * (self.step_size / 6.0);
-            self.s_popf[(t + 1, 0)] = self.s_popf[(t, 0)] + ds;
-            self.i_popf[(t + 1, 0)] = self.i_popf[(t, 0)] + di;
-            self.r_popf[(t + 1, 0)] = self.r_popf[(t, 0)] + dr;
-            if t % 10 == 0 {
-                println!(
-                    "t={:.1} s={:.6} i={:.6} r={:.6}",
-                    (t as f64) * self.step_size,
-                    self.s_popf[(t, 0)],
-                    self.i_popf[(t, 0)],
-                    self.r_popf[(t, 0)],
-                );
+    pub fn run_rk4(&mut self) -> &Self {
+        self.solve("rk4", None)
+            .expect("solving without a sink cannot fail");
+        return self;
+    }
+
+    /// Solve the model with `solver`, by name, as `run_<solver>` does, also
+    /// writing the run metadata and then every step's s, i, r, incidence and
+    /// cumulative incidence to `sink` as it is computed. Solvers are `euler`,
+    /// `heun`, `midpoint`, `rk4` and `patankar`.
+    pub fn run_into(&mut self, solver: &str, sink: &mut dyn OutputSink) -> io::Result<&Self> {
+        sink.metadata(&self.metadata(solver))?;
+        self.solve(solver, Some(sink))?;
+        return Ok(self);
+    }
+
+    /// Solve the model with `solver`, by name, writing every step to `sink`,
+    /// if given.
+    fn solve(&mut self, solver: &str, sink: Option<&mut dyn OutputSink>) -> io::Result<()> {
+        let _span = tracing::info_span!("run", model = "sir", solver).entered();
+        let h = self.step_size;
+        return match solver {
+            "euler" => self.run_one_step(sink, |model, t, y| {
+                return model.scale(&model.derivatives(t, y), h);
+            }),
+            "heun" => self.run_one_step(sink, |model, t, y| {
+                let k1 = model.derivatives(t, y);
+                let k2 = model.derivatives(t + h, &model.advance(y, &k1, h));
+                let sum = SystemVars {
+                    s: k1.s + k2.s,
+                    i: k1.i + k2.i,
+                    r: k1.r + k2.r,
+                    x: k1.x + k2.x,
+                };
+                return model.scale(&sum, h / 2.0);
+            }),
+            "midpoint" => self.run_one_step(sink, |model, t, y| {
+                let k1 = model.derivatives(t, y);
+                let k2 = model.derivatives(t + (h / 2.0), &model.advance(y, &k1, h / 2.0));
+                return model.scale(&k2, h);
+            }),
+            "rk4" => {
+                let mut stages = self.init_y();
+                let mut k = self.init_k();
+                self.run_one_step(sink, |model, t, y| {
+                    stages[0] = *y;
+                    return model.rk4_step(t, &mut stages, &mut k);
+                })
+            }
+            "patankar" => {
+                // `flux * step / weight`, zero when the weight is, as then the
+                // flux is.
+                let coefficient = |flux: T, weight: T, step: f64| {
+                    if weight == T::zero_impl() {
+                        return T::zero_impl();
+                    }
+                    return flux * from_f64(step) / weight;
+                };
+                self.run_one_step(sink, |model, t, y| {
+                    let f1 = model.fluxes(t, y.s, y.i);
+                    let y2 = model.patankar_solve(
+                        y,
+                        coefficient(f1[0], y.s, h),
+                        coefficient(f1[1], y.i, h),
+                        coefficient(f1[2], y.i, h),
+                    );
+                    let f2 = model.fluxes(t + h, y2.s, y2.i);
+                    let next = model.patankar_solve(
+                        y,
+                        coefficient(f1[0] + f2[0], y2.s, h / 2.0),
+                        coefficient(f1[1] + f2[1], y2.i, h / 2.0),
+                        coefficient(f1[2] + f2[2], y2.i, h / 2.0),
+                    );
+                    return SystemVars {
+                        s: next.s - y.s,
+                        i: next.i - y.i,
+                        r: next.r - y.r,
+                        x: next.x,
+                    };
+                })
+            }
+            _ => panic!(
+                "unknown solver {}, expected euler, heun, midpoint, rk4 or patankar",
+                solver
+            ),
+        };
+    }
+
+    /// State at index 0, from the initial population fractions, for
+    /// stepping with [`Model::step_into`].
+    pub fn state(&self) -> State<T> {
+        return State {
+            index: 0,
+            s: self.population - self.i_popf_init - self.r_popf_init,
+            i: self.i_popf_init,
+            r: self.r_popf_init,
+            incidence: T::zero_impl(),
+            cumulative_incidence: T::zero_impl(),
+            y: self.init_y(),
+            k: self.init_k(),
+        };
+    }
+
+    /// Advance `state` one step by the 4th order Runge-Kutta method, in
+    /// place. Takes the same steps as [`Model::run_rk4`] without allocating
+    /// or storing the series, for hot loops such as large ensembles.
+    pub fn step_into(&self, state: &mut State<T>) {
+        state.y[0].s = state.s;
+        state.y[0].i = state.i;
+        state.y[0].r = state.r;
+        let time = self.grid().time(state.index);
+        let d = self.rk4_step(time, &mut state.y, &mut state.k);
+        state.index += 1;
+        state.s = state.s + d.s;
+        state.i = state.i + d.i;
+        state.r = state.r + d.r;
+        state.incidence = d.x;
+        state.cumulative_incidence = state.cumulative_incidence + d.x;
+    }
+
+    /// Lazily computed `(t, state)` pairs at each index of the series,
+    /// starting from index 0, taking the same steps as [`Model::run_rk4`].
+    /// Nothing is stored, so very long runs can be consumed or stopped early
+    /// without the trajectory matrices.
+    pub fn iter_steps(&self) -> Steps<'_, T> {
+        return Steps {
+            model: self,
+            state: self.state(),
+            n_steps: self.grid().n_steps,
+            started: false,
+        };
+    }
+
+    /// Solve the system as [`Model::run_rk4`] does, storing only the steps
+    /// chosen by `output`. Memory grows with the number of stored steps
+    /// rather than the length of the run.
+    pub fn run_rk4_output(&self, output: &Output) -> Trajectory<T> {
+        let mut trajectory = Trajectory {
+            times: Vec::new(),
+            s_popf: Vec::new(),
+            i_popf: Vec::new(),
+            r_popf: Vec::new(),
+            cumulative_incidence: Vec::new(),
+        };
+        for (t, state) in self.iter_steps() {
+            if output.keeps(state.index, self.step_size) {
+                trajectory.times.push(t);
+                trajectory.s_popf.push(state.s);
+                trajectory.i_popf.push(state.i);
+                trajectory.r_popf.push(state.r);
+                trajectory
+                    .cumulative_incidence
+                    .push(state.cumulative_incidence);
             }
         }
-        return self;
+        return trajectory;
+    }
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self::zeroed();
+    }
+
+    /// Compartments, flows and parameters of the model.
+    pub fn schema() -> ModelSchema {
+        let mut schema = ModelSchema::new("sir", "Three compartment SIR model");
+        schema
+            .compartment("S")
+            .compartment("I")
+            .compartment("R")
+            .flow("S", "I", "incidence_rate * I")
+            .flow("I", "R", "removal_rate")
+            .flow("I", "S", "recovery_rate")
+            .parameter("length", "Length of the series", 1.0, f64::INFINITY, "time")
+            .parameter(
+                "step_size",
+                "Size of integration step",
+                0.0,
+                f64::INFINITY,
+                "time",
+            )
+            .parameter(
+                "i_popf_init",
+                "Initial infectious population fraction",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "r_popf_init",
+                "Initial removed population fraction",
+                0.0,
+                1.0,
+                "fraction",
+            )
+            .parameter(
+                "incidence_rate",
+                "Transition rate from S into I",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "removal_rate",
+                "Transition rate from I into R",
+                0.0,
+                1.0,
+                "1/time",
+            )
+            .parameter(
+                "recovery_rate",
+                "Transition rate from I into S",
+                0.0,
+                1.0,
+                "1/time",
+            );
+        return schema;
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Metadata of a run of `solver`, for example `rk4`, with the model as
+    /// configured. The parameters hash covers the configuration, including
+    /// changepoints as `incidence_rate@<t>`, when set, importation as
+    /// `importation` and `importation@<t>`, and in count mode `population`.
+    pub fn metadata(&self, solver: &str) -> RunMetadata {
+        let mut parameters = Parameters::from([
+            ("length".to_string(), self.length as f64),
+            ("i_popf_init".to_string(), self.i_popf_init.to_f64()),
+            ("r_popf_init".to_string(), self.r_popf_init.to_f64()),
+            ("incidence_rate".to_string(), self.incidence_rate.to_f64()),
+            ("removal_rate".to_string(), self.removal_rate.to_f64()),
+            ("recovery_rate".to_string(), self.recovery_rate.to_f64()),
+        ]);
+        for (t, rate) in self.incidence_rate_changes.iter() {
+            parameters.insert(format!("incidence_rate@{}", t), rate.to_f64());
+        }
+        if self.population != T::one_impl() {
+            parameters.insert("population".to_string(), self.population.to_f64());
+        }
+        if let Some(seasonality) = self.seasonality {
+            parameters.insert("seasonality_amplitude".to_string(), seasonality.amplitude);
+            parameters.insert("seasonality_phase".to_string(), seasonality.phase);
+            parameters.insert("seasonality_period".to_string(), seasonality.period);
+        }
+        if self.importation != RateSchedule::constant(0.0) {
+            parameters.insert("importation".to_string(), self.importation.initial);
+            for (t, rate) in self.importation.breakpoints.iter() {
+                parameters.insert(format!("importation@{}", t), *rate);
+            }
+        }
+        return RunMetadata::new(solver, self.step_size, &parameters);
+    }
+
+    /// The solved series with metadata of a run of `solver`, see
+    /// [`Model::metadata`].
+    pub fn result(&self, solver: &str) -> SimulationResult {
+        let columns = [
+            &self.s_popf,
+            &self.i_popf,
+            &self.r_popf,
+            &self.incidence,
+            &self.cumulative_incidence,
+        ];
+        let n_steps = self.s_popf.nrows();
+        return SimulationResult {
+            metadata: self.metadata(solver),
+            names: COLUMNS.iter().map(|name| name.to_string()).collect(),
+            times: self.grid().times(),
+            values: Mat::from_fn(n_steps, columns.len(), |t, j| columns[j][(t, 0)].to_f64()),
+        };
+    }
+
+    /// Jacobian of the equations at time `t` and `state`, (S, I, R)
+    /// population fractions, with rows the derivatives of S, I and R.
+    pub fn jacobian(&self, t: f64, state: &[f64]) -> Mat<f64> {
+        let (s, i) = (state[0], state[1]);
+        let beta = (self.incidence_rate_at(t) / self.population).to_f64();
+        let iota = self.importation_at(t).to_f64();
+        let (removal, recovery) = (self.removal_rate.to_f64(), self.recovery_rate.to_f64());
+        let gamma = recovery + removal;
+        return faer::mat![
+            [-(beta * i) - iota, (-beta * s) + recovery, 0.0],
+            [(beta * i) + iota, (beta * s) - gamma, 0.0],
+            [0.0, removal, 0.0],
+        ];
+    }
+
+    /// Disease-free and endemic equilibria of the model, found by Newton's
+    /// method on the right-hand side rather than by integrating to long
+    /// times.
+    ///
+    /// Rates are those in effect after the last changepoint, and the total
+    /// population is conserved, with R held at `r_popf_init`.
+    /// The disease-free equilibrium is always returned first. An endemic
+    /// equilibrium exists only without removal, when infection is sustained
+    /// by recovery back into S, and only if it has I > 0. Importation is
+    /// assumed to be zero, without which there is no disease-free state.
+    pub fn solve_equilibrium(&self) -> Vec<Equilibrium> {
+        let total = (self.population - self.r_popf_init).to_f64();
+        let mut equilibria = vec![self.equilibrium(total, 0.0)];
+        if self.removal_rate == T::zero_impl()
+            && let Some((s, i)) = self.newton_equilibrium(0.0, total)
+            && (i > 1e-12)
+        {
+            equilibria.push(self.equilibrium(s, i));
+        }
+        return equilibria;
+    }
+
+    /// Newton's method for the root of (S + I - total, dI/dt) from
+    /// `(s, i)`, the conservation constraint replacing dS/dt, which is
+    /// dependent on the other equations.
+    fn newton_equilibrium(&self, mut s: f64, mut i: f64) -> Option<(f64, f64)> {
+        let total = s + i;
+        let t = f64::INFINITY;
+        for _ in 0..100 {
+            let f = [
+                s + i - total,
+                self.didt(t, from_f64(s), from_f64(i)).to_f64(),
+            ];
+            let jacobian = self.jacobian(t, &[s, i, self.r_popf_init.to_f64()]);
+            // Solve [[1, 1], [c, d]] (ds, di) = f.
+            let (c, d) = (jacobian[(1, 0)], jacobian[(1, 1)]);
+            let det = d - c;
+            if det == 0.0 {
+                return None;
+            }
+            let ds = ((d * f[0]) - f[1]) / det;
+            let di = (f[1] - (c * f[0])) / det;
+            s -= ds;
+            i -= di;
+            if (ds.abs() + di.abs()) < 1e-14 {
+                return Some((s, i));
+            }
+        }
+        return None;
+    }
+
+    /// Equilibrium at `(s, i)` with its Jacobian eigenvalues.
+    fn equilibrium(&self, s: f64, i: f64) -> Equilibrium {
+        let r = self.r_popf_init.to_f64();
+        let stability = self.stability_at(f64::INFINITY, &[s, i, r]);
+        return Equilibrium {
+            endemic: i > 0.0,
+            s,
+            i,
+            r,
+            stable: stability.stable(),
+            eigenvalues: stability.eigenvalues,
+        };
+    }
+
+    /// Eigenvalues of the Jacobian at time `t` and `state`, see
+    /// [`Model::jacobian`]. At a disease-free state the growth rate is
+    /// positive exactly when R0 > 1.
+    pub fn stability_at(&self, t: f64, state: &[f64]) -> Stability {
+        return Stability::of(&self.jacobian(t, state));
+    }
+
+    /// Estimate the global error of `solver`, `euler`, `heun`, `midpoint`,
+    /// `rk4` or `patankar`, at the configured step size by step doubling.
+    /// The model is solved at `h` and `h / 2`, and for a method of order `p`
+    /// the error of the `h` solution is about
+    /// `|y_h - y_{h/2}| 2^p / (2^p - 1)` at every shared index. The model's
+    /// own outputs are left as they are.
+    pub fn estimate_error(&self, solver: &str) -> ErrorEstimate {
+        let order = solver_order(solver);
+        let mut coarse = self.with_step_size(self.step_size);
+        let mut fine = self.with_step_size(self.step_size / 2.0);
+        coarse.run_solver(solver);
+        fine.run_solver(solver);
+        let factor = 2f64.powi(order) / (2f64.powi(order) - 1.0);
+        let error = |a: &Mat<T>, b: &Mat<T>| {
+            return (0..a.nrows())
+                .map(|k| (a[(k, 0)] - b[(2 * k, 0)]).to_f64().abs() * factor)
+                .fold(0.0, f64::max);
+        };
+        return ErrorEstimate {
+            step_size: self.step_size,
+            s: error(&coarse.s_popf, &fine.s_popf),
+            i: error(&coarse.i_popf, &fine.i_popf),
+            r: error(&coarse.r_popf, &fine.r_popf),
+        };
+    }
+
+    /// Halve the step size until the estimated global error of `solver` is
+    /// at most `tolerance`, or `max_halvings` halvings are used, then solve
+    /// the model at that step size. Returns the error estimate at the final
+    /// step size, see [`Model::estimate_error`].
+    pub fn refine_step_size(
+        &mut self,
+        solver: &str,
+        tolerance: f64,
+        max_halvings: usize,
+    ) -> ErrorEstimate {
+        let mut estimate = self.estimate_error(solver);
+        let mut halvings = 0;
+        while (estimate.max() > tolerance) & (halvings < max_halvings) {
+            *self = self.with_step_size(self.step_size / 2.0);
+            estimate = self.estimate_error(solver);
+            halvings += 1;
+        }
+        *self = self.with_step_size(self.step_size);
+        self.run_solver(solver);
+        tracing::info!(
+            solver,
+            step_size = self.step_size,
+            error = estimate.max(),
+            halvings,
+            "refined step size"
+        );
+        return estimate;
+    }
+
+    /// A copy of the model's configuration at `step_size`, initialized and
+    /// ready to solve.
+    fn with_step_size(&self, step_size: f64) -> Self {
+        let mut model = Self::zeroed();
+        model.configure(
+            self.length,
+            step_size,
+            self.i_popf_init,
+            self.r_popf_init,
+            Rate::per_day(self.incidence_rate.to_f64()),
+            Rate::per_day(self.removal_rate.to_f64()),
+            Rate::per_day(self.recovery_rate.to_f64()),
+        );
+        model.incidence_rate_changes = self.incidence_rate_changes.clone();
+        model.importation = self.importation.clone();
+        model.seasonality = self.seasonality;
+        model.population = self.population;
+        model.init_popf();
+        return model;
+    }
+
+    /// Solve the model with `solver`, by name.
+    fn run_solver(&mut self, solver: &str) {
+        self.solve(solver, None)
+            .expect("solving without a sink cannot fail");
     }
 }
 
+/// Order of accuracy of `solver`.
+fn solver_order(solver: &str) -> i32 {
+    return match solver {
+        "euler" => 1,
+        "heun" | "midpoint" | "patankar" => 2,
+        "rk4" => 4,
+        _ => panic!(
+            "unknown solver {}, expected euler, heun, midpoint, rk4 or patankar",
+            solver
+        ),
+    };
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::sirrs::pipeline::Pipeline;
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::sink::MemorySink;
     use crate::sirrs::sir::Model;
+    use crate::sirrs::units::{Duration, Rate, TimeUnit};
     use faer::Mat;
 
     #[test]
@@ -315,7 +1252,15 @@ mod tests {
     #[test]
     fn test_configure() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         let n_steps = ((model.length as f64) / model.step_size).ceil() as usize;
         assert_eq!(
             model.length, 10,
@@ -367,10 +1312,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_configure_converts_rates() {
+        let week = TimeUnit::Week;
+        let mut model = Model::new();
+        model.configure(
+            70,
+            0.5,
+            0.01,
+            0.0,
+            Rate::new(2.8, week).unwrap(),
+            Duration::new(1.0, week).unwrap().rate(),
+            Rate::per_day(0.0),
+        );
+        for (name, actual, expected) in [
+            ("incidence_rate", model.incidence_rate, 0.4),
+            ("removal_rate", model.removal_rate, 1.0 / 7.0),
+        ] {
+            assert!(
+                (actual - expected).abs() < 1e-12,
+                "Bad {}, expected {} got {}",
+                name,
+                expected,
+                actual
+            );
+        }
+    }
+
     #[test]
     fn test_init_popf() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         model.init_popf();
         assert_eq!(
             model.s_popf.shape(),
@@ -439,14 +1419,23 @@ mod tests {
     #[test]
     fn test_run_euler() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         model.init_popf();
         model.run_euler();
         let h = model.step_size;
         let n = ((model.length as f64) / h).ceil() as usize;
         for t in 1..n - 1 {
-            let dsdt = model.dsdt(model.s_popf[(t - 1, 0)], model.i_popf[(t - 1, 0)]);
-            let didt = model.didt(model.s_popf[(t - 1, 0)], model.i_popf[(t - 1, 0)]);
+            let time = ((t - 1) as f64) * h;
+            let dsdt = model.dsdt(time, model.s_popf[(t - 1, 0)], model.i_popf[(t - 1, 0)]);
+            let didt = model.didt(time, model.s_popf[(t - 1, 0)], model.i_popf[(t - 1, 0)]);
             let drdt = model.drdt(model.i_popf[(t - 1, 0)]);
             model.s_popf[(t, 0)] = model.s_popf[(t - 1, 0)] + (h * dsdt);
             model.i_popf[(t, 0)] = model.i_popf[(t - 1, 0)] + (h * didt);
@@ -499,7 +1488,15 @@ mod tests {
     #[test]
     fn test_init_h() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         let h = model.init_h();
         assert!(
             h.len() == 4,
@@ -531,7 +1528,15 @@ mod tests {
     #[test]
     fn test_init_y() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         let y = model.init_y();
         assert!(
             y.len() == 5,
@@ -563,7 +1568,15 @@ mod tests {
     #[test]
     fn test_init_k() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         let k = model.init_k();
         assert!(
             k.len() == 5,
@@ -595,7 +1608,15 @@ mod tests {
     #[test]
     fn test_run_rk4() {
         let mut model = Model::new();
-        model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
         model.init_popf();
         model.run_rk4();
         let h = model.step_size;
@@ -605,8 +1626,8 @@ mod tests {
             let mut k = model.init_k();
             let h = model.init_h();
             for i in 0..4 {
-                k[i + 1].s = model.dsdt(y[i].s, y[i].i);
-                k[i + 1].i = model.didt(y[i].s, y[i].i);
+                k[i + 1].s = model.dsdt(t as f64, y[i].s, y[i].i);
+                k[i + 1].i = model.didt(t as f64, y[i].s, y[i].i);
                 k[i + 1].r = model.drdt(y[i].i);
                 y[i + 1].s = model.next_y(y[0].s, k[i + 1].s, h[i]);
                 y[i + 1].i = model.next_y(y[0].i, k[i + 1].i, h[i]);
@@ -662,4 +1683,847 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_incidence_rate_at() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.02),
+            Rate::per_day(0.03),
+            Rate::per_day(0.04),
+        );
+        model.changepoints(vec![(6.0, Rate::per_day(0.5)), (3.0, Rate::per_day(0.1))]);
+        assert_eq!(
+            model.incidence_rate_at(0.0),
+            0.02,
+            "Bad incidence_rate_at(0.0), expected 0.02 got {}",
+            model.incidence_rate_at(0.0)
+        );
+        assert_eq!(
+            model.incidence_rate_at(3.0),
+            0.1,
+            "Bad incidence_rate_at(3.0), expected 0.1 got {}",
+            model.incidence_rate_at(3.0)
+        );
+        assert_eq!(
+            model.incidence_rate_at(9.5),
+            0.5,
+            "Bad incidence_rate_at(9.5), expected 0.5 got {}",
+            model.incidence_rate_at(9.5)
+        );
+    }
+
+    #[test]
+    fn test_seasonality() {
+        let forced = || {
+            let mut model = Model::new();
+            model.configure(
+                3650,
+                1.0,
+                0.01,
+                0.0,
+                Rate::per_day(0.3),
+                Rate::per_day(0.0),
+                Rate::per_day(0.2),
+            );
+            model.seasonality(0.4, 0.0, 365.0);
+            return model;
+        };
+        let mut model = forced();
+        for (t, expected) in [(0.0, 0.42), (182.5, 0.18), (365.0, 0.42)] {
+            assert!(
+                (model.incidence_rate_at(t) - expected).abs() < 1e-12,
+                "Bad incidence_rate_at({}), expected {} got {}",
+                t,
+                expected,
+                model.incidence_rate_at(t)
+            );
+        }
+        model.init_popf();
+        model.run_rk4();
+        // After the transient, prevalence follows the annual cycle of
+        // transmission around the endemic level of the mean rate, 1 / 3.
+        let year = |k: usize| (k * 365)..((k + 1) * 365);
+        for t in year(8) {
+            let (now, next) = (model.i_popf[(t, 0)], model.i_popf[(t + 365, 0)]);
+            assert!(
+                (now - next).abs() < 1e-6,
+                "Expected an annual cycle at t = {}, got {} then {}",
+                t,
+                now,
+                next
+            );
+        }
+        let last: Vec<f64> = year(9).map(|t| model.i_popf[(t, 0)]).collect();
+        let (low, high) = (
+            last.iter().cloned().fold(f64::INFINITY, f64::min),
+            last.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        );
+        assert!(
+            (high - low > 0.1) & (low < 1.0 / 3.0) & (high > 1.0 / 3.0),
+            "Expected oscillation around 1 / 3, got [{}, {}]",
+            low,
+            high
+        );
+        let mut pipeline = Pipeline::new(Box::new(forced()));
+        pipeline.configure(3650, 1.0);
+        pipeline.run_rk4();
+        assert!(
+            (pipeline.state[(3000, 1)] - model.i_popf[(3000, 0)]).abs() < 1e-9,
+            "Bad pipeline prevalence, expected {} got {}",
+            model.i_popf[(3000, 0)],
+            pipeline.state[(3000, 1)]
+        );
+    }
+
+    #[test]
+    fn test_incidence() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        // Without recovery back into S, every departure from S is a new
+        // infection.
+        for t in 1..model.s_popf.nrows() {
+            let expected = model.s_popf[(0, 0)] - model.s_popf[(t, 0)];
+            assert!(
+                (model.cumulative_incidence[(t, 0)] - expected).abs() < 1e-12,
+                "Bad cumulative_incidence at index {}, expected {} got {}",
+                t,
+                expected,
+                model.cumulative_incidence[(t, 0)]
+            );
+            assert!(
+                (model.incidence[(t, 0)] - (model.s_popf[(t - 1, 0)] - model.s_popf[(t, 0)])).abs()
+                    < 1e-12,
+                "Bad incidence at index {}, got {}",
+                t,
+                model.incidence[(t, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut fractions = Model::new();
+        fractions.configure(
+            60,
+            0.5,
+            0.01,
+            0.1,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+        );
+        fractions.init_popf();
+        fractions.run_rk4();
+        let mut counts = Model::new();
+        counts.configure(
+            60,
+            0.5,
+            100.0,
+            1000.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.02),
+        );
+        counts.counts(10000.0);
+        counts.init_popf();
+        counts.run_rk4();
+        for t in 0..counts.s_popf.nrows() {
+            for (name, count, fraction) in [
+                ("s", counts.s_popf[(t, 0)], fractions.s_popf[(t, 0)]),
+                ("i", counts.i_popf[(t, 0)], fractions.i_popf[(t, 0)]),
+                ("r", counts.r_popf[(t, 0)], fractions.r_popf[(t, 0)]),
+                (
+                    "cumulative_incidence",
+                    counts.cumulative_incidence[(t, 0)],
+                    fractions.cumulative_incidence[(t, 0)],
+                ),
+            ] {
+                assert!(
+                    (count - (10000.0 * fraction)).abs() < 1e-8,
+                    "Bad {} count at index {}, expected {} got {}",
+                    name,
+                    t,
+                    10000.0 * fraction,
+                    count
+                );
+            }
+        }
+        let equilibrium = &counts.solve_equilibrium()[0];
+        assert_eq!(
+            equilibrium.s, 9000.0,
+            "Bad disease-free s, expected 9000 got {}",
+            equilibrium.s
+        );
+    }
+
+    #[test]
+    fn test_importation() {
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.5,
+            0.0,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        assert_eq!(
+            model.i_popf[(199, 0)],
+            0.0,
+            "Expected no epidemic without infectious or importation"
+        );
+        model.importation(RateSchedule::new(
+            Rate::per_day(0.0),
+            vec![(10.0, Rate::per_day(1e-4))],
+        ));
+        model.init_popf();
+        model.run_rk4();
+        assert_eq!(
+            model.i_popf[(19, 0)],
+            0.0,
+            "Expected no infections before importation starts"
+        );
+        let expected = 1e-4 * model.s_popf[(30, 0)];
+        assert!(
+            (model.dxdt(15.0, model.s_popf[(30, 0)], 0.0) - expected).abs() < 1e-15,
+            "Bad importation flux, expected {} got {}",
+            expected,
+            model.dxdt(15.0, model.s_popf[(30, 0)], 0.0)
+        );
+        assert!(
+            model.r_popf[(199, 0)] > 0.5,
+            "Expected importation to seed an epidemic, got a final size of {}",
+            model.r_popf[(199, 0)]
+        );
+        let hash = model.result("rk4").metadata.parameters_hash;
+        model.importation(Rate::per_day(0.0));
+        assert_ne!(
+            model.result("rk4").metadata.parameters_hash,
+            hash,
+            "Expected importation to change the parameters hash"
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = Model::schema();
+        assert_eq!(
+            schema.compartments,
+            vec!["S", "I", "R"],
+            "Bad compartments, got {:?}",
+            schema.compartments
+        );
+        let mut values = crate::sirrs::pipeline::Parameters::new();
+        for (name, value) in [
+            ("length", 10.0),
+            ("step_size", 1.0),
+            ("i_popf_init", 0.01),
+            ("r_popf_init", 0.0),
+            ("incidence_rate", 0.3),
+            ("removal_rate", 0.1),
+            ("recovery_rate", 0.0),
+        ] {
+            values.insert(name.to_string(), value);
+        }
+        assert!(
+            schema.validate(&values).is_ok(),
+            "Expected valid configuration, got {:?}",
+            schema.validate(&values)
+        );
+        values.insert("incidence_rate".to_string(), 1.5);
+        assert!(
+            schema.validate(&values).is_err(),
+            "Expected incidence_rate out of bounds"
+        );
+    }
+
+    #[test]
+    fn test_scalar_types() {
+        let mut reference = Model::new();
+        reference.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        let mut single = Model::<f32>::zeroed();
+        single.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        single.init_popf();
+        single.run_rk4();
+        let mut quad = Model::<faer::fx128>::zeroed();
+        quad.configure(
+            60,
+            0.5,
+            faer::fx128::from_f64(0.01),
+            faer::fx128::from_f64(0.0),
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        quad.init_popf();
+        quad.run_rk4();
+        for t in 0..reference.i_popf.nrows() {
+            let expected = reference.i_popf[(t, 0)];
+            assert!(
+                ((single.i_popf[(t, 0)] as f64) - expected).abs() < 1e-5,
+                "Bad f32 i_popf at index {}, expected {} got {}",
+                t,
+                expected,
+                single.i_popf[(t, 0)]
+            );
+            assert!(
+                (quad.i_popf[(t, 0)].0 - expected).abs() < 1e-14,
+                "Bad fx128 i_popf at index {}, expected {} got {}",
+                t,
+                expected,
+                quad.i_popf[(t, 0)].0
+            );
+        }
+        let expected = reference.result("rk4");
+        let result = single.result("rk4");
+        assert!(
+            (result.values[(59, 1)] - expected.values[(59, 1)]).abs() < 1e-5,
+            "Bad f32 result i, expected {} got {}",
+            expected.values[(59, 1)],
+            result.values[(59, 1)]
+        );
+        let expected = reference.solve_equilibrium();
+        let equilibria = quad.solve_equilibrium();
+        assert!(
+            equilibria.len() == expected.len(),
+            "Bad fx128 equilibria, expected {} got {}",
+            expected.len(),
+            equilibria.len()
+        );
+    }
+
+    #[test]
+    fn test_run_into_sink() {
+        for solver in ["euler", "heun", "midpoint", "rk4", "patankar"] {
+            let mut model = Model::new();
+            model.configure(
+                30,
+                0.5,
+                0.01,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.05),
+            );
+            model.init_popf();
+            let mut sink = MemorySink::new();
+            model.run_into(solver, &mut sink).unwrap();
+            let expected = model.result(solver);
+            assert_eq!(
+                sink.names, expected.names,
+                "Bad {} column names, got {:?}",
+                solver, sink.names
+            );
+            assert_eq!(
+                sink.metadata
+                    .as_ref()
+                    .map(|metadata| metadata.solver.as_str()),
+                Some(solver),
+                "Bad {} metadata",
+                solver
+            );
+            assert_eq!(
+                sink.to_mat(),
+                expected.values,
+                "Bad {} written values",
+                solver
+            );
+        }
+    }
+
+    #[test]
+    fn test_tracing_events() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata};
+
+        /// Counts debug and info events.
+        struct Counter {
+            debug: Arc<AtomicUsize>,
+            info: Arc<AtomicUsize>,
+        }
+
+        impl tracing::Subscriber for Counter {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                return true;
+            }
+            fn new_span(&self, _span: &Attributes<'_>) -> Id {
+                return Id::from_u64(1);
+            }
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                match *event.metadata().level() {
+                    Level::DEBUG => self.debug.fetch_add(1, Ordering::SeqCst),
+                    Level::INFO => self.info.fetch_add(1, Ordering::SeqCst),
+                    _ => 0,
+                };
+            }
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let debug = Arc::new(AtomicUsize::new(0));
+        let info = Arc::new(AtomicUsize::new(0));
+        let counter = Counter {
+            debug: Arc::clone(&debug),
+            info: Arc::clone(&info),
+        };
+        tracing::subscriber::with_default(counter, || {
+            let mut model = Model::new();
+            model.configure(
+                10,
+                0.5,
+                0.01,
+                0.0,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                Rate::per_day(0.0),
+            );
+            model.init_popf();
+            model.run_rk4();
+        });
+        assert_eq!(
+            debug.load(Ordering::SeqCst),
+            19,
+            "Bad number of step events, expected 19 got {}",
+            debug.load(Ordering::SeqCst)
+        );
+        assert_eq!(
+            info.load(Ordering::SeqCst),
+            1,
+            "Bad number of summary events, expected 1 got {}",
+            info.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn test_step_into() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        model.changepoints(vec![(20.0, Rate::per_day(0.2))]);
+        model.init_popf();
+        model.run_rk4();
+        let mut state = model.state();
+        for t in 1..model.i_popf.nrows() {
+            model.step_into(&mut state);
+            assert_eq!(
+                state.index, t,
+                "Bad index, expected {} got {}",
+                t, state.index
+            );
+            for (name, value, expected) in [
+                ("s", state.s, model.s_popf[(t, 0)]),
+                ("i", state.i, model.i_popf[(t, 0)]),
+                ("r", state.r, model.r_popf[(t, 0)]),
+                ("incidence", state.incidence, model.incidence[(t, 0)]),
+                (
+                    "cumulative_incidence",
+                    state.cumulative_incidence,
+                    model.cumulative_incidence[(t, 0)],
+                ),
+            ] {
+                assert_eq!(
+                    value, expected,
+                    "Bad {} at index {}, expected {} got {}",
+                    name, t, expected, value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_steps() {
+        let mut model = Model::new();
+        model.configure(
+            30,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let steps: Vec<(f64, super::State)> = model.iter_steps().collect();
+        assert_eq!(
+            steps.len(),
+            model.i_popf.nrows(),
+            "Bad number of steps, expected {} got {}",
+            model.i_popf.nrows(),
+            steps.len()
+        );
+        for (t, state) in steps.iter() {
+            assert_eq!(
+                *t,
+                (state.index as f64) * 0.5,
+                "Bad time at index {}, got {}",
+                state.index,
+                t
+            );
+            assert_eq!(
+                state.i,
+                model.i_popf[(state.index, 0)],
+                "Bad i at index {}, expected {} got {}",
+                state.index,
+                model.i_popf[(state.index, 0)],
+                state.i
+            );
+        }
+        let early = model.iter_steps().take_while(|(t, _)| *t <= 10.0).count();
+        assert_eq!(early, 21, "Bad number of early steps, got {}", early);
+    }
+
+    #[test]
+    fn test_run_rk4_output() {
+        use crate::sirrs::output::Output;
+        let mut model = Model::new();
+        model.configure(
+            30,
+            0.1,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let daily = model.run_rk4_output(&Output::Every(10));
+        assert_eq!(
+            daily.times.len(),
+            30,
+            "Bad number of stored steps, expected 30 got {}",
+            daily.times.len()
+        );
+        for (row, t) in daily.times.iter().enumerate() {
+            assert_eq!(
+                daily.i_popf[row],
+                model.i_popf[(row * 10, 0)],
+                "Bad i_popf at time {}, expected {} got {}",
+                t,
+                model.i_popf[(row * 10, 0)],
+                daily.i_popf[row]
+            );
+        }
+        let reports = model.run_rk4_output(&Output::times(vec![7.0, 14.0]));
+        assert_eq!(
+            reports.cumulative_incidence,
+            vec![
+                model.cumulative_incidence[(70, 0)],
+                model.cumulative_incidence[(140, 0)]
+            ],
+            "Bad cumulative incidence at report times"
+        );
+    }
+
+    #[test]
+    fn test_second_order_methods() {
+        let solve = |step_size: f64, method: &str| {
+            let mut model = Model::new();
+            model.configure(
+                20,
+                step_size,
+                0.01,
+                0.0,
+                Rate::per_day(0.5),
+                Rate::per_day(0.1),
+                Rate::per_day(0.05),
+            );
+            model.init_popf();
+            match method {
+                "euler" => model.run_euler(),
+                "heun" => model.run_heun(),
+                "midpoint" => model.run_midpoint(),
+                "patankar" => model.run_patankar(),
+                _ => model.run_rk4(),
+            };
+            let t = ((15.0 / step_size) as usize, 0);
+            return model.i_popf[t];
+        };
+        let reference = solve(0.01, "rk4");
+        for (method, order) in [
+            ("euler", 1.0),
+            ("heun", 2.0),
+            ("midpoint", 2.0),
+            ("patankar", 2.0),
+        ] {
+            let coarse = (solve(0.1, method) - reference).abs();
+            let fine = (solve(0.05, method) - reference).abs();
+            let observed = (coarse / fine).log2();
+            assert!(
+                (observed - order).abs() < 0.3,
+                "Bad {} convergence order, expected {} got {}",
+                method,
+                order,
+                observed
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_patankar() {
+        let mut model = Model::new();
+        model.configure(
+            60,
+            7.0,
+            10.0,
+            0.0,
+            Rate::per_day(0.9),
+            Rate::per_day(0.6),
+            Rate::per_day(0.1),
+        );
+        model.counts(1000.0);
+        model.init_popf();
+        model.run_euler();
+        let negative = (0..model.grid().n_steps)
+            .any(|t| (model.s_popf[(t, 0)] < 0.0) | (model.i_popf[(t, 0)] < 0.0));
+        assert!(negative, "Expected euler to go negative at a coarse step");
+        model.run_patankar();
+        for t in 0..model.grid().n_steps {
+            let (s, i, r) = (
+                model.s_popf[(t, 0)],
+                model.i_popf[(t, 0)],
+                model.r_popf[(t, 0)],
+            );
+            assert!(
+                (s >= 0.0) & (i >= 0.0) & (r >= 0.0),
+                "Bad state at index {}, expected non-negative got ({}, {}, {})",
+                t,
+                s,
+                i,
+                r
+            );
+            assert!(
+                (s + i + r - 1000.0).abs() < 1e-9,
+                "Bad total at index {}, expected 1000 got {}",
+                t,
+                s + i + r
+            );
+        }
+        let mut reference = Model::new();
+        reference.configure(
+            60,
+            0.01,
+            10.0,
+            0.0,
+            Rate::per_day(0.9),
+            Rate::per_day(0.6),
+            Rate::per_day(0.1),
+        );
+        reference.counts(1000.0);
+        reference.init_popf();
+        reference.run_rk4();
+        let (coarse, fine) = (
+            model.cumulative_incidence[(model.grid().n_steps - 1, 0)],
+            reference.cumulative_incidence[(reference.grid().n_steps - 1, 0)],
+        );
+        assert!(
+            (coarse / fine - 1.0).abs() < 0.2,
+            "Bad cumulative incidence, expected about {} got {}",
+            fine,
+            coarse
+        );
+    }
+
+    #[test]
+    fn test_estimate_error() {
+        let mut reference = Model::new();
+        reference.configure(
+            20,
+            0.001,
+            0.01,
+            0.0,
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        reference.init_popf();
+        reference.run_rk4();
+        let mut model = Model::new();
+        model.configure(
+            20,
+            0.1,
+            0.01,
+            0.0,
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        for solver in ["euler", "heun", "rk4"] {
+            let estimate = model.estimate_error(solver);
+            let mut solved = Model::new();
+            solved.configure(
+                20,
+                0.1,
+                0.01,
+                0.0,
+                Rate::per_day(0.5),
+                Rate::per_day(0.1),
+                Rate::per_day(0.05),
+            );
+            solved.init_popf();
+            solved.run_solver(solver);
+            let actual = (0..solved.i_popf.nrows())
+                .map(|k| (solved.i_popf[(k, 0)] - reference.i_popf[(100 * k, 0)]).abs())
+                .fold(0.0, f64::max);
+            let ratio = estimate.i / actual;
+            assert!(
+                (0.8 < ratio) & (ratio < 1.25),
+                "Bad {} error estimate, expected {} got {}",
+                solver,
+                actual,
+                estimate.i
+            );
+        }
+        let estimate = model.refine_step_size("euler", 1e-4, 10);
+        assert!(
+            estimate.max() <= 1e-4,
+            "Expected the tolerance to be met, got {:?}",
+            estimate
+        );
+        assert_eq!(
+            model.step_size, estimate.step_size,
+            "Bad step size, expected {} got {}",
+            estimate.step_size, model.step_size
+        );
+        assert_eq!(
+            model.s_popf.nrows(),
+            model.grid().n_steps,
+            "Expected outputs on the refined grid"
+        );
+        assert!(
+            model.r_popf[(model.grid().n_steps - 1, 0)] > 0.0,
+            "Expected the model solved at the refined step size"
+        );
+    }
+
+    #[test]
+    fn test_solve_equilibrium() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.1,
+            Rate::per_day(0.5),
+            Rate::per_day(0.0),
+            Rate::per_day(0.2),
+        );
+        let equilibria = model.solve_equilibrium();
+        assert_eq!(
+            equilibria.len(),
+            2,
+            "Expected two equilibria, got {:?}",
+            equilibria
+        );
+        let free = &equilibria[0];
+        assert!(
+            !free.endemic & !free.stable,
+            "Expected an unstable disease-free equilibrium with R0 > 1, got {:?}",
+            free
+        );
+        let endemic = &equilibria[1];
+        let expected = 0.9 - (0.2 / 0.5);
+        assert!(
+            (endemic.i - expected).abs() < 1e-12,
+            "Bad endemic i, expected {} got {}",
+            expected,
+            endemic.i
+        );
+        assert!(endemic.stable, "Expected a stable endemic equilibrium");
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+            Rate::per_day(0.2),
+        );
+        let equilibria = model.solve_equilibrium();
+        assert_eq!(
+            equilibria.len(),
+            1,
+            "Expected only the disease-free equilibrium, got {:?}",
+            equilibria
+        );
+        assert!(
+            equilibria[0].stable,
+            "Expected a stable disease-free equilibrium with R0 < 1"
+        );
+    }
+
+    #[test]
+    fn test_stability_at() {
+        let mut model = Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let stability = model.stability_at(0.0, &[1.0, 0.0, 0.0]);
+        assert!(
+            (stability.growth_rate - 0.2).abs() < 1e-12,
+            "Bad growth rate, expected 0.2 got {}",
+            stability.growth_rate
+        );
+        model.changepoints(vec![(5.0, Rate::per_day(0.05))]);
+        let stability = model.stability_at(6.0, &[1.0, 0.0, 0.0]);
+        assert!(
+            stability.stable(),
+            "Expected a stable disease-free state with R0 < 1, got {:?}",
+            stability
+        );
+    }
 }
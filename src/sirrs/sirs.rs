@@ -0,0 +1,330 @@
+//! SIRS model with waning immunity and reinfection tracking.
+//!
+//! Immunity is lost at a waning rate, returning removed individuals to
+//! susceptibility. Susceptibles are split by infection history, so first
+//! infections and reinfections are counted separately. Allows transition
+//! rates:
+//!  - S_naive → I, first infections
+//!  - S_waned → I, reinfections, at a relative susceptibility
+//!  - I → R
+//!  - R → S_waned
+//!
+//! With no waning this is the SIR model without recovery.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Fraction, Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run an SIRS model.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction. Everyone else starts naive.
+    pub i_popf_init: f64,
+    /// Transition rate from S into I. Must be in [0, 1].
+    pub incidence_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Transition rate from R into S, the inverse of the mean duration of
+    /// immunity.
+    pub waning_rate: f64,
+    /// Susceptibility of the waned relative to the naive. Must be in [0, 1].
+    pub reinfection_susceptibility: f64,
+    /// Never infected population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_naive_popf: Mat<f64>,
+    /// Susceptible again after waning population fraction at each index. 1D
+    /// Array with one element per index of [`Model::grid`].
+    pub s_waned_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// Cumulative first infections, as a population fraction, up to each
+    /// index. 1D Array with one element per index of [`Model::grid`].
+    pub cumulative_first_infections: Mat<f64>,
+    /// Cumulative reinfections, as a population fraction, up to each index.
+    /// 1D Array with one element per index of [`Model::grid`].
+    pub cumulative_reinfections: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            waning_rate: 0.0,
+            reinfection_susceptibility: 1.0,
+            s_naive_popf: Mat::new(),
+            s_waned_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            cumulative_first_infections: Mat::new(),
+            cumulative_reinfections: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters. Rates are per day.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        waning_rate: Rate,
+        reinfection_susceptibility: Fraction,
+    ) -> &mut Self {
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.waning_rate = waning_rate.per(TimeUnit::Day);
+        self.reinfection_susceptibility = reinfection_susceptibility.value();
+        self.s_naive_popf = Mat::zeros(n_steps, 1);
+        self.s_waned_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.cumulative_first_infections = Mat::zeros(n_steps, 1);
+        self.cumulative_reinfections = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment equal to the corresponding initial population fraction.
+    pub fn init_popf(&mut self) -> &mut Model {
+        self.store(
+            0,
+            &[1.0 - self.i_popf_init, 0.0, self.i_popf_init, 0.0, 0.0, 0.0],
+        );
+        return self;
+    }
+
+    /// Pack the state at index `t` into a vector ordered S_naive, S_waned,
+    /// I, R, cumulative first infections, cumulative reinfections.
+    fn load(&self, t: usize) -> [f64; 6] {
+        return [
+            self.s_naive_popf[(t, 0)],
+            self.s_waned_popf[(t, 0)],
+            self.i_popf[(t, 0)],
+            self.r_popf[(t, 0)],
+            self.cumulative_first_infections[(t, 0)],
+            self.cumulative_reinfections[(t, 0)],
+        ];
+    }
+
+    /// Unpack a state vector into index `t`.
+    fn store(&mut self, t: usize, y: &[f64; 6]) {
+        self.s_naive_popf[(t, 0)] = y[0];
+        self.s_waned_popf[(t, 0)] = y[1];
+        self.i_popf[(t, 0)] = y[2];
+        self.r_popf[(t, 0)] = y[3];
+        self.cumulative_first_infections[(t, 0)] = y[4];
+        self.cumulative_reinfections[(t, 0)] = y[5];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64; 6]) -> [f64; 6] {
+        let first = self.incidence_rate * y[0] * y[2];
+        let reinfection = self.reinfection_susceptibility * self.incidence_rate * y[1] * y[2];
+        let removal = self.removal_rate * y[2];
+        let waning = self.waning_rate * y[3];
+        return [
+            -first,
+            waning - reinfection,
+            first + reinfection - removal,
+            removal - waning,
+            first,
+            reinfection,
+        ];
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            self.store(t + 1, &std::array::from_fn(|j| y[j] + (h * d[j])));
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let k2 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])));
+            let k3 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])));
+            let k4 = self.derivatives(&std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.store(
+                t + 1,
+                &std::array::from_fn(|j| {
+                    y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+                }),
+            );
+        }
+        return self;
+    }
+
+    /// Susceptible population fraction, naive and waned, at each index.
+    pub fn s_popf(&self) -> Mat<f64> {
+        return Mat::from_fn(self.s_naive_popf.nrows(), 1, |t, _| {
+            self.s_naive_popf[(t, 0)] + self.s_waned_popf[(t, 0)]
+        });
+    }
+
+    /// Share of cumulative infections up to each index that are
+    /// reinfections. Zero before any infection.
+    pub fn cumulative_reinfection_share(&self) -> Mat<f64> {
+        return Mat::from_fn(self.cumulative_reinfections.nrows(), 1, |t, _| {
+            let first = self.cumulative_first_infections[(t, 0)];
+            let re = self.cumulative_reinfections[(t, 0)];
+            if first + re <= 0.0 {
+                return 0.0;
+            }
+            return re / (first + re);
+        });
+    }
+
+    /// Share of new infections over the step ending at each index that are
+    /// reinfections. Zero at index 0 and over steps without infections.
+    pub fn reinfection_share(&self) -> Mat<f64> {
+        return Mat::from_fn(self.cumulative_reinfections.nrows(), 1, |t, _| {
+            if t == 0 {
+                return 0.0;
+            }
+            let first = self.cumulative_first_infections[(t, 0)]
+                - self.cumulative_first_infections[(t - 1, 0)];
+            let re =
+                self.cumulative_reinfections[(t, 0)] - self.cumulative_reinfections[(t - 1, 0)];
+            if first + re <= 0.0 {
+                return 0.0;
+            }
+            return re / (first + re);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sirs::Model;
+    use crate::sirrs::units::{Fraction, Rate};
+
+    #[test]
+    fn test_no_waning_matches_sir() {
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+            Fraction::of(1.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = crate::sirrs::sir::Model::new();
+        sir.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+        let last = model.i_popf.nrows() - 1;
+        assert_eq!(
+            model.cumulative_reinfections[(last, 0)],
+            0.0,
+            "Expected no reinfections without waning"
+        );
+    }
+
+    #[test]
+    fn test_reinfection_share() {
+        let mut slow = Model::new();
+        slow.configure(
+            400,
+            0.5,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.005),
+            Fraction::of(1.0),
+        );
+        slow.init_popf();
+        slow.run_rk4();
+        let mut fast = Model::new();
+        fast.configure(
+            400,
+            0.5,
+            0.01,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+            Fraction::of(1.0),
+        );
+        fast.init_popf();
+        fast.run_rk4();
+        let last = fast.i_popf.nrows() - 1;
+        for t in 0..=last {
+            let total = fast.s_popf()[(t, 0)] + fast.i_popf[(t, 0)] + fast.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+        let slow_share = slow.cumulative_reinfection_share()[(last, 0)];
+        let fast_share = fast.cumulative_reinfection_share()[(last, 0)];
+        assert!(
+            (0.0 < slow_share) & (slow_share < fast_share) & (fast_share < 1.0),
+            "Expected faster waning to raise the reinfection share, got {} and {}",
+            slow_share,
+            fast_share
+        );
+        // At the endemic equilibrium almost every new infection is a
+        // reinfection, as the naive are used up.
+        let share = fast.reinfection_share()[(last, 0)];
+        assert!(
+            share > 0.9,
+            "Bad late reinfection share, expected above 0.9 got {}",
+            share
+        );
+    }
+}
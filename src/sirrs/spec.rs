@@ -0,0 +1,822 @@
+//! Declarative model specifications.
+//!
+//! A [`ModelSpec`] describes a compartmental model in TOML: its
+//! compartments and initial values, named parameters, transitions between
+//! compartments with per capita rates written as expressions, and
+//! interventions on the parameters. The spec is a [`System`], so any model
+//! written this way is solved by a [`Pipeline`] with observation models and
+//! output sinks, without writing Rust.
+//!
+//! ```toml
+//! name = "seir"
+//! description = "SEIR model with a lockdown"
+//! compartments = ["S", "E", "I", "R"]
+//!
+//! [initial]
+//! I = 0.01
+//!
+//! [parameters]
+//! beta = 0.4
+//! sigma = 0.2
+//! gamma = 0.1
+//!
+//! [[transitions]]
+//! from = "S"
+//! to = "E"
+//! rate = "beta * I / N"
+//! incidence = true
+//!
+//! [[transitions]]
+//! from = "E"
+//! to = "I"
+//! rate = "sigma"
+//!
+//! [[transitions]]
+//! from = "I"
+//! to = "R"
+//! rate = "gamma"
+//!
+//! [[interventions]]
+//! type = "scale"
+//! parameter = "beta"
+//! start = 20
+//! end = 50
+//! multiplier = 0.5
+//! ```
+//!
+//! Compartments missing from `initial` start at 0, except the first, which
+//! by default holds the remainder of a total of 1. The flux of a transition
+//! is its rate times the population of `from`, and transitions marked
+//! `incidence` are counted as new cases.
+//!
+//! Rates are expressions of numbers, parameters, compartments, `t` (time)
+//! and `N` (the sum of all compartments), with `+`, `-`, `*`, `/`, `^`,
+//! parentheses and the functions `exp`, `ln`, `sqrt`, `sin` and `cos`.
+//!
+//! Interventions are `type = "scale"`, a [`ScaleParameter`] with
+//! `parameter`, `start`, `end` and `multiplier`, or `type = "schedule"`, a
+//! [`ScheduleParameter`] with `parameter`, `initial` and `breakpoints` as
+//! `[t, value]` pairs.
+use crate::sirrs::pipeline::{
+    Intervention, Parameters, Pipeline, ScaleParameter, ScheduleParameter, System,
+};
+use crate::sirrs::reproducible::{exp, ln, powf};
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::schema::ModelSchema;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Build an `InvalidData` error about a spec.
+fn invalid(message: String) -> Error {
+    return Error::new(ErrorKind::InvalidData, message);
+}
+
+/// A function callable in a rate expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Exp,
+    Ln,
+    Sqrt,
+    Sin,
+    Cos,
+}
+
+/// A rate expression, with names resolved to state and parameter indices.
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Number(f64),
+    State(usize),
+    Parameter(usize),
+    Time,
+    Total,
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
+    Call(Function, Box<Expression>),
+}
+
+impl Expression {
+    /// Value at time `t`, state `y` and parameter values `p`, in the order
+    /// names were resolved.
+    fn eval(&self, t: f64, y: &[f64], p: &[f64]) -> f64 {
+        return match self {
+            Expression::Number(x) => *x,
+            Expression::State(j) => y[*j],
+            Expression::Parameter(j) => p[*j],
+            Expression::Time => t,
+            Expression::Total => y.iter().sum(),
+            Expression::Negate(a) => -a.eval(t, y, p),
+            Expression::Add(a, b) => a.eval(t, y, p) + b.eval(t, y, p),
+            Expression::Subtract(a, b) => a.eval(t, y, p) - b.eval(t, y, p),
+            Expression::Multiply(a, b) => a.eval(t, y, p) * b.eval(t, y, p),
+            Expression::Divide(a, b) => a.eval(t, y, p) / b.eval(t, y, p),
+            Expression::Power(a, b) => powf(a.eval(t, y, p), b.eval(t, y, p)),
+            Expression::Call(f, a) => {
+                let x = a.eval(t, y, p);
+                match f {
+                    Function::Exp => exp(x),
+                    Function::Ln => ln(x),
+                    Function::Sqrt => x.sqrt(),
+                    Function::Sin => libm::sin(x),
+                    Function::Cos => libm::cos(x),
+                }
+            }
+        };
+    }
+}
+
+/// Recursive descent parser of rate expressions.
+struct ExpressionParser<'a> {
+    chars: Vec<char>,
+    at: usize,
+    compartments: &'a [String],
+    parameters: &'a [String],
+}
+
+impl ExpressionParser<'_> {
+    fn parse(
+        text: &str,
+        compartments: &[String],
+        parameters: &[String],
+    ) -> Result<Expression, String> {
+        let mut parser = ExpressionParser {
+            chars: text.chars().collect(),
+            at: 0,
+            compartments,
+            parameters,
+        };
+        let expression = parser.sum()?;
+        parser.skip_whitespace();
+        if parser.at < parser.chars.len() {
+            return Err(format!("unexpected '{}'", parser.chars[parser.at]));
+        }
+        return Ok(expression);
+    }
+
+    fn skip_whitespace(&mut self) {
+        while (self.at < self.chars.len()) && self.chars[self.at].is_whitespace() {
+            self.at += 1;
+        }
+    }
+
+    /// The next non-whitespace character, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        return self.chars.get(self.at).copied();
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut left = self.product()?;
+        while let Some(op) = self.peek().filter(|c| (*c == '+') | (*c == '-')) {
+            self.at += 1;
+            let right = Box::new(self.product()?);
+            left = match op {
+                '+' => Expression::Add(Box::new(left), right),
+                _ => Expression::Subtract(Box::new(left), right),
+            };
+        }
+        return Ok(left);
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek().filter(|c| (*c == '*') | (*c == '/')) {
+            self.at += 1;
+            let right = Box::new(self.unary()?);
+            left = match op {
+                '*' => Expression::Multiply(Box::new(left), right),
+                _ => Expression::Divide(Box::new(left), right),
+            };
+        }
+        return Ok(left);
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.peek() == Some('-') {
+            self.at += 1;
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.peek() == Some('^') {
+            self.at += 1;
+            return Ok(Expression::Power(Box::new(base), Box::new(self.unary()?)));
+        }
+        return Ok(base);
+    }
+
+    fn atom(&mut self) -> Result<Expression, String> {
+        let c = self.peek().ok_or_else(|| "unexpected end".to_string())?;
+        if c == '(' {
+            self.at += 1;
+            let inner = self.sum()?;
+            if self.peek() != Some(')') {
+                return Err("expected ')'".to_string());
+            }
+            self.at += 1;
+            return Ok(inner);
+        }
+        let start = self.at;
+        if c.is_ascii_digit() | (c == '.') {
+            while (self.at < self.chars.len())
+                && (self.chars[self.at].is_ascii_digit() | ".eE".contains(self.chars[self.at])
+                    || (("+-".contains(self.chars[self.at]))
+                        && "eE".contains(self.chars[self.at - 1])))
+            {
+                self.at += 1;
+            }
+            let text: String = self.chars[start..self.at].iter().collect();
+            return text
+                .parse::<f64>()
+                .map(Expression::Number)
+                .map_err(|_| format!("bad number '{}'", text));
+        }
+        if !(c.is_alphabetic() | (c == '_')) {
+            return Err(format!("unexpected '{}'", c));
+        }
+        while (self.at < self.chars.len())
+            && (self.chars[self.at].is_alphanumeric() | (self.chars[self.at] == '_'))
+        {
+            self.at += 1;
+        }
+        let name: String = self.chars[start..self.at].iter().collect();
+        if self.peek() == Some('(') {
+            let function = match name.as_str() {
+                "exp" => Function::Exp,
+                "ln" => Function::Ln,
+                "sqrt" => Function::Sqrt,
+                "sin" => Function::Sin,
+                "cos" => Function::Cos,
+                _ => return Err(format!("unknown function '{}'", name)),
+            };
+            self.at += 1;
+            let argument = self.sum()?;
+            if self.peek() != Some(')') {
+                return Err("expected ')'".to_string());
+            }
+            self.at += 1;
+            return Ok(Expression::Call(function, Box::new(argument)));
+        }
+        if let Some(j) = self.compartments.iter().position(|c| *c == name) {
+            return Ok(Expression::State(j));
+        }
+        if let Some(j) = self.parameters.iter().position(|p| *p == name) {
+            return Ok(Expression::Parameter(j));
+        }
+        return match name.as_str() {
+            "t" => Ok(Expression::Time),
+            "N" => Ok(Expression::Total),
+            _ => Err(format!("unknown name '{}'", name)),
+        };
+    }
+}
+
+/// A transition between two compartments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    /// Source compartment.
+    pub from: String,
+    /// Destination compartment.
+    pub to: String,
+    /// Per capita rate of the transition, as an expression.
+    pub rate: String,
+    /// Whether the flux is counted as incidence.
+    pub incidence: bool,
+}
+
+/// An intervention on a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterventionSpec {
+    /// See [`ScaleParameter`].
+    Scale {
+        /// Name of the parameter to scale.
+        parameter: String,
+        /// Time the intervention starts.
+        start: f64,
+        /// Time the intervention ends.
+        end: f64,
+        /// Factor applied to the parameter.
+        multiplier: f64,
+    },
+    /// See [`ScheduleParameter`].
+    Schedule {
+        /// Name of the parameter to set.
+        parameter: String,
+        /// Value of the parameter over time.
+        schedule: RateSchedule,
+    },
+}
+
+impl InterventionSpec {
+    /// The intervention as a pipeline layer.
+    pub fn build(&self) -> Box<dyn Intervention> {
+        return match self {
+            InterventionSpec::Scale {
+                parameter,
+                start,
+                end,
+                multiplier,
+            } => Box::new(ScaleParameter::new(parameter, *start, *end, *multiplier)),
+            InterventionSpec::Schedule {
+                parameter,
+                schedule,
+            } => Box::new(ScheduleParameter::new(parameter, schedule.clone())),
+        };
+    }
+}
+
+/// A compartmental model described by a spec.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSpec {
+    /// Name of the model.
+    pub name: String,
+    /// What the model is.
+    pub description: String,
+    /// Names of the compartments, in state vector order.
+    pub compartments: Vec<String>,
+    /// Initial value of each compartment.
+    pub initial: Vec<f64>,
+    /// Parameter values before any intervention.
+    pub parameters: Parameters,
+    /// Transitions between compartments.
+    pub transitions: Vec<Transition>,
+    /// Interventions, applied in order.
+    pub interventions: Vec<InterventionSpec>,
+    /// Compiled rate of each transition.
+    rates: Vec<Expression>,
+    /// Source and destination index of each transition.
+    ends: Vec<(usize, usize)>,
+}
+
+/// A number from a TOML value, integer or float.
+fn number(value: &Value, what: &str) -> Result<f64, Error> {
+    return value
+        .as_float()
+        .or(value.as_integer().map(|x| x as f64))
+        .ok_or_else(|| invalid(format!("{} must be a number", what)));
+}
+
+/// A string from a TOML value.
+fn string(value: &Value, what: &str) -> Result<String, Error> {
+    return value
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| invalid(format!("{} must be a string", what)));
+}
+
+/// Member `key` of `table`, which must be present.
+fn required<'a>(table: &'a Table, key: &str, what: &str) -> Result<&'a Value, Error> {
+    return table
+        .get(key)
+        .ok_or_else(|| invalid(format!("{} is missing '{}'", what, key)));
+}
+
+/// Check `table` has no keys outside `known`.
+fn check_keys(table: &Table, known: &[&str], what: &str) -> Result<(), Error> {
+    if let Some(key) = table.keys().find(|k| !known.contains(&k.as_str())) {
+        return Err(invalid(format!("unknown key '{}' in {}", key, what)));
+    }
+    return Ok(());
+}
+
+/// Tables of an array of tables `key`, empty if missing.
+fn tables<'a>(table: &'a Table, key: &str) -> Result<Vec<&'a Table>, Error> {
+    let Some(value) = table.get(key) else {
+        return Ok(Vec::new());
+    };
+    let items = value
+        .as_array()
+        .ok_or_else(|| invalid(format!("{} must be an array of tables", key)))?;
+    return items
+        .iter()
+        .map(|item| {
+            item.as_table()
+                .ok_or_else(|| invalid(format!("{} must be an array of tables", key)))
+        })
+        .collect();
+}
+
+impl ModelSpec {
+    /// Parse a spec from TOML text, checking every name and rate.
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        let table: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| invalid(format!("bad toml: {}", e.message())))?;
+        check_keys(
+            &table,
+            &[
+                "name",
+                "description",
+                "compartments",
+                "initial",
+                "parameters",
+                "transitions",
+                "interventions",
+            ],
+            "spec",
+        )?;
+        let name = string(required(&table, "name", "spec")?, "name")?;
+        let description = match table.get("description") {
+            Some(value) => string(value, "description")?,
+            None => String::new(),
+        };
+        let compartments: Vec<String> = required(&table, "compartments", "spec")?
+            .as_array()
+            .ok_or_else(|| invalid("compartments must be an array".to_string()))?
+            .iter()
+            .map(|value| string(value, "compartment"))
+            .collect::<Result<_, _>>()?;
+        if compartments.is_empty() {
+            return Err(invalid("expected at least one compartment".to_string()));
+        }
+        for (j, compartment) in compartments.iter().enumerate() {
+            if compartments[..j].contains(compartment) {
+                return Err(invalid(format!("repeated compartment '{}'", compartment)));
+            }
+        }
+
+        let mut parameters = Parameters::new();
+        if let Some(value) = table.get("parameters") {
+            let values = value
+                .as_table()
+                .ok_or_else(|| invalid("parameters must be a table".to_string()))?;
+            for (key, value) in values.iter() {
+                if compartments.contains(key) | (key == "t") | (key == "N") {
+                    return Err(invalid(format!(
+                        "parameter '{}' shadows a compartment or built-in name",
+                        key
+                    )));
+                }
+                parameters.insert(key.clone(), number(value, &format!("parameter {}", key))?);
+            }
+        }
+        let parameter_names: Vec<String> = parameters.keys().cloned().collect();
+
+        let mut initial = vec![0.0; compartments.len()];
+        let mut given = vec![false; compartments.len()];
+        if let Some(value) = table.get("initial") {
+            let values = value
+                .as_table()
+                .ok_or_else(|| invalid("initial must be a table".to_string()))?;
+            for (key, value) in values.iter() {
+                let j = compartments.iter().position(|c| c == key).ok_or_else(|| {
+                    invalid(format!("initial value of unknown compartment '{}'", key))
+                })?;
+                initial[j] = number(value, &format!("initial {}", key))?;
+                given[j] = true;
+            }
+        }
+        if !given[0] {
+            initial[0] = 1.0 - initial[1..].iter().sum::<f64>();
+        }
+
+        let index = |name: &str, what: &str| -> Result<usize, Error> {
+            return compartments
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| invalid(format!("{} names unknown compartment '{}'", what, name)));
+        };
+        let mut transitions = Vec::new();
+        let mut rates = Vec::new();
+        let mut ends = Vec::new();
+        for (k, item) in tables(&table, "transitions")?.into_iter().enumerate() {
+            let what = format!("transition {}", k + 1);
+            check_keys(item, &["from", "to", "rate", "incidence"], &what)?;
+            let transition = Transition {
+                from: string(required(item, "from", &what)?, "from")?,
+                to: string(required(item, "to", &what)?, "to")?,
+                rate: string(required(item, "rate", &what)?, "rate")?,
+                incidence: match item.get("incidence") {
+                    Some(value) => value.as_bool().ok_or_else(|| {
+                        invalid(format!("incidence of {} must be true or false", what))
+                    })?,
+                    None => false,
+                },
+            };
+            ends.push((
+                index(&transition.from, &what)?,
+                index(&transition.to, &what)?,
+            ));
+            let rate = ExpressionParser::parse(&transition.rate, &compartments, &parameter_names)
+                .map_err(|e| {
+                invalid(format!("bad rate '{}' in {}: {}", transition.rate, what, e))
+            })?;
+            rates.push(rate);
+            transitions.push(transition);
+        }
+
+        let mut interventions = Vec::new();
+        for (k, item) in tables(&table, "interventions")?.into_iter().enumerate() {
+            let what = format!("intervention {}", k + 1);
+            let parameter = string(required(item, "parameter", &what)?, "parameter")?;
+            if !parameters.contains_key(&parameter) {
+                return Err(invalid(format!(
+                    "{} names unknown parameter '{}'",
+                    what, parameter
+                )));
+            }
+            let kind = string(required(item, "type", &what)?, "type")?;
+            let intervention = match kind.as_str() {
+                "scale" => {
+                    check_keys(
+                        item,
+                        &["type", "parameter", "start", "end", "multiplier"],
+                        &what,
+                    )?;
+                    InterventionSpec::Scale {
+                        parameter,
+                        start: number(required(item, "start", &what)?, "start")?,
+                        end: number(required(item, "end", &what)?, "end")?,
+                        multiplier: number(required(item, "multiplier", &what)?, "multiplier")?,
+                    }
+                }
+                "schedule" => {
+                    check_keys(
+                        item,
+                        &["type", "parameter", "initial", "breakpoints"],
+                        &what,
+                    )?;
+                    let mut breakpoints = Vec::new();
+                    if let Some(value) = item.get("breakpoints") {
+                        let pairs = value.as_array().ok_or_else(|| {
+                            invalid(format!("breakpoints of {} must be an array", what))
+                        })?;
+                        for pair in pairs.iter() {
+                            match pair.as_array().map(|p| p.as_slice()) {
+                                Some([t, value]) => breakpoints.push((
+                                    number(t, "breakpoint time")?,
+                                    number(value, "breakpoint value")?,
+                                )),
+                                _ => {
+                                    return Err(invalid(format!(
+                                        "breakpoints of {} must be [t, value] pairs",
+                                        what
+                                    )));
+                                }
+                            }
+                        }
+                    }
+                    InterventionSpec::Schedule {
+                        parameter,
+                        schedule: RateSchedule::new(
+                            number(required(item, "initial", &what)?, "initial")?,
+                            breakpoints,
+                        ),
+                    }
+                }
+                other => {
+                    return Err(invalid(format!(
+                        "{} has unknown type '{}', expected scale or schedule",
+                        what, other
+                    )));
+                }
+            };
+            interventions.push(intervention);
+        }
+
+        return Ok(Self {
+            name,
+            description,
+            compartments,
+            initial,
+            parameters,
+            transitions,
+            interventions,
+            rates,
+            ends,
+        });
+    }
+
+    /// Description of the model's structure and parameters. Parameters are
+    /// bounded below by 0 and have no units.
+    pub fn schema(&self) -> ModelSchema {
+        let mut schema = ModelSchema::new(&self.name, &self.description);
+        for compartment in self.compartments.iter() {
+            schema.compartment(compartment);
+        }
+        for transition in self.transitions.iter() {
+            schema.flow(&transition.from, &transition.to, &transition.rate);
+        }
+        for name in self.parameters.keys() {
+            schema.parameter(name, "", 0.0, f64::INFINITY, "");
+        }
+        return schema;
+    }
+
+    /// A pipeline solving the model with its interventions, configured with
+    /// `length` and `step_size`.
+    pub fn pipeline(&self, length: usize, step_size: f64) -> Pipeline {
+        let mut pipeline = Pipeline::new(Box::new(self.clone()));
+        pipeline.configure(length, step_size);
+        for intervention in self.interventions.iter() {
+            pipeline.intervention(intervention.build());
+        }
+        return pipeline;
+    }
+
+    /// Flux of every transition at time `t`.
+    fn fluxes(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let p: Vec<f64> = self
+            .parameters
+            .keys()
+            .map(|name| parameters[name])
+            .collect();
+        return self
+            .rates
+            .iter()
+            .zip(self.ends.iter())
+            .map(|(rate, (from, _))| rate.eval(t, y, &p) * y[*from])
+            .collect();
+    }
+}
+
+/// Read a spec from a TOML file. See [`ModelSpec::from_toml`].
+pub fn read_spec(path: impl AsRef<Path>) -> Result<ModelSpec, Error> {
+    let text = fs::read_to_string(path)?;
+    return ModelSpec::from_toml(&text);
+}
+
+/// The state is the compartments, in the order listed.
+impl System for ModelSpec {
+    fn state_names(&self) -> Vec<String> {
+        return self.compartments.clone();
+    }
+
+    fn initial_state(&self) -> Vec<f64> {
+        return self.initial.clone();
+    }
+
+    fn parameters(&self) -> Parameters {
+        return self.parameters.clone();
+    }
+
+    fn derivatives(&self, t: f64, y: &[f64], parameters: &Parameters) -> Vec<f64> {
+        let mut d = vec![0.0; y.len()];
+        for (flux, (from, to)) in self.fluxes(t, y, parameters).iter().zip(self.ends.iter()) {
+            d[*from] -= flux;
+            d[*to] += flux;
+        }
+        return d;
+    }
+
+    fn incidence(&self, t: f64, y: &[f64], parameters: &Parameters) -> f64 {
+        return self
+            .fluxes(t, y, parameters)
+            .iter()
+            .zip(self.transitions.iter())
+            .filter(|(_, transition)| transition.incidence)
+            .map(|(flux, _)| flux)
+            .sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::pipeline::{Pipeline, ScaleParameter};
+    use crate::sirrs::sir;
+    use crate::sirrs::spec::ModelSpec;
+    use crate::sirrs::units::Rate;
+
+    const SIR: &str = r#"
+name = "sir"
+compartments = ["S", "I", "R"]
+
+[initial]
+I = 0.01
+
+[parameters]
+incidence_rate = 0.4
+removal_rate = 0.1
+
+[[transitions]]
+from = "S"
+to = "I"
+rate = "incidence_rate * I / N"
+incidence = true
+
+[[transitions]]
+from = "I"
+to = "R"
+rate = "removal_rate"
+
+[[interventions]]
+type = "scale"
+parameter = "incidence_rate"
+start = 10
+end = 30
+multiplier = 0.25
+"#;
+
+    #[test]
+    fn test_matches_sir() {
+        let spec = ModelSpec::from_toml(SIR).unwrap();
+        assert_eq!(spec.initial, vec![0.99, 0.01, 0.0], "Bad initial state");
+        let mut pipeline = spec.pipeline(50, 0.5);
+        pipeline.run_rk4();
+        let mut model = sir::Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut expected = Pipeline::new(Box::new(model));
+        expected.configure(50, 0.5);
+        expected.intervention(Box::new(ScaleParameter::new(
+            "incidence_rate",
+            10.0,
+            30.0,
+            0.25,
+        )));
+        expected.run_rk4();
+        for t in 0..expected.state.nrows() {
+            for j in 0..3 {
+                assert!(
+                    (pipeline.state[(t, j)] - expected.state[(t, j)]).abs() < 1e-12,
+                    "Bad state {} at index {}, expected {} got {}",
+                    j,
+                    t,
+                    expected.state[(t, j)],
+                    pipeline.state[(t, j)]
+                );
+            }
+            assert!(
+                (pipeline.incidence[(t, 0)] - expected.incidence[(t, 0)]).abs() < 1e-12,
+                "Bad incidence at index {}, expected {} got {}",
+                t,
+                expected.incidence[(t, 0)],
+                pipeline.incidence[(t, 0)]
+            );
+        }
+        let schema = spec.schema();
+        assert_eq!(
+            schema.flows[0].rate, "incidence_rate * I / N",
+            "Bad schema flow rate"
+        );
+    }
+
+    #[test]
+    fn test_rate_expressions() {
+        let spec = ModelSpec::from_toml(
+            r#"
+name = "seasonal"
+compartments = ["S", "I"]
+parameters = { a = 0.5, b = 2 }
+
+[[transitions]]
+from = "S"
+to = "I"
+rate = "-a^2 + b * (1 + cos(2 * 3.14159 * t / 365)) / 2e0 + exp(ln(2)) - sqrt(4)"
+"#,
+        )
+        .unwrap();
+        let fluxes = spec.fluxes(0.0, &[0.5, 0.5], &spec.parameters);
+        assert!(
+            (fluxes[0] - (0.5 * 1.75)).abs() < 1e-12,
+            "Bad flux, expected 0.875 got {}",
+            fluxes[0]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for (edit, message) in [
+            (
+                ("rate = \"removal_rate\"", "rate = \"removal * I\""),
+                "unknown name 'removal'",
+            ),
+            (("to = \"R\"", "to = \"D\""), "unknown compartment 'D'"),
+            (
+                ("rate = \"removal_rate\"", "rate = \"(removal_rate\""),
+                "expected ')'",
+            ),
+            (
+                ("type = \"scale\"", "type = \"lockdown\""),
+                "unknown type 'lockdown'",
+            ),
+            (("I = 0.01", "I = \"high\""), "initial I must be a number"),
+            (
+                ("incidence = true", "incidence = true\nweight = 2"),
+                "unknown key 'weight'",
+            ),
+            (("compartments = [", "compartments = "), "bad toml"),
+        ] {
+            let error = ModelSpec::from_toml(&SIR.replace(edit.0, edit.1))
+                .unwrap_err()
+                .to_string();
+            assert!(
+                error.contains(message),
+                "Bad error, expected {} got {}",
+                message,
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,600 @@
+//! Exact stochastic simulation of the SIR model.
+//!
+//! Individual infection and removal events are simulated in continuous time
+//! by Gillespie's direct method, in counts:
+//!  - S → I at rate β S I / N + ι(t) S
+//!  - I → R at rate γ I
+//!
+//! where `ι(t)` is an optional importation rate, infections from outside the
+//! population, which can re-seed a run after local extinction. It is
+//! piecewise constant, and waiting times are redrawn at each breakpoint,
+//! which is exact as the process is memoryless.
+//!
+//! Large populations produce enormous event streams, so how events are kept
+//! is configurable. They may all be stored, a uniform random sample of fixed
+//! size may be kept by reservoir sampling, or only running summaries of event
+//! times may be accumulated. The summaries are always kept and are exact, and
+//! a reservoir sample is an unbiased sample of the whole stream, so memory
+//! can be capped without biasing summaries.
+//!
+//! A full event log can be written out and later replayed through
+//! [`Model::replay`], which rebuilds the counts and summaries without
+//! re-simulating, so observation models can be varied over the same
+//! realization of the dynamics.
+use crate::sirrs::reproducible::ln;
+use crate::sirrs::rng;
+use crate::sirrs::schedule::RateSchedule;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+use rand::Rng;
+use std::io::{self, Write};
+
+/// Kind of an individual event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A susceptible individual was infected.
+    Infection,
+    /// An infectious individual was removed.
+    Removal,
+}
+
+/// A single event in the stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    /// Time of the event.
+    pub t: f64,
+    /// What happened.
+    pub kind: EventKind,
+}
+
+/// How individual events are kept during a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recording {
+    /// Keep no events, only summaries.
+    Summary,
+    /// Keep a uniform random sample of at most this many events.
+    Reservoir(usize),
+    /// Keep every event.
+    All,
+}
+
+/// Fixed-size uniform random sample of a stream of unknown length,
+/// maintained by reservoir sampling (Algorithm R).
+pub struct Reservoir<T> {
+    /// Maximum number of items kept.
+    pub capacity: usize,
+    /// Number of items offered so far.
+    pub seen: usize,
+    /// The sample. Every item offered so far is in it with equal
+    /// probability `capacity / seen`.
+    pub items: Vec<T>,
+}
+
+impl<T> Reservoir<T> {
+    /// Create an empty reservoir.
+    pub fn new(capacity: usize) -> Self {
+        return Self {
+            capacity,
+            seen: 0,
+            items: Vec::with_capacity(capacity),
+        };
+    }
+
+    /// Offer the next item of the stream.
+    pub fn offer(&mut self, item: T, rng: &mut impl Rng) {
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(item);
+            return;
+        }
+        let j = rng.gen_range(0..self.seen);
+        if j < self.capacity {
+            self.items[j] = item;
+        }
+    }
+}
+
+/// Running count, mean, variance and range of a stream of values, by
+/// Welford's method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// Number of values.
+    pub count: usize,
+    /// Mean of the values.
+    pub mean: f64,
+    /// Sum of squared deviations from the mean.
+    m2: f64,
+    /// Smallest value.
+    pub min: f64,
+    /// Largest value.
+    pub max: f64,
+}
+
+impl Summary {
+    /// Create an empty summary.
+    pub fn new() -> Self {
+        return Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        };
+    }
+
+    /// Add a value.
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / (self.count as f64);
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    /// Sample variance of the values. NaN with fewer than two values.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::NAN;
+        }
+        return self.m2 / ((self.count - 1) as f64);
+    }
+}
+
+/// Create and run a stochastic SIR simulation.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Spacing of the recorded counts.
+    pub step_size: f64,
+    /// Total population size.
+    pub population: u64,
+    /// Initial number of infectious individuals.
+    pub i_init: u64,
+    /// Transition rate from S into I.
+    pub incidence_rate: f64,
+    /// Transition rate from I into R.
+    pub removal_rate: f64,
+    /// Rate at which each susceptible is infected from outside the
+    /// population. Zero unless set.
+    pub importation: RateSchedule,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// How individual events are kept.
+    pub recording: Recording,
+    /// Susceptible count at each index. 1D Array with `length` number of elements.
+    pub s: Mat<f64>,
+    /// Infectious count at each index. 1D Array with `length` number of elements.
+    pub i: Mat<f64>,
+    /// Removed count at each index. 1D Array with `length` number of elements.
+    pub r: Mat<f64>,
+    /// Kept events. Every event with [`Recording::All`], a uniform sample
+    /// in no particular order with [`Recording::Reservoir`], otherwise empty.
+    pub events: Vec<Event>,
+    /// Summary of infection event times.
+    pub infection_times: Summary,
+    /// Summary of removal event times.
+    pub removal_times: Summary,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            population: 0,
+            i_init: 0,
+            incidence_rate: 0.0,
+            removal_rate: 0.0,
+            importation: RateSchedule::constant(0.0),
+            seed: 0,
+            recording: Recording::Summary,
+            s: Mat::new(),
+            i: Mat::new(),
+            r: Mat::new(),
+            events: Vec::new(),
+            infection_times: Summary::new(),
+            removal_times: Summary::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        population: u64,
+        i_init: u64,
+        incidence_rate: Rate,
+        removal_rate: Rate,
+        seed: u64,
+    ) -> &mut Self {
+        assert!(
+            i_init <= population,
+            "i_init must not exceed population, got {} > {}",
+            i_init,
+            population
+        );
+        let n_steps = ((length as f64) / step_size).ceil() as usize;
+        self.length = length;
+        self.step_size = step_size;
+        self.population = population;
+        self.i_init = i_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.importation = RateSchedule::constant(0.0);
+        self.seed = seed;
+        self.s = Mat::zeros(n_steps, 1);
+        self.i = Mat::zeros(n_steps, 1);
+        self.r = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Set the rate at which each susceptible is infected from outside the
+    /// population, constant or time-varying. It is stored per day.
+    pub fn importation(&mut self, schedule: impl Into<RateSchedule<Rate>>) -> &mut Self {
+        self.importation = schedule.into().per(TimeUnit::Day);
+        return self;
+    }
+
+    /// Set how individual events are kept.
+    pub fn recording(&mut self, recording: Recording) -> &mut Self {
+        self.recording = recording;
+        return self;
+    }
+
+    /// Initialize counts. Sets the 0th index of each compartment.
+    pub fn init_counts(&mut self) -> &mut Model {
+        self.s[(0, 0)] = (self.population - self.i_init) as f64;
+        self.i[(0, 0)] = self.i_init as f64;
+        self.r[(0, 0)] = 0.0;
+        return self;
+    }
+
+    /// Simulate events by Gillespie's direct method until the end of the
+    /// series, or until no event is possible: extinction without
+    /// importation.
+    pub fn run(&mut self) -> &Model {
+        let mut rng = rng::rng(self.seed);
+        let n = self.s.nrows();
+        let n_pop = self.population as f64;
+        let (mut s, mut i, mut r) = (self.s[(0, 0)], self.i[(0, 0)], self.r[(0, 0)]);
+        // Sampling draws from its own generator so the trajectory does not
+        // depend on how events are kept.
+        let mut sampling_rng = rng::stream(self.seed, 1);
+        let mut reservoir = match self.recording {
+            Recording::Reservoir(capacity) => Some(Reservoir::new(capacity)),
+            _ => None,
+        };
+        self.events = Vec::new();
+        self.infection_times = Summary::new();
+        self.removal_times = Summary::new();
+        let mut time = 0.0;
+        let mut next_index = 1;
+        loop {
+            let importation = self.importation.at(time) * s;
+            let infection = (self.incidence_rate * s * i / n_pop) + importation;
+            let removal = self.removal_rate * i;
+            let total = infection + removal;
+            let wait = if total > 0.0 {
+                -ln(1.0 - rng.r#gen::<f64>()) / total
+            } else {
+                f64::INFINITY
+            };
+            // Rates are constant only up to the next importation breakpoint.
+            let breakpoint = self
+                .importation
+                .breakpoints
+                .iter()
+                .map(|(t, _)| *t)
+                .find(|t| *t > time);
+            let changed = breakpoint.is_some_and(|t| t < time + wait);
+            time = if changed {
+                breakpoint.unwrap()
+            } else {
+                time + wait
+            };
+            // Record the state held over every index passed before the event.
+            while (next_index < n) && ((next_index as f64) * self.step_size < time) {
+                self.s[(next_index, 0)] = s;
+                self.i[(next_index, 0)] = i;
+                self.r[(next_index, 0)] = r;
+                next_index += 1;
+            }
+            if next_index >= n {
+                break;
+            }
+            if changed {
+                continue;
+            }
+            let kind = if rng.r#gen::<f64>() * total < infection {
+                s -= 1.0;
+                i += 1.0;
+                self.infection_times.push(time);
+                EventKind::Infection
+            } else {
+                i -= 1.0;
+                r += 1.0;
+                self.removal_times.push(time);
+                EventKind::Removal
+            };
+            let event = Event { t: time, kind };
+            match self.recording {
+                Recording::All => self.events.push(event),
+                Recording::Reservoir(_) => {
+                    reservoir.as_mut().unwrap().offer(event, &mut sampling_rng)
+                }
+                Recording::Summary => {}
+            }
+        }
+        if let Some(reservoir) = reservoir {
+            self.events = reservoir.items;
+        }
+        return self;
+    }
+
+    /// Rebuild counts and summaries from a recorded event log instead of
+    /// simulating. The model must be configured and initialized as it was
+    /// for the recorded run. Events are kept, as with [`Recording::All`].
+    pub fn replay(&mut self, mut events: Vec<Event>) -> &Model {
+        events.sort_by(|a, b| a.t.total_cmp(&b.t));
+        let n = self.s.nrows();
+        let (mut s, mut i, mut r) = (self.s[(0, 0)], self.i[(0, 0)], self.r[(0, 0)]);
+        self.infection_times = Summary::new();
+        self.removal_times = Summary::new();
+        let mut next_index = 1;
+        for event in events.iter() {
+            while (next_index < n) && ((next_index as f64) * self.step_size < event.t) {
+                self.s[(next_index, 0)] = s;
+                self.i[(next_index, 0)] = i;
+                self.r[(next_index, 0)] = r;
+                next_index += 1;
+            }
+            if next_index >= n {
+                break;
+            }
+            match event.kind {
+                EventKind::Infection => {
+                    s -= 1.0;
+                    i += 1.0;
+                    self.infection_times.push(event.t);
+                }
+                EventKind::Removal => {
+                    i -= 1.0;
+                    r += 1.0;
+                    self.removal_times.push(event.t);
+                }
+            }
+        }
+        for index in next_index..n {
+            self.s[(index, 0)] = s;
+            self.i[(index, 0)] = i;
+            self.r[(index, 0)] = r;
+        }
+        self.events = events;
+        return self;
+    }
+
+    /// New infections over each unit time, from t to t + 1, for use with
+    /// [`crate::observation::ReportingModel`].
+    pub fn incidence_per_unit_time(&self) -> Mat<f64> {
+        let per_unit = (1.0 / self.step_size).round() as usize;
+        let n = (self.s.nrows() - 1) / per_unit;
+        return Mat::from_fn(n, 1, |t, _| {
+            self.s[(t * per_unit, 0)] - self.s[((t + 1) * per_unit, 0)]
+        });
+    }
+}
+
+/// Write an event log as csv with columns `t,kind`. Read it back with
+/// [`crate::data::parse_event_log`].
+pub fn write_event_log<W: Write>(events: &[Event], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "t,kind")?;
+    for event in events.iter() {
+        let kind = match event.kind {
+            EventKind::Infection => "infection",
+            EventKind::Removal => "removal",
+        };
+        writeln!(writer, "{},{}", event.t, kind)?;
+    }
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::data::parse_event_log;
+    use crate::sirrs::observation::ReportingModel;
+    use crate::sirrs::rng;
+    use crate::sirrs::schedule::RateSchedule;
+    use crate::sirrs::ssa::{EventKind, Model, Recording, Reservoir, Summary, write_event_log};
+    use crate::sirrs::units::{Fraction, Rate};
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::new();
+        for x in [1.0, 2.0, 3.0, 4.0] {
+            summary.push(x);
+        }
+        assert_eq!(
+            summary.mean, 2.5,
+            "Bad mean, expected 2.5 got {}",
+            summary.mean
+        );
+        assert!(
+            (summary.variance() - (5.0 / 3.0)).abs() < 1e-12,
+            "Bad variance, expected {} got {}",
+            5.0 / 3.0,
+            summary.variance()
+        );
+        assert_eq!((summary.min, summary.max), (1.0, 4.0), "Bad range");
+    }
+
+    #[test]
+    fn test_reservoir_is_uniform() {
+        let mut rng = rng::rng(5);
+        let mut counts: [f64; 10] = [0.0; 10];
+        for _ in 0..20000 {
+            let mut reservoir = Reservoir::new(3);
+            for x in 0..10 {
+                reservoir.offer(x, &mut rng);
+            }
+            for &x in reservoir.items.iter() {
+                counts[x] += 1.0;
+            }
+        }
+        for (x, count) in counts.iter().enumerate() {
+            let p = count / 20000.0;
+            assert!(
+                (p - 0.3).abs() < 0.02,
+                "Bad inclusion probability of item {}, expected 0.3 got {}",
+                x,
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_conserves_population() {
+        let mut model = Model::new();
+        model.configure(50, 1.0, 500, 5, Rate::per_day(0.4), Rate::per_day(0.1), 3);
+        model.init_counts();
+        model.run();
+        for t in 0..50 {
+            let total = model.s[(t, 0)] + model.i[(t, 0)] + model.r[(t, 0)];
+            assert_eq!(total, 500.0, "Population not conserved at index {}", t);
+        }
+    }
+
+    #[test]
+    fn test_importation_reseeds() {
+        let mut model = Model::new();
+        model.configure(100, 1.0, 500, 0, Rate::per_day(0.4), Rate::per_day(0.2), 4);
+        model.importation(RateSchedule::new(
+            Rate::per_day(0.0),
+            vec![(50.0, Rate::per_day(1e-3))],
+        ));
+        model.init_counts();
+        model.run();
+        assert_eq!(
+            model.i[(50, 0)],
+            0.0,
+            "Expected no infections before importation starts"
+        );
+        assert!(
+            model.infection_times.min >= 50.0,
+            "Expected the first infection after 50, got {}",
+            model.infection_times.min
+        );
+        assert!(
+            model.infection_times.count > 0,
+            "Expected importation to re-seed the population"
+        );
+        for t in 0..100 {
+            let total = model.s[(t, 0)] + model.i[(t, 0)] + model.r[(t, 0)];
+            assert_eq!(total, 500.0, "Population not conserved at index {}", t);
+        }
+    }
+
+    #[test]
+    fn test_recording_modes_agree() {
+        let run = |recording: Recording| -> Model {
+            let mut model = Model::new();
+            model.configure(
+                60,
+                1.0,
+                1000,
+                10,
+                Rate::per_day(0.4),
+                Rate::per_day(0.1),
+                11,
+            );
+            model.recording(recording);
+            model.init_counts();
+            model.run();
+            return model;
+        };
+        let all = run(Recording::All);
+        let sampled = run(Recording::Reservoir(50));
+        let summary = run(Recording::Summary);
+        assert_eq!(all.i, sampled.i, "Recording mode changed the trajectory");
+        let infections = all
+            .events
+            .iter()
+            .filter(|e| e.kind == EventKind::Infection)
+            .count();
+        assert_eq!(
+            infections, all.infection_times.count,
+            "Summary count does not match kept infection events"
+        );
+        assert_eq!(
+            sampled.events.len(),
+            50,
+            "Bad reservoir size, expected 50 got {}",
+            sampled.events.len()
+        );
+        assert!(
+            sampled.events.iter().all(|e| all.events.contains(e)),
+            "Reservoir holds events not in the full stream"
+        );
+        assert!(
+            summary.events.is_empty(),
+            "Expected no kept events in summary mode"
+        );
+        assert_eq!(
+            summary.infection_times, all.infection_times,
+            "Summaries differ between recording modes"
+        );
+    }
+
+    #[test]
+    fn test_replay_event_log() {
+        let mut original = Model::new();
+        original.configure(
+            60,
+            0.5,
+            1000,
+            10,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            21,
+        );
+        original.recording(Recording::All);
+        original.init_counts();
+        original.run();
+        let mut log = Vec::new();
+        write_event_log(&original.events, &mut log).unwrap();
+        let events = parse_event_log(&String::from_utf8(log).unwrap()).unwrap();
+        let mut replayed = Model::new();
+        replayed.configure(60, 0.5, 1000, 10, Rate::per_day(0.4), Rate::per_day(0.1), 0);
+        replayed.init_counts();
+        replayed.replay(events);
+        assert_eq!(replayed.s, original.s, "Replayed s differs from the run");
+        assert_eq!(replayed.r, original.r, "Replayed r differs from the run");
+        assert_eq!(
+            replayed.infection_times.count, original.infection_times.count,
+            "Replayed infection summary differs from the run"
+        );
+        // Two observation models over the same realization.
+        let incidence = replayed.incidence_per_unit_time();
+        let mut half = ReportingModel::new();
+        half.configure(Fraction::of(0.5), vec![1.0], None, 0);
+        let mut delayed = ReportingModel::new();
+        delayed.configure(Fraction::of(1.0), vec![0.0, 1.0], None, 0);
+        let total: f64 = (0..incidence.nrows()).map(|t| incidence[(t, 0)]).sum();
+        let half_reports = half.expected(&incidence);
+        let half_total: f64 = (0..incidence.nrows()).map(|t| half_reports[(t, 0)]).sum();
+        assert_eq!(
+            half_total,
+            0.5 * total,
+            "Bad halved reports, expected {} got {}",
+            0.5 * total,
+            half_total
+        );
+        assert_eq!(
+            delayed.expected(&incidence)[(1, 0)],
+            incidence[(0, 0)],
+            "Bad delayed reports"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Linear stability analysis.
+//!
+//! Models expose the analytic Jacobian of their equations, for example
+//! [`crate::sir::Model::jacobian`], and a `stability_at` method summarizing
+//! its eigenvalues as a [`Stability`]. At a disease-free state the largest
+//! real part is the initial epidemic growth rate, positive exactly when the
+//! epidemic threshold is crossed. The spread of the real parts shows
+//! whether the equations are stiff, and so whether an explicit solver such
+//! as RK4 needs a small step.
+use faer::{Mat, c64};
+
+/// Eigenvalues of a Jacobian and what they imply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stability {
+    /// Eigenvalues of the Jacobian.
+    pub eigenvalues: Vec<c64>,
+    /// Largest real part of any eigenvalue, the growth rate of the fastest
+    /// growing perturbation.
+    pub growth_rate: f64,
+    /// Ratio of the largest to the smallest magnitude of the nonzero real
+    /// parts. Ratios in the thousands or more mean the equations are stiff.
+    /// 1 if no real part is nonzero.
+    pub stiffness_ratio: f64,
+}
+
+impl Stability {
+    /// Stability implied by `jacobian`.
+    pub fn of(jacobian: &Mat<f64>) -> Self {
+        let eigenvalues = jacobian
+            .eigenvalues()
+            .expect("eigenvalues of a real square matrix");
+        let growth_rate = eigenvalues
+            .iter()
+            .map(|e| e.re)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let scale = eigenvalues.iter().map(|e| e.norm()).fold(0.0, f64::max);
+        let magnitudes: Vec<f64> = eigenvalues
+            .iter()
+            .map(|e| e.re.abs())
+            .filter(|re| *re > 1e-12 * scale.max(1.0))
+            .collect();
+        let mut stiffness_ratio = 1.0;
+        if !magnitudes.is_empty() {
+            let largest = magnitudes.iter().cloned().fold(0.0, f64::max);
+            let smallest = magnitudes.iter().cloned().fold(f64::INFINITY, f64::min);
+            stiffness_ratio = largest / smallest;
+        }
+        return Self {
+            eigenvalues,
+            growth_rate,
+            stiffness_ratio,
+        };
+    }
+
+    /// Whether the state is locally stable: no eigenvalue has a positive
+    /// real part. Zero eigenvalues, from conserved totals or lines of
+    /// equilibria, are neutral.
+    pub fn stable(&self) -> bool {
+        let scale = self
+            .eigenvalues
+            .iter()
+            .map(|e| e.norm())
+            .fold(0.0, f64::max);
+        return self.growth_rate <= 1e-12 * scale.max(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::stability::Stability;
+    use faer::mat;
+
+    #[test]
+    fn test_of() {
+        let stability = Stability::of(&mat![[-1000.0, 0.0], [1.0, -0.5]]);
+        assert_eq!(
+            stability.growth_rate, -0.5,
+            "Bad growth rate, expected -0.5 got {}",
+            stability.growth_rate
+        );
+        assert!(
+            (stability.stiffness_ratio - 2000.0).abs() < 1e-9,
+            "Bad stiffness ratio, expected 2000 got {}",
+            stability.stiffness_ratio
+        );
+        assert!(stability.stable(), "Expected a stable matrix");
+        let rotation = Stability::of(&mat![[0.1, -1.0], [1.0, 0.1]]);
+        assert!(!rotation.stable(), "Expected a growing spiral");
+    }
+}
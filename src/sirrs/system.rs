@@ -0,0 +1,177 @@
+//! Models as systems of ordinary differential equations.
+//!
+//! Each model's solvers call its derivative functions directly. The
+//! [`DynamicalSystem`] trait exposes the same right-hand side over a flat
+//! state vector, so external ODE solvers, such as the `ode_solvers` crate,
+//! and analysis tools can drive any model without knowing its compartments.
+use crate::sirrs::{dismod, hospital, sir};
+use faer::Mat;
+
+/// A system of ordinary differential equations dy/dt = f(t, y).
+pub trait DynamicalSystem {
+    /// Number of state variables.
+    fn dimension(&self) -> usize;
+    /// Name of each state variable, in state order.
+    fn state_names(&self) -> Vec<String>;
+    /// Write f(`t`, `state`) into `deriv`. Both have length
+    /// [`DynamicalSystem::dimension`].
+    fn rhs(&self, t: f64, state: &[f64], deriv: &mut [f64]);
+    /// Jacobian of f at `t` and `state`, with rows the derivatives of each
+    /// state variable.
+    fn jacobian(&self, t: f64, state: &[f64]) -> Mat<f64>;
+}
+
+/// State (S, I, R) population fractions, or counts after [`sir::Model::counts`].
+impl DynamicalSystem for sir::Model {
+    fn dimension(&self) -> usize {
+        return 3;
+    }
+
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "i".to_string(), "r".to_string()];
+    }
+
+    fn rhs(&self, t: f64, state: &[f64], deriv: &mut [f64]) {
+        let (s, i) = (state[0], state[1]);
+        deriv[0] = self.dsdt(t, s, i);
+        deriv[1] = self.didt(t, s, i);
+        deriv[2] = self.drdt(i);
+    }
+
+    fn jacobian(&self, t: f64, state: &[f64]) -> Mat<f64> {
+        return sir::Model::jacobian(self, t, state);
+    }
+}
+
+/// State (S, C) population fractions.
+impl DynamicalSystem for dismod::Model {
+    fn dimension(&self) -> usize {
+        return 2;
+    }
+
+    fn state_names(&self) -> Vec<String> {
+        return vec!["s".to_string(), "c".to_string()];
+    }
+
+    fn rhs(&self, _t: f64, state: &[f64], deriv: &mut [f64]) {
+        let (s, c) = (state[0], state[1]);
+        deriv[0] = self.dsdt(s, c);
+        deriv[1] = self.dcdt(s, c);
+    }
+
+    fn jacobian(&self, t: f64, state: &[f64]) -> Mat<f64> {
+        return dismod::Model::jacobian(self, t, state);
+    }
+}
+
+/// State (S, I, H, U, R) population fractions.
+impl DynamicalSystem for hospital::Model {
+    fn dimension(&self) -> usize {
+        return 5;
+    }
+
+    fn state_names(&self) -> Vec<String> {
+        return ["s", "i", "h", "u", "r"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+
+    fn rhs(&self, _t: f64, state: &[f64], deriv: &mut [f64]) {
+        let (s, i, h, u) = (state[0], state[1], state[2], state[3]);
+        deriv[0] = self.dsdt(s, i);
+        deriv[1] = self.didt(s, i);
+        deriv[2] = self.dhdt(i, h);
+        deriv[3] = self.dudt(h, u);
+        deriv[4] = self.drdt(i, h, u);
+    }
+
+    fn jacobian(&self, t: f64, state: &[f64]) -> Mat<f64> {
+        return hospital::Model::jacobian(self, t, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::system::DynamicalSystem;
+    use crate::sirrs::units::{Duration, Fraction, Rate};
+    use crate::sirrs::{dismod, hospital, sir};
+
+    /// Check the Jacobian of `system` against central differences of its
+    /// right-hand side at `state`.
+    fn check_jacobian(system: &dyn DynamicalSystem, state: &[f64]) {
+        let n = system.dimension();
+        assert_eq!(system.state_names().len(), n, "Bad number of names");
+        let jacobian = system.jacobian(1.0, state);
+        let h = 1e-6;
+        for j in 0..n {
+            let mut up = state.to_vec();
+            let mut down = state.to_vec();
+            up[j] += h;
+            down[j] -= h;
+            let mut f_up = vec![0.0; n];
+            let mut f_down = vec![0.0; n];
+            system.rhs(1.0, &up, &mut f_up);
+            system.rhs(1.0, &down, &mut f_down);
+            for i in 0..n {
+                let expected = (f_up[i] - f_down[i]) / (2.0 * h);
+                assert!(
+                    (jacobian[(i, j)] - expected).abs() < 1e-8,
+                    "Bad Jacobian at ({}, {}), expected {} got {}",
+                    i,
+                    j,
+                    expected,
+                    jacobian[(i, j)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rhs() {
+        let mut model = sir::Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.1,
+            0.0,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Rate::per_day(0.05),
+        );
+        let mut deriv = vec![0.0; 3];
+        model.rhs(0.0, &[0.8, 0.15, 0.05], &mut deriv);
+        assert_eq!(
+            deriv[1],
+            model.didt(0.0, 0.8, 0.15),
+            "Bad dI/dt, expected {} got {}",
+            model.didt(0.0, 0.8, 0.15),
+            deriv[1]
+        );
+        check_jacobian(&model, &[0.8, 0.15, 0.05]);
+        let mut model = dismod::Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.1,
+            Rate::per_year(0.02),
+            Rate::per_year(0.01),
+            Rate::per_year(0.05),
+            Rate::per_year(0.1),
+        );
+        check_jacobian(&model, &[0.7, 0.2]);
+        let mut model = hospital::Model::new();
+        model.configure(
+            10,
+            1.0,
+            0.1,
+            Rate::per_day(0.3),
+            Rate::per_day(0.1),
+            Fraction::of(0.2),
+            Fraction::of(0.3),
+            Duration::days(7.0),
+            Duration::days(10.0),
+        );
+        check_jacobian(&model, &[0.6, 0.2, 0.05, 0.02, 0.13]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Property checks and strategies for testing models.
+//!
+//! The checks test invariants every compartmental solution should satisfy
+//! on a [`SimulationResult`], returning a message naming the first
+//! violation:
+//!  - [`check_non_negative`], no compartment goes below 0
+//!  - [`check_conservation`], compartments sum to a constant population
+//!  - [`check_monotone`], cumulative compartments never decrease
+//!
+//! With the `proptest` feature, [`parameters`] and [`sir_config`] are
+//! proptest strategies over valid parameter ranges, so a property is tested
+//! across the whole space a model accepts rather than a few hand-picked
+//! configurations.
+use crate::sirrs::metadata::SimulationResult;
+#[cfg(any(test, feature = "proptest"))]
+use crate::sirrs::{pipeline::Parameters, scenarios::NamedConfig, schema::ModelSchema};
+#[cfg(any(test, feature = "proptest"))]
+use proptest::prelude::*;
+
+/// Column index of series `name` in `result`.
+fn column(result: &SimulationResult, name: &str) -> Result<usize, String> {
+    return result
+        .names
+        .iter()
+        .position(|n| n == name)
+        .ok_or_else(|| format!("no series '{}'", name));
+}
+
+/// Check series `names` of `result` are never below `-tolerance`.
+pub fn check_non_negative(
+    result: &SimulationResult,
+    names: &[&str],
+    tolerance: f64,
+) -> Result<(), String> {
+    for name in names.iter() {
+        let j = column(result, name)?;
+        for k in 0..result.values.nrows() {
+            let value = result.values[(k, j)];
+            if (value < -tolerance) | value.is_nan() {
+                return Err(format!(
+                    "{} is negative at t = {}, got {}",
+                    name, result.times[k], value
+                ));
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Check the sum of series `names` of `result` stays within `tolerance` of
+/// its initial value.
+pub fn check_conservation(
+    result: &SimulationResult,
+    names: &[&str],
+    tolerance: f64,
+) -> Result<(), String> {
+    let columns = names
+        .iter()
+        .map(|name| column(result, name))
+        .collect::<Result<Vec<usize>, String>>()?;
+    let total = |k: usize| columns.iter().map(|j| result.values[(k, *j)]).sum::<f64>();
+    let initial = total(0);
+    for k in 1..result.values.nrows() {
+        let value = total(k);
+        if (value - initial).abs() > tolerance {
+            return Err(format!(
+                "total of {} is not conserved at t = {}, expected {} got {}",
+                names.join(" + "),
+                result.times[k],
+                initial,
+                value
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// Check series `name` of `result` never decreases by more than
+/// `tolerance` from one index to the next.
+pub fn check_monotone(result: &SimulationResult, name: &str, tolerance: f64) -> Result<(), String> {
+    let j = column(result, name)?;
+    for k in 1..result.values.nrows() {
+        let (previous, value) = (result.values[(k - 1, j)], result.values[(k, j)]);
+        if value < previous - tolerance {
+            return Err(format!(
+                "{} decreases at t = {}, from {} to {}",
+                name, result.times[k], previous, value
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// Strategy drawing every parameter of `schema` uniformly within its
+/// bounds. Unbounded parameters are drawn up to `cap` above their lower
+/// bound.
+#[cfg(any(test, feature = "proptest"))]
+pub fn parameters(schema: &ModelSchema, cap: f64) -> impl Strategy<Value = Parameters> {
+    let ranges: Vec<(String, f64, f64)> = schema
+        .parameters
+        .iter()
+        .map(|p| (p.name.clone(), p.lower, p.upper.min(p.lower + cap)))
+        .collect();
+    let names: Vec<String> = ranges.iter().map(|r| r.0.clone()).collect();
+    let values: Vec<_> = ranges
+        .into_iter()
+        .map(|(_, lower, upper)| lower..=upper)
+        .collect();
+    return values.prop_map(move |values| names.iter().cloned().zip(values).collect());
+}
+
+/// Strategy drawing [`NamedConfig`]s of the SIR model, with rates and
+/// initial fractions within [`crate::sir::Model::schema`] and step sizes
+/// small enough for every solver to stay stable. See [`NamedConfig::model`].
+#[cfg(any(test, feature = "proptest"))]
+pub fn sir_config() -> impl Strategy<Value = NamedConfig> {
+    return (
+        1usize..200,
+        0.05f64..=0.5,
+        0.0001f64..=0.5,
+        0.0f64..=0.4,
+        0.0f64..=1.0,
+        0.0f64..=1.0,
+        0.0f64..=0.5,
+    )
+        .prop_map(|(length, step_size, i, r, incidence, removal, recovery)| {
+            return NamedConfig::new(
+                "proptest", length, step_size, i, r, incidence, removal, recovery,
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sir;
+    use crate::sirrs::testing::{
+        check_conservation, check_monotone, check_non_negative, parameters, sir_config,
+    };
+    use crate::sirrs::units::Rate;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_checks() {
+        let mut model = sir::Model::new();
+        model.configure(
+            20,
+            1.0,
+            0.01,
+            0.0,
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut result = model.result("rk4");
+        assert_eq!(check_non_negative(&result, &["s", "i", "r"], 0.0), Ok(()));
+        assert_eq!(check_conservation(&result, &["s", "i", "r"], 1e-12), Ok(()));
+        assert_eq!(check_monotone(&result, "r", 0.0), Ok(()));
+        result.values[(5, 1)] = -0.1;
+        assert_eq!(
+            check_non_negative(&result, &["i"], 0.0),
+            Err("i is negative at t = 5, got -0.1".to_string())
+        );
+        assert!(
+            check_conservation(&result, &["s", "i", "r"], 1e-12).is_err(),
+            "Expected a perturbed total to fail"
+        );
+        assert!(
+            check_monotone(&result, "s", 0.0).is_err(),
+            "Expected s to decrease"
+        );
+        assert_eq!(
+            check_monotone(&result, "x", 0.0),
+            Err("no series 'x'".to_string())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_sir_invariants(config in sir_config()) {
+            let mut model = config.model();
+            for solver in ["euler", "heun", "midpoint", "rk4"] {
+                match solver {
+                    "euler" => model.run_euler(),
+                    "heun" => model.run_heun(),
+                    "midpoint" => model.run_midpoint(),
+                    _ => model.run_rk4(),
+                };
+                let result = model.result(solver);
+                check_non_negative(&result, &["s", "i", "r", "incidence"], 1e-12).unwrap();
+                check_conservation(&result, &["s", "i", "r"], 1e-12).unwrap();
+                check_monotone(&result, "r", 1e-12).unwrap();
+                check_monotone(&result, "cumulative_incidence", 1e-12).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_parameters_valid(values in parameters(&sir::Model::schema(), 100.0)) {
+            sir::Model::schema().validate(&values).unwrap();
+        }
+    }
+}
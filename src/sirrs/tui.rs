@@ -0,0 +1,210 @@
+//! Live terminal plots of a run, with the `tui` feature.
+//!
+//! [`TuiSink`] is an [`OutputSink`] drawing every series it is given as a
+//! braille line chart on a ratatui terminal, redrawn as steps arrive, so a
+//! run streamed by [`crate::pipeline::Pipeline::run_rk4_into`] is watched
+//! as it is solved, for example over ssh on a remote server. Redraws are at
+//! most one per [`TuiSink::interval`], so drawing does not slow down runs of
+//! many short steps. `sirrs <model> --tui` runs a model this way.
+use crate::sirrs::metadata::RunMetadata;
+use crate::sirrs::sink::OutputSink;
+use ratatui::Terminal;
+use ratatui::backend::Backend;
+use ratatui::layout::Constraint;
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Colours of the series, in order, repeating.
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Red,
+    Color::Blue,
+];
+
+/// Draws the series of a run on a terminal as it is solved.
+pub struct TuiSink<B: Backend> {
+    /// Terminal drawn on.
+    pub terminal: Terminal<B>,
+    /// Least time between redraws.
+    pub interval: Duration,
+    /// Time of the last step of the run, the end of the time axis.
+    t_end: f64,
+    /// Description of the run, from its metadata.
+    title: String,
+    /// Name of each series.
+    names: Vec<String>,
+    /// `(t, value)` of each step of each series.
+    points: Vec<Vec<(f64, f64)>>,
+    /// Smallest and largest value so far.
+    bounds: [f64; 2],
+    /// When the chart was last drawn.
+    drawn: Option<Instant>,
+    /// Whether the run has finished.
+    finished: bool,
+}
+
+impl<B: Backend> TuiSink<B> {
+    /// Sink drawing on `terminal` a run whose last step is at `t_end`,
+    /// redrawing at most every 50 ms.
+    pub fn new(terminal: Terminal<B>, t_end: f64) -> Self {
+        return Self {
+            terminal,
+            interval: Duration::from_millis(50),
+            t_end,
+            title: "run".to_string(),
+            names: Vec::new(),
+            points: Vec::new(),
+            bounds: [f64::INFINITY, f64::NEG_INFINITY],
+            drawn: None,
+            finished: false,
+        };
+    }
+
+    /// Draw the chart of the steps so far.
+    pub fn draw(&mut self) -> io::Result<()> {
+        let (low, high) = if self.bounds[0] <= self.bounds[1] {
+            (self.bounds[0], self.bounds[1])
+        } else {
+            (0.0, 1.0)
+        };
+        let pad = if high > low { 0.0 } else { 0.5 };
+        let (low, high) = (low - pad, high + pad);
+        let t = self
+            .points
+            .first()
+            .and_then(|p| p.last())
+            .map_or(0.0, |p| p.0);
+        let status = if self.finished {
+            "finished, press any key".to_string()
+        } else {
+            format!("t = {:.2}", t)
+        };
+        let title = format!(" {}, {} ", self.title, status);
+        let datasets: Vec<Dataset> = self
+            .names
+            .iter()
+            .zip(self.points.iter())
+            .enumerate()
+            .map(|(j, (name, points))| {
+                Dataset::default()
+                    .name(name.clone())
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(COLORS[j % COLORS.len()]))
+                    .data(points)
+            })
+            .collect();
+        let label = |x: f64| format!("{:.3}", x);
+        // Keep the legend unless it would cover half the chart.
+        let half = Constraint::Ratio(1, 2);
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title(title))
+            .hidden_legend_constraints((half, half))
+            .x_axis(
+                Axis::default()
+                    .title("t")
+                    .bounds([0.0, self.t_end])
+                    .labels([label(0.0), label(0.5 * self.t_end), label(self.t_end)]),
+            )
+            .y_axis(Axis::default().bounds([low, high]).labels([
+                label(low),
+                label(0.5 * (low + high)),
+                label(high),
+            ]));
+        self.terminal
+            .draw(|frame| frame.render_widget(chart, frame.area()))?;
+        self.drawn = Some(Instant::now());
+        return Ok(());
+    }
+}
+
+impl<B: Backend> OutputSink for TuiSink<B> {
+    fn metadata(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        self.title = format!(
+            "{} run, parameters {}",
+            metadata.solver, metadata.parameters_hash
+        );
+        return Ok(());
+    }
+
+    fn start(&mut self, names: &[String]) -> io::Result<()> {
+        self.names = names.to_vec();
+        self.points = vec![Vec::new(); names.len()];
+        self.bounds = [f64::INFINITY, f64::NEG_INFINITY];
+        self.finished = false;
+        return self.draw();
+    }
+
+    fn write_step(&mut self, t: f64, values: &[f64]) -> io::Result<()> {
+        for (points, value) in self.points.iter_mut().zip(values.iter()) {
+            points.push((t, *value));
+            if value.is_finite() {
+                self.bounds[0] = self.bounds[0].min(*value);
+                self.bounds[1] = self.bounds[1].max(*value);
+            }
+        }
+        if self
+            .drawn
+            .is_none_or(|drawn| drawn.elapsed() >= self.interval)
+        {
+            self.draw()?;
+        }
+        return Ok(());
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        return self.draw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::pipeline::Pipeline;
+    use crate::sirrs::sir;
+    use crate::sirrs::tui::TuiSink;
+    use crate::sirrs::units::Rate;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_tui_sink() {
+        let mut system = sir::Model::new();
+        system.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        let mut pipeline = Pipeline::new(Box::new(system));
+        pipeline.configure(50, 0.5);
+        let terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut sink = TuiSink::new(terminal, 49.5);
+        pipeline.run_rk4_into(&mut sink).unwrap();
+        let buffer = sink.terminal.backend().buffer();
+        let text: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+        assert!(
+            text.contains("rk4 run, parameters") & text.contains("finished, press any key"),
+            "Expected the finished title, got {}",
+            text
+        );
+        assert!(
+            text.contains("incidence") & text.contains("49.500"),
+            "Expected the legend and time axis, got {}",
+            text
+        );
+        assert!(
+            text.chars().any(|c| ('\u{2801}'..='\u{28ff}').contains(&c)),
+            "Expected braille lines"
+        );
+    }
+}
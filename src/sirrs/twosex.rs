@@ -0,0 +1,186 @@
+//! Two-sex SIS model for heterosexually transmitted infections.
+//!
+//! Groups belong to one of two sexes and form partnerships only with the
+//! other sex, bipartite mixing. Transmission per partnership differs by
+//! direction, as it does for most sexually transmitted infections, where
+//! male to female transmission is often the more efficient. The model is a
+//! [`riskgroup::Model`] with [`bipartite_contacts`] and a transmission matrix
+//! by direction, so each sex may be split further into activity groups.
+//!
+//! Partnerships must balance: the partnerships women form with men per unit
+//! time are those men form with women. When the reported activities do not
+//! balance, each sex's activity is scaled to the geometric mean of the two
+//! totals.
+use crate::sirrs::riskgroup;
+use crate::sirrs::units::{Fraction, Rate};
+use faer::Mat;
+
+/// Sex of a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+/// Partners per unit time an individual in group `a` (row) acquires in
+/// group `b` (column) under bipartite mixing, from the population fraction
+/// and activity of each group, columns with one row per group, and the sex
+/// of each group.
+///
+/// Partners are chosen among the groups of the other sex in proportion to
+/// the partnerships they offer, `N[b] c[b]`, with activities balanced.
+pub fn bipartite_contacts(population: &Mat<f64>, activity: &Mat<f64>, sex: &[Sex]) -> Mat<f64> {
+    let n = sex.len();
+    assert!(
+        (population.nrows() == n) & (activity.nrows() == n),
+        "population and activity must have one row per group, got {} and {} for {} groups",
+        population.nrows(),
+        activity.nrows(),
+        n
+    );
+    let offered = |s: Sex| -> f64 {
+        return (0..n)
+            .filter(|&g| sex[g] == s)
+            .map(|g| population[(g, 0)] * activity[(g, 0)])
+            .sum();
+    };
+    let (female, male) = (offered(Sex::Female), offered(Sex::Male));
+    assert!(
+        (female > 0.0) & (male > 0.0),
+        "both sexes must offer partnerships, got {} and {}",
+        female,
+        male
+    );
+    let balanced = (female * male).sqrt();
+    let total = |s: Sex| if s == Sex::Female { female } else { male };
+    return Mat::from_fn(n, n, |a, b| {
+        if sex[a] == sex[b] {
+            return 0.0;
+        }
+        let supply = population[(b, 0)] * activity[(b, 0)] / total(sex[b]);
+        activity[(a, 0)] * (balanced / total(sex[a])) * supply
+    });
+}
+
+/// Probability of transmission per partnership from group `b` (column) to
+/// group `a` (row), `male_to_female` into female groups and
+/// `female_to_male` into male groups.
+pub fn transmission_matrix(sex: &[Sex], male_to_female: f64, female_to_male: f64) -> Mat<f64> {
+    return Mat::from_fn(sex.len(), sex.len(), |a, b| match (sex[a], sex[b]) {
+        (Sex::Female, Sex::Male) => male_to_female,
+        (Sex::Male, Sex::Female) => female_to_male,
+        _ => 0.0,
+    });
+}
+
+/// A configured two-sex [`riskgroup::Model`], with groups of `sex`, each
+/// with a population fraction and activity, columns with one row per group.
+pub fn model(
+    length: usize,
+    step_size: f64,
+    population: Mat<f64>,
+    activity: Mat<f64>,
+    sex: &[Sex],
+    i_init: f64,
+    male_to_female: Fraction,
+    female_to_male: Fraction,
+    recovery_rate: Rate,
+) -> riskgroup::Model {
+    let contacts = bipartite_contacts(&population, &activity, sex);
+    let mut model = riskgroup::Model::new();
+    model.configure(
+        length,
+        step_size,
+        population,
+        activity,
+        Fraction::of(0.0),
+        i_init,
+        Fraction::of(0.0),
+        recovery_rate,
+    );
+    model.contacts(contacts);
+    model.transmission(transmission_matrix(
+        sex,
+        male_to_female.value(),
+        female_to_male.value(),
+    ));
+    return model;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::twosex::{Sex, bipartite_contacts, model};
+    use crate::sirrs::units::{Fraction, Rate};
+    use faer::mat;
+
+    #[test]
+    fn test_bipartite_contacts() {
+        let population = mat![[0.5], [0.4], [0.1]];
+        let activity = mat![[1.0], [0.5], [4.0]];
+        let sex = [Sex::Female, Sex::Male, Sex::Male];
+        let contacts = bipartite_contacts(&population, &activity, &sex);
+        assert_eq!(contacts[(1, 2)], 0.0, "Expected no same-sex partners");
+        for (a, b) in [(0, 1), (0, 2)] {
+            let (forward, back) = (
+                population[(a, 0)] * contacts[(a, b)],
+                population[(b, 0)] * contacts[(b, a)],
+            );
+            assert!(
+                (forward - back).abs() < 1e-12,
+                "Unbalanced partnerships between {} and {}, got {} and {}",
+                a,
+                b,
+                forward,
+                back
+            );
+        }
+        // Women offer 0.5 and men 0.6 partnerships, balanced to √0.3.
+        let female_total = 0.5 * (contacts[(0, 1)] + contacts[(0, 2)]);
+        assert!(
+            (female_total - 0.3f64.sqrt()).abs() < 1e-12,
+            "Bad balanced partnerships, expected {} got {}",
+            0.3f64.sqrt(),
+            female_total
+        );
+    }
+
+    #[test]
+    fn test_asymmetric_transmission() {
+        let mut twosex = model(
+            2000,
+            0.5,
+            mat![[0.5], [0.5]],
+            mat![[2.0], [2.0]],
+            &[Sex::Female, Sex::Male],
+            0.01,
+            Fraction::of(0.3),
+            Fraction::of(0.1),
+            Rate::per_day(0.2),
+        );
+        // R0 is the geometric mean of the two directions, β c / γ for each.
+        let (a, b): (f64, f64) = (0.3 * 2.0 / 0.2, 0.1 * 2.0 / 0.2);
+        let expected = (a * b).sqrt();
+        assert!(
+            (twosex.r0() - expected).abs() < 1e-9,
+            "Bad r0, expected {} got {}",
+            expected,
+            twosex.r0()
+        );
+        twosex.init_popf();
+        twosex.run_rk4();
+        let prevalence = twosex.prevalence();
+        let last = prevalence.nrows() - 1;
+        // At equilibrium p_f = β_mf c p_m (1 - p_f) / γ and likewise for men.
+        let female = ((a * b) - 1.0) / (b * (1.0 + a));
+        let male = ((a * b) - 1.0) / (a * (1.0 + b));
+        for (g, expected) in [(0, female), (1, male)] {
+            assert!(
+                (prevalence[(last, g)] - expected).abs() < 1e-6,
+                "Bad prevalence in group {}, expected {} got {}",
+                g,
+                expected,
+                prevalence[(last, g)]
+            );
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Parameter types carrying their units and bounds.
+//!
+//! A bare `f64` rate says nothing of its unit, so a rate per week given to a
+//! model in days, or a per step probability given as a rate, would run
+//! without complaint and give silently wrong dynamics. [`Rate`],
+//! [`Duration`] and [`Fraction`] check their value on construction and
+//! carry their [`TimeUnit`], and only give up a bare value when asked for it
+//! in a particular unit:
+//!
+//! ```
+//! use sirrs::units::{Duration, Rate, TimeUnit};
+//! let removal = Duration::new(1.0, TimeUnit::Week).unwrap().rate();
+//! assert!((removal.per(TimeUnit::Day) - (1.0 / 7.0)).abs() < 1e-12);
+//! let incidence = Rate::new(0.5, TimeUnit::Day).unwrap();
+//! assert!((incidence.per(TimeUnit::Week) - 3.5).abs() < 1e-12);
+//! ```
+//!
+//! Every way into a model's rates, durations and fractions, from
+//! `configure` to changepoints, schedules, importation, the fitters and
+//! [`crate::dismod::Hierarchy`], takes these types, and the model stores
+//! them in its own unit time: days for the epidemic models and years for
+//! DisMod. The fields are bare values in that unit, read by the solvers.
+//! Literals known to be valid can be written [`Rate::per_day`],
+//! [`Duration::days`] and [`Fraction::of`], and schedules of rates are
+//! [`crate::schedule::RateSchedule`]s of [`Rate`].
+use crate::sirrs::reproducible::{exp, ln};
+use std::io::{Error, ErrorKind};
+
+/// Unit of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Day,
+    Week,
+    /// 365.25 days.
+    Year,
+}
+
+impl TimeUnit {
+    /// Length of the unit in days.
+    pub fn days(&self) -> f64 {
+        match self {
+            TimeUnit::Day => return 1.0,
+            TimeUnit::Week => return 7.0,
+            TimeUnit::Year => return 365.25,
+        }
+    }
+
+    /// Name of the unit, as written in a schema.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeUnit::Day => return "day",
+            TimeUnit::Week => return "week",
+            TimeUnit::Year => return "year",
+        }
+    }
+}
+
+/// Error for a value outside its bounds.
+fn invalid(message: String) -> Error {
+    return Error::new(ErrorKind::InvalidInput, message);
+}
+
+/// A non-negative, finite rate of transition per unit time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    value: f64,
+    unit: TimeUnit,
+}
+
+impl Rate {
+    /// A rate of `value` per `unit`.
+    pub fn new(value: f64, unit: TimeUnit) -> Result<Self, Error> {
+        if !(value.is_finite() & (value >= 0.0)) {
+            return Err(invalid(format!(
+                "rate must be non-negative and finite, got {} per {}",
+                value,
+                unit.name()
+            )));
+        }
+        return Ok(Self { value, unit });
+    }
+
+    /// A rate of `value` per day, for values known to be valid, such as
+    /// literals. Panics otherwise.
+    pub fn per_day(value: f64) -> Self {
+        return Self::new(value, TimeUnit::Day).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// A rate of `value` per year, see [`Rate::per_day`].
+    pub fn per_year(value: f64) -> Self {
+        return Self::new(value, TimeUnit::Year).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Rate of a transition that happens with probability `p` within one
+    /// step of `step_size` in `unit`, see [`crate::convert::prob_to_rate`].
+    pub fn from_step_probability(p: f64, step_size: f64, unit: TimeUnit) -> Result<Self, Error> {
+        let p = Fraction::new(p)?.value();
+        if (p == 1.0) | (step_size <= 0.0) | step_size.is_nan() {
+            return Err(invalid(format!(
+                "step probability must be below 1 over a positive step, got {} over {}",
+                p, step_size
+            )));
+        }
+        return Self::new(-ln(1.0 - p) / step_size, unit);
+    }
+
+    /// Unit the rate was given in.
+    pub fn unit(&self) -> TimeUnit {
+        return self.unit;
+    }
+
+    /// The rate per `unit`, exactly as given when `unit` is its own.
+    pub fn per(&self, unit: TimeUnit) -> f64 {
+        if unit == self.unit {
+            return self.value;
+        }
+        return self.value * unit.days() / self.unit.days();
+    }
+
+    /// Probability of the transition within one step of `step_size` in
+    /// `unit`. Not `rate * step_size`, which only approximates it for small
+    /// steps.
+    pub fn step_probability(&self, step_size: f64, unit: TimeUnit) -> f64 {
+        return 1.0 - exp(-self.per(unit) * step_size);
+    }
+
+    /// Mean waiting time of the transition, an error for a zero rate, whose
+    /// wait is infinite.
+    pub fn duration(&self) -> Result<Duration, Error> {
+        return Duration::new(1.0 / self.value, self.unit);
+    }
+}
+
+/// A positive, finite length of time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Duration {
+    value: f64,
+    unit: TimeUnit,
+}
+
+impl Duration {
+    /// A duration of `value` `unit`s.
+    pub fn new(value: f64, unit: TimeUnit) -> Result<Self, Error> {
+        if !(value.is_finite() & (value > 0.0)) {
+            return Err(invalid(format!(
+                "duration must be positive and finite, got {} {}",
+                value,
+                unit.name()
+            )));
+        }
+        return Ok(Self { value, unit });
+    }
+
+    /// A duration of `value` days, for values known to be valid, such as
+    /// literals. Panics otherwise.
+    pub fn days(value: f64) -> Self {
+        return Self::new(value, TimeUnit::Day).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// A duration of `value` years, see [`Duration::days`].
+    pub fn years(value: f64) -> Self {
+        return Self::new(value, TimeUnit::Year).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Unit the duration was given in.
+    pub fn unit(&self) -> TimeUnit {
+        return self.unit;
+    }
+
+    /// The duration in `unit`s, exactly as given when `unit` is its own.
+    pub fn in_unit(&self, unit: TimeUnit) -> f64 {
+        if unit == self.unit {
+            return self.value;
+        }
+        return self.value * self.unit.days() / unit.days();
+    }
+
+    /// Rate of a transition with this mean waiting time.
+    pub fn rate(&self) -> Rate {
+        return Rate {
+            value: 1.0 / self.value,
+            unit: self.unit,
+        };
+    }
+}
+
+/// A fraction in [0, 1], such as a population fraction or probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fraction(f64);
+
+impl Fraction {
+    /// The fraction `value`.
+    pub fn new(value: f64) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(invalid(format!(
+                "fraction must be in [0, 1], got {}",
+                value
+            )));
+        }
+        return Ok(Self(value));
+    }
+
+    /// The fraction `value`, for values known to be valid, such as
+    /// literals. Panics otherwise.
+    pub fn of(value: f64) -> Self {
+        return Self::new(value).unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// The fraction as a bare value.
+    pub fn value(&self) -> f64 {
+        return self.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::units::{Duration, Fraction, Rate, TimeUnit};
+
+    #[test]
+    fn test_validation() {
+        assert!(
+            Rate::new(-0.1, TimeUnit::Day).is_err(),
+            "Expected negative rate rejected"
+        );
+        assert!(
+            Rate::new(f64::NAN, TimeUnit::Day).is_err(),
+            "Expected NaN rate rejected"
+        );
+        assert!(
+            Duration::new(0.0, TimeUnit::Week).is_err(),
+            "Expected zero duration rejected"
+        );
+        assert!(
+            Duration::new(f64::INFINITY, TimeUnit::Day).is_err(),
+            "Expected infinite duration rejected"
+        );
+        assert!(
+            Fraction::new(1.5).is_err(),
+            "Expected fraction above 1 rejected"
+        );
+        assert!(
+            Rate::from_step_probability(1.0, 0.5, TimeUnit::Day).is_err(),
+            "Expected certain transition rejected"
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let rate = Rate::new(2.0, TimeUnit::Week).unwrap();
+        assert!(
+            (rate.per(TimeUnit::Day) - (2.0 / 7.0)).abs() < 1e-12,
+            "Bad rate per day, got {}",
+            rate.per(TimeUnit::Day)
+        );
+        assert!(
+            (rate.duration().unwrap().in_unit(TimeUnit::Day) - 3.5).abs() < 1e-12,
+            "Bad mean duration, got {}",
+            rate.duration().unwrap().in_unit(TimeUnit::Day)
+        );
+        let stepped = Rate::from_step_probability(0.1, 0.5, TimeUnit::Day).unwrap();
+        assert!(
+            (stepped.step_probability(0.5, TimeUnit::Day) - 0.1).abs() < 1e-12,
+            "Bad step probability, got {}",
+            stepped.step_probability(0.5, TimeUnit::Day)
+        );
+        let year = Duration::new(1.0, TimeUnit::Year).unwrap();
+        assert_eq!(year.in_unit(TimeUnit::Day), 365.25, "Bad days in a year");
+    }
+}
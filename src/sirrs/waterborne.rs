@@ -0,0 +1,338 @@
+//! SIWR model with an environmental pathogen reservoir.
+//!
+//! Infectious individuals shed pathogen into a reservoir W, such as a water
+//! supply, where it decays. Susceptibles are infected both by direct contact
+//! with I and by ingesting pathogen from W, the structure of Tien and Earn
+//! (2010) for cholera. W is a pathogen concentration, in units such that
+//! shedding from the whole population infectious sustains a concentration of
+//! `shedding_rate / decay_rate`. Allows transition rates:
+//!  - S → I, by contact with I and by ingestion from W
+//!  - I → R
+//!
+//! and the reservoir changes as `dW/dt = α I - ξ W`.
+use crate::sirrs::grid::TimeGrid;
+use crate::sirrs::units::{Rate, TimeUnit};
+use faer::Mat;
+
+/// Create and run a model with an environmental reservoir.
+pub struct Model {
+    /// Number of indices to generate and solve. The length of the series.
+    pub length: usize,
+    /// Size of integration step.
+    pub step_size: f64,
+    /// Initial infectious population fraction.
+    pub i_popf_init: f64,
+    /// Initial pathogen concentration in the reservoir.
+    pub w_init: f64,
+    /// Transition rate from S into I by direct contact with I.
+    pub incidence_rate: f64,
+    /// Transition rate from S into I per unit of pathogen concentration
+    /// ingested from W.
+    pub ingestion_rate: f64,
+    /// Rate infectious individuals shed pathogen into W.
+    pub shedding_rate: f64,
+    /// Rate pathogen in W decays, the inverse of its mean survival time.
+    pub decay_rate: f64,
+    /// Transition rate from I into R. Must be in [0, 1].
+    pub removal_rate: f64,
+    /// Susceptible population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub s_popf: Mat<f64>,
+    /// Infectious population fraction at each index. 1D Array with one
+    /// element per index of [`Model::grid`].
+    pub i_popf: Mat<f64>,
+    /// Removed population fraction at each index. 1D Array with one element
+    /// per index of [`Model::grid`].
+    pub r_popf: Mat<f64>,
+    /// Pathogen concentration in the reservoir at each index. 1D Array with
+    /// one element per index of [`Model::grid`].
+    pub w_level: Mat<f64>,
+}
+
+impl Model {
+    /// Create a new model object.
+    pub fn new() -> Self {
+        return Self {
+            length: 0,
+            step_size: 0.0,
+            i_popf_init: 0.0,
+            w_init: 0.0,
+            incidence_rate: 0.0,
+            ingestion_rate: 0.0,
+            shedding_rate: 0.0,
+            decay_rate: 0.0,
+            removal_rate: 0.0,
+            s_popf: Mat::new(),
+            i_popf: Mat::new(),
+            r_popf: Mat::new(),
+            w_level: Mat::new(),
+        };
+    }
+
+    /// Configure model parameters.
+    pub fn configure(
+        &mut self,
+        length: usize,
+        step_size: f64,
+        i_popf_init: f64,
+        w_init: f64,
+        incidence_rate: Rate,
+        ingestion_rate: Rate,
+        shedding_rate: Rate,
+        decay_rate: Rate,
+        removal_rate: Rate,
+    ) -> &mut Self {
+        assert!(
+            decay_rate.per(TimeUnit::Day) > 0.0,
+            "decay_rate must be positive"
+        );
+        let n_steps = TimeGrid::from_length(length, step_size).n_steps;
+        self.length = length;
+        self.step_size = step_size;
+        self.i_popf_init = i_popf_init;
+        self.w_init = w_init;
+        self.incidence_rate = incidence_rate.per(TimeUnit::Day);
+        self.ingestion_rate = ingestion_rate.per(TimeUnit::Day);
+        self.shedding_rate = shedding_rate.per(TimeUnit::Day);
+        self.decay_rate = decay_rate.per(TimeUnit::Day);
+        self.removal_rate = removal_rate.per(TimeUnit::Day);
+        self.s_popf = Mat::zeros(n_steps, 1);
+        self.i_popf = Mat::zeros(n_steps, 1);
+        self.r_popf = Mat::zeros(n_steps, 1);
+        self.w_level = Mat::zeros(n_steps, 1);
+        return self;
+    }
+
+    /// Time grid of the solved series.
+    pub fn grid(&self) -> TimeGrid {
+        return TimeGrid::from_length(self.length, self.step_size);
+    }
+
+    /// Initialize population fractions. Sets the 0th index of each
+    /// compartment, and of the reservoir, equal to the corresponding initial
+    /// value.
+    pub fn init_popf(&mut self) -> &mut Model {
+        let s_init = 1.0 - self.i_popf_init; // Population fractions must sum to 1.
+        self.store(0, &[s_init, self.i_popf_init, 0.0, self.w_init]);
+        return self;
+    }
+
+    /// Basic reproduction number, summing transmission by direct contact and
+    /// through the reservoir, where each infection sheds `α / γ` pathogen
+    /// which survives `1 / ξ` on average.
+    pub fn r0(&self) -> f64 {
+        return (self.incidence_rate
+            + (self.ingestion_rate * self.shedding_rate / self.decay_rate))
+            / self.removal_rate;
+    }
+
+    /// Force of infection at each index, the rate a susceptible is infected
+    /// by both routes.
+    pub fn force_of_infection(&self) -> Mat<f64> {
+        return Mat::from_fn(self.i_popf.nrows(), 1, |t, _| {
+            (self.incidence_rate * self.i_popf[(t, 0)])
+                + (self.ingestion_rate * self.w_level[(t, 0)])
+        });
+    }
+
+    /// Pack the state at index `t` into a vector ordered S, I, R, W.
+    fn load(&self, t: usize) -> [f64; 4] {
+        return [
+            self.s_popf[(t, 0)],
+            self.i_popf[(t, 0)],
+            self.r_popf[(t, 0)],
+            self.w_level[(t, 0)],
+        ];
+    }
+
+    /// Unpack a state vector into index `t`.
+    fn store(&mut self, t: usize, y: &[f64; 4]) {
+        self.s_popf[(t, 0)] = y[0];
+        self.i_popf[(t, 0)] = y[1];
+        self.r_popf[(t, 0)] = y[2];
+        self.w_level[(t, 0)] = y[3];
+    }
+
+    /// Compute the derivative of every state variable.
+    fn derivatives(&self, y: &[f64; 4]) -> [f64; 4] {
+        let infection = ((self.incidence_rate * y[1]) + (self.ingestion_rate * y[3])) * y[0];
+        let removal = self.removal_rate * y[1];
+        return [
+            -infection,
+            infection - removal,
+            removal,
+            (self.shedding_rate * y[1]) - (self.decay_rate * y[3]),
+        ];
+    }
+
+    /// Run the differential equations by the first-order euler method.
+    ///
+    /// This solution method is very rough and only suitable for demonstration.
+    pub fn run_euler(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let d = self.derivatives(&y);
+            self.store(t + 1, &std::array::from_fn(|j| y[j] + (h * d[j])));
+        }
+        return self;
+    }
+
+    /// Solve the system by the 4th order Runge-Kutta method.
+    ///
+    /// This method is suitable for general purposes. A step size well below
+    /// `1 / decay_rate` is needed when pathogen decays quickly.
+    pub fn run_rk4(&mut self) -> &Model {
+        let h = self.step_size;
+        for t in 0..self.grid().n_steps - 1 {
+            let y = self.load(t);
+            let k1 = self.derivatives(&y);
+            let k2 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k1[j])));
+            let k3 = self.derivatives(&std::array::from_fn(|j| y[j] + (h / 2.0 * k2[j])));
+            let k4 = self.derivatives(&std::array::from_fn(|j| y[j] + (h * k3[j])));
+            self.store(
+                t + 1,
+                &std::array::from_fn(|j| {
+                    y[j] + ((k1[j] + (2.0 * k2[j]) + (2.0 * k3[j]) + k4[j]) * (h / 6.0))
+                }),
+            );
+        }
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sirrs::sir;
+    use crate::sirrs::units::Rate;
+    use crate::sirrs::waterborne::Model;
+
+    #[test]
+    fn test_direct_transmission_matches_sir() {
+        // Without ingestion the reservoir plays no part in transmission.
+        let mut model = Model::new();
+        model.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.0),
+            Rate::per_day(1.0),
+            Rate::per_day(0.5),
+            Rate::per_day(0.1),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = sir::Model::new();
+        sir.configure(
+            50,
+            0.5,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        for t in 0..model.i_popf.nrows() {
+            assert!(
+                (model.i_popf[(t, 0)] - sir.i_popf[(t, 0)]).abs() < 1e-12,
+                "Bad i_popf at index {}, expected {} got {}",
+                t,
+                sir.i_popf[(t, 0)],
+                model.i_popf[(t, 0)]
+            );
+        }
+        // The reservoir still fills from shedding.
+        assert!(
+            model.w_level[(model.w_level.nrows() - 1, 0)] > 0.0,
+            "Expected shed pathogen in the reservoir"
+        );
+    }
+
+    #[test]
+    fn test_fast_decay_matches_sir() {
+        // A short-lived reservoir tracks I, W ≈ α I / ξ, so environmental
+        // transmission acts as direct transmission at rate β_W α / ξ.
+        let mut model = Model::new();
+        model.configure(
+            60,
+            0.01,
+            0.01,
+            0.0,
+            Rate::per_day(0.2),
+            Rate::per_day(0.1),
+            Rate::per_day(100.0),
+            Rate::per_day(50.0),
+            Rate::per_day(0.1),
+        );
+        assert!(
+            (model.r0() - 4.0).abs() < 1e-12,
+            "Bad r0, expected 4 got {}",
+            model.r0()
+        );
+        model.init_popf();
+        model.run_rk4();
+        let mut sir = sir::Model::new();
+        sir.configure(
+            60,
+            0.01,
+            0.01,
+            0.0,
+            Rate::per_day(0.4),
+            Rate::per_day(0.1),
+            Rate::per_day(0.0),
+        );
+        sir.init_popf();
+        sir.run_rk4();
+        let last = model.s_popf.nrows() - 1;
+        for t in 0..=last {
+            let total = model.s_popf[(t, 0)] + model.i_popf[(t, 0)] + model.r_popf[(t, 0)];
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "Population fractions do not sum to 1 at index {}, got {}",
+                t,
+                total
+            );
+        }
+        assert!(
+            (model.r_popf[(last, 0)] - sir.r_popf[(last, 0)]).abs() < 1e-2,
+            "Bad final r_popf, expected {} got {}",
+            sir.r_popf[(last, 0)],
+            model.r_popf[(last, 0)]
+        );
+    }
+
+    #[test]
+    fn test_reservoir_seeds_outbreak() {
+        // Contaminated water alone starts an epidemic with nobody infectious.
+        let mut model = Model::new();
+        model.configure(
+            100,
+            0.1,
+            0.0,
+            0.1,
+            Rate::per_day(0.0),
+            Rate::per_day(0.5),
+            Rate::per_day(0.5),
+            Rate::per_day(0.2),
+            Rate::per_day(0.25),
+        );
+        model.init_popf();
+        model.run_rk4();
+        let last = model.r_popf.nrows() - 1;
+        assert!(
+            model.r_popf[(last, 0)] > 0.5,
+            "Expected a large epidemic from the reservoir, got final r_popf {}",
+            model.r_popf[(last, 0)]
+        );
+        let force = model.force_of_infection();
+        assert!(
+            (force[(0, 0)] - 0.05).abs() < 1e-12,
+            "Bad initial force of infection, expected 0.05 got {}",
+            force[(0, 0)]
+        );
+    }
+}
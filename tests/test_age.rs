@@ -0,0 +1,36 @@
+use faer::mat;
+use sirrs::age::Model;
+use sirrs::data::parse_coverage_csv;
+use sirrs::units::{Fraction, Rate};
+
+#[test]
+fn age_vaccination_from_csv() {
+    let coverage = parse_coverage_csv(
+        "date,age_group,coverage\n2021-01-05,0,0.3\n2021-01-05,1,0.6\n",
+        Some("2021-01-01"),
+    )
+    .unwrap();
+    let mut model = Model::new();
+    model.configure(
+        30,
+        1.0,
+        mat![[0.7], [0.3]],
+        mat![[6.0, 1.0], [2.0, 4.0]],
+        0.001,
+        Fraction::of(0.05),
+        Rate::per_day(0.2),
+        Fraction::of(1.0),
+    );
+    model.vaccination(coverage);
+    model.init_popf();
+    model.run_rk4();
+    for (g, expected) in [(0, 0.3 * 0.7), (1, 0.6 * 0.3)] {
+        assert!(
+            (model.v_popf[(29, g)] - expected).abs() < 1e-12,
+            "Bad v_popf for fully effective vaccine in group {}, expected {} got {}",
+            g,
+            expected,
+            model.v_popf[(29, g)]
+        );
+    }
+}
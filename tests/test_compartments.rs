@@ -0,0 +1,89 @@
+#![cfg(feature = "derive")]
+use sirrs::compartments::{CompartmentModel, Compartmental, Compartments, unpack};
+use sirrs::pipeline::{Parameters, System};
+
+#[derive(Compartments, Debug, PartialEq)]
+struct Seir {
+    s: f64,
+    e: f64,
+    i: f64,
+    r: f64,
+}
+
+struct Model;
+
+impl CompartmentModel for Model {
+    type State = Seir;
+
+    fn initial(&self) -> Seir {
+        return Seir {
+            s: 0.99,
+            e: 0.0,
+            i: 0.01,
+            r: 0.0,
+        };
+    }
+
+    fn parameters(&self) -> Parameters {
+        return Parameters::from([
+            ("beta".to_string(), 0.5),
+            ("sigma".to_string(), 0.2),
+            ("gamma".to_string(), 0.1),
+        ]);
+    }
+
+    fn derivatives(&self, t: f64, x: &Seir, p: &Parameters) -> Seir {
+        let infection = self.incidence(t, x, p);
+        return Seir {
+            s: -infection,
+            e: infection - p["sigma"] * x.e,
+            i: p["sigma"] * x.e - p["gamma"] * x.i,
+            r: p["gamma"] * x.i,
+        };
+    }
+
+    fn incidence(&self, _t: f64, x: &Seir, p: &Parameters) -> f64 {
+        return p["beta"] * x.s * x.i;
+    }
+}
+
+#[test]
+fn derive_compartments() {
+    let x = Seir {
+        s: 0.7,
+        e: 0.1,
+        i: 0.15,
+        r: 0.05,
+    };
+    assert_eq!(Seir::NAMES, ["s", "e", "i", "r"], "Bad compartment names");
+    assert_eq!(x.to_state(), vec![0.7, 0.1, 0.15, 0.05], "Bad state vector");
+    assert_eq!(Seir::from_state(&x.to_state()), x, "Bad round trip");
+}
+
+#[test]
+fn derived_model_conserves_population() {
+    let system = Compartmental(Model);
+    assert_eq!(
+        system.state_names(),
+        vec!["s", "e", "i", "r"],
+        "Bad state names"
+    );
+    let mut pipeline = system.pipeline(100, 0.5);
+    pipeline.run_rk4();
+    for t in 0..pipeline.state.nrows() {
+        let x: Seir = unpack(&pipeline.state, t);
+        let total = x.s + x.e + x.i + x.r;
+        assert!(
+            (total - 1.0).abs() < 1e-12,
+            "Bad total at index {}, expected 1 got {}",
+            t,
+            total
+        );
+    }
+    let last: Seir = unpack(&pipeline.state, pipeline.state.nrows() - 1);
+    assert!(
+        last.r > 0.5,
+        "Expected most of the population removed, got {:?}",
+        last
+    );
+}
@@ -1,9 +1,18 @@
 use sirrs::dismod::Model;
+use sirrs::units::Rate;
 
 #[test]
 fn dismod_init_popf() {
     let mut model = Model::new();
-    model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+    model.configure(
+        10,
+        1.0,
+        0.01,
+        Rate::per_year(0.01),
+        Rate::per_year(0.02),
+        Rate::per_year(0.03),
+        Rate::per_year(0.04),
+    );
     model.init_popf();
     assert_eq!(
         model.s.shape(),
@@ -52,7 +61,15 @@ fn dismod_init_popf() {
 #[test]
 fn dismod_run_euler() {
     let mut model = Model::new();
-    model.configure(10, 1.0, 0.01, 0.01, 0.02, 0.03, 0.04);
+    model.configure(
+        10,
+        1.0,
+        0.01,
+        Rate::per_year(0.01),
+        Rate::per_year(0.02),
+        Rate::per_year(0.03),
+        Rate::per_year(0.04),
+    );
     model.init_popf();
     model.run_euler();
     for t in 1..model.length {
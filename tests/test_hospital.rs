@@ -0,0 +1,46 @@
+use sirrs::hospital::{CapacityEventKind, Model};
+use sirrs::units::{Duration, Fraction, Rate};
+
+#[test]
+fn hospital_run_rk4() {
+    let mut model = Model::new();
+    model.configure(
+        100,
+        0.5,
+        0.01,
+        Rate::per_day(0.3),
+        Rate::per_day(0.1),
+        Fraction::of(0.05),
+        Fraction::of(0.2),
+        Duration::days(7.0),
+        Duration::days(10.0),
+    );
+    model.capacity(0.001, 0.0001);
+    model.init_popf();
+    model.run_rk4();
+    for t in 0..model.s_popf.nrows() {
+        for (name, x) in [
+            ("s_popf", model.s_popf[(t, 0)]),
+            ("i_popf", model.i_popf[(t, 0)]),
+            ("h_popf", model.h_popf[(t, 0)]),
+            ("u_popf", model.u_popf[(t, 0)]),
+            ("r_popf", model.r_popf[(t, 0)]),
+        ] {
+            assert!(
+                (0.0..=1.0).contains(&x),
+                "{}[(t, 0)] not in [0, 1] at time {}, got {}",
+                name,
+                t,
+                x
+            );
+        }
+    }
+    assert!(
+        model
+            .events
+            .iter()
+            .any(|e| e.kind == CapacityEventKind::HospitalExceeded),
+        "Expected a HospitalExceeded event, got {:?}",
+        model.events
+    );
+}
@@ -0,0 +1,57 @@
+use sirrs::reproducible;
+use sirrs::ssa::Model;
+use sirrs::units::Rate;
+
+// Strict mode is process wide, so it is exercised in its own test binary
+// with a single test.
+#[test]
+fn strict_mode() {
+    reproducible::set_strict(true);
+    assert!(reproducible::is_strict(), "Expected strict mode to be on");
+    assert_eq!(
+        reproducible::threads(8),
+        1,
+        "Bad thread count in strict mode, expected 1 got {}",
+        reproducible::threads(8)
+    );
+    for x in [0.3, 1.0, 7.5] {
+        assert_eq!(
+            reproducible::exp(x).to_bits(),
+            libm::exp(x).to_bits(),
+            "Expected strict exp({}) to use libm",
+            x
+        );
+        assert_eq!(
+            reproducible::ln(x).to_bits(),
+            libm::log(x).to_bits(),
+            "Expected strict ln({}) to use libm",
+            x
+        );
+    }
+    let run = || {
+        let mut model = Model::new();
+        model.configure(50, 1.0, 1000, 5, Rate::per_day(0.4), Rate::per_day(0.1), 7);
+        model.init_counts();
+        model.run();
+        return model.r;
+    };
+    let first = run();
+    let second = run();
+    for t in 0..first.nrows() {
+        assert_eq!(
+            first[(t, 0)].to_bits(),
+            second[(t, 0)].to_bits(),
+            "Runs differ at index {}, got {} and {}",
+            t,
+            first[(t, 0)],
+            second[(t, 0)]
+        );
+    }
+    reproducible::set_strict(false);
+    assert_eq!(
+        reproducible::threads(8),
+        8,
+        "Bad thread count outside strict mode, expected 8 got {}",
+        reproducible::threads(8)
+    );
+}
@@ -1,9 +1,18 @@
 use sirrs::sir::Model;
+use sirrs::units::Rate;
 
 #[test]
 fn sir_init_popf() {
     let mut model = Model::new();
-    model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+    model.configure(
+        10,
+        1.0,
+        0.01,
+        0.0,
+        Rate::per_day(0.02),
+        Rate::per_day(0.03),
+        Rate::per_day(0.04),
+    );
     model.init_popf();
     assert_eq!(
         model.s_popf.shape(),
@@ -72,7 +81,15 @@ fn sir_init_popf() {
 #[test]
 fn sir_run_euler() {
     let mut model = Model::new();
-    model.configure(10, 1.0, 0.01, 0.0, 0.02, 0.03, 0.04);
+    model.configure(
+        10,
+        1.0,
+        0.01,
+        0.0,
+        Rate::per_day(0.02),
+        Rate::per_day(0.03),
+        Rate::per_day(0.04),
+    );
     model.init_popf();
     model.run_euler();
     for t in 1..model.length {